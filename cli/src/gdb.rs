@@ -0,0 +1,472 @@
+//! A stub for the GDB remote serial protocol, so gdb can debug the emulated program:
+//!   $ retrowin32 --gdb 1234 foo.exe
+//!   $ gdb foo.exe -ex 'target remote localhost:1234'
+//! Emulated addresses are the program's own, so the exe works as a symbol file.
+//! See https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use x86::Register;
+
+/// General registers, in the order of gdb's i386 'g' packet; eip and eflags follow.
+const GPRS: [Register; 8] = [
+    Register::EAX,
+    Register::ECX,
+    Register::EDX,
+    Register::EBX,
+    Register::ESP,
+    Register::EBP,
+    Register::ESI,
+    Register::EDI,
+];
+/// Segment registers, which follow eflags.
+const SEGMENTS: [Register; 6] = [
+    Register::CS,
+    Register::SS,
+    Register::DS,
+    Register::ES,
+    Register::FS,
+    Register::GS,
+];
+
+/// How many blocks to run between checks for an interrupt from gdb.
+const POLL_INTERVAL: usize = 10_000;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+enum Packet {
+    Data(String),
+    /// The user hit ctrl-c.
+    Interrupt,
+}
+
+struct Conn {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Conn {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let mut b = [0u8];
+        match self.reader.read(&mut b)? {
+            0 => Ok(None),
+            _ => Ok(Some(b[0])),
+        }
+    }
+
+    /// Read the next packet, or None if gdb disconnected.
+    fn recv(&mut self) -> std::io::Result<Option<Packet>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => break,
+                Some(0x03) => return Ok(Some(Packet::Interrupt)),
+                Some(_) => {} // acks
+            }
+        }
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(b) => data.push(b),
+            }
+        }
+        // We're on a reliable stream, so skip verifying the checksum.
+        for _ in 0..2 {
+            self.read_byte()?;
+        }
+        self.writer.write_all(b"+")?;
+        Ok(Some(Packet::Data(
+            String::from_utf8_lossy(&data).into_owned(),
+        )))
+    }
+
+    fn send(&mut self, data: &str) -> std::io::Result<()> {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        loop {
+            write!(self.writer, "${data}#{sum:02x}")?;
+            self.writer.flush()?;
+            match self.read_byte()? {
+                Some(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Check, without blocking, whether gdb sent an interrupt.
+    fn poll_interrupt(&mut self) -> std::io::Result<bool> {
+        self.reader.get_ref().set_nonblocking(true)?;
+        let buf = match self.reader.fill_buf() {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => &[],
+            Err(err) => return Err(err),
+        };
+        let interrupted = buf.first() == Some(&0x03);
+        if interrupted {
+            self.reader.consume(1);
+        }
+        self.reader.get_ref().set_nonblocking(false)?;
+        Ok(interrupted)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(str: &str) -> Option<Vec<u8>> {
+    if str.len() % 2 != 0 {
+        return None;
+    }
+    (0..str.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(str.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parse "addr,len" as found in memory packets.
+fn parse_range(str: &str) -> Option<(u32, u32)> {
+    let (addr, len) = str.split_once(',')?;
+    Some((
+        u32::from_str_radix(addr, 16).ok()?,
+        u32::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+struct Stub<'a> {
+    machine: &'a mut win32::Machine,
+    conn: Conn,
+    /// Addresses of breakpoints gdb asked for.
+    breakpoints: HashSet<u32>,
+}
+
+impl Stub<'_> {
+    /// Register values in gdb's numbering.
+    fn regs(&self) -> Vec<u32> {
+        let cpu = self.machine.emu.x86.cpu();
+        let mut regs: Vec<u32> = GPRS.iter().map(|&r| cpu.regs.get32(r)).collect();
+        regs.push(cpu.regs.eip);
+        regs.push(cpu.flags.bits());
+        regs.extend(SEGMENTS.iter().map(|&r| cpu.regs.get16(r) as u32));
+        regs
+    }
+
+    fn set_reg(&mut self, index: usize, value: u32) -> bool {
+        let cpu = self.machine.emu.x86.cpu_mut();
+        match index {
+            0..=7 => cpu.regs.set32(GPRS[index], value),
+            8 => cpu.regs.eip = value,
+            9 => cpu.flags = x86::Flags::from_bits_truncate(value),
+            10..=15 => *cpu.regs.get16_mut(SEGMENTS[index - 10]) = value as u16,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Read memory as gdb expects to see it, without our breakpoints' int3s.
+    fn read_memory(&mut self, addr: u32, len: u32) -> Option<Vec<u8>> {
+        let mem = self.machine.mem();
+        let end = addr.checked_add(len)?;
+        if end > mem.len() {
+            return None;
+        }
+        let mut bytes = mem.slice(addr..end).to_vec();
+        for (addr, byte) in (addr..end).zip(bytes.iter_mut()) {
+            if let Some(&mut orig) = self.machine.breakpoint_byte(addr) {
+                *byte = orig;
+            }
+        }
+        Some(bytes)
+    }
+
+    fn write_memory(&mut self, addr: u32, bytes: &[u8]) -> bool {
+        let Some(end) = addr.checked_add(bytes.len() as u32) else {
+            return false;
+        };
+        if end > self.machine.mem().len() {
+            return false;
+        }
+        for (addr, &b) in (addr..end).zip(bytes) {
+            // Leave the int3s in place, and change what they'll restore.
+            match self.machine.breakpoint_byte(addr) {
+                Some(orig) => *orig = b,
+                None => *self.machine.mem().view_mut::<u8>(addr) = b,
+            }
+        }
+        for addr in addr..end {
+            self.machine.emu.x86.icache.clear_cache(addr);
+        }
+        true
+    }
+
+    /// The stop reply describing why the machine isn't running.
+    fn stop_reply(&self) -> String {
+        match &self.machine.status {
+            win32::Status::Exit(code) => format!("W{:02x}", code & 0xFF),
+            win32::Status::Error { message } => {
+                log::error!("{message}");
+                format!("S{SIGSEGV:02x}")
+            }
+            _ => format!("S{SIGTRAP:02x}"),
+        }
+    }
+
+    /// Execute one instruction.
+    fn step(&mut self) {
+        self.machine.single_step();
+    }
+
+    /// Resume execution until something stops it, returning the stop reply.
+    fn resume(&mut self, single_step: bool) -> std::io::Result<String> {
+        // Pick up after a breakpoint.
+        if matches!(self.machine.status, win32::Status::DebugBreak) {
+            self.machine.unblock();
+        }
+
+        // If we're sitting on a breakpoint, step past it with the original instruction.
+        let eip = self.machine.emu.x86.cpu().regs.eip;
+        if self.breakpoints.contains(&eip) {
+            self.machine.clear_breakpoint(eip);
+            self.step();
+            self.machine.add_breakpoint(eip);
+            if single_step || !self.machine.status.is_running() {
+                return Ok(self.stop_reply());
+            }
+        } else if single_step {
+            self.step();
+            return Ok(self.stop_reply());
+        }
+
+        loop {
+            for _ in 0..POLL_INTERVAL {
+                if !self.machine.run() {
+                    return Ok(self.stop_reply());
+                }
+            }
+            if self.conn.poll_interrupt()? {
+                return Ok(format!("S{SIGINT:02x}"));
+            }
+        }
+    }
+
+    /// Handle a packet, returning the reply, or None to end the session.
+    fn handle(&mut self, packet: &str) -> std::io::Result<Option<String>> {
+        let (cmd, args) = packet.split_at(packet.len().min(1));
+        let reply = match cmd {
+            "?" => self.stop_reply(),
+            "g" => self.regs().iter().map(|r| hex(&r.to_le_bytes())).collect(),
+            "G" => {
+                let bytes = unhex(args).unwrap_or_default();
+                for (i, chunk) in bytes.chunks_exact(4).enumerate() {
+                    self.set_reg(i, u32::from_le_bytes(chunk.try_into().unwrap()));
+                }
+                "OK".into()
+            }
+            "p" => match usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|i| self.regs().get(i).copied())
+            {
+                Some(value) => hex(&value.to_le_bytes()),
+                None => "E01".into(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(index, value)| {
+                    let value = unhex(value)?;
+                    Some((
+                        usize::from_str_radix(index, 16).ok()?,
+                        u32::from_le_bytes(value.try_into().ok()?),
+                    ))
+                });
+                match parsed {
+                    Some((index, value)) if self.set_reg(index, value) => "OK".into(),
+                    _ => "E01".into(),
+                }
+            }
+            "m" => match parse_range(args).and_then(|(addr, len)| self.read_memory(addr, len)) {
+                Some(bytes) => hex(&bytes),
+                None => "E14".into(),
+            },
+            "M" => {
+                let written = args.split_once(':').and_then(|(range, data)| {
+                    let (addr, len) = parse_range(range)?;
+                    let bytes = unhex(data)?;
+                    if bytes.len() != len as usize {
+                        return None;
+                    }
+                    Some(self.write_memory(addr, &bytes))
+                });
+                match written {
+                    Some(true) => "OK".into(),
+                    _ => "E14".into(),
+                }
+            }
+            "Z" | "z" => {
+                // Only software breakpoints: "Z0,addr,kind".
+                let addr = args
+                    .strip_prefix("0,")
+                    .and_then(|args| args.split(',').next())
+                    .and_then(|addr| u32::from_str_radix(addr, 16).ok());
+                match addr {
+                    Some(addr) if cmd == "Z" => {
+                        if self.machine.mem().is_oob::<u8>(addr) {
+                            "E01".into()
+                        } else {
+                            self.breakpoints.insert(addr);
+                            self.machine.add_breakpoint(addr);
+                            "OK".into()
+                        }
+                    }
+                    Some(addr) => {
+                        self.breakpoints.remove(&addr);
+                        self.machine.clear_breakpoint(addr);
+                        "OK".into()
+                    }
+                    None => String::new(),
+                }
+            }
+            "c" | "s" => {
+                if let Ok(addr) = u32::from_str_radix(args, 16) {
+                    self.machine.emu.x86.cpu_mut().regs.eip = addr;
+                }
+                self.resume(cmd == "s")?
+            }
+            "k" => {
+                self.machine.exit(0);
+                return Ok(None);
+            }
+            "D" => {
+                self.conn.send("OK")?;
+                return Ok(None);
+            }
+            "H" | "T" => "OK".into(),
+            "q" => match args.split(':').next().unwrap() {
+                "Supported" => "PacketSize=4000".into(),
+                "Attached" => "1".into(),
+                "C" => "QC1".into(),
+                "fThreadInfo" => "m1".into(),
+                "sThreadInfo" => "l".into(),
+                _ => String::new(),
+            },
+            // Empty replies mean "unsupported".
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+}
+
+/// Wait for gdb to connect on the port, then let it control the machine until it
+/// detaches or the program stops.  Afterwards the machine is left free to keep running.
+pub fn serve(machine: &mut win32::Machine, port: u16) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    log::info!("gdb: waiting for connection on port {port}");
+    let (stream, addr) = listener.accept()?;
+    log::info!("gdb: connected from {addr}");
+    stream.set_nodelay(true)?;
+
+    let mut stub = Stub {
+        machine,
+        conn: Conn {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        },
+        breakpoints: HashSet::new(),
+    };
+    while let Some(packet) = stub.conn.recv()? {
+        let packet = match packet {
+            Packet::Data(data) => data,
+            // Nothing is running while gdb is in control.
+            Packet::Interrupt => continue,
+        };
+        match stub.handle(&packet)? {
+            Some(reply) => stub.conn.send(&reply)?,
+            None => break,
+        }
+        if !matches!(
+            stub.machine.status,
+            win32::Status::Running | win32::Status::DebugBreak
+        ) {
+            break;
+        }
+    }
+
+    for addr in stub.breakpoints.drain() {
+        stub.machine.clear_breakpoint(addr);
+    }
+    if matches!(stub.machine.status, win32::Status::DebugBreak) {
+        stub.machine.unblock();
+    }
+    log::info!("gdb: detached");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use win32::testing::alloc_code;
+
+    /// A stub over a fresh machine, connected to a socket nobody reads.
+    fn stub(machine: &mut win32::Machine) -> Stub<'_> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let _gdb = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        Stub {
+            machine,
+            conn: Conn {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+            },
+            breakpoints: HashSet::new(),
+        }
+    }
+
+    fn handle(stub: &mut Stub, packet: &str) -> String {
+        stub.handle(packet).unwrap().unwrap()
+    }
+
+    #[test]
+    fn registers() {
+        let mut machine = win32::testing::machine();
+        let mut stub = stub(&mut machine);
+        assert_eq!(handle(&mut stub, "P0=78563412"), "OK");
+        assert_eq!(handle(&mut stub, "p0"), "78563412");
+        assert!(handle(&mut stub, "g").starts_with("78563412"));
+        assert_eq!(handle(&mut stub, "P8=00104000"), "OK");
+        assert_eq!(stub.machine.emu.x86.cpu().regs.eip, 0x401000);
+        assert_eq!(handle(&mut stub, "p10"), "E01");
+        assert_eq!(handle(&mut stub, "P10=00000000"), "E01");
+    }
+
+    /// gdb sees and edits memory as if its breakpoints weren't there.
+    #[test]
+    fn breakpoints_hidden() {
+        let mut machine = win32::testing::machine();
+        let addr = alloc_code(&mut machine, &[0x90; 3]);
+        let mut stub = stub(&mut machine);
+
+        assert_eq!(handle(&mut stub, &format!("Z0,{:x},1", addr + 1)), "OK");
+        assert_eq!(stub.machine.mem().slice(addr..addr + 3), [0x90, 0xcc, 0x90]);
+        assert_eq!(handle(&mut stub, &format!("m{addr:x},3")), "909090");
+
+        assert_eq!(handle(&mut stub, &format!("M{addr:x},3:c3c3c3")), "OK");
+        assert_eq!(stub.machine.mem().slice(addr..addr + 3), [0xc3, 0xcc, 0xc3]);
+        assert_eq!(handle(&mut stub, &format!("m{addr:x},3")), "c3c3c3");
+
+        assert_eq!(handle(&mut stub, &format!("z0,{:x},1", addr + 1)), "OK");
+        assert_eq!(stub.machine.mem().slice(addr..addr + 3), [0xc3, 0xc3, 0xc3]);
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut machine = win32::testing::machine();
+        let mut stub = stub(&mut machine);
+        assert_eq!(handle(&mut stub, "Z0,ffffffff,1"), "E01");
+        assert!(stub.breakpoints.is_empty());
+        assert_eq!(handle(&mut stub, "mfffffffe,4"), "E14");
+        assert_eq!(handle(&mut stub, "Mfffffffe,1:00"), "E14");
+        assert_eq!(handle(&mut stub, "M0,2:00"), "E14");
+    }
+}
@@ -0,0 +1,233 @@
+//! Golden-image testing: run a program headless for some number of frames and compare
+//! what it rendered against a stored PNG.
+
+use crate::host::EnvRef;
+use anyhow::{anyhow, bail};
+use win32::Host;
+
+fn frame_count(host: &EnvRef) -> usize {
+    host.0
+        .borrow()
+        .gui
+        .as_ref()
+        .map_or(0, |gui| gui.frame_count())
+}
+
+/// Run the machine until it has presented `frames` frames, then stop it as if it had
+/// exited successfully.  If it stops on its own before then, its status is left as is.
+pub fn run_frames(machine: &mut win32::Machine, host: &EnvRef, frames: usize) {
+    while frame_count(host) < frames {
+        if !machine.run() {
+            log::warn!(
+                "program stopped after {} of {frames} frames",
+                frame_count(host)
+            );
+            return;
+        }
+    }
+    machine.exit(0);
+}
+
+fn read_png(path: &str) -> anyhow::Result<(u32, u32, Vec<u8>)> {
+    let file = std::fs::File::open(path).map_err(|err| anyhow!("{path}: {err}"))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    buf.truncate(info.buffer_size());
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 0xFF])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&p| [p, p, p, 0xFF]).collect(),
+        png::ColorType::Indexed => unreachable!("expanded by normalize_to_color8"),
+    };
+    Ok((info.width, info.height, pixels))
+}
+
+fn write_png(path: &str, (width, height, pixels): &(u32, u32, Vec<u8>)) -> anyhow::Result<()> {
+    let file = std::fs::File::create(path).map_err(|err| anyhow!("{path}: {err}"))?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), *width, *height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+    Ok(())
+}
+
+/// Compare the last presented frame against the PNG at `path`, allowing each color
+/// channel to differ by up to `tolerance`.  Alpha is ignored, as surfaces don't
+/// reliably fill it in.  With `update`, writes the frame to `path` instead.
+pub fn check(host: &EnvRef, path: &str, tolerance: u8, update: bool) -> anyhow::Result<()> {
+    let frame = host.capture_frame();
+    if frame.0 == 0 || frame.1 == 0 {
+        bail!("no frame was presented");
+    }
+    if update {
+        return write_png(path, &frame);
+    }
+
+    let (width, height, pixels) = frame;
+    let (gwidth, gheight, golden) = read_png(path)?;
+    if (width, height) != (gwidth, gheight) {
+        bail!("{path}: frame is {width}x{height}, expected {gwidth}x{gheight}");
+    }
+    let mut mismatches = 0;
+    let mut first = None;
+    for (i, (a, b)) in pixels
+        .chunks_exact(4)
+        .zip(golden.chunks_exact(4))
+        .enumerate()
+    {
+        if (0..3).any(|c| a[c].abs_diff(b[c]) > tolerance) {
+            mismatches += 1;
+            first.get_or_insert(i as u32);
+        }
+    }
+    if let Some(i) = first {
+        bail!(
+            "{path}: {mismatches} pixels differ, first at ({}, {})",
+            i % width,
+            i / width
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::new_host;
+    use win32::{
+        testing::{alloc_code, call, create_window, push, start},
+        winapi::kernel32::get_symbol,
+    };
+
+    fn temp_png(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "retrowin32-golden-{}-{name}.png",
+            std::process::id()
+        ));
+        path.to_str().unwrap().to_string()
+    }
+
+    /// Present a width x height frame filled with color.
+    fn present(host: &EnvRef, width: u32, height: u32, color: [u8; 4]) {
+        let opts = win32::SurfaceOptions {
+            width,
+            height,
+            primary: true,
+        };
+        let mut surface = host.clone().create_surface(0, &opts);
+        surface.write_pixels(&vec![color; (width * height) as usize]);
+        surface.show();
+    }
+
+    #[test]
+    fn check_frames() {
+        let path = temp_png("check");
+        let host = new_host();
+        present(&host, 4, 3, [0x10, 0x20, 0x30, 0xFF]);
+        check(&host, &path, 0, true).unwrap();
+        check(&host, &path, 0, false).unwrap();
+
+        // Alpha doesn't count, and small differences pass within the tolerance.
+        present(&host, 4, 3, [0x12, 0x1E, 0x30, 0]);
+        check(&host, &path, 2, false).unwrap();
+        let err = check(&host, &path, 1, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{path}: 12 pixels differ, first at (0, 0)")
+        );
+
+        present(&host, 3, 4, [0x10, 0x20, 0x30, 0xFF]);
+        let err = check(&host, &path, 0, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{path}: frame is 3x4, expected 4x3")
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        let err = check(&host, &path, 0, false).unwrap_err();
+        assert!(err.to_string().starts_with(&path), "{err}");
+    }
+
+    #[test]
+    fn check_first_mismatch() {
+        let path = temp_png("mismatch");
+        let host = new_host();
+        present(&host, 4, 3, [0, 0, 0, 0xFF]);
+        check(&host, &path, 0, true).unwrap();
+
+        let opts = win32::SurfaceOptions {
+            width: 4,
+            height: 3,
+            primary: true,
+        };
+        let mut surface = host.clone().create_surface(0, &opts);
+        surface.write_pixels_rect(1, 2, 2, 1, &[[0xFF, 0, 0, 0xFF]; 2]);
+        surface.show();
+        let err = check(&host, &path, 0, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{path}: 2 pixels differ, first at (1, 2)")
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn check_no_frame() {
+        let path = temp_png("none");
+        let err = check(&new_host(), &path, 0, true).unwrap_err();
+        assert_eq!(err.to_string(), "no frame was presented");
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    /// A program that opens a window and fills a rectangle in it with a red brush,
+    /// compared against a stored PNG.
+    #[test]
+    fn golden_patblt() {
+        let host = new_host();
+        let mut machine = win32::Machine::new(Box::new(host.clone()), "test.exe".into());
+        let mut code = Vec::new();
+        create_window(&mut machine, &mut code, "golden", 0x9000_0000); // WS_POPUP | WS_VISIBLE
+        code.push(0x50); // push eax
+        call(&mut code, get_symbol(&mut machine, "user32.dll", "GetDC"));
+        code.extend_from_slice(&[0x89, 0xc3]); // mov ebx, eax
+        push(&mut code, 0x0000FF); // RGB(0xFF, 0, 0)
+        call(
+            &mut code,
+            get_symbol(&mut machine, "gdi32.dll", "CreateSolidBrush"),
+        );
+        code.extend_from_slice(&[0x50, 0x53]); // push eax; push ebx
+        call(
+            &mut code,
+            get_symbol(&mut machine, "gdi32.dll", "SelectObject"),
+        );
+        // PatBlt(hdc, 4, 2, 8, 4, PATCOPY)
+        for arg in [0x00F0_0021, 4, 8, 2, 4] {
+            push(&mut code, arg);
+        }
+        code.push(0x53); // push ebx
+        call(&mut code, get_symbol(&mut machine, "gdi32.dll", "PatBlt"));
+        // Exits with 1 if the program gets past the frame run_frames waits for.
+        push(&mut code, 1);
+        call(
+            &mut code,
+            get_symbol(&mut machine, "kernel32.dll", "ExitProcess"),
+        );
+        let entry_point = alloc_code(&mut machine, &code);
+        start(&mut machine, entry_point);
+
+        run_frames(&mut machine, &host, 1);
+        assert!(matches!(machine.status, win32::Status::Exit(0)));
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/patblt.png");
+        check(&host, path, 0, false).unwrap();
+    }
+}
@@ -1,22 +1,32 @@
+use std::{cell::RefCell, rc::Rc};
+
+/// The most recently presented frame, shared between the GUI and its surfaces.
+#[derive(Default)]
+struct Present {
+    width: u32,
+    height: u32,
+    /// RGBA pixels.
+    pixels: Vec<u8>,
+    /// Number of frames presented so far.
+    count: usize,
+}
+
 pub struct GUI {
     start: std::time::Instant,
+    present: Rc<RefCell<Present>>,
 }
 
 impl GUI {
-    pub fn new() -> anyhow::Result<Self> {
+    pub fn new(start: std::time::Instant) -> anyhow::Result<Self> {
         Ok(GUI {
-            start: std::time::Instant::now(),
+            start,
+            present: Default::default(),
         })
     }
 
-    pub fn time(&self) -> u32 {
-        std::time::Instant::now()
-            .duration_since(self.start)
-            .as_millis() as u32
-    }
-
     pub fn get_message(&mut self) -> Option<win32::Message> {
-        unimplemented!();
+        // No input without a real window.
+        None
     }
 
     pub fn block(&mut self, wait: Option<u32>) -> bool {
@@ -25,17 +35,136 @@ impl GUI {
             if let Some(remaining) = when.checked_duration_since(std::time::Instant::now()) {
                 std::thread::sleep(remaining);
             }
-            true
         } else {
-            unimplemented!();
+            // Waiting for a message that will never come; don't spin the CPU while
+            // the caller polls again.
+            std::thread::sleep(std::time::Duration::from_millis(10));
         }
+        true
     }
 
     pub fn create_window(&mut self, _hwnd: u32) -> Box<dyn win32::Window> {
-        unimplemented!();
+        Box::new(Window)
+    }
+
+    pub fn create_surface(&mut self, opts: &win32::SurfaceOptions) -> Box<dyn win32::Surface> {
+        Box::new(Surface::new(opts, self.present.clone()))
+    }
+
+    /// The pixels of the last surface shown, as RGBA.
+    pub fn capture_frame(&self) -> (u32, u32, Vec<u8>) {
+        let present = self.present.borrow();
+        (present.width, present.height, present.pixels.clone())
+    }
+
+    /// Number of frames shown so far.
+    pub fn frame_count(&self) -> usize {
+        self.present.borrow().count
+    }
+}
+
+struct Window;
+
+impl win32::Window for Window {
+    fn set_title(&mut self, _title: &str) {}
+    fn set_size(&mut self, _width: u32, _height: u32) {}
+    fn set_visible(&mut self, _visible: bool) {}
+    fn fullscreen(&mut self) {}
+}
+
+/// In-memory surface, which on show() becomes the captured frame.
+struct Surface {
+    width: u32,
+    height: u32,
+    pixels: Vec<[u8; 4]>,
+    present: Rc<RefCell<Present>>,
+}
+
+impl Surface {
+    fn new(opts: &win32::SurfaceOptions, present: Rc<RefCell<Present>>) -> Self {
+        Surface {
+            width: opts.width,
+            height: opts.height,
+            pixels: vec![[0, 0, 0, 0xFF]; (opts.width * opts.height) as usize],
+            present,
+        }
+    }
+
+    /// Copy the src rect to the dst rect, scaling with nearest-neighbor as SDL does.
+    fn blit(
+        &mut self,
+        (dx, dy, dw, dh): (u32, u32, u32, u32),
+        src: &dyn win32::Surface,
+        (sx, sy, sw, sh): (u32, u32, u32, u32),
+    ) {
+        let src = unsafe { &*(src as *const dyn win32::Surface as *const Surface) };
+        for y in 0..dh {
+            let ty = dy + y;
+            let fy = sy + y * sh / dh;
+            if ty >= self.height || fy >= src.height {
+                continue;
+            }
+            for x in 0..dw {
+                let tx = dx + x;
+                let fx = sx + x * sw / dw;
+                if tx >= self.width || fx >= src.width {
+                    continue;
+                }
+                self.pixels[(ty * self.width + tx) as usize] =
+                    src.pixels[(fy * src.width + fx) as usize];
+            }
+        }
+    }
+}
+
+impl win32::Surface for Surface {
+    fn write_pixels(&mut self, pixels: &[[u8; 4]]) {
+        let len = pixels.len().min(self.pixels.len());
+        self.pixels[..len].copy_from_slice(&pixels[..len]);
+    }
+
+    fn write_pixels_rect(&mut self, x: u32, y: u32, w: u32, h: u32, pixels: &[[u8; 4]]) {
+        for row in 0..h.min(self.height.saturating_sub(y)) {
+            let cols = w.min(self.width.saturating_sub(x)) as usize;
+            let src = (row * w) as usize;
+            let dst = ((y + row) * self.width + x) as usize;
+            self.pixels[dst..dst + cols].copy_from_slice(&pixels[src..src + cols]);
+        }
+    }
+
+    fn show(&mut self) {
+        let mut present = self.present.borrow_mut();
+        present.width = self.width;
+        present.height = self.height;
+        present.pixels = self.pixels.iter().flatten().copied().collect();
+        present.count += 1;
+    }
+
+    fn bit_blt(
+        &mut self,
+        dx: u32,
+        dy: u32,
+        src: &dyn win32::Surface,
+        sx: u32,
+        sy: u32,
+        w: u32,
+        h: u32,
+    ) {
+        self.blit((dx, dy, w, h), src, (sx, sy, w, h));
     }
 
-    pub fn create_surface(&mut self, _opts: &win32::SurfaceOptions) -> Box<dyn win32::Surface> {
-        unimplemented!();
+    fn stretch_blt(
+        &mut self,
+        dx: u32,
+        dy: u32,
+        dw: u32,
+        dh: u32,
+        src: &dyn win32::Surface,
+        sx: u32,
+        sy: u32,
+        sw: u32,
+        sh: u32,
+    ) {
+        self.blit((dx, dy, dw, dh), src, (sx, sy, sw, sh));
     }
 }
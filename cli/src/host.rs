@@ -2,6 +2,7 @@
 use crate::headless::GUI;
 #[cfg(feature = "sdl")]
 use crate::sdl::GUI;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::{cell::RefCell, io::Write, rc::Rc};
@@ -89,17 +90,30 @@ impl ReadDir for ReadDirFile {
 }
 
 pub struct Env {
-    gui: Option<GUI>,
+    pub gui: Option<GUI>,
+    /// Process start, the zero point of Host::ticks().
+    start: std::time::Instant,
+    /// If set, reported as the wall clock time instead of the real time, so that
+    /// runs are reproducible.
+    pub fixed_time: Option<chrono::DateTime<chrono::Local>>,
+    /// Host directories standing in for well-known folders; the rest are the
+    /// current directory.
+    pub special_folders: HashMap<win32::SpecialFolder, PathBuf>,
 }
 
 impl Env {
     pub fn new() -> Self {
-        Env { gui: None }
+        Env {
+            gui: None,
+            start: std::time::Instant::now(),
+            fixed_time: None,
+            special_folders: HashMap::new(),
+        }
     }
 
     pub fn ensure_gui(&mut self) -> anyhow::Result<&mut GUI> {
         if self.gui.is_none() {
-            self.gui = Some(GUI::new()?);
+            self.gui = Some(GUI::new(self.start)?);
         }
         Ok(self.gui.as_mut().unwrap())
     }
@@ -110,13 +124,14 @@ pub struct EnvRef(pub Rc<RefCell<Env>>);
 
 impl win32::Host for EnvRef {
     fn ticks(&self) -> u32 {
-        let mut env = self.0.borrow_mut();
-        let gui = env.ensure_gui().unwrap();
-        gui.time()
+        self.0.borrow().start.elapsed().as_millis() as u32
     }
 
     fn system_time(&self) -> chrono::DateTime<chrono::Local> {
-        chrono::Local::now()
+        self.0
+            .borrow()
+            .fixed_time
+            .unwrap_or_else(chrono::Local::now)
     }
 
     fn get_message(&self) -> Option<win32::Message> {
@@ -127,7 +142,7 @@ impl win32::Host for EnvRef {
 
     fn block(&self, wait: Option<u32>) -> bool {
         let mut env = self.0.borrow_mut();
-        let gui = env.gui.as_mut().unwrap();
+        let gui = env.ensure_gui().unwrap();
         gui.block(wait)
     }
 
@@ -204,6 +219,17 @@ impl win32::Host for EnvRef {
         std::io::stdout().lock().write_all(buf).unwrap();
     }
 
+    fn write_err(&self, buf: &[u8]) {
+        std::io::stderr().lock().write_all(buf).unwrap();
+    }
+
+    fn special_folder(&self, folder: win32::SpecialFolder) -> Option<WindowsPathBuf> {
+        match self.0.borrow().special_folders.get(&folder) {
+            Some(path) => Some(host_to_windows_path(path)),
+            None => self.current_dir().ok(),
+        }
+    }
+
     fn create_window(&mut self, hwnd: u32) -> Box<dyn win32::Window> {
         let mut env = self.0.borrow_mut();
         let gui = env.ensure_gui().unwrap();
@@ -219,6 +245,21 @@ impl win32::Host for EnvRef {
         let gui = env.ensure_gui().unwrap();
         gui.create_surface(opts)
     }
+
+    #[cfg(feature = "sdl")]
+    fn open_audio(&mut self, format: &win32::AudioFormat) -> Box<dyn win32::Audio> {
+        let mut env = self.0.borrow_mut();
+        let gui = env.ensure_gui().unwrap();
+        gui.open_audio(format)
+    }
+
+    #[cfg(not(feature = "sdl"))]
+    fn capture_frame(&self) -> (u32, u32, Vec<u8>) {
+        match &self.0.borrow().gui {
+            Some(gui) => gui.capture_frame(),
+            None => (0, 0, Vec::new()),
+        }
+    }
 }
 
 pub fn new_host() -> EnvRef {
@@ -286,3 +327,26 @@ fn windows_to_host_path(path: &WindowsPath) -> PathBuf {
 fn host_to_windows_path(path: &Path) -> WindowsPathBuf {
     WindowsPathBuf::from(path.as_os_str().as_encoded_bytes())
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use win32::{Host, SpecialFolder};
+
+    #[test]
+    fn special_folders() {
+        let host = new_host();
+        host.0
+            .borrow_mut()
+            .special_folders
+            .insert(SpecialFolder::AppData, PathBuf::from("/home/user/appdata"));
+        assert_eq!(
+            host.special_folder(SpecialFolder::AppData),
+            Some(WindowsPathBuf::from(r"Z:\home\user\appdata"))
+        );
+        assert_eq!(
+            host.special_folder(SpecialFolder::Personal),
+            host.current_dir().ok()
+        );
+    }
+}
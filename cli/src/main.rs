@@ -1,3 +1,7 @@
+#[cfg(feature = "x86-emu")]
+mod gdb;
+#[cfg(all(feature = "x86-emu", not(feature = "sdl")))]
+mod golden;
 mod host;
 mod logging;
 
@@ -30,6 +34,21 @@ struct Args {
     #[cfg(feature = "x86-emu")]
     trace_blocks: bool,
 
+    /// log every executed instruction with register changes to this file
+    #[argh(option)]
+    #[cfg(feature = "x86-emu")]
+    trace_instrs: Option<String>,
+
+    /// with --trace-instrs, keep only the last N instructions and write them on crash
+    #[argh(option)]
+    #[cfg(feature = "x86-emu")]
+    trace_ring: Option<usize>,
+
+    /// wait for a gdb connection on this port before running
+    #[argh(option)]
+    #[cfg(feature = "x86-emu")]
+    gdb: Option<u16>,
+
     /// log CPU state first time each point reached
     #[argh(option, from_str_fn(parse_trace_points))]
     trace_points: Option<std::collections::VecDeque<u32>>,
@@ -38,6 +57,54 @@ struct Args {
     #[argh(switch)]
     debug: bool,
 
+    /// set an environment variable for the program, as NAME=value
+    #[argh(option)]
+    env: Vec<String>,
+
+    /// use a host directory as a well-known folder, as NAME=DIR where NAME is one of
+    /// desktop, documents, appdata, localappdata, commonappdata, windows, system or
+    /// programfiles; unset folders are the current directory
+    #[argh(option, from_str_fn(parse_special_folder))]
+    folder: Vec<(win32::SpecialFolder, std::path::PathBuf)>,
+
+    /// seed the registry from this .reg file (as exported by regedit)
+    #[argh(option)]
+    registry: Option<String>,
+
+    /// stop after the program presents this many frames
+    #[argh(option)]
+    #[cfg(all(feature = "x86-emu", not(feature = "sdl")))]
+    frames: Option<usize>,
+
+    /// compare the last presented frame against this PNG, failing if it differs
+    #[argh(option)]
+    #[cfg(all(feature = "x86-emu", not(feature = "sdl")))]
+    golden: Option<String>,
+
+    /// with --golden, the allowed per-channel difference
+    #[argh(option, default = "0")]
+    #[cfg(all(feature = "x86-emu", not(feature = "sdl")))]
+    tolerance: u8,
+
+    /// with --golden, write the last presented frame to the PNG instead of comparing
+    #[argh(switch)]
+    #[cfg(all(feature = "x86-emu", not(feature = "sdl")))]
+    update_golden: bool,
+
+    /// report this fixed wall clock time (RFC 3339, e.g. 2000-01-01T00:00:00Z) to the
+    /// program rather than the current time, for reproducible runs
+    #[argh(option, from_str_fn(parse_time))]
+    fixed_time: Option<chrono::DateTime<chrono::Local>>,
+
+    /// how to call the exe's entry point: "windows" (default), "main" to pass
+    /// WinMain/main arguments to an exe without a CRT, or "raw" for no arguments
+    #[argh(option, from_str_fn(parse_entry_mode))]
+    entry: Option<win32::winapi::kernel32::EntryMode>,
+
+    /// count and time win32 calls (and sample x86 code), reporting on exit
+    #[argh(switch)]
+    profile: bool,
+
     /// command line to run
     #[argh(positional, greedy)]
     cmdline: Vec<String>,
@@ -94,6 +161,41 @@ fn parse_trace_points(param: &str) -> Result<std::collections::VecDeque<u32>, St
     Ok(trace_points)
 }
 
+fn parse_entry_mode(param: &str) -> Result<win32::winapi::kernel32::EntryMode, String> {
+    use win32::winapi::kernel32::EntryMode;
+    match param {
+        "windows" => Ok(EntryMode::Windows),
+        "main" => Ok(EntryMode::Main),
+        "raw" => Ok(EntryMode::Raw),
+        _ => Err(format!("bad entry mode {param:?}")),
+    }
+}
+
+fn parse_special_folder(param: &str) -> Result<(win32::SpecialFolder, std::path::PathBuf), String> {
+    use win32::SpecialFolder;
+    let (name, dir) = param
+        .split_once('=')
+        .ok_or_else(|| format!("bad folder {param:?}: expected NAME=DIR"))?;
+    let folder = match name {
+        "desktop" => SpecialFolder::Desktop,
+        "documents" => SpecialFolder::Personal,
+        "appdata" => SpecialFolder::AppData,
+        "localappdata" => SpecialFolder::LocalAppData,
+        "commonappdata" => SpecialFolder::CommonAppData,
+        "windows" => SpecialFolder::Windows,
+        "system" => SpecialFolder::System,
+        "programfiles" => SpecialFolder::ProgramFiles,
+        _ => return Err(format!("bad folder name {name:?}")),
+    };
+    Ok((folder, dir.into()))
+}
+
+fn parse_time(param: &str) -> Result<chrono::DateTime<chrono::Local>, String> {
+    chrono::DateTime::parse_from_rfc3339(param)
+        .map(|time| time.with_timezone(&chrono::Local))
+        .map_err(|err| format!("bad time {param:?}: {err}"))
+}
+
 fn main() -> anyhow::Result<ExitCode> {
     #[cfg(feature = "x86-64")]
     unsafe {
@@ -120,6 +222,11 @@ fn main() -> anyhow::Result<ExitCode> {
     let exe = std::fs::canonicalize(exe).map_err(|err| anyhow!("{}: {}", exe, err))?;
     let buf = std::fs::read(&exe).map_err(|err| anyhow!("{}: {}", exe.display(), err))?;
     let host = host::new_host();
+    host.0.borrow_mut().fixed_time = args.fixed_time;
+    for (folder, dir) in &args.folder {
+        let dir = std::path::absolute(dir).map_err(|err| anyhow!("{}: {}", dir.display(), err))?;
+        host.0.borrow_mut().special_folders.insert(*folder, dir);
+    }
 
     let mut cmdline = args.cmdline.clone();
     let cwd = host
@@ -136,6 +243,27 @@ fn main() -> anyhow::Result<ExitCode> {
         .collect::<Vec<_>>()
         .join(" ");
     let mut machine = win32::Machine::new(Box::new(host.clone()), cmdline);
+    for var in &args.env {
+        let (name, value) = var
+            .split_once('=')
+            .ok_or_else(|| anyhow!("--env {var:?}: expected NAME=value"))?;
+        machine.state.kernel32.env.set(name, Some(value));
+    }
+    if let Some(path) = &args.registry {
+        let text = std::fs::read_to_string(path).map_err(|err| anyhow!("{path}: {err}"))?;
+        machine
+            .state
+            .advapi32
+            .registry
+            .load_reg(&text)
+            .map_err(|err| anyhow!("{path}: {err}"))?;
+    }
+    if let Some(mode) = args.entry {
+        machine.state.kernel32.entry_mode = mode;
+    }
+    if args.profile {
+        machine.profiler = Some(Default::default());
+    }
 
     let addrs = machine
         .load_exe(&buf, &exe, None)
@@ -159,6 +287,18 @@ fn main() -> anyhow::Result<ExitCode> {
     {
         _ = addrs;
 
+        if let Some(path) = &args.trace_instrs {
+            let out = std::io::BufWriter::new(
+                std::fs::File::create(path).map_err(|err| anyhow!("{}: {}", path, err))?,
+            );
+            let trace = match args.trace_ring {
+                Some(0) => return Err(anyhow!("--trace-ring must be at least 1")),
+                Some(size) => x86::itrace::InstrTrace::new_ring(Box::new(out), size),
+                None => x86::itrace::InstrTrace::new(Box::new(out)),
+            };
+            machine.emu.x86.trace = Some(Box::new(trace));
+        }
+
         let start = std::time::Instant::now();
         if args.trace_blocks {
             let mut seen_blocks = std::collections::HashSet::new();
@@ -181,11 +321,19 @@ fn main() -> anyhow::Result<ExitCode> {
                     }
                 }
                 machine.clear_breakpoint(next_trace);
+                machine.unblock();
 
                 print_trace(&machine);
             }
-        } else {
+        } else if let Some(port) = args.gdb {
+            gdb::serve(&mut machine, port)?;
             while machine.run() {}
+        } else {
+            #[cfg(not(feature = "sdl"))]
+            if let Some(frames) = args.frames {
+                golden::run_frames(&mut machine, &host, frames);
+            }
+            while machine.status.is_running() && machine.run() {}
         }
 
         match &machine.status {
@@ -197,8 +345,21 @@ fn main() -> anyhow::Result<ExitCode> {
                 machine.dump_state(0);
                 exit_code = 1;
             }
+            win32::Status::DebugBreak => {
+                log::error!("stopped at debug break");
+                machine.dump_state(0);
+                exit_code = 1;
+            }
             _ => unreachable!(),
         }
+        if let Some(trace) = &mut machine.emu.x86.trace {
+            trace.dump();
+        }
+
+        #[cfg(not(feature = "sdl"))]
+        if let Some(path) = &args.golden {
+            golden::check(&host, path, args.tolerance, args.update_golden)?;
+        }
 
         let millis = start.elapsed().as_millis() as usize;
         if millis > 0 {
@@ -232,6 +393,10 @@ fn main() -> anyhow::Result<ExitCode> {
         }
     }
 
+    if let Some(profiler) = &machine.profiler {
+        eprint!("{}", profiler.report(&machine.labels));
+    }
+
     Ok(ExitCode::from(exit_code as u8))
 }
 
@@ -239,15 +404,26 @@ fn escape_arg(arg: &str) -> Cow<str> {
     if arg.contains(['"', ' ', '\t', '\n'].as_ref()) {
         let mut escaped = String::with_capacity(arg.len() + 2);
         escaped.push('"');
+        // Backslashes are only special when they precede a quote, in which case
+        // they must be doubled; see CommandLineToArgvW.
+        let mut backslashes = 0;
         for c in arg.chars() {
             match c {
+                '\\' => backslashes += 1,
                 '"' => {
-                    escaped.push('\\');
-                    escaped.push(c);
+                    escaped.extend(std::iter::repeat('\\').take(backslashes * 2 + 1));
+                    backslashes = 0;
+                }
+                _ => {
+                    escaped.extend(std::iter::repeat('\\').take(backslashes));
+                    backslashes = 0;
                 }
-                _ => escaped.push(c),
+            }
+            if c != '\\' {
+                escaped.push(c);
             }
         }
+        escaped.extend(std::iter::repeat('\\').take(backslashes * 2));
         escaped.push('"');
         Cow::Owned(escaped)
     } else {
@@ -12,6 +12,47 @@ fn map_button(b: sdl2::mouse::MouseButton) -> Option<win32::MouseButton> {
     })
 }
 
+/// Map an SDL keycode to a Windows virtual-key code.
+fn map_key(key: sdl2::keyboard::Keycode) -> Option<u32> {
+    use sdl2::keyboard::Keycode;
+    use win32::winapi::user32::VK;
+    let code = key as i32;
+    match code {
+        // SDL keycodes for letters are lowercase ASCII; VKs are uppercase.
+        0x61..=0x7A => return Some((code - 0x20) as u32),
+        0x30..=0x39 => return Some(code as u32),
+        _ => {}
+    }
+    let f1 = Keycode::F1 as i32;
+    if (f1..=Keycode::F12 as i32).contains(&code) {
+        return Some((code - f1) as u32 + VK::F1 as u32);
+    }
+    let vk = match key {
+        Keycode::Backspace => VK::BACK,
+        Keycode::Tab => VK::TAB,
+        Keycode::Return => VK::RETURN,
+        Keycode::LShift | Keycode::RShift => VK::SHIFT,
+        Keycode::LCtrl | Keycode::RCtrl => VK::CONTROL,
+        Keycode::LAlt | Keycode::RAlt => VK::MENU,
+        Keycode::Pause => VK::PAUSE,
+        Keycode::CapsLock => VK::CAPITAL,
+        Keycode::Escape => VK::ESCAPE,
+        Keycode::Space => VK::SPACE,
+        Keycode::PageUp => VK::PRIOR,
+        Keycode::PageDown => VK::NEXT,
+        Keycode::End => VK::END,
+        Keycode::Home => VK::HOME,
+        Keycode::Left => VK::LEFT,
+        Keycode::Up => VK::UP,
+        Keycode::Right => VK::RIGHT,
+        Keycode::Down => VK::DOWN,
+        Keycode::Insert => VK::INSERT,
+        Keycode::Delete => VK::DELETE,
+        _ => return None,
+    };
+    Some(vk as u32)
+}
+
 fn message_from_event(hwnd: u32, event: sdl2::event::Event) -> Option<win32::Message> {
     let (time, detail) = match event {
         sdl2::event::Event::Quit { timestamp } => (timestamp, win32::MessageDetail::Quit),
@@ -56,6 +97,52 @@ fn message_from_event(hwnd: u32, event: sdl2::event::Event) -> Option<win32::Mes
                 y: y as u32,
             }),
         ),
+        sdl2::event::Event::MouseWheel {
+            timestamp,
+            y,
+            direction,
+            ..
+        } => (
+            timestamp,
+            win32::MessageDetail::MouseWheel(win32::MouseWheelMessage {
+                delta: match direction {
+                    sdl2::mouse::MouseWheelDirection::Flipped => -y,
+                    _ => y,
+                },
+            }),
+        ),
+        sdl2::event::Event::KeyDown {
+            timestamp,
+            keycode: Some(keycode),
+            ..
+        } => (
+            timestamp,
+            win32::MessageDetail::Key(win32::KeyMessage {
+                down: true,
+                vk: map_key(keycode)?,
+            }),
+        ),
+        sdl2::event::Event::KeyUp {
+            timestamp,
+            keycode: Some(keycode),
+            ..
+        } => (
+            timestamp,
+            win32::MessageDetail::Key(win32::KeyMessage {
+                down: false,
+                vk: map_key(keycode)?,
+            }),
+        ),
+        sdl2::event::Event::Window {
+            timestamp,
+            win_event: sdl2::event::WindowEvent::FocusGained,
+            ..
+        } => (timestamp, win32::MessageDetail::Activate(true)),
+        sdl2::event::Event::Window {
+            timestamp,
+            win_event: sdl2::event::WindowEvent::FocusLost,
+            ..
+        } => (timestamp, win32::MessageDetail::Activate(false)),
         _ => {
             // log::warn!("unhandled event: {:?}", event);
             return None;
@@ -79,31 +166,50 @@ fn message_from_events(
 
 pub struct GUI {
     video: sdl2::VideoSubsystem,
+    /// None if the host has no sound device, in which case audio is discarded.
+    audio: Option<sdl2::AudioSubsystem>,
     pump: sdl2::EventPump,
-    timer: sdl2::TimerSubsystem,
+    /// Process start, shared with Host::ticks().
+    start: std::time::Instant,
+    /// SDL event timestamps count from SDL init; this is the offset from start to then.
+    sdl_epoch: u32,
     win: Option<WindowRef>,
     msg_queue: Option<win32::Message>,
 }
 
 impl GUI {
-    pub fn new() -> anyhow::Result<Self> {
+    pub fn new(start: std::time::Instant) -> anyhow::Result<Self> {
         assert!(sdl2::hint::set("SDL_NO_SIGNAL_HANDLERS", "1"));
         let sdl = sdl2::init().map_err(|err| anyhow::anyhow!(err))?;
         let video = sdl.video().map_err(|err| anyhow::anyhow!(err))?;
+        let audio = sdl
+            .audio()
+            .map_err(|err| log::warn!("no audio: {err}"))
+            .ok();
         let pump = sdl.event_pump().map_err(|err| anyhow::anyhow!(err))?;
         let timer = sdl.timer().map_err(|err| anyhow::anyhow!(err))?;
+        let sdl_epoch = (start.elapsed().as_millis() as u32).saturating_sub(timer.ticks());
 
         Ok(GUI {
             video,
+            audio,
             pump,
-            timer,
+            start,
+            sdl_epoch,
             win: None,
             msg_queue: None,
         })
     }
 
     pub fn time(&self) -> u32 {
-        self.timer.ticks()
+        self.start.elapsed().as_millis() as u32
+    }
+
+    fn rebase(&self, msg: Option<win32::Message>) -> Option<win32::Message> {
+        msg.map(|msg| win32::Message {
+            time: msg.time + self.sdl_epoch,
+            ..msg
+        })
     }
 
     pub fn get_message(&mut self) -> Option<win32::Message> {
@@ -114,7 +220,8 @@ impl GUI {
             Some(w) => w.0.borrow().hwnd,
             None => 0,
         };
-        message_from_events(hwnd, || self.pump.poll_event())
+        let msg = message_from_events(hwnd, || self.pump.poll_event());
+        self.rebase(msg)
     }
 
     pub fn block(&mut self, wait: Option<u32>) -> bool {
@@ -139,7 +246,7 @@ impl GUI {
             },
         };
         assert!(self.msg_queue.is_none());
-        self.msg_queue = msg;
+        self.msg_queue = self.rebase(msg);
         true
     }
 
@@ -157,6 +264,58 @@ impl GUI {
     pub fn create_surface(&mut self, opts: &win32::SurfaceOptions) -> Box<dyn win32::Surface> {
         Box::new(Texture::new(self.win.as_ref().unwrap(), opts))
     }
+
+    pub fn open_audio(&mut self, format: &win32::AudioFormat) -> Box<dyn win32::Audio> {
+        let Some(audio) = &self.audio else {
+            return Box::new(win32::NullAudio);
+        };
+        let spec = sdl2::audio::AudioSpecDesired {
+            freq: Some(format.sample_rate as i32),
+            channels: Some(format.channels as u8),
+            samples: None,
+        };
+        let queue: Result<Box<dyn win32::Audio>, String> = match format.bits_per_sample {
+            8 => audio
+                .open_queue::<u8, _>(None::<&str>, &spec)
+                .map(|queue| Box::new(AudioQueue(queue)) as Box<dyn win32::Audio>),
+            16 => audio
+                .open_queue::<i16, _>(None::<&str>, &spec)
+                .map(|queue| Box::new(AudioQueue(queue)) as Box<dyn win32::Audio>),
+            bits => Err(format!("unsupported {bits} bits per sample")),
+        };
+        match queue {
+            Ok(queue) => queue,
+            Err(err) => {
+                log::warn!("open audio {format:?}: {err}");
+                Box::new(win32::NullAudio)
+            }
+        }
+    }
+}
+
+/// A playing SDL audio queue, fed with samples in the format it was opened with.
+struct AudioQueue<T: sdl2::audio::AudioFormatNum>(sdl2::audio::AudioQueue<T>);
+
+impl win32::Audio for AudioQueue<u8> {
+    fn queue(&mut self, samples: &[u8]) {
+        if let Err(err) = self.0.queue_audio(samples) {
+            log::warn!("queue audio: {err}");
+        }
+        self.0.resume();
+    }
+}
+
+impl win32::Audio for AudioQueue<i16> {
+    fn queue(&mut self, samples: &[u8]) {
+        let samples = samples
+            .chunks_exact(2)
+            .map(|s| i16::from_le_bytes([s[0], s[1]]))
+            .collect::<Vec<_>>();
+        if let Err(err) = self.0.queue_audio(&samples) {
+            log::warn!("queue audio: {err}");
+        }
+        self.0.resume();
+    }
 }
 
 struct Window {
@@ -192,6 +351,16 @@ impl win32::Window for WindowRef {
             .unwrap();
     }
 
+    fn set_visible(&mut self, visible: bool) {
+        let mut win = self.0.borrow_mut();
+        let win = win.canvas.window_mut();
+        if visible {
+            win.show();
+        } else {
+            win.hide();
+        }
+    }
+
     fn fullscreen(&mut self) {
         log::info!("fullscreen request ignored for debugging ease");
         // self.0
@@ -242,6 +411,15 @@ impl win32::Surface for Texture {
             .unwrap();
     }
 
+    fn write_pixels_rect(&mut self, x: u32, y: u32, w: u32, h: u32, pixels: &[[u8; 4]]) {
+        let pixels_u8 =
+            unsafe { std::slice::from_raw_parts(pixels.as_ptr() as *const u8, pixels.len() * 4) };
+        let rect = sdl2::rect::Rect::new(x as i32, y as i32, w, h);
+        self.texture
+            .update(rect, pixels_u8, w as usize * 4)
+            .unwrap();
+    }
+
     fn show(&mut self) {
         let canvas = &mut self.window.0.borrow_mut().canvas;
         // Passing None/None for the src/dst rects means to do a scaling full copy,
@@ -273,4 +451,31 @@ impl win32::Surface for Texture {
             })
             .unwrap();
     }
+
+    fn stretch_blt(
+        &mut self,
+        dx: u32,
+        dy: u32,
+        dw: u32,
+        dh: u32,
+        src: &dyn win32::Surface,
+        sx: u32,
+        sy: u32,
+        sw: u32,
+        sh: u32,
+    ) {
+        let src_rect = sdl2::rect::Rect::new(sx as i32, sy as i32, sw, sh);
+        let src = &unsafe { &*(src as *const dyn win32::Surface as *const Texture) }.texture;
+        let dst_rect = sdl2::rect::Rect::new(dx as i32, dy as i32, dw, dh);
+
+        // SDL copies scale to the destination rect; textures default to nearest-neighbor.
+        self.window
+            .0
+            .borrow_mut()
+            .canvas
+            .with_texture_canvas(&mut self.texture, |canvas| {
+                canvas.copy(src, src_rect, dst_rect).unwrap()
+            })
+            .unwrap();
+    }
 }
@@ -0,0 +1,9 @@
+#!/bin/sh
+
+set -e
+
+clang_flags="-fuse-ld=lld -target i586-pc-windows-msvc"
+link_flags="/Brepro /safeseh:no /nodefaultlib /subsystem:console"
+
+clang-cl $clang_flags local.s /link $link_flags /dll /noentry /def:local.def /out:local.dll
+clang-cl $clang_flags main.s /link $link_flags /entry:mainCRTStartup /out:main.exe local.lib
@@ -0,0 +1,6 @@
+# A DLL that isn't a builtin, for loading from the exe's directory.
+.intel_syntax noprefix
+.globl _answer
+_answer:
+  mov eax, 42
+  ret
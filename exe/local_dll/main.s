@@ -0,0 +1,6 @@
+# Exits with the result of a function imported from local.dll.
+.intel_syntax noprefix
+.globl _mainCRTStartup
+_mainCRTStartup:
+  call [__imp__answer]
+  ret
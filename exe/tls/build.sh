@@ -0,0 +1,8 @@
+#!/bin/sh
+
+set -e
+
+clang_flags="-fuse-ld=lld -target i586-pc-windows-msvc"
+link_flags="/Brepro /safeseh:no /nodefaultlib /subsystem:console"
+
+clang-cl $clang_flags tls.s /link $link_flags /entry:mainCRTStartup /out:tls.exe
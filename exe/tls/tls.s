@@ -0,0 +1,46 @@
+# Exe with a TLS callback, which must run before the entry point.
+# The callback copies a __declspec(thread) variable's initial value out of the
+# thread's TLS block; the exe exits with 0 if it sees it.
+.intel_syntax noprefix
+
+# The static TLS template, which the loader copies into each thread's block.
+.section .tls,"dw"
+_tls_start:
+_tls_value:
+  .long 0x7153C0DE
+_tls_end:
+
+.data
+__tls_index:
+  .long 0
+_sentinel:
+  .long 0
+_tls_callbacks:
+  .long _tls_callback, 0
+
+# Without the CRT (whose tlssup.obj normally provides it) we must supply the TLS
+# directory ourselves; the linker points IMAGE_DIRECTORY_ENTRY_TLS at __tls_used.
+.section .rdata,"dr"
+.globl __tls_used
+__tls_used:
+  .long _tls_start, _tls_end, __tls_index, _tls_callbacks, 0, 0
+
+.text
+# void NTAPI tls_callback(PVOID, DWORD reason, PVOID)
+_tls_callback:
+  cmp dword ptr [esp+8], 1 # DLL_PROCESS_ATTACH
+  jne 1f
+  mov eax, dword ptr fs:[0x2c] # TEB.ThreadLocalStoragePointer
+  mov ecx, dword ptr [__tls_index]
+  mov eax, dword ptr [eax+ecx*4]
+  mov eax, dword ptr [eax] # _tls_value, at the start of the block
+  mov dword ptr [_sentinel], eax
+1:
+  ret 12
+
+.globl _mainCRTStartup
+_mainCRTStartup:
+  xor eax, eax
+  cmp dword ptr [_sentinel], 0x7153C0DE
+  setne al
+  ret
@@ -56,6 +56,16 @@ impl win32::Surface for WebSurface {
         self.ctx.put_image_data(&image_data, 0.0, 0.0).unwrap();
     }
 
+    fn write_pixels_rect(&mut self, x: u32, y: u32, w: u32, _h: u32, pixels: &[[u8; 4]]) {
+        let slice =
+            unsafe { std::slice::from_raw_parts(pixels.as_ptr() as *const _, pixels.len() * 4) };
+        let image_data =
+            web_sys::ImageData::new_with_u8_clamped_array(wasm_bindgen::Clamped(slice), w).unwrap();
+        self.ctx
+            .put_image_data(&image_data, x as f64, y as f64)
+            .unwrap();
+    }
+
     fn show(&mut self) {
         self.screen
             .draw_image_with_html_canvas_element(&self.canvas, 0.0, 0.0)
@@ -90,6 +100,36 @@ impl win32::Surface for WebSurface {
             )
             .unwrap();
     }
+
+    fn stretch_blt(
+        &mut self,
+        dx: u32,
+        dy: u32,
+        dw: u32,
+        dh: u32,
+        src: &dyn win32::Surface,
+        sx: u32,
+        sy: u32,
+        sw: u32,
+        sh: u32,
+    ) {
+        let src = unsafe { &*(src as *const dyn win32::Surface as *const WebSurface) };
+        // DirectDraw stretches with nearest-neighbor sampling.
+        self.ctx.set_image_smoothing_enabled(false);
+        self.ctx
+            .draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                &src.canvas,
+                sx as f64,
+                sy as f64,
+                sw as f64,
+                sh as f64,
+                dx as f64,
+                dy as f64,
+                dw as f64,
+                dh as f64,
+            )
+            .unwrap();
+    }
 }
 
 #[wasm_bindgen(typescript_custom_section)]
@@ -97,6 +137,7 @@ const JSWINDOW_TS: &'static str = r#"
 export interface JsWindow {
   title: string;
   set_size(width: number, height: number): void;
+  set_visible(visible: boolean): void;
 }"#;
 
 #[wasm_bindgen]
@@ -106,6 +147,8 @@ extern "C" {
     fn set_title(this: &JsWindow, title: &str);
     #[wasm_bindgen(method)]
     fn set_size(this: &JsWindow, width: u32, height: u32);
+    #[wasm_bindgen(method)]
+    fn set_visible(this: &JsWindow, visible: bool);
 }
 
 impl win32::Window for JsWindow {
@@ -117,6 +160,10 @@ impl win32::Window for JsWindow {
         JsWindow::set_size(self, width, height);
     }
 
+    fn set_visible(&mut self, visible: bool) {
+        JsWindow::set_visible(self, visible);
+    }
+
     fn fullscreen(&mut self) {
         log::warn!("todo: fullscreen");
     }
@@ -231,6 +278,26 @@ fn message_from_event(event: web_sys::Event) -> anyhow::Result<win32::Message> {
             event.down = false;
             win32::MessageDetail::Mouse(event)
         }
+        "wheel" => {
+            let event = event.unchecked_into::<web_sys::WheelEvent>();
+            // DOM deltas grow downward and are in pixels/lines; only the direction is kept.
+            let delta = match event.delta_y() {
+                d if d < 0.0 => 1,
+                d if d > 0.0 => -1,
+                _ => bail!("unhandled horizontal wheel"),
+            };
+            win32::MessageDetail::MouseWheel(win32::MouseWheelMessage { delta })
+        }
+        "keydown" | "keyup" => {
+            let event = event.unchecked_into::<web_sys::KeyboardEvent>();
+            // The legacy keyCode values match Windows virtual-key codes.
+            win32::MessageDetail::Key(win32::KeyMessage {
+                down: event.type_() == "keydown",
+                vk: event.key_code(),
+            })
+        }
+        "focus" => win32::MessageDetail::Activate(true),
+        "blur" => win32::MessageDetail::Activate(false),
         ty => bail!("unhandled event type {ty}"),
     };
     log::info!("msg: {:?}", detail);
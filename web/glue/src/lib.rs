@@ -80,8 +80,9 @@ impl Emulator {
     }
 
     pub fn disassemble_json(&self, addr: u32, limit: usize) -> String {
-        serde_json::to_string(&x86::debug::disassemble(self.machine.mem(), addr, limit))
-            .unwrap_throw()
+        let mem = self.machine.mem();
+        let code = mem.slice(addr.min(mem.len())..);
+        serde_json::to_string(&x86::debug::disassemble(code, addr, limit)).unwrap_throw()
     }
 
     pub fn unblock(&mut self) {
@@ -107,6 +108,7 @@ impl Emulator {
         Ok(match &self.machine.status {
             win32::Status::Running => Status::Running,
             win32::Status::Blocked => Status::Blocked,
+            win32::Status::DebugBreak => Status::DebugBreak,
             win32::Status::Error { message } => return Err(JsError::new(message)),
             win32::Status::Exit(_code) => {
                 // TODO: use exit code
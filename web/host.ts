@@ -18,14 +18,28 @@ class Window implements glue.JsWindow {
     this.canvas.onmousedown = stashEvent;
     this.canvas.onmouseup = stashEvent;
     this.canvas.onmousemove = stashEvent;
+    this.canvas.onwheel = stashEvent;
+    // Make the canvas focusable so it receives key events.
+    this.canvas.tabIndex = 0;
+    this.canvas.onkeydown = stashEvent;
+    this.canvas.onkeyup = stashEvent;
+    // Focus changes become activation of the app's windows.
+    this.canvas.onfocus = stashEvent;
+    this.canvas.onblur = stashEvent;
     this.canvas.oncontextmenu = (ev) => {
       return false;
     };
   }
 
   title: string = '';
+  visible: boolean = true;
   canvas: HTMLCanvasElement = document.createElement('canvas');
 
+  set_visible(visible: boolean) {
+    this.visible = visible;
+    this.jsHost.emuHost.onWindowChanged();
+  }
+
   set_size(w: number, h: number) {
     // Note: the canvas must be sized to the size of physical pixels,
     // or else it will be scaled up and pixels will be blurry.
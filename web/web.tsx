@@ -69,7 +69,7 @@ namespace EmulatorComponent {
 }
 export class EmulatorComponent extends preact.Component<EmulatorComponent.Props> {
   render() {
-    return this.props.emulator.windows.map((window) => {
+    return this.props.emulator.windows.filter((window) => window.visible).map((window) => {
       return (
         <WindowComponent
           key={window.hwnd}
@@ -9,12 +9,29 @@ pub trait Surface {
     /// Used for copying an image to the surface via GDI calls, and for Lock/Unlock pixel writes.
     fn write_pixels(&mut self, pixels: &[[u8; 4]]);
 
+    /// Write RGBA pixel data to a w x h rectangle at x,y.  Used for Unlock of a sub-rectangle.
+    fn write_pixels_rect(&mut self, x: u32, y: u32, w: u32, h: u32, pixels: &[[u8; 4]]);
+
     /// Show the this surface as the foreground.  Called by ::Flip().
     fn show(&mut self);
 
     // TODO: the trait object here means we end up needing to cast, but the alternative
     // isn't object safe, bleh.
     fn bit_blt(&mut self, dx: u32, dy: u32, src: &dyn Surface, sx: u32, sy: u32, w: u32, h: u32);
+
+    /// Like bit_blt, but scaling the source rect to fill the destination rect.
+    fn stretch_blt(
+        &mut self,
+        dx: u32,
+        dy: u32,
+        dw: u32,
+        dh: u32,
+        src: &dyn Surface,
+        sx: u32,
+        sy: u32,
+        sw: u32,
+        sh: u32,
+    );
 }
 
 #[cfg_attr(feature = "wasm", wasm_bindgen::prelude::wasm_bindgen)]
@@ -38,11 +55,32 @@ impl Default for SurfaceOptions {
 pub trait Window {
     fn set_title(&mut self, title: &str);
     fn set_size(&mut self, width: u32, height: u32);
+    fn set_visible(&mut self, visible: bool);
     fn fullscreen(&mut self);
 }
 
+/// The PCM format of an audio stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+}
+
+/// Audio output stream, e.g. for a playing DirectSound buffer.
+pub trait Audio {
+    /// Append interleaved PCM samples, in the stream's format, to the output.
+    fn queue(&mut self, samples: &[u8]);
+}
+
+/// Audio output that discards everything, for hosts without sound.
+pub struct NullAudio;
+impl Audio for NullAudio {
+    fn queue(&mut self, _samples: &[u8]) {}
+}
+
 #[cfg_attr(feature = "wasm", wasm_bindgen::prelude::wasm_bindgen)]
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub struct FileOptions {
     /// Permit read access.
     pub read: bool,
@@ -113,10 +151,28 @@ pub struct MouseMessage {
     pub y: u32,
 }
 
+#[derive(Debug)]
+pub struct MouseWheelMessage {
+    /// Wheel clicks; positive is away from the user (scroll up).
+    pub delta: i32,
+}
+
+#[derive(Debug)]
+pub struct KeyMessage {
+    pub down: bool,
+    /// Windows virtual-key code, see winapi::user32::VK.
+    /// The scan code and other lParam bits are derived from it.
+    pub vk: u32,
+}
+
 #[derive(Debug)]
 pub enum MessageDetail {
     Quit,
     Mouse(MouseMessage),
+    MouseWheel(MouseWheelMessage),
+    Key(KeyMessage),
+    /// The host window gained (true) or lost (false) input focus, e.g. via alt-tab.
+    Activate(bool),
 }
 
 #[derive(Debug)]
@@ -126,9 +182,41 @@ pub struct Message {
     pub time: u32, // in units of Host::time()
 }
 
+/// A button in a message box.  The values match the IDOK etc. results of MessageBox.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageBoxButton {
+    Ok = 1,
+    Cancel = 2,
+    Abort = 3,
+    Retry = 4,
+    Ignore = 5,
+    Yes = 6,
+    No = 7,
+}
+
+/// A well-known folder, as located by SHGetFolderPath.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpecialFolder {
+    Desktop,
+    /// "My Documents".
+    Personal,
+    /// Per-user roaming application data.
+    AppData,
+    /// Per-user application data that stays on this machine.
+    LocalAppData,
+    /// Application data shared by all users.
+    CommonAppData,
+    Windows,
+    System,
+    ProgramFiles,
+}
+
 pub trait Host {
-    /// Get an arbitrary time counter, measured in milliseconds.
+    /// Milliseconds elapsed since the process started.  Must be monotonic, as it
+    /// backs GetTickCount and Sleep deadlines passed to block().
     fn ticks(&self) -> u32;
+    /// The wall clock time, backing GetSystemTime and friends.  Unlike ticks() this
+    /// may jump around, and hosts may pin it to a fixed instant for reproducibility.
     fn system_time(&self) -> chrono::DateTime<chrono::Local>;
 
     /// Get the next pending message, or None if no message waiting.
@@ -154,8 +242,67 @@ pub trait Host {
     fn remove_file(&self, path: &WindowsPath) -> Result<(), ERROR>;
     /// Remove a directory at the given (Windows-style) path.
     fn remove_dir(&self, path: &WindowsPath) -> Result<(), ERROR>;
+    /// Write to the process's stdout.
     fn log(&self, buf: &[u8]);
+    /// Write to the process's stderr.  Hosts without a separate error stream can
+    /// leave this as the default, which shares stdout.
+    fn write_err(&self, buf: &[u8]) {
+        self.log(buf)
+    }
+    /// Report a string from OutputDebugString, as a debugger would display it.
+    fn debug_string(&self, msg: &str) {
+        log::debug!("OutputDebugString: {msg}");
+    }
+    /// The SW_* state the exe's main window was asked to start in, as a shortcut's
+    /// "Run: Minimized" would request.  Reported to the exe via GetStartupInfo.
+    fn initial_show_state(&self) -> u16 {
+        1 // SW_SHOWNORMAL
+    }
+
+    /// Show a modal message box offering the given buttons, and return the chosen one.
+    /// Headless hosts can leave this as the default, which logs the message and picks
+    /// buttons[default] so that automated runs don't hang.
+    fn message_box(
+        &mut self,
+        text: &str,
+        caption: &str,
+        buttons: &[MessageBoxButton],
+        default: usize,
+    ) -> MessageBoxButton {
+        self.log(format!("MessageBox: {caption}\n{text}").as_bytes());
+        buttons[default]
+    }
+
+    /// Hand a file or URL to the host's own handler for the verb, as ShellExecute does.
+    /// Hosts that can't launch anything can leave this as the default, which logs the
+    /// request and reports success.
+    fn open_external(&mut self, verb: &str, target: &str) -> Result<(), ERROR> {
+        self.log(format!("ShellExecute: {verb} {target}").as_bytes());
+        Ok(())
+    }
+
+    /// The (Windows-style) path of a well-known folder, or None if there isn't one.
+    /// Hosts without a notion of user folders can leave this as the default, which
+    /// puts them all in the current directory.
+    fn special_folder(&self, folder: SpecialFolder) -> Option<WindowsPathBuf> {
+        log::info!("no {folder:?} folder, using the current directory");
+        self.current_dir().ok()
+    }
 
     fn create_window(&mut self, hwnd: u32) -> Box<dyn Window>;
     fn create_surface(&mut self, hwnd: u32, opts: &SurfaceOptions) -> Box<dyn Surface>;
+
+    /// The frame most recently shown via Surface::show(), as (width, height, RGBA pixels),
+    /// for comparing rendering against known-good images.  Hosts that can't read back
+    /// their display can leave this as the default, which returns an empty frame.
+    fn capture_frame(&self) -> (u32, u32, Vec<u8>) {
+        (0, 0, Vec::new())
+    }
+
+    /// Open an audio output stream.  Hosts without sound can leave this as the
+    /// default, which discards the samples.
+    fn open_audio(&mut self, format: &AudioFormat) -> Box<dyn Audio> {
+        log::info!("no audio output for {format:?}");
+        Box::new(NullAudio)
+    }
 }
@@ -1,6 +1,7 @@
 mod host;
 mod machine;
 pub mod pe;
+pub mod profile;
 mod segments;
 pub mod shims;
 pub mod str16;
@@ -9,6 +10,10 @@ pub mod winapi;
 
 #[cfg(feature = "x86-emu")]
 mod machine_emu;
+#[cfg(feature = "x86-emu")]
+mod save_state;
+#[cfg(all(any(test, feature = "testing"), feature = "x86-emu"))]
+pub mod testing;
 
 #[cfg(feature = "x86-64")]
 mod ldt;
@@ -22,3 +27,5 @@ mod machine_unicorn;
 
 pub use host::*;
 pub use machine::{Machine, Status};
+#[cfg(feature = "x86-emu")]
+pub use machine_emu::{RunResult, RunStatus};
@@ -1,4 +1,4 @@
-use crate::{host, winapi};
+use crate::{host, profile::Profiler, winapi};
 use std::{collections::HashMap, path::PathBuf};
 
 #[cfg(feature = "x86-emu")]
@@ -21,6 +21,8 @@ pub struct MachineX<Emu> {
     pub labels: HashMap<u32, String>,
     pub exe_path: PathBuf,
     pub status: Status,
+    /// Set to collect profiling data; see profile.rs.
+    pub profiler: Option<Profiler>,
 }
 
 /// Status of the machine/process.  Separate from CPU state because multiple threads
@@ -32,6 +34,8 @@ pub enum Status {
     Running,
     /// All threads are blocked awaiting results.
     Blocked,
+    /// Stopped at a breakpoint or watchpoint; unblock() to continue.
+    DebugBreak,
     /// CPU error.
     Error {
         message: String,
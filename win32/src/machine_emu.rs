@@ -10,6 +10,26 @@ use crate::{
 use memory::{Extensions, ExtensionsMut, Mem};
 use std::{collections::HashMap, path::Path};
 
+/// Why run_for() returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunStatus {
+    /// The process exited with the given code.
+    Exited(u32),
+    /// Stopped with all threads blocked or at a breakpoint; it may be resumed.
+    Halted,
+    /// Ran the requested number of instructions and is still running.
+    BudgetExhausted,
+    /// Stopped on a CPU error.
+    Faulted(String),
+}
+
+#[derive(Debug)]
+pub struct RunResult {
+    pub status: RunStatus,
+    /// Instructions executed during the run.
+    pub instructions: usize,
+}
+
 pub struct BoxMem(Box<[u8]>);
 
 impl BoxMem {
@@ -71,6 +91,7 @@ impl MachineX<Emulator> {
             labels: HashMap::new(),
             exe_path: Default::default(),
             status: Default::default(),
+            profiler: None,
         }
     }
 
@@ -164,22 +185,121 @@ impl MachineX<Emulator> {
                     self.status = Status::Blocked;
                 }
             }
+            x86::CPUState::Fault(fault) => {
+                let fault = fault.clone();
+                self.fault(fault);
+            }
             x86::CPUState::Error(message) => {
                 self.status = Status::Error {
                     message: message.clone(),
                 };
             }
-            state => unimplemented!("{state:?}"),
+            x86::CPUState::DebugBreak => self.status = Status::DebugBreak,
+            x86::CPUState::Suspended | x86::CPUState::Exited | x86::CPUState::Waiting => {
+                self.status = Status::Error {
+                    message: "deadlock: no threads can run".into(),
+                };
+            }
         }
         self.status.is_running()
     }
 
+    /// Run at most max_insns instructions, stopping early if the machine stops.
+    /// Unlike run(), this returns only once it's done, servicing host events and async
+    /// shims along the way; use a budget for harnesses that must regain control.
+    pub fn run_for(&mut self, max_insns: usize) -> RunResult {
+        let start = self.emu.x86.instr_count;
+        self.emu.x86.budget = max_insns;
+        let status = loop {
+            match &self.status {
+                Status::Running => {}
+                Status::Exit(code) => break RunStatus::Exited(*code),
+                Status::Blocked | Status::DebugBreak => break RunStatus::Halted,
+                Status::Error { message } => break RunStatus::Faulted(message.clone()),
+            }
+            if self.emu.x86.budget == 0 {
+                break RunStatus::BudgetExhausted;
+            }
+            self.run();
+        };
+        self.emu.x86.budget = usize::MAX;
+        RunResult {
+            status,
+            instructions: self.emu.x86.instr_count.wrapping_sub(start),
+        }
+    }
+
     fn execute_block(&mut self) {
-        self.emu.x86.execute_block(self.emu.memory.mem())
+        if self.state.kernel32.mappings.take_changed() {
+            self.sync_memory_map();
+        }
+        if let Some(trace) = &mut self.emu.x86.trace {
+            // Labels only grow as modules are loaded, so a size change means new symbols.
+            if trace.symbols.len() != self.labels.len() {
+                trace.symbols = self.labels.clone();
+            }
+        }
+        self.emu.x86.execute_block(self.emu.memory.mem());
+        if let Some(profiler) = &mut self.profiler {
+            profiler.sample(self.emu.x86.instr_count, self.emu.x86.cpu().regs.eip);
+        }
+    }
+
+    /// Mirror the kernel's mappings into the CPU, which checks memory accesses against them.
+    fn sync_memory_map(&mut self) {
+        let regions = self
+            .state
+            .kernel32
+            .mappings
+            .vec()
+            .iter()
+            .map(|mapping| {
+                let flags = if mapping.committed {
+                    mapping.flags
+                } else {
+                    pe::ImageSectionFlags::empty()
+                };
+                let mut protect = x86::Protect::empty();
+                // Writable or executable memory is always readable on x86.
+                if flags.intersects(
+                    pe::ImageSectionFlags::MEM_READ
+                        | pe::ImageSectionFlags::MEM_WRITE
+                        | pe::ImageSectionFlags::MEM_EXECUTE,
+                ) {
+                    protect |= x86::Protect::READ;
+                }
+                if flags.contains(pe::ImageSectionFlags::MEM_WRITE) {
+                    protect |= x86::Protect::WRITE;
+                }
+                if flags.contains(pe::ImageSectionFlags::MEM_EXECUTE) {
+                    protect |= x86::Protect::EXECUTE;
+                }
+                x86::Region {
+                    start: mapping.addr,
+                    end: mapping.addr + mapping.size,
+                    protect,
+                }
+            })
+            .collect();
+        self.emu.x86.set_memory_map(x86::MemoryMap::new(regions));
+    }
+
+    /// Hand a CPU fault to the SEH dispatcher, which runs as a future on the faulting CPU.
+    fn fault(&mut self, fault: x86::Fault) {
+        self.emu.x86.cpu_mut().state = x86::CPUState::Running;
+        let context = winapi::kernel32::CONTEXT::capture(self.emu.x86.cpu());
+        let machine: *mut Machine = self;
+        self.emu.x86.cpu_mut().run_async(Box::pin(async move {
+            let machine = unsafe { &mut *machine };
+            winapi::kernel32::dispatch_fault(machine, fault, context).await;
+        }));
     }
 
     fn syscall(&mut self) {
         self.emu.x86.cpu_mut().state = x86::CPUState::Running;
+        // Syscalls are frequent enough to keep rdtsc roughly in step with the host clock,
+        // without paying for a clock read per instruction.
+        self.emu.x86.set_host_time(self.host.ticks());
 
         // See doc/shims.md for the state of the stack when we get here.
 
@@ -198,9 +318,14 @@ impl MachineX<Emulator> {
         };
 
         let stack_args = esp + 8;
+        let name = shim.name;
         match shim.func {
             Handler::Sync(func) => {
+                let start = self.profiler.as_ref().map(|_| std::time::Instant::now());
                 let ret = unsafe { func(self, stack_args) };
+                if let (Some(profiler), Some(start)) = (&mut self.profiler, start) {
+                    profiler.record_shim(name, start.elapsed());
+                }
                 let regs = &mut self.emu.x86.cpu_mut().regs;
                 regs.set32(x86::Register::EAX, ret);
 
@@ -212,18 +337,27 @@ impl MachineX<Emulator> {
 
             Handler::Async(func) => {
                 let eip = regs.eip; // return address
-                let future = unsafe { func(self, stack_args) };
+                let mut future = unsafe { func(self, stack_args) };
+                if self.profiler.is_some() {
+                    future = crate::profile::time_async(self, name, future);
+                }
                 self.emu.x86.cpu_mut().call_async(future, eip);
             }
         }
     }
 
     pub async fn call_x86(&mut self, func: u32, args: Vec<u32>) -> u32 {
-        self.emu
+        let start = self.profiler.as_ref().map(|_| std::time::Instant::now());
+        let ret = self
+            .emu
             .x86
             .cpu_mut()
             .call_x86(self.emu.memory.mem(), func, args)
-            .await
+            .await;
+        if let (Some(profiler), Some(start)) = (&mut self.profiler, start) {
+            profiler.record_target(func, start.elapsed());
+        }
+        ret
     }
 
     pub fn dump_stack(&self) {
@@ -258,6 +392,27 @@ impl MachineX<Emulator> {
         }
     }
 
+    /// Watch [addr, addr+len) for the given kinds of access.
+    pub fn add_watchpoint(
+        &mut self,
+        addr: u32,
+        len: u32,
+        access: x86::Protect,
+        action: x86::WatchAction,
+    ) {
+        self.emu.x86.add_watch(x86::Watch {
+            start: addr,
+            end: addr + len,
+            access,
+            action,
+        });
+    }
+
+    /// Undo an add_watchpoint().
+    pub fn clear_watchpoint(&mut self, addr: u32) -> bool {
+        self.emu.x86.clear_watch(addr)
+    }
+
     /// Undo an add_breakpoint().
     pub fn clear_breakpoint(&mut self, addr: u32) -> bool {
         match self.emu.breakpoints.remove(&addr) {
@@ -270,7 +425,47 @@ impl MachineX<Emulator> {
         }
     }
 
+    /// The original byte under the breakpoint at addr, which clear_breakpoint() restores.
+    pub fn breakpoint_byte(&mut self, addr: u32) -> Option<&mut u8> {
+        self.emu.breakpoints.get_mut(&addr)
+    }
+
     pub fn exit(&mut self, exit_code: u32) {
         self.status = Status::Exit(exit_code);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn run_for_budget() {
+        let mut machine = testing::machine();
+        // 100 nops, then mov eax, 5; ret
+        let mut code = vec![0x90; 100];
+        code.extend_from_slice(&[0xb8, 5, 0, 0, 0, 0xc3]);
+        let entry = testing::alloc_code(&mut machine, &code);
+        testing::start(&mut machine, entry);
+
+        let result = machine.run_for(10);
+        assert_eq!(result.status, RunStatus::BudgetExhausted);
+        assert_eq!(result.instructions, 10);
+        let eip = machine.emu.x86.cpu().regs.eip;
+        assert!((entry..entry + 100).contains(&eip));
+
+        // Resumes where it left off, one nop per instruction.
+        let result = machine.run_for(10);
+        assert_eq!(result.status, RunStatus::BudgetExhausted);
+        assert_eq!(result.instructions, 10);
+        assert_eq!(machine.emu.x86.cpu().regs.eip, eip + 10);
+
+        let result = machine.run_for(1000);
+        assert_eq!(result.status, RunStatus::Exited(5));
+        // The remaining nops, mov and ret, plus returning through retrowin32_main.
+        let rest = (entry + 100 - (eip + 10)) as usize + 2;
+        assert!((rest..1000).contains(&result.instructions));
+        assert_eq!(machine.emu.x86.budget, usize::MAX);
+    }
+}
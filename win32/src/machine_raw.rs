@@ -43,6 +43,7 @@ impl MachineX<Emulator> {
             labels: HashMap::new(),
             exe_path: Default::default(),
             status: Default::default(),
+            profiler: None,
         }
     }
 
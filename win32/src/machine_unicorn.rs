@@ -97,6 +97,7 @@ impl MachineX<Emulator> {
             labels: HashMap::new(),
             exe_path: Default::default(),
             status: Default::default(),
+            profiler: None,
         }
     }
 
@@ -216,9 +217,14 @@ impl MachineX<Emulator> {
         };
 
         let stack_args = esp + 8;
+        let name = shim.name;
         match shim.func {
             Handler::Sync(func) => {
+                let start = self.profiler.as_ref().map(|_| std::time::Instant::now());
                 let ret = unsafe { func(self, stack_args) };
+                if let (Some(profiler), Some(start)) = (&mut self.profiler, start) {
+                    profiler.record_shim(name, start.elapsed());
+                }
 
                 self.emu
                     .unicorn
@@ -227,7 +233,10 @@ impl MachineX<Emulator> {
             }
             Handler::Async(func) => {
                 let return_address = eip;
-                let future = unsafe { func(self, stack_args) };
+                let mut future = unsafe { func(self, stack_args) };
+                if self.profiler.is_some() {
+                    future = crate::profile::time_async(self, name, future);
+                }
                 self.call_async(future, return_address as u32);
             }
         };
@@ -255,11 +264,8 @@ impl MachineX<Emulator> {
     /// Poll the current future, removing it from the queue if it's done.
     fn async_executor(&mut self) {
         let future = self.emu.futures.last_mut().unwrap();
-        // TODO: we don't use the waker at all.  Rust doesn't like us passing a random null pointer
-        // here but it seems like nothing accesses it(?).
-        //let c = unsafe { std::task::Context::from_waker(&Waker::from_raw(std::task::RawWaker::)) };
-        #[allow(deref_nullptr)]
-        let context: &mut std::task::Context = unsafe { &mut *std::ptr::null_mut() };
+        // We don't use the waker at all; futures are polled again on each scheduling pass.
+        let context = &mut std::task::Context::from_waker(std::task::Waker::noop());
         let poll = future.as_mut().poll(context);
         match poll {
             std::task::Poll::Ready(()) => {
@@ -1,8 +1,18 @@
 #![allow(non_snake_case)]
 #![allow(non_camel_case_types)]
 
-use crate::str16::expect_ascii;
+use crate::{str16::expect_ascii, winapi::ImportSymbol};
 use memory::Extensions;
+use std::ops::Range;
+
+/// An entry in the export address table.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Export {
+    /// RVA of the exported code or data.
+    Addr(u32),
+    /// Forwarder to another DLL's export, like "NTDLL.RtlAllocateHeap".
+    Forward(String),
+}
 
 #[derive(Debug, Clone)]
 #[repr(C)]
@@ -33,6 +43,22 @@ impl IMAGE_EXPORT_DIRECTORY {
         image.iter_pod::<u32>(self.AddressOfFunctions, self.NumberOfFunctions)
     }
 
+    /// Returns an iterator of exports in ordinal order.  `range` is the span of the export
+    /// directory; an address within it is actually the RVA of a forwarder string.
+    pub fn exports<'a>(
+        &self,
+        image: &'a [u8],
+        range: Range<u32>,
+    ) -> impl Iterator<Item = Export> + 'a {
+        self.fns(image).map(move |addr| {
+            if range.contains(&addr) {
+                Export::Forward(expect_ascii(image.slicez(addr)).to_string())
+            } else {
+                Export::Addr(addr)
+            }
+        })
+    }
+
     /// Returns an iterator of (name, index) pairs, where index is an index into fn()s.
     pub fn names<'a>(&self, image: &'a [u8]) -> impl Iterator<Item = (&'a str, u16)> {
         let names = image.iter_pod::<u32>(self.AddressOfNames, self.NumberOfNames);
@@ -46,3 +72,58 @@ impl IMAGE_EXPORT_DIRECTORY {
 pub fn read_exports(section: &[u8]) -> IMAGE_EXPORT_DIRECTORY {
     section.get_pod::<IMAGE_EXPORT_DIRECTORY>(0)
 }
+
+/// Split a forwarder target like "NTDLL.RtlAllocateHeap" or "NTDLL.#12" into
+/// the module name and symbol.
+pub fn parse_forward(target: &str) -> Option<(&str, ImportSymbol<'_>)> {
+    let (dll_name, name) = target.split_once('.')?;
+    let sym = match name.strip_prefix('#') {
+        Some(ord) => ImportSymbol::Ordinal(ord.parse().ok()?),
+        None => ImportSymbol::Name(name),
+    };
+    Some((dll_name, sym))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarder() {
+        // Export directory at 0x100, containing its tables and the forwarder string.
+        let mut image = vec![0u8; 0x200];
+        let put = |image: &mut Vec<u8>, ofs: usize, val: u32| {
+            image[ofs..ofs + 4].copy_from_slice(&val.to_le_bytes())
+        };
+        let dir = 0x100;
+        put(&mut image, dir + 0x10, 1); // Base
+        put(&mut image, dir + 0x14, 2); // NumberOfFunctions
+        put(&mut image, dir + 0x1c, 0x140); // AddressOfFunctions
+        put(&mut image, 0x140, 0x1000); // ordinal 1: code
+        put(&mut image, 0x144, 0x160); // ordinal 2: forwarder
+        let target = b"KERNEL32.HeapAlloc\0";
+        image[0x160..0x160 + target.len()].copy_from_slice(target);
+
+        let exports = read_exports(&image[dir..])
+            .exports(&image, 0x100..0x200)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            exports,
+            vec![
+                Export::Addr(0x1000),
+                Export::Forward("KERNEL32.HeapAlloc".into())
+            ]
+        );
+
+        let Export::Forward(target) = &exports[1] else {
+            unreachable!()
+        };
+        let (dll, sym) = parse_forward(target).unwrap();
+        assert_eq!(dll, "KERNEL32");
+        assert!(matches!(sym, ImportSymbol::Name("HeapAlloc")));
+        assert!(matches!(
+            parse_forward("NTDLL.#12"),
+            Some(("NTDLL", ImportSymbol::Ordinal(12)))
+        ));
+    }
+}
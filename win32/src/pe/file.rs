@@ -3,17 +3,65 @@
 
 use super::reader::Reader;
 use crate::winapi::types::{DWORD, WORD};
-use anyhow::{anyhow, bail};
+use anyhow::anyhow;
 use bitflags::bitflags;
 use memory::Extensions;
 
 // https://docs.microsoft.com/en-us/previous-versions/ms809762(v=msdn.10)
 // https://learn.microsoft.com/en-us/windows/win32/debug/pe-format
 
-fn dos_header<'m>(r: &mut Reader<'m>) -> anyhow::Result<u32> {
-    r.expect("MZ")?;
-    r.skip(0x3a)?;
-    Ok(r.read::<DWORD>())
+/// Reasons a buffer can't be loaded as a PE file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// Missing the "MZ" signature of the DOS header.
+    BadDosSignature,
+    /// Missing the "PE" signature where the DOS header points, e.g. a plain DOS executable.
+    BadPeSignature,
+    /// Built for a CPU other than x86, e.g. a 64-bit image.
+    UnsupportedMachine(u16),
+    /// An optional header other than PE32, e.g. the PE32+ format of 64-bit images.
+    UnsupportedOptionalHeader(u16),
+    /// A .NET image, whose code needs the CLR.
+    ManagedImage,
+    /// The file ends within the named structure.
+    Truncated(&'static str),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::BadDosSignature => write!(f, "missing MZ signature, not an executable"),
+            ParseError::BadPeSignature => {
+                write!(f, "missing PE signature, might be a DOS executable?")
+            }
+            ParseError::UnsupportedMachine(IMAGE_FILE_MACHINE_AMD64) => {
+                write!(f, "64-bit executables are unsupported")
+            }
+            ParseError::UnsupportedMachine(machine) => {
+                write!(f, "unsupported machine type {machine:#x}")
+            }
+            ParseError::UnsupportedOptionalHeader(magic) => {
+                write!(f, "unsupported optional header magic {magic:#x}")
+            }
+            ParseError::ManagedImage => write!(f, ".NET executables are unsupported"),
+            ParseError::Truncated(what) => write!(f, "file truncated in {what}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub const IMAGE_FILE_MACHINE_I386: u16 = 0x14c;
+pub const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+const IMAGE_NT_OPTIONAL_HDR32_MAGIC: u16 = 0x10b;
+
+fn dos_header<'m>(r: &mut Reader<'m>) -> Result<u32, ParseError> {
+    let truncated = ParseError::Truncated("DOS header");
+    if r.read_bytes(2).ok_or(truncated.clone())? != b"MZ" {
+        return Err(ParseError::BadDosSignature);
+    }
+    r.seek(0x3c).ok_or(truncated.clone())?;
+    r.read::<DWORD>().ok_or(truncated)
 }
 
 #[derive(Debug, Default, Clone)]
@@ -82,7 +130,7 @@ pub struct IMAGE_OPTIONAL_HEADER32 {
 unsafe impl memory::Pod for IMAGE_OPTIONAL_HEADER32 {}
 
 #[repr(C)]
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct IMAGE_DATA_DIRECTORY {
     pub VirtualAddress: DWORD,
     pub Size: DWORD,
@@ -116,12 +164,16 @@ pub enum IMAGE_DIRECTORY_ENTRY {
     COM_DESCRIPTOR = 14,
 }
 
-fn pe_header<'m>(r: &mut Reader<'m>) -> anyhow::Result<IMAGE_FILE_HEADER> {
-    r.expect("PE\0\0")?;
+fn pe_header<'m>(r: &mut Reader<'m>) -> Result<IMAGE_FILE_HEADER, ParseError> {
+    if r.read_bytes(4) != Some(b"PE\0\0") {
+        return Err(ParseError::BadPeSignature);
+    }
 
-    let header = r.read::<IMAGE_FILE_HEADER>();
-    if header.Machine != 0x14c {
-        bail!("bad machine {:?}", header.Machine);
+    let header = r
+        .read::<IMAGE_FILE_HEADER>()
+        .ok_or(ParseError::Truncated("PE header"))?;
+    if header.Machine != IMAGE_FILE_MACHINE_I386 {
+        return Err(ParseError::UnsupportedMachine(header.Machine));
     }
     Ok(header)
 }
@@ -157,7 +209,7 @@ impl IMAGE_SECTION_HEADER {
 }
 
 bitflags! {
-    #[derive(serde::Serialize)]
+    #[derive(serde::Serialize, serde::Deserialize)]
     pub struct ImageSectionFlags: u32 {
         const CODE = 0x20;
         const INITIALIZED_DATA = 0x40;
@@ -204,31 +256,39 @@ impl File {
     }
 }
 
-pub fn parse(buf: &[u8]) -> anyhow::Result<File> {
+pub fn parse(buf: &[u8]) -> Result<File, ParseError> {
     let mut r = Reader::new(buf);
 
-    let pe_header_ofs = dos_header(&mut r).map_err(|err| anyhow!("reading DOS header: {}", err))?;
-    if pe_header_ofs as usize + std::mem::size_of::<IMAGE_FILE_HEADER>() >= buf.len() {
-        bail!("invalid PE offset in DOS header, might be a DOS executable?");
-    }
-    r.seek(pe_header_ofs)
-        .map_err(|err| anyhow!("seeking PE header {pe_header_ofs:x}: {}", err))?;
+    let pe_header_ofs = dos_header(&mut r)?;
+    r.seek(pe_header_ofs).ok_or(ParseError::BadPeSignature)?;
+    let header = pe_header(&mut r)?;
 
-    let header = pe_header(&mut r).map_err(|err| anyhow!("reading PE header: {}", err))?;
-    let opt_header = r.read::<IMAGE_OPTIONAL_HEADER32>();
+    let opt_header = r
+        .read::<IMAGE_OPTIONAL_HEADER32>()
+        .ok_or(ParseError::Truncated("optional header"))?;
+    if opt_header.Magic != IMAGE_NT_OPTIONAL_HDR32_MAGIC {
+        return Err(ParseError::UnsupportedOptionalHeader(opt_header.Magic));
+    }
     let data_directory = r
         .read_n::<IMAGE_DATA_DIRECTORY>(opt_header.NumberOfRvaAndSizes)
-        .unwrap();
+        .ok_or(ParseError::Truncated("data directory"))?;
     let sections = r
         .read_n::<IMAGE_SECTION_HEADER>(header.NumberOfSections as u32)
-        .unwrap();
+        .ok_or(ParseError::Truncated("section table"))?;
 
-    Ok(File {
+    let file = File {
         header,
         opt_header,
         data_directory,
         sections,
-    })
+    };
+    if file
+        .get_data_directory(IMAGE_DIRECTORY_ENTRY::COM_DESCRIPTOR)
+        .is_some()
+    {
+        return Err(ParseError::ManagedImage);
+    }
+    Ok(file)
 }
 
 #[cfg(test)]
@@ -242,7 +302,28 @@ mod tests {
         buf.write(b"MZ").unwrap();
         buf.write(&[0; 0x3a]).unwrap();
         buf.write(&0xFFFFFFFFu32.to_le_bytes()).unwrap();
-        assert!(parse(&buf).is_err()); // no crash
+        assert_eq!(parse(&buf).unwrap_err(), ParseError::BadPeSignature);
+        assert_eq!(
+            parse(b"MZ").unwrap_err(),
+            ParseError::Truncated("DOS header")
+        );
+        assert_eq!(parse(b"ELF").unwrap_err(), ParseError::BadDosSignature);
+    }
+
+    #[test]
+    fn amd64() {
+        let mut buf: Vec<u8> = Vec::new();
+        buf.write(b"MZ").unwrap();
+        buf.write(&[0; 0x3a]).unwrap();
+        buf.write(&0x40u32.to_le_bytes()).unwrap();
+        buf.write(b"PE\0\0").unwrap();
+        buf.write(&IMAGE_FILE_MACHINE_AMD64.to_le_bytes()).unwrap();
+        assert_eq!(parse(&buf).unwrap_err(), ParseError::Truncated("PE header"));
+        buf.write(&[0; 18]).unwrap();
+        assert_eq!(
+            parse(&buf).unwrap_err(),
+            ParseError::UnsupportedMachine(IMAGE_FILE_MACHINE_AMD64)
+        );
     }
 
     #[test]
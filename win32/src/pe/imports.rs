@@ -64,6 +64,9 @@ pub fn read_imports<'m>(buf: &'m [u8]) -> impl Iterator<Item = IMAGE_IMPORT_DESC
         .take_while(|desc| desc.Name != 0)
 }
 
+/// Set in an ILT entry when importing by ordinal rather than by name.
+pub const IMAGE_ORDINAL_FLAG32: u32 = 1 << 31;
+
 #[repr(transparent)]
 #[derive(Clone)]
 pub struct ILTEntry(u32);
@@ -72,7 +75,7 @@ unsafe impl memory::Pod for ILTEntry {}
 impl ILTEntry {
     pub fn as_import_symbol(self, image: &[u8]) -> ImportSymbol {
         let entry = self.0;
-        if entry & (1 << 31) != 0 {
+        if entry & IMAGE_ORDINAL_FLAG32 != 0 {
             let ordinal = entry & 0xFFFF;
             ImportSymbol::Ordinal(ordinal)
         } else {
@@ -83,3 +86,101 @@ impl ILTEntry {
         }
     }
 }
+
+/// Delay-load import descriptor, describing imports resolved on first call
+/// rather than at load time.
+#[derive(Clone, Debug, Default)]
+#[repr(C)]
+pub struct IMAGE_DELAYLOAD_DESCRIPTOR {
+    Attributes: DWORD,
+    DllNameRVA: DWORD,
+    /// Where the loaded module's HMODULE is stored.
+    ModuleHandleRVA: DWORD,
+    ImportAddressTableRVA: DWORD,
+    ImportNameTableRVA: DWORD,
+    BoundImportAddressTableRVA: DWORD,
+    UnloadInformationTableRVA: DWORD,
+    TimeDateStamp: DWORD,
+}
+unsafe impl memory::Pod for IMAGE_DELAYLOAD_DESCRIPTOR {}
+
+impl IMAGE_DELAYLOAD_DESCRIPTOR {
+    /// Old (VC6-era) descriptors hold VAs rather than RVAs, indicated by
+    /// the dlattrRva attribute bit being clear.
+    fn uses_vas(&self) -> bool {
+        const DLATTR_RVA: u32 = 1;
+        self.Attributes & DLATTR_RVA == 0
+    }
+
+    /// Convert the address fields of a VA-based descriptor to RVAs.
+    /// (The INT entries are adjusted on iteration, in int().)
+    fn to_rvas(mut self, base: u32) -> Self {
+        if self.uses_vas() {
+            for field in [
+                &mut self.DllNameRVA,
+                &mut self.ModuleHandleRVA,
+                &mut self.ImportAddressTableRVA,
+                &mut self.ImportNameTableRVA,
+            ] {
+                *field = field.wrapping_sub(base);
+            }
+        }
+        self
+    }
+
+    pub fn image_name<'m>(&self, image: &'m [u8]) -> &'m str {
+        expect_ascii(image.slicez(self.DllNameRVA))
+    }
+
+    /// The Import Name Table, in the same format as the ILT of regular imports.
+    pub fn int<'m>(&self, image: &'m [u8], base: u32) -> impl Iterator<Item = ILTEntry> + 'm {
+        let adjust = if self.uses_vas() { base } else { 0 };
+        image[self.ImportNameTableRVA as usize..]
+            .into_iter_pod::<ILTEntry>()
+            .take_while(|entry| entry.0 != 0)
+            .map(move |entry| {
+                if entry.0 & IMAGE_ORDINAL_FLAG32 != 0 {
+                    entry // ordinal
+                } else {
+                    ILTEntry(entry.0.wrapping_sub(adjust))
+                }
+            })
+    }
+
+    pub fn iat_offset(&self) -> u32 {
+        self.ImportAddressTableRVA
+    }
+
+    pub fn module_handle_offset(&self) -> u32 {
+        self.ModuleHandleRVA
+    }
+}
+
+/// Reads delay-load descriptors, normalizing any VA-based ones to RVAs
+/// relative to `base`, the address the image was loaded at.
+pub fn read_delay_imports<'m>(
+    buf: &'m [u8],
+    base: u32,
+) -> impl Iterator<Item = IMAGE_DELAYLOAD_DESCRIPTOR> + 'm {
+    buf.into_iter_pod::<IMAGE_DELAYLOAD_DESCRIPTOR>()
+        .take_while(|desc| desc.DllNameRVA != 0)
+        .map(move |desc| desc.to_rvas(base))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordinal_and_name() {
+        let mut image = vec![0u8; 0x20];
+        image[0x12..0x17].copy_from_slice(b"Beep\0");
+
+        let sym = ILTEntry(IMAGE_ORDINAL_FLAG32 | 17).as_import_symbol(&image);
+        assert!(matches!(sym, ImportSymbol::Ordinal(17)));
+        assert_eq!(format!("user32.dll!{sym}"), "user32.dll!#17");
+
+        let sym = ILTEntry(0x10).as_import_symbol(&image);
+        assert!(matches!(sym, ImportSymbol::Name("Beep")));
+    }
+}
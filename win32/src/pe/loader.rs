@@ -38,7 +38,22 @@ fn load_image(
             .kernel32
             .mappings
             .find_space(file.opt_header.SizeOfImage),
-        None => file.opt_header.ImageBase,
+        None => {
+            let base = file.opt_header.ImageBase;
+            let size = file.opt_header.SizeOfImage;
+            if !machine.state.kernel32.mappings.is_free(base, size)
+                && file
+                    .get_data_directory(pe::IMAGE_DIRECTORY_ENTRY::BASERELOC)
+                    .is_some()
+            {
+                // Preferred base is taken (e.g. by the null page guard), so relocate.
+                let addr = machine.state.kernel32.mappings.find_space(size);
+                log::info!("{filename}: image base {base:x} unavailable, loading at {addr:x}");
+                addr
+            } else {
+                base
+            }
+        }
     };
 
     let first_page_size = std::cmp::min(buf.len(), 0x1000);
@@ -47,8 +62,11 @@ fn load_image(
         winapi::kernel32::Mapping {
             addr,
             size: first_page_size as u32,
+            base: addr,
             desc: filename.into(),
             flags: pe::ImageSectionFlags::MEM_READ,
+            committed: true,
+            image: true,
         },
         Some(&buf[..first_page_size]),
     );
@@ -86,26 +104,44 @@ fn load_section(
     let load_data = flags.contains(pe::ImageSectionFlags::CODE)
         || flags.contains(pe::ImageSectionFlags::INITIALIZED_DATA);
 
+    let access = pe::ImageSectionFlags::MEM_READ
+        | pe::ImageSectionFlags::MEM_WRITE
+        | pe::ImageSectionFlags::MEM_EXECUTE;
+    let mut flags = flags;
+    if !flags.intersects(access) {
+        // Some packers leave the access bits off entirely; rather than making the section
+        // inaccessible, assume it is meant to be used.
+        log::warn!("{filename}: section {:?} has no access flags", sec.name());
+        flags |= access;
+    }
+
     let mapping = winapi::kernel32::Mapping {
         addr: dst as u32,
         size: sec.VirtualSize as u32,
+        base: dst as u32,
         desc: format!(
             "{filename} {:?} ({:?})",
             sec.name().unwrap_or("[invalid]"),
             flags
         ),
         flags,
+        committed: true,
+        image: true,
     };
 
-    map_memory(
-        machine,
-        mapping,
-        if load_data && data_size > 0 {
-            Some(&buf[src..][..data_size as usize])
-        } else {
-            None
-        },
-    );
+    let data = if load_data && data_size > 0 {
+        let data = buf.get(src..).unwrap_or_default();
+        if data.len() < data_size as usize {
+            log::warn!(
+                "{filename}: section {:?} extends past end of file",
+                sec.name()
+            );
+        }
+        Some(&data[..data.len().min(data_size as usize)])
+    } else {
+        None
+    };
+    map_memory(machine, mapping, data);
 }
 
 fn patch_iat(machine: &mut Machine, base: u32, imports_data: &IMAGE_DATA_DIRECTORY) {
@@ -121,20 +157,25 @@ fn patch_iat(machine: &mut Machine, base: u32, imports_data: &IMAGE_DATA_DIRECTO
     for dll_imports in pe::read_imports(section) {
         let dll_name = dll_imports.image_name(image).to_ascii_lowercase();
         let hmodule = winapi::kernel32::load_library(machine, &dll_name);
-        let mut dll = machine.state.kernel32.dlls.get_mut(&hmodule);
         for (i, entry) in dll_imports.ilt(image).enumerate() {
             let sym = entry.as_import_symbol(image);
-            let name = format!("{}!{}", dll_name, sym.to_string());
+            let name = format!("{dll_name}!{sym}");
             let iat_addr = base + dll_imports.iat_offset() + (i as u32 * 4);
             machine.labels.insert(iat_addr, format!("{}@IAT", name));
 
-            let resolved_addr = if let Some(dll) = dll.as_mut() {
-                if let Some(sym) = dll.resolve(&sym) {
-                    Some(sym)
-                } else {
-                    log::warn!("missing symbol {name}");
-                    None
+            // Note: the pseudo retrowin32.dll has a null HMODULE, so check for presence.
+            let resolved_addr = if machine.state.kernel32.dlls.contains_key(&hmodule) {
+                // Chases forwarded exports to the DLL that implements them.
+                let addr = winapi::kernel32::resolve_export(machine, hmodule, &sym);
+                if addr.is_none() {
+                    match sym {
+                        winapi::ImportSymbol::Ordinal(ord) => {
+                            log::warn!("missing symbol {name}: {dll_name} has no export with ordinal {ord}")
+                        }
+                        winapi::ImportSymbol::Name(_) => log::warn!("missing symbol {name}"),
+                    }
                 }
+                addr
             } else {
                 None
             };
@@ -152,6 +193,85 @@ fn patch_iat(machine: &mut Machine, base: u32, imports_data: &IMAGE_DATA_DIRECTO
     }
 }
 
+/// Size of each thunk written by patch_delay_iat.
+const DELAY_THUNK_SIZE: u32 = 0x20;
+
+/// Delay-loaded imports are resolved on first call.  Point each IAT slot at a thunk that
+/// passes the import's index to retrowin32_delay_load, which resolves and patches the
+/// slot, then jumps to the resolved address.
+fn patch_delay_iat(
+    machine: &mut Machine,
+    filename: &str,
+    base: u32,
+    delay_imports_data: &IMAGE_DATA_DIRECTORY,
+) {
+    let image: &[u8] = unsafe { std::mem::transmute(machine.mem().slice(base..)) };
+    let section = match delay_imports_data.as_slice(image) {
+        None => return,
+        Some(s) => s,
+    };
+
+    let mut imports = Vec::new();
+    for desc in pe::read_delay_imports(section, base) {
+        let dll = desc.image_name(image).to_ascii_lowercase();
+        for (i, entry) in desc.int(image, base).enumerate() {
+            let (name, ordinal) = match entry.as_import_symbol(image) {
+                winapi::ImportSymbol::Name(name) => (Some(name.to_string()), 0),
+                winapi::ImportSymbol::Ordinal(ordinal) => (None, ordinal),
+            };
+            imports.push(winapi::kernel32::DelayImport {
+                dll: dll.clone(),
+                name,
+                ordinal,
+                iat_addr: base + desc.iat_offset() + (i as u32 * 4),
+                hmodule_addr: base + desc.module_handle_offset(),
+            });
+        }
+    }
+    if imports.is_empty() {
+        return;
+    }
+
+    let helper = winapi::kernel32::get_kernel32_builtin(machine, "retrowin32_delay_load");
+    let thunks = machine
+        .state
+        .kernel32
+        .mappings
+        .alloc(
+            imports.len() as u32 * DELAY_THUNK_SIZE,
+            format!("{filename} delay-load thunks"),
+            &mut machine.emu.memory,
+        )
+        .addr;
+
+    for (i, import) in imports.into_iter().enumerate() {
+        let index = machine.state.kernel32.delay_imports.len() as u32;
+        let thunk = thunks + i as u32 * DELAY_THUNK_SIZE;
+        let mut code = Vec::with_capacity(DELAY_THUNK_SIZE as usize);
+        code.extend_from_slice(&[0x51, 0x52]); // push ecx; push edx
+        code.push(0x68); // push index
+        code.extend_from_slice(&index.to_le_bytes());
+        code.push(0xb8); // mov eax, helper
+        code.extend_from_slice(&helper.to_le_bytes());
+        code.extend_from_slice(&[0xff, 0xd0]); // call eax
+        code.extend_from_slice(&[0x5a, 0x59]); // pop edx; pop ecx
+        code.extend_from_slice(&[0xff, 0xe0]); // jmp eax
+        code.resize(DELAY_THUNK_SIZE as usize, 0xcc); // int3
+        machine
+            .mem()
+            .sub32_mut(thunk, DELAY_THUNK_SIZE)
+            .copy_from_slice(&code);
+
+        machine.mem().put_pod::<u32>(import.iat_addr, thunk);
+        let name = format!("{}!{}", import.dll, import.symbol());
+        machine
+            .labels
+            .insert(import.iat_addr, format!("{name}@IAT"));
+        machine.labels.insert(thunk, format!("{name}@delay"));
+        machine.state.kernel32.delay_imports.push(import);
+    }
+}
+
 fn load_pe(
     machine: &mut Machine,
     filename: &str,
@@ -183,7 +303,8 @@ fn load_pe(
                         let addr = base + addr;
                         machine.mem().put_pod::<u32>(addr, val);
                     },
-                );
+                )
+                .map_err(|err| anyhow::anyhow!("relocating {filename}: {err}"))?;
             }
         }
     }
@@ -192,14 +313,97 @@ fn load_pe(
         patch_iat(machine, base, imports);
     }
 
+    if let Some(delay_imports) = file.get_data_directory(pe::IMAGE_DIRECTORY_ENTRY::DELAY_IMPORT) {
+        patch_delay_iat(machine, filename, base, delay_imports);
+    }
+
     Ok(base)
 }
 
+/// Set up static (__declspec(thread)) TLS for the exe: copy its template into a fresh
+/// block, point the TEB's TLS array at it, and record the TLS callbacks so they run
+/// before the entry point.
+fn load_tls(
+    machine: &mut Machine,
+    filename: &str,
+    base: u32,
+    dir: &IMAGE_DATA_DIRECTORY,
+) -> anyhow::Result<()> {
+    let image = machine.mem().slice(base..);
+    let section = dir
+        .as_slice(image)
+        .ok_or_else(|| anyhow::anyhow!("invalid TLS directory"))?;
+    let tls = pe::read_tls(section);
+
+    // The exe is the only module with static TLS, so its index is 0 and the TLS array
+    // has a single entry.  Lay out the array followed by the block itself.
+    let block_ofs = 0x10;
+    let addr = machine
+        .state
+        .kernel32
+        .mappings
+        .alloc(
+            block_ofs + tls.block_size(),
+            format!("{filename} TLS"),
+            &mut machine.emu.memory,
+        )
+        .addr;
+    let block = addr + block_ofs;
+    let template_len = tls
+        .EndAddressOfRawData
+        .saturating_sub(tls.StartAddressOfRawData);
+    let template = machine
+        .mem()
+        .sub32(tls.StartAddressOfRawData, template_len)
+        .to_vec();
+    machine
+        .mem()
+        .sub32_mut(block, template_len)
+        .copy_from_slice(&template);
+    machine
+        .mem()
+        .sub32_mut(block + template_len, tls.SizeOfZeroFill)
+        .fill(0);
+    machine.mem().put_pod::<u32>(addr, block);
+    if tls.AddressOfIndex != 0 {
+        machine.mem().put_pod::<u32>(tls.AddressOfIndex, 0);
+    }
+    winapi::kernel32::teb_mut(machine).ThreadLocalStoragePointer = addr;
+
+    let mut callbacks = Vec::new();
+    if tls.AddressOfCallBacks != 0 {
+        let mut ptr = tls.AddressOfCallBacks;
+        loop {
+            let callback = machine.mem().get_pod::<u32>(ptr);
+            if callback == 0 {
+                break;
+            }
+            callbacks.push(callback);
+            ptr += 4;
+        }
+    }
+    machine.state.kernel32.tls.callbacks = callbacks;
+
+    Ok(())
+}
+
 pub struct EXEFields {
     pub entry_point: u32,
     pub stack_size: u32,
 }
 
+/// The exe's full Windows path, from the command line the host started us with.
+fn exe_path(machine: &Machine, filename: &str) -> String {
+    let arg0 = match machine.state.kernel32.cmdline.args.first() {
+        Some(arg0) => arg0.as_str(),
+        None => filename,
+    };
+    match machine.host.current_dir() {
+        Ok(cwd) => cwd.join(arg0).normalize().to_string_lossy().into_owned(),
+        Err(_) => arg0.to_string(),
+    }
+}
+
 pub fn load_exe(
     machine: &mut Machine,
     buf: &[u8],
@@ -209,8 +413,11 @@ pub fn load_exe(
     let file = pe::parse(buf)?;
 
     let filename = path.file_name().unwrap().to_string_lossy();
+    // Imports are resolved by load_pe, and DLLs are found relative to the exe.
+    machine.state.kernel32.exe_path = exe_path(machine, &filename);
     let base = load_pe(machine, &filename, buf, &file, relocate)?;
     machine.state.kernel32.image_base = base;
+    machine.state.kernel32.subsystem = file.opt_header.Subsystem;
 
     if let Some(res_data) = file
         .data_directory
@@ -219,6 +426,10 @@ pub fn load_exe(
         machine.state.kernel32.resources = res_data.clone();
     }
 
+    if let Some(tls) = file.get_data_directory(pe::IMAGE_DIRECTORY_ENTRY::TLS) {
+        load_tls(machine, &filename, base, tls)?;
+    }
+
     let entry_point = base + file.opt_header.AddressOfEntryPoint;
 
     let addrs = EXEFields {
@@ -228,7 +439,7 @@ pub fn load_exe(
     Ok(addrs)
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct DLL {
     /// Image base address.
     pub base: u32,
@@ -240,6 +451,9 @@ pub struct DLL {
     pub ordinal_base: u32,
     pub fns: Vec<u32>,
 
+    /// Forwarded exports: address of forwarder string => target, like "NTDLL.RtlAllocateHeap".
+    pub forwards: HashMap<u32, String>,
+
     pub resources: Option<IMAGE_DATA_DIRECTORY>,
 
     /// Address of DllMain() entry point.
@@ -260,13 +474,18 @@ pub fn load_dll(machine: &mut Machine, filename: &str, buf: &[u8]) -> anyhow::Re
     let mut ordinal_base = 1;
     let mut fns = Vec::new();
     let mut names = HashMap::new();
+    let mut forwards = HashMap::new();
     if let Some(dir) = file.get_data_directory(pe::IMAGE_DIRECTORY_ENTRY::EXPORT) {
         let section = dir
             .as_slice(image)
             .ok_or_else(|| anyhow::anyhow!("invalid exports"))?;
+        let range = dir.VirtualAddress..dir.VirtualAddress + dir.Size;
         let dir = pe::read_exports(section);
         ordinal_base = dir.Base;
-        for addr in dir.fns(image) {
+        for (addr, export) in dir.fns(image).zip(dir.exports(image, range)) {
+            if let pe::Export::Forward(target) = export {
+                forwards.insert(base + addr, target);
+            }
             fns.push(base + addr);
         }
         for (name, i) in dir.names(image) {
@@ -284,7 +503,40 @@ pub fn load_dll(machine: &mut Machine, filename: &str, buf: &[u8]) -> anyhow::Re
         names,
         ordinal_base,
         fns,
+        forwards,
         resources,
         entry_point,
     })
 }
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use crate::{testing, Machine, Status};
+    use std::{cell::RefCell, path::Path, rc::Rc};
+
+    /// An exe's imports are looked for next to the exe, even when that isn't the current
+    /// directory.
+    #[test]
+    fn load_exe_local_dll() {
+        let host = testing::TestHost::default();
+        let dll = include_bytes!("../../../exe/local_dll/local.dll");
+        host.files.borrow_mut().insert(
+            r"C:\game\local.dll".into(),
+            Rc::new(RefCell::new(dll.to_vec())),
+        );
+        let mut machine = Machine::new(Box::new(host), r"C:\game\main.exe".into());
+        let exe = include_bytes!("../../../exe/local_dll/main.exe");
+        machine.load_exe(exe, Path::new("main.exe"), None).unwrap();
+        assert_eq!(machine.state.kernel32.exe_path, r"C:\game\main.exe");
+        assert!(matches!(testing::run(&mut machine), Status::Exit(42)));
+    }
+
+    /// TLS callbacks run before the entry point, and see the exe's static TLS block.
+    #[test]
+    fn load_exe_tls_callback() {
+        let mut machine = testing::machine();
+        let exe = include_bytes!("../../../exe/tls/tls.exe");
+        machine.load_exe(exe, Path::new("tls.exe"), None).unwrap();
+        assert!(matches!(testing::run(&mut machine), Status::Exit(0)));
+    }
+}
@@ -5,6 +5,7 @@ mod loader;
 mod reader;
 mod relocations;
 mod resources;
+mod tls;
 
 pub use exports::*;
 pub use file::*;
@@ -12,3 +13,4 @@ pub use imports::*;
 pub use loader::*;
 pub use relocations::*;
 pub use resources::*;
+pub use tls::*;
@@ -1,8 +1,9 @@
 use std::mem::size_of;
 
-use anyhow::bail;
 use memory::Extensions;
 
+/// Bounds-checked reading of a file buffer.  Reads past the end return None rather
+/// than panicking, so callers can report which part of the file was truncated.
 pub struct Reader<'m> {
     pub buf: &'m [u8],
     pub pos: usize,
@@ -13,49 +14,33 @@ impl<'m> Reader<'m> {
         Reader { buf, pos: 0 }
     }
 
-    fn check_eof(&self) -> anyhow::Result<()> {
-        if self.pos > self.buf.len() {
-            bail!("EOF");
+    pub fn seek(&mut self, ofs: u32) -> Option<()> {
+        if ofs as usize > self.buf.len() {
+            return None;
         }
-        Ok(())
-    }
-
-    pub fn seek(&mut self, ofs: u32) -> anyhow::Result<()> {
         self.pos = ofs as usize;
-        self.check_eof()
-    }
-
-    pub fn skip(&mut self, n: usize) -> anyhow::Result<()> {
-        self.pos += n;
-        self.check_eof()
+        Some(())
     }
 
-    pub fn expect(&mut self, s: &str) -> anyhow::Result<()> {
-        let got = &self.buf[self.pos..][..s.len()];
-        if got != s.as_bytes() {
-            bail!("expected {:?}, got {:?}", s, got);
-        }
-        self.pos += s.len();
-        Ok(())
+    pub fn read_bytes(&mut self, len: usize) -> Option<&'m [u8]> {
+        let bytes = self.buf.get(self.pos..)?.get(..len)?;
+        self.pos += len;
+        Some(bytes)
     }
 
-    pub fn read<T: memory::Pod + Clone>(&mut self) -> T {
-        let t = self.buf.get_pod::<T>(self.pos as u32);
-        self.pos += size_of::<T>();
-        t
+    pub fn read<T: memory::Pod + Clone>(&mut self) -> Option<T> {
+        self.read_bytes(size_of::<T>())?;
+        Some(self.buf.get_pod::<T>((self.pos - size_of::<T>()) as u32))
     }
 
-    pub fn read_n<T: memory::Pod + Clone>(&mut self, count: u32) -> anyhow::Result<Box<[T]>> {
-        let len = size_of::<T>() * count as usize;
-        if self.pos + len > self.buf.len() {
-            bail!("EOF");
-        }
-        let values = self
-            .buf
-            .iter_pod::<T>(self.pos as u32, count)
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
-        self.pos += len;
-        Ok(values)
+    pub fn read_n<T: memory::Pod + Clone>(&mut self, count: u32) -> Option<Box<[T]>> {
+        let start = self.pos;
+        self.read_bytes(size_of::<T>().checked_mul(count as usize)?)?;
+        Some(
+            self.buf
+                .iter_pod::<T>(start as u32, count)
+                .collect::<Vec<_>>()
+                .into_boxed_slice(),
+        )
     }
 }
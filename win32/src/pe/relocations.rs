@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 #![allow(non_camel_case_types)]
 
+use anyhow::{anyhow, bail};
 use memory::Extensions;
 
 #[repr(C)]
@@ -12,25 +13,39 @@ struct IMAGE_BASE_RELOCATION {
 unsafe impl memory::Pod for IMAGE_BASE_RELOCATION {}
 
 /// Iterates IMAGE_BASE_RELOCATION+body blocks.
-fn block_iter(mut buf: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+fn block_iter(mut buf: &[u8]) -> impl Iterator<Item = anyhow::Result<(u32, &[u8])>> {
     std::iter::from_fn(move || {
-        if buf.len() == 0 {
+        if buf.len() < std::mem::size_of::<IMAGE_BASE_RELOCATION>() {
             return None;
         }
         let reloc = buf.get_pod::<IMAGE_BASE_RELOCATION>(0);
-        let body = &buf[std::mem::size_of::<IMAGE_BASE_RELOCATION>()..reloc.SizeOfBlock as usize];
-        buf = &buf[reloc.SizeOfBlock as usize..];
-        Some((reloc.VirtualAddress, body))
+        let size = reloc.SizeOfBlock as usize;
+        if size < std::mem::size_of::<IMAGE_BASE_RELOCATION>() || size > buf.len() {
+            buf = &[];
+            return Some(Err(anyhow!(
+                "invalid relocation block size {size:#x} at {:#x}",
+                reloc.VirtualAddress
+            )));
+        }
+        let body = &buf[std::mem::size_of::<IMAGE_BASE_RELOCATION>()..size];
+        buf = &buf[size..];
+        Some(Ok((reloc.VirtualAddress, body)))
     })
 }
 
+const IMAGE_REL_BASED_ABSOLUTE: u16 = 0;
+const IMAGE_REL_BASED_HIGHLOW: u16 = 3;
+
+/// Apply the relocations in the `relocs` (.reloc section contents) for an image
+/// that was linked at `prev_base` but loaded at `base`.
+/// `read`/`write` access dwords by RVA.
 pub fn apply_relocs(
     prev_base: u32,
     base: u32,
     relocs: &[u8],
     mut read: impl FnMut(u32) -> u32,
     mut write: impl FnMut(u32, u32),
-) {
+) -> anyhow::Result<()> {
     // monolife.exe has no IMAGE_DIRECTORY_ENTRY::BASERELOC, but does
     // have a .reloc section that is invalid (?).
     // Note: IMAGE_SECTION_HEADER itself also has some relocation-related fields
@@ -38,15 +53,16 @@ pub fn apply_relocs(
 
     let offset = base.wrapping_sub(prev_base);
 
-    for (addr, body) in block_iter(relocs) {
+    for block in block_iter(relocs) {
+        let (addr, body) = block?;
         for entry in body.into_iter_pod::<u16>() {
             let etype = entry >> 12;
             let ofs = entry & 0x0FFF;
             let addr = addr + ofs as u32;
             match etype {
-                0 => {} // skip
-                3 => {
-                    // IMAGE_REL_BASED_HIGHLOW, 32-bit adjustment
+                IMAGE_REL_BASED_ABSOLUTE => {} // padding
+                IMAGE_REL_BASED_HIGHLOW => {
+                    // 32-bit adjustment
                     // win2k's glu32.dll has a relocation offsetting the value 0x6fa7a09
                     // despite the image base being 0x6fac000, so it is a reference to memory
                     // before the image?!
@@ -54,8 +70,66 @@ pub fn apply_relocs(
                     let new = old.wrapping_add(offset);
                     write(addr, new);
                 }
-                _ => panic!("unhandled relocation type {etype}"),
+                _ => bail!("unhandled relocation type {etype} at {addr:#x}"),
             }
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe;
+
+    /// Lay out a DLL's sections at a non-preferred base and check the relocated
+    /// pointer to its import table.
+    #[test]
+    fn relocate_dll() {
+        let buf = include_bytes!("../../dll/winmm.dll");
+        let file = pe::parse(buf).unwrap();
+        let mut image = vec![0u8; file.opt_header.SizeOfImage as usize];
+        for sec in file.sections.iter() {
+            let src = &buf[sec.PointerToRawData as usize..][..sec.SizeOfRawData as usize];
+            let len = std::cmp::min(src.len(), sec.VirtualSize as usize);
+            image[sec.VirtualAddress as usize..][..len].copy_from_slice(&src[..len]);
+        }
+
+        let prev_base = file.opt_header.ImageBase;
+        let base = prev_base + 0x0100_0000;
+        let relocs = file
+            .get_data_directory(pe::IMAGE_DIRECTORY_ENTRY::BASERELOC)
+            .unwrap()
+            .as_slice(&image)
+            .unwrap()
+            .to_vec();
+        let mut patched = Vec::new();
+        apply_relocs(
+            prev_base,
+            base,
+            &relocs,
+            |addr| image.get_pod::<u32>(addr),
+            |addr, val| patched.push((addr, val)),
+        )
+        .unwrap();
+
+        // Each shim is `call [__imp__retrowin32_syscall]`, so the first relocation
+        // is the operand of that call, pointing into the IAT.
+        let iat = file
+            .get_data_directory(pe::IMAGE_DIRECTORY_ENTRY::IAT)
+            .unwrap();
+        let (_, target) = patched[0];
+        assert_eq!(target, base + iat.VirtualAddress);
+    }
+
+    #[test]
+    fn unknown_type() {
+        let mut relocs = Vec::new();
+        relocs.extend_from_slice(&0x1000u32.to_le_bytes());
+        relocs.extend_from_slice(&12u32.to_le_bytes());
+        relocs.extend_from_slice(&0u16.to_le_bytes()); // ABSOLUTE padding
+        relocs.extend_from_slice(&(10u16 << 12).to_le_bytes()); // DIR64
+        let err = apply_relocs(0x40_0000, 0x50_0000, &relocs, |_| 0, |_, _| {}).unwrap_err();
+        assert!(err.to_string().contains("relocation type 10"));
+    }
 }
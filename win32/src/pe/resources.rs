@@ -50,11 +50,19 @@ unsafe impl memory::Pod for IMAGE_RESOURCE_DIRECTORY_ENTRY {}
 #[derive(Clone, Copy)]
 #[repr(u32)]
 pub enum RT {
+    CURSOR = 1,
     BITMAP = 2,
+    ICON = 3,
+    MENU = 4,
+    DIALOG = 5,
     STRING = 6,
+    ACCELERATOR = 9,
+    GROUP_CURSOR = 12,
+    GROUP_ICON = 14,
+    VERSION = 16,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResourceName<'a> {
     Name(&'a Str16),
     Id(u32),
@@ -99,6 +107,103 @@ struct IMAGE_RESOURCE_DATA_ENTRY {
 }
 unsafe impl memory::Pod for IMAGE_RESOURCE_DATA_ENTRY {}
 
+impl IMAGE_RESOURCE_DATA_ENTRY {
+    fn range(&self) -> Range<u32> {
+        self.OffsetToData..(self.OffsetToData + self.Size)
+    }
+}
+
+/// One resource found by ResourceTree::entries().
+#[derive(Debug)]
+pub struct ResourceEntry<'a> {
+    pub name: ResourceName<'a>,
+    pub lang: u32,
+    /// Memory range within the image of the data.
+    pub data: Range<u32>,
+}
+
+/// View over a resources section.
+///
+/// Resources are structured as generic nested directories, but in practice there
+/// are always exactly three levels: type, then name, then language.
+/// Lookups return ranges within the image rather than copies, because the data
+/// entries hold image RVAs and callers want pointers into the mapped image.
+pub struct ResourceTree<'a> {
+    section: &'a [u8],
+}
+
+impl<'a> ResourceTree<'a> {
+    pub fn new(section: &'a [u8]) -> Self {
+        ResourceTree { section }
+    }
+
+    /// Find the subdirectory with the given name within a directory.
+    fn subdir(&self, dir: &'a [u8], query: ResourceName) -> Option<&'a [u8]> {
+        let section = self.section;
+        let entry =
+            IMAGE_RESOURCE_DIRECTORY::entries(dir).find(|entry| entry.name(section) == query)?;
+        match entry.value(section) {
+            ResourceValue::Dir(dir) => Some(dir),
+            ResourceValue::Data(_) => {
+                log::warn!("resource {query:?}: expected directory, found data");
+                None
+            }
+        }
+    }
+
+    /// The (lang, data) pairs of a language directory.
+    fn langs(&self, dir: &'a [u8]) -> impl Iterator<Item = (u32, Range<u32>)> + 'a {
+        let section = self.section;
+        IMAGE_RESOURCE_DIRECTORY::entries(dir).filter_map(move |entry| {
+            let ResourceName::Id(lang) = entry.name(section) else {
+                return None;
+            };
+            match entry.value(section) {
+                ResourceValue::Data(data) => Some((lang, data.range())),
+                ResourceValue::Dir(_) => None,
+            }
+        })
+    }
+
+    /// Look up a resource by type, name, and language; with no language given,
+    /// the first one present is used.
+    /// Returns the memory range within the image of the data.
+    pub fn find(
+        &self,
+        typ: ResourceName,
+        name: ResourceName,
+        lang: Option<u32>,
+    ) -> Option<Range<u32>> {
+        let dir = self.subdir(self.section, typ)?;
+        let dir = self.subdir(dir, name)?;
+        let mut langs = self.langs(dir);
+        match lang {
+            Some(lang) => langs.find(|&(l, _)| l == lang).map(|(_, data)| data),
+            None => langs.next().map(|(_, data)| data),
+        }
+    }
+
+    /// Iterate all resources of a given type.
+    pub fn entries(&self, typ: ResourceName) -> impl Iterator<Item = ResourceEntry<'a>> + 'a {
+        let section = self.section;
+        let tree = ResourceTree { section };
+        let names = self
+            .subdir(section, typ)
+            .map(IMAGE_RESOURCE_DIRECTORY::entries);
+        names.into_iter().flatten().flat_map(move |entry| {
+            let name = entry.name(section);
+            let langs = match entry.value(section) {
+                ResourceValue::Dir(dir) => Some(tree.langs(dir)),
+                ResourceValue::Data(_) => None,
+            };
+            langs
+                .into_iter()
+                .flatten()
+                .map(move |(lang, data)| ResourceEntry { name, lang, data })
+        })
+    }
+}
+
 /// Look up a resource by its type/id values.
 /// Returns the memory range within the image of the data.
 pub fn find_resource(
@@ -106,29 +211,74 @@ pub fn find_resource(
     query_type: ResourceName,
     query_id: ResourceName,
 ) -> Option<Range<u32>> {
-    // Resources are structured as generic nested directories, but in practice there
-    // are always exactly three levels with known semantics.
-    let mut dir = IMAGE_RESOURCE_DIRECTORY::entries(section);
-
-    let etype = dir.find(|entry| entry.name(section) == query_type)?;
-    let mut dir = match etype.value(section) {
-        ResourceValue::Dir(dir) => IMAGE_RESOURCE_DIRECTORY::entries(dir),
-        _ => todo!(),
-    };
-
-    let eid = dir.find(|entry| entry.name(section) == query_id)?;
-    let mut dir = match eid.value(section) {
-        ResourceValue::Dir(dir) => IMAGE_RESOURCE_DIRECTORY::entries(dir),
-        _ => todo!(),
-    };
-
-    let first = dir.next()?;
-    if dir.next().is_some() {
-        log::warn!("multiple res entries, picking first");
+    ResourceTree::new(section).find(query_type, query_id, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a resource section holding a single RT_STRING bundle, as the resource
+    /// compiler would for a string table with just string 1 ("hi") in it.
+    fn string_table_section() -> (Vec<u8>, u32) {
+        const SECTION_RVA: u32 = 0x3000;
+        fn dir(buf: &mut Vec<u8>, name: u32, offset: u32) {
+            buf.extend_from_slice(&[0; 12]); // Characteristics..MinorVersion
+            buf.extend_from_slice(&0u16.to_le_bytes()); // NumberOfNamedEntries
+            buf.extend_from_slice(&1u16.to_le_bytes()); // NumberOfIdEntries
+            buf.extend_from_slice(&name.to_le_bytes());
+            buf.extend_from_slice(&offset.to_le_bytes());
+        }
+        let mut buf = Vec::new();
+        dir(&mut buf, RT::STRING as u32, 0x8000_0018); // type
+        dir(&mut buf, 1, 0x8000_0030); // bundle 1 holds strings 0-15
+        dir(&mut buf, 0x409, 0x48); // en-US
+        let data_rva = SECTION_RVA + 0x58;
+        buf.extend_from_slice(&data_rva.to_le_bytes());
+        buf.extend_from_slice(&(16 * 2 + 4u32).to_le_bytes());
+        buf.extend_from_slice(&[0; 8]); // CodePage, Reserved
+        assert_eq!(buf.len(), 0x58);
+        // The bundle: 16 length-prefixed strings, all empty except index 1.
+        for i in 0..16 {
+            if i == 1 {
+                buf.extend_from_slice(&2u16.to_le_bytes());
+                buf.extend_from_slice(&(b'h' as u16).to_le_bytes());
+                buf.extend_from_slice(&(b'i' as u16).to_le_bytes());
+            } else {
+                buf.extend_from_slice(&0u16.to_le_bytes());
+            }
+        }
+        (buf, data_rva)
+    }
+
+    #[test]
+    fn string_table() {
+        let (section, data_rva) = string_table_section();
+        let tree = ResourceTree::new(&section);
+
+        let typ = ResourceName::Id(RT::STRING as u32);
+        let range = tree.find(typ, ResourceName::Id(1), None).unwrap();
+        assert_eq!(range, data_rva..data_rva + 36);
+        assert_eq!(
+            tree.find(typ, ResourceName::Id(1), Some(0x409)),
+            Some(range)
+        );
+        assert_eq!(tree.find(typ, ResourceName::Id(1), Some(0x407)), None);
+        assert_eq!(tree.find(typ, ResourceName::Id(2), None), None);
+        assert_eq!(
+            tree.find(
+                ResourceName::Id(RT::BITMAP as u32),
+                ResourceName::Id(1),
+                None
+            ),
+            None
+        );
+
+        let entries = tree.entries(typ).collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, ResourceName::Id(1));
+        assert_eq!(entries[0].lang, 0x409);
+        assert_eq!(entries[0].data, data_rva..data_rva + 36);
+        assert_eq!(tree.entries(ResourceName::Id(RT::ICON as u32)).count(), 0);
     }
-    let data = match first.value(section) {
-        ResourceValue::Data(data) => data,
-        _ => todo!(),
-    };
-    Some(data.OffsetToData..(data.OffsetToData + data.Size))
 }
@@ -0,0 +1,35 @@
+#![allow(non_snake_case)]
+#![allow(non_camel_case_types)]
+
+use memory::Extensions;
+
+/// The TLS directory, describing a module's static (__declspec(thread)) storage.
+/// Unlike most PE structures, the addresses here are VAs, not RVAs.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct IMAGE_TLS_DIRECTORY32 {
+    /// Template data to copy into each thread's TLS block.
+    pub StartAddressOfRawData: u32,
+    pub EndAddressOfRawData: u32,
+    /// Where the loader writes the module's TLS index.
+    pub AddressOfIndex: u32,
+    /// Null-terminated array of PIMAGE_TLS_CALLBACK.
+    pub AddressOfCallBacks: u32,
+    /// Bytes of zeros following the template data.
+    pub SizeOfZeroFill: u32,
+    pub Characteristics: u32,
+}
+unsafe impl memory::Pod for IMAGE_TLS_DIRECTORY32 {}
+
+impl IMAGE_TLS_DIRECTORY32 {
+    /// Total size of the TLS block, template plus zero fill.
+    pub fn block_size(&self) -> u32 {
+        self.EndAddressOfRawData
+            .saturating_sub(self.StartAddressOfRawData)
+            + self.SizeOfZeroFill
+    }
+}
+
+pub fn read_tls(section: &[u8]) -> IMAGE_TLS_DIRECTORY32 {
+    section.get_pod::<IMAGE_TLS_DIRECTORY32>(0)
+}
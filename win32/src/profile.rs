@@ -0,0 +1,143 @@
+//! An optional profiler, for finding where a slow program spends its time.
+//! It counts calls and accumulates host time per shim and per x86 callback target,
+//! and (with the emulator) periodically samples eip.
+//! It's off unless Machine::profiler is set, which costs one check per shim call.
+
+use crate::Machine;
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    future::Future,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+/// Instructions executed between eip samples.
+const SAMPLE_INTERVAL: usize = 10_000;
+
+#[derive(Default)]
+struct Stats {
+    calls: u64,
+    time: Duration,
+}
+
+impl Stats {
+    fn add(&mut self, time: Duration) {
+        self.calls += 1;
+        self.time += time;
+    }
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    /// Per shim, by name.
+    shims: HashMap<&'static str, Stats>,
+    /// Per x86 function called back into via call_x86, by address.
+    targets: HashMap<u32, Stats>,
+    /// Sample counts, by eip.
+    samples: HashMap<u32, u64>,
+    next_sample: usize,
+}
+
+impl Profiler {
+    pub fn record_shim(&mut self, name: &'static str, time: Duration) {
+        self.shims.entry(name).or_default().add(time);
+    }
+
+    pub fn record_target(&mut self, func: u32, time: Duration) {
+        self.targets.entry(func).or_default().add(time);
+    }
+
+    /// Called after each executed block; records eip every SAMPLE_INTERVAL instructions.
+    pub fn sample(&mut self, instr_count: usize, eip: u32) {
+        if instr_count < self.next_sample {
+            return;
+        }
+        self.next_sample = instr_count + SAMPLE_INTERVAL;
+        *self.samples.entry(eip).or_default() += 1;
+    }
+
+    /// A report of everything recorded, most expensive first.
+    /// Addresses are attributed to the nearest label at or below them.
+    pub fn report(&self, labels: &HashMap<u32, String>) -> String {
+        let mut sorted_labels: Vec<(u32, &str)> = labels
+            .iter()
+            .map(|(&addr, name)| (addr, name.as_str()))
+            .collect();
+        sorted_labels.sort();
+        let label = |addr: u32| -> String {
+            let i = sorted_labels.partition_point(|&(label_addr, _)| label_addr <= addr);
+            match i.checked_sub(1).map(|i| sorted_labels[i]) {
+                Some((label_addr, name)) if label_addr == addr => name.to_string(),
+                Some((label_addr, name)) => format!("{name}+{:x}", addr - label_addr),
+                None => format!("{addr:08x}"),
+            }
+        };
+
+        let mut out = String::new();
+        let mut stats = |title: &str, entries: Vec<(String, &Stats)>| {
+            let mut entries = entries;
+            entries.sort_by(|a, b| b.1.time.cmp(&a.1.time));
+            writeln!(out, "{title}:").unwrap();
+            writeln!(out, "{:>10} {:>12}  name", "calls", "total ms").unwrap();
+            for (name, stats) in entries {
+                let ms = stats.time.as_secs_f64() * 1000.0;
+                writeln!(out, "{:>10} {ms:>12.3}  {name}", stats.calls).unwrap();
+            }
+        };
+        stats(
+            "shims",
+            self.shims
+                .iter()
+                .map(|(&name, stats)| (name.to_string(), stats))
+                .collect(),
+        );
+        stats(
+            "callbacks",
+            self.targets
+                .iter()
+                .map(|(&addr, stats)| (label(addr), stats))
+                .collect(),
+        );
+
+        if !self.samples.is_empty() {
+            // Bucket by function rather than by exact eip.
+            let mut buckets: HashMap<String, u64> = HashMap::new();
+            for (&eip, &count) in &self.samples {
+                let i = sorted_labels.partition_point(|&(label_addr, _)| label_addr <= eip);
+                let name = match i.checked_sub(1) {
+                    Some(i) => sorted_labels[i].1.to_string(),
+                    None => "?".to_string(),
+                };
+                *buckets.entry(name).or_default() += count;
+            }
+            let total: u64 = buckets.values().sum();
+            let mut buckets: Vec<_> = buckets.into_iter().collect();
+            buckets.sort_by(|a, b| b.1.cmp(&a.1));
+            writeln!(out, "samples ({total}, every {SAMPLE_INTERVAL} instrs):").unwrap();
+            for (name, count) in buckets {
+                let pct = count as f64 * 100.0 / total as f64;
+                writeln!(out, "{count:>10} {pct:>11.1}%  {name}").unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// Wrap an async shim's future to record its time when it completes.
+/// This is wall time, so it includes time spent blocked and in callbacks.
+pub fn time_async(
+    machine: *mut Machine,
+    name: &'static str,
+    future: Pin<Box<dyn Future<Output = u32>>>,
+) -> Pin<Box<dyn Future<Output = u32>>> {
+    Box::pin(async move {
+        let start = Instant::now();
+        let ret = future.await;
+        let machine = unsafe { &mut *machine };
+        if let Some(profiler) = &mut machine.profiler {
+            profiler.record_shim(name, start.elapsed());
+        }
+        ret
+    })
+}
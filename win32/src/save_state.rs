@@ -0,0 +1,373 @@
+//! Save states: snapshots of a running Machine that it can later be rewound to, e.g. to
+//! replay a stretch of execution while debugging nondeterminism.
+//!
+//! A save state holds the CPUs, the contents of mapped memory, and the state of each DLL:
+//! handle tables, windows, GDI objects, DirectX objects and so on.  Async calls in flight
+//! (such as the call into the exe's entry point) can't be serialized, so a save state can
+//! only be restored into the Machine it came from, while the same calls are still pending.
+//!
+//! Host objects behind that state can't be serialized either, so restoring recreates them:
+//! windows and surfaces get new host counterparts showing their saved pixels, and open
+//! files and directory searches are reopened at their saved positions.  The contents of
+//! files and the host clock aren't rewound; replay is only exact if they don't influence
+//! the rerun.
+
+use crate::{
+    host,
+    machine::Machine,
+    winapi::{self, ddraw, dsound, user32},
+};
+use anyhow::{anyhow, bail};
+use memory::{Extensions, ExtensionsMut};
+
+/// Bumped whenever the layout of SaveState changes, so stale blobs are rejected.
+const VERSION: u32 = 2;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SaveState<S> {
+    /// Must come first, so it can be checked before decoding the rest.
+    version: u32,
+    x86: x86::snapshot::X86Snapshot,
+    /// The winapi::State, borrowed when saving.
+    state: S,
+    /// Contents of each committed mapping, by address.
+    memory: Vec<(u32, Vec<u8>)>,
+}
+
+/// Stands in for the host objects of a deserialized state until restore_state()
+/// replaces them; see detached().
+struct Detached;
+
+impl host::Window for Detached {
+    fn set_title(&mut self, _title: &str) {
+        unreachable!("detached window")
+    }
+    fn set_size(&mut self, _width: u32, _height: u32) {
+        unreachable!("detached window")
+    }
+    fn set_visible(&mut self, _visible: bool) {
+        unreachable!("detached window")
+    }
+    fn fullscreen(&mut self) {
+        unreachable!("detached window")
+    }
+}
+
+impl host::Surface for Detached {
+    fn write_pixels(&mut self, _pixels: &[[u8; 4]]) {
+        unreachable!("detached surface")
+    }
+    fn write_pixels_rect(&mut self, _x: u32, _y: u32, _w: u32, _h: u32, _pixels: &[[u8; 4]]) {
+        unreachable!("detached surface")
+    }
+    fn show(&mut self) {
+        unreachable!("detached surface")
+    }
+    fn bit_blt(
+        &mut self,
+        _dx: u32,
+        _dy: u32,
+        _src: &dyn host::Surface,
+        _sx: u32,
+        _sy: u32,
+        _w: u32,
+        _h: u32,
+    ) {
+        unreachable!("detached surface")
+    }
+    fn stretch_blt(
+        &mut self,
+        _dx: u32,
+        _dy: u32,
+        _dw: u32,
+        _dh: u32,
+        _src: &dyn host::Surface,
+        _sx: u32,
+        _sy: u32,
+        _sw: u32,
+        _sh: u32,
+    ) {
+        unreachable!("detached surface")
+    }
+}
+
+impl std::io::Read for Detached {
+    fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+        unreachable!("detached file")
+    }
+}
+impl std::io::Write for Detached {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        unreachable!("detached file")
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        unreachable!("detached file")
+    }
+}
+impl std::io::Seek for Detached {
+    fn seek(&mut self, _pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        unreachable!("detached file")
+    }
+}
+impl host::File for Detached {
+    fn stat(&self) -> Result<host::Stat, host::ERROR> {
+        unreachable!("detached file")
+    }
+    fn set_len(&self, _len: u64) -> Result<(), host::ERROR> {
+        unreachable!("detached file")
+    }
+}
+
+impl host::ReadDir for Detached {
+    fn next(&mut self) -> Result<Option<host::ReadDirEntry>, host::ERROR> {
+        unreachable!("detached directory search")
+    }
+}
+
+pub trait Detach {
+    fn detached() -> Box<Self>;
+}
+impl Detach for dyn host::Window {
+    fn detached() -> Box<Self> {
+        Box::new(Detached)
+    }
+}
+impl Detach for dyn host::Surface {
+    fn detached() -> Box<Self> {
+        Box::new(Detached)
+    }
+}
+impl Detach for dyn host::File {
+    fn detached() -> Box<Self> {
+        Box::new(Detached)
+    }
+}
+impl Detach for dyn host::ReadDir {
+    fn detached() -> Box<Self> {
+        Box::new(Detached)
+    }
+}
+
+/// Placeholder for a host object field skipped by serialization, for #[serde(default)].
+pub fn detached<T: Detach + ?Sized>() -> Box<T> {
+    T::detached()
+}
+
+/// Serde for arrays longer than the 32 elements serde supports, for #[serde(with)].
+pub mod array {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer, T: Serialize, const N: usize>(
+        array: &[T; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(array)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[T; N], D::Error> {
+        let vec = Vec::<T>::deserialize(deserializer)?;
+        let len = vec.len();
+        vec.try_into()
+            .map_err(|_| serde::de::Error::invalid_length(len, &"an array"))
+    }
+}
+
+/// Serde for an optional Windows path, which typed_path doesn't provide, for #[serde(with)].
+pub mod windows_path {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use typed_path::WindowsPathBuf;
+
+    pub fn serialize<S: Serializer>(
+        path: &Option<WindowsPathBuf>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        path.as_ref()
+            .map(|path| path.to_string_lossy())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<WindowsPathBuf>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?.map(WindowsPathBuf::from))
+    }
+}
+
+impl Machine {
+    /// Serialize the machine's state into a blob for restore_state().
+    pub fn save_state(&mut self) -> anyhow::Result<Vec<u8>> {
+        if !self.status.is_running() {
+            bail!("can't save state of a stopped machine");
+        }
+        for file in self.state.kernel32.files.iter_mut() {
+            file.save_position()?;
+        }
+        let mem = self.mem();
+        let memory = self
+            .state
+            .kernel32
+            .mappings
+            .vec()
+            .iter()
+            .filter(|mapping| mapping.committed && mapping.size > 0)
+            .map(|mapping| (mapping.addr, mem.sub32(mapping.addr, mapping.size).to_vec()))
+            .collect();
+        let state = SaveState {
+            version: VERSION,
+            x86: self.emu.x86.snapshot(),
+            state: &self.state,
+            memory,
+        };
+        Ok(bincode::serialize(&state)?)
+    }
+
+    /// Rewind the machine to a blob from save_state().
+    pub fn restore_state(&mut self, buf: &[u8]) -> anyhow::Result<()> {
+        let version: u32 = bincode::deserialize(buf)?;
+        if version != VERSION {
+            bail!("save state version {version}, expected {VERSION}");
+        }
+        let mut saved: SaveState<winapi::State> = bincode::deserialize(buf)?;
+
+        // Reopen files first, as they're the part of restoring that can fail.
+        let host = &*self.host;
+        for file in saved.state.kernel32.files.iter_mut() {
+            file.reopen(host)
+                .map_err(|err| anyhow!("reopening file: {err:?}"))?;
+        }
+        for find in saved.state.kernel32.find_handles.iter_mut() {
+            find.reopen(host)
+                .map_err(|err| anyhow!("reopening directory search: {err:?}"))?;
+        }
+
+        self.emu
+            .x86
+            .restore(&saved.x86)
+            .map_err(|err| anyhow!("restoring cpu: {err}"))?;
+        let mem = self.emu.memory.mem();
+        for (addr, bytes) in saved.memory {
+            mem.sub32_mut(addr, bytes.len() as u32)
+                .copy_from_slice(&bytes);
+        }
+        self.state = saved.state;
+        user32::reattach_windows(self);
+        ddraw::reattach_surfaces(self);
+        dsound::reattach_buffers(self);
+        self.status = crate::Status::Running;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use crate::{
+        machine_emu::RunStatus,
+        testing,
+        winapi::{gdi32, kernel32::get_symbol, types::*},
+    };
+    use memory::Extensions;
+    use std::{cell::RefCell, io::Read, rc::Rc};
+
+    #[test]
+    fn rewind_mid_run() {
+        let mut machine = testing::machine();
+        let counter = testing::alloc_data(&mut machine, &[0; 4]);
+        let counter_bytes = counter.to_le_bytes();
+
+        // loop: inc dword [counter]; cmp dword [counter], 1000; jne loop
+        // mov eax, [counter]; ret
+        let mut code = vec![0xff, 0x05];
+        code.extend_from_slice(&counter_bytes);
+        code.extend_from_slice(&[0x81, 0x3d]);
+        code.extend_from_slice(&counter_bytes);
+        code.extend_from_slice(&1000u32.to_le_bytes());
+        code.extend_from_slice(&[0x75, 0xee, 0xa1]);
+        code.extend_from_slice(&counter_bytes);
+        code.push(0xc3);
+        let entry = testing::alloc_code(&mut machine, &code);
+        testing::start(&mut machine, entry);
+
+        let run = |machine: &mut crate::Machine, n| {
+            let result = machine.run_for(n);
+            assert_eq!(result.status, RunStatus::BudgetExhausted);
+            (
+                machine.mem().get_pod::<u32>(counter),
+                machine.emu.x86.cpu().regs.eip,
+                machine.emu.x86.instr_count,
+            )
+        };
+        let saved_at = run(&mut machine, 200);
+        let state = machine.save_state().unwrap();
+
+        let later = run(&mut machine, 100);
+        assert!(later.0 > saved_at.0);
+        let scratch = testing::alloc_data(&mut machine, &[1; 4]);
+
+        machine.restore_state(&state).unwrap();
+        assert_eq!(machine.mem().get_pod::<u32>(counter), saved_at.0);
+        assert_eq!(machine.emu.x86.cpu().regs.eip, saved_at.1);
+        assert!(machine.state.kernel32.mappings.find(scratch).is_none());
+        // Rerunning the same stretch gets to the same place.
+        assert_eq!(run(&mut machine, 100), later);
+
+        assert!(matches!(
+            testing::run(&mut machine),
+            crate::Status::Exit(1000)
+        ));
+    }
+
+    #[test]
+    fn rewind_window_and_file() {
+        let host = testing::TestHost::default();
+        host.files.borrow_mut().insert(
+            "C:\\data.txt".into(),
+            Rc::new(RefCell::new(b"0123456789".to_vec())),
+        );
+        let windows = host.windows.clone();
+        let mut machine = crate::Machine::new(Box::new(host), "test.exe".into());
+
+        let mut code = Vec::new();
+        testing::create_window(&mut machine, &mut code, "saved", 0x9000_0000); // WS_POPUP | WS_VISIBLE
+        code.extend_from_slice(&[0x89, 0xc3]); // mov ebx, eax
+                                               // CreateFileA(path, GENERIC_READ, 0, NULL, OPEN_EXISTING, 0, NULL)
+        let path = testing::alloc_data(&mut machine, b"C:\\data.txt\0");
+        for arg in [0, 0, 3, 0, 0, 0x8000_0000, path] {
+            testing::push(&mut code, arg);
+        }
+        testing::call(
+            &mut code,
+            get_symbol(&mut machine, "kernel32.dll", "CreateFileA"),
+        );
+        code.extend_from_slice(&[0x89, 0xc6]); // mov esi, eax
+        testing::start_spinning(&mut machine, code);
+        let regs = &machine.emu.x86.cpu().regs;
+        let hwnd = HWND::from_raw(regs.get32(x86::Register::EBX));
+        let hfile = HFILE::from_raw(regs.get32(x86::Register::ESI));
+        assert!(!hwnd.is_null() && !hfile.is_invalid());
+
+        let read = |machine: &mut crate::Machine, len| {
+            let file = &mut machine.state.kernel32.files.get_mut(hfile).unwrap().file;
+            let mut buf = vec![0; len];
+            file.read_exact(&mut buf).unwrap();
+            buf
+        };
+        assert_eq!(read(&mut machine, 4), b"0123");
+        let state = machine.save_state().unwrap();
+
+        assert_eq!(read(&mut machine, 3), b"456");
+        machine.state.kernel32.files.remove(hfile);
+        machine.state.user32.windows.remove(hwnd);
+        let hdc = gdi32::CreateCompatibleDC(&mut machine, gdi32::HDC::null());
+
+        machine.restore_state(&state).unwrap();
+        let window = machine.state.user32.windows.get(hwnd).unwrap();
+        assert_eq!(window.text, "saved");
+        // The window got a new host window.
+        assert_eq!(*windows.borrow(), [hwnd.to_raw(), hwnd.to_raw()]);
+        assert_eq!(read(&mut machine, 3), b"456");
+        assert!(machine.state.gdi32.dcs.get(hdc).is_none());
+        assert_eq!(machine.run_for(10).status, RunStatus::BudgetExhausted);
+    }
+}
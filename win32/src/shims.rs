@@ -48,9 +48,8 @@ impl Shims {
 }
 
 /// Synchronously evaluate a Future, under the assumption that it is always immediately Ready.
-#[allow(deref_nullptr)]
 pub fn call_sync<T>(future: std::pin::Pin<&mut impl std::future::Future<Output = T>>) -> T {
-    let context: &mut std::task::Context = unsafe { &mut *std::ptr::null_mut() };
+    let context = &mut std::task::Context::from_waker(std::task::Waker::noop());
     match future.poll(context) {
         std::task::Poll::Pending => unreachable!(),
         std::task::Poll::Ready(t) => t,
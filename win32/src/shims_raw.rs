@@ -41,8 +41,16 @@ unsafe extern "C" fn call64() -> u32 {
         Err(name) => unimplemented!("{}", name),
     };
     let stack_args = STACK32 + 16; // stack[4]
+    let name = shim.name;
     match shim.func {
-        Handler::Sync(func) => func(machine, stack_args),
+        Handler::Sync(func) => {
+            let start = machine.profiler.as_ref().map(|_| std::time::Instant::now());
+            let ret = func(machine, stack_args);
+            if let (Some(profiler), Some(start)) = (&mut machine.profiler, start) {
+                profiler.record_shim(name, start.elapsed());
+            }
+            ret
+        }
         Handler::Async(_) => unimplemented!(),
     }
 }
@@ -0,0 +1,431 @@
+//! Helpers for tests that run hand-assembled x86 code on a Machine.
+
+use crate::{
+    host::*,
+    machine::{Machine, Status},
+    pe::ImageSectionFlags,
+    winapi,
+};
+use memory::ExtensionsMut;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+/// Contents of a file in TestHost's filesystem, shared by its open handles.
+pub type TestFile = Rc<RefCell<Vec<u8>>>;
+
+/// A host with no input, whose clock only advances when the emulator blocks with a
+/// deadline.  Its windows and surfaces draw nowhere.
+#[derive(Default)]
+pub struct TestHost {
+    pub ticks: Cell<u32>,
+    /// Everything written to stdout/stderr, shared so tests can read it once the
+    /// host is boxed up in a Machine.
+    pub output: Rc<RefCell<Vec<u8>>>,
+    /// The folders special_folder() knows about; others don't exist.
+    pub special_folders: HashMap<SpecialFolder, WindowsPathBuf>,
+    /// The filesystem, by Windows path.  Directories exist only as the parents of
+    /// files, and can't be listed.
+    pub files: Rc<RefCell<HashMap<String, TestFile>>>,
+    /// The hwnds create_window() was called for, in order.
+    pub windows: Rc<RefCell<Vec<u32>>>,
+    /// Everything queued on the streams open_audio() returned, in order.
+    pub audio: Rc<RefCell<Vec<u8>>>,
+}
+
+struct OpenTestFile {
+    data: TestFile,
+    pos: u64,
+}
+
+impl std::io::Read for OpenTestFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let data = self.data.borrow();
+        let start = (self.pos as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl std::io::Write for OpenTestFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut data = self.data.borrow_mut();
+        let end = self.pos as usize + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[self.pos as usize..end].copy_from_slice(buf);
+        self.pos = end as u64;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::io::Seek for OpenTestFile {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.pos = match pos {
+            std::io::SeekFrom::Start(ofs) => ofs,
+            std::io::SeekFrom::Current(ofs) => self.pos.saturating_add_signed(ofs),
+            std::io::SeekFrom::End(ofs) => {
+                (self.data.borrow().len() as u64).saturating_add_signed(ofs)
+            }
+        };
+        Ok(self.pos)
+    }
+}
+
+impl File for OpenTestFile {
+    fn stat(&self) -> Result<Stat, ERROR> {
+        Ok(Stat {
+            kind: StatKind::File,
+            size: self.data.borrow().len() as u64,
+            atime: 0,
+            ctime: 0,
+            mtime: 0,
+        })
+    }
+    fn set_len(&self, len: u64) -> Result<(), ERROR> {
+        self.data.borrow_mut().resize(len as usize, 0);
+        Ok(())
+    }
+}
+
+struct TestAudio(Rc<RefCell<Vec<u8>>>);
+impl Audio for TestAudio {
+    fn queue(&mut self, samples: &[u8]) {
+        self.0.borrow_mut().extend_from_slice(samples);
+    }
+}
+
+struct TestWindow;
+impl Window for TestWindow {
+    fn set_title(&mut self, _title: &str) {}
+    fn set_size(&mut self, _width: u32, _height: u32) {}
+    fn set_visible(&mut self, _visible: bool) {}
+    fn fullscreen(&mut self) {}
+}
+
+struct TestSurface;
+impl Surface for TestSurface {
+    fn write_pixels(&mut self, _pixels: &[[u8; 4]]) {}
+    fn write_pixels_rect(&mut self, _x: u32, _y: u32, _w: u32, _h: u32, _pixels: &[[u8; 4]]) {}
+    fn show(&mut self) {}
+    fn bit_blt(
+        &mut self,
+        _dx: u32,
+        _dy: u32,
+        _src: &dyn Surface,
+        _sx: u32,
+        _sy: u32,
+        _w: u32,
+        _h: u32,
+    ) {
+    }
+    fn stretch_blt(
+        &mut self,
+        _dx: u32,
+        _dy: u32,
+        _dw: u32,
+        _dh: u32,
+        _src: &dyn Surface,
+        _sx: u32,
+        _sy: u32,
+        _sw: u32,
+        _sh: u32,
+    ) {
+    }
+}
+
+impl Host for TestHost {
+    fn ticks(&self) -> u32 {
+        self.ticks.get()
+    }
+    fn system_time(&self) -> chrono::DateTime<chrono::Local> {
+        chrono::DateTime::default()
+    }
+    fn get_message(&self) -> Option<Message> {
+        None
+    }
+    fn block(&self, wait: Option<u32>) -> bool {
+        match wait {
+            Some(deadline) => {
+                self.ticks.set(self.ticks.get().max(deadline));
+                true
+            }
+            None => false,
+        }
+    }
+    fn current_dir(&self) -> Result<WindowsPathBuf, ERROR> {
+        Ok(WindowsPathBuf::from("C:\\"))
+    }
+    fn open(&self, path: &WindowsPath, options: FileOptions) -> Result<Box<dyn File>, ERROR> {
+        let path = path.to_string_lossy().into_owned();
+        let mut files = self.files.borrow_mut();
+        let data = match files.get(&path) {
+            Some(_) if options.create_new => return Err(ERROR::FILE_EXISTS),
+            Some(data) => data.clone(),
+            None if options.create || options.create_new => files.entry(path).or_default().clone(),
+            None => return Err(ERROR::FILE_NOT_FOUND),
+        };
+        if options.truncate {
+            data.borrow_mut().clear();
+        }
+        Ok(Box::new(OpenTestFile { data, pos: 0 }))
+    }
+    fn stat(&self, path: &WindowsPath) -> Result<Stat, ERROR> {
+        let path = path.to_string_lossy();
+        let files = self.files.borrow();
+        if let Some(data) = files.get(path.as_ref()) {
+            return OpenTestFile {
+                data: data.clone(),
+                pos: 0,
+            }
+            .stat();
+        }
+        let dir = path.trim_end_matches('\\').to_string() + "\\";
+        if files.keys().any(|file| file.starts_with(&dir)) {
+            return Ok(Stat {
+                kind: StatKind::Directory,
+                size: 0,
+                atime: 0,
+                ctime: 0,
+                mtime: 0,
+            });
+        }
+        Err(ERROR::FILE_NOT_FOUND)
+    }
+    fn read_dir(&self, _path: &WindowsPath) -> Result<Box<dyn ReadDir>, ERROR> {
+        Err(ERROR::FILE_NOT_FOUND)
+    }
+    fn create_dir(&self, _path: &WindowsPath) -> Result<(), ERROR> {
+        Err(ERROR::ACCESS_DENIED)
+    }
+    fn remove_file(&self, _path: &WindowsPath) -> Result<(), ERROR> {
+        Err(ERROR::FILE_NOT_FOUND)
+    }
+    fn remove_dir(&self, _path: &WindowsPath) -> Result<(), ERROR> {
+        Err(ERROR::FILE_NOT_FOUND)
+    }
+    fn log(&self, buf: &[u8]) {
+        self.output.borrow_mut().extend_from_slice(buf);
+    }
+    fn special_folder(&self, folder: SpecialFolder) -> Option<WindowsPathBuf> {
+        self.special_folders.get(&folder).cloned()
+    }
+    fn create_window(&mut self, hwnd: u32) -> Box<dyn Window> {
+        self.windows.borrow_mut().push(hwnd);
+        Box::new(TestWindow)
+    }
+    fn create_surface(&mut self, _hwnd: u32, _opts: &SurfaceOptions) -> Box<dyn Surface> {
+        Box::new(TestSurface)
+    }
+    fn open_audio(&mut self, _format: &AudioFormat) -> Box<dyn Audio> {
+        Box::new(TestAudio(self.audio.clone()))
+    }
+}
+
+pub fn machine() -> Machine {
+    Machine::new(Box::new(TestHost::default()), "test.exe".into())
+}
+
+/// Copy data into a fresh read/write mapping, returning its address.
+pub fn alloc_data(machine: &mut Machine, data: &[u8]) -> u32 {
+    let mapping = machine.state.kernel32.mappings.alloc(
+        data.len().max(1) as u32,
+        "test data".into(),
+        &mut machine.emu.memory,
+    );
+    let addr = mapping.addr;
+    machine
+        .mem()
+        .sub32_mut(addr, data.len() as u32)
+        .copy_from_slice(data);
+    addr
+}
+
+/// Copy code into a fresh executable mapping, returning its address.
+pub fn alloc_code(machine: &mut Machine, code: &[u8]) -> u32 {
+    let addr = alloc_data(machine, code);
+    machine.state.kernel32.mappings.modify(addr, 1, |m| {
+        m.flags |= ImageSectionFlags::MEM_EXECUTE;
+        m.desc = "test code".into();
+    });
+    addr
+}
+
+/// Set the machine up to start the process at entry_point, as load_exe does for an exe.
+pub fn start(machine: &mut Machine, entry_point: u32) {
+    let stack_pointer = machine.create_stack("stack".into(), 0x10000);
+    let retrowin32_main = winapi::kernel32::get_kernel32_builtin(machine, "retrowin32_main");
+    let mem = machine.emu.memory.mem();
+    let cpu = machine.emu.x86.cpu_mut();
+    cpu.regs.set32(x86::Register::ESP, stack_pointer);
+    cpu.regs.set32(x86::Register::EBP, stack_pointer);
+    cpu.regs.fs_addr = machine.state.kernel32.teb;
+    x86::ops::push(cpu, mem, entry_point);
+    x86::ops::push(cpu, mem, 0); // return address
+    cpu.regs.eip = retrowin32_main;
+}
+
+/// Start the process running code, and run it until it reaches the end, where it's left
+/// spinning so the test can look at the machine mid-run.
+pub fn start_spinning(machine: &mut Machine, mut code: Vec<u8>) {
+    code.extend_from_slice(&[0xeb, 0xfe]); // jmp $
+    let entry = alloc_code(machine, &code);
+    let spin = entry + code.len() as u32 - 2;
+    start(machine, entry);
+    while machine.emu.x86.cpu().regs.eip != spin {
+        let result = machine.run_for(1);
+        assert!(
+            matches!(result.status, crate::RunStatus::BudgetExhausted),
+            "{:?}",
+            result.status
+        );
+    }
+}
+
+/// Run until the machine stops, returning how it stopped.
+pub fn run(machine: &mut Machine) -> &Status {
+    while machine.run() {}
+    &machine.status
+}
+
+/// Start the process at entry_point and run it to exit, returning the exit code.
+pub fn run_exe(machine: &mut Machine, entry_point: u32) -> u32 {
+    start(machine, entry_point);
+    match run(machine) {
+        Status::Exit(code) => *code,
+        Status::Error { message } => panic!("machine error: {message}"),
+        Status::Blocked => panic!("machine blocked"),
+        Status::DebugBreak => panic!("machine hit a breakpoint"),
+        Status::Running => unreachable!(),
+    }
+}
+
+/// Append `push imm32`.
+pub fn push(code: &mut Vec<u8>, value: u32) {
+    code.push(0x68);
+    code.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Append a call to func, which clobbers eax.
+pub fn call(code: &mut Vec<u8>, func: u32) {
+    code.push(0xb8); // mov eax, func
+    code.extend_from_slice(&func.to_le_bytes());
+    code.extend_from_slice(&[0xff, 0xd0]); // call eax
+}
+
+/// An argument to a call appended by kernel32().
+pub enum Arg {
+    Imm(u32),
+    /// A dword read from memory at call time.
+    Mem(u32),
+}
+
+/// Append a stdcall to a kernel32 function, leaving its result in eax.
+pub fn kernel32(machine: &mut Machine, code: &mut Vec<u8>, name: &str, args: &[Arg]) {
+    for arg in args.iter().rev() {
+        match *arg {
+            Arg::Imm(value) => push(code, value),
+            Arg::Mem(addr) => {
+                code.extend_from_slice(&[0xff, 0x35]); // push dword [addr]
+                code.extend_from_slice(&addr.to_le_bytes());
+            }
+        }
+    }
+    call(code, winapi::kernel32::get_kernel32_builtin(machine, name));
+}
+
+/// Append `mov [addr], eax`.
+pub fn store_eax(code: &mut Vec<u8>, addr: u32) {
+    code.push(0xa3);
+    code.extend_from_slice(&addr.to_le_bytes());
+}
+
+/// Append `mov eax, [addr]`.
+pub fn load_eax(code: &mut Vec<u8>, addr: u32) {
+    code.push(0xa1);
+    code.extend_from_slice(&addr.to_le_bytes());
+}
+
+/// Append CreateThread(NULL, 0, func, param, flags, NULL), leaving the handle in ebx.
+pub fn create_thread(machine: &mut Machine, code: &mut Vec<u8>, func: u32, param: u32, flags: u32) {
+    let args = [0, 0, func, param, flags, 0].map(Arg::Imm);
+    kernel32(machine, code, "CreateThread", &args);
+    code.extend_from_slice(&[0x89, 0xc3]); // mov ebx, eax
+}
+
+/// Append WaitForSingleObject(ebx, INFINITE), e.g. to wait for a thread.
+pub fn wait_ebx(machine: &mut Machine, code: &mut Vec<u8>) {
+    push(code, 0xFFFF_FFFF); // INFINITE
+    code.push(0x53); // push ebx
+    call(
+        code,
+        winapi::kernel32::get_kernel32_builtin(machine, "WaitForSingleObject"),
+    );
+}
+
+/// Append code that registers a window class with DefWindowProcA as its window procedure
+/// and creates a window of it, leaving the HWND in eax.  The class is named after the
+/// title, so titles must differ.
+pub fn create_window(machine: &mut Machine, code: &mut Vec<u8>, title: &str, style: u32) {
+    let class_name = alloc_data(machine, format!("{title} class\0").as_bytes());
+    let title = alloc_data(machine, format!("{title}\0").as_bytes());
+    let wndproc = winapi::kernel32::get_symbol(machine, "user32.dll", "DefWindowProcA");
+    let wndclass: Vec<u8> = [0, wndproc, 0, 0, 0, 0, 0, 0, 0, class_name]
+        .iter()
+        .flat_map(|dw: &u32| dw.to_le_bytes())
+        .collect();
+    let wndclass = alloc_data(machine, &wndclass);
+
+    push(code, wndclass);
+    call(
+        code,
+        winapi::kernel32::get_symbol(machine, "user32.dll", "RegisterClassA"),
+    );
+    // CreateWindowExA(0, atom, title, style, 0, 0, 64, 64, NULL, NULL, NULL, NULL)
+    for arg in [0, 0, 0, 0, 64, 64, 0, 0, style, title] {
+        push(code, arg);
+    }
+    code.push(0x50); // push eax
+    push(code, 0);
+    call(
+        code,
+        winapi::kernel32::get_symbol(machine, "user32.dll", "CreateWindowExA"),
+    );
+}
+
+/// Append code that creates a visible child window of the registered class named class,
+/// with the HWND of its parent in ebx, leaving the child's HWND in eax.
+pub fn create_child_window(machine: &mut Machine, code: &mut Vec<u8>, class: &str, id: u32) {
+    let class = alloc_data(machine, format!("{class}\0").as_bytes());
+    // CreateWindowExA(0, class, "", WS_CHILD | WS_VISIBLE, 0, 0, 32, 16, ebx, id, NULL, NULL)
+    for arg in [0, 0, id] {
+        push(code, arg);
+    }
+    code.push(0x53); // push ebx
+    let title = alloc_data(machine, b"\0");
+    for arg in [16, 32, 0, 0, 0x5000_0000, title, class, 0] {
+        push(code, arg);
+    }
+    call(
+        code,
+        winapi::kernel32::get_symbol(machine, "user32.dll", "CreateWindowExA"),
+    );
+}
+
+/// Run a shim's future to completion, for shims that don't end up calling x86 code.
+pub fn poll<F: std::future::Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let context = &mut std::task::Context::from_waker(std::task::Waker::noop());
+    match future.as_mut().poll(context) {
+        std::task::Poll::Ready(output) => output,
+        std::task::Poll::Pending => panic!("shim called into x86 code"),
+    }
+}
@@ -1,12 +1,275 @@
 #![allow(non_snake_case)]
 
-use super::types::Str16;
+use super::{kernel32, types::Str16, ERROR};
 use crate::machine::Machine;
+use anyhow::{anyhow, bail};
+use memory::ExtensionsMut;
+use std::collections::{BTreeMap, HashMap};
 
 const TRACE_CONTEXT: &'static str = "advapi32";
 
 pub type HKEY = u32;
 
+const HKEY_CLASSES_ROOT: HKEY = 0x8000_0000;
+const HKEY_CURRENT_USER: HKEY = 0x8000_0001;
+const HKEY_LOCAL_MACHINE: HKEY = 0x8000_0002;
+const HKEY_USERS: HKEY = 0x8000_0003;
+
+/// The root key names, as found in .reg files.
+const ROOTS: [(HKEY, &str); 4] = [
+    (HKEY_CLASSES_ROOT, "HKEY_CLASSES_ROOT"),
+    (HKEY_CURRENT_USER, "HKEY_CURRENT_USER"),
+    (HKEY_LOCAL_MACHINE, "HKEY_LOCAL_MACHINE"),
+    (HKEY_USERS, "HKEY_USERS"),
+];
+
+/// Handles for opened keys start here, well clear of the predefined keys.
+const HKEY_BASE: HKEY = 0x4000;
+
+const REG_SZ: u32 = 1;
+const REG_BINARY: u32 = 3;
+const REG_DWORD: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Value {
+    Sz(String),
+    Dword(u32),
+    Binary(Vec<u8>),
+}
+
+impl Value {
+    fn typ(&self) -> u32 {
+        match self {
+            Value::Sz(_) => REG_SZ,
+            Value::Dword(_) => REG_DWORD,
+            Value::Binary(_) => REG_BINARY,
+        }
+    }
+
+    /// The value's data as returned by the A or (if wide) W APIs.
+    fn data(&self, wide: bool) -> Vec<u8> {
+        match self {
+            Value::Sz(s) if wide => s
+                .encode_utf16()
+                .chain(std::iter::once(0))
+                .flat_map(|c| c.to_le_bytes())
+                .collect(),
+            Value::Sz(s) => s
+                .encode_utf16()
+                .map(kernel32::to_ansi)
+                .chain(std::iter::once(0))
+                .collect(),
+            Value::Dword(n) => n.to_le_bytes().to_vec(),
+            Value::Binary(b) => b.clone(),
+        }
+    }
+}
+
+/// A registry key.  Names are case-insensitive, so children are keyed by their
+/// lowercased name, alongside the name as given.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Key {
+    subkeys: BTreeMap<String, (String, Key)>,
+    values: BTreeMap<String, (String, Value)>,
+}
+
+impl Key {
+    /// Look up a backslash-separated path below this key.
+    fn subkey(&self, path: &str) -> Option<&Key> {
+        path.split('\\')
+            .filter(|name| !name.is_empty())
+            .try_fold(self, |key, name| {
+                key.subkeys.get(&name.to_lowercase()).map(|(_, key)| key)
+            })
+    }
+
+    /// Find or create the key at a backslash-separated path below this key.
+    pub fn create(&mut self, path: &str) -> &mut Key {
+        path.split('\\')
+            .filter(|name| !name.is_empty())
+            .fold(self, |key, name| {
+                &mut key
+                    .subkeys
+                    .entry(name.to_lowercase())
+                    .or_insert_with(|| (name.to_string(), Key::default()))
+                    .1
+            })
+    }
+
+    /// Set a value; the empty name is the key's default value.
+    pub fn set_value(&mut self, name: &str, value: Value) {
+        self.values
+            .insert(name.to_lowercase(), (name.to_string(), value));
+    }
+
+    fn value(&self, name: &str) -> Option<&Value> {
+        self.values
+            .get(&name.to_lowercase())
+            .map(|(_, value)| value)
+    }
+
+    /// Merge in the contents of a .reg file, as exported by regedit.
+    /// Only string, dword and hex (binary) values are supported.
+    pub fn load_reg(&mut self, text: &str) -> anyhow::Result<()> {
+        // Join lines continued with a trailing backslash, as in long hex: values.
+        let text = text.replace("\\\r\n", "").replace("\\\n", "");
+        let mut path: Option<String> = None;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with(';')
+                || line == "REGEDIT4"
+                || line.starts_with("Windows Registry Editor")
+            {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[') {
+                let section = section
+                    .strip_suffix(']')
+                    .ok_or_else(|| anyhow!("bad section {line:?}"))?;
+                if section.starts_with('-') {
+                    bail!("key deletion unsupported: {line:?}");
+                }
+                self.create(section);
+                path = Some(section.to_string());
+                continue;
+            }
+            let Some(path) = &path else {
+                bail!("value outside of a key: {line:?}");
+            };
+            let (name, rest) = if let Some(rest) = line.strip_prefix('@') {
+                (String::new(), rest)
+            } else {
+                parse_reg_string(line).ok_or_else(|| anyhow!("bad value name: {line:?}"))?
+            };
+            let data = rest
+                .trim_start()
+                .strip_prefix('=')
+                .ok_or_else(|| anyhow!("expected '=': {line:?}"))?
+                .trim();
+            let value = if data.starts_with('"') {
+                Value::Sz(
+                    parse_reg_string(data)
+                        .ok_or_else(|| anyhow!("bad string: {line:?}"))?
+                        .0,
+                )
+            } else if let Some(hex) = data.strip_prefix("dword:") {
+                Value::Dword(u32::from_str_radix(hex, 16)?)
+            } else if let Some(hex) = data.strip_prefix("hex:") {
+                Value::Binary(
+                    hex.split(',')
+                        .map(|b| b.trim())
+                        .filter(|b| !b.is_empty())
+                        .map(|b| u8::from_str_radix(b, 16))
+                        .collect::<Result<_, _>>()?,
+                )
+            } else {
+                bail!("unsupported value type: {line:?}");
+            };
+            self.create(path).set_value(&name, value);
+        }
+        Ok(())
+    }
+}
+
+/// Parse a quoted .reg file string with backslash escapes, returning it and the rest of the line.
+fn parse_reg_string(text: &str) -> Option<(String, &str)> {
+    let mut chars = text.strip_prefix('"')?.char_indices();
+    let mut out = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((out, &text[i + 2..])),
+            '\\' => out.push(chars.next()?.1),
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct State {
+    /// The root of the registry, holding the HKEY_* keys by name.
+    pub registry: Key,
+    /// Open HKEYs, mapped to their path from the root.
+    open: HashMap<HKEY, String>,
+    next_hkey: HKEY,
+}
+
+impl State {
+    /// The path from the registry root of a predefined or opened key.
+    fn path(&self, hKey: HKEY) -> Option<String> {
+        if let Some((_, name)) = ROOTS.iter().find(|(root, _)| *root == hKey) {
+            return Some(name.to_string());
+        }
+        self.open.get(&hKey).cloned()
+    }
+
+    fn key(&self, hKey: HKEY) -> Option<&Key> {
+        self.registry.subkey(&self.path(hKey)?)
+    }
+}
+
+/// Copy registry data into an x86 buffer of *lpcbData bytes, where a null buffer
+/// just queries the size, as RegQueryValueEx and RegEnumValue do.
+fn copy_data(machine: &mut Machine, data: &[u8], lpData: u32, lpcbData: Option<&mut u32>) -> u32 {
+    let Some(cb) = lpcbData else {
+        // Without a size there can be no buffer, so this was just an existence check.
+        return ERROR::SUCCESS as u32;
+    };
+    let len = data.len() as u32;
+    if lpData == 0 {
+        *cb = len;
+        return ERROR::SUCCESS as u32;
+    }
+    if *cb < len {
+        *cb = len;
+        return ERROR::MORE_DATA as u32;
+    }
+    machine.mem().sub32_mut(lpData, len).copy_from_slice(data);
+    *cb = len;
+    ERROR::SUCCESS as u32
+}
+
+/// Copy a nul-terminated ANSI name into an x86 buffer of *lpcchName chars, leaving
+/// *lpcchName as the length excluding the nul, as the RegEnum* functions do.
+fn copy_name(machine: &mut Machine, name: &str, lpName: u32, lpcchName: Option<&mut u32>) -> u32 {
+    let Some(cch) = lpcchName else {
+        return ERROR::INVALID_PARAMETER as u32;
+    };
+    let bytes: Vec<u8> = name.encode_utf16().map(kernel32::to_ansi).collect();
+    if lpName == 0 || (*cch as usize) < bytes.len() + 1 {
+        return ERROR::MORE_DATA as u32;
+    }
+    let buf = machine.mem().sub32_mut(lpName, bytes.len() as u32 + 1);
+    buf[..bytes.len()].copy_from_slice(&bytes);
+    buf[bytes.len()] = 0;
+    *cch = bytes.len() as u32;
+    ERROR::SUCCESS as u32
+}
+
+fn query_value(
+    machine: &mut Machine,
+    hKey: HKEY,
+    name: &str,
+    wide: bool,
+    lpType: Option<&mut u32>,
+    lpData: u32,
+    lpcbData: Option<&mut u32>,
+) -> u32 {
+    let state = &machine.state.advapi32;
+    let Some(key) = state.key(hKey) else {
+        return ERROR::INVALID_HANDLE as u32;
+    };
+    let Some(value) = key.value(name) else {
+        return ERROR::FILE_NOT_FOUND as u32;
+    };
+    let (typ, data) = (value.typ(), value.data(wide));
+    if let Some(lpType) = lpType {
+        *lpType = typ;
+    }
+    copy_data(machine, &data, lpData, lpcbData)
+}
+
 #[win32_derive::dllexport]
 pub fn RegCreateKeyA(
     _machine: &mut Machine,
@@ -35,24 +298,48 @@ pub fn RegCreateKeyExW(
 
 #[win32_derive::dllexport]
 pub fn RegOpenKeyExA(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     hKey: HKEY,
     lpSubKey: Option<&str>,
     ulOptions: u32,
     samDesired: u32,
     phkResult: Option<&mut HKEY>,
 ) -> u32 {
-    todo!();
+    let Some(phkResult) = phkResult else {
+        return ERROR::INVALID_PARAMETER as u32;
+    };
+    let state = &mut machine.state.advapi32;
+    let Some(parent) = state.path(hKey) else {
+        return ERROR::INVALID_HANDLE as u32;
+    };
+    let path = match lpSubKey {
+        Some(sub) if !sub.is_empty() => format!("{parent}\\{sub}"),
+        _ => parent,
+    };
+    if state.registry.subkey(&path).is_none() {
+        return ERROR::FILE_NOT_FOUND as u32;
+    }
+    let hkey = HKEY_BASE + state.next_hkey;
+    state.next_hkey += 1;
+    state.open.insert(hkey, path);
+    *phkResult = hkey;
+    ERROR::SUCCESS as u32
 }
 
 #[win32_derive::dllexport]
-pub fn RegCloseKey(_machine: &mut Machine, hKey: HKEY) -> u32 {
-    0 // success
+pub fn RegCloseKey(machine: &mut Machine, hKey: HKEY) -> u32 {
+    if ROOTS.iter().any(|(root, _)| *root == hKey)
+        || machine.state.advapi32.open.remove(&hKey).is_some()
+    {
+        ERROR::SUCCESS as u32
+    } else {
+        ERROR::INVALID_HANDLE as u32
+    }
 }
 
 #[win32_derive::dllexport]
 pub fn RegQueryValueExA(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     hKey: HKEY,
     lpValueName: Option<&str>,
     lpReserved: u32,
@@ -60,12 +347,13 @@ pub fn RegQueryValueExA(
     lpData: u32,
     lpcbData: Option<&mut u32>,
 ) -> u32 {
-    2 // ERROR_FILE_NOT_FOUND
+    let name = lpValueName.unwrap_or_default().to_string();
+    query_value(machine, hKey, &name, false, lpType, lpData, lpcbData)
 }
 
 #[win32_derive::dllexport]
 pub fn RegQueryValueExW(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     hKey: HKEY,
     lpValueName: Option<&Str16>,
     lpReserved: u32,
@@ -73,7 +361,62 @@ pub fn RegQueryValueExW(
     lpData: u32,
     lpcbData: Option<&mut u32>,
 ) -> u32 {
-    2 // ERROR_FILE_NOT_FOUND
+    let name = lpValueName.map(|s| s.to_string()).unwrap_or_default();
+    query_value(machine, hKey, &name, true, lpType, lpData, lpcbData)
+}
+
+#[win32_derive::dllexport]
+pub fn RegEnumKeyExA(
+    machine: &mut Machine,
+    hKey: HKEY,
+    dwIndex: u32,
+    lpName: u32,
+    lpcchName: Option<&mut u32>,
+    lpReserved: u32,
+    lpClass: u32,
+    lpcchClass: Option<&mut u32>,
+    lpftLastWriteTime: u32,
+) -> u32 {
+    let Some(key) = machine.state.advapi32.key(hKey) else {
+        return ERROR::INVALID_HANDLE as u32;
+    };
+    let Some((name, _)) = key.subkeys.values().nth(dwIndex as usize) else {
+        return ERROR::NO_MORE_ITEMS as u32;
+    };
+    if let Some(cch) = lpcchClass {
+        *cch = 0; // no class names
+    }
+    let name = name.clone();
+    copy_name(machine, &name, lpName, lpcchName)
+}
+
+#[win32_derive::dllexport]
+pub fn RegEnumValueA(
+    machine: &mut Machine,
+    hKey: HKEY,
+    dwIndex: u32,
+    lpValueName: u32,
+    lpcchValueName: Option<&mut u32>,
+    lpReserved: u32,
+    lpType: Option<&mut u32>,
+    lpData: u32,
+    lpcbData: Option<&mut u32>,
+) -> u32 {
+    let Some(key) = machine.state.advapi32.key(hKey) else {
+        return ERROR::INVALID_HANDLE as u32;
+    };
+    let Some((name, value)) = key.values.values().nth(dwIndex as usize) else {
+        return ERROR::NO_MORE_ITEMS as u32;
+    };
+    let (name, typ, data) = (name.clone(), value.typ(), value.data(false));
+    let err = copy_name(machine, &name, lpValueName, lpcchValueName);
+    if err != ERROR::SUCCESS as u32 {
+        return err;
+    }
+    if let Some(lpType) = lpType {
+        *lpType = typ;
+    }
+    copy_data(machine, &data, lpData, lpcbData)
 }
 
 #[win32_derive::dllexport]
@@ -101,3 +444,143 @@ pub fn RegSetValueExW(
 ) -> u32 {
     0 // success
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_reg() {
+        let mut root = Key::default();
+        root.load_reg(
+            r#"REGEDIT4
+
+[HKEY_LOCAL_MACHINE\Software\Game]
+@="default"
+"Path"="C:\\Games\\\"Game\""
+"Count"=dword:00000010
+"Blob"=hex:01,02,\
+  ff
+"#,
+        )
+        .unwrap();
+        let key = root.subkey("hkey_local_machine\\SOFTWARE\\game").unwrap();
+        assert_eq!(key.value(""), Some(&Value::Sz("default".into())));
+        assert_eq!(
+            key.value("path"),
+            Some(&Value::Sz("C:\\Games\\\"Game\"".into()))
+        );
+        assert_eq!(key.value("Count"), Some(&Value::Dword(16)));
+        assert_eq!(key.value("Blob"), Some(&Value::Binary(vec![1, 2, 0xff])));
+        assert!(root.subkey("HKEY_LOCAL_MACHINE\\Software\\Other").is_none());
+
+        assert!(root.load_reg("\"Orphan\"=dword:1").is_err());
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_reg_apis() {
+        use crate::testing;
+        use memory::Extensions;
+
+        let mut machine = testing::machine();
+        let registry = &mut machine.state.advapi32.registry;
+        registry
+            .create("HKEY_LOCAL_MACHINE\\Software\\Game\\Saves")
+            .set_value("Slot", Value::Dword(3));
+        let game = registry.create("HKEY_LOCAL_MACHINE\\Software\\Game");
+        game.set_value("Path", Value::Sz("C:\\Game".into()));
+        game.set_value("Count", Value::Dword(16));
+        let ok = ERROR::SUCCESS as u32;
+
+        // Opening needs somewhere to put the key.
+        let software = Some("software\\game");
+        assert_eq!(
+            RegOpenKeyExA(&mut machine, HKEY_LOCAL_MACHINE, software, 0, 0, None),
+            ERROR::INVALID_PARAMETER as u32
+        );
+        let mut missing = 0;
+        assert_eq!(
+            RegOpenKeyExA(
+                &mut machine,
+                HKEY_LOCAL_MACHINE,
+                Some("Software\\Other"),
+                0,
+                0,
+                Some(&mut missing)
+            ),
+            ERROR::FILE_NOT_FOUND as u32
+        );
+        let mut hkey = 0;
+        assert_eq!(
+            RegOpenKeyExA(
+                &mut machine,
+                HKEY_LOCAL_MACHINE,
+                software,
+                0,
+                0,
+                Some(&mut hkey)
+            ),
+            ok
+        );
+
+        // Query the size, then too small a buffer, then the value.
+        let buf = testing::alloc_data(&mut machine, &[0xFF; 16]);
+        let (mut typ, mut cb) = (0, 0);
+        let query = |machine: &mut Machine, name, typ: &mut u32, data, cb: &mut u32| {
+            RegQueryValueExA(machine, hkey, Some(name), 0, Some(typ), data, Some(cb))
+        };
+        assert_eq!(query(&mut machine, "path", &mut typ, 0, &mut cb), ok);
+        assert_eq!((typ, cb), (REG_SZ, 8));
+        cb = 4;
+        assert_eq!(
+            query(&mut machine, "path", &mut typ, buf, &mut cb),
+            ERROR::MORE_DATA as u32
+        );
+        assert_eq!(cb, 8);
+        assert_eq!(query(&mut machine, "path", &mut typ, buf, &mut cb), ok);
+        assert_eq!(machine.mem().sub32(buf, 8), b"C:\\Game\0");
+        cb = 16;
+        assert_eq!(query(&mut machine, "Count", &mut typ, buf, &mut cb), ok);
+        assert_eq!(
+            (typ, cb, machine.mem().get_pod::<u32>(buf)),
+            (REG_DWORD, 4, 16)
+        );
+        assert_eq!(
+            query(&mut machine, "Nope", &mut typ, buf, &mut cb),
+            ERROR::FILE_NOT_FOUND as u32
+        );
+
+        // Enumerate the subkeys and values.
+        let mut cch = 16;
+        assert_eq!(
+            RegEnumKeyExA(&mut machine, hkey, 0, buf, Some(&mut cch), 0, 0, None, 0),
+            ok
+        );
+        assert_eq!((cch, machine.mem().sub32(buf, 6)), (5, &b"Saves\0"[..]));
+        assert_eq!(
+            RegEnumKeyExA(&mut machine, hkey, 1, buf, Some(&mut cch), 0, 0, None, 0),
+            ERROR::NO_MORE_ITEMS as u32
+        );
+        let names: Vec<_> = (0..)
+            .map_while(|i| {
+                let mut cch = 16;
+                let err =
+                    RegEnumValueA(&mut machine, hkey, i, buf, Some(&mut cch), 0, None, 0, None);
+                (err == ok)
+                    .then(|| String::from_utf8(machine.mem().sub32(buf, cch).to_vec()).unwrap())
+            })
+            .collect();
+        assert_eq!(names, ["Count", "Path"]);
+
+        assert_eq!(RegCloseKey(&mut machine, hkey), ok);
+        assert_eq!(
+            RegCloseKey(&mut machine, hkey),
+            ERROR::INVALID_HANDLE as u32
+        );
+        assert_eq!(
+            query(&mut machine, "Path", &mut typ, 0, &mut cb),
+            ERROR::INVALID_HANDLE as u32
+        );
+    }
+}
@@ -4,7 +4,7 @@
 #![allow(non_snake_case)]
 
 use super::types::*;
-use memory::{Extensions, Mem};
+use memory::{Extensions, ExtensionsMut, Mem};
 
 #[derive(Debug, Eq, PartialEq, win32_derive::TryFromEnum)]
 pub enum BI {
@@ -16,6 +16,24 @@ pub enum BI {
     PNG = 5,
 }
 
+/// A palette entry, shared by GDI logical palettes and DirectDraw palettes.
+#[repr(C)]
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct PALETTEENTRY {
+    pub peRed: u8,
+    pub peGreen: u8,
+    pub peBlue: u8,
+    pub peFlags: u8,
+}
+unsafe impl memory::Pod for PALETTEENTRY {}
+
+impl PALETTEENTRY {
+    /// The RGBA color a palette index maps to, for both GDI and DirectDraw.
+    pub fn to_rgba(&self) -> [u8; 4] {
+        [self.peRed, self.peGreen, self.peBlue, 0xFF]
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct BITMAPCOREHEADER {
@@ -67,6 +85,11 @@ impl BITMAPINFOHEADER {
     pub fn compression(&self) -> Result<BI, u32> {
         BI::try_from(self.biCompression)
     }
+    /// Whether BitmapRGBA32::parse can decode pixels in this format.
+    pub fn is_decodable(&self) -> bool {
+        matches!(self.compression(), Ok(BI::RGB | BI::BITFIELDS))
+            && matches!(self.biBitCount, 1 | 4 | 8 | 24 | 32)
+    }
 }
 
 /// The parsed header of a bitmap, either v2 (BITMAPCOREHEADER) or v3 (BITMAPINFOHEADER).
@@ -124,27 +147,30 @@ impl<'a> BitmapInfo<'a> {
 
     /// buf is the bytes following the header.
     fn parseBMPv3(header: &BITMAPINFOHEADER, buf: &'a [u8]) -> Self {
+        let compression = header.compression().unwrap();
         let palette_len = match header.biBitCount {
+            8 | 4 | 1 if header.biClrUsed != 0 => header.biClrUsed as usize,
             8 => 256,
             4 => 16,
             1 => 2,
-            _ => unimplemented!(),
+            _ => 0,
         };
-        // TODO: biClrUsed determines the size of the palette.
+        // With BI_BITFIELDS, three DWORD color masks precede the palette.
+        let masks_size = if compression == BI::BITFIELDS { 12 } else { 0 };
         let palette_entry_size = 4usize;
         let palette_size = palette_len * palette_entry_size;
-        let palette = buf.sub32(0, palette_size as u32);
+        let palette = buf.sub32(masks_size as u32, palette_size as u32);
 
         BitmapInfo {
             width: header.biWidth as usize,
             height: header.height() as usize,
             stride: header.stride(),
-            is_top_down: false,
+            is_top_down: header.is_top_down(),
             bit_count: header.biBitCount as u8,
-            compression: BI::RGB,
+            compression,
             palette_entry_size,
             palette,
-            header_length: 40 + palette_size,
+            header_length: 40 + masks_size + palette_size,
         }
     }
 }
@@ -154,6 +180,7 @@ pub trait Bitmap {
     fn height(&self) -> u32;
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub enum PixelData<T> {
     Owned(Box<[T]>),
     Ptr(u32, u32),
@@ -180,17 +207,30 @@ impl<T: memory::Pod> PixelData<T> {
         }
     }
 
-    pub fn as_slice_mut(&mut self) -> &mut [T] {
+    pub fn as_slice_mut<'a>(&'a mut self, mem: Mem<'a>) -> &'a mut [T] {
         match self {
             PixelData::Owned(b) => &mut *b,
-            _ => unimplemented!(),
+            &mut PixelData::Ptr(addr, len) => {
+                let bytes = mem.sub32_mut(addr, len);
+                assert!(bytes.len() % std::mem::size_of::<T>() == 0);
+                unsafe {
+                    std::slice::from_raw_parts_mut(
+                        bytes.as_mut_ptr() as *mut _,
+                        bytes.len() / std::mem::size_of::<T>(),
+                    )
+                }
+            }
         }
     }
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct BitmapRGBA32 {
     pub width: u32,
     pub height: u32,
+    /// False only for bottom-up DIB sections, whose rows the program lays out in x86
+    /// memory from the bottom up; see to_top_down.
+    pub top_down: bool,
     pub pixels: PixelData<[u8; 4]>,
 }
 
@@ -207,10 +247,41 @@ impl BitmapRGBA32 {
         BitmapRGBA32 {
             width: self.width,
             height: self.height,
+            top_down: self.top_down,
             pixels,
         }
     }
 
+    /// A top-down copy of the pixels, e.g. for blitting from.
+    pub fn to_top_down(&self, mem: Mem) -> BitmapRGBA32 {
+        if self.top_down || self.width == 0 {
+            return self.clone();
+        }
+        let rows = self.pixels.as_slice(mem).chunks_exact(self.width as usize);
+        BitmapRGBA32 {
+            width: self.width,
+            height: self.height,
+            top_down: true,
+            pixels: PixelData::Owned(rows.rev().flatten().copied().collect()),
+        }
+    }
+
+    /// Store top-down pixels, as produced by to_top_down, back in this bitmap's row order.
+    pub fn store_top_down(&mut self, mem: Mem, src: &[[u8; 4]]) {
+        let width = self.width as usize;
+        let dst = self.pixels.as_slice_mut(mem);
+        if self.top_down || width == 0 {
+            dst.copy_from_slice(src);
+            return;
+        }
+        for (d, s) in dst
+            .chunks_exact_mut(width)
+            .zip(src.chunks_exact(width).rev())
+        {
+            d.copy_from_slice(s);
+        }
+    }
+
     /// If pixels is not None, only parse the given number of lines from the given pixels.
     /// Otherwise pixels are expected to immediately follow the header in memory.
     pub fn parse(buf: &[u8], pixels: Option<(&[u8], usize)>) -> BitmapRGBA32 {
@@ -225,6 +296,24 @@ impl BitmapRGBA32 {
         Self::parse_pixels(&header, pixels, lines)
     }
 
+    /// Like parse, for the given lines of pixels, but with the color table supplied
+    /// separately as RGBA, for DIBs whose own table holds palette indices.
+    pub fn parse_with_palette(
+        buf: &[u8],
+        palette: &[[u8; 4]],
+        pixels: &[u8],
+        lines: usize,
+    ) -> BitmapRGBA32 {
+        let colors: Vec<u8> = palette
+            .iter()
+            .flat_map(|&[r, g, b, _]| [b, g, r, 0])
+            .collect();
+        let mut header = BitmapInfo::parse(buf);
+        header.palette = &colors;
+        header.palette_entry_size = 4;
+        Self::parse_pixels(&header, pixels, Some(lines))
+    }
+
     /// Parse a BITMAPINFO/HEADER and pixel data.
     fn parse_pixels(header: &BitmapInfo, pixels: &[u8], lines: Option<usize>) -> BitmapRGBA32 {
         match header.compression {
@@ -261,6 +350,11 @@ impl BitmapRGBA32 {
                     // TODO: might need to swizzle here, depending on BI::BITFIELDS.
                     dst.extend_from_slice(transmute_pixels(&row[..width * 4]));
                 }
+                24 => {
+                    for p in row[..width * 3].chunks_exact(3) {
+                        dst.push([p[2], p[1], p[0], 255]);
+                    }
+                }
                 8 => {
                     for &p in &row[..width] {
                         dst.push(get_pixel(header, p));
@@ -291,6 +385,7 @@ impl BitmapRGBA32 {
         BitmapRGBA32 {
             width: header.width as u32,
             height: height as u32,
+            top_down: true,
             pixels: PixelData::Owned(dst.into_boxed_slice()),
         }
     }
@@ -316,6 +411,95 @@ impl Bitmap for BitmapRGBA32 {
     }
 }
 
+/// An 8bpp palettized bitmap, as created by CreateDIBSection, whose pixels index into
+/// the palette given at creation time.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct BitmapPal8 {
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32,
+    pub top_down: bool,
+    pub palette: Box<[[u8; 4]]>,
+    pub pixels: PixelData<u8>,
+}
+
+impl BitmapPal8 {
+    /// Expand to RGBA pixels, e.g. for blitting from.
+    pub fn to_rgba32(&self, mem: Mem) -> BitmapRGBA32 {
+        let src = self.pixels.as_slice(mem);
+        let (width, height, stride) = (
+            self.width as usize,
+            self.height as usize,
+            self.stride as usize,
+        );
+        let mut dst: Vec<[u8; 4]> = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let y_src = if self.top_down { y } else { height - y - 1 };
+            let row = &src[y_src * stride..][..width];
+            dst.extend(
+                row.iter()
+                    .map(|&p| *self.palette.get(p as usize).unwrap_or(&[0, 0, 0, 255])),
+            );
+        }
+        BitmapRGBA32 {
+            width: self.width,
+            height: self.height,
+            top_down: true,
+            pixels: PixelData::Owned(dst.into_boxed_slice()),
+        }
+    }
+
+    /// Store RGBA pixels, as produced by to_rgba32, back as the nearest palette entries.
+    pub fn store_rgba32(&mut self, mem: Mem, src: &[[u8; 4]]) {
+        let (width, height, stride) = (
+            self.width as usize,
+            self.height as usize,
+            self.stride as usize,
+        );
+        let palette = &self.palette;
+        let nearest = |p: [u8; 4]| -> u8 {
+            let dist = |c: &[u8; 4]| -> u32 {
+                (0..3)
+                    .map(|i| (c[i] as i32 - p[i] as i32).pow(2) as u32)
+                    .sum()
+            };
+            (0..palette.len())
+                .min_by_key(|&i| dist(&palette[i]))
+                .unwrap_or(0) as u8
+        };
+        // Drawing usually produces few distinct colors, so remember the matches.
+        let mut cache = std::collections::HashMap::new();
+        let dst = self.pixels.as_slice_mut(mem);
+        for y in 0..height {
+            let y_dst = if self.top_down { y } else { height - y - 1 };
+            let row = &mut dst[y_dst * stride..][..width];
+            for (d, &p) in row.iter_mut().zip(&src[y * width..][..width]) {
+                *d = *cache.entry(p).or_insert_with(|| nearest(p));
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for BitmapPal8 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BitmapPal8")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+impl Bitmap for BitmapPal8 {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct BitmapMono {
     pub width: u32,
     pub height: u32,
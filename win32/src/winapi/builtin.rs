@@ -57,6 +57,52 @@ pub mod advapi32 {
             )
             .to_raw()
         }
+        pub unsafe fn RegEnumKeyExA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hKey = <HKEY>::from_stack(mem, stack_args + 0u32);
+            let dwIndex = <u32>::from_stack(mem, stack_args + 4u32);
+            let lpName = <u32>::from_stack(mem, stack_args + 8u32);
+            let lpcchName = <Option<&mut u32>>::from_stack(mem, stack_args + 12u32);
+            let lpReserved = <u32>::from_stack(mem, stack_args + 16u32);
+            let lpClass = <u32>::from_stack(mem, stack_args + 20u32);
+            let lpcchClass = <Option<&mut u32>>::from_stack(mem, stack_args + 24u32);
+            let lpftLastWriteTime = <u32>::from_stack(mem, stack_args + 28u32);
+            winapi::advapi32::RegEnumKeyExA(
+                machine,
+                hKey,
+                dwIndex,
+                lpName,
+                lpcchName,
+                lpReserved,
+                lpClass,
+                lpcchClass,
+                lpftLastWriteTime,
+            )
+            .to_raw()
+        }
+        pub unsafe fn RegEnumValueA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hKey = <HKEY>::from_stack(mem, stack_args + 0u32);
+            let dwIndex = <u32>::from_stack(mem, stack_args + 4u32);
+            let lpValueName = <u32>::from_stack(mem, stack_args + 8u32);
+            let lpcchValueName = <Option<&mut u32>>::from_stack(mem, stack_args + 12u32);
+            let lpReserved = <u32>::from_stack(mem, stack_args + 16u32);
+            let lpType = <Option<&mut u32>>::from_stack(mem, stack_args + 20u32);
+            let lpData = <u32>::from_stack(mem, stack_args + 24u32);
+            let lpcbData = <Option<&mut u32>>::from_stack(mem, stack_args + 28u32);
+            winapi::advapi32::RegEnumValueA(
+                machine,
+                hKey,
+                dwIndex,
+                lpValueName,
+                lpcchValueName,
+                lpReserved,
+                lpType,
+                lpData,
+                lpcbData,
+            )
+            .to_raw()
+        }
         pub unsafe fn RegOpenKeyExA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hKey = <HKEY>::from_stack(mem, stack_args + 0u32);
@@ -146,7 +192,7 @@ pub mod advapi32 {
             .to_raw()
         }
     }
-    const SHIMS: [Shim; 8usize] = [
+    const SHIMS: [Shim; 10usize] = [
         Shim {
             name: "RegCloseKey",
             func: Handler::Sync(impls::RegCloseKey),
@@ -159,6 +205,14 @@ pub mod advapi32 {
             name: "RegCreateKeyExW",
             func: Handler::Sync(impls::RegCreateKeyExW),
         },
+        Shim {
+            name: "RegEnumKeyExA",
+            func: Handler::Sync(impls::RegEnumKeyExA),
+        },
+        Shim {
+            name: "RegEnumValueA",
+            func: Handler::Sync(impls::RegEnumValueA),
+        },
         Shim {
             name: "RegOpenKeyExA",
             func: Handler::Sync(impls::RegOpenKeyExA),
@@ -274,6 +328,104 @@ pub mod bass {
         raw: std::include_bytes!("../../dll/bass.dll"),
     };
 }
+pub mod comctl32 {
+    use super::*;
+    mod impls {
+        use crate::{
+            machine::Machine,
+            winapi::{self, stack_args::*, types::*},
+        };
+        use memory::Extensions;
+        use winapi::comctl32::*;
+        pub unsafe fn CreateStatusWindowA(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
+            let mem = machine.mem().detach();
+            let style = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpszText = <Option<&str>>::from_stack(mem, stack_args + 4u32);
+            let hwndParent = <HWND>::from_stack(mem, stack_args + 8u32);
+            let wID = <u32>::from_stack(mem, stack_args + 12u32);
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::comctl32::CreateStatusWindowA(machine, style, lpszText, hwndParent, wID)
+                    .await
+                    .to_raw()
+            })
+        }
+        pub unsafe fn InitCommonControls(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            winapi::comctl32::InitCommonControls(machine).to_raw()
+        }
+        pub unsafe fn InitCommonControlsEx(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let picce = <Option<&INITCOMMONCONTROLSEX>>::from_stack(mem, stack_args + 0u32);
+            winapi::comctl32::InitCommonControlsEx(machine, picce).to_raw()
+        }
+        pub unsafe fn retrowin32_progress_wndproc(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
+            let mem = machine.mem().detach();
+            let hwnd = <HWND>::from_stack(mem, stack_args + 0u32);
+            let msg = <u32>::from_stack(mem, stack_args + 4u32);
+            let wParam = <u32>::from_stack(mem, stack_args + 8u32);
+            let lParam = <u32>::from_stack(mem, stack_args + 12u32);
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::comctl32::retrowin32_progress_wndproc(machine, hwnd, msg, wParam, lParam)
+                    .await
+                    .to_raw()
+            })
+        }
+        pub unsafe fn retrowin32_status_wndproc(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
+            let mem = machine.mem().detach();
+            let hwnd = <HWND>::from_stack(mem, stack_args + 0u32);
+            let msg = <u32>::from_stack(mem, stack_args + 4u32);
+            let wParam = <u32>::from_stack(mem, stack_args + 8u32);
+            let lParam = <u32>::from_stack(mem, stack_args + 12u32);
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::comctl32::retrowin32_status_wndproc(machine, hwnd, msg, wParam, lParam)
+                    .await
+                    .to_raw()
+            })
+        }
+    }
+    const SHIMS: [Shim; 5usize] = [
+        Shim {
+            name: "CreateStatusWindowA",
+            func: Handler::Async(impls::CreateStatusWindowA),
+        },
+        Shim {
+            name: "InitCommonControls",
+            func: Handler::Sync(impls::InitCommonControls),
+        },
+        Shim {
+            name: "InitCommonControlsEx",
+            func: Handler::Sync(impls::InitCommonControlsEx),
+        },
+        Shim {
+            name: "retrowin32_progress_wndproc",
+            func: Handler::Async(impls::retrowin32_progress_wndproc),
+        },
+        Shim {
+            name: "retrowin32_status_wndproc",
+            func: Handler::Async(impls::retrowin32_status_wndproc),
+        },
+    ];
+    pub const DLL: BuiltinDLL = BuiltinDLL {
+        file_name: "comctl32.dll",
+        shims: &SHIMS,
+        raw: std::include_bytes!("../../dll/comctl32.dll"),
+    };
+}
 pub mod ddraw {
     use super::*;
     mod impls {
@@ -285,7 +437,7 @@ pub mod ddraw {
         use winapi::ddraw::*;
         pub unsafe fn DirectDrawCreate(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let lpGuid = <Option<&GUID>>::from_stack(mem, stack_args + 0u32);
+            let lpGuid = <u32>::from_stack(mem, stack_args + 0u32);
             let lplpDD = <Option<&mut u32>>::from_stack(mem, stack_args + 4u32);
             let pUnkOuter = <u32>::from_stack(mem, stack_args + 8u32);
             winapi::ddraw::DirectDrawCreate(machine, lpGuid, lplpDD, pUnkOuter).to_raw()
@@ -300,12 +452,17 @@ pub mod ddraw {
         }
         pub unsafe fn DirectDrawCreateEx(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let lpGuid = <Option<&GUID>>::from_stack(mem, stack_args + 0u32);
+            let lpGuid = <u32>::from_stack(mem, stack_args + 0u32);
             let lplpDD = <Option<&mut u32>>::from_stack(mem, stack_args + 4u32);
             let iid = <Option<&GUID>>::from_stack(mem, stack_args + 8u32);
             let pUnkOuter = <u32>::from_stack(mem, stack_args + 12u32);
             winapi::ddraw::DirectDrawCreateEx(machine, lpGuid, lplpDD, iid, pUnkOuter).to_raw()
         }
+        pub unsafe fn IDirectDraw2_AddRef(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ddraw::IDirectDraw2::AddRef(machine, this).to_raw()
+        }
         pub unsafe fn IDirectDraw2_CreateSurface(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -356,7 +513,7 @@ pub mod ddraw {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
             let riid = <Option<&GUID>>::from_stack(mem, stack_args + 4u32);
-            let ppvObject = <u32>::from_stack(mem, stack_args + 8u32);
+            let ppvObject = <Option<&mut u32>>::from_stack(mem, stack_args + 8u32);
             winapi::ddraw::IDirectDraw2::QueryInterface(machine, this, riid, ppvObject).to_raw()
         }
         pub unsafe fn IDirectDraw2_Release(machine: &mut Machine, stack_args: u32) -> u32 {
@@ -372,6 +529,35 @@ pub mod ddraw {
             let bpp = <u32>::from_stack(mem, stack_args + 12u32);
             winapi::ddraw::IDirectDraw2::SetDisplayMode(machine, this, width, height, bpp).to_raw()
         }
+        pub unsafe fn IDirectDraw4_CreateSurface(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let desc = <Option<&DDSURFACEDESC2>>::from_stack(mem, stack_args + 4u32);
+            let lplpDDSurface = <Option<&mut u32>>::from_stack(mem, stack_args + 8u32);
+            let pUnkOuter = <u32>::from_stack(mem, stack_args + 12u32);
+            winapi::ddraw::IDirectDraw4::CreateSurface(
+                machine,
+                this,
+                desc,
+                lplpDDSurface,
+                pUnkOuter,
+            )
+            .to_raw()
+        }
+        pub unsafe fn IDirectDraw7_AddRef(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ddraw::IDirectDraw7::AddRef(machine, this).to_raw()
+        }
+        pub unsafe fn IDirectDraw7_CreateClipper(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let unused = <u32>::from_stack(mem, stack_args + 4u32);
+            let lplpClipper = <Option<&mut u32>>::from_stack(mem, stack_args + 8u32);
+            let reserved = <u32>::from_stack(mem, stack_args + 12u32);
+            winapi::ddraw::IDirectDraw7::CreateClipper(machine, this, unused, lplpClipper, reserved)
+                .to_raw()
+        }
         pub unsafe fn IDirectDraw7_CreatePalette(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -429,12 +615,56 @@ pub mod ddraw {
                 .to_raw()
             })
         }
+        pub unsafe fn IDirectDraw7_GetAvailableVidMem(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpDDSCaps2 = <Option<&DDSCAPS2>>::from_stack(mem, stack_args + 4u32);
+            let lpdwTotal = <Option<&mut u32>>::from_stack(mem, stack_args + 8u32);
+            let lpdwFree = <Option<&mut u32>>::from_stack(mem, stack_args + 12u32);
+            winapi::ddraw::IDirectDraw7::GetAvailableVidMem(
+                machine, this, lpDDSCaps2, lpdwTotal, lpdwFree,
+            )
+            .to_raw()
+        }
+        pub unsafe fn IDirectDraw7_GetCaps(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpDDDriverCaps = <u32>::from_stack(mem, stack_args + 4u32);
+            let lpDDHELCaps = <u32>::from_stack(mem, stack_args + 8u32);
+            winapi::ddraw::IDirectDraw7::GetCaps(machine, this, lpDDDriverCaps, lpDDHELCaps)
+                .to_raw()
+        }
         pub unsafe fn IDirectDraw7_GetDisplayMode(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
             let lpDDSurfaceDesc = <Option<&mut DDSURFACEDESC2>>::from_stack(mem, stack_args + 4u32);
             winapi::ddraw::IDirectDraw7::GetDisplayMode(machine, this, lpDDSurfaceDesc).to_raw()
         }
+        pub unsafe fn IDirectDraw7_GetVerticalBlankStatus(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpbIsInVB = <Option<&mut u32>>::from_stack(mem, stack_args + 4u32);
+            winapi::ddraw::IDirectDraw7::GetVerticalBlankStatus(machine, this, lpbIsInVB).to_raw()
+        }
+        pub unsafe fn IDirectDraw7_Initialize(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpGUID = <u32>::from_stack(mem, stack_args + 4u32);
+            winapi::ddraw::IDirectDraw7::Initialize(machine, this, lpGUID).to_raw()
+        }
+        pub unsafe fn IDirectDraw7_QueryInterface(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let riid = <Option<&GUID>>::from_stack(mem, stack_args + 4u32);
+            let ppvObject = <Option<&mut u32>>::from_stack(mem, stack_args + 8u32);
+            winapi::ddraw::IDirectDraw7::QueryInterface(machine, this, riid, ppvObject).to_raw()
+        }
         pub unsafe fn IDirectDraw7_Release(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -474,19 +704,60 @@ pub mod ddraw {
         pub unsafe fn IDirectDraw7_WaitForVerticalBlank(
             machine: &mut Machine,
             stack_args: u32,
-        ) -> u32 {
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
             let flags = <u32>::from_stack(mem, stack_args + 4u32);
             let _unused = <u32>::from_stack(mem, stack_args + 8u32);
-            winapi::ddraw::IDirectDraw7::WaitForVerticalBlank(machine, this, flags, _unused)
-                .to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::ddraw::IDirectDraw7::WaitForVerticalBlank(machine, this, flags, _unused)
+                    .await
+                    .to_raw()
+            })
+        }
+        pub unsafe fn IDirectDrawClipper_AddRef(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ddraw::IDirectDrawClipper::AddRef(machine, this).to_raw()
+        }
+        pub unsafe fn IDirectDrawClipper_GetClipList(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpRect = <Option<&RECT>>::from_stack(mem, stack_args + 4u32);
+            let lpClipList = <u32>::from_stack(mem, stack_args + 8u32);
+            let lpdwSize = <Option<&mut u32>>::from_stack(mem, stack_args + 12u32);
+            winapi::ddraw::IDirectDrawClipper::GetClipList(
+                machine, this, lpRect, lpClipList, lpdwSize,
+            )
+            .to_raw()
+        }
+        pub unsafe fn IDirectDrawClipper_GetHWnd(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lphWnd = <Option<&mut HWND>>::from_stack(mem, stack_args + 4u32);
+            winapi::ddraw::IDirectDrawClipper::GetHWnd(machine, this, lphWnd).to_raw()
         }
         pub unsafe fn IDirectDrawClipper_Release(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
             winapi::ddraw::IDirectDrawClipper::Release(machine, this).to_raw()
         }
+        pub unsafe fn IDirectDrawClipper_SetClipList(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpClipList = <u32>::from_stack(mem, stack_args + 4u32);
+            let dwFlags = <u32>::from_stack(mem, stack_args + 8u32);
+            winapi::ddraw::IDirectDrawClipper::SetClipList(machine, this, lpClipList, dwFlags)
+                .to_raw()
+        }
         pub unsafe fn IDirectDrawClipper_SetHWnd(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -494,6 +765,29 @@ pub mod ddraw {
             let hwnd = <HWND>::from_stack(mem, stack_args + 8u32);
             winapi::ddraw::IDirectDrawClipper::SetHWnd(machine, this, unused, hwnd).to_raw()
         }
+        pub unsafe fn IDirectDrawPalette_AddRef(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ddraw::IDirectDrawPalette::AddRef(machine, this).to_raw()
+        }
+        pub unsafe fn IDirectDrawPalette_GetCaps(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpdwCaps = <Option<&mut u32>>::from_stack(mem, stack_args + 4u32);
+            winapi::ddraw::IDirectDrawPalette::GetCaps(machine, this, lpdwCaps).to_raw()
+        }
+        pub unsafe fn IDirectDrawPalette_GetEntries(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let unused = <u32>::from_stack(mem, stack_args + 4u32);
+            let start = <u32>::from_stack(mem, stack_args + 8u32);
+            let count = <u32>::from_stack(mem, stack_args + 12u32);
+            let entries = <u32>::from_stack(mem, stack_args + 16u32);
+            winapi::ddraw::IDirectDrawPalette::GetEntries(
+                machine, this, unused, start, count, entries,
+            )
+            .to_raw()
+        }
         pub unsafe fn IDirectDrawPalette_Release(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -511,6 +805,11 @@ pub mod ddraw {
             )
             .to_raw()
         }
+        pub unsafe fn IDirectDrawSurface2_AddRef(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ddraw::IDirectDrawSurface2::AddRef(machine, this).to_raw()
+        }
         pub unsafe fn IDirectDrawSurface2_GetAttachedSurface(
             machine: &mut Machine,
             stack_args: u32,
@@ -563,6 +862,11 @@ pub mod ddraw {
             let ptr = <u32>::from_stack(mem, stack_args + 4u32);
             winapi::ddraw::IDirectDrawSurface2::Unlock(machine, this, ptr).to_raw()
         }
+        pub unsafe fn IDirectDrawSurface7_AddRef(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ddraw::IDirectDrawSurface7::AddRef(machine, this).to_raw()
+        }
         pub unsafe fn IDirectDrawSurface7_Blt(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -583,7 +887,7 @@ pub mod ddraw {
             let y = <u32>::from_stack(mem, stack_args + 8u32);
             let lpSrc = <u32>::from_stack(mem, stack_args + 12u32);
             let lpRect = <Option<&RECT>>::from_stack(mem, stack_args + 16u32);
-            let flags = <u32>::from_stack(mem, stack_args + 20u32);
+            let flags = <Result<DDBLTFAST, u32>>::from_stack(mem, stack_args + 20u32);
             winapi::ddraw::IDirectDrawSurface7::BltFast(machine, this, x, y, lpSrc, lpRect, flags)
                 .to_raw()
         }
@@ -616,6 +920,16 @@ pub mod ddraw {
             let lpDDSCAPS2 = <Option<&mut DDSCAPS2>>::from_stack(mem, stack_args + 4u32);
             winapi::ddraw::IDirectDrawSurface7::GetCaps(machine, this, lpDDSCAPS2).to_raw()
         }
+        pub unsafe fn IDirectDrawSurface7_GetColorKey(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let flags = <Result<DDCKEY, u32>>::from_stack(mem, stack_args + 4u32);
+            let key = <Option<&mut DDCOLORKEY>>::from_stack(mem, stack_args + 8u32);
+            winapi::ddraw::IDirectDrawSurface7::GetColorKey(machine, this, flags, key).to_raw()
+        }
         pub unsafe fn IDirectDrawSurface7_GetDC(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -640,6 +954,11 @@ pub mod ddraw {
             let lpDesc = <Option<&mut DDSURFACEDESC2>>::from_stack(mem, stack_args + 4u32);
             winapi::ddraw::IDirectDrawSurface7::GetSurfaceDesc(machine, this, lpDesc).to_raw()
         }
+        pub unsafe fn IDirectDrawSurface7_IsLost(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ddraw::IDirectDrawSurface7::IsLost(machine, this).to_raw()
+        }
         pub unsafe fn IDirectDrawSurface7_Lock(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -650,6 +969,17 @@ pub mod ddraw {
             winapi::ddraw::IDirectDrawSurface7::Lock(machine, this, rect, desc, flags, unused)
                 .to_raw()
         }
+        pub unsafe fn IDirectDrawSurface7_QueryInterface(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let riid = <Option<&GUID>>::from_stack(mem, stack_args + 4u32);
+            let ppvObject = <Option<&mut u32>>::from_stack(mem, stack_args + 8u32);
+            winapi::ddraw::IDirectDrawSurface7::QueryInterface(machine, this, riid, ppvObject)
+                .to_raw()
+        }
         pub unsafe fn IDirectDrawSurface7_Release(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -663,8 +993,8 @@ pub mod ddraw {
         }
         pub unsafe fn IDirectDrawSurface7_Restore(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let _this = <u32>::from_stack(mem, stack_args + 0u32);
-            winapi::ddraw::IDirectDrawSurface7::Restore(machine, _this).to_raw()
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ddraw::IDirectDrawSurface7::Restore(machine, this).to_raw()
         }
         pub unsafe fn IDirectDrawSurface7_SetClipper(
             machine: &mut Machine,
@@ -675,6 +1005,16 @@ pub mod ddraw {
             let clipper = <u32>::from_stack(mem, stack_args + 4u32);
             winapi::ddraw::IDirectDrawSurface7::SetClipper(machine, this, clipper).to_raw()
         }
+        pub unsafe fn IDirectDrawSurface7_SetColorKey(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let flags = <Result<DDCKEY, u32>>::from_stack(mem, stack_args + 4u32);
+            let key = <Option<&DDCOLORKEY>>::from_stack(mem, stack_args + 8u32);
+            winapi::ddraw::IDirectDrawSurface7::SetColorKey(machine, this, flags, key).to_raw()
+        }
         pub unsafe fn IDirectDrawSurface7_SetPalette(
             machine: &mut Machine,
             stack_args: u32,
@@ -687,9 +1027,14 @@ pub mod ddraw {
         pub unsafe fn IDirectDrawSurface7_Unlock(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
-            let rect = <Option<&mut RECT>>::from_stack(mem, stack_args + 4u32);
+            let rect = <Option<&RECT>>::from_stack(mem, stack_args + 4u32);
             winapi::ddraw::IDirectDrawSurface7::Unlock(machine, this, rect).to_raw()
         }
+        pub unsafe fn IDirectDrawSurface_AddRef(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ddraw::IDirectDrawSurface::AddRef(machine, this).to_raw()
+        }
         pub unsafe fn IDirectDrawSurface_GetAttachedSurface(
             machine: &mut Machine,
             stack_args: u32,
@@ -733,6 +1078,11 @@ pub mod ddraw {
             let ptr = <u32>::from_stack(mem, stack_args + 4u32);
             winapi::ddraw::IDirectDrawSurface::Unlock(machine, this, ptr).to_raw()
         }
+        pub unsafe fn IDirectDraw_AddRef(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ddraw::IDirectDraw::AddRef(machine, this).to_raw()
+        }
         pub unsafe fn IDirectDraw_CreateSurface(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -788,7 +1138,7 @@ pub mod ddraw {
             winapi::ddraw::IDirectDraw::SetDisplayMode(machine, this, width, height, bpp).to_raw()
         }
     }
-    const SHIMS: [Shim; 53usize] = [
+    const SHIMS: [Shim; 77usize] = [
         Shim {
             name: "DirectDrawCreate",
             func: Handler::Sync(impls::DirectDrawCreate),
@@ -801,6 +1151,10 @@ pub mod ddraw {
             name: "DirectDrawCreateEx",
             func: Handler::Sync(impls::DirectDrawCreateEx),
         },
+        Shim {
+            name: "IDirectDraw2::AddRef",
+            func: Handler::Sync(impls::IDirectDraw2_AddRef),
+        },
         Shim {
             name: "IDirectDraw2::CreateSurface",
             func: Handler::Sync(impls::IDirectDraw2_CreateSurface),
@@ -825,6 +1179,18 @@ pub mod ddraw {
             name: "IDirectDraw2::SetDisplayMode",
             func: Handler::Sync(impls::IDirectDraw2_SetDisplayMode),
         },
+        Shim {
+            name: "IDirectDraw4::CreateSurface",
+            func: Handler::Sync(impls::IDirectDraw4_CreateSurface),
+        },
+        Shim {
+            name: "IDirectDraw7::AddRef",
+            func: Handler::Sync(impls::IDirectDraw7_AddRef),
+        },
+        Shim {
+            name: "IDirectDraw7::CreateClipper",
+            func: Handler::Sync(impls::IDirectDraw7_CreateClipper),
+        },
         Shim {
             name: "IDirectDraw7::CreatePalette",
             func: Handler::Sync(impls::IDirectDraw7_CreatePalette),
@@ -837,10 +1203,30 @@ pub mod ddraw {
             name: "IDirectDraw7::EnumDisplayModes",
             func: Handler::Async(impls::IDirectDraw7_EnumDisplayModes),
         },
+        Shim {
+            name: "IDirectDraw7::GetAvailableVidMem",
+            func: Handler::Sync(impls::IDirectDraw7_GetAvailableVidMem),
+        },
+        Shim {
+            name: "IDirectDraw7::GetCaps",
+            func: Handler::Sync(impls::IDirectDraw7_GetCaps),
+        },
         Shim {
             name: "IDirectDraw7::GetDisplayMode",
             func: Handler::Sync(impls::IDirectDraw7_GetDisplayMode),
         },
+        Shim {
+            name: "IDirectDraw7::GetVerticalBlankStatus",
+            func: Handler::Sync(impls::IDirectDraw7_GetVerticalBlankStatus),
+        },
+        Shim {
+            name: "IDirectDraw7::Initialize",
+            func: Handler::Sync(impls::IDirectDraw7_Initialize),
+        },
+        Shim {
+            name: "IDirectDraw7::QueryInterface",
+            func: Handler::Sync(impls::IDirectDraw7_QueryInterface),
+        },
         Shim {
             name: "IDirectDraw7::Release",
             func: Handler::Sync(impls::IDirectDraw7_Release),
@@ -859,16 +1245,44 @@ pub mod ddraw {
         },
         Shim {
             name: "IDirectDraw7::WaitForVerticalBlank",
-            func: Handler::Sync(impls::IDirectDraw7_WaitForVerticalBlank),
+            func: Handler::Async(impls::IDirectDraw7_WaitForVerticalBlank),
+        },
+        Shim {
+            name: "IDirectDrawClipper::AddRef",
+            func: Handler::Sync(impls::IDirectDrawClipper_AddRef),
+        },
+        Shim {
+            name: "IDirectDrawClipper::GetClipList",
+            func: Handler::Sync(impls::IDirectDrawClipper_GetClipList),
+        },
+        Shim {
+            name: "IDirectDrawClipper::GetHWnd",
+            func: Handler::Sync(impls::IDirectDrawClipper_GetHWnd),
         },
         Shim {
             name: "IDirectDrawClipper::Release",
             func: Handler::Sync(impls::IDirectDrawClipper_Release),
         },
+        Shim {
+            name: "IDirectDrawClipper::SetClipList",
+            func: Handler::Sync(impls::IDirectDrawClipper_SetClipList),
+        },
         Shim {
             name: "IDirectDrawClipper::SetHWnd",
             func: Handler::Sync(impls::IDirectDrawClipper_SetHWnd),
         },
+        Shim {
+            name: "IDirectDrawPalette::AddRef",
+            func: Handler::Sync(impls::IDirectDrawPalette_AddRef),
+        },
+        Shim {
+            name: "IDirectDrawPalette::GetCaps",
+            func: Handler::Sync(impls::IDirectDrawPalette_GetCaps),
+        },
+        Shim {
+            name: "IDirectDrawPalette::GetEntries",
+            func: Handler::Sync(impls::IDirectDrawPalette_GetEntries),
+        },
         Shim {
             name: "IDirectDrawPalette::Release",
             func: Handler::Sync(impls::IDirectDrawPalette_Release),
@@ -877,6 +1291,10 @@ pub mod ddraw {
             name: "IDirectDrawPalette::SetEntries",
             func: Handler::Sync(impls::IDirectDrawPalette_SetEntries),
         },
+        Shim {
+            name: "IDirectDrawSurface2::AddRef",
+            func: Handler::Sync(impls::IDirectDrawSurface2_AddRef),
+        },
         Shim {
             name: "IDirectDrawSurface2::GetAttachedSurface",
             func: Handler::Sync(impls::IDirectDrawSurface2_GetAttachedSurface),
@@ -901,6 +1319,10 @@ pub mod ddraw {
             name: "IDirectDrawSurface2::Unlock",
             func: Handler::Sync(impls::IDirectDrawSurface2_Unlock),
         },
+        Shim {
+            name: "IDirectDrawSurface7::AddRef",
+            func: Handler::Sync(impls::IDirectDrawSurface7_AddRef),
+        },
         Shim {
             name: "IDirectDrawSurface7::Blt",
             func: Handler::Sync(impls::IDirectDrawSurface7_Blt),
@@ -921,6 +1343,10 @@ pub mod ddraw {
             name: "IDirectDrawSurface7::GetCaps",
             func: Handler::Sync(impls::IDirectDrawSurface7_GetCaps),
         },
+        Shim {
+            name: "IDirectDrawSurface7::GetColorKey",
+            func: Handler::Sync(impls::IDirectDrawSurface7_GetColorKey),
+        },
         Shim {
             name: "IDirectDrawSurface7::GetDC",
             func: Handler::Sync(impls::IDirectDrawSurface7_GetDC),
@@ -933,10 +1359,18 @@ pub mod ddraw {
             name: "IDirectDrawSurface7::GetSurfaceDesc",
             func: Handler::Sync(impls::IDirectDrawSurface7_GetSurfaceDesc),
         },
+        Shim {
+            name: "IDirectDrawSurface7::IsLost",
+            func: Handler::Sync(impls::IDirectDrawSurface7_IsLost),
+        },
         Shim {
             name: "IDirectDrawSurface7::Lock",
             func: Handler::Sync(impls::IDirectDrawSurface7_Lock),
         },
+        Shim {
+            name: "IDirectDrawSurface7::QueryInterface",
+            func: Handler::Sync(impls::IDirectDrawSurface7_QueryInterface),
+        },
         Shim {
             name: "IDirectDrawSurface7::Release",
             func: Handler::Sync(impls::IDirectDrawSurface7_Release),
@@ -953,6 +1387,10 @@ pub mod ddraw {
             name: "IDirectDrawSurface7::SetClipper",
             func: Handler::Sync(impls::IDirectDrawSurface7_SetClipper),
         },
+        Shim {
+            name: "IDirectDrawSurface7::SetColorKey",
+            func: Handler::Sync(impls::IDirectDrawSurface7_SetColorKey),
+        },
         Shim {
             name: "IDirectDrawSurface7::SetPalette",
             func: Handler::Sync(impls::IDirectDrawSurface7_SetPalette),
@@ -961,6 +1399,10 @@ pub mod ddraw {
             name: "IDirectDrawSurface7::Unlock",
             func: Handler::Sync(impls::IDirectDrawSurface7_Unlock),
         },
+        Shim {
+            name: "IDirectDrawSurface::AddRef",
+            func: Handler::Sync(impls::IDirectDrawSurface_AddRef),
+        },
         Shim {
             name: "IDirectDrawSurface::GetAttachedSurface",
             func: Handler::Sync(impls::IDirectDrawSurface_GetAttachedSurface),
@@ -981,6 +1423,10 @@ pub mod ddraw {
             name: "IDirectDrawSurface::Unlock",
             func: Handler::Sync(impls::IDirectDrawSurface_Unlock),
         },
+        Shim {
+            name: "IDirectDraw::AddRef",
+            func: Handler::Sync(impls::IDirectDraw_AddRef),
+        },
         Shim {
             name: "IDirectDraw::CreateSurface",
             func: Handler::Sync(impls::IDirectDraw_CreateSurface),
@@ -1030,6 +1476,17 @@ pub mod dsound {
             let lpContext = <u32>::from_stack(mem, stack_args + 4u32);
             winapi::dsound::DirectSoundEnumerateA(machine, lpDSEnumCallback, lpContext).to_raw()
         }
+        pub unsafe fn IDirectSoundBuffer_AddRef(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::dsound::IDirectSoundBuffer::AddRef(machine, this).to_raw()
+        }
+        pub unsafe fn IDirectSoundBuffer_GetCaps(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpDSBufferCaps = <Option<&mut DSBUFFERCAPS>>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSoundBuffer::GetCaps(machine, this, lpDSBufferCaps).to_raw()
+        }
         pub unsafe fn IDirectSoundBuffer_GetCurrentPosition(
             machine: &mut Machine,
             stack_args: u32,
@@ -1046,12 +1503,61 @@ pub mod dsound {
             )
             .to_raw()
         }
+        pub unsafe fn IDirectSoundBuffer_GetFormat(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpwfxFormat = <u32>::from_stack(mem, stack_args + 4u32);
+            let dwSizeAllocated = <u32>::from_stack(mem, stack_args + 8u32);
+            let lpdwSizeWritten = <Option<&mut u32>>::from_stack(mem, stack_args + 12u32);
+            winapi::dsound::IDirectSoundBuffer::GetFormat(
+                machine,
+                this,
+                lpwfxFormat,
+                dwSizeAllocated,
+                lpdwSizeWritten,
+            )
+            .to_raw()
+        }
+        pub unsafe fn IDirectSoundBuffer_GetFrequency(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpdwFrequency = <Option<&mut u32>>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSoundBuffer::GetFrequency(machine, this, lpdwFrequency).to_raw()
+        }
+        pub unsafe fn IDirectSoundBuffer_GetPan(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lplPan = <Option<&mut i32>>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSoundBuffer::GetPan(machine, this, lplPan).to_raw()
+        }
         pub unsafe fn IDirectSoundBuffer_GetStatus(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
             let lpdwStatus = <Option<&mut u32>>::from_stack(mem, stack_args + 4u32);
             winapi::dsound::IDirectSoundBuffer::GetStatus(machine, this, lpdwStatus).to_raw()
         }
+        pub unsafe fn IDirectSoundBuffer_GetVolume(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lplVolume = <Option<&mut i32>>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSoundBuffer::GetVolume(machine, this, lplVolume).to_raw()
+        }
+        pub unsafe fn IDirectSoundBuffer_Initialize(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpDirectSound = <u32>::from_stack(mem, stack_args + 4u32);
+            let lpcDSBufferDesc = <Option<&DSBUFFERDESC>>::from_stack(mem, stack_args + 8u32);
+            winapi::dsound::IDirectSoundBuffer::Initialize(
+                machine,
+                this,
+                lpDirectSound,
+                lpcDSBufferDesc,
+            )
+            .to_raw()
+        }
         pub unsafe fn IDirectSoundBuffer_Lock(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -1090,17 +1596,69 @@ pub mod dsound {
             )
             .to_raw()
         }
+        pub unsafe fn IDirectSoundBuffer_QueryInterface(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let riid = <Option<&GUID>>::from_stack(mem, stack_args + 4u32);
+            let ppvObject = <Option<&mut u32>>::from_stack(mem, stack_args + 8u32);
+            winapi::dsound::IDirectSoundBuffer::QueryInterface(machine, this, riid, ppvObject)
+                .to_raw()
+        }
         pub unsafe fn IDirectSoundBuffer_Release(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
             winapi::dsound::IDirectSoundBuffer::Release(machine, this).to_raw()
         }
+        pub unsafe fn IDirectSoundBuffer_Restore(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::dsound::IDirectSoundBuffer::Restore(machine, this).to_raw()
+        }
+        pub unsafe fn IDirectSoundBuffer_SetCurrentPosition(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let dwNewPosition = <u32>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSoundBuffer::SetCurrentPosition(machine, this, dwNewPosition)
+                .to_raw()
+        }
         pub unsafe fn IDirectSoundBuffer_SetFormat(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
             let lpcfxFormat = <Option<&WAVEFORMATEX>>::from_stack(mem, stack_args + 4u32);
             winapi::dsound::IDirectSoundBuffer::SetFormat(machine, this, lpcfxFormat).to_raw()
         }
+        pub unsafe fn IDirectSoundBuffer_SetFrequency(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let dwFrequency = <u32>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSoundBuffer::SetFrequency(machine, this, dwFrequency).to_raw()
+        }
+        pub unsafe fn IDirectSoundBuffer_SetPan(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lPan = <i32>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSoundBuffer::SetPan(machine, this, lPan).to_raw()
+        }
+        pub unsafe fn IDirectSoundBuffer_SetVolume(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lVolume = <i32>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSoundBuffer::SetVolume(machine, this, lVolume).to_raw()
+        }
+        pub unsafe fn IDirectSoundBuffer_Stop(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::dsound::IDirectSoundBuffer::Stop(machine, this).to_raw()
+        }
         pub unsafe fn IDirectSoundBuffer_Unlock(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -1118,6 +1676,16 @@ pub mod dsound {
             )
             .to_raw()
         }
+        pub unsafe fn IDirectSound_AddRef(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::dsound::IDirectSound::AddRef(machine, this).to_raw()
+        }
+        pub unsafe fn IDirectSound_Compact(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::dsound::IDirectSound::Compact(machine, this).to_raw()
+        }
         pub unsafe fn IDirectSound_CreateSoundBuffer(
             machine: &mut Machine,
             stack_args: u32,
@@ -1136,6 +1704,48 @@ pub mod dsound {
             )
             .to_raw()
         }
+        pub unsafe fn IDirectSound_DuplicateSoundBuffer(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpDsbOriginal = <u32>::from_stack(mem, stack_args + 4u32);
+            let lplpDsbDuplicate = <Option<&mut u32>>::from_stack(mem, stack_args + 8u32);
+            winapi::dsound::IDirectSound::DuplicateSoundBuffer(
+                machine,
+                this,
+                lpDsbOriginal,
+                lplpDsbDuplicate,
+            )
+            .to_raw()
+        }
+        pub unsafe fn IDirectSound_GetCaps(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpDSCaps = <Option<&mut DSCAPS>>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSound::GetCaps(machine, this, lpDSCaps).to_raw()
+        }
+        pub unsafe fn IDirectSound_GetSpeakerConfig(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpdwSpeakerConfig = <Option<&mut u32>>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSound::GetSpeakerConfig(machine, this, lpdwSpeakerConfig)
+                .to_raw()
+        }
+        pub unsafe fn IDirectSound_Initialize(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let pcGuidDevice = <Option<&GUID>>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSound::Initialize(machine, this, pcGuidDevice).to_raw()
+        }
+        pub unsafe fn IDirectSound_QueryInterface(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let riid = <Option<&GUID>>::from_stack(mem, stack_args + 4u32);
+            let ppvObject = <Option<&mut u32>>::from_stack(mem, stack_args + 8u32);
+            winapi::dsound::IDirectSound::QueryInterface(machine, this, riid, ppvObject).to_raw()
+        }
         pub unsafe fn IDirectSound_Release(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let this = <u32>::from_stack(mem, stack_args + 0u32);
@@ -1151,8 +1761,14 @@ pub mod dsound {
             let dwLevel = <u32>::from_stack(mem, stack_args + 8u32);
             winapi::dsound::IDirectSound::SetCooperativeLevel(machine, this, hwnd, dwLevel).to_raw()
         }
+        pub unsafe fn IDirectSound_SetSpeakerConfig(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let this = <u32>::from_stack(mem, stack_args + 0u32);
+            let dwSpeakerConfig = <u32>::from_stack(mem, stack_args + 4u32);
+            winapi::dsound::IDirectSound::SetSpeakerConfig(machine, this, dwSpeakerConfig).to_raw()
+        }
     }
-    const SHIMS: [Shim; 12usize] = [
+    const SHIMS: [Shim; 34usize] = [
         Shim {
             name: "DirectSoundCreate",
             func: Handler::Sync(impls::DirectSoundCreate),
@@ -1161,14 +1777,42 @@ pub mod dsound {
             name: "DirectSoundEnumerateA",
             func: Handler::Sync(impls::DirectSoundEnumerateA),
         },
+        Shim {
+            name: "IDirectSoundBuffer::AddRef",
+            func: Handler::Sync(impls::IDirectSoundBuffer_AddRef),
+        },
+        Shim {
+            name: "IDirectSoundBuffer::GetCaps",
+            func: Handler::Sync(impls::IDirectSoundBuffer_GetCaps),
+        },
         Shim {
             name: "IDirectSoundBuffer::GetCurrentPosition",
             func: Handler::Sync(impls::IDirectSoundBuffer_GetCurrentPosition),
         },
+        Shim {
+            name: "IDirectSoundBuffer::GetFormat",
+            func: Handler::Sync(impls::IDirectSoundBuffer_GetFormat),
+        },
+        Shim {
+            name: "IDirectSoundBuffer::GetFrequency",
+            func: Handler::Sync(impls::IDirectSoundBuffer_GetFrequency),
+        },
+        Shim {
+            name: "IDirectSoundBuffer::GetPan",
+            func: Handler::Sync(impls::IDirectSoundBuffer_GetPan),
+        },
         Shim {
             name: "IDirectSoundBuffer::GetStatus",
             func: Handler::Sync(impls::IDirectSoundBuffer_GetStatus),
         },
+        Shim {
+            name: "IDirectSoundBuffer::GetVolume",
+            func: Handler::Sync(impls::IDirectSoundBuffer_GetVolume),
+        },
+        Shim {
+            name: "IDirectSoundBuffer::Initialize",
+            func: Handler::Sync(impls::IDirectSoundBuffer_Initialize),
+        },
         Shim {
             name: "IDirectSoundBuffer::Lock",
             func: Handler::Sync(impls::IDirectSoundBuffer_Lock),
@@ -1177,22 +1821,78 @@ pub mod dsound {
             name: "IDirectSoundBuffer::Play",
             func: Handler::Sync(impls::IDirectSoundBuffer_Play),
         },
+        Shim {
+            name: "IDirectSoundBuffer::QueryInterface",
+            func: Handler::Sync(impls::IDirectSoundBuffer_QueryInterface),
+        },
         Shim {
             name: "IDirectSoundBuffer::Release",
             func: Handler::Sync(impls::IDirectSoundBuffer_Release),
         },
+        Shim {
+            name: "IDirectSoundBuffer::Restore",
+            func: Handler::Sync(impls::IDirectSoundBuffer_Restore),
+        },
+        Shim {
+            name: "IDirectSoundBuffer::SetCurrentPosition",
+            func: Handler::Sync(impls::IDirectSoundBuffer_SetCurrentPosition),
+        },
         Shim {
             name: "IDirectSoundBuffer::SetFormat",
             func: Handler::Sync(impls::IDirectSoundBuffer_SetFormat),
         },
+        Shim {
+            name: "IDirectSoundBuffer::SetFrequency",
+            func: Handler::Sync(impls::IDirectSoundBuffer_SetFrequency),
+        },
+        Shim {
+            name: "IDirectSoundBuffer::SetPan",
+            func: Handler::Sync(impls::IDirectSoundBuffer_SetPan),
+        },
+        Shim {
+            name: "IDirectSoundBuffer::SetVolume",
+            func: Handler::Sync(impls::IDirectSoundBuffer_SetVolume),
+        },
+        Shim {
+            name: "IDirectSoundBuffer::Stop",
+            func: Handler::Sync(impls::IDirectSoundBuffer_Stop),
+        },
         Shim {
             name: "IDirectSoundBuffer::Unlock",
             func: Handler::Sync(impls::IDirectSoundBuffer_Unlock),
         },
+        Shim {
+            name: "IDirectSound::AddRef",
+            func: Handler::Sync(impls::IDirectSound_AddRef),
+        },
+        Shim {
+            name: "IDirectSound::Compact",
+            func: Handler::Sync(impls::IDirectSound_Compact),
+        },
         Shim {
             name: "IDirectSound::CreateSoundBuffer",
             func: Handler::Sync(impls::IDirectSound_CreateSoundBuffer),
         },
+        Shim {
+            name: "IDirectSound::DuplicateSoundBuffer",
+            func: Handler::Sync(impls::IDirectSound_DuplicateSoundBuffer),
+        },
+        Shim {
+            name: "IDirectSound::GetCaps",
+            func: Handler::Sync(impls::IDirectSound_GetCaps),
+        },
+        Shim {
+            name: "IDirectSound::GetSpeakerConfig",
+            func: Handler::Sync(impls::IDirectSound_GetSpeakerConfig),
+        },
+        Shim {
+            name: "IDirectSound::Initialize",
+            func: Handler::Sync(impls::IDirectSound_Initialize),
+        },
+        Shim {
+            name: "IDirectSound::QueryInterface",
+            func: Handler::Sync(impls::IDirectSound_QueryInterface),
+        },
         Shim {
             name: "IDirectSound::Release",
             func: Handler::Sync(impls::IDirectSound_Release),
@@ -1201,6 +1901,10 @@ pub mod dsound {
             name: "IDirectSound::SetCooperativeLevel",
             func: Handler::Sync(impls::IDirectSound_SetCooperativeLevel),
         },
+        Shim {
+            name: "IDirectSound::SetSpeakerConfig",
+            func: Handler::Sync(impls::IDirectSound_SetSpeakerConfig),
+        },
     ];
     pub const DLL: BuiltinDLL = BuiltinDLL {
         file_name: "dsound.dll",
@@ -1255,7 +1959,7 @@ pub mod gdi32 {
         pub unsafe fn CreateDIBSection(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
-            let pbmi = <Option<&BITMAPINFOHEADER>>::from_stack(mem, stack_args + 4u32);
+            let pbmi = <u32>::from_stack(mem, stack_args + 4u32);
             let usage = <u32>::from_stack(mem, stack_args + 8u32);
             let ppvBits = <Option<&mut u32>>::from_stack(mem, stack_args + 12u32);
             let hSection = <u32>::from_stack(mem, stack_args + 16u32);
@@ -1317,7 +2021,7 @@ pub mod gdi32 {
         }
         pub unsafe fn DeleteDC(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let hdc = <u32>::from_stack(mem, stack_args + 0u32);
+            let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
             winapi::gdi32::DeleteDC(machine, hdc).to_raw()
         }
         pub unsafe fn DeleteObject(machine: &mut Machine, stack_args: u32) -> u32 {
@@ -1325,12 +2029,29 @@ pub mod gdi32 {
             let handle = <HGDIOBJ>::from_stack(mem, stack_args + 0u32);
             winapi::gdi32::DeleteObject(machine, handle).to_raw()
         }
+        pub unsafe fn Ellipse(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
+            let left = <i32>::from_stack(mem, stack_args + 4u32);
+            let top = <i32>::from_stack(mem, stack_args + 8u32);
+            let right = <i32>::from_stack(mem, stack_args + 12u32);
+            let bottom = <i32>::from_stack(mem, stack_args + 16u32);
+            winapi::gdi32::Ellipse(machine, hdc, left, top, right, bottom).to_raw()
+        }
         pub unsafe fn GetDCOrgEx(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
             let lpPoint = <Option<&mut POINT>>::from_stack(mem, stack_args + 4u32);
             winapi::gdi32::GetDCOrgEx(machine, hdc, lpPoint).to_raw()
         }
+        pub unsafe fn GetDIBColorTable(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
+            let iStart = <u32>::from_stack(mem, stack_args + 4u32);
+            let cEntries = <u32>::from_stack(mem, stack_args + 8u32);
+            let prgbq = <u32>::from_stack(mem, stack_args + 12u32);
+            winapi::gdi32::GetDIBColorTable(machine, hdc, iStart, cEntries, prgbq).to_raw()
+        }
         pub unsafe fn GetDeviceCaps(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
@@ -1402,15 +2123,15 @@ pub mod gdi32 {
         pub unsafe fn LineTo(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
-            let x = <u32>::from_stack(mem, stack_args + 4u32);
-            let y = <u32>::from_stack(mem, stack_args + 8u32);
+            let x = <i32>::from_stack(mem, stack_args + 4u32);
+            let y = <i32>::from_stack(mem, stack_args + 8u32);
             winapi::gdi32::LineTo(machine, hdc, x, y).to_raw()
         }
         pub unsafe fn MoveToEx(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
-            let x = <u32>::from_stack(mem, stack_args + 4u32);
-            let y = <u32>::from_stack(mem, stack_args + 8u32);
+            let x = <i32>::from_stack(mem, stack_args + 4u32);
+            let y = <i32>::from_stack(mem, stack_args + 8u32);
             let lppt = <Option<&mut POINT>>::from_stack(mem, stack_args + 12u32);
             winapi::gdi32::MoveToEx(machine, hdc, x, y, lppt).to_raw()
         }
@@ -1431,12 +2152,33 @@ pub mod gdi32 {
             let y = <i32>::from_stack(mem, stack_args + 8u32);
             winapi::gdi32::PtVisible(machine, hdc, x, y).to_raw()
         }
+        pub unsafe fn RealizePalette(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
+            winapi::gdi32::RealizePalette(machine, hdc).to_raw()
+        }
+        pub unsafe fn Rectangle(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
+            let left = <i32>::from_stack(mem, stack_args + 4u32);
+            let top = <i32>::from_stack(mem, stack_args + 8u32);
+            let right = <i32>::from_stack(mem, stack_args + 12u32);
+            let bottom = <i32>::from_stack(mem, stack_args + 16u32);
+            winapi::gdi32::Rectangle(machine, hdc, left, top, right, bottom).to_raw()
+        }
         pub unsafe fn SelectObject(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
             let hGdiObj = <HGDIOBJ>::from_stack(mem, stack_args + 4u32);
             winapi::gdi32::SelectObject(machine, hdc, hGdiObj).to_raw()
         }
+        pub unsafe fn SelectPalette(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
+            let hPal = <HGDIOBJ>::from_stack(mem, stack_args + 4u32);
+            let bForceBkgd = <bool>::from_stack(mem, stack_args + 8u32);
+            winapi::gdi32::SelectPalette(machine, hdc, hPal, bForceBkgd).to_raw()
+        }
         pub unsafe fn SetBkColor(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
@@ -1446,7 +2188,7 @@ pub mod gdi32 {
         pub unsafe fn SetBkMode(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
-            let mode = <i32>::from_stack(mem, stack_args + 4u32);
+            let mode = <Result<BkMode, u32>>::from_stack(mem, stack_args + 4u32);
             winapi::gdi32::SetBkMode(machine, hdc, mode).to_raw()
         }
         pub unsafe fn SetBrushOrgEx(machine: &mut Machine, stack_args: u32) -> u32 {
@@ -1457,15 +2199,23 @@ pub mod gdi32 {
             let lppt = <Option<&mut POINT>>::from_stack(mem, stack_args + 12u32);
             winapi::gdi32::SetBrushOrgEx(machine, hdc, x, y, lppt).to_raw()
         }
+        pub unsafe fn SetDIBColorTable(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
+            let iStart = <u32>::from_stack(mem, stack_args + 4u32);
+            let cEntries = <u32>::from_stack(mem, stack_args + 8u32);
+            let prgbq = <u32>::from_stack(mem, stack_args + 12u32);
+            winapi::gdi32::SetDIBColorTable(machine, hdc, iStart, cEntries, prgbq).to_raw()
+        }
         pub unsafe fn SetDIBitsToDevice(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
-            let xDest = <u32>::from_stack(mem, stack_args + 4u32);
-            let yDest = <u32>::from_stack(mem, stack_args + 8u32);
+            let xDest = <i32>::from_stack(mem, stack_args + 4u32);
+            let yDest = <i32>::from_stack(mem, stack_args + 8u32);
             let w = <u32>::from_stack(mem, stack_args + 12u32);
             let h = <u32>::from_stack(mem, stack_args + 16u32);
-            let xSrc = <u32>::from_stack(mem, stack_args + 20u32);
-            let ySrc = <u32>::from_stack(mem, stack_args + 24u32);
+            let xSrc = <i32>::from_stack(mem, stack_args + 20u32);
+            let ySrc = <i32>::from_stack(mem, stack_args + 24u32);
             let StartScan = <u32>::from_stack(mem, stack_args + 28u32);
             let cLines = <u32>::from_stack(mem, stack_args + 32u32);
             let lpvBits = <u32>::from_stack(mem, stack_args + 36u32);
@@ -1514,13 +2264,13 @@ pub mod gdi32 {
             let hdcDest = <HDC>::from_stack(mem, stack_args + 0u32);
             let xDest = <i32>::from_stack(mem, stack_args + 4u32);
             let yDest = <i32>::from_stack(mem, stack_args + 8u32);
-            let wDest = <u32>::from_stack(mem, stack_args + 12u32);
-            let hDest = <u32>::from_stack(mem, stack_args + 16u32);
+            let wDest = <i32>::from_stack(mem, stack_args + 12u32);
+            let hDest = <i32>::from_stack(mem, stack_args + 16u32);
             let hdcSrc = <HDC>::from_stack(mem, stack_args + 20u32);
             let xSrc = <i32>::from_stack(mem, stack_args + 24u32);
             let ySrc = <i32>::from_stack(mem, stack_args + 28u32);
-            let wSrc = <u32>::from_stack(mem, stack_args + 32u32);
-            let hSrc = <u32>::from_stack(mem, stack_args + 36u32);
+            let wSrc = <i32>::from_stack(mem, stack_args + 32u32);
+            let hSrc = <i32>::from_stack(mem, stack_args + 36u32);
             let rop = <Result<RasterOp, u32>>::from_stack(mem, stack_args + 40u32);
             winapi::gdi32::StretchBlt(
                 machine, hdcDest, xDest, yDest, wDest, hDest, hdcSrc, xSrc, ySrc, wSrc, hSrc, rop,
@@ -1530,14 +2280,14 @@ pub mod gdi32 {
         pub unsafe fn StretchDIBits(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
-            let xDest = <u32>::from_stack(mem, stack_args + 4u32);
-            let yDest = <u32>::from_stack(mem, stack_args + 8u32);
-            let DestWidth = <u32>::from_stack(mem, stack_args + 12u32);
-            let DestHeight = <u32>::from_stack(mem, stack_args + 16u32);
-            let xSrc = <u32>::from_stack(mem, stack_args + 20u32);
-            let ySrc = <u32>::from_stack(mem, stack_args + 24u32);
-            let SrcWidth = <u32>::from_stack(mem, stack_args + 28u32);
-            let SrcHeight = <u32>::from_stack(mem, stack_args + 32u32);
+            let xDest = <i32>::from_stack(mem, stack_args + 4u32);
+            let yDest = <i32>::from_stack(mem, stack_args + 8u32);
+            let DestWidth = <i32>::from_stack(mem, stack_args + 12u32);
+            let DestHeight = <i32>::from_stack(mem, stack_args + 16u32);
+            let xSrc = <i32>::from_stack(mem, stack_args + 20u32);
+            let ySrc = <i32>::from_stack(mem, stack_args + 24u32);
+            let SrcWidth = <i32>::from_stack(mem, stack_args + 28u32);
+            let SrcHeight = <i32>::from_stack(mem, stack_args + 32u32);
             let lpBits = <u32>::from_stack(mem, stack_args + 36u32);
             let lpbmi = <u32>::from_stack(mem, stack_args + 40u32);
             let iUsage = <u32>::from_stack(mem, stack_args + 44u32);
@@ -1551,21 +2301,21 @@ pub mod gdi32 {
         pub unsafe fn TextOutA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
-            let x = <u32>::from_stack(mem, stack_args + 4u32);
-            let y = <u32>::from_stack(mem, stack_args + 8u32);
+            let x = <i32>::from_stack(mem, stack_args + 4u32);
+            let y = <i32>::from_stack(mem, stack_args + 8u32);
             let lpString = <ArrayWithSize<u8>>::from_stack(mem, stack_args + 12u32);
             winapi::gdi32::TextOutA(machine, hdc, x, y, lpString).to_raw()
         }
         pub unsafe fn TextOutW(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hdc = <HDC>::from_stack(mem, stack_args + 0u32);
-            let x = <u32>::from_stack(mem, stack_args + 4u32);
-            let y = <u32>::from_stack(mem, stack_args + 8u32);
+            let x = <i32>::from_stack(mem, stack_args + 4u32);
+            let y = <i32>::from_stack(mem, stack_args + 8u32);
             let lpString = <ArrayWithSize<u16>>::from_stack(mem, stack_args + 12u32);
             winapi::gdi32::TextOutW(machine, hdc, x, y, lpString).to_raw()
         }
     }
-    const SHIMS: [Shim; 40usize] = [
+    const SHIMS: [Shim; 46usize] = [
         Shim {
             name: "BitBlt",
             func: Handler::Sync(impls::BitBlt),
@@ -1610,10 +2360,18 @@ pub mod gdi32 {
             name: "DeleteObject",
             func: Handler::Sync(impls::DeleteObject),
         },
+        Shim {
+            name: "Ellipse",
+            func: Handler::Sync(impls::Ellipse),
+        },
         Shim {
             name: "GetDCOrgEx",
             func: Handler::Sync(impls::GetDCOrgEx),
         },
+        Shim {
+            name: "GetDIBColorTable",
+            func: Handler::Sync(impls::GetDIBColorTable),
+        },
         Shim {
             name: "GetDeviceCaps",
             func: Handler::Sync(impls::GetDeviceCaps),
@@ -1670,10 +2428,22 @@ pub mod gdi32 {
             name: "PtVisible",
             func: Handler::Sync(impls::PtVisible),
         },
+        Shim {
+            name: "RealizePalette",
+            func: Handler::Sync(impls::RealizePalette),
+        },
+        Shim {
+            name: "Rectangle",
+            func: Handler::Sync(impls::Rectangle),
+        },
         Shim {
             name: "SelectObject",
             func: Handler::Sync(impls::SelectObject),
         },
+        Shim {
+            name: "SelectPalette",
+            func: Handler::Sync(impls::SelectPalette),
+        },
         Shim {
             name: "SetBkColor",
             func: Handler::Sync(impls::SetBkColor),
@@ -1686,6 +2456,10 @@ pub mod gdi32 {
             name: "SetBrushOrgEx",
             func: Handler::Sync(impls::SetBrushOrgEx),
         },
+        Shim {
+            name: "SetDIBColorTable",
+            func: Handler::Sync(impls::SetDIBColorTable),
+        },
         Shim {
             name: "SetDIBitsToDevice",
             func: Handler::Sync(impls::SetDIBitsToDevice),
@@ -1807,6 +2581,25 @@ pub mod kernel32 {
             )
             .to_raw()
         }
+        pub unsafe fn CreateFileMappingA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hFile = <HFILE>::from_stack(mem, stack_args + 0u32);
+            let lpFileMappingAttributes = <u32>::from_stack(mem, stack_args + 4u32);
+            let flProtect = <Result<PAGE, u32>>::from_stack(mem, stack_args + 8u32);
+            let dwMaximumSizeHigh = <u32>::from_stack(mem, stack_args + 12u32);
+            let dwMaximumSizeLow = <u32>::from_stack(mem, stack_args + 16u32);
+            let lpName = <Option<&str>>::from_stack(mem, stack_args + 20u32);
+            winapi::kernel32::CreateFileMappingA(
+                machine,
+                hFile,
+                lpFileMappingAttributes,
+                flProtect,
+                dwMaximumSizeHigh,
+                dwMaximumSizeLow,
+                lpName,
+            )
+            .to_raw()
+        }
         pub unsafe fn CreateFileW(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let lpFileName = <Option<&Str16>>::from_stack(mem, stack_args + 0u32);
@@ -1830,6 +2623,14 @@ pub mod kernel32 {
             )
             .to_raw()
         }
+        pub unsafe fn CreateMutexA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let lpMutexAttributes = <u32>::from_stack(mem, stack_args + 0u32);
+            let bInitialOwner = <bool>::from_stack(mem, stack_args + 4u32);
+            let lpName = <Option<&str>>::from_stack(mem, stack_args + 8u32);
+            winapi::kernel32::CreateMutexA(machine, lpMutexAttributes, bInitialOwner, lpName)
+                .to_raw()
+        }
         pub unsafe fn CreateThread(
             machine: &mut Machine,
             stack_args: u32,
@@ -1840,7 +2641,7 @@ pub mod kernel32 {
             let lpStartAddress = <u32>::from_stack(mem, stack_args + 8u32);
             let lpParameter = <u32>::from_stack(mem, stack_args + 12u32);
             let dwCreationFlags = <u32>::from_stack(mem, stack_args + 16u32);
-            let lpThreadId = <u32>::from_stack(mem, stack_args + 20u32);
+            let lpThreadId = <Option<&mut u32>>::from_stack(mem, stack_args + 20u32);
             let machine: *mut Machine = machine;
             Box::pin(async move {
                 let machine = unsafe { &mut *machine };
@@ -1886,6 +2687,11 @@ pub mod kernel32 {
             let uExitCode = <u32>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::ExitProcess(machine, uExitCode).to_raw()
         }
+        pub unsafe fn ExitThread(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let dwExitCode = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::ExitThread(machine, dwExitCode).to_raw()
+        }
         pub unsafe fn FileTimeToSystemTime(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let lpFileTime = <Option<&FILETIME>>::from_stack(mem, stack_args + 0u32);
@@ -1974,12 +2780,13 @@ pub mod kernel32 {
         }
         pub unsafe fn FreeEnvironmentStringsA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let _penv = <u32>::from_stack(mem, stack_args + 0u32);
-            winapi::kernel32::FreeEnvironmentStringsA(machine, _penv).to_raw()
+            let penv = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::FreeEnvironmentStringsA(machine, penv).to_raw()
         }
         pub unsafe fn FreeEnvironmentStringsW(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            winapi::kernel32::FreeEnvironmentStringsW(machine).to_raw()
+            let penv = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::FreeEnvironmentStringsW(machine, penv).to_raw()
         }
         pub unsafe fn FreeLibrary(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
@@ -2048,6 +2855,10 @@ pub mod kernel32 {
             let mem = machine.mem().detach();
             winapi::kernel32::GetEnvironmentStrings(machine).to_raw()
         }
+        pub unsafe fn GetEnvironmentStringsA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            winapi::kernel32::GetEnvironmentStringsA(machine).to_raw()
+        }
         pub unsafe fn GetEnvironmentStringsW(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             winapi::kernel32::GetEnvironmentStringsW(machine).to_raw()
@@ -2055,13 +2866,13 @@ pub mod kernel32 {
         pub unsafe fn GetEnvironmentVariableA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let name = <Option<&str>>::from_stack(mem, stack_args + 0u32);
-            let buf = <ArrayWithSize<u8>>::from_stack(mem, stack_args + 4u32);
+            let buf = <ArrayWithSizeMut<u8>>::from_stack(mem, stack_args + 4u32);
             winapi::kernel32::GetEnvironmentVariableA(machine, name, buf).to_raw()
         }
         pub unsafe fn GetEnvironmentVariableW(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let name = <Option<&Str16>>::from_stack(mem, stack_args + 0u32);
-            let buf = <ArrayWithSize<u16>>::from_stack(mem, stack_args + 4u32);
+            let buf = <ArrayWithSizeMut<u16>>::from_stack(mem, stack_args + 4u32);
             winapi::kernel32::GetEnvironmentVariableW(machine, name, buf).to_raw()
         }
         pub unsafe fn GetFileAttributesA(machine: &mut Machine, stack_args: u32) -> u32 {
@@ -2150,9 +2961,8 @@ pub mod kernel32 {
         pub unsafe fn GetModuleFileNameW(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hModule = <HMODULE>::from_stack(mem, stack_args + 0u32);
-            let _lpFilename = <u32>::from_stack(mem, stack_args + 4u32);
-            let _nSize = <u32>::from_stack(mem, stack_args + 8u32);
-            winapi::kernel32::GetModuleFileNameW(machine, hModule, _lpFilename, _nSize).to_raw()
+            let filename = <ArrayWithSizeMut<u16>>::from_stack(mem, stack_args + 4u32);
+            winapi::kernel32::GetModuleFileNameW(machine, hModule, filename).to_raw()
         }
         pub unsafe fn GetModuleHandleA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
@@ -2292,6 +3102,10 @@ pub mod kernel32 {
             let mem = machine.mem().detach();
             winapi::kernel32::GetTickCount(machine).to_raw()
         }
+        pub unsafe fn GetTickCount64(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            winapi::kernel32::GetTickCount64(machine).to_raw()
+        }
         pub unsafe fn GetTimeZoneInformation(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let lpTimeZoneInformation =
@@ -2304,8 +3118,7 @@ pub mod kernel32 {
         }
         pub unsafe fn GetVersionExA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let lpVersionInformation =
-                <Option<&mut OSVERSIONINFO>>::from_stack(mem, stack_args + 0u32);
+            let lpVersionInformation = <u32>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::GetVersionExA(machine, lpVersionInformation).to_raw()
         }
         pub unsafe fn GetWindowsDirectoryA(machine: &mut Machine, stack_args: u32) -> u32 {
@@ -2330,6 +3143,16 @@ pub mod kernel32 {
             let hMem = <u32>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::GlobalFree(machine, hMem).to_raw()
         }
+        pub unsafe fn GlobalHandle(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let pMem = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::GlobalHandle(machine, pMem).to_raw()
+        }
+        pub unsafe fn GlobalLock(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hMem = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::GlobalLock(machine, hMem).to_raw()
+        }
         pub unsafe fn GlobalReAlloc(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hMem = <u32>::from_stack(mem, stack_args + 0u32);
@@ -2337,6 +3160,16 @@ pub mod kernel32 {
             let uFlags = <GMEM>::from_stack(mem, stack_args + 8u32);
             winapi::kernel32::GlobalReAlloc(machine, hMem, dwBytes, uFlags).to_raw()
         }
+        pub unsafe fn GlobalSize(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hMem = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::GlobalSize(machine, hMem).to_raw()
+        }
+        pub unsafe fn GlobalUnlock(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hMem = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::GlobalUnlock(machine, hMem).to_raw()
+        }
         pub unsafe fn HeapAlloc(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hHeap = <u32>::from_stack(mem, stack_args + 0u32);
@@ -2366,7 +3199,7 @@ pub mod kernel32 {
         pub unsafe fn HeapReAlloc(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hHeap = <u32>::from_stack(mem, stack_args + 0u32);
-            let dwFlags = <u32>::from_stack(mem, stack_args + 4u32);
+            let dwFlags = <Result<HeapAllocFlags, u32>>::from_stack(mem, stack_args + 4u32);
             let lpMem = <u32>::from_stack(mem, stack_args + 8u32);
             let dwBytes = <u32>::from_stack(mem, stack_args + 12u32);
             winapi::kernel32::HeapReAlloc(machine, hHeap, dwFlags, lpMem, dwBytes).to_raw()
@@ -2455,11 +3288,31 @@ pub mod kernel32 {
             let ListHead = <Option<&mut SLIST_HEADER>>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::InitializeSListHead(machine, ListHead).to_raw()
         }
+        pub unsafe fn InterlockedCompareExchange(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let Destination = <Option<&mut u32>>::from_stack(mem, stack_args + 0u32);
+            let Exchange = <u32>::from_stack(mem, stack_args + 4u32);
+            let Comperand = <u32>::from_stack(mem, stack_args + 8u32);
+            winapi::kernel32::InterlockedCompareExchange(machine, Destination, Exchange, Comperand)
+                .to_raw()
+        }
         pub unsafe fn InterlockedDecrement(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let addend = <Option<&mut u32>>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::InterlockedDecrement(machine, addend).to_raw()
         }
+        pub unsafe fn InterlockedExchange(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let Target = <Option<&mut u32>>::from_stack(mem, stack_args + 0u32);
+            let Value = <u32>::from_stack(mem, stack_args + 4u32);
+            winapi::kernel32::InterlockedExchange(machine, Target, Value).to_raw()
+        }
+        pub unsafe fn InterlockedExchangeAdd(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let Addend = <Option<&mut u32>>::from_stack(mem, stack_args + 0u32);
+            let Value = <u32>::from_stack(mem, stack_args + 4u32);
+            winapi::kernel32::InterlockedExchangeAdd(machine, Addend, Value).to_raw()
+        }
         pub unsafe fn InterlockedIncrement(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let addend = <Option<&mut u32>>::from_stack(mem, stack_args + 0u32);
@@ -2532,17 +3385,51 @@ pub mod kernel32 {
             let lpCriticalSection = <u32>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::LeaveCriticalSection(machine, lpCriticalSection).to_raw()
         }
-        pub unsafe fn LoadLibraryA(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn LoadLibraryA(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let filename = <Option<&str>>::from_stack(mem, stack_args + 0u32);
-            winapi::kernel32::LoadLibraryA(machine, filename).to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::kernel32::LoadLibraryA(machine, filename)
+                    .await
+                    .to_raw()
+            })
+        }
+        pub unsafe fn LoadLibraryExA(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
+            let mem = machine.mem().detach();
+            let lpLibFileName = <Option<&str>>::from_stack(mem, stack_args + 0u32);
+            let hFile = <HFILE>::from_stack(mem, stack_args + 4u32);
+            let dwFlags = <u32>::from_stack(mem, stack_args + 8u32);
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::kernel32::LoadLibraryExA(machine, lpLibFileName, hFile, dwFlags)
+                    .await
+                    .to_raw()
+            })
         }
-        pub unsafe fn LoadLibraryExW(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn LoadLibraryExW(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let lpLibFileName = <Option<&Str16>>::from_stack(mem, stack_args + 0u32);
             let hFile = <HFILE>::from_stack(mem, stack_args + 4u32);
             let dwFlags = <u32>::from_stack(mem, stack_args + 8u32);
-            winapi::kernel32::LoadLibraryExW(machine, lpLibFileName, hFile, dwFlags).to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::kernel32::LoadLibraryExW(machine, lpLibFileName, hFile, dwFlags)
+                    .await
+                    .to_raw()
+            })
         }
         pub unsafe fn LoadResource(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
@@ -2561,11 +3448,43 @@ pub mod kernel32 {
             let hMem = <u32>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::LocalFree(machine, hMem).to_raw()
         }
+        pub unsafe fn LocalHandle(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let pMem = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::LocalHandle(machine, pMem).to_raw()
+        }
+        pub unsafe fn LocalLock(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hMem = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::LocalLock(machine, hMem).to_raw()
+        }
+        pub unsafe fn LocalUnlock(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hMem = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::LocalUnlock(machine, hMem).to_raw()
+        }
         pub unsafe fn LockResource(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hResData = <HRSRC>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::LockResource(machine, hResData).to_raw()
         }
+        pub unsafe fn MapViewOfFile(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hFileMappingObject = <HFILEMAPPING>::from_stack(mem, stack_args + 0u32);
+            let dwDesiredAccess = <u32>::from_stack(mem, stack_args + 4u32);
+            let dwFileOffsetHigh = <u32>::from_stack(mem, stack_args + 8u32);
+            let dwFileOffsetLow = <u32>::from_stack(mem, stack_args + 12u32);
+            let dwNumberOfBytesToMap = <u32>::from_stack(mem, stack_args + 16u32);
+            winapi::kernel32::MapViewOfFile(
+                machine,
+                hFileMappingObject,
+                dwDesiredAccess,
+                dwFileOffsetHigh,
+                dwFileOffsetLow,
+                dwNumberOfBytesToMap,
+            )
+            .to_raw()
+        }
         pub unsafe fn MulDiv(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let nNumber = <i32>::from_stack(mem, stack_args + 0u32);
@@ -2596,8 +3515,18 @@ pub mod kernel32 {
         }
         pub unsafe fn OutputDebugStringA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let msg = <Option<&str>>::from_stack(mem, stack_args + 0u32);
-            winapi::kernel32::OutputDebugStringA(machine, msg).to_raw()
+            let lpOutputString = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::OutputDebugStringA(machine, lpOutputString).to_raw()
+        }
+        pub unsafe fn OutputDebugStringW(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let lpOutputString = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::OutputDebugStringW(machine, lpOutputString).to_raw()
+        }
+        pub unsafe fn PulseEvent(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hEvent = <HEVENT>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::PulseEvent(machine, hEvent).to_raw()
         }
         pub unsafe fn QueryPerformanceCounter(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
@@ -2607,23 +3536,31 @@ pub mod kernel32 {
         }
         pub unsafe fn QueryPerformanceFrequency(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let lpFrequency = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpFrequency = <Option<&mut LARGE_INTEGER>>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::QueryPerformanceFrequency(machine, lpFrequency).to_raw()
         }
-        pub unsafe fn RaiseException(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn RaiseException(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let dwExceptionCode = <u32>::from_stack(mem, stack_args + 0u32);
             let dwExceptionFlags = <u32>::from_stack(mem, stack_args + 4u32);
             let nNumberOfArguments = <u32>::from_stack(mem, stack_args + 8u32);
             let lpArguments = <u32>::from_stack(mem, stack_args + 12u32);
-            winapi::kernel32::RaiseException(
-                machine,
-                dwExceptionCode,
-                dwExceptionFlags,
-                nNumberOfArguments,
-                lpArguments,
-            )
-            .to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::kernel32::RaiseException(
+                    machine,
+                    dwExceptionCode,
+                    dwExceptionFlags,
+                    nNumberOfArguments,
+                    lpArguments,
+                )
+                .await
+                .to_raw()
+            })
         }
         pub unsafe fn ReadFile(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
@@ -2634,6 +3571,11 @@ pub mod kernel32 {
             winapi::kernel32::ReadFile(machine, hFile, lpBuffer, lpNumberOfBytesRead, lpOverlapped)
                 .to_raw()
         }
+        pub unsafe fn ReleaseMutex(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hMutex = <HMUTEX>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::ReleaseMutex(machine, hMutex).to_raw()
+        }
         pub unsafe fn ReleaseSRWLockExclusive(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let SRWLock = <Option<&mut SRWLOCK>>::from_stack(mem, stack_args + 0u32);
@@ -2649,25 +3591,38 @@ pub mod kernel32 {
             let lpPathName = <Option<&str>>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::RemoveDirectoryA(machine, lpPathName).to_raw()
         }
+        pub unsafe fn ResetEvent(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hEvent = <HEVENT>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::ResetEvent(machine, hEvent).to_raw()
+        }
         pub unsafe fn ResumeThread(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hThread = <HTHREAD>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::ResumeThread(machine, hThread).to_raw()
         }
-        pub unsafe fn RtlUnwind(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn RtlUnwind(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let TargetFrame = <u32>::from_stack(mem, stack_args + 0u32);
             let TargetIp = <u32>::from_stack(mem, stack_args + 4u32);
             let ExceptionRecord = <u32>::from_stack(mem, stack_args + 8u32);
             let ReturnValue = <u32>::from_stack(mem, stack_args + 12u32);
-            winapi::kernel32::RtlUnwind(
-                machine,
-                TargetFrame,
-                TargetIp,
-                ExceptionRecord,
-                ReturnValue,
-            )
-            .to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::kernel32::RtlUnwind(
+                    machine,
+                    TargetFrame,
+                    TargetIp,
+                    ExceptionRecord,
+                    ReturnValue,
+                )
+                .await
+                .to_raw()
+            })
         }
         pub unsafe fn SetConsoleCtrlHandler(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
@@ -2675,6 +3630,11 @@ pub mod kernel32 {
             let _add = <u32>::from_stack(mem, stack_args + 4u32);
             winapi::kernel32::SetConsoleCtrlHandler(machine, _handlerRoutine, _add).to_raw()
         }
+        pub unsafe fn SetCurrentDirectoryA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let lpPathName = <Option<&str>>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::SetCurrentDirectoryA(machine, lpPathName).to_raw()
+        }
         pub unsafe fn SetEndOfFile(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hFile = <HFILE>::from_stack(mem, stack_args + 0u32);
@@ -2686,6 +3646,12 @@ pub mod kernel32 {
             let value = <Option<&str>>::from_stack(mem, stack_args + 4u32);
             winapi::kernel32::SetEnvironmentVariableA(machine, name, value).to_raw()
         }
+        pub unsafe fn SetEnvironmentVariableW(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let name = <Option<&Str16>>::from_stack(mem, stack_args + 0u32);
+            let value = <Option<&Str16>>::from_stack(mem, stack_args + 4u32);
+            winapi::kernel32::SetEnvironmentVariableW(machine, name, value).to_raw()
+        }
         pub unsafe fn SetEvent(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hEvent = <HEVENT>::from_stack(mem, stack_args + 0u32);
@@ -2768,8 +3734,8 @@ pub mod kernel32 {
         }
         pub unsafe fn SetUnhandledExceptionFilter(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let _lpTopLevelExceptionFilter = <u32>::from_stack(mem, stack_args + 0u32);
-            winapi::kernel32::SetUnhandledExceptionFilter(machine, _lpTopLevelExceptionFilter)
+            let lpTopLevelExceptionFilter = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::SetUnhandledExceptionFilter(machine, lpTopLevelExceptionFilter)
                 .to_raw()
         }
         pub unsafe fn SizeofResource(machine: &mut Machine, stack_args: u32) -> u32 {
@@ -2792,6 +3758,11 @@ pub mod kernel32 {
                     .to_raw()
             })
         }
+        pub unsafe fn SuspendThread(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hThread = <HTHREAD>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::SuspendThread(machine, hThread).to_raw()
+        }
         pub unsafe fn SystemTimeToFileTime(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let lpSystemTime = <Option<&SYSTEMTIME>>::from_stack(mem, stack_args + 0u32);
@@ -2800,7 +3771,7 @@ pub mod kernel32 {
         }
         pub unsafe fn TerminateProcess(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let hProcess = <u32>::from_stack(mem, stack_args + 0u32);
+            let hProcess = <HANDLE<()>>::from_stack(mem, stack_args + 0u32);
             let uExitCode = <u32>::from_stack(mem, stack_args + 4u32);
             winapi::kernel32::TerminateProcess(machine, hProcess, uExitCode).to_raw()
         }
@@ -2829,10 +3800,24 @@ pub mod kernel32 {
             let SRWLock = <Option<&mut SRWLOCK>>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::TryAcquireSRWLockExclusive(machine, SRWLock).to_raw()
         }
-        pub unsafe fn UnhandledExceptionFilter(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn UnhandledExceptionFilter(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
+            let mem = machine.mem().detach();
+            let exceptionInfo = <u32>::from_stack(mem, stack_args + 0u32);
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::kernel32::UnhandledExceptionFilter(machine, exceptionInfo)
+                    .await
+                    .to_raw()
+            })
+        }
+        pub unsafe fn UnmapViewOfFile(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let _exceptionInfo = <u32>::from_stack(mem, stack_args + 0u32);
-            winapi::kernel32::UnhandledExceptionFilter(machine, _exceptionInfo).to_raw()
+            let lpBaseAddress = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::kernel32::UnmapViewOfFile(machine, lpBaseAddress).to_raw()
         }
         pub unsafe fn VirtualAlloc(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
@@ -2847,14 +3832,14 @@ pub mod kernel32 {
             let mem = machine.mem().detach();
             let lpAddress = <u32>::from_stack(mem, stack_args + 0u32);
             let dwSize = <u32>::from_stack(mem, stack_args + 4u32);
-            let dwFreeType = <u32>::from_stack(mem, stack_args + 8u32);
+            let dwFreeType = <Result<MEM, u32>>::from_stack(mem, stack_args + 8u32);
             winapi::kernel32::VirtualFree(machine, lpAddress, dwSize, dwFreeType).to_raw()
         }
         pub unsafe fn VirtualProtect(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let lpAddress = <u32>::from_stack(mem, stack_args + 0u32);
             let dwSize = <u32>::from_stack(mem, stack_args + 4u32);
-            let flNewProtect = <u32>::from_stack(mem, stack_args + 8u32);
+            let flNewProtect = <Result<PAGE, u32>>::from_stack(mem, stack_args + 8u32);
             let lpflOldProtect = <Option<&mut u32>>::from_stack(mem, stack_args + 12u32);
             winapi::kernel32::VirtualProtect(
                 machine,
@@ -2873,11 +3858,43 @@ pub mod kernel32 {
             let dwLength = <u32>::from_stack(mem, stack_args + 8u32);
             winapi::kernel32::VirtualQuery(machine, lpAddress, lpBuffer, dwLength).to_raw()
         }
-        pub unsafe fn WaitForSingleObject(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn WaitForMultipleObjects(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
+            let mem = machine.mem().detach();
+            let nCount = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpHandles = <u32>::from_stack(mem, stack_args + 4u32);
+            let bWaitAll = <bool>::from_stack(mem, stack_args + 8u32);
+            let dwMilliseconds = <u32>::from_stack(mem, stack_args + 12u32);
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::kernel32::WaitForMultipleObjects(
+                    machine,
+                    nCount,
+                    lpHandles,
+                    bWaitAll,
+                    dwMilliseconds,
+                )
+                .await
+                .to_raw()
+            })
+        }
+        pub unsafe fn WaitForSingleObject(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
-            let hHandle = <HEVENT>::from_stack(mem, stack_args + 0u32);
+            let hHandle = <u32>::from_stack(mem, stack_args + 0u32);
             let dwMilliseconds = <u32>::from_stack(mem, stack_args + 4u32);
-            winapi::kernel32::WaitForSingleObject(machine, hHandle, dwMilliseconds).to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::kernel32::WaitForSingleObject(machine, hHandle, dwMilliseconds)
+                    .await
+                    .to_raw()
+            })
         }
         pub unsafe fn WideCharToMultiByte(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
@@ -2887,7 +3904,8 @@ pub mod kernel32 {
             let cchWideChar = <i32>::from_stack(mem, stack_args + 12u32);
             let lpMultiByteStr = <u32>::from_stack(mem, stack_args + 16u32);
             let cbMultiByte = <i32>::from_stack(mem, stack_args + 20u32);
-            let lpUsedDefaultChar = <Option<&mut u32>>::from_stack(mem, stack_args + 24u32);
+            let lpDefaultChar = <Option<&u8>>::from_stack(mem, stack_args + 24u32);
+            let lpUsedDefaultChar = <Option<&mut u32>>::from_stack(mem, stack_args + 28u32);
             winapi::kernel32::WideCharToMultiByte(
                 machine,
                 CodePage,
@@ -2896,13 +3914,14 @@ pub mod kernel32 {
                 cchWideChar,
                 lpMultiByteStr,
                 cbMultiByte,
+                lpDefaultChar,
                 lpUsedDefaultChar,
             )
             .to_raw()
         }
         pub unsafe fn WriteConsoleA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let hConsoleOutput = <HANDLE<()>>::from_stack(mem, stack_args + 0u32);
+            let hConsoleOutput = <HFILE>::from_stack(mem, stack_args + 0u32);
             let lpBuffer = <ArrayWithSize<u8>>::from_stack(mem, stack_args + 4u32);
             let lpNumberOfCharsWritten = <Option<&mut u32>>::from_stack(mem, stack_args + 12u32);
             let lpReserved = <u32>::from_stack(mem, stack_args + 16u32);
@@ -2952,16 +3971,28 @@ pub mod kernel32 {
             let lpString = <Option<&Str16>>::from_stack(mem, stack_args + 8u32);
             winapi::kernel32::WriteProfileStringW(machine, lpAppName, lpKeyName, lpString).to_raw()
         }
+        pub unsafe fn lstrcatA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let lpString1 = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpString2 = <u32>::from_stack(mem, stack_args + 4u32);
+            winapi::kernel32::lstrcatA(machine, lpString1, lpString2).to_raw()
+        }
+        pub unsafe fn lstrcmpA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let lpString1 = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpString2 = <u32>::from_stack(mem, stack_args + 4u32);
+            winapi::kernel32::lstrcmpA(machine, lpString1, lpString2).to_raw()
+        }
         pub unsafe fn lstrcmpiA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let lpString1 = <Option<&str>>::from_stack(mem, stack_args + 0u32);
-            let lpString2 = <Option<&str>>::from_stack(mem, stack_args + 4u32);
+            let lpString1 = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpString2 = <u32>::from_stack(mem, stack_args + 4u32);
             winapi::kernel32::lstrcmpiA(machine, lpString1, lpString2).to_raw()
         }
         pub unsafe fn lstrcpyA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let lpString1 = <u32>::from_stack(mem, stack_args + 0u32);
-            let lpString2 = <Option<&str>>::from_stack(mem, stack_args + 4u32);
+            let lpString2 = <u32>::from_stack(mem, stack_args + 4u32);
             winapi::kernel32::lstrcpyA(machine, lpString1, lpString2).to_raw()
         }
         pub unsafe fn lstrcpyW(machine: &mut Machine, stack_args: u32) -> u32 {
@@ -2970,9 +4001,16 @@ pub mod kernel32 {
             let lpString2 = <Option<&Str16>>::from_stack(mem, stack_args + 4u32);
             winapi::kernel32::lstrcpyW(machine, lpString1, lpString2).to_raw()
         }
+        pub unsafe fn lstrcpynA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let lpString1 = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpString2 = <u32>::from_stack(mem, stack_args + 4u32);
+            let iMaxLength = <i32>::from_stack(mem, stack_args + 8u32);
+            winapi::kernel32::lstrcpynA(machine, lpString1, lpString2, iMaxLength).to_raw()
+        }
         pub unsafe fn lstrlenA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
-            let lpString = <Option<&str>>::from_stack(mem, stack_args + 0u32);
+            let lpString = <u32>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::lstrlenA(machine, lpString).to_raw()
         }
         pub unsafe fn lstrlenW(machine: &mut Machine, stack_args: u32) -> u32 {
@@ -2980,6 +4018,20 @@ pub mod kernel32 {
             let lpString = <Option<&Str16>>::from_stack(mem, stack_args + 0u32);
             winapi::kernel32::lstrlenW(machine, lpString).to_raw()
         }
+        pub unsafe fn retrowin32_delay_load(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
+            let mem = machine.mem().detach();
+            let index = <u32>::from_stack(mem, stack_args + 0u32);
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::kernel32::retrowin32_delay_load(machine, index)
+                    .await
+                    .to_raw()
+            })
+        }
         pub unsafe fn retrowin32_main(
             machine: &mut Machine,
             stack_args: u32,
@@ -3010,7 +4062,7 @@ pub mod kernel32 {
             })
         }
     }
-    const SHIMS: [Shim; 168usize] = [
+    const SHIMS: [Shim; 198usize] = [
         Shim {
             name: "AcquireSRWLockExclusive",
             func: Handler::Sync(impls::AcquireSRWLockExclusive),
@@ -3039,10 +4091,18 @@ pub mod kernel32 {
             name: "CreateFileA",
             func: Handler::Sync(impls::CreateFileA),
         },
+        Shim {
+            name: "CreateFileMappingA",
+            func: Handler::Sync(impls::CreateFileMappingA),
+        },
         Shim {
             name: "CreateFileW",
             func: Handler::Sync(impls::CreateFileW),
         },
+        Shim {
+            name: "CreateMutexA",
+            func: Handler::Sync(impls::CreateMutexA),
+        },
         Shim {
             name: "CreateThread",
             func: Handler::Async(impls::CreateThread),
@@ -3071,6 +4131,10 @@ pub mod kernel32 {
             name: "ExitProcess",
             func: Handler::Sync(impls::ExitProcess),
         },
+        Shim {
+            name: "ExitThread",
+            func: Handler::Sync(impls::ExitThread),
+        },
         Shim {
             name: "FileTimeToSystemTime",
             func: Handler::Sync(impls::FileTimeToSystemTime),
@@ -3167,6 +4231,10 @@ pub mod kernel32 {
             name: "GetEnvironmentStrings",
             func: Handler::Sync(impls::GetEnvironmentStrings),
         },
+        Shim {
+            name: "GetEnvironmentStringsA",
+            func: Handler::Sync(impls::GetEnvironmentStringsA),
+        },
         Shim {
             name: "GetEnvironmentStringsW",
             func: Handler::Sync(impls::GetEnvironmentStringsW),
@@ -3299,6 +4367,10 @@ pub mod kernel32 {
             name: "GetTickCount",
             func: Handler::Sync(impls::GetTickCount),
         },
+        Shim {
+            name: "GetTickCount64",
+            func: Handler::Sync(impls::GetTickCount64),
+        },
         Shim {
             name: "GetTimeZoneInformation",
             func: Handler::Sync(impls::GetTimeZoneInformation),
@@ -3327,10 +4399,26 @@ pub mod kernel32 {
             name: "GlobalFree",
             func: Handler::Sync(impls::GlobalFree),
         },
+        Shim {
+            name: "GlobalHandle",
+            func: Handler::Sync(impls::GlobalHandle),
+        },
+        Shim {
+            name: "GlobalLock",
+            func: Handler::Sync(impls::GlobalLock),
+        },
         Shim {
             name: "GlobalReAlloc",
             func: Handler::Sync(impls::GlobalReAlloc),
         },
+        Shim {
+            name: "GlobalSize",
+            func: Handler::Sync(impls::GlobalSize),
+        },
+        Shim {
+            name: "GlobalUnlock",
+            func: Handler::Sync(impls::GlobalUnlock),
+        },
         Shim {
             name: "HeapAlloc",
             func: Handler::Sync(impls::HeapAlloc),
@@ -3387,10 +4475,22 @@ pub mod kernel32 {
             name: "InitializeSListHead",
             func: Handler::Sync(impls::InitializeSListHead),
         },
+        Shim {
+            name: "InterlockedCompareExchange",
+            func: Handler::Sync(impls::InterlockedCompareExchange),
+        },
         Shim {
             name: "InterlockedDecrement",
             func: Handler::Sync(impls::InterlockedDecrement),
         },
+        Shim {
+            name: "InterlockedExchange",
+            func: Handler::Sync(impls::InterlockedExchange),
+        },
+        Shim {
+            name: "InterlockedExchangeAdd",
+            func: Handler::Sync(impls::InterlockedExchangeAdd),
+        },
         Shim {
             name: "InterlockedIncrement",
             func: Handler::Sync(impls::InterlockedIncrement),
@@ -3441,11 +4541,15 @@ pub mod kernel32 {
         },
         Shim {
             name: "LoadLibraryA",
-            func: Handler::Sync(impls::LoadLibraryA),
+            func: Handler::Async(impls::LoadLibraryA),
+        },
+        Shim {
+            name: "LoadLibraryExA",
+            func: Handler::Async(impls::LoadLibraryExA),
         },
         Shim {
             name: "LoadLibraryExW",
-            func: Handler::Sync(impls::LoadLibraryExW),
+            func: Handler::Async(impls::LoadLibraryExW),
         },
         Shim {
             name: "LoadResource",
@@ -3459,10 +4563,26 @@ pub mod kernel32 {
             name: "LocalFree",
             func: Handler::Sync(impls::LocalFree),
         },
+        Shim {
+            name: "LocalHandle",
+            func: Handler::Sync(impls::LocalHandle),
+        },
+        Shim {
+            name: "LocalLock",
+            func: Handler::Sync(impls::LocalLock),
+        },
+        Shim {
+            name: "LocalUnlock",
+            func: Handler::Sync(impls::LocalUnlock),
+        },
         Shim {
             name: "LockResource",
             func: Handler::Sync(impls::LockResource),
         },
+        Shim {
+            name: "MapViewOfFile",
+            func: Handler::Sync(impls::MapViewOfFile),
+        },
         Shim {
             name: "MulDiv",
             func: Handler::Sync(impls::MulDiv),
@@ -3479,6 +4599,14 @@ pub mod kernel32 {
             name: "OutputDebugStringA",
             func: Handler::Sync(impls::OutputDebugStringA),
         },
+        Shim {
+            name: "OutputDebugStringW",
+            func: Handler::Sync(impls::OutputDebugStringW),
+        },
+        Shim {
+            name: "PulseEvent",
+            func: Handler::Sync(impls::PulseEvent),
+        },
         Shim {
             name: "QueryPerformanceCounter",
             func: Handler::Sync(impls::QueryPerformanceCounter),
@@ -3489,12 +4617,16 @@ pub mod kernel32 {
         },
         Shim {
             name: "RaiseException",
-            func: Handler::Sync(impls::RaiseException),
+            func: Handler::Async(impls::RaiseException),
         },
         Shim {
             name: "ReadFile",
             func: Handler::Sync(impls::ReadFile),
         },
+        Shim {
+            name: "ReleaseMutex",
+            func: Handler::Sync(impls::ReleaseMutex),
+        },
         Shim {
             name: "ReleaseSRWLockExclusive",
             func: Handler::Sync(impls::ReleaseSRWLockExclusive),
@@ -3507,18 +4639,26 @@ pub mod kernel32 {
             name: "RemoveDirectoryA",
             func: Handler::Sync(impls::RemoveDirectoryA),
         },
+        Shim {
+            name: "ResetEvent",
+            func: Handler::Sync(impls::ResetEvent),
+        },
         Shim {
             name: "ResumeThread",
             func: Handler::Sync(impls::ResumeThread),
         },
         Shim {
             name: "RtlUnwind",
-            func: Handler::Sync(impls::RtlUnwind),
+            func: Handler::Async(impls::RtlUnwind),
         },
         Shim {
             name: "SetConsoleCtrlHandler",
             func: Handler::Sync(impls::SetConsoleCtrlHandler),
         },
+        Shim {
+            name: "SetCurrentDirectoryA",
+            func: Handler::Sync(impls::SetCurrentDirectoryA),
+        },
         Shim {
             name: "SetEndOfFile",
             func: Handler::Sync(impls::SetEndOfFile),
@@ -3527,6 +4667,10 @@ pub mod kernel32 {
             name: "SetEnvironmentVariableA",
             func: Handler::Sync(impls::SetEnvironmentVariableA),
         },
+        Shim {
+            name: "SetEnvironmentVariableW",
+            func: Handler::Sync(impls::SetEnvironmentVariableW),
+        },
         Shim {
             name: "SetEvent",
             func: Handler::Sync(impls::SetEvent),
@@ -3583,6 +4727,10 @@ pub mod kernel32 {
             name: "Sleep",
             func: Handler::Async(impls::Sleep),
         },
+        Shim {
+            name: "SuspendThread",
+            func: Handler::Sync(impls::SuspendThread),
+        },
         Shim {
             name: "SystemTimeToFileTime",
             func: Handler::Sync(impls::SystemTimeToFileTime),
@@ -3613,7 +4761,11 @@ pub mod kernel32 {
         },
         Shim {
             name: "UnhandledExceptionFilter",
-            func: Handler::Sync(impls::UnhandledExceptionFilter),
+            func: Handler::Async(impls::UnhandledExceptionFilter),
+        },
+        Shim {
+            name: "UnmapViewOfFile",
+            func: Handler::Sync(impls::UnmapViewOfFile),
         },
         Shim {
             name: "VirtualAlloc",
@@ -3631,9 +4783,13 @@ pub mod kernel32 {
             name: "VirtualQuery",
             func: Handler::Sync(impls::VirtualQuery),
         },
+        Shim {
+            name: "WaitForMultipleObjects",
+            func: Handler::Async(impls::WaitForMultipleObjects),
+        },
         Shim {
             name: "WaitForSingleObject",
-            func: Handler::Sync(impls::WaitForSingleObject),
+            func: Handler::Async(impls::WaitForSingleObject),
         },
         Shim {
             name: "WideCharToMultiByte",
@@ -3655,6 +4811,14 @@ pub mod kernel32 {
             name: "WriteProfileStringW",
             func: Handler::Sync(impls::WriteProfileStringW),
         },
+        Shim {
+            name: "lstrcatA",
+            func: Handler::Sync(impls::lstrcatA),
+        },
+        Shim {
+            name: "lstrcmpA",
+            func: Handler::Sync(impls::lstrcmpA),
+        },
         Shim {
             name: "lstrcmpiA",
             func: Handler::Sync(impls::lstrcmpiA),
@@ -3667,6 +4831,10 @@ pub mod kernel32 {
             name: "lstrcpyW",
             func: Handler::Sync(impls::lstrcpyW),
         },
+        Shim {
+            name: "lstrcpynA",
+            func: Handler::Sync(impls::lstrcpynA),
+        },
         Shim {
             name: "lstrlenA",
             func: Handler::Sync(impls::lstrlenA),
@@ -3675,6 +4843,10 @@ pub mod kernel32 {
             name: "lstrlenW",
             func: Handler::Sync(impls::lstrlenW),
         },
+        Shim {
+            name: "retrowin32_delay_load",
+            func: Handler::Async(impls::retrowin32_delay_load),
+        },
         Shim {
             name: "retrowin32_main",
             func: Handler::Async(impls::retrowin32_main),
@@ -3753,16 +4925,77 @@ pub mod ole32 {
         };
         use memory::Extensions;
         use winapi::ole32::*;
+        pub unsafe fn CoCreateInstance(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let rclsid = <Option<&GUID>>::from_stack(mem, stack_args + 0u32);
+            let pUnkOuter = <u32>::from_stack(mem, stack_args + 4u32);
+            let dwClsContext = <u32>::from_stack(mem, stack_args + 8u32);
+            let riid = <Option<&GUID>>::from_stack(mem, stack_args + 12u32);
+            let ppv = <Option<&mut u32>>::from_stack(mem, stack_args + 16u32);
+            winapi::ole32::CoCreateInstance(machine, rclsid, pUnkOuter, dwClsContext, riid, ppv)
+                .to_raw()
+        }
+        pub unsafe fn CoInitialize(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let pvReserved = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ole32::CoInitialize(machine, pvReserved).to_raw()
+        }
+        pub unsafe fn CoInitializeEx(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let pvReserved = <u32>::from_stack(mem, stack_args + 0u32);
+            let dwCoInit = <u32>::from_stack(mem, stack_args + 4u32);
+            winapi::ole32::CoInitializeEx(machine, pvReserved, dwCoInit).to_raw()
+        }
+        pub unsafe fn CoTaskMemAlloc(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let cb = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ole32::CoTaskMemAlloc(machine, cb).to_raw()
+        }
+        pub unsafe fn CoTaskMemFree(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let pv = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::ole32::CoTaskMemFree(machine, pv).to_raw()
+        }
+        pub unsafe fn CoUninitialize(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            winapi::ole32::CoUninitialize(machine).to_raw()
+        }
         pub unsafe fn OleInitialize(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let _pvReserved = <u32>::from_stack(mem, stack_args + 0u32);
             winapi::ole32::OleInitialize(machine, _pvReserved).to_raw()
         }
     }
-    const SHIMS: [Shim; 1usize] = [Shim {
-        name: "OleInitialize",
-        func: Handler::Sync(impls::OleInitialize),
-    }];
+    const SHIMS: [Shim; 7usize] = [
+        Shim {
+            name: "CoCreateInstance",
+            func: Handler::Sync(impls::CoCreateInstance),
+        },
+        Shim {
+            name: "CoInitialize",
+            func: Handler::Sync(impls::CoInitialize),
+        },
+        Shim {
+            name: "CoInitializeEx",
+            func: Handler::Sync(impls::CoInitializeEx),
+        },
+        Shim {
+            name: "CoTaskMemAlloc",
+            func: Handler::Sync(impls::CoTaskMemAlloc),
+        },
+        Shim {
+            name: "CoTaskMemFree",
+            func: Handler::Sync(impls::CoTaskMemFree),
+        },
+        Shim {
+            name: "CoUninitialize",
+            func: Handler::Sync(impls::CoUninitialize),
+        },
+        Shim {
+            name: "OleInitialize",
+            func: Handler::Sync(impls::OleInitialize),
+        },
+    ];
     pub const DLL: BuiltinDLL = BuiltinDLL {
         file_name: "ole32.dll",
         shims: &SHIMS,
@@ -3821,6 +5054,61 @@ pub mod retrowin32_test {
         raw: std::include_bytes!("../../dll/retrowin32_test.dll"),
     };
 }
+pub mod shell32 {
+    use super::*;
+    mod impls {
+        use crate::{
+            machine::Machine,
+            winapi::{self, stack_args::*, types::*},
+        };
+        use memory::Extensions;
+        use winapi::shell32::*;
+        pub unsafe fn SHGetFolderPathA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hwnd = <HWND>::from_stack(mem, stack_args + 0u32);
+            let csidl = <u32>::from_stack(mem, stack_args + 4u32);
+            let hToken = <u32>::from_stack(mem, stack_args + 8u32);
+            let dwFlags = <u32>::from_stack(mem, stack_args + 12u32);
+            let pszPath = <u32>::from_stack(mem, stack_args + 16u32);
+            winapi::shell32::SHGetFolderPathA(machine, hwnd, csidl, hToken, dwFlags, pszPath)
+                .to_raw()
+        }
+        pub unsafe fn ShellExecuteA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hwnd = <HWND>::from_stack(mem, stack_args + 0u32);
+            let lpOperation = <Option<&str>>::from_stack(mem, stack_args + 4u32);
+            let lpFile = <u32>::from_stack(mem, stack_args + 8u32);
+            let lpParameters = <Option<&str>>::from_stack(mem, stack_args + 12u32);
+            let lpDirectory = <u32>::from_stack(mem, stack_args + 16u32);
+            let nShowCmd = <u32>::from_stack(mem, stack_args + 20u32);
+            winapi::shell32::ShellExecuteA(
+                machine,
+                hwnd,
+                lpOperation,
+                lpFile,
+                lpParameters,
+                lpDirectory,
+                nShowCmd,
+            )
+            .to_raw()
+        }
+    }
+    const SHIMS: [Shim; 2usize] = [
+        Shim {
+            name: "SHGetFolderPathA",
+            func: Handler::Sync(impls::SHGetFolderPathA),
+        },
+        Shim {
+            name: "ShellExecuteA",
+            func: Handler::Sync(impls::ShellExecuteA),
+        },
+    ];
+    pub const DLL: BuiltinDLL = BuiltinDLL {
+        file_name: "shell32.dll",
+        shims: &SHIMS,
+        raw: std::include_bytes!("../../dll/shell32.dll"),
+    };
+}
 pub mod ucrtbase {
     use super::*;
     mod impls {
@@ -4286,11 +5574,20 @@ pub mod user32 {
             let lpNewItem = <Option<&str>>::from_stack(mem, stack_args + 12u32);
             winapi::user32::AppendMenuA(machine, hMenu, uFlags, uIDNewItem, lpNewItem).to_raw()
         }
-        pub unsafe fn BeginPaint(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn BeginPaint(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
             let lpPaint = <Option<&mut PAINTSTRUCT>>::from_stack(mem, stack_args + 4u32);
-            winapi::user32::BeginPaint(machine, hWnd, lpPaint).to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::user32::BeginPaint(machine, hWnd, lpPaint)
+                    .await
+                    .to_raw()
+            })
         }
         pub unsafe fn CheckDlgButton(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
@@ -4347,6 +5644,31 @@ pub mod user32 {
             )
             .to_raw()
         }
+        pub unsafe fn CreateDialogParamA(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
+            let mem = machine.mem().detach();
+            let hInstance = <u32>::from_stack(mem, stack_args + 0u32);
+            let lpTemplateName = <ResourceKey<&str>>::from_stack(mem, stack_args + 4u32);
+            let hWndParent = <HWND>::from_stack(mem, stack_args + 8u32);
+            let lpDialogFunc = <u32>::from_stack(mem, stack_args + 12u32);
+            let dwInitParam = <u32>::from_stack(mem, stack_args + 16u32);
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::user32::CreateDialogParamA(
+                    machine,
+                    hInstance,
+                    lpTemplateName,
+                    hWndParent,
+                    lpDialogFunc,
+                    dwInitParam,
+                )
+                .await
+                .to_raw()
+            })
+        }
         pub unsafe fn CreatePopupMenu(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             winapi::user32::CreatePopupMenu(machine).to_raw()
@@ -4464,61 +5786,92 @@ pub mod user32 {
                     .to_raw()
             })
         }
-        pub unsafe fn DestroyWindow(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn DestroyWindow(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
-            winapi::user32::DestroyWindow(machine, hWnd).to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::user32::DestroyWindow(machine, hWnd).await.to_raw()
+            })
         }
-        pub unsafe fn DialogBoxIndirectParamA(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn DialogBoxIndirectParamA(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let hInstance = <u32>::from_stack(mem, stack_args + 0u32);
             let hDialogTemplate = <u32>::from_stack(mem, stack_args + 4u32);
             let hWndParent = <HWND>::from_stack(mem, stack_args + 8u32);
             let lpDialogFunc = <u32>::from_stack(mem, stack_args + 12u32);
             let dwInitParam = <u32>::from_stack(mem, stack_args + 16u32);
-            winapi::user32::DialogBoxIndirectParamA(
-                machine,
-                hInstance,
-                hDialogTemplate,
-                hWndParent,
-                lpDialogFunc,
-                dwInitParam,
-            )
-            .to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::user32::DialogBoxIndirectParamA(
+                    machine,
+                    hInstance,
+                    hDialogTemplate,
+                    hWndParent,
+                    lpDialogFunc,
+                    dwInitParam,
+                )
+                .await
+                .to_raw()
+            })
         }
-        pub unsafe fn DialogBoxParamA(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn DialogBoxParamA(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let hInstance = <u32>::from_stack(mem, stack_args + 0u32);
-            let lpTemplateName = <u32>::from_stack(mem, stack_args + 4u32);
+            let lpTemplateName = <ResourceKey<&str>>::from_stack(mem, stack_args + 4u32);
             let hWndParent = <HWND>::from_stack(mem, stack_args + 8u32);
             let lpDialogFunc = <u32>::from_stack(mem, stack_args + 12u32);
             let dwInitParam = <u32>::from_stack(mem, stack_args + 16u32);
-            winapi::user32::DialogBoxParamA(
-                machine,
-                hInstance,
-                lpTemplateName,
-                hWndParent,
-                lpDialogFunc,
-                dwInitParam,
-            )
-            .to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::user32::DialogBoxParamA(
+                    machine,
+                    hInstance,
+                    lpTemplateName,
+                    hWndParent,
+                    lpDialogFunc,
+                    dwInitParam,
+                )
+                .await
+                .to_raw()
+            })
         }
-        pub unsafe fn DialogBoxParamW(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn DialogBoxParamW(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let hInstance = <u32>::from_stack(mem, stack_args + 0u32);
-            let lpTemplateName = <u32>::from_stack(mem, stack_args + 4u32);
+            let lpTemplateName = <ResourceKey<&Str16>>::from_stack(mem, stack_args + 4u32);
             let hWndParent = <HWND>::from_stack(mem, stack_args + 8u32);
             let lpDialogFunc = <u32>::from_stack(mem, stack_args + 12u32);
             let dwInitParam = <u32>::from_stack(mem, stack_args + 16u32);
-            winapi::user32::DialogBoxParamW(
-                machine,
-                hInstance,
-                lpTemplateName,
-                hWndParent,
-                lpDialogFunc,
-                dwInitParam,
-            )
-            .to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::user32::DialogBoxParamW(
+                    machine,
+                    hInstance,
+                    lpTemplateName,
+                    hWndParent,
+                    lpDialogFunc,
+                    dwInitParam,
+                )
+                .await
+                .to_raw()
+            })
         }
         pub unsafe fn DispatchMessageA(
             machine: &mut Machine,
@@ -4573,7 +5926,7 @@ pub mod user32 {
         pub unsafe fn EndDialog(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hDlg = <HWND>::from_stack(mem, stack_args + 0u32);
-            let nResult = <Option<&mut u32>>::from_stack(mem, stack_args + 4u32);
+            let nResult = <u32>::from_stack(mem, stack_args + 4u32);
             winapi::user32::EndDialog(machine, hDlg, nResult).to_raw()
         }
         pub unsafe fn EndPaint(machine: &mut Machine, stack_args: u32) -> u32 {
@@ -4606,6 +5959,11 @@ pub mod user32 {
             let mem = machine.mem().detach();
             winapi::user32::GetActiveWindow(machine).to_raw()
         }
+        pub unsafe fn GetAsyncKeyState(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let vKey = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::user32::GetAsyncKeyState(machine, vKey).to_raw()
+        }
         pub unsafe fn GetCapture(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             winapi::user32::GetCapture(machine).to_raw()
@@ -4616,6 +5974,11 @@ pub mod user32 {
             let lpRect = <Option<&mut RECT>>::from_stack(mem, stack_args + 4u32);
             winapi::user32::GetClientRect(machine, hWnd, lpRect).to_raw()
         }
+        pub unsafe fn GetCursorPos(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let lpPoint = <Option<&mut POINT>>::from_stack(mem, stack_args + 0u32);
+            winapi::user32::GetCursorPos(machine, lpPoint).to_raw()
+        }
         pub unsafe fn GetDC(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
@@ -4883,9 +6246,8 @@ pub mod user32 {
             let mem = machine.mem().detach();
             let hInstance = <u32>::from_stack(mem, stack_args + 0u32);
             let uID = <u32>::from_stack(mem, stack_args + 4u32);
-            let lpBuffer = <u32>::from_stack(mem, stack_args + 8u32);
-            let cchBufferMax = <u32>::from_stack(mem, stack_args + 12u32);
-            winapi::user32::LoadStringA(machine, hInstance, uID, lpBuffer, cchBufferMax).to_raw()
+            let lpBuffer = <ArrayWithSizeMut<u8>>::from_stack(mem, stack_args + 8u32);
+            winapi::user32::LoadStringA(machine, hInstance, uID, lpBuffer).to_raw()
         }
         pub unsafe fn LoadStringW(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
@@ -4905,8 +6267,8 @@ pub mod user32 {
         pub unsafe fn MessageBoxA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
-            let lpText = <Option<&str>>::from_stack(mem, stack_args + 4u32);
-            let lpCaption = <Option<&str>>::from_stack(mem, stack_args + 8u32);
+            let lpText = <u32>::from_stack(mem, stack_args + 4u32);
+            let lpCaption = <u32>::from_stack(mem, stack_args + 8u32);
             let uType = <u32>::from_stack(mem, stack_args + 12u32);
             winapi::user32::MessageBoxA(machine, hWnd, lpText, lpCaption, uType).to_raw()
         }
@@ -4979,6 +6341,14 @@ pub mod user32 {
             )
             .to_raw()
         }
+        pub unsafe fn PostMessageA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
+            let Msg = <u32>::from_stack(mem, stack_args + 4u32);
+            let wParam = <u32>::from_stack(mem, stack_args + 8u32);
+            let lParam = <u32>::from_stack(mem, stack_args + 12u32);
+            winapi::user32::PostMessageA(machine, hWnd, Msg, wParam, lParam).to_raw()
+        }
         pub unsafe fn PostMessageW(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
@@ -5033,13 +6403,19 @@ pub mod user32 {
             let hdc = <HDC>::from_stack(mem, stack_args + 4u32);
             winapi::user32::ReleaseDC(machine, hwnd, hdc).to_raw()
         }
+        pub unsafe fn ScreenToClient(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
+            let lpPoint = <Option<&mut POINT>>::from_stack(mem, stack_args + 4u32);
+            winapi::user32::ScreenToClient(machine, hWnd, lpPoint).to_raw()
+        }
         pub unsafe fn SendMessageA(
             machine: &mut Machine,
             stack_args: u32,
         ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
-            let Msg = <Result<WM, u32>>::from_stack(mem, stack_args + 4u32);
+            let Msg = <u32>::from_stack(mem, stack_args + 4u32);
             let wParam = <u32>::from_stack(mem, stack_args + 8u32);
             let lParam = <u32>::from_stack(mem, stack_args + 12u32);
             let machine: *mut Machine = machine;
@@ -5056,7 +6432,7 @@ pub mod user32 {
         ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
-            let Msg = <Result<WM, u32>>::from_stack(mem, stack_args + 4u32);
+            let Msg = <u32>::from_stack(mem, stack_args + 4u32);
             let wParam = <u32>::from_stack(mem, stack_args + 8u32);
             let lParam = <u32>::from_stack(mem, stack_args + 12u32);
             let machine: *mut Machine = machine;
@@ -5067,6 +6443,20 @@ pub mod user32 {
                     .to_raw()
             })
         }
+        pub unsafe fn SetActiveWindow(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
+            let mem = machine.mem().detach();
+            let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::user32::SetActiveWindow(machine, hWnd)
+                    .await
+                    .to_raw()
+            })
+        }
         pub unsafe fn SetCapture(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let hwnd = <HWND>::from_stack(mem, stack_args + 0u32);
@@ -5105,15 +6495,31 @@ pub mod user32 {
             let lpString = <Option<&Str16>>::from_stack(mem, stack_args + 8u32);
             winapi::user32::SetDlgItemTextW(machine, hDlg, nIDDlgItem, lpString).to_raw()
         }
-        pub unsafe fn SetFocus(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn SetFocus(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
-            winapi::user32::SetFocus(machine, hWnd).to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::user32::SetFocus(machine, hWnd).await.to_raw()
+            })
         }
-        pub unsafe fn SetForegroundWindow(machine: &mut Machine, stack_args: u32) -> u32 {
+        pub unsafe fn SetForegroundWindow(
+            machine: &mut Machine,
+            stack_args: u32,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = u32>>> {
             let mem = machine.mem().detach();
             let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
-            winapi::user32::SetForegroundWindow(machine, hWnd).to_raw()
+            let machine: *mut Machine = machine;
+            Box::pin(async move {
+                let machine = unsafe { &mut *machine };
+                winapi::user32::SetForegroundWindow(machine, hWnd)
+                    .await
+                    .to_raw()
+            })
         }
         pub unsafe fn SetMenu(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
@@ -5151,6 +6557,13 @@ pub mod user32 {
             let lpTimerFunc = <u32>::from_stack(mem, stack_args + 12u32);
             winapi::user32::SetTimer(machine, hWnd, nIDEvent, uElapse, lpTimerFunc).to_raw()
         }
+        pub unsafe fn SetWindowLongA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let hWnd = <HWND>::from_stack(mem, stack_args + 0u32);
+            let nIndex = <i32>::from_stack(mem, stack_args + 4u32);
+            let dwNewLong = <i32>::from_stack(mem, stack_args + 8u32);
+            winapi::user32::SetWindowLongA(machine, hWnd, nIndex, dwNewLong).to_raw()
+        }
         pub unsafe fn SetWindowPos(
             machine: &mut Machine,
             stack_args: u32,
@@ -5249,7 +6662,7 @@ pub mod user32 {
         pub unsafe fn wsprintfA(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             let buf = <u32>::from_stack(mem, stack_args + 0u32);
-            let fmt = <Option<&str>>::from_stack(mem, stack_args + 4u32);
+            let fmt = <u32>::from_stack(mem, stack_args + 4u32);
             let args = <VarArgs>::from_stack(mem, stack_args + 8u32);
             winapi::user32::wsprintfA(machine, buf, fmt, args).to_raw()
         }
@@ -5260,8 +6673,15 @@ pub mod user32 {
             let args = <VarArgs>::from_stack(mem, stack_args + 8u32);
             winapi::user32::wsprintfW(machine, buf, fmt, args).to_raw()
         }
+        pub unsafe fn wvsprintfA(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let buf = <u32>::from_stack(mem, stack_args + 0u32);
+            let fmt = <u32>::from_stack(mem, stack_args + 4u32);
+            let arglist = <u32>::from_stack(mem, stack_args + 8u32);
+            winapi::user32::wvsprintfA(machine, buf, fmt, arglist).to_raw()
+        }
     }
-    const SHIMS: [Shim; 118usize] = [
+    const SHIMS: [Shim; 126usize] = [
         Shim {
             name: "AdjustWindowRect",
             func: Handler::Sync(impls::AdjustWindowRect),
@@ -5276,7 +6696,7 @@ pub mod user32 {
         },
         Shim {
             name: "BeginPaint",
-            func: Handler::Sync(impls::BeginPaint),
+            func: Handler::Async(impls::BeginPaint),
         },
         Shim {
             name: "CheckDlgButton",
@@ -5302,6 +6722,10 @@ pub mod user32 {
             name: "CreateCursor",
             func: Handler::Sync(impls::CreateCursor),
         },
+        Shim {
+            name: "CreateDialogParamA",
+            func: Handler::Async(impls::CreateDialogParamA),
+        },
         Shim {
             name: "CreatePopupMenu",
             func: Handler::Sync(impls::CreatePopupMenu),
@@ -5324,19 +6748,19 @@ pub mod user32 {
         },
         Shim {
             name: "DestroyWindow",
-            func: Handler::Sync(impls::DestroyWindow),
+            func: Handler::Async(impls::DestroyWindow),
         },
         Shim {
             name: "DialogBoxIndirectParamA",
-            func: Handler::Sync(impls::DialogBoxIndirectParamA),
+            func: Handler::Async(impls::DialogBoxIndirectParamA),
         },
         Shim {
             name: "DialogBoxParamA",
-            func: Handler::Sync(impls::DialogBoxParamA),
+            func: Handler::Async(impls::DialogBoxParamA),
         },
         Shim {
             name: "DialogBoxParamW",
-            func: Handler::Sync(impls::DialogBoxParamW),
+            func: Handler::Async(impls::DialogBoxParamW),
         },
         Shim {
             name: "DispatchMessageA",
@@ -5382,6 +6806,10 @@ pub mod user32 {
             name: "GetActiveWindow",
             func: Handler::Sync(impls::GetActiveWindow),
         },
+        Shim {
+            name: "GetAsyncKeyState",
+            func: Handler::Sync(impls::GetAsyncKeyState),
+        },
         Shim {
             name: "GetCapture",
             func: Handler::Sync(impls::GetCapture),
@@ -5390,6 +6818,10 @@ pub mod user32 {
             name: "GetClientRect",
             func: Handler::Sync(impls::GetClientRect),
         },
+        Shim {
+            name: "GetCursorPos",
+            func: Handler::Sync(impls::GetCursorPos),
+        },
         Shim {
             name: "GetDC",
             func: Handler::Sync(impls::GetDC),
@@ -5586,6 +7018,10 @@ pub mod user32 {
             name: "PeekMessageW",
             func: Handler::Sync(impls::PeekMessageW),
         },
+        Shim {
+            name: "PostMessageA",
+            func: Handler::Sync(impls::PostMessageA),
+        },
         Shim {
             name: "PostMessageW",
             func: Handler::Sync(impls::PostMessageW),
@@ -5626,6 +7062,10 @@ pub mod user32 {
             name: "ReleaseDC",
             func: Handler::Sync(impls::ReleaseDC),
         },
+        Shim {
+            name: "ScreenToClient",
+            func: Handler::Sync(impls::ScreenToClient),
+        },
         Shim {
             name: "SendMessageA",
             func: Handler::Async(impls::SendMessageA),
@@ -5634,6 +7074,10 @@ pub mod user32 {
             name: "SendMessageW",
             func: Handler::Async(impls::SendMessageW),
         },
+        Shim {
+            name: "SetActiveWindow",
+            func: Handler::Async(impls::SetActiveWindow),
+        },
         Shim {
             name: "SetCapture",
             func: Handler::Sync(impls::SetCapture),
@@ -5660,11 +7104,11 @@ pub mod user32 {
         },
         Shim {
             name: "SetFocus",
-            func: Handler::Sync(impls::SetFocus),
+            func: Handler::Async(impls::SetFocus),
         },
         Shim {
             name: "SetForegroundWindow",
-            func: Handler::Sync(impls::SetForegroundWindow),
+            func: Handler::Async(impls::SetForegroundWindow),
         },
         Shim {
             name: "SetMenu",
@@ -5686,6 +7130,10 @@ pub mod user32 {
             name: "SetTimer",
             func: Handler::Sync(impls::SetTimer),
         },
+        Shim {
+            name: "SetWindowLongA",
+            func: Handler::Sync(impls::SetWindowLongA),
+        },
         Shim {
             name: "SetWindowPos",
             func: Handler::Async(impls::SetWindowPos),
@@ -5734,6 +7182,10 @@ pub mod user32 {
             name: "wsprintfW",
             func: Handler::Sync(impls::wsprintfW),
         },
+        Shim {
+            name: "wvsprintfA",
+            func: Handler::Sync(impls::wvsprintfA),
+        },
     ];
     pub const DLL: BuiltinDLL = BuiltinDLL {
         file_name: "user32.dll",
@@ -5799,6 +7251,17 @@ pub mod winmm {
             let uPeriod = <u32>::from_stack(mem, stack_args + 0u32);
             winapi::winmm::timeBeginPeriod(machine, uPeriod).to_raw()
         }
+        pub unsafe fn timeEndPeriod(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let uPeriod = <u32>::from_stack(mem, stack_args + 0u32);
+            winapi::winmm::timeEndPeriod(machine, uPeriod).to_raw()
+        }
+        pub unsafe fn timeGetDevCaps(machine: &mut Machine, stack_args: u32) -> u32 {
+            let mem = machine.mem().detach();
+            let ptc = <Option<&mut TIMECAPS>>::from_stack(mem, stack_args + 0u32);
+            let cbtc = <u32>::from_stack(mem, stack_args + 4u32);
+            winapi::winmm::timeGetDevCaps(machine, ptc, cbtc).to_raw()
+        }
         pub unsafe fn timeGetTime(machine: &mut Machine, stack_args: u32) -> u32 {
             let mem = machine.mem().detach();
             winapi::winmm::timeGetTime(machine).to_raw()
@@ -5876,7 +7339,7 @@ pub mod winmm {
             winapi::winmm::waveOutWrite(machine, hwo, pwh, cbwh).to_raw()
         }
     }
-    const SHIMS: [Shim; 13usize] = [
+    const SHIMS: [Shim; 15usize] = [
         Shim {
             name: "PlaySoundW",
             func: Handler::Sync(impls::PlaySoundW),
@@ -5885,6 +7348,14 @@ pub mod winmm {
             name: "timeBeginPeriod",
             func: Handler::Sync(impls::timeBeginPeriod),
         },
+        Shim {
+            name: "timeEndPeriod",
+            func: Handler::Sync(impls::timeEndPeriod),
+        },
+        Shim {
+            name: "timeGetDevCaps",
+            func: Handler::Sync(impls::timeGetDevCaps),
+        },
         Shim {
             name: "timeGetTime",
             func: Handler::Sync(impls::timeGetTime),
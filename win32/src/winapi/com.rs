@@ -1,3 +1,6 @@
+use super::heap::Heap;
+use memory::{ExtensionsMut, Mem};
+
 #[allow(non_snake_case)]
 #[repr(C)]
 #[derive(PartialEq)]
@@ -26,9 +29,64 @@ impl std::fmt::Debug for GUID {
     }
 }
 
+#[allow(non_upper_case_globals)]
+pub const IID_IUnknown: GUID = GUID {
+    Data1: 0x00000000,
+    Data2: 0x0000,
+    Data3: 0x0000,
+    Data4: [0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+};
+
+/// QueryInterface result for an interface the object doesn't implement.
+pub const E_NOINTERFACE: u32 = 0x80004002;
+
 macro_rules! vtable {
     ($($fn:ident: $impl:tt,)*) => {
         // macro is parsed by win32-derive codegen
     };
 }
 pub(crate) use vtable;
+
+/// Layout of the COM objects we vend: the vtable pointer, which is all that
+/// callers look at, followed by the reference count.
+#[repr(C)]
+#[derive(Clone)]
+pub struct ComObject {
+    pub vtable: u32,
+    pub refcount: u32,
+}
+unsafe impl memory::Pod for ComObject {}
+
+impl ComObject {
+    /// Allocate an object with a reference count of 1.
+    pub fn alloc(heap: &mut Heap, mem: Mem, vtable: u32) -> u32 {
+        let addr = heap.alloc(mem, std::mem::size_of::<ComObject>() as u32);
+        mem.put_pod::<ComObject>(
+            addr,
+            ComObject {
+                vtable,
+                refcount: 1,
+            },
+        );
+        addr
+    }
+
+    /// Increment the reference count, returning the new count.
+    pub fn add_ref(mem: Mem, this: u32) -> u32 {
+        let obj = mem.view_mut::<ComObject>(this);
+        obj.refcount += 1;
+        obj.refcount
+    }
+
+    /// Decrement the reference count, returning the new count.
+    /// When it reaches 0 the caller is responsible for freeing the object.
+    pub fn release(mem: Mem, this: u32) -> u32 {
+        let obj = mem.view_mut::<ComObject>(this);
+        if obj.refcount == 0 {
+            log::warn!("{this:x}->Release() of released object");
+            return 0;
+        }
+        obj.refcount -= 1;
+        obj.refcount
+    }
+}
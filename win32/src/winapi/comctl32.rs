@@ -0,0 +1,686 @@
+#![allow(non_snake_case)]
+
+//! Common controls.  Only the progress bar and status bar exist, implemented by
+//! builtin window procedures that draw onto their parent window.
+
+use crate::{
+    winapi::{
+        gdi32::{self, COLORREF},
+        kernel32::{get_symbol, to_ansi},
+        types::{Str16, HWND, RECT},
+        user32::{
+            self, CreateWindowClassName, WindowStyle, WindowStyleEx, WindowType, WndClass, WM,
+        },
+    },
+    Machine,
+};
+use memory::{Extensions, ExtensionsMut};
+use std::collections::HashMap;
+
+const TRACE_CONTEXT: &'static str = "comctl32";
+
+const PROGRESS_CLASS: &str = "msctls_progress32";
+const STATUSCLASSNAME: &str = "msctls_statusbar32";
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct State {
+    progress: HashMap<HWND, Progress>,
+    status: HashMap<HWND, StatusBar>,
+}
+
+fn register_classes(machine: &mut Machine) {
+    for (name, wndproc) in [
+        (PROGRESS_CLASS, "retrowin32_progress_wndproc"),
+        (STATUSCLASSNAME, "retrowin32_status_wndproc"),
+    ] {
+        if machine.state.user32.has_class(name) {
+            continue;
+        }
+        let wndproc = get_symbol(machine, "comctl32.dll", wndproc);
+        user32::register_class(
+            machine,
+            WndClass {
+                atom: 0,
+                name: name.into(),
+                style: 0,
+                wndproc,
+                wnd_extra: 0,
+                background: Default::default(),
+                menu: false,
+            },
+        );
+    }
+}
+
+#[win32_derive::dllexport]
+pub fn InitCommonControls(machine: &mut Machine) {
+    register_classes(machine);
+}
+
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct INITCOMMONCONTROLSEX {
+    pub dwSize: u32,
+    pub dwICC: u32,
+}
+unsafe impl memory::Pod for INITCOMMONCONTROLSEX {}
+
+#[win32_derive::dllexport]
+pub fn InitCommonControlsEx(machine: &mut Machine, picce: Option<&INITCOMMONCONTROLSEX>) -> bool {
+    // The ICC_* flags pick which classes to register; we register everything we have.
+    if picce.is_none() {
+        return false;
+    }
+    register_classes(machine);
+    true
+}
+
+#[win32_derive::dllexport]
+pub async fn CreateStatusWindowA(
+    machine: &mut Machine,
+    style: u32,
+    lpszText: Option<&str>,
+    hwndParent: HWND,
+    wID: u32,
+) -> HWND {
+    register_classes(machine);
+    user32::CreateWindowExA(
+        machine,
+        Ok(WindowStyleEx::empty()),
+        CreateWindowClassName::Name(STATUSCLASSNAME),
+        lpszText,
+        WindowStyle::from_bits(style).ok_or(style),
+        0,
+        0,
+        0,
+        0,
+        hwndParent,
+        wID,
+        0,
+        0,
+    )
+    .await
+}
+
+/// The top-level window a control draws into, and the control's rect within it.
+fn control_rect(machine: &Machine, hwnd: HWND) -> Option<(HWND, RECT)> {
+    let window = machine.state.user32.windows.get(hwnd)?;
+    let mut rect = RECT {
+        left: 0,
+        top: 0,
+        right: window.width as i32,
+        bottom: window.height as i32,
+    };
+    let mut cur = window;
+    loop {
+        match cur.typ {
+            WindowType::TopLevel(_) => return Some((cur.hwnd, rect)),
+            WindowType::Child => {
+                rect = rect.add(cur.x, cur.y);
+                cur = machine.state.user32.windows.get(cur.parent)?;
+            }
+        }
+    }
+}
+
+trait RectExt {
+    fn add(&self, x: i32, y: i32) -> RECT;
+    fn inset(&self, n: i32) -> RECT;
+}
+
+impl RectExt for RECT {
+    fn add(&self, x: i32, y: i32) -> RECT {
+        RECT {
+            left: self.left + x,
+            top: self.top + y,
+            right: self.right + x,
+            bottom: self.bottom + y,
+        }
+    }
+
+    fn inset(&self, n: i32) -> RECT {
+        RECT {
+            left: self.left + n,
+            top: self.top + n,
+            right: self.right - n,
+            bottom: self.bottom - n,
+        }
+    }
+}
+
+const FACE: COLORREF = COLORREF::from_rgb(0xc0, 0xc0, 0xc0);
+const SHADOW: COLORREF = COLORREF::from_rgb(0x80, 0x80, 0x80);
+const HIGHLIGHT: COLORREF = COLORREF::from_rgb(0, 0, 0x80);
+
+/// Draw a sunken box filled with fill, returning its interior.
+fn draw_well(machine: &mut Machine, hdc: gdi32::HDC, rect: &RECT, fill: COLORREF) -> RECT {
+    let inner = rect.inset(1);
+    gdi32::fill_rect(machine, hdc, rect, SHADOW);
+    gdi32::fill_rect(machine, hdc, &inner, fill);
+    inner
+}
+
+fn draw_text(machine: &mut Machine, hdc: gdi32::HDC, x: i32, y: i32, text: &[u8]) {
+    // The built-in font only covers ASCII.
+    let text: Vec<u8> = text
+        .iter()
+        .map(|&c| if c.is_ascii() { c } else { b'?' })
+        .collect();
+    gdi32::TextOutA(machine, hdc, x, y, Some(&text));
+}
+
+/// Redraw a common control onto its top-level window.
+fn paint(machine: &mut Machine, hwnd: HWND) {
+    let Some((toplevel, rect)) = control_rect(machine, hwnd) else {
+        return;
+    };
+    let hdc = machine.state.gdi32.new_window_dc(toplevel);
+    gdi32::SetBkMode(machine, hdc, Ok(gdi32::BkMode::TRANSPARENT));
+    if let Some(progress) = machine.state.comctl32.progress.get(&hwnd) {
+        let filled = progress.filled(rect.right - rect.left - 2);
+        let inner = draw_well(machine, hdc, &rect, FACE);
+        let bar = RECT {
+            right: inner.left + filled,
+            ..inner
+        };
+        gdi32::fill_rect(machine, hdc, &bar, HIGHLIGHT);
+    } else if let Some(status) = machine.state.comctl32.status.get(&hwnd) {
+        let parts: Vec<(RECT, PartText)> = status
+            .part_rects(&rect)
+            .into_iter()
+            .zip(status.texts.iter().cloned())
+            .collect();
+        gdi32::fill_rect(machine, hdc, &rect, FACE);
+        for (part, text) in parts {
+            let inner = draw_well(machine, hdc, &part.inset(1), FACE);
+            // Owner-drawn parts are left for the app to draw on WM_DRAWITEM, which we don't send.
+            if let PartText::Text(text) = text {
+                draw_text(machine, hdc, inner.left + 2, inner.top + 1, &text);
+            }
+        }
+    }
+    machine.state.gdi32.dcs.remove(hdc);
+    user32::flush_window(machine, toplevel);
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct Progress {
+    min: i32,
+    max: i32,
+    pos: i32,
+    step: i32,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress {
+            min: 0,
+            max: 100,
+            pos: 0,
+            step: 10,
+        }
+    }
+}
+
+impl Progress {
+    /// Move to pos, clamped to the range, returning the previous position.  pos is wide
+    /// enough that offsets from any position can't overflow.
+    fn set_pos(&mut self, pos: i64) -> i32 {
+        let pos = pos.clamp(self.min as i64, self.max.max(self.min) as i64);
+        std::mem::replace(&mut self.pos, pos as i32)
+    }
+
+    fn set_range(&mut self, min: i32, max: i32) -> (i32, i32) {
+        let old = (self.min, self.max);
+        (self.min, self.max) = (min, max);
+        self.set_pos(self.pos as i64);
+        old
+    }
+
+    /// Advance by the step, wrapping around to the start on passing the end.
+    fn step_it(&mut self) -> i32 {
+        let (min, max) = (self.min as i64, self.max as i64);
+        let mut pos = self.pos as i64 + self.step as i64;
+        let span = max - min;
+        if pos > max && span > 0 {
+            pos = min + (pos - min) % span;
+        }
+        self.set_pos(pos)
+    }
+
+    /// How many of width pixels the bar covers.
+    fn filled(&self, width: i32) -> i32 {
+        let span = self.max as i64 - self.min as i64;
+        if span <= 0 {
+            return 0;
+        }
+        ((self.pos as i64 - self.min as i64) * width as i64 / span) as i32
+    }
+}
+
+const PBM_SETRANGE: u32 = 0x0401;
+const PBM_SETPOS: u32 = 0x0402;
+const PBM_DELTAPOS: u32 = 0x0403;
+const PBM_SETSTEP: u32 = 0x0404;
+const PBM_STEPIT: u32 = 0x0405;
+const PBM_SETRANGE32: u32 = 0x0406;
+const PBM_GETRANGE: u32 = 0x0407;
+const PBM_GETPOS: u32 = 0x0408;
+
+/// Window procedure for PROGRESS_CLASS.  Not a real export; it's the class's wndproc.
+#[win32_derive::dllexport]
+pub async fn retrowin32_progress_wndproc(
+    machine: &mut Machine,
+    hwnd: HWND,
+    msg: u32,
+    wParam: u32,
+    lParam: u32,
+) -> u32 {
+    let progress = machine.state.comctl32.progress.entry(hwnd).or_default();
+    let ret = match msg {
+        PBM_SETRANGE => {
+            let (min, max) = progress.set_range(lParam as u16 as i32, (lParam >> 16) as u16 as i32);
+            (max as u32) << 16 | (min as u32 & 0xFFFF)
+        }
+        PBM_SETRANGE32 => {
+            let (min, max) = progress.set_range(wParam as i32, lParam as i32);
+            (max as u32) << 16 | (min as u32 & 0xFFFF)
+        }
+        PBM_GETRANGE => {
+            if lParam != 0 {
+                // PBRANGE: iLow, iHigh.
+                let mem = machine.emu.memory.mem();
+                mem.put_pod::<i32>(lParam, progress.min);
+                mem.put_pod::<i32>(lParam + 4, progress.max);
+            }
+            let value = if wParam != 0 {
+                progress.min
+            } else {
+                progress.max
+            };
+            return value as u32;
+        }
+        PBM_SETPOS => progress.set_pos(wParam as i32 as i64) as u32,
+        PBM_DELTAPOS => progress.set_pos(progress.pos as i64 + wParam as i32 as i64) as u32,
+        PBM_SETSTEP => std::mem::replace(&mut progress.step, wParam as i32) as u32,
+        PBM_STEPIT => progress.step_it() as u32,
+        PBM_GETPOS => return progress.pos as u32,
+        _ => {
+            if msg == WM::DESTROY as u32 {
+                machine.state.comctl32.progress.remove(&hwnd);
+            }
+            let ret =
+                user32::DefWindowProcA(machine, hwnd, WM::try_from(msg), wParam, lParam).await;
+            if msg == WM::PAINT as u32 {
+                paint(machine, hwnd);
+            }
+            return ret;
+        }
+    };
+    paint(machine, hwnd);
+    ret
+}
+
+/// Height of a status bar, which always spans the bottom of its parent.
+const STATUS_HEIGHT: u32 = 20;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StatusBar {
+    /// Right edges of the parts, with -1 meaning the right edge of the bar.
+    parts: Vec<i32>,
+    texts: Vec<PartText>,
+}
+
+impl Default for StatusBar {
+    fn default() -> Self {
+        StatusBar {
+            parts: vec![-1],
+            texts: vec![PartText::default()],
+        }
+    }
+}
+
+/// What was set on a status bar part.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+enum PartText {
+    /// Text in the ANSI code page, as the app passed it.
+    Text(Vec<u8>),
+    /// A value for the app to draw the part from, set with SBT_OWNERDRAW.
+    OwnerDraw(u32),
+}
+
+impl Default for PartText {
+    fn default() -> Self {
+        PartText::Text(Vec::new())
+    }
+}
+
+impl StatusBar {
+    fn part_rects(&self, rect: &RECT) -> Vec<RECT> {
+        let mut left = rect.left;
+        self.parts
+            .iter()
+            .map(|&right| {
+                let right = if right < 0 {
+                    rect.right
+                } else {
+                    (rect.left + right).min(rect.right)
+                };
+                let part = RECT {
+                    left,
+                    right,
+                    ..*rect
+                };
+                left = right;
+                part
+            })
+            .collect()
+    }
+}
+
+/// Move a status bar to the bottom of its parent.
+fn dock_status(machine: &mut Machine, hwnd: HWND) {
+    let Some(window) = machine.state.user32.windows.get(hwnd) else {
+        return;
+    };
+    let Some(parent) = machine.state.user32.windows.get(window.parent) else {
+        return;
+    };
+    let (width, height) = (parent.width, parent.height);
+    let window = machine.state.user32.windows.get_mut(hwnd).unwrap();
+    window.x = 0;
+    window.y = height.saturating_sub(STATUS_HEIGHT) as i32;
+    window.width = width;
+    window.height = STATUS_HEIGHT;
+}
+
+const SB_SETTEXTA: u32 = 0x0401;
+const SB_GETTEXTA: u32 = 0x0402;
+const SB_GETTEXTLENGTHA: u32 = 0x0403;
+const SB_SETPARTS: u32 = 0x0404;
+const SB_GETPARTS: u32 = 0x0406;
+const SB_SETTEXTW: u32 = 0x040B;
+
+/// SB_SETTEXT drawing type: lParam is app data rather than text.
+const SBT_OWNERDRAW: u32 = 0x1000;
+
+/// Window procedure for STATUSCLASSNAME.  Not a real export; it's the class's wndproc.
+#[win32_derive::dllexport]
+pub async fn retrowin32_status_wndproc(
+    machine: &mut Machine,
+    hwnd: HWND,
+    msg: u32,
+    wParam: u32,
+    lParam: u32,
+) -> u32 {
+    let mem = machine.emu.memory.mem();
+    let status = machine.state.comctl32.status.entry(hwnd).or_default();
+    // The low byte of wParam is the part index, and the rest are drawing flags.
+    let part = (wParam & 0xFF) as usize;
+    match msg {
+        SB_SETTEXTA | SB_SETTEXTW => {
+            let Some(slot) = status.texts.get_mut(part) else {
+                return 0;
+            };
+            *slot = match msg {
+                _ if wParam & SBT_OWNERDRAW != 0 => PartText::OwnerDraw(lParam),
+                _ if lParam == 0 => PartText::default(),
+                SB_SETTEXTA => PartText::Text(mem.slicez(lParam).to_vec()),
+                _ => PartText::Text(
+                    unsafe { Str16::from_nul_term_ptr(mem, lParam) }
+                        .map(|s| s.buf().iter().map(|&c| to_ansi(c)).collect())
+                        .unwrap_or_default(),
+                ),
+            };
+            paint(machine, hwnd);
+            1
+        }
+        SB_GETTEXTA | SB_GETTEXTLENGTHA => {
+            let text = match status.texts.get(part) {
+                Some(PartText::Text(text)) => text,
+                // For owner-drawn parts, SB_GETTEXT returns the app's value instead.
+                Some(&PartText::OwnerDraw(data)) if msg == SB_GETTEXTA => return data,
+                _ => return 0,
+            };
+            if msg == SB_GETTEXTA && lParam != 0 {
+                let buf = mem.sub32_mut(lParam, text.len() as u32 + 1);
+                buf[..text.len()].copy_from_slice(text);
+                buf[text.len()] = 0;
+            }
+            text.len() as u32
+        }
+        SB_SETPARTS => {
+            let count = wParam.clamp(1, 256);
+            status.parts = mem.iter_pod::<i32>(lParam, count).collect();
+            status.texts.resize(count as usize, PartText::default());
+            paint(machine, hwnd);
+            1
+        }
+        SB_GETPARTS => {
+            let count = status.parts.len() as u32;
+            if lParam != 0 {
+                for (i, &right) in status.parts.iter().take(wParam as usize).enumerate() {
+                    mem.put_pod::<i32>(lParam + i as u32 * 4, right);
+                }
+            }
+            count
+        }
+        _ => {
+            if msg == WM::SETTEXT as u32 {
+                status.texts[0] = match lParam {
+                    0 => PartText::default(),
+                    _ => PartText::Text(mem.slicez(lParam).to_vec()),
+                };
+            }
+            if msg == WM::CREATE as u32 {
+                // The window name, e.g. from CreateStatusWindow, is the first part's text.
+                if let Some(window) = machine.state.user32.windows.get(hwnd) {
+                    let text = window.text.encode_utf16().map(to_ansi).collect();
+                    status.texts[0] = PartText::Text(text);
+                }
+            }
+            if msg == WM::CREATE as u32 || msg == WM::SIZE as u32 {
+                dock_status(machine, hwnd);
+            }
+            if msg == WM::DESTROY as u32 {
+                machine.state.comctl32.status.remove(&hwnd);
+            }
+            let ret =
+                user32::DefWindowProcA(machine, hwnd, WM::try_from(msg), wParam, lParam).await;
+            if msg == WM::PAINT as u32 || msg == WM::SETTEXT as u32 {
+                paint(machine, hwnd);
+            }
+            ret
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress() {
+        let mut p = Progress::default();
+        assert_eq!(p.set_pos(50), 0);
+        assert_eq!(p.filled(200), 100);
+        assert_eq!(p.set_pos(150), 50);
+        assert_eq!(p.pos, 100);
+        assert_eq!(p.set_range(10, 20), (0, 100));
+        assert_eq!(p.pos, 20);
+        p.set_pos(15);
+        p.step = 4;
+        p.step_it();
+        assert_eq!(p.pos, 19);
+        p.step_it();
+        assert_eq!(p.pos, 13);
+    }
+
+    #[test]
+    fn progress_full_range() {
+        let mut p = Progress::default();
+        p.set_range(i32::MIN, i32::MAX);
+        p.set_pos(i32::MAX as i64);
+        assert_eq!(p.filled(200), 200);
+        p.step = i32::MAX;
+        p.step_it();
+        assert_eq!(p.pos, -1);
+    }
+
+    #[test]
+    fn status_parts() {
+        let bar = StatusBar {
+            parts: vec![50, 120, -1],
+            texts: vec![PartText::default(); 3],
+        };
+        let rect = RECT {
+            left: 0,
+            top: 100,
+            right: 300,
+            bottom: 120,
+        };
+        let lefts: Vec<(i32, i32)> = bar
+            .part_rects(&rect)
+            .iter()
+            .map(|r| (r.left, r.right))
+            .collect();
+        assert_eq!(lefts, [(0, 50), (50, 120), (120, 300)]);
+    }
+}
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod wndproc_tests {
+    use super::*;
+    use crate::{
+        testing,
+        winapi::user32::{RemoveMsg, MSG},
+    };
+
+    /// A visible top-level window holding a progress bar and a status bar reading
+    /// "Ready", returning the three HWNDs.
+    fn controls(machine: &mut Machine) -> (HWND, HWND, HWND) {
+        InitCommonControls(machine);
+        let mut code = Vec::new();
+        testing::create_window(machine, &mut code, "parent", 0x9000_0000); // WS_POPUP | WS_VISIBLE
+        code.extend_from_slice(&[0x89, 0xc3]); // mov ebx, eax
+        testing::create_child_window(machine, &mut code, PROGRESS_CLASS, 1);
+        code.extend_from_slice(&[0x89, 0xc6]); // mov esi, eax
+                                               // CreateStatusWindowA(WS_CHILD | WS_VISIBLE, "Ready", ebx, 2)
+        testing::push(&mut code, 2);
+        code.push(0x53); // push ebx
+        let text = testing::alloc_data(machine, b"Ready\0");
+        testing::push(&mut code, text);
+        testing::push(&mut code, 0x5000_0000);
+        let create = get_symbol(machine, "comctl32.dll", "CreateStatusWindowA");
+        testing::call(&mut code, create);
+        code.extend_from_slice(&[0x89, 0xc7]); // mov edi, eax
+        testing::start_spinning(machine, code);
+
+        let regs = &machine.emu.x86.cpu().regs;
+        let [parent, progress, status] =
+            [x86::Register::EBX, x86::Register::ESI, x86::Register::EDI]
+                .map(|reg| HWND::from_raw(regs.get32(reg)));
+        assert!(!parent.is_null() && !progress.is_null() && !status.is_null());
+        (parent, progress, status)
+    }
+
+    fn progress_msg(machine: &mut Machine, hwnd: HWND, msg: u32, wParam: u32, lParam: u32) -> u32 {
+        testing::poll(retrowin32_progress_wndproc(
+            machine, hwnd, msg, wParam, lParam,
+        ))
+    }
+
+    fn status_msg(machine: &mut Machine, hwnd: HWND, msg: u32, wParam: u32, lParam: u32) -> u32 {
+        testing::poll(retrowin32_status_wndproc(
+            machine, hwnd, msg, wParam, lParam,
+        ))
+    }
+
+    #[test]
+    fn progress_messages() {
+        let mut machine = testing::machine();
+        let (_, progress, _) = controls(&mut machine);
+
+        assert_eq!(progress_msg(&mut machine, progress, PBM_SETPOS, 30, 0), 0);
+        assert_eq!(progress_msg(&mut machine, progress, PBM_DELTAPOS, 5, 0), 30);
+        assert_eq!(progress_msg(&mut machine, progress, PBM_GETPOS, 0, 0), 35);
+        assert_eq!(progress_msg(&mut machine, progress, PBM_SETPOS, 500, 0), 35);
+        assert_eq!(progress_msg(&mut machine, progress, PBM_GETPOS, 0, 0), 100);
+
+        // The extremes of the range don't overflow.
+        let (min, max) = (i32::MIN as u32, i32::MAX as u32);
+        progress_msg(&mut machine, progress, PBM_SETRANGE32, min, max);
+        progress_msg(&mut machine, progress, PBM_DELTAPOS, i32::MAX as u32, 0);
+        assert_eq!(progress_msg(&mut machine, progress, PBM_GETPOS, 0, 0), max);
+        progress_msg(&mut machine, progress, PBM_SETSTEP, i32::MAX as u32, 0);
+        progress_msg(&mut machine, progress, PBM_STEPIT, 0, 0);
+        assert_eq!(
+            progress_msg(&mut machine, progress, PBM_GETPOS, 0, 0),
+            -1i32 as u32
+        );
+        progress_msg(&mut machine, progress, PBM_DELTAPOS, i32::MIN as u32, 0);
+        progress_msg(&mut machine, progress, PBM_DELTAPOS, i32::MIN as u32, 0);
+        assert_eq!(progress_msg(&mut machine, progress, PBM_GETPOS, 0, 0), min);
+    }
+
+    #[test]
+    fn status_messages() {
+        let mut machine = testing::machine();
+        let (_, _, status) = controls(&mut machine);
+        let buf = testing::alloc_data(&mut machine, &[0; 16]);
+        let get_text = |machine: &mut Machine, part: u32| {
+            let len = status_msg(machine, status, SB_GETTEXTA, part, buf);
+            machine.mem().sub32(buf, len).to_vec()
+        };
+
+        // CreateStatusWindow's text is the first part's.
+        assert_eq!(get_text(&mut machine, 0), b"Ready");
+
+        // ANSI text comes back byte for byte.
+        let text = testing::alloc_data(&mut machine, b"caf\xe9\0");
+        assert_eq!(status_msg(&mut machine, status, SB_SETTEXTA, 0, text), 1);
+        assert_eq!(get_text(&mut machine, 0), b"caf\xe9");
+        let len = status_msg(&mut machine, status, SB_GETTEXTLENGTHA, 0, 0);
+        assert_eq!(len, 4);
+
+        let wide = testing::alloc_data(&mut machine, &[0xe9, 0, 0xAC, 0x20, 0, 0]); // "é€"
+        assert_eq!(status_msg(&mut machine, status, SB_SETTEXTW, 0, wide), 1);
+        assert_eq!(get_text(&mut machine, 0), b"\xe9\x80");
+
+        // Owner-drawn parts hold the app's value, which SB_GETTEXT returns.
+        let parts = testing::alloc_data(&mut machine, &[50, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(status_msg(&mut machine, status, SB_SETPARTS, 2, parts), 1);
+        let data = 0x1234_5678;
+        let ret = status_msg(&mut machine, status, SB_SETTEXTA, SBT_OWNERDRAW | 1, data);
+        assert_eq!(ret, 1);
+        assert_eq!(status_msg(&mut machine, status, SB_GETTEXTA, 1, 0), data);
+        assert_eq!(status_msg(&mut machine, status, SB_GETTEXTLENGTHA, 1, 0), 0);
+        assert_eq!(status_msg(&mut machine, status, SB_SETTEXTA, 2, text), 0);
+    }
+
+    /// Child windows repaint on their own WM_PAINT, after their parent's.
+    #[test]
+    fn child_paint_order() {
+        let mut machine = testing::machine();
+        let (parent, progress, status) = controls(&mut machine);
+        let mut paints = Vec::new();
+        let mut msg = MSG {
+            hwnd: HWND::null(),
+            message: 0,
+            wParam: 0,
+            lParam: 0,
+            time: 0,
+            pt_x: 0,
+            pt_y: 0,
+        };
+        let remove = Ok(RemoveMsg::PM_REMOVE);
+        while user32::PeekMessageA(&mut machine, Some(&mut msg), HWND::null(), 0, 0, remove) {
+            if msg.message == WM::PAINT as u32 {
+                paints.push(msg.hwnd);
+                // As BeginPaint would, so the window stops asking to be painted.
+                user32::ValidateRect(&mut machine, msg.hwnd, None);
+            }
+        }
+        assert_eq!(paints, [parent, progress, status]);
+    }
+}
@@ -1,12 +1,54 @@
 use super::DD_OK;
 use crate::{
-    winapi::{com::vtable, kernel32::get_symbol, types::HWND},
+    winapi::{
+        com::vtable,
+        ddraw,
+        types::{HWND, RECT},
+    },
     Machine,
 };
-use memory::ExtensionsMut;
+use memory::{Extensions, ExtensionsMut, Pod};
 
 const TRACE_CONTEXT: &'static str = "ddraw/clipper";
 
+const DDERR_REGIONTOOSMALL: u32 = 0x8876019A;
+
+/// The region an IDirectDrawClipper restricts blits to.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Clipper {
+    /// Window whose client area is the clip region, per SetHWnd.
+    pub hwnd: HWND,
+    /// Explicit clip list, per SetClipList.  Only used when there's no hwnd.
+    pub rects: Vec<RECT>,
+}
+
+impl Clipper {
+    /// The rects a blit may draw into, in surface coordinates.
+    pub fn region(&self, machine: &Machine) -> Vec<RECT> {
+        if self.hwnd.is_null() {
+            return self.rects.clone();
+        }
+        match machine.state.user32.windows.get(self.hwnd) {
+            // Surfaces are created at window size, so the client area maps directly onto it.
+            Some(window) => vec![window.client_rect()],
+            None => vec![],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct RGNDATAHEADER {
+    pub dwSize: u32,
+    pub iType: u32,
+    pub nCount: u32,
+    pub nRgnSize: u32,
+    pub rcBound: RECT,
+}
+unsafe impl Pod for RGNDATAHEADER {}
+
+const RDH_RECTANGLES: u32 = 1;
+
 #[win32_derive::dllexport]
 pub fn DirectDrawCreateClipper(
     machine: &mut Machine,
@@ -15,6 +57,7 @@ pub fn DirectDrawCreateClipper(
     pUnkOuter: u32,
 ) -> u32 {
     assert!(dwFlags == 0);
+    ddraw::init(machine);
     *lplpDDClipper.unwrap() = IDirectDrawClipper::new(machine);
     DD_OK
 }
@@ -25,32 +68,104 @@ pub mod IDirectDrawClipper {
 
     vtable![
         QueryInterface: todo,
-        AddRef: todo,
+        AddRef: ok,
         Release: ok,
 
-        GetClipList: todo,
-        GetHWnd: todo,
+        GetClipList: ok,
+        GetHWnd: ok,
         Initialize: todo,
         IsClipListChanged: todo,
-        SetClipList: todo,
+        SetClipList: ok,
         SetHWnd: ok,
     ];
 
     pub fn new(machine: &mut Machine) -> u32 {
-        let ddraw = &mut machine.state.ddraw;
-        let clipper = ddraw.heap.alloc(machine.emu.memory.mem(), 4);
-        let vtable = get_symbol(machine, "ddraw.dll", "IDirectDrawClipper");
-        machine.mem().put_pod::<u32>(clipper, vtable);
+        let clipper = ddraw::new_object(machine, "IDirectDrawClipper");
+        machine
+            .state
+            .ddraw
+            .clippers
+            .insert(clipper, Clipper::default());
         clipper
     }
 
     #[win32_derive::dllexport]
-    pub fn Release(_machine: &mut Machine, this: u32) -> u32 {
-        0 // TODO: return refcount?
+    pub fn AddRef(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::add_ref(machine, this)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Release(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::release(machine, this)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetClipList(
+        machine: &mut Machine,
+        this: u32,
+        lpRect: Option<&RECT>,
+        lpClipList: u32,
+        lpdwSize: Option<&mut u32>,
+    ) -> u32 {
+        let clipper = machine.state.ddraw.clippers.get(&this).unwrap();
+        let mut rects = clipper.region(machine);
+        if let Some(limit) = lpRect {
+            rects = rects
+                .iter()
+                .map(|r| r.intersect(limit))
+                .filter(|r| !r.is_empty())
+                .collect();
+        }
+        let header_size = std::mem::size_of::<RGNDATAHEADER>() as u32;
+        let rgn_size = (rects.len() * std::mem::size_of::<RECT>()) as u32;
+        let size = lpdwSize.unwrap();
+        if lpClipList == 0 {
+            *size = header_size + rgn_size;
+            return DD_OK;
+        }
+        if *size < header_size + rgn_size {
+            return DDERR_REGIONTOOSMALL;
+        }
+        let header = RGNDATAHEADER {
+            dwSize: header_size,
+            iType: RDH_RECTANGLES,
+            nCount: rects.len() as u32,
+            nRgnSize: rgn_size,
+            rcBound: rects.iter().fold(RECT::default(), |acc, r| acc.union(r)),
+        };
+        let mem = machine.mem();
+        mem.put_pod::<RGNDATAHEADER>(lpClipList, header);
+        for (i, rect) in rects.into_iter().enumerate() {
+            let addr = lpClipList + header_size + (i * std::mem::size_of::<RECT>()) as u32;
+            mem.put_pod::<RECT>(addr, rect);
+        }
+        DD_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetHWnd(machine: &mut Machine, this: u32, lphWnd: Option<&mut HWND>) -> u32 {
+        *lphWnd.unwrap() = machine.state.ddraw.clippers.get(&this).unwrap().hwnd;
+        DD_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn SetClipList(machine: &mut Machine, this: u32, lpClipList: u32, dwFlags: u32) -> u32 {
+        let rects = if lpClipList == 0 {
+            vec![]
+        } else {
+            let mem = machine.mem();
+            let header = mem.get_pod::<RGNDATAHEADER>(lpClipList);
+            mem.view_n::<RECT>(lpClipList + header.dwSize, header.nCount)
+                .to_vec()
+        };
+        let clipper = machine.state.ddraw.clippers.get_mut(&this).unwrap();
+        clipper.rects = rects;
+        DD_OK
     }
 
     #[win32_derive::dllexport]
-    pub fn SetHWnd(_machine: &mut Machine, this: u32, unused: u32, hwnd: HWND) -> u32 {
+    pub fn SetHWnd(machine: &mut Machine, this: u32, unused: u32, hwnd: HWND) -> u32 {
+        machine.state.ddraw.clippers.get_mut(&this).unwrap().hwnd = hwnd;
         DD_OK
     }
 }
@@ -6,10 +6,9 @@ use super::{
     DD_OK, GUID,
 };
 use crate::{
-    winapi::{com::vtable, ddraw, kernel32::get_symbol, types::*},
+    winapi::{com::vtable, ddraw, types::*},
     Machine,
 };
-use memory::ExtensionsMut;
 use memory::Pod;
 
 const TRACE_CONTEXT: &'static str = "ddraw/2";
@@ -21,54 +20,58 @@ pub const IID_IDirectDraw2: GUID = GUID {
     Data4: [0xa2, 0xde, 0x00, 0xaa, 0x00, 0xb9, 0x33, 0x56],
 };
 
+pub const IID_IDirectDrawSurface2: GUID = GUID {
+    Data1: 0x57805885,
+    Data2: 0x6eec,
+    Data3: 0x11cf,
+    Data4: [0x94, 0x41, 0xa8, 0x23, 0x03, 0xc1, 0x0e, 0x27],
+};
+
 #[win32_derive::dllexport]
 pub mod IDirectDraw2 {
     use super::*;
 
     vtable![
         QueryInterface: ok,
-        AddRef: todo,
+        AddRef: ok,
         Release: ok,
         Compact: todo,
-        CreateClipper: todo,
+        CreateClipper: (IDirectDraw7::CreateClipper),
         CreatePalette: (IDirectDraw7::CreatePalette),
         CreateSurface: ok,
         DuplicateSurface: todo,
         EnumDisplayModes: ok,
         EnumSurfaces: todo,
         FlipToGDISurface: todo,
-        GetCaps: todo,
+        GetCaps: (IDirectDraw7::GetCaps),
         GetDisplayMode: ok,
         GetFourCCCodes: todo,
         GetGDISurface: todo,
         GetMonitorFrequency: todo,
         GetScanLine: todo,
-        GetVerticalBlankStatus: todo,
-        Initialize: todo,
+        GetVerticalBlankStatus: (IDirectDraw7::GetVerticalBlankStatus),
+        Initialize: (IDirectDraw7::Initialize),
         RestoreDisplayMode: (IDirectDraw7::RestoreDisplayMode),
         SetCooperativeLevel: (IDirectDraw7::SetCooperativeLevel),
         SetDisplayMode: ok,
         WaitForVerticalBlank: (IDirectDraw7::WaitForVerticalBlank),
 
-        GetAvailableVidMem: todo,
+        // Takes a DDSCAPS rather than DDSCAPS2, but the caps are ignored anyway.
+        GetAvailableVidMem: (IDirectDraw7::GetAvailableVidMem),
     ];
 
     pub fn new(machine: &mut Machine) -> u32 {
-        let ddraw = &mut machine.state.ddraw;
-        let lpDirectDraw = ddraw.heap.alloc(machine.emu.memory.mem(), 4);
-        let vtable = get_symbol(machine, "ddraw.dll", "IDirectDraw2");
-        machine.mem().put_pod::<u32>(lpDirectDraw, vtable);
-        lpDirectDraw
+        ddraw::new_object(machine, "IDirectDraw2")
     }
 
     #[win32_derive::dllexport]
     pub fn QueryInterface(
-        _machine: &mut Machine,
+        machine: &mut Machine,
         this: u32,
         riid: Option<&GUID>,
-        ppvObject: u32,
+        ppvObject: Option<&mut u32>,
     ) -> u32 {
-        0x80004002 // E_NOINTERFACE
+        ddraw::query_interface(machine, this, &ddraw::DDRAW_INTERFACES, riid, ppvObject)
     }
 
     #[win32_derive::dllexport]
@@ -79,26 +82,12 @@ pub mod IDirectDraw2 {
         lplpDDSurface: Option<&mut u32>,
         pUnkOuter: u32,
     ) -> u32 {
-        let surfaces = ddraw::Surface::create(
+        ddraw::create_surfaces(
             machine,
-            machine.state.ddraw.hwnd,
             &DDSURFACEDESC2::from_desc(desc.unwrap()),
-        );
-        if surfaces.len() > 2 {
-            todo!()
-        }
-
-        let mut prev = 0;
-        for mut surface in surfaces.into_iter().rev() {
-            let ptr = IDirectDrawSurface2::new(machine);
-            surface.attached = prev;
-            machine.state.ddraw.surfaces.insert(ptr, surface);
-            prev = ptr;
-        }
-
-        *lplpDDSurface.unwrap() = prev;
-
-        DD_OK
+            IDirectDrawSurface2::new,
+            lplpDDSurface.unwrap(),
+        )
     }
 
     #[win32_derive::dllexport]
@@ -110,35 +99,24 @@ pub mod IDirectDraw2 {
         lpContext: u32,
         lpEnumCallback: u32,
     ) -> u32 {
-        if lpSurfaceDesc.is_some() {
-            todo!()
-        }
+        let filter = lpSurfaceDesc.map(DDSURFACEDESC2::from_desc);
+        let modes = ddraw::display_modes(dwFlags, filter.as_ref());
+
         let mem = machine.emu.memory.mem();
         let desc_addr = machine
             .state
             .ddraw
             .heap
             .alloc(mem, std::mem::size_of::<DDSURFACEDESC>() as u32);
-        let desc = mem.view_mut::<DDSURFACEDESC>(desc_addr);
-        *desc = DDSURFACEDESC::zeroed();
-        // TODO: offer multiple display modes rather than hardcoding this one.
-        desc.dwSize = std::mem::size_of::<DDSURFACEDESC>() as u32;
-        desc.dwWidth = 320;
-        desc.dwHeight = 200;
-        desc.ddpfPixelFormat = DDPIXELFORMAT {
-            dwSize: std::mem::size_of::<DDPIXELFORMAT>() as u32,
-            dwFlags: 0,
-            dwFourCC: 0,
-            dwRGBBitCount: 8,
-            dwRBitMask: 0xFF000000,
-            dwGBitMask: 0x00FF0000,
-            dwBBitMask: 0x0000FF00,
-            dwRGBAlphaBitMask: 0x000000FF,
-        };
-
-        machine
-            .call_x86(lpEnumCallback, vec![desc_addr, lpContext])
-            .await;
+        for mode in modes {
+            *machine.mem().view_mut::<DDSURFACEDESC>(desc_addr) = DDSURFACEDESC::from_desc2(&mode);
+            let ret = machine
+                .call_x86(lpEnumCallback, vec![desc_addr, lpContext])
+                .await;
+            if ret == ddraw::DDENUMRET_CANCEL {
+                break;
+            }
+        }
 
         machine
             .state
@@ -171,9 +149,13 @@ pub mod IDirectDraw2 {
     }
 
     #[win32_derive::dllexport]
-    pub fn Release(_machine: &mut Machine, this: u32) -> u32 {
-        log::warn!("{this:x}->Release()");
-        0 // TODO: return refcount?
+    pub fn AddRef(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::add_ref(machine, this)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Release(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::release(machine, this)
     }
 
     #[win32_derive::dllexport]
@@ -193,14 +175,14 @@ pub mod IDirectDrawSurface2 {
     use super::*;
 
     vtable![
-        QueryInterface: todo,
-        AddRef: todo,
+        QueryInterface: (IDirectDrawSurface7::QueryInterface),
+        AddRef: ok,
         Release: ok,
         AddAttachedSurface: todo,
         AddOverlayDirtyRect: todo,
         Blt: (IDirectDrawSurface7::Blt),
         BltBatch: todo,
-        BltFast: todo,
+        BltFast: (IDirectDrawSurface7::BltFast),
         DeleteAttachedSurface: todo,
         EnumAttachedSurfaces: todo,
         EnumOverlayZOrders: todo,
@@ -209,7 +191,7 @@ pub mod IDirectDrawSurface2 {
         GetBltStatus: todo,
         GetCaps: ok,
         GetClipper: todo,
-        GetColorKey: todo,
+        GetColorKey: (IDirectDrawSurface7::GetColorKey),
         GetDC: (IDirectDrawSurface7::GetDC),
         GetFlipStatus: todo,
         GetOverlayPosition: todo,
@@ -217,12 +199,12 @@ pub mod IDirectDrawSurface2 {
         GetPixelFormat: (IDirectDrawSurface7::GetPixelFormat),
         GetSurfaceDesc: ok,
         Initialize: todo,
-        IsLost: todo,
+        IsLost: (IDirectDrawSurface7::IsLost),
         Lock: ok,
         ReleaseDC: (IDirectDrawSurface7::ReleaseDC),
-        Restore: todo,
+        Restore: (IDirectDrawSurface7::Restore),
         SetClipper: (IDirectDrawSurface7::SetClipper),
-        SetColorKey: todo,
+        SetColorKey: (IDirectDrawSurface7::SetColorKey),
         SetOverlayPosition: todo,
         SetPalette: (IDirectDrawSurface7::SetPalette),
         Unlock: ok,
@@ -236,16 +218,17 @@ pub mod IDirectDrawSurface2 {
     ];
 
     pub fn new(machine: &mut Machine) -> u32 {
-        let ddraw = &mut machine.state.ddraw;
-        let lpDirectDrawSurface = ddraw.heap.alloc(machine.emu.memory.mem(), 4);
-        let vtable = get_symbol(machine, "ddraw.dll", "IDirectDrawSurface2");
-        machine.mem().put_pod::<u32>(lpDirectDrawSurface, vtable);
-        lpDirectDrawSurface
+        ddraw::new_object(machine, "IDirectDrawSurface2")
     }
 
     #[win32_derive::dllexport]
-    pub fn Release(_machine: &mut Machine, this: u32) -> u32 {
-        0
+    pub fn AddRef(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::add_ref(machine, this)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Release(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::release(machine, this)
     }
 
     #[win32_derive::dllexport]
@@ -255,10 +238,8 @@ pub mod IDirectDrawSurface2 {
         lpDDSCaps: Option<&DDSCAPS>,
         lpDirectDrawSurface: Option<&mut u32>,
     ) -> u32 {
-        // TODO: consider caps.
-        let surface = machine.state.ddraw.surfaces.get(&this).unwrap();
-        *lpDirectDrawSurface.unwrap() = surface.attached;
-        DD_OK
+        let caps = lpDDSCaps.copied().unwrap_or(DDSCAPS::empty());
+        ddraw::get_attached_surface(machine, this, caps, lpDirectDrawSurface)
     }
 
     #[win32_derive::dllexport]
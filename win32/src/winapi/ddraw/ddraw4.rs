@@ -0,0 +1,133 @@
+//! Implementation of DirectDraw4 interfaces, which take the same DDSURFACEDESC2 and
+//! DDSCAPS2 as the DirectDraw7 interfaces and so mostly forward to them.
+
+use super::{types::*, GUID};
+use crate::{
+    winapi::{com::vtable, ddraw},
+    Machine,
+};
+
+const TRACE_CONTEXT: &'static str = "ddraw/4";
+
+pub const IID_IDirectDraw4: GUID = GUID {
+    Data1: 0x9c59509a,
+    Data2: 0x39bd,
+    Data3: 0x11d1,
+    Data4: [0x8c, 0x4a, 0x00, 0xc0, 0x4f, 0xd9, 0x30, 0xc5],
+};
+
+pub const IID_IDirectDrawSurface4: GUID = GUID {
+    Data1: 0x0b2b8630,
+    Data2: 0xad35,
+    Data3: 0x11d0,
+    Data4: [0x8e, 0xa6, 0x00, 0x60, 0x97, 0x97, 0xea, 0x5b],
+};
+
+#[win32_derive::dllexport]
+pub mod IDirectDraw4 {
+    use super::*;
+
+    vtable![
+        QueryInterface: (IDirectDraw7::QueryInterface),
+        AddRef: (IDirectDraw7::AddRef),
+        Release: (IDirectDraw7::Release),
+        Compact: todo,
+        CreateClipper: (IDirectDraw7::CreateClipper),
+        CreatePalette: (IDirectDraw7::CreatePalette),
+        CreateSurface: ok,
+        DuplicateSurface: todo,
+        EnumDisplayModes: (IDirectDraw7::EnumDisplayModes),
+        EnumSurfaces: todo,
+        FlipToGDISurface: todo,
+        GetCaps: (IDirectDraw7::GetCaps),
+        GetDisplayMode: (IDirectDraw7::GetDisplayMode),
+        GetFourCCCodes: todo,
+        GetGDISurface: todo,
+        GetMonitorFrequency: todo,
+        GetScanLine: todo,
+        GetVerticalBlankStatus: (IDirectDraw7::GetVerticalBlankStatus),
+        Initialize: (IDirectDraw7::Initialize),
+        RestoreDisplayMode: (IDirectDraw7::RestoreDisplayMode),
+        SetCooperativeLevel: (IDirectDraw7::SetCooperativeLevel),
+        SetDisplayMode: (IDirectDraw7::SetDisplayMode),
+        WaitForVerticalBlank: (IDirectDraw7::WaitForVerticalBlank),
+        GetAvailableVidMem: (IDirectDraw7::GetAvailableVidMem),
+        GetSurfaceFromDC: todo,
+        RestoreAllSurfaces: todo,
+        TestCooperativeLevel: todo,
+        GetDeviceIdentifier: todo,
+    ];
+
+    #[win32_derive::dllexport]
+    pub fn CreateSurface(
+        machine: &mut Machine,
+        this: u32,
+        desc: Option<&DDSURFACEDESC2>,
+        lplpDDSurface: Option<&mut u32>,
+        pUnkOuter: u32,
+    ) -> u32 {
+        ddraw::create_surfaces(
+            machine,
+            desc.unwrap(),
+            IDirectDrawSurface4::new,
+            lplpDDSurface.unwrap(),
+        )
+    }
+}
+
+#[win32_derive::dllexport]
+pub mod IDirectDrawSurface4 {
+    use super::*;
+
+    vtable![
+        QueryInterface: (IDirectDrawSurface7::QueryInterface),
+        AddRef: (IDirectDrawSurface7::AddRef),
+        Release: (IDirectDrawSurface7::Release),
+        AddAttachedSurface: todo,
+        AddOverlayDirtyRect: todo,
+        Blt: (IDirectDrawSurface7::Blt),
+        BltBatch: todo,
+        BltFast: (IDirectDrawSurface7::BltFast),
+        DeleteAttachedSurface: todo,
+        EnumAttachedSurfaces: todo,
+        EnumOverlayZOrders: todo,
+        Flip: (IDirectDrawSurface7::Flip),
+        GetAttachedSurface: (IDirectDrawSurface7::GetAttachedSurface),
+        GetBltStatus: todo,
+        GetCaps: (IDirectDrawSurface7::GetCaps),
+        GetClipper: todo,
+        GetColorKey: (IDirectDrawSurface7::GetColorKey),
+        GetDC: (IDirectDrawSurface7::GetDC),
+        GetFlipStatus: todo,
+        GetOverlayPosition: todo,
+        GetPalette: todo,
+        GetPixelFormat: (IDirectDrawSurface7::GetPixelFormat),
+        GetSurfaceDesc: (IDirectDrawSurface7::GetSurfaceDesc),
+        Initialize: todo,
+        IsLost: (IDirectDrawSurface7::IsLost),
+        Lock: (IDirectDrawSurface7::Lock),
+        ReleaseDC: (IDirectDrawSurface7::ReleaseDC),
+        Restore: (IDirectDrawSurface7::Restore),
+        SetClipper: (IDirectDrawSurface7::SetClipper),
+        SetColorKey: (IDirectDrawSurface7::SetColorKey),
+        SetOverlayPosition: todo,
+        SetPalette: (IDirectDrawSurface7::SetPalette),
+        Unlock: (IDirectDrawSurface7::Unlock),
+        UpdateOverlay: todo,
+        UpdateOverlayDisplay: todo,
+        UpdateOverlayZOrder: todo,
+        GetDDInterface: todo,
+        PageLock: todo,
+        PageUnlock: todo,
+        SetSurfaceDesc: todo,
+        SetPrivateData: todo,
+        GetPrivateData: todo,
+        FreePrivateData: todo,
+        GetUniquenessValue: todo,
+        ChangeUniquenessValue: todo,
+    ];
+
+    pub fn new(machine: &mut Machine) -> u32 {
+        ddraw::new_object(machine, "IDirectDrawSurface4")
+    }
+}
@@ -1,10 +1,16 @@
 //! Implementation of DirectDraw7 interfaces.
 
-use super::{palette::IDirectDrawPalette, types::*, DD_OK};
+use super::{
+    clipper::IDirectDrawClipper,
+    palette::{IDirectDrawPalette, Palette},
+    types::*,
+    DD_OK,
+};
 pub use crate::winapi::com::GUID;
 use crate::{
-    winapi::{com::vtable, ddraw, kernel32::get_symbol, types::*},
-    Machine,
+    host,
+    winapi::{com::vtable, ddraw, kernel32, types::*},
+    Machine, SurfaceOptions,
 };
 use bitflags::bitflags;
 use memory::ExtensionsMut;
@@ -19,6 +25,17 @@ pub const IID_IDirectDraw7: GUID = GUID {
     Data4: [0xb9, 0x2f, 0x00, 0x60, 0x97, 0x97, 0xea, 0x5b],
 };
 
+pub const IID_IDirectDrawSurface7: GUID = GUID {
+    Data1: 0x06675a80,
+    Data2: 0x3b9b,
+    Data3: 0x11d2,
+    Data4: [0xb9, 0x2f, 0x00, 0x60, 0x97, 0x97, 0xea, 0x5b],
+};
+
+/// WaitForVerticalBlank flags.
+const DDWAITVB_BLOCKBEGIN: u32 = 0x1;
+const DDWAITVB_BLOCKEND: u32 = 0x4;
+
 bitflags! {
     pub struct DDSCL: u32 {
         const FULLSCREEN = 0x0001;
@@ -69,30 +86,30 @@ pub mod IDirectDraw7 {
     use super::*;
 
     vtable![
-        QueryInterface: todo,
-        AddRef: todo,
+        QueryInterface: ok,
+        AddRef: ok,
         Release: ok,
         Compact: todo,
-        CreateClipper: todo,
+        CreateClipper: ok,
         CreatePalette: ok,
         CreateSurface: ok,
         DuplicateSurface: todo,
         EnumDisplayModes: ok,
         EnumSurfaces: todo,
         FlipToGDISurface: todo,
-        GetCaps: todo,
+        GetCaps: ok,
         GetDisplayMode: ok,
         GetFourCCCodes: todo,
         GetGDISurface: todo,
         GetMonitorFrequency: todo,
         GetScanLine: todo,
-        GetVerticalBlankStatus: todo,
-        Initialize: todo,
+        GetVerticalBlankStatus: ok,
+        Initialize: ok,
         RestoreDisplayMode: ok,
         SetCooperativeLevel: ok,
         SetDisplayMode: ok,
         WaitForVerticalBlank: ok,
-        GetAvailableVidMem: todo,
+        GetAvailableVidMem: ok,
         GetSurfaceFromDC: todo,
         RestoreAllSurfaces: todo,
         TestCooperativeLevel: todo,
@@ -102,17 +119,80 @@ pub mod IDirectDraw7 {
     ];
 
     pub fn new(machine: &mut Machine) -> u32 {
-        let ddraw = &mut machine.state.ddraw;
-        let lpDirectDraw = ddraw.heap.alloc(machine.emu.memory.mem(), 4);
-        let vtable = get_symbol(machine, "ddraw.dll", "IDirectDraw7");
-        machine.mem().put_pod::<u32>(lpDirectDraw, vtable);
-        lpDirectDraw
+        ddraw::new_object(machine, "IDirectDraw7")
+    }
+
+    #[win32_derive::dllexport]
+    pub fn QueryInterface(
+        machine: &mut Machine,
+        this: u32,
+        riid: Option<&GUID>,
+        ppvObject: Option<&mut u32>,
+    ) -> u32 {
+        ddraw::query_interface(machine, this, &ddraw::DDRAW_INTERFACES, riid, ppvObject)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn AddRef(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::add_ref(machine, this)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Initialize(_machine: &mut Machine, this: u32, lpGUID: u32) -> u32 {
+        // Objects from CoCreateInstance need this call; there's only one device anyway.
+        DD_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Release(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::release(machine, this)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn CreateClipper(
+        machine: &mut Machine,
+        this: u32,
+        unused: u32,
+        lplpClipper: Option<&mut u32>,
+        reserved: u32,
+    ) -> u32 {
+        *lplpClipper.unwrap() = IDirectDrawClipper::new(machine);
+        DD_OK
     }
 
     #[win32_derive::dllexport]
-    pub fn Release(_machine: &mut Machine, this: u32) -> u32 {
-        log::warn!("{this:x}->Release()");
-        0 // TODO: return refcount?
+    pub fn GetCaps(machine: &mut Machine, this: u32, lpDDDriverCaps: u32, lpDDHELCaps: u32) -> u32 {
+        // Everything is emulated, but to the program the emulator is the hardware,
+        // so the driver and HEL caps are the same.
+        let caps = ddraw::caps(machine);
+        for addr in [lpDDDriverCaps, lpDDHELCaps] {
+            if addr != 0 {
+                let ret = ddraw::write_caps(machine, addr, &caps);
+                if ret != DD_OK {
+                    return ret;
+                }
+            }
+        }
+        DD_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetAvailableVidMem(
+        machine: &mut Machine,
+        this: u32,
+        lpDDSCaps2: Option<&DDSCAPS2>,
+        lpdwTotal: Option<&mut u32>,
+        lpdwFree: Option<&mut u32>,
+    ) -> u32 {
+        // All surfaces come from the same pool regardless of the requested caps.
+        let (total, free) = ddraw::video_memory(machine);
+        if let Some(lpdwTotal) = lpdwTotal {
+            *lpdwTotal = total;
+        }
+        if let Some(lpdwFree) = lpdwFree {
+            *lpdwFree = free;
+        }
+        DD_OK
     }
 
     #[win32_derive::dllexport]
@@ -125,18 +205,9 @@ pub mod IDirectDraw7 {
         unused: u32,
     ) -> u32 {
         let flags = flags.unwrap();
-        if !flags.contains(DDPCAPS::_8BIT) {
-            todo!();
-        }
-        // TODO: if palette is DDPCAPS_8BITENTRIES then SetEntries needs change too.
-
         let palette = IDirectDrawPalette::new(machine);
-        let entries = machine
-            .mem()
-            .view_n::<PALETTEENTRY>(entries, 256)
-            .to_vec()
-            .into_boxed_slice();
-        machine.state.ddraw.palettes.insert(palette, entries);
+        let contents = Palette::new(machine.emu.memory.mem(), flags, entries);
+        machine.state.ddraw.palettes.insert(palette, contents);
         machine.mem().put_pod::<u32>(lplpPalette, palette);
         DD_OK
     }
@@ -149,22 +220,12 @@ pub mod IDirectDraw7 {
         lpDirectDrawSurface7: Option<&mut u32>,
         unused: u32,
     ) -> u32 {
-        let surfaces = ddraw::Surface::create(machine, machine.state.ddraw.hwnd, desc.unwrap());
-        if surfaces.len() > 2 {
-            todo!()
-        }
-
-        let mut prev = 0;
-        for mut surface in surfaces.into_iter().rev() {
-            let ptr = IDirectDrawSurface7::new(machine);
-            surface.attached = prev;
-            machine.state.ddraw.surfaces.insert(ptr, surface);
-            prev = ptr;
-        }
-
-        *lpDirectDrawSurface7.unwrap() = prev;
-
-        DD_OK
+        ddraw::create_surfaces(
+            machine,
+            desc.unwrap(),
+            IDirectDrawSurface7::new,
+            lpDirectDrawSurface7.unwrap(),
+        )
     }
 
     #[win32_derive::dllexport]
@@ -176,9 +237,7 @@ pub mod IDirectDraw7 {
         lpContext: u32,
         lpEnumCallback: u32,
     ) -> u32 {
-        if lpSurfaceDesc.is_some() {
-            todo!()
-        }
+        let modes = ddraw::display_modes(dwFlags, lpSurfaceDesc);
 
         let mem = machine.emu.memory.mem();
         let desc_addr = machine
@@ -186,26 +245,15 @@ pub mod IDirectDraw7 {
             .ddraw
             .heap
             .alloc(mem, std::mem::size_of::<DDSURFACEDESC2>() as u32);
-        let desc = mem.view_mut::<DDSURFACEDESC2>(desc_addr);
-        *desc = DDSURFACEDESC2::zeroed();
-        // TODO: offer multiple display modes rather than hardcoding this one.
-        desc.dwSize = std::mem::size_of::<DDSURFACEDESC2>() as u32;
-        desc.dwWidth = 320;
-        desc.dwHeight = 200;
-        desc.ddpfPixelFormat = DDPIXELFORMAT {
-            dwSize: std::mem::size_of::<DDPIXELFORMAT>() as u32,
-            dwFlags: 0,
-            dwFourCC: 0,
-            dwRGBBitCount: 8,
-            dwRBitMask: 0xFF000000,
-            dwGBitMask: 0x00FF0000,
-            dwBBitMask: 0x0000FF00,
-            dwRGBAlphaBitMask: 0x000000FF,
-        };
-
-        machine
-            .call_x86(lpEnumCallback, vec![desc_addr, lpContext])
-            .await;
+        for mode in modes {
+            *machine.mem().view_mut::<DDSURFACEDESC2>(desc_addr) = mode;
+            let ret = machine
+                .call_x86(lpEnumCallback, vec![desc_addr, lpContext])
+                .await;
+            if ret == ddraw::DDENUMRET_CANCEL {
+                break;
+            }
+        }
 
         machine
             .state
@@ -229,8 +277,12 @@ pub mod IDirectDraw7 {
     }
 
     #[win32_derive::dllexport]
-    pub fn RestoreDisplayMode(_machine: &mut Machine, this: u32) -> u32 {
-        0
+    pub fn RestoreDisplayMode(machine: &mut Machine, this: u32) -> u32 {
+        machine.state.ddraw.refresh_rate = ddraw::DEFAULT_REFRESH_RATE;
+        if machine.state.ddraw.display_mode.take().is_some() {
+            ddraw::lose_surfaces(machine);
+        }
+        DD_OK
     }
 
     #[win32_derive::dllexport]
@@ -247,6 +299,10 @@ pub mod IDirectDraw7 {
             let window = machine.state.user32.windows.get_mut(hwnd).unwrap();
             window.expect_toplevel_mut().host.fullscreen();
         }
+        let exclusive = flags.contains(DDSCL::EXCLUSIVE);
+        if std::mem::replace(&mut machine.state.ddraw.exclusive, exclusive) != exclusive {
+            ddraw::lose_surfaces(machine);
+        }
         DD_OK
     }
 
@@ -260,6 +316,10 @@ pub mod IDirectDraw7 {
         refresh: u32,
         flags: u32,
     ) -> u32 {
+        if DDPIXELFORMAT::for_bpp(bpp).is_none() {
+            log::warn!("SetDisplayMode: unsupported depth {bpp}bpp");
+            return ddraw::DDERR_UNSUPPORTED;
+        }
         if let Some(wnd) = machine
             .state
             .user32
@@ -269,19 +329,50 @@ pub mod IDirectDraw7 {
             wnd.set_client_size(&mut *machine.host, width, height);
         }
         machine.state.ddraw.bytes_per_pixel = bpp / 8;
+        let mode = Some((width, height, bpp));
+        machine.state.ddraw.refresh_rate = if refresh != 0 {
+            refresh
+        } else {
+            ddraw::DEFAULT_REFRESH_RATE
+        };
+        if std::mem::replace(&mut machine.state.ddraw.display_mode, mode) != mode {
+            ddraw::lose_surfaces(machine);
+        }
         DD_OK
     }
 
     #[win32_derive::dllexport]
-    pub fn WaitForVerticalBlank(
-        _machine: &mut Machine,
+    pub async fn WaitForVerticalBlank(
+        machine: &mut Machine,
         this: u32,
         flags: u32,
         _unused: u32,
     ) -> u32 {
-        // TODO: effect.exe uses this to pace itself; actually sync to a clock here?
-        #[cfg(not(feature = "wasm"))]
-        std::thread::sleep(std::time::Duration::from_millis(10));
+        let (_, begin, end) = ddraw::vblank(machine);
+        let ms = match flags {
+            DDWAITVB_BLOCKBEGIN => begin,
+            DDWAITVB_BLOCKEND => end,
+            _ => {
+                log::warn!("WaitForVerticalBlank: unsupported flags {flags:x}");
+                return ddraw::DDERR_UNSUPPORTED;
+            }
+        };
+        // Sleep yields to the host while we wait.
+        kernel32::Sleep(machine, ms).await;
+        DD_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetVerticalBlankStatus(
+        machine: &mut Machine,
+        this: u32,
+        lpbIsInVB: Option<&mut u32>,
+    ) -> u32 {
+        let Some(lpbIsInVB) = lpbIsInVB else {
+            return ddraw::DDERR_INVALIDPARAMS;
+        };
+        let (in_vblank, _, _) = ddraw::vblank(machine);
+        *lpbIsInVB = in_vblank as u32;
         DD_OK
     }
 }
@@ -291,8 +382,8 @@ pub mod IDirectDrawSurface7 {
     use super::*;
 
     vtable![
-        QueryInterface: todo,
-        AddRef: todo,
+        QueryInterface: ok,
+        AddRef: ok,
         Release: ok,
         AddAttachedSurface: todo,
         AddOverlayDirtyRect: todo,
@@ -307,7 +398,7 @@ pub mod IDirectDrawSurface7 {
         GetBltStatus: todo,
         GetCaps: ok,
         GetClipper: todo,
-        GetColorKey: todo,
+        GetColorKey: ok,
         GetDC: ok,
         GetFlipStatus: todo,
         GetOverlayPosition: todo,
@@ -315,12 +406,12 @@ pub mod IDirectDrawSurface7 {
         GetPixelFormat: ok,
         GetSurfaceDesc: ok,
         Initialize: todo,
-        IsLost: todo,
+        IsLost: ok,
         Lock: ok,
         ReleaseDC: ok,
         Restore: ok,
         SetClipper: ok,
-        SetColorKey: todo,
+        SetColorKey: ok,
         SetOverlayPosition: todo,
         SetPalette: ok,
         Unlock: ok,
@@ -343,17 +434,27 @@ pub mod IDirectDrawSurface7 {
     ];
 
     pub fn new(machine: &mut Machine) -> u32 {
-        let ddraw = &mut machine.state.ddraw;
-        let lpDirectDrawSurface7 = ddraw.heap.alloc(machine.emu.memory.mem(), 4);
-        let vtable = get_symbol(machine, "ddraw.dll", "IDirectDrawSurface7");
-        machine.mem().put_pod::<u32>(lpDirectDrawSurface7, vtable);
-        lpDirectDrawSurface7
+        ddraw::new_object(machine, "IDirectDrawSurface7")
     }
 
     #[win32_derive::dllexport]
-    pub fn Release(_machine: &mut Machine, this: u32) -> u32 {
-        log::warn!("{this:x}->Release()");
-        0 // TODO: return refcount?
+    pub fn QueryInterface(
+        machine: &mut Machine,
+        this: u32,
+        riid: Option<&GUID>,
+        ppvObject: Option<&mut u32>,
+    ) -> u32 {
+        ddraw::query_interface(machine, this, &ddraw::SURFACE_INTERFACES, riid, ppvObject)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn AddRef(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::add_ref(machine, this)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Release(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::release(machine, this)
     }
 
     #[win32_derive::dllexport]
@@ -366,16 +467,36 @@ pub mod IDirectDrawSurface7 {
         flags: Result<DDBLT, u32>,
         lpDDBLTFX: Option<&DDBLTFX>,
     ) -> u32 {
-        if lpDstRect.is_some() || lpSrcRect.is_some() {
-            log::warn!("todo: Blt with rects");
+        let this = ddraw::object(machine, this);
+        let lpSrc = ddraw::object(machine, lpSrc);
+        if is_lost(machine, this) || (lpSrc != 0 && is_lost(machine, lpSrc)) {
+            return ddraw::DDERR_SURFACELOST;
         }
         let flags = flags.unwrap();
+        let dst_rect = match lpDstRect {
+            Some(rect) => *rect,
+            None => machine.state.ddraw.surfaces.get(&this).unwrap().rect(),
+        };
         if flags.contains(DDBLT::COLORFILL) {
-            log::warn!("todo: DDBLT::COLORFILL");
+            let Some(fx) = lpDDBLTFX else {
+                return ddraw::DDERR_INVALIDPARAMS;
+            };
+            fill(machine, this, &dst_rect, fx.fill);
             return DD_OK;
         }
-        log::warn!("Blt: ignoring behavioral flags");
-        BltFast(machine, this, 0, 0, lpSrc, None, 0)
+        let src_rect = match lpSrcRect {
+            Some(rect) => *rect,
+            None => machine.state.ddraw.surfaces.get(&lpSrc).unwrap().rect(),
+        };
+        let mut keys = DDBLTFAST::NOCOLORKEY;
+        if flags.contains(DDBLT::KEYSRC) {
+            keys |= DDBLTFAST::SRCCOLORKEY;
+        }
+        if flags.contains(DDBLT::KEYDEST) {
+            keys |= DDBLTFAST::DESTCOLORKEY;
+        }
+        blit(machine, this, &dst_rect, lpSrc, &src_rect, keys);
+        DD_OK
     }
 
     #[win32_derive::dllexport]
@@ -386,36 +507,307 @@ pub mod IDirectDrawSurface7 {
         y: u32,
         lpSrc: u32,
         lpRect: Option<&RECT>,
-        flags: u32,
+        flags: Result<DDBLTFAST, u32>,
     ) -> u32 {
-        if flags != 0 {
-            log::warn!("BltFast flags: {:x}", flags);
+        let this = ddraw::object(machine, this);
+        let lpSrc = ddraw::object(machine, lpSrc);
+        if is_lost(machine, this) || is_lost(machine, lpSrc) {
+            return ddraw::DDERR_SURFACELOST;
+        }
+        let flags = flags.unwrap();
+        let src_rect = match lpRect {
+            Some(rect) => *rect,
+            None => machine.state.ddraw.surfaces.get(&lpSrc).unwrap().rect(),
+        };
+        let (x, y) = (x as i32, y as i32);
+        let dst_rect = RECT {
+            left: x,
+            top: y,
+            right: x + (src_rect.right - src_rect.left),
+            bottom: y + (src_rect.bottom - src_rect.top),
+        };
+        blit(machine, this, &dst_rect, lpSrc, &src_rect, flags);
+        DD_OK
+    }
+
+    fn is_lost(machine: &Machine, surface: u32) -> bool {
+        machine.state.ddraw.surfaces.get(&surface).unwrap().lost
+    }
+
+    /// Fill dst_rect of this with a color in the surface's pixel format, within its clip region.
+    fn fill(machine: &mut Machine, this: u32, dst_rect: &RECT, color: u32) {
+        let surf = machine.state.ddraw.surfaces.get(&this).unwrap();
+        // The clip region is already within the surface's bounds.
+        let parts: Vec<RECT> = surf
+            .clip_region(machine)
+            .iter()
+            .map(|rect| rect.intersect(dst_rect))
+            .filter(|part| !part.is_empty())
+            .collect();
+        let (pitch, bpp) = (surf.pitch(), surf.bytes_per_pixel());
+        let pixels = ddraw::pixels(machine, this);
+        let color = &color.to_le_bytes()[..bpp as usize];
+        let mem = machine.emu.memory.mem();
+        for part in &parts {
+            for y in part.top as u32..part.bottom as u32 {
+                for x in part.left as u32..part.right as u32 {
+                    let addr = pixels + y * pitch + x * bpp;
+                    for (i, &b) in color.iter().enumerate() {
+                        mem.put_pod::<u8>(addr + i as u32, b);
+                    }
+                }
+            }
+        }
+        for part in &parts {
+            ddraw::flush_pixels(machine, this, Some(part));
+        }
+    }
+
+    /// Maps destination coordinates to source coordinates, nearest-neighbor,
+    /// for a blit from src_rect to dst_rect.
+    struct Scale<'a> {
+        dst: &'a RECT,
+        src: &'a RECT,
+    }
+
+    impl Scale<'_> {
+        fn x(&self, x: i32) -> i32 {
+            let (dw, sw) = (
+                self.dst.right - self.dst.left,
+                self.src.right - self.src.left,
+            );
+            self.src.left + (x - self.dst.left) * sw / dw
+        }
+
+        fn y(&self, y: i32) -> i32 {
+            let (dh, sh) = (
+                self.dst.bottom - self.dst.top,
+                self.src.bottom - self.src.top,
+            );
+            self.src.top + (y - self.dst.top) * sh / dh
+        }
+
+        /// The source rect that lands on part of the destination.
+        fn rect(&self, part: &RECT) -> RECT {
+            RECT {
+                left: self.x(part.left),
+                top: self.y(part.top),
+                right: self.x(part.right),
+                bottom: self.y(part.bottom),
+            }
+        }
+    }
+
+    /// Copy src_rect of lpSrc into dst_rect of this, stretching if their sizes differ,
+    /// and drawing only within the destination's clip region.
+    /// keys selects which color keys, if any, mask the copy.
+    fn blit(
+        machine: &mut Machine,
+        this: u32,
+        dst_rect: &RECT,
+        lpSrc: u32,
+        src_rect: &RECT,
+        keys: DDBLTFAST,
+    ) {
+        if dst_rect.is_empty() || src_rect.is_empty() {
+            return;
+        }
+        let scale = Scale {
+            dst: dst_rect,
+            src: src_rect,
+        };
+        let clip = machine
+            .state
+            .ddraw
+            .surfaces
+            .get(&this)
+            .unwrap()
+            .clip_region(machine);
+
+        let mut keys = keys & (DDBLTFAST::SRCCOLORKEY | DDBLTFAST::DESTCOLORKEY);
+        if !keys.is_empty() && machine.state.ddraw.buffer(lpSrc).pixels == 0 {
+            // Host surfaces can't be read back, so keying needs the source's x86 pixels.
+            log::warn!("color keyed blit from a surface never locked; ignoring keys");
+            keys = DDBLTFAST::NOCOLORKEY;
         }
+        if !keys.is_empty() {
+            ddraw::pixels(machine, this);
+            let mut runs = Vec::new();
+            for rect in clip {
+                let part = rect.intersect(dst_rect);
+                if part.is_empty() {
+                    continue;
+                }
+                keyed_blit(machine, this, &part, lpSrc, &scale, keys, &mut runs);
+            }
+            // The destination's x86 pixels may be stale where it was drawn to only on the
+            // host, so upload just the pixels the blit wrote.
+            for run in &runs {
+                ddraw::flush_pixels(machine, this, Some(run));
+            }
+            return;
+        }
+
+        // Blitting within a surface may overlap, so copy the source rect aside first.
+        let tmp = (lpSrc == this).then(|| copy_aside(machine, this, src_rect));
+        let tmp_rect = RECT {
+            left: 0,
+            top: 0,
+            right: src_rect.right - src_rect.left,
+            bottom: src_rect.bottom - src_rect.top,
+        };
+        let scale = Scale {
+            dst: dst_rect,
+            src: if tmp.is_some() { &tmp_rect } else { src_rect },
+        };
+
         let (dst, src) = unsafe {
-            let dst = machine.state.ddraw.surfaces.get_mut(&this).unwrap() as *mut ddraw::Surface;
-            let src = machine.state.ddraw.surfaces.get(&lpSrc).unwrap() as *const ddraw::Surface;
-            assert_ne!(dst as *const ddraw::Surface, src);
+            let dst = machine.state.ddraw.buffer_mut(this) as *mut ddraw::Buffer;
+            let src = match &tmp {
+                Some(tmp) => tmp.as_ref(),
+                None => machine.state.ddraw.buffer(lpSrc).host.as_ref(),
+            } as *const dyn host::Surface;
             (&mut *dst, &*src)
         };
-        if let Some(rect) = lpRect {
-            let sx = rect.left as u32;
-            let w = (rect.right - rect.left) as u32;
-            let sy = rect.top as u32;
-            let h = (rect.bottom - rect.top) as u32;
-            dst.host.bit_blt(x, y, src.host.as_ref(), sx, sy, w, h);
-        } else {
-            dst.host
-                .bit_blt(x, y, src.host.as_ref(), 0, 0, src.width, src.height);
+        for rect in clip {
+            let part = rect.intersect(dst_rect);
+            if part.is_empty() {
+                continue;
+            }
+            let src_part = scale.rect(&part);
+            let (w, h) = (part.right - part.left, part.bottom - part.top);
+            let (sw, sh) = (
+                src_part.right - src_part.left,
+                src_part.bottom - src_part.top,
+            );
+            if (w, h) == (sw, sh) {
+                dst.host.bit_blt(
+                    part.left as u32,
+                    part.top as u32,
+                    src,
+                    src_part.left as u32,
+                    src_part.top as u32,
+                    w as u32,
+                    h as u32,
+                );
+            } else {
+                dst.host.stretch_blt(
+                    part.left as u32,
+                    part.top as u32,
+                    w as u32,
+                    h as u32,
+                    src,
+                    src_part.left as u32,
+                    src_part.top as u32,
+                    sw as u32,
+                    sh as u32,
+                );
+            }
+        }
+    }
+
+    /// Copy a rect of a surface into a new host surface of its size.
+    fn copy_aside(machine: &mut Machine, this: u32, rect: &RECT) -> Box<dyn host::Surface> {
+        let opts = SurfaceOptions {
+            width: (rect.right - rect.left) as u32,
+            height: (rect.bottom - rect.top) as u32,
+            primary: false,
+        };
+        let mut tmp = machine.host.create_surface(0, &opts);
+        tmp.bit_blt(
+            0,
+            0,
+            machine.state.ddraw.buffer(this).host.as_ref(),
+            rect.left as u32,
+            rect.top as u32,
+            opts.width,
+            opts.height,
+        );
+        tmp
+    }
+
+    /// Copy pixels between the surfaces' x86 pixel buffers, skipping those masked by color keys:
+    /// source pixels within the source key, and destination pixels outside the destination key.
+    /// Appends the spans of each row that were written to runs.
+    fn keyed_blit(
+        machine: &mut Machine,
+        this: u32,
+        part: &RECT,
+        lpSrc: u32,
+        scale: &Scale,
+        keys: DDBLTFAST,
+        runs: &mut Vec<RECT>,
+    ) {
+        let ddraw = &machine.state.ddraw;
+        let dst = ddraw.surfaces.get(&this).unwrap();
+        let src = ddraw.surfaces.get(&lpSrc).unwrap();
+        let bpp = dst.bytes_per_pixel();
+        if src.bytes_per_pixel() != bpp {
+            log::warn!("todo: keyed blit between pixel formats");
+            return;
+        }
+        let src_key = src
+            .src_color_key
+            .filter(|_| keys.contains(DDBLTFAST::SRCCOLORKEY));
+        let dst_key = dst
+            .dst_color_key
+            .filter(|_| keys.contains(DDBLTFAST::DESTCOLORKEY));
+        let (src_pixels, dst_pixels) = (ddraw.buffer(lpSrc).pixels, ddraw.buffer(this).pixels);
+        let mem = machine.emu.memory.mem();
+        // Keys are compared against palette indices for paletted surfaces,
+        // and against the color channels (ignoring alpha) otherwise.
+        let color_mask = dst.pixel_format.color_mask();
+        let read = |addr: u32| ddraw::pixel_value(mem.slice(addr..addr + bpp)) & color_mask;
+        for y in part.top..part.bottom {
+            let sy = scale.y(y) as u32;
+            let mut run_start = None;
+            for x in part.left..part.right {
+                let sx = scale.x(x) as u32;
+                let src_addr = src_pixels + sy * src.pitch() + sx * bpp;
+                let dst_addr = dst_pixels + y as u32 * dst.pitch() + x as u32 * bpp;
+                let masked = src_key.map_or(false, |key| key.matches(read(src_addr)))
+                    || dst_key.map_or(false, |key| !key.matches(read(dst_addr)));
+                if masked {
+                    if let Some(left) = run_start.take() {
+                        runs.push(RECT {
+                            left,
+                            top: y,
+                            right: x,
+                            bottom: y + 1,
+                        });
+                    }
+                    continue;
+                }
+                mem.copy(src_addr, dst_addr, bpp);
+                run_start.get_or_insert(x);
+            }
+            if let Some(left) = run_start {
+                runs.push(RECT {
+                    left,
+                    top: y,
+                    right: part.right,
+                    bottom: y + 1,
+                });
+            }
         }
-        DD_OK
     }
 
     #[win32_derive::dllexport]
     pub fn Flip(machine: &mut Machine, this: u32, lpSurf: u32, flags: Result<DDFLIP, u32>) -> u32 {
-        let surface = machine.state.ddraw.surfaces.get(&this).unwrap();
-        let attached = surface.attached;
-        let back = machine.state.ddraw.surfaces.get_mut(&attached).unwrap();
-        back.host.show();
+        let this = ddraw::object(machine, this);
+        if lpSurf != 0 {
+            log::warn!("Flip: ignoring target surface {lpSurf:x}");
+        }
+        let ddraw = &mut machine.state.ddraw;
+        let surf = ddraw.surfaces.get_mut(&this).unwrap();
+        if surf.flip_chain.is_empty() {
+            return ddraw::DDERR_NOTFLIPPABLE;
+        }
+        // Rotate the buffers rather than their contents: each surface takes over the buffer of
+        // the one after it, so the front shows what was drawn into the first back buffer, and
+        // the last back buffer gets the old front buffer.
+        surf.flips = (surf.flips + 1) % (surf.flip_chain.len() + 1);
+        ddraw.buffer_mut(this).host.show();
         DD_OK
     }
 
@@ -426,10 +818,8 @@ pub mod IDirectDrawSurface7 {
         lpDDSCaps2: Option<&DDSCAPS2>,
         lpDirectDrawSurface7: Option<&mut u32>,
     ) -> u32 {
-        // TODO: consider caps.
-        let surface = machine.state.ddraw.surfaces.get(&this).unwrap();
-        *lpDirectDrawSurface7.unwrap() = surface.attached;
-        DD_OK
+        let caps = lpDDSCaps2.map_or(DDSCAPS::empty(), |caps| caps.dwCaps);
+        ddraw::get_attached_surface(machine, this, caps, lpDirectDrawSurface7)
     }
 
     #[win32_derive::dllexport]
@@ -439,6 +829,7 @@ pub mod IDirectDrawSurface7 {
 
     #[win32_derive::dllexport]
     pub fn GetDC(machine: &mut Machine, this: u32, lpHDC: u32) -> u32 {
+        let this = ddraw::object(machine, this);
         let dc =
             crate::winapi::gdi32::DC::new(crate::winapi::gdi32::DCTarget::DirectDrawSurface(this));
         let handle = machine.state.gdi32.dcs.add(dc);
@@ -448,22 +839,15 @@ pub mod IDirectDrawSurface7 {
 
     #[win32_derive::dllexport]
     pub fn GetPixelFormat(
-        _machine: &mut Machine,
+        machine: &mut Machine,
         this: u32,
         fmt: Option<&mut DDPIXELFORMAT>,
     ) -> u32 {
+        let this = ddraw::object(machine, this);
         let fmt = fmt.unwrap();
         assert!(fmt.dwSize == std::mem::size_of::<DDPIXELFORMAT>() as u32);
-        *fmt = DDPIXELFORMAT {
-            dwSize: std::mem::size_of::<DDPIXELFORMAT>() as u32,
-            dwFlags: 0x00000040,
-            dwFourCC: 0,
-            dwRGBBitCount: 32,
-            dwRBitMask: 0xFF00_0000,
-            dwGBitMask: 0x00FF_0000,
-            dwBBitMask: 0x0000_FF00,
-            dwRGBAlphaBitMask: 0x0000_00FF,
-        };
+        let surf = machine.state.ddraw.surfaces.get(&this).unwrap();
+        *fmt = surf.pixel_format.clone();
         DD_OK
     }
 
@@ -473,6 +857,7 @@ pub mod IDirectDrawSurface7 {
         this: u32,
         lpDesc: Option<&mut DDSURFACEDESC2>,
     ) -> u32 {
+        let this = ddraw::object(machine, this);
         let surf = machine.state.ddraw.surfaces.get(&this).unwrap();
         let desc = lpDesc.unwrap();
         assert!(desc.dwSize as usize == std::mem::size_of::<DDSURFACEDESC2>());
@@ -482,12 +867,9 @@ pub mod IDirectDrawSurface7 {
         desc.dwFlags.insert(DDSD::WIDTH);
         desc.dwHeight = surf.height;
         desc.dwFlags.insert(DDSD::HEIGHT);
-        desc.lPitch_dwLinearSize = surf.width * 4;
+        desc.lPitch_dwLinearSize = surf.pitch();
         desc.dwFlags.insert(DDSD::PITCH);
-
-        // TODO: a trace of a ddraw2 program had the result contain
-        // DDPF_RGB, r/g/b bitmasks
-        desc.ddpfPixelFormat.dwRGBBitCount = 32;
+        desc.ddpfPixelFormat = surf.pixel_format.clone();
         desc.dwFlags.insert(DDSD::PIXELFORMAT);
         DD_OK
     }
@@ -501,23 +883,30 @@ pub mod IDirectDrawSurface7 {
         flags: Result<DDLOCK, u32>,
         unused: u32,
     ) -> u32 {
-        if rect.is_some() {
-            // TODO: once we implement this, we need corresponding logic in Unlock.
-            // Note also ddraw1's Unlock has a different type than 7.
-            todo!();
+        let this = ddraw::object(machine, this);
+        let surf = machine.state.ddraw.surfaces.get(&this).unwrap();
+        if surf.lost {
+            return ddraw::DDERR_SURFACELOST;
         }
+        let rect = match rect {
+            Some(rect) => {
+                if rect.left > rect.right || rect.top > rect.bottom {
+                    return ddraw::DDERR_INVALIDRECT;
+                }
+                rect.intersect(&surf.rect())
+            }
+            None => surf.rect(),
+        };
         let desc = desc.unwrap();
+        let pixels = ddraw::pixels(machine, this);
         let surf = machine.state.ddraw.surfaces.get_mut(&this).unwrap();
-        if surf.pixels == 0 {
-            surf.pixels = machine.state.ddraw.heap.alloc(
-                machine.emu.memory.mem(),
-                surf.width * surf.height * machine.state.ddraw.bytes_per_pixel,
-            );
-        }
         // It seems callers (effect, monolife) don't provide flags for what they want,
         // and instead expect all fields to be included.
-        desc.lpSurface = surf.pixels;
-        desc.lPitch_dwLinearSize = surf.width * machine.state.ddraw.bytes_per_pixel;
+        // The pointer is to the rect's top-left, with rows still a full pitch apart.
+        desc.lpSurface =
+            pixels + rect.top as u32 * surf.pitch() + rect.left as u32 * surf.bytes_per_pixel();
+        desc.lPitch_dwLinearSize = surf.pitch();
+        surf.locked = Some(rect);
         DD_OK
     }
 
@@ -528,75 +917,130 @@ pub mod IDirectDrawSurface7 {
     }
 
     #[win32_derive::dllexport]
-    pub fn Restore(_machine: &mut Machine, _this: u32) -> u32 {
+    pub fn GetColorKey(
+        machine: &mut Machine,
+        this: u32,
+        flags: Result<DDCKEY, u32>,
+        key: Option<&mut DDCOLORKEY>,
+    ) -> u32 {
+        let this = ddraw::object(machine, this);
+        let flags = flags.unwrap();
+        let surf = machine.state.ddraw.surfaces.get(&this).unwrap();
+        let found = if flags.contains(DDCKEY::SRCBLT) {
+            surf.src_color_key
+        } else if flags.contains(DDCKEY::DESTBLT) {
+            surf.dst_color_key
+        } else {
+            log::warn!("GetColorKey: unsupported flags {flags:?}");
+            None
+        };
+        match found {
+            Some(found) => {
+                *key.unwrap() = found;
+                DD_OK
+            }
+            None => ddraw::DDERR_NOCOLORKEY,
+        }
+    }
+
+    #[win32_derive::dllexport]
+    pub fn IsLost(machine: &mut Machine, this: u32) -> u32 {
+        let this = ddraw::object(machine, this);
+        if is_lost(machine, this) {
+            ddraw::DDERR_SURFACELOST
+        } else {
+            DD_OK
+        }
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Restore(machine: &mut Machine, this: u32) -> u32 {
+        let this = ddraw::object(machine, this);
+        let hwnd = machine.state.ddraw.hwnd;
+        let surf = machine.state.ddraw.surfaces.get_mut(&this).unwrap();
+        if !surf.lost {
+            return DD_OK;
+        }
+        let opts = SurfaceOptions {
+            width: surf.width,
+            height: surf.height,
+            primary: surf.primary,
+        };
+        // As on Windows, the restored surface's contents are undefined.
+        surf.lost = false;
+        machine.state.ddraw.buffer_mut(this).host =
+            machine.host.create_surface(hwnd.to_raw(), &opts);
+        DD_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn SetClipper(machine: &mut Machine, this: u32, clipper: u32) -> u32 {
+        let this = ddraw::object(machine, this);
+        if clipper != 0 {
+            ddraw::add_ref(machine, clipper);
+        }
+        let surface = machine.state.ddraw.surfaces.get_mut(&this).unwrap();
+        let old = std::mem::replace(&mut surface.clipper, clipper);
+        if old != 0 {
+            ddraw::release(machine, old);
+        }
         DD_OK
     }
 
     #[win32_derive::dllexport]
-    pub fn SetClipper(_machine: &mut Machine, this: u32, clipper: u32) -> u32 {
-        // e.g. machine.state.ddraw.surfaces.get_mut(&this).unwrap().palette = palette;
+    pub fn SetColorKey(
+        machine: &mut Machine,
+        this: u32,
+        flags: Result<DDCKEY, u32>,
+        key: Option<&DDCOLORKEY>,
+    ) -> u32 {
+        let this = ddraw::object(machine, this);
+        let flags = flags.unwrap();
+        // Without COLORSPACE, the key is the single low value.
+        let key = key.map(|key| DDCOLORKEY {
+            dwColorSpaceLowValue: key.dwColorSpaceLowValue,
+            dwColorSpaceHighValue: if flags.contains(DDCKEY::COLORSPACE) {
+                key.dwColorSpaceHighValue
+            } else {
+                key.dwColorSpaceLowValue
+            },
+        });
+        let surf = machine.state.ddraw.surfaces.get_mut(&this).unwrap();
+        if flags.contains(DDCKEY::SRCBLT) {
+            surf.src_color_key = key;
+        } else if flags.contains(DDCKEY::DESTBLT) {
+            surf.dst_color_key = key;
+        } else {
+            log::warn!("SetColorKey: unsupported flags {flags:?}");
+        }
         DD_OK
     }
 
     #[win32_derive::dllexport]
     pub fn SetPalette(machine: &mut Machine, this: u32, palette: u32) -> u32 {
+        let this = ddraw::object(machine, this);
         machine.state.ddraw.surfaces.get_mut(&this).unwrap().palette = palette;
         machine.state.ddraw.palette_hack = palette;
         DD_OK
     }
 
     #[win32_derive::dllexport]
-    pub fn Unlock(machine: &mut Machine, this: u32, rect: Option<&mut RECT>) -> u32 {
+    pub fn Unlock(machine: &mut Machine, this: u32, rect: Option<&RECT>) -> u32 {
+        let this = ddraw::object(machine, this);
+        // The rect identifies which Lock this is, but we only track one lock per surface.
         let surf = machine.state.ddraw.surfaces.get_mut(&this).unwrap();
-        if let Some(rect) = rect {
-            // TODO: needs to match the rect passed in Lock.
-            rect.left = 0;
-            rect.top = 0;
-            rect.right = surf.width as i32;
-            rect.bottom = surf.height as i32;
-        }
-        assert!(surf.pixels != 0);
-        match machine.state.ddraw.bytes_per_pixel {
-            1 => {
-                let pixels = machine
-                    .emu
-                    .memory
-                    .mem()
-                    .view_n::<u8>(surf.pixels, surf.width * surf.height);
-                if let Some(palette) = machine
-                    .state
-                    .ddraw
-                    .palettes
-                    .get(&machine.state.ddraw.palette_hack)
-                {
-                    // XXX very inefficient
-                    let pixels32: Vec<_> = pixels
-                        .iter()
-                        .map(|&i| {
-                            let p = &palette[i as usize];
-                            [p.peRed, p.peGreen, p.peBlue, 255]
-                        })
-                        .collect();
-                    surf.host.write_pixels(&pixels32);
-                }
-            }
-            4 => {
-                let pixels = machine
-                    .emu
-                    .memory
-                    .mem()
-                    .view_n::<[u8; 4]>(surf.pixels, surf.width * surf.height);
-                // XXX setting alpha channel manually, very inefficient :(
-                let pixels32: Vec<_> = pixels.iter().map(|&[r, g, b, _a]| [r, g, b, 255]).collect();
-                surf.host.write_pixels(&pixels32);
+        let locked = surf.locked.take();
+        if let (Some(rect), Some(locked)) = (rect, locked) {
+            if !locked.contains(rect) {
+                log::warn!("Unlock: rect {rect:?} doesn't match locked {locked:?}");
             }
-            bpp => todo!("Unlock for {bpp}bpp"),
         }
+        ddraw::flush_pixels(machine, this, locked.as_ref());
 
         // If surface is primary then updates should show immediately.
         // XXX probably need something other than attached here
-        if surf.attached == 0 {
-            surf.host.show();
+        if machine.state.ddraw.surfaces[&this].attached == 0 {
+            machine.state.ddraw.buffer_mut(this).host.show();
         }
 
         DD_OK
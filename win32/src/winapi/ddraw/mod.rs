@@ -4,52 +4,112 @@
 mod clipper;
 mod ddraw1;
 mod ddraw2;
+mod ddraw4;
 mod ddraw7;
 mod palette;
 mod types;
 
 pub use crate::winapi::com::GUID;
+pub use clipper::Clipper;
 pub use clipper::DirectDrawCreateClipper;
 pub use clipper::IDirectDrawClipper;
 pub use ddraw1::*;
 pub use ddraw2::*;
+pub use ddraw4::*;
 pub use ddraw7::*;
-pub use palette::IDirectDrawPalette;
+pub use palette::{realize_gdi_palette, IDirectDrawPalette, Palette};
 
-use super::{heap::Heap, types::*};
+use super::{
+    com::{ComObject, IID_IUnknown, E_NOINTERFACE},
+    heap::Heap,
+    kernel32::get_symbol,
+    types::*,
+};
 use crate::{host, machine::Machine, SurfaceOptions};
+use memory::{Extensions, ExtensionsMut, Pod};
 use std::collections::HashMap;
 pub use types::*;
 
 const TRACE_CONTEXT: &'static str = "ddraw";
 
-pub struct Surface {
+/// The memory behind a surface, which Flip exchanges among a flipping chain's surfaces.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Buffer {
+    #[serde(skip, default = "crate::save_state::detached")]
     pub host: Box<dyn host::Surface>,
+    /// x86 address to pixel buffer, or 0 if unused.
+    pixels: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Surface {
+    /// The buffer this surface was created with, which after a Flip may be in use by
+    /// another surface of its chain; see State::buffer.
+    buffer: Buffer,
     pub width: u32,
     pub height: u32,
     pub palette: u32, // same as key in palettes
-    /// x86 address to pixel buffer, or 0 if unused.
-    pixels: u32,
-    /// Address of attached surface, e.g. back buffer.
+    pub pixel_format: DDPIXELFORMAT,
+    /// Address of attached surface: the next surface in a flipping chain's ring, or 0.
     attached: u32,
+    /// For the front surface of a flipping chain, its back buffers in order; they are owned by it.
+    flip_chain: Vec<u32>,
+    /// For the front surface of a flipping chain, the number of flips modulo the chain length.
+    /// Surface i of the chain, counting from the front, uses the buffer of surface i + flips.
+    flips: usize,
+    /// For a surface in a flipping chain, the front surface and this surface's index in the chain.
+    chain: Option<(u32, usize)>,
+    /// Address of clipper set by SetClipper, or 0 if none.
+    clipper: u32,
+    /// Whether the host surface was created as the primary.
+    primary: bool,
+    /// Set when a display mode change invalidates the surface, until Restore.
+    lost: bool,
+    /// Rect passed to Lock, to be uploaded on Unlock.
+    locked: Option<RECT>,
+    /// Color keys set by SetColorKey, in the surface's pixel format.
+    src_color_key: Option<DDCOLORKEY>,
+    dst_color_key: Option<DDCOLORKEY>,
 }
 
 impl Surface {
-    fn new(machine: &mut Machine, hwnd: HWND, opts: &SurfaceOptions) -> Self {
+    fn new(
+        machine: &mut Machine,
+        hwnd: HWND,
+        opts: &SurfaceOptions,
+        pixel_format: &DDPIXELFORMAT,
+    ) -> Self {
         if opts.width == 0 || opts.height == 0 {
             panic!("cannot create 0-sized surface");
         }
         Surface {
-            host: machine.host.create_surface(hwnd.to_raw(), &opts),
+            buffer: Buffer {
+                host: machine.host.create_surface(hwnd.to_raw(), &opts),
+                pixels: 0,
+            },
             width: opts.width,
             height: opts.height,
             palette: 0,
-            pixels: 0,
+            pixel_format: pixel_format.clone(),
             attached: 0,
+            flip_chain: Vec::new(),
+            flips: 0,
+            chain: None,
+            clipper: 0,
+            primary: opts.primary,
+            lost: false,
+            locked: None,
+            src_color_key: None,
+            dst_color_key: None,
         }
     }
 
-    pub fn create(machine: &mut Machine, hwnd: HWND, desc: &DDSURFACEDESC2) -> Vec<Surface> {
+    /// None if desc asks for a pixel format we can't hold, such as a zero bit count.
+    pub fn create(
+        machine: &mut Machine,
+        hwnd: HWND,
+        desc: &DDSURFACEDESC2,
+    ) -> Option<Vec<Surface>> {
         assert!(std::mem::size_of::<DDSURFACEDESC2>() == desc.dwSize as usize);
 
         let mut surfaces = Vec::new();
@@ -76,19 +136,69 @@ impl Surface {
             }
         }
 
-        surfaces.push(Surface::new(machine, hwnd, &opts));
+        // Primary surfaces are in the display format; others may ask for their own.
+        let pixel_format = if !opts.primary && desc.dwFlags.contains(DDSD::PIXELFORMAT) {
+            let format = &desc.ddpfPixelFormat;
+            if DDPIXELFORMAT::for_bpp(format.dwRGBBitCount).is_none() {
+                log::warn!(
+                    "CreateSurface: unsupported depth {}bpp",
+                    format.dwRGBBitCount
+                );
+                return None;
+            }
+            format.clone()
+        } else {
+            // SetDisplayMode only accepts depths we have a format for.
+            DDPIXELFORMAT::for_bpp(machine.state.ddraw.bytes_per_pixel * 8).unwrap()
+        };
+
+        surfaces.push(Surface::new(machine, hwnd, &opts, &pixel_format));
 
         if let Some(count) = desc.back_buffer_count() {
             opts.primary = false;
             for _ in 0..count {
-                surfaces.push(Surface::new(machine, hwnd, &opts));
+                surfaces.push(Surface::new(machine, hwnd, &opts, &pixel_format));
             }
         }
 
-        surfaces
+        Some(surfaces)
+    }
+
+    fn bytes_per_pixel(&self) -> u32 {
+        self.pixel_format.bytes_per_pixel()
+    }
+
+    fn pitch(&self) -> u32 {
+        self.width * self.bytes_per_pixel()
+    }
+
+    /// The full extent of the surface.
+    fn rect(&self) -> RECT {
+        RECT {
+            left: 0,
+            top: 0,
+            right: self.width as i32,
+            bottom: self.height as i32,
+        }
+    }
+
+    /// The rects a blit into this surface may touch: the surface bounds,
+    /// narrowed by the clipper's region if there is one.
+    fn clip_region(&self, machine: &Machine) -> Vec<RECT> {
+        let bounds = self.rect();
+        let Some(clipper) = machine.state.ddraw.clippers.get(&self.clipper) else {
+            return vec![bounds];
+        };
+        clipper
+            .region(machine)
+            .iter()
+            .map(|r| r.intersect(&bounds))
+            .filter(|r| !r.is_empty())
+            .collect()
     }
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct State {
     heap: Heap,
 
@@ -96,15 +206,57 @@ pub struct State {
     hwnd: HWND,
     pub surfaces: HashMap<u32, Surface>,
 
+    /// Depth of the display mode, per SetDisplayMode.
     bytes_per_pixel: u32,
+    /// (width, height, bpp) per SetDisplayMode, or None for the desktop mode.
+    display_mode: Option<(u32, u32, u32)>,
+    /// Refresh rate in Hz of the display mode, which drives the emulated vertical blank.
+    refresh_rate: u32,
+    /// Whether SetCooperativeLevel took exclusive (fullscreen) mode.
+    exclusive: bool,
 
-    palettes: HashMap<u32, Box<[PALETTEENTRY]>>,
+    palettes: HashMap<u32, Palette>,
     /// XXX monolife attaches palette only to back surface, then flips; we need to rearrange
     /// how surface flipping works for the palettes to work out, so this is hacked for now.
     palette_hack: u32,
+
+    clippers: HashMap<u32, Clipper>,
+
+    /// Interface pointers vended by QueryInterface for other versions of an object,
+    /// mapped to the object they are a view of.  State is keyed by the object.
+    interfaces: HashMap<u32, u32>,
 }
 
 impl State {
+    /// The current (width, height, bpp) of the screen, which is the desktop mode
+    /// unless SetDisplayMode changed it.
+    pub fn screen_mode(&self) -> (u32, u32, u32) {
+        self.display_mode.unwrap_or((640, 480, 32))
+    }
+
+    /// The surface whose buffer the given surface currently uses: itself, unless it's in
+    /// a flipping chain that has flipped.
+    fn buffer_owner(&self, surface: u32) -> u32 {
+        let Some((front, i)) = self.surfaces[&surface].chain else {
+            return surface;
+        };
+        let front_surf = &self.surfaces[&front];
+        match (i + front_surf.flips) % (front_surf.flip_chain.len() + 1) {
+            0 => front,
+            j => front_surf.flip_chain[j - 1],
+        }
+    }
+
+    /// The buffer a surface currently draws into and displays.
+    pub fn buffer(&self, surface: u32) -> &Buffer {
+        &self.surfaces[&self.buffer_owner(surface)].buffer
+    }
+
+    pub fn buffer_mut(&mut self, surface: u32) -> &mut Buffer {
+        let owner = self.buffer_owner(surface);
+        &mut self.surfaces.get_mut(&owner).unwrap().buffer
+    }
+
     pub fn new_init(machine: &mut Machine) -> Self {
         let mut ddraw = State::default();
         ddraw.heap = machine.state.kernel32.new_private_heap(
@@ -123,54 +275,817 @@ impl Default for State {
             hwnd: HWND::null(),
             surfaces: HashMap::new(),
             bytes_per_pixel: 4,
+            display_mode: None,
+            refresh_rate: DEFAULT_REFRESH_RATE,
+            exclusive: false,
             palettes: HashMap::new(),
             palette_hack: 0,
+            clippers: HashMap::new(),
+            interfaces: HashMap::new(),
+        }
+    }
+}
+
+/// Allocate a COM object with the named vtable in the ddraw heap.
+fn new_object(machine: &mut Machine, vtable: &str) -> u32 {
+    let vtable = get_symbol(machine, "ddraw.dll", vtable);
+    ComObject::alloc(
+        &mut machine.state.ddraw.heap,
+        machine.emu.memory.mem(),
+        vtable,
+    )
+}
+
+/// The object an interface pointer refers to, which is what state is keyed by.
+fn object(machine: &Machine, this: u32) -> u32 {
+    match machine.state.ddraw.interfaces.get(&this) {
+        Some(&obj) => obj,
+        None => this,
+    }
+}
+
+/// An interface pointer to obj with the given vtable, reusing an existing one if possible.
+/// Interfaces share the object's reference count; the caller is responsible for adding a reference.
+fn interface(machine: &mut Machine, obj: u32, vtable: u32) -> u32 {
+    let ddraw = &mut machine.state.ddraw;
+    let mem = machine.emu.memory.mem();
+    if mem.get_pod::<ComObject>(obj).vtable == vtable {
+        return obj;
+    }
+    let existing = ddraw
+        .interfaces
+        .iter()
+        .find(|&(&iface, &o)| o == obj && mem.get_pod::<ComObject>(iface).vtable == vtable);
+    if let Some((&iface, _)) = existing {
+        return iface;
+    }
+    let iface = ComObject::alloc(&mut ddraw.heap, mem, vtable);
+    ddraw.interfaces.insert(iface, obj);
+    iface
+}
+
+fn add_ref(machine: &mut Machine, this: u32) -> u32 {
+    let obj = object(machine, this);
+    ComObject::add_ref(machine.emu.memory.mem(), obj)
+}
+
+/// Shared Release for all DirectDraw objects.  Objects are keyed by their
+/// address, so at zero we can drop whatever state is associated with it.
+fn release(machine: &mut Machine, this: u32) -> u32 {
+    let this = object(machine, this);
+    let count = ComObject::release(machine.emu.memory.mem(), this);
+    if count > 0 {
+        return count;
+    }
+    let ddraw = &mut machine.state.ddraw;
+    let mem = machine.emu.memory.mem();
+    let mut owned = Vec::new();
+    if let Some(surface) = ddraw.surfaces.remove(&this) {
+        if surface.buffer.pixels != 0 {
+            ddraw.heap.free(mem, surface.buffer.pixels);
+        }
+        // A flipping chain's back buffers are owned by its front surface,
+        // and a surface holds a reference to its clipper.
+        owned = surface.flip_chain;
+        owned.push(surface.clipper);
+    }
+    ddraw.palettes.remove(&this);
+    ddraw.clippers.remove(&this);
+    ddraw.heap.free(mem, this);
+    ddraw.interfaces.retain(|&iface, &mut obj| {
+        if obj == this {
+            ddraw.heap.free(mem, iface);
+        }
+        obj != this
+    });
+    for obj in owned {
+        if obj != 0 {
+            release(machine, obj);
+        }
+    }
+    0
+}
+
+/// Create the surfaces described by desc with the given constructor, storing the front one
+/// in out.  A flipping chain's surfaces are linked in a ring via attached, front first.
+fn create_surfaces(
+    machine: &mut Machine,
+    desc: &DDSURFACEDESC2,
+    new: fn(&mut Machine) -> u32,
+    out: &mut u32,
+) -> u32 {
+    let Some(surfaces) = Surface::create(machine, machine.state.ddraw.hwnd, desc) else {
+        return DDERR_INVALIDPIXELFORMAT;
+    };
+    let addrs: Vec<u32> = surfaces.iter().map(|_| new(machine)).collect();
+    for (i, mut surface) in surfaces.into_iter().enumerate() {
+        if addrs.len() > 1 {
+            surface.attached = addrs[(i + 1) % addrs.len()];
+            surface.chain = Some((addrs[0], i));
+        }
+        if i == 0 {
+            surface.flip_chain = addrs[1..].to_vec();
+        }
+        machine.state.ddraw.surfaces.insert(addrs[i], surface);
+    }
+    *out = addrs[0];
+    DD_OK
+}
+
+/// GetAttachedSurface for all versions.  Caps of BACKBUFFER or FRONTBUFFER find that buffer
+/// of this surface's flipping chain; otherwise it's the next surface in the ring.
+fn get_attached_surface(
+    machine: &mut Machine,
+    this: u32,
+    caps: DDSCAPS,
+    lpDDSurface: Option<&mut u32>,
+) -> u32 {
+    let Some(lpDDSurface) = lpDDSurface else {
+        return DDERR_INVALIDPARAMS;
+    };
+    // The attached surface is returned as the same interface version as this.
+    let vtable = machine.mem().get_pod::<u32>(this);
+    let this = object(machine, this);
+    let surfaces = &machine.state.ddraw.surfaces;
+    let next = surfaces[&this].attached;
+    if next == 0 {
+        *lpDDSurface = 0;
+        return DDERR_NOTFOUND;
+    }
+    let found = if caps.intersects(DDSCAPS::BACKBUFFER | DDSCAPS::FRONTBUFFER) {
+        // Walk the ring to the front surface, which is the one holding the chain.
+        let mut front = this;
+        while surfaces[&front].flip_chain.is_empty() {
+            front = surfaces[&front].attached;
+        }
+        if caps.contains(DDSCAPS::BACKBUFFER) {
+            surfaces[&front].attached
+        } else {
+            front
+        }
+    } else {
+        next
+    };
+    add_ref(machine, found);
+    *lpDDSurface = interface(machine, found, vtable);
+    DD_OK
+}
+
+/// Mark all surfaces lost, as happens on a display mode change.
+fn lose_surfaces(machine: &mut Machine) {
+    for surf in machine.state.ddraw.surfaces.values_mut() {
+        surf.lost = true;
+    }
+}
+
+/// The x86 address of a surface's pixel buffer, allocating it on first use.
+fn pixels(machine: &mut Machine, this: u32) -> u32 {
+    let ddraw = &mut machine.state.ddraw;
+    let surf = &ddraw.surfaces[&this];
+    let size = surf.pitch() * surf.height;
+    let owner = ddraw.buffer_owner(this);
+    let buffer = &mut ddraw.surfaces.get_mut(&owner).unwrap().buffer;
+    if buffer.pixels == 0 {
+        buffer.pixels = ddraw.heap.alloc(machine.emu.memory.mem(), size);
+    }
+    buffer.pixels
+}
+
+/// Copy a rect (or all) of a surface's x86 pixel buffer to its host surface.
+fn flush_pixels(machine: &mut Machine, this: u32, rect: Option<&RECT>) {
+    let ddraw = &machine.state.ddraw;
+    let surf = &ddraw.surfaces[&this];
+    let surf_pixels = ddraw.buffer(this).pixels;
+    assert!(surf_pixels != 0);
+    let rect = match rect {
+        Some(rect) => rect.intersect(&surf.rect()),
+        None => surf.rect(),
+    };
+    if rect.is_empty() {
+        return;
+    }
+    let bpp = surf.bytes_per_pixel();
+    let (x, y) = (rect.left as u32, rect.top as u32);
+    let (w, h) = (
+        (rect.right - rect.left) as u32,
+        (rect.bottom - rect.top) as u32,
+    );
+    let mem = machine.emu.memory.mem();
+    let rows = (y..y + h).map(|row| {
+        let start = surf_pixels + row * surf.pitch() + x * bpp;
+        mem.slice(start..start + w * bpp)
+    });
+    // XXX very inefficient
+    let pixels32: Vec<_> = if surf.pixel_format.is_palettized() {
+        // Without a DirectDraw palette, fall back to the one realized via GDI, so
+        // that both agree on the screen's colors.
+        let palette = match machine
+            .state
+            .ddraw
+            .palettes
+            .get(&machine.state.ddraw.palette_hack)
+        {
+            Some(palette) => &palette.entries,
+            None => &machine.state.gdi32.screen_palette,
+        };
+        if palette.is_empty() {
+            return;
+        }
+        rows.flatten()
+            .map(|&i| {
+                palette
+                    .get(i as usize)
+                    .map_or([0, 0, 0, 255], |p| p.to_rgba())
+            })
+            .collect()
+    } else {
+        rows.flat_map(|row| row.chunks_exact(bpp as usize))
+            .map(|px| surf.pixel_format.to_rgba(pixel_value(px)))
+            .collect()
+    };
+    let whole = (w, h) == (surf.width, surf.height);
+    let host = &mut machine.state.ddraw.buffer_mut(this).host;
+    if whole {
+        host.write_pixels(&pixels32);
+    } else {
+        host.write_pixels_rect(x, y, w, h, &pixels32);
+    }
+}
+
+/// Give the surfaces of a state loaded by restore_state() new host surfaces, uploading the
+/// pixels held in x86 memory.  Surfaces only ever drawn to by host-side blits come back
+/// blank until the program redraws them.
+pub fn reattach_surfaces(machine: &mut Machine) {
+    let ddraw = &mut machine.state.ddraw;
+    if ddraw.exclusive {
+        if let Some(window) = machine.state.user32.windows.get_mut(ddraw.hwnd) {
+            window.expect_toplevel_mut().host.fullscreen();
+        }
+    }
+    let addrs: Vec<u32> = ddraw.surfaces.keys().copied().collect();
+    for surf in ddraw.surfaces.values_mut() {
+        surf.buffer.host = machine.host.create_surface(
+            ddraw.hwnd.to_raw(),
+            &SurfaceOptions {
+                width: surf.width,
+                height: surf.height,
+                primary: surf.primary,
+            },
+        );
+    }
+    for addr in addrs {
+        if machine.state.ddraw.buffer(addr).pixels != 0 {
+            flush_pixels(machine, addr, None);
+        }
+        if machine.state.ddraw.surfaces[&addr].primary {
+            machine.state.ddraw.buffer_mut(addr).host.show();
+        }
+    }
+}
+
+/// Display modes offered by EnumDisplayModes, as (width, height).
+const DISPLAY_MODES: [(u32, u32); 4] = [(320, 200), (640, 480), (800, 600), (1024, 768)];
+/// Depths offered for each display mode.
+const DISPLAY_BPPS: [u32; 3] = [8, 16, 32];
+
+/// Refresh rate of every display mode, unless SetDisplayMode asks for another.
+const DEFAULT_REFRESH_RATE: u32 = 60;
+
+/// EnumDisplayModes flag: report refresh rates.
+const DDEDM_REFRESHRATES: u32 = 1;
+/// Callback return value to stop enumeration.
+const DDENUMRET_CANCEL: u32 = 0;
+
+/// The display modes EnumDisplayModes reports, restricted to those matching
+/// the fields set in filter.
+fn display_modes(dwFlags: u32, filter: Option<&DDSURFACEDESC2>) -> Vec<DDSURFACEDESC2> {
+    let mut modes = Vec::new();
+    for (width, height) in DISPLAY_MODES {
+        for bpp in DISPLAY_BPPS {
+            let mut desc = DDSURFACEDESC2::default();
+            desc.dwFlags = DDSD::WIDTH | DDSD::HEIGHT | DDSD::PITCH | DDSD::PIXELFORMAT;
+            desc.dwWidth = width;
+            desc.dwHeight = height;
+            desc.lPitch_dwLinearSize = width * bpp / 8;
+            desc.ddpfPixelFormat = DDPIXELFORMAT::for_bpp(bpp).unwrap();
+            if dwFlags & DDEDM_REFRESHRATES != 0 {
+                desc.dwFlags |= DDSD::REFRESHRATE;
+                desc.dwMipMapCount_dwRefreshRate_dwSrcVBHandle = DEFAULT_REFRESH_RATE;
+            }
+
+            if let Some(filter) = filter {
+                if filter.dwFlags.contains(DDSD::WIDTH) && filter.dwWidth != width {
+                    continue;
+                }
+                if filter.dwFlags.contains(DDSD::HEIGHT) && filter.dwHeight != height {
+                    continue;
+                }
+                if filter.dwFlags.contains(DDSD::PIXELFORMAT)
+                    && filter.ddpfPixelFormat.dwRGBBitCount != bpp
+                {
+                    continue;
+                }
+                if filter.dwFlags.contains(DDSD::REFRESHRATE)
+                    && filter.dwMipMapCount_dwRefreshRate_dwSrcVBHandle != 0
+                    && filter.dwMipMapCount_dwRefreshRate_dwSrcVBHandle != DEFAULT_REFRESH_RATE
+                {
+                    continue;
+                }
+            }
+            modes.push(desc);
         }
     }
+    modes
+}
+
+/// The vertical blanking interval is the first 1/VBLANK_FRACTION of each frame.
+/// It must last at least a tick at any refresh rate, or pollers could miss it.
+const VBLANK_FRACTION: u64 = 10;
+
+/// The emulated vertical blank, timed from the host clock with frames starting at
+/// multiples of the refresh period.  Returns whether we're in the blanking interval,
+/// and the ms until the next one begins and until the current (or next) one ends.
+fn vblank(machine: &Machine) -> (bool, u32, u32) {
+    let hz = machine.state.ddraw.refresh_rate as u64;
+    // Position within the current frame, in thousandths of a frame.
+    let pos = machine.host.ticks() as u64 * hz % 1000;
+    let blank = 1000 / VBLANK_FRACTION;
+    let in_vblank = pos < blank;
+    // Ceiling division so that waiting that long never leaves us short of the target.
+    let ms = |frames: u64| ((frames - pos + hz - 1) / hz) as u32;
+    let begin = ms(1000);
+    let end = if in_vblank {
+        ms(blank)
+    } else {
+        ms(1000 + blank)
+    };
+    (in_vblank, begin, end)
+}
+
+/// Video memory we claim to have.
+const VIDEO_MEMORY: u32 = 16 << 20;
+
+/// (total, free) video memory, counting surfaces as occupying it.
+fn video_memory(machine: &Machine) -> (u32, u32) {
+    let used: u32 = machine
+        .state
+        .ddraw
+        .surfaces
+        .values()
+        .map(|surf| surf.pitch() * surf.height)
+        .sum();
+    (VIDEO_MEMORY, VIDEO_MEMORY.saturating_sub(used))
+}
+
+/// The capabilities we implement, for GetCaps.  Overlays, FourCC formats,
+/// z-buffers and alpha blending are deliberately absent.
+fn caps(machine: &Machine) -> DDCAPS {
+    let mut caps = DDCAPS::zeroed();
+    caps.dwSize = std::mem::size_of::<DDCAPS>() as u32;
+    caps.dwCaps = DDCAPS_BLT
+        | DDCAPS_BLTSTRETCH
+        | DDCAPS_BLTCOLORFILL
+        | DDCAPS_COLORKEY
+        | DDCAPS_PALETTE
+        | DDCAPS_CANCLIP
+        | DDCAPS_CANCLIPSTRETCHED
+        | DDCAPS_CANBLTSYSMEM;
+    caps.dwCKeyCaps = DDCKEYCAPS_SRCBLT
+        | DDCKEYCAPS_SRCBLTCLRSPACE
+        | DDCKEYCAPS_DESTBLT
+        | DDCKEYCAPS_DESTBLTCLRSPACE;
+    caps.dwFXCaps =
+        DDFXCAPS_BLTSHRINKX | DDFXCAPS_BLTSHRINKY | DDFXCAPS_BLTSTRETCHX | DDFXCAPS_BLTSTRETCHY;
+    caps.dwPalCaps = (DDPCAPS::_1BIT
+        | DDPCAPS::_2BIT
+        | DDPCAPS::_4BIT
+        | DDPCAPS::_8BIT
+        | DDPCAPS::_8BITENTRIES
+        | DDPCAPS::PRIMARYSURFACE)
+        .bits();
+    (caps.dwVidMemTotal, caps.dwVidMemFree) = video_memory(machine);
+    let surface_caps = DDSCAPS::BACKBUFFER
+        | DDSCAPS::COMPLEX
+        | DDSCAPS::FLIP
+        | DDSCAPS::FRONTBUFFER
+        | DDSCAPS::OFFSCREENPLAIN
+        | DDSCAPS::PALETTE
+        | DDSCAPS::PRIMARYSURFACE
+        | DDSCAPS::SYSTEMMEMORY
+        | DDSCAPS::VIDEOMEMORY;
+    caps.ddsOldCaps = surface_caps;
+    caps.ddsCaps.dwCaps = surface_caps;
+    caps
+}
+
+/// Copy caps into a caller's DDCAPS, which may be an older and smaller version per its dwSize.
+fn write_caps(machine: &mut Machine, addr: u32, caps: &DDCAPS) -> u32 {
+    let mem = machine.emu.memory.mem();
+    let size = mem.get_pod::<u32>(addr);
+    if size == 0 || size > std::mem::size_of::<DDCAPS>() as u32 {
+        return DDERR_INVALIDPARAMS;
+    }
+    let bytes =
+        unsafe { std::slice::from_raw_parts(caps as *const DDCAPS as *const u8, size as usize) };
+    mem.sub32_mut(addr, size).copy_from_slice(bytes);
+    // dwSize stays as the caller set it.
+    mem.put_pod::<u32>(addr, size);
+    DD_OK
 }
 
 const DD_OK: u32 = 0;
+const CLASS_E_NOAGGREGATION: u32 = 0x80040110;
 // DD error codes are generated with this MAKE_HRESULT macro, maybe it doesn't matter too much.
-const DDERR_GENERIC: u32 = 0x80004005;
+const DDERR_INVALIDPARAMS: u32 = 0x80070057;
+const DDERR_INVALIDPIXELFORMAT: u32 = 0x88760091;
+const DDERR_INVALIDRECT: u32 = 0x88760096;
+const DDERR_NOCOLORKEY: u32 = 0x887600D7;
+const DDERR_NOTFOUND: u32 = 0x887600FF;
+const DDERR_NOTFLIPPABLE: u32 = 0x88760246;
+const DDERR_SURFACELOST: u32 = 0x887601C2;
+const DDERR_UNSUPPORTED: u32 = 0x80004001;
+
+/// Set up the ddraw state on the first call into the DLL.
+fn init(machine: &mut Machine) {
+    if machine.state.ddraw.heap.addr == 0 {
+        machine.state.ddraw = State::new_init(machine);
+    }
+}
+
+/// The versions of IDirectDraw, by IID and vtable name.
+const DDRAW_INTERFACES: [(&GUID, &str); 4] = [
+    (&ddraw1::IID_IDirectDraw, "IDirectDraw"),
+    (&ddraw2::IID_IDirectDraw2, "IDirectDraw2"),
+    (&ddraw4::IID_IDirectDraw4, "IDirectDraw4"),
+    (&ddraw7::IID_IDirectDraw7, "IDirectDraw7"),
+];
+
+/// The versions of IDirectDrawSurface, by IID and vtable name.
+const SURFACE_INTERFACES: [(&GUID, &str); 4] = [
+    (&ddraw1::IID_IDirectDrawSurface, "IDirectDrawSurface"),
+    (&ddraw2::IID_IDirectDrawSurface2, "IDirectDrawSurface2"),
+    (&ddraw4::IID_IDirectDrawSurface4, "IDirectDrawSurface4"),
+    (&ddraw7::IID_IDirectDrawSurface7, "IDirectDrawSurface7"),
+];
+
+/// QueryInterface across the versions of an interface, which are all views of the same object.
+fn query_interface(
+    machine: &mut Machine,
+    this: u32,
+    versions: &[(&GUID, &str)],
+    riid: Option<&GUID>,
+    ppvObject: Option<&mut u32>,
+) -> u32 {
+    let (Some(riid), Some(ppvObject)) = (riid, ppvObject) else {
+        return DDERR_INVALIDPARAMS;
+    };
+    if *riid == IID_IUnknown {
+        // COM identity: IUnknown is the same pointer whichever interface it's asked of,
+        // so hand out the object itself.
+        let obj = object(machine, this);
+        add_ref(machine, obj);
+        *ppvObject = obj;
+        return DD_OK;
+    }
+    let Some(&(_, name)) = versions.iter().find(|(iid, _)| *iid == riid) else {
+        *ppvObject = 0;
+        return E_NOINTERFACE;
+    };
+    let vtable = get_symbol(machine, "ddraw.dll", name);
+    let obj = object(machine, this);
+    add_ref(machine, obj);
+    *ppvObject = interface(machine, obj, vtable);
+    DD_OK
+}
+
+pub const CLSID_DirectDraw: GUID = GUID {
+    Data1: 0xd7b70ee0,
+    Data2: 0x4340,
+    Data3: 0x11cf,
+    Data4: [0xb0, 0x63, 0x00, 0x20, 0xaf, 0xc2, 0xcd, 0x35],
+};
+
+/// CoCreateInstance of CLSID_DirectDraw.  Unlike DirectDrawCreate, the caller then
+/// calls Initialize on the object.
+pub fn create_instance(machine: &mut Machine, riid: Option<&GUID>, ppv: Option<&mut u32>) -> u32 {
+    init(machine);
+    let obj = ddraw1::IDirectDraw::new(machine);
+    let ret = query_interface(machine, obj, &DDRAW_INTERFACES, riid, ppv);
+    release(machine, obj);
+    ret
+}
 
 #[win32_derive::dllexport]
 pub fn DirectDrawCreate(
     machine: &mut Machine,
-    lpGuid: Option<&GUID>,
+    lpGuid: u32,
     lplpDD: Option<&mut u32>,
     pUnkOuter: u32,
 ) -> u32 {
-    DirectDrawCreateEx(machine, lpGuid, lplpDD, None, pUnkOuter)
+    // lpGuid is null, a device GUID, or a DDCREATE_* value; there's only one device anyway.
+    let Some(lplpDD) = lplpDD else {
+        return DDERR_INVALIDPARAMS;
+    };
+    if pUnkOuter != 0 {
+        return CLASS_E_NOAGGREGATION;
+    }
+    init(machine);
+    // Programs wanting a newer interface upgrade via QueryInterface.
+    *lplpDD = ddraw1::IDirectDraw::new(machine);
+    DD_OK
 }
 
 #[win32_derive::dllexport]
 pub fn DirectDrawCreateEx(
     machine: &mut Machine,
-    lpGuid: Option<&GUID>,
+    lpGuid: u32,
     lplpDD: Option<&mut u32>,
     iid: Option<&GUID>,
     pUnkOuter: u32,
 ) -> u32 {
-    assert!(lpGuid.is_none());
-    assert!(pUnkOuter == 0);
+    let Some(lplpDD) = lplpDD else {
+        return DDERR_INVALIDPARAMS;
+    };
+    if pUnkOuter != 0 {
+        return CLASS_E_NOAGGREGATION;
+    }
+    // Only IDirectDraw7 may be created this way.
+    if iid != Some(&ddraw7::IID_IDirectDraw7) {
+        log::error!("DirectDrawCreateEx: unsupported IID {iid:x?}");
+        return DDERR_INVALIDPARAMS;
+    }
+    init(machine);
+    *lplpDD = ddraw7::IDirectDraw7::new(machine);
+    DD_OK
+}
 
-    if machine.state.ddraw.heap.addr == 0 {
-        machine.state.ddraw = State::new_init(machine);
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// A host surface that records its tag when shown.
+    struct TaggedSurface(u32, Rc<RefCell<Vec<u32>>>);
+
+    impl host::Surface for TaggedSurface {
+        fn write_pixels(&mut self, _pixels: &[[u8; 4]]) {}
+        fn write_pixels_rect(&mut self, _x: u32, _y: u32, _w: u32, _h: u32, _p: &[[u8; 4]]) {}
+        fn show(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+        fn bit_blt(
+            &mut self,
+            _: u32,
+            _: u32,
+            _: &dyn host::Surface,
+            _: u32,
+            _: u32,
+            _: u32,
+            _: u32,
+        ) {
+        }
+        fn stretch_blt(
+            &mut self,
+            _: u32,
+            _: u32,
+            _: u32,
+            _: u32,
+            _: &dyn host::Surface,
+            _: u32,
+            _: u32,
+            _: u32,
+            _: u32,
+        ) {
+        }
     }
 
-    match iid {
-        None => {
-            // DirectDrawCreate
-            *lplpDD.unwrap() = ddraw1::IDirectDraw::new(machine);
-            return DD_OK;
+    /// Create a primary surface with len - 1 back buffers via CreateSurface, returning the
+    /// chain from the front.  Each buffer's host surface records its index when shown.
+    fn flip_chain(machine: &mut Machine, len: u32, shown: &Rc<RefCell<Vec<u32>>>) -> Vec<u32> {
+        let mut dd = 0;
+        let iid = Some(&ddraw7::IID_IDirectDraw7);
+        assert_eq!(DirectDrawCreateEx(machine, 0, Some(&mut dd), iid, 0), DD_OK);
+        let mut desc = DDSURFACEDESC2::default();
+        desc.dwFlags = DDSD::CAPS | DDSD::WIDTH | DDSD::HEIGHT | DDSD::BACKBUFFERCOUNT;
+        desc.dwWidth = 1;
+        desc.dwHeight = 1;
+        desc.dwBackBufferCount_dwDepth = len - 1;
+        desc.ddsCaps.dwCaps = DDSCAPS::PRIMARYSURFACE | DDSCAPS::FLIP | DDSCAPS::COMPLEX;
+        let mut front = 0;
+        let ret = IDirectDraw7::CreateSurface(machine, dd, Some(&desc), Some(&mut front), 0);
+        assert_eq!(ret, DD_OK);
+
+        let mut addrs = vec![front];
+        addrs.extend_from_slice(&machine.state.ddraw.surfaces[&front].flip_chain);
+        assert_eq!(addrs.len() as u32, len);
+        for (i, &addr) in addrs.iter().enumerate() {
+            pixels(machine, addr);
+            let surface = machine.state.ddraw.surfaces.get_mut(&addr).unwrap();
+            surface.buffer.host = Box::new(TaggedSurface(i as u32, shown.clone()));
         }
-        Some(&ddraw7::IID_IDirectDraw7) => {
-            *lplpDD.unwrap() = ddraw7::IDirectDraw7::new(machine);
-            DD_OK
+        addrs
+    }
+
+    #[test]
+    fn flip() {
+        let mut machine = crate::testing::machine();
+        let shown = Rc::new(RefCell::new(Vec::new()));
+        let addrs = flip_chain(&mut machine, 3, &shown);
+        let buffers = |machine: &Machine| {
+            addrs
+                .iter()
+                .map(|&addr| machine.state.ddraw.buffer(addr).pixels)
+                .collect::<Vec<_>>()
+        };
+
+        let [a, b, c] = buffers(&machine)[..] else {
+            unreachable!()
+        };
+
+        // Only the front surface flips.
+        let flip = |machine: &mut Machine, addr| {
+            IDirectDrawSurface7::Flip(machine, addr, 0, Ok(DDFLIP::empty()))
+        };
+        assert_eq!(flip(&mut machine, addrs[1]), DDERR_NOTFLIPPABLE);
+        assert_eq!(buffers(&machine), [a, b, c]);
+
+        // Each surface takes over the buffer of the one after it, and the front is shown.
+        assert_eq!(flip(&mut machine, addrs[0]), DD_OK);
+        assert_eq!(buffers(&machine), [b, c, a]);
+        assert_eq!(flip(&mut machine, addrs[0]), DD_OK);
+        assert_eq!(buffers(&machine), [c, a, b]);
+        assert_eq!(flip(&mut machine, addrs[0]), DD_OK);
+        assert_eq!(buffers(&machine), [a, b, c]);
+        assert_eq!(*shown.borrow(), [1, 2, 0]);
+
+        // Drawing goes to the buffer a surface currently uses.
+        flip(&mut machine, addrs[0]);
+        assert_eq!(pixels(&mut machine, addrs[1]), c);
+    }
+
+    #[test]
+    fn query_interface_identity() {
+        let mut machine = crate::testing::machine();
+        let mut dd = 0;
+        assert_eq!(DirectDrawCreate(&mut machine, 0, Some(&mut dd), 0), DD_OK);
+        let query = |machine: &mut Machine, this, iid: &GUID| {
+            let mut out = 0xFFFF_FFFF;
+            let ret = query_interface(machine, this, &DDRAW_INTERFACES, Some(iid), Some(&mut out));
+            (ret, out)
+        };
+
+        let (_, dd7) = query(&mut machine, dd, &ddraw7::IID_IDirectDraw7);
+        assert_ne!(dd7, dd);
+        let (ret, unknown) = query(&mut machine, dd7, &IID_IUnknown);
+        assert_eq!(ret, DD_OK);
+        assert_eq!(query(&mut machine, dd, &IID_IUnknown), (DD_OK, unknown));
+        assert_eq!(
+            query(&mut machine, unknown, &IID_IUnknown),
+            (DD_OK, unknown)
+        );
+        assert_eq!(
+            query(&mut machine, dd7, &CLSID_DirectDraw),
+            (E_NOINTERFACE, 0)
+        );
+    }
+
+    /// A host surface that records the rects written to it.
+    struct RecordingSurface(Rc<RefCell<Vec<(u32, u32, u32, u32)>>>);
+
+    impl host::Surface for RecordingSurface {
+        fn write_pixels(&mut self, _pixels: &[[u8; 4]]) {
+            unimplemented!()
+        }
+        fn write_pixels_rect(&mut self, x: u32, y: u32, w: u32, h: u32, pixels: &[[u8; 4]]) {
+            assert_eq!(pixels.len() as u32, w * h);
+            self.0.borrow_mut().push((x, y, w, h));
+        }
+        fn show(&mut self) {}
+        fn bit_blt(
+            &mut self,
+            _: u32,
+            _: u32,
+            _: &dyn host::Surface,
+            _: u32,
+            _: u32,
+            _: u32,
+            _: u32,
+        ) {
         }
-        _ => {
-            log::error!("DirectDrawCreateEx: unknown IID {iid:x?}");
-            DDERR_GENERIC
+        fn stretch_blt(
+            &mut self,
+            _: u32,
+            _: u32,
+            _: u32,
+            _: u32,
+            _: &dyn host::Surface,
+            _: u32,
+            _: u32,
+            _: u32,
+            _: u32,
+        ) {
         }
     }
+
+    #[test]
+    fn fill_clipped() {
+        let mut machine = crate::testing::machine();
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let pixels = crate::testing::alloc_data(&mut machine, &[0xFF; 4 * 4 * 2]);
+        let (surface, clipper) = (0x100, 0x200);
+        machine.state.ddraw.clippers.insert(
+            clipper,
+            Clipper {
+                hwnd: HWND::null(),
+                rects: vec![
+                    RECT {
+                        left: 0,
+                        top: 0,
+                        right: 2,
+                        bottom: 2,
+                    },
+                    RECT {
+                        left: 3,
+                        top: 0,
+                        right: 4,
+                        bottom: 1,
+                    },
+                ],
+            },
+        );
+        machine.state.ddraw.surfaces.insert(
+            surface,
+            Surface {
+                buffer: Buffer {
+                    host: Box::new(RecordingSurface(writes.clone())),
+                    pixels,
+                },
+                width: 4,
+                height: 2,
+                palette: 0,
+                pixel_format: DDPIXELFORMAT::for_bpp(32).unwrap(),
+                attached: 0,
+                flip_chain: Vec::new(),
+                flips: 0,
+                chain: None,
+                clipper,
+                primary: false,
+                lost: false,
+                locked: None,
+                src_color_key: None,
+                dst_color_key: None,
+            },
+        );
+
+        // A fill extending past the surface only touches, and only uploads, the parts
+        // within the clip list.
+        let mut fx = DDBLTFX::zeroed();
+        fx.fill = 0x112233;
+        let dst = RECT {
+            left: 1,
+            top: 0,
+            right: 8,
+            bottom: 8,
+        };
+        let ret = IDirectDrawSurface7::Blt(
+            &mut machine,
+            surface,
+            Some(&dst),
+            0,
+            None,
+            Ok(DDBLT::COLORFILL),
+            Some(&fx),
+        );
+        assert_eq!(ret, DD_OK);
+        let (x, o) = (0x112233, 0xFFFFFFFF);
+        let filled: Vec<u32> = machine.mem().iter_pod::<u32>(pixels, 8).collect();
+        assert_eq!(filled, [o, x, o, x, o, x, o, o]);
+        assert_eq!(*writes.borrow(), [(1, 0, 1, 2), (3, 0, 1, 1)]);
+    }
+
+    #[test]
+    fn create_surface_pixel_format() {
+        let mut machine = crate::testing::machine();
+        let mut dd = 0;
+        let iid = Some(&ddraw7::IID_IDirectDraw7);
+        assert_eq!(
+            DirectDrawCreateEx(&mut machine, 0, Some(&mut dd), iid, 0),
+            DD_OK
+        );
+        let mut desc = DDSURFACEDESC2::default();
+        desc.dwFlags = DDSD::WIDTH | DDSD::HEIGHT | DDSD::PIXELFORMAT;
+        desc.dwWidth = 2;
+        desc.dwHeight = 2;
+
+        // A zero bit count has no pixel size, so is refused rather than drawn with.
+        let mut surface = 0;
+        let ret = IDirectDraw7::CreateSurface(&mut machine, dd, Some(&desc), Some(&mut surface), 0);
+        assert_eq!(ret, DDERR_INVALIDPIXELFORMAT);
+        assert_eq!(surface, 0);
+
+        desc.ddpfPixelFormat = DDPIXELFORMAT::for_bpp(16).unwrap();
+        let ret = IDirectDraw7::CreateSurface(&mut machine, dd, Some(&desc), Some(&mut surface), 0);
+        assert_eq!(ret, DD_OK);
+        assert_eq!(machine.state.ddraw.surfaces[&surface].bytes_per_pixel(), 2);
+    }
 }
@@ -1,39 +1,127 @@
 use crate::{
-    winapi::{com::vtable, kernel32::get_symbol},
+    winapi::{com::vtable, ddraw},
     Machine,
 };
-use memory::ExtensionsMut;
+use memory::{ExtensionsMut, Mem};
 
 const TRACE_CONTEXT: &'static str = "ddraw/palette";
 
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Palette {
+    pub flags: ddraw::DDPCAPS,
+    pub entries: Box<[ddraw::PALETTEENTRY]>,
+}
+
+impl Palette {
+    /// Create a palette from its initial entries in x86 memory.
+    pub fn new(mem: Mem, flags: ddraw::DDPCAPS, entries: u32) -> Self {
+        let size = if flags.contains(ddraw::DDPCAPS::_1BIT) {
+            2
+        } else if flags.contains(ddraw::DDPCAPS::_2BIT) {
+            4
+        } else if flags.contains(ddraw::DDPCAPS::_4BIT) {
+            16
+        } else {
+            256
+        };
+        let mut palette = Palette {
+            flags,
+            entries: vec![ddraw::PALETTEENTRY::default(); size].into_boxed_slice(),
+        };
+        palette.read_entries(mem, 0, size as u32, entries);
+        palette
+    }
+
+    /// With DDPCAPS_8BITENTRIES, entries are single bytes indexing another palette.
+    /// We don't resolve those, but keep the byte in peRed so it round-trips.
+    fn is_8bit_entries(&self) -> bool {
+        self.flags.contains(ddraw::DDPCAPS::_8BITENTRIES)
+    }
+
+    fn read_entries(&mut self, mem: Mem, start: u32, count: u32, addr: u32) {
+        let eight_bit = self.is_8bit_entries();
+        let dst = &mut self.entries[start as usize..][..count as usize];
+        if eight_bit {
+            for (entry, &index) in dst.iter_mut().zip(mem.view_n::<u8>(addr, count)) {
+                *entry = ddraw::PALETTEENTRY {
+                    peRed: index,
+                    ..Default::default()
+                };
+            }
+        } else {
+            dst.clone_from_slice(mem.view_n::<ddraw::PALETTEENTRY>(addr, count));
+        }
+    }
+
+    fn write_entries(&self, mem: Mem, start: u32, count: u32, addr: u32) {
+        let src = &self.entries[start as usize..][..count as usize];
+        for (i, entry) in src.iter().enumerate() {
+            let i = i as u32;
+            if self.is_8bit_entries() {
+                mem.put_pod::<u8>(addr + i, entry.peRed);
+            } else {
+                mem.put_pod::<ddraw::PALETTEENTRY>(addr + i * 4, entry.clone());
+            }
+        }
+    }
+
+    fn in_range(&self, start: u32, count: u32) -> bool {
+        start as usize + count as usize <= self.entries.len()
+    }
+}
+
 #[win32_derive::dllexport]
 pub mod IDirectDrawPalette {
-    use crate::winapi::ddraw::{DD_OK, PALETTEENTRY};
+    use crate::winapi::ddraw::{DDERR_INVALIDPARAMS, DD_OK};
 
     use super::*;
 
     vtable![
         QueryInterface: todo,
-        AddRef: todo,
+        AddRef: ok,
         Release: ok,
-        GetCaps: todo,
-        GetEntries: todo,
+        GetCaps: ok,
+        GetEntries: ok,
         Initialize: todo,
         SetEntries: ok,
     ];
 
     pub fn new(machine: &mut Machine) -> u32 {
-        let ddraw = &mut machine.state.ddraw;
-        let lpDirectDrawPalette = ddraw.heap.alloc(machine.emu.memory.mem(), 4);
-        let vtable = get_symbol(machine, "ddraw.dll", "IDirectDrawPalette");
-        machine.mem().put_pod::<u32>(lpDirectDrawPalette, vtable);
-        lpDirectDrawPalette
+        ddraw::new_object(machine, "IDirectDrawPalette")
     }
 
     #[win32_derive::dllexport]
-    pub fn Release(_machine: &mut Machine, this: u32) -> u32 {
-        log::warn!("{this:x}->Release()");
-        0 // TODO: return refcount?
+    pub fn AddRef(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::add_ref(machine, this)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Release(machine: &mut Machine, this: u32) -> u32 {
+        ddraw::release(machine, this)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetCaps(machine: &mut Machine, this: u32, lpdwCaps: Option<&mut u32>) -> u32 {
+        let palette = machine.state.ddraw.palettes.get(&this).unwrap();
+        *lpdwCaps.unwrap() = palette.flags.bits();
+        DD_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetEntries(
+        machine: &mut Machine,
+        this: u32,
+        unused: u32,
+        start: u32,
+        count: u32,
+        entries: u32,
+    ) -> u32 {
+        let palette = machine.state.ddraw.palettes.get(&this).unwrap();
+        if !palette.in_range(start, count) || entries == 0 {
+            return DDERR_INVALIDPARAMS;
+        }
+        palette.write_entries(machine.emu.memory.mem(), start, count, entries);
+        DD_OK
     }
 
     #[win32_derive::dllexport]
@@ -46,13 +134,50 @@ pub mod IDirectDrawPalette {
         entries: u32,
     ) -> u32 {
         let palette = machine.state.ddraw.palettes.get_mut(&this).unwrap();
-        // TODO: if palette is DDPCAPS_8BITENTRIES then entries are one byte, not 4.
-        let entries = machine
-            .emu
-            .memory
-            .mem()
-            .view_n::<PALETTEENTRY>(entries, count);
-        palette[start as usize..][..count as usize].clone_from_slice(entries);
+        if !palette.in_range(start, count) || entries == 0 {
+            return DDERR_INVALIDPARAMS;
+        }
+        palette.read_entries(machine.emu.memory.mem(), start, count, entries);
+        refresh_surfaces(machine, this);
         DD_OK
     }
 }
+
+/// Palette changes (e.g. fades) are visible without the program touching pixels,
+/// so re-expand any surfaces drawn with the given palette.
+fn refresh_surfaces(machine: &mut Machine, palette: u32) {
+    let palette_hack = machine.state.ddraw.palette_hack;
+    let ddraw = &machine.state.ddraw;
+    let surfaces: Vec<u32> = ddraw
+        .surfaces
+        .iter()
+        .filter(|&(&addr, surf)| {
+            ddraw.buffer(addr).pixels != 0
+                && surf.pixel_format.is_palettized()
+                && (surf.palette == palette || palette_hack == palette)
+        })
+        .map(|(&addr, _)| addr)
+        .collect();
+    for addr in surfaces {
+        ddraw::flush_pixels(machine, addr, None);
+        // As in Unlock, surfaces other than flipping chains show immediately.
+        if machine.state.ddraw.surfaces[&addr].attached == 0 {
+            machine.state.ddraw.buffer_mut(addr).host.show();
+        }
+    }
+}
+
+/// Called when GDI realizes a palette on a screen DC: the screen has one palette,
+/// so copy the entries into the primary's DirectDraw palette, if any.
+pub fn realize_gdi_palette(machine: &mut Machine, entries: &[ddraw::PALETTEENTRY]) {
+    let this = machine.state.ddraw.palette_hack;
+    let Some(palette) = machine.state.ddraw.palettes.get_mut(&this) else {
+        return;
+    };
+    if palette.is_8bit_entries() {
+        return;
+    }
+    let count = entries.len().min(palette.entries.len());
+    palette.entries[..count].clone_from_slice(&entries[..count]);
+    refresh_surfaces(machine, this);
+}
@@ -75,6 +75,7 @@ bitflags! {
 }
 
 bitflags! {
+    #[derive(serde::Serialize, serde::Deserialize)]
     pub struct DDPCAPS: u32 {
         const _4BIT = 0x00000001;
         const _8BITENTRIES = 0x00000002;
@@ -122,13 +123,37 @@ impl TryFrom<u32> for DDLOCK {
 }
 
 #[repr(C)]
-#[derive(Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct DDCOLORKEY {
-    dwColorSpaceLowValue: DWORD,
-    dwColorSpaceHighValue: DWORD,
+    pub dwColorSpaceLowValue: DWORD,
+    pub dwColorSpaceHighValue: DWORD,
 }
 unsafe impl memory::Pod for DDCOLORKEY {}
 
+impl DDCOLORKEY {
+    /// Whether a pixel value (palette index, or color for truecolor surfaces) is keyed.
+    pub fn matches(&self, pixel: u32) -> bool {
+        self.dwColorSpaceLowValue <= pixel && pixel <= self.dwColorSpaceHighValue
+    }
+}
+
+bitflags! {
+    pub struct DDCKEY: u32 {
+        const COLORSPACE  = 0x00000001;
+        const DESTBLT     = 0x00000002;
+        const DESTOVERLAY = 0x00000004;
+        const SRCBLT      = 0x00000008;
+        const SRCOVERLAY  = 0x00000010;
+    }
+}
+impl TryFrom<u32> for DDCKEY {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        DDCKEY::from_bits(value).ok_or(value)
+    }
+}
+
 #[repr(C)]
 pub struct DDSURFACEDESC {
     pub dwSize: DWORD,
@@ -229,7 +254,8 @@ impl DDSURFACEDESC {
 
             lPitch_dwLinearSize: desc2.lPitch_dwLinearSize,
             dwBackBufferCount: desc2.dwBackBufferCount_dwDepth,
-            dwMipMapCount_dwZBufferBitDepth_dwRefreshRate: Default::default(),
+            dwMipMapCount_dwZBufferBitDepth_dwRefreshRate: desc2
+                .dwMipMapCount_dwRefreshRate_dwSrcVBHandle,
             dwAlphaBitDepth: Default::default(),
             dwReserved: Default::default(),
             lpSurface: desc2.lpSurface,
@@ -357,7 +383,8 @@ impl DDSURFACEDESC2 {
             dwWidth: desc.dwWidth,
             lPitch_dwLinearSize: desc.lPitch_dwLinearSize,
             dwBackBufferCount_dwDepth: desc.dwBackBufferCount,
-            dwMipMapCount_dwRefreshRate_dwSrcVBHandle: Default::default(),
+            dwMipMapCount_dwRefreshRate_dwSrcVBHandle: desc
+                .dwMipMapCount_dwZBufferBitDepth_dwRefreshRate,
             dwAlphaBitDepth: Default::default(),
             dwReserved: Default::default(),
             lpSurface: desc.lpSurface,
@@ -365,7 +392,7 @@ impl DDSURFACEDESC2 {
             ddckCKDestBlt: Default::default(),
             ddckCKSrcOverlay: Default::default(),
             ddckCKSrcBlt: Default::default(),
-            ddpfPixelFormat: Default::default(),
+            ddpfPixelFormat: desc.ddpfPixelFormat.clone(),
             ddsCaps: DDSCAPS2 {
                 dwCaps: desc.ddsCaps,
                 dwCaps2: Default::default(),
@@ -378,7 +405,7 @@ impl DDSURFACEDESC2 {
 }
 
 #[repr(C)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
 pub struct DDPIXELFORMAT {
     pub dwSize: DWORD,
     pub dwFlags: DWORD,
@@ -391,15 +418,73 @@ pub struct DDPIXELFORMAT {
 }
 unsafe impl memory::Pod for DDPIXELFORMAT {}
 
-#[repr(C)]
-#[derive(Clone, Debug)]
-pub struct PALETTEENTRY {
-    pub peRed: u8,
-    pub peGreen: u8,
-    pub peBlue: u8,
-    pub peFlags: u8,
+const DDPF_PALETTEINDEXED8: u32 = 0x00000020;
+const DDPF_RGB: u32 = 0x00000040;
+
+impl DDPIXELFORMAT {
+    /// The RGB format we use for a given display depth: paletted at 8bpp, 5:6:5 at 16bpp.
+    /// None for depths we don't support.
+    pub fn for_bpp(bpp: u32) -> Option<DDPIXELFORMAT> {
+        let (flags, r, g, b) = match bpp {
+            8 => (DDPF_RGB | DDPF_PALETTEINDEXED8, 0, 0, 0),
+            16 => (DDPF_RGB, 0xF800, 0x07E0, 0x001F),
+            24 | 32 => (DDPF_RGB, 0xFF_0000, 0x00_FF00, 0x00_00FF),
+            _ => return None,
+        };
+        Some(DDPIXELFORMAT {
+            dwSize: std::mem::size_of::<DDPIXELFORMAT>() as u32,
+            dwFlags: flags,
+            dwFourCC: 0,
+            dwRGBBitCount: bpp,
+            dwRBitMask: r,
+            dwGBitMask: g,
+            dwBBitMask: b,
+            dwRGBAlphaBitMask: 0,
+        })
+    }
+
+    pub fn bytes_per_pixel(&self) -> u32 {
+        self.dwRGBBitCount / 8
+    }
+
+    pub fn is_palettized(&self) -> bool {
+        self.dwFlags & DDPF_PALETTEINDEXED8 != 0
+    }
+
+    /// Bits of a pixel value that carry color, for comparing against color keys.
+    pub fn color_mask(&self) -> u32 {
+        if self.is_palettized() {
+            0xFF
+        } else {
+            self.dwRBitMask | self.dwGBitMask | self.dwBBitMask
+        }
+    }
+
+    /// Expand an RGB pixel value to RGBA per the channel masks.
+    pub fn to_rgba(&self, pixel: u32) -> [u8; 4] {
+        fn channel(pixel: u32, mask: u32) -> u8 {
+            if mask == 0 {
+                return 0;
+            }
+            let max = (mask >> mask.trailing_zeros()) as u64;
+            let value = ((pixel & mask) >> mask.trailing_zeros()) as u64;
+            (value * 255 / max) as u8
+        }
+        [
+            channel(pixel, self.dwRBitMask),
+            channel(pixel, self.dwGBitMask),
+            channel(pixel, self.dwBBitMask),
+            255,
+        ]
+    }
+}
+
+/// Read a little-endian pixel value of 1-4 bytes.
+pub fn pixel_value(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |v, &b| (v << 8) | b as u32)
 }
-unsafe impl memory::Pod for PALETTEENTRY {}
+
+pub use crate::winapi::bitmap::PALETTEENTRY;
 
 bitflags! {
     pub struct DDBLT: u32 {
@@ -440,6 +525,23 @@ impl TryFrom<u32> for DDBLT {
     }
 }
 
+bitflags! {
+    pub struct DDBLTFAST: u32 {
+        const NOCOLORKEY   = 0x00000000;
+        const SRCCOLORKEY  = 0x00000001;
+        const DESTCOLORKEY = 0x00000002;
+        const WAIT         = 0x00000010;
+        const DONOTWAIT    = 0x00000020;
+    }
+}
+impl TryFrom<u32> for DDBLTFAST {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        DDBLTFAST::from_bits(value).ok_or(value)
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct DDBLTFX {
@@ -482,3 +584,91 @@ bitflags! {
         const ZBUFFERBASEDEST = 0x100;
     }
 }
+
+/// Driver capabilities, as returned by IDirectDraw::GetCaps.
+/// This is the DirectX 7 layout; callers from older versions pass a smaller dwSize.
+#[repr(C)]
+pub struct DDCAPS {
+    pub dwSize: DWORD,
+    pub dwCaps: DWORD,
+    pub dwCaps2: DWORD,
+    pub dwCKeyCaps: DWORD,
+    pub dwFXCaps: DWORD,
+    pub dwFXAlphaCaps: DWORD,
+    pub dwPalCaps: DWORD,
+    pub dwSVCaps: DWORD,
+    pub dwAlphaBltConstBitDepths: DWORD,
+    pub dwAlphaBltPixelBitDepths: DWORD,
+    pub dwAlphaBltSurfaceBitDepths: DWORD,
+    pub dwAlphaOverlayConstBitDepths: DWORD,
+    pub dwAlphaOverlayPixelBitDepths: DWORD,
+    pub dwAlphaOverlaySurfaceBitDepths: DWORD,
+    pub dwZBufferBitDepths: DWORD,
+    pub dwVidMemTotal: DWORD,
+    pub dwVidMemFree: DWORD,
+    pub dwMaxVisibleOverlays: DWORD,
+    pub dwCurrVisibleOverlays: DWORD,
+    pub dwNumFourCCCodes: DWORD,
+    pub dwAlignBoundarySrc: DWORD,
+    pub dwAlignSizeSrc: DWORD,
+    pub dwAlignBoundaryDest: DWORD,
+    pub dwAlignSizeDest: DWORD,
+    pub dwAlignStrideAlign: DWORD,
+    pub dwRops: [DWORD; 8],
+    pub ddsOldCaps: DDSCAPS,
+    pub dwMinOverlayStretch: DWORD,
+    pub dwMaxOverlayStretch: DWORD,
+    pub dwMinLiveVideoStretch: DWORD,
+    pub dwMaxLiveVideoStretch: DWORD,
+    pub dwMinHwCodecStretch: DWORD,
+    pub dwMaxHwCodecStretch: DWORD,
+    pub dwReserved1: DWORD,
+    pub dwReserved2: DWORD,
+    pub dwReserved3: DWORD,
+    pub dwSVBCaps: DWORD,
+    pub dwSVBCKeyCaps: DWORD,
+    pub dwSVBFXCaps: DWORD,
+    pub dwSVBRops: [DWORD; 8],
+    pub dwVSBCaps: DWORD,
+    pub dwVSBCKeyCaps: DWORD,
+    pub dwVSBFXCaps: DWORD,
+    pub dwVSBRops: [DWORD; 8],
+    pub dwSSBCaps: DWORD,
+    pub dwSSBCKeyCaps: DWORD,
+    pub dwSSBFXCaps: DWORD,
+    pub dwSSBRops: [DWORD; 8],
+    // DirectX 3 size ends here.
+    pub dwMaxVideoPorts: DWORD,
+    pub dwCurrVideoPorts: DWORD,
+    pub dwSVBCaps2: DWORD,
+    pub dwNLVBCaps: DWORD,
+    pub dwNLVBCaps2: DWORD,
+    pub dwNLVBCKeyCaps: DWORD,
+    pub dwNLVBFXCaps: DWORD,
+    pub dwNLVBRops: [DWORD; 8],
+    // DirectX 5 size ends here.
+    pub ddsCaps: DDSCAPS2,
+}
+unsafe impl memory::Pod for DDCAPS {}
+
+// DDCAPS.dwCaps
+pub const DDCAPS_BLT: u32 = 0x00000040;
+pub const DDCAPS_BLTSTRETCH: u32 = 0x00000200;
+pub const DDCAPS_PALETTE: u32 = 0x00008000;
+pub const DDCAPS_COLORKEY: u32 = 0x00400000;
+pub const DDCAPS_BLTCOLORFILL: u32 = 0x04000000;
+pub const DDCAPS_CANCLIP: u32 = 0x20000000;
+pub const DDCAPS_CANCLIPSTRETCHED: u32 = 0x40000000;
+pub const DDCAPS_CANBLTSYSMEM: u32 = 0x80000000;
+
+// DDCAPS.dwCKeyCaps
+pub const DDCKEYCAPS_DESTBLT: u32 = 0x00000002;
+pub const DDCKEYCAPS_DESTBLTCLRSPACE: u32 = 0x00000004;
+pub const DDCKEYCAPS_SRCBLT: u32 = 0x00000200;
+pub const DDCKEYCAPS_SRCBLTCLRSPACE: u32 = 0x00000400;
+
+// DDCAPS.dwFXCaps
+pub const DDFXCAPS_BLTSHRINKX: u32 = 0x00000400;
+pub const DDFXCAPS_BLTSHRINKY: u32 = 0x00001000;
+pub const DDFXCAPS_BLTSTRETCHX: u32 = 0x00004000;
+pub const DDFXCAPS_BLTSTRETCHY: u32 = 0x00010000;
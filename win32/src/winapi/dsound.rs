@@ -4,17 +4,34 @@
 use super::heap::Heap;
 pub use crate::winapi::com::GUID;
 use crate::{
+    host,
     machine::Machine,
-    winapi::{com::vtable, kernel32::get_symbol},
+    winapi::{
+        com::{vtable, ComObject, IID_IUnknown, E_NOINTERFACE},
+        kernel32::get_symbol,
+    },
 };
-use memory::ExtensionsMut;
+use memory::{Extensions, ExtensionsMut, Mem};
 use std::collections::HashMap;
 
 const TRACE_CONTEXT: &'static str = "dsound";
 
 /// Set to true to make DirectSoundCreate report no sound device available.
-/// Doing this from the beginning would have been a better idea than trying to implement stubs here...
-const DISABLE: bool = true;
+const DISABLE: bool = false;
+
+pub const IID_IDirectSound: GUID = GUID {
+    Data1: 0x279afa83,
+    Data2: 0x4981,
+    Data3: 0x11ce,
+    Data4: [0xa5, 0x21, 0x00, 0x20, 0xaf, 0x0b, 0xe5, 0x60],
+};
+
+pub const IID_IDirectSoundBuffer: GUID = GUID {
+    Data1: 0x279afa85,
+    Data2: 0x4981,
+    Data3: 0x11ce,
+    Data4: [0xa5, 0x21, 0x00, 0x20, 0xaf, 0x0b, 0xe5, 0x60],
+};
 
 pub const DS_OK: u32 = 0;
 #[allow(unused)]
@@ -23,12 +40,32 @@ const E_FAIL: u32 = 0x80004005;
 pub const DSERR_GENERIC: u32 = E_FAIL;
 #[allow(unused)]
 pub const DSERR_NODRIVER: u32 = make_dhsresult(120);
+pub const DSERR_INVALIDPARAM: u32 = 0x80070057;
+pub const DSERR_CONTROLUNAVAIL: u32 = make_dhsresult(30);
+pub const DSERR_ALREADYINITIALIZED: u32 = make_dhsresult(130);
+
+/// How far ahead of the play cursor we keep the host fed, in milliseconds.
+/// This is also where the write cursor sits.
+const LEAD_MS: u64 = 100;
+
+const DSBPLAY_LOOPING: u32 = 1;
+const DSBSTATUS_PLAYING: u32 = 1;
+const DSBSTATUS_LOOPING: u32 = 4;
+const DSSPEAKER_STEREO: u32 = 4;
+
+const DSBVOLUME_MIN: i32 = -10000;
+const DSBVOLUME_MAX: i32 = 0;
+const DSBPAN_LEFT: i32 = -10000;
+const DSBPAN_RIGHT: i32 = 10000;
+const DSBFREQUENCY_ORIGINAL: u32 = 0;
+const DSBFREQUENCY_MIN: u32 = 100;
+const DSBFREQUENCY_MAX: u32 = 100000;
 
 const fn make_dhsresult(code: u32) -> u32 {
     (1 << 31) | (0x878 << 16) | code
 }
 
-#[derive(Default)]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct State {
     heap: Heap,
     buffers: HashMap<u32, Buffer>,
@@ -46,18 +83,145 @@ impl State {
     }
 }
 
-#[derive(Default)]
+/// Bring all playing buffers up to date with the host clock, so they keep playing while the
+/// game waits on the message loop rather than polling them.  Returns the host ticks by
+/// which this should be called again, if any buffer is playing.
+pub fn update_all(machine: &mut Machine) -> Option<u32> {
+    let now = machine.host.ticks();
+    let mem = machine.emu.memory.mem();
+    let mut playing = false;
+    for buf in machine.state.dsound.buffers.values_mut() {
+        buf.update(mem, now);
+        playing |= buf.playing.is_some();
+    }
+    if playing {
+        Some(now + LEAD_MS as u32 / 2)
+    } else {
+        None
+    }
+}
+
+/// Reopen host audio for the playing buffers of a state loaded by restore_state().
+pub fn reattach_buffers(machine: &mut Machine) {
+    for buf in machine.state.dsound.buffers.values_mut() {
+        if let (Some(format), Some(_)) = (&buf.format, &buf.playing) {
+            buf.audio = Some(machine.host.open_audio(&format.to_host()));
+        }
+    }
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 struct Buffer {
+    /// The IDirectSound that created this buffer, which releases it along with itself.
+    device: u32,
+    /// Sample memory, which duplicates of a buffer share.
     addr: u32,
     size: u32,
+    /// DSBCAPS the buffer was created with.
+    flags: u32,
     lock: Option<Lock>,
+    format: Option<WAVEFORMATEX>,
+    #[serde(skip)]
+    audio: Option<Box<dyn host::Audio>>,
+    playing: Option<Playback>,
+    /// The play cursor while stopped, where Play resumes from.
+    position: u32,
+    /// Attenuation in hundredths of a decibel, which isn't applied to the host output.
+    volume: i32,
+    /// Left/right attenuation in hundredths of a decibel, which isn't applied either.
+    pan: i32,
+    /// Playback rate from SetFrequency, or 0 for the format's rate.
+    frequency: u32,
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 struct Lock {
     addr: u32,
     size: u32,
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Playback {
+    /// Host ticks when Play was called.
+    start: u32,
+    /// The buffer offset playback started from.
+    offset: u32,
+    looping: bool,
+    /// Total bytes handed to the host so far, which may exceed the buffer size
+    /// when looping.
+    submitted: u64,
+}
+
+impl Buffer {
+    fn caps(&self) -> DSBCAPS {
+        DSBCAPS::from_bits_truncate(self.flags)
+    }
+
+    fn bytes_per_sec(&self) -> u64 {
+        self.format
+            .as_ref()
+            .map_or(0, |fmt| fmt.nAvgBytesPerSec as u64)
+    }
+
+    /// Total bytes played, counting from the start of the buffer, per the host clock.
+    fn played(&self, playback: &Playback, now: u32) -> u64 {
+        playback.offset as u64
+            + now.wrapping_sub(playback.start) as u64 * self.bytes_per_sec() / 1000
+    }
+
+    /// Start playing from the buffer offset position.
+    fn start(&mut self, now: u32, position: u32, looping: bool) {
+        self.playing = Some(Playback {
+            start: now,
+            offset: position,
+            looping,
+            submitted: position as u64,
+        });
+    }
+
+    /// Advance playback to now, handing the host the buffer contents up to
+    /// LEAD_MS past the play cursor and wrapping around for looping buffers.
+    fn update(&mut self, mem: Mem, now: u32) {
+        let Some(mut playback) = self.playing.take() else {
+            return;
+        };
+        let size = self.size as u64;
+        let played = self.played(&playback, now);
+        let finished = !playback.looping && played >= size;
+        let mut target = played + LEAD_MS * self.bytes_per_sec() / 1000;
+        if !playback.looping {
+            target = target.min(size);
+        }
+        if let Some(audio) = &mut self.audio {
+            while playback.submitted < target {
+                let ofs = playback.submitted % size;
+                let len = (size - ofs).min(target - playback.submitted);
+                audio.queue(mem.sub32(self.addr + ofs as u32, len as u32));
+                playback.submitted += len;
+            }
+        }
+        if finished {
+            self.position = 0;
+        } else {
+            self.playing = Some(playback);
+        }
+    }
+
+    /// The play and write cursors, as offsets into the buffer.
+    fn cursors(&self, now: u32) -> (u32, u32) {
+        let Some(playback) = &self.playing else {
+            return (self.position, self.position);
+        };
+        let size = self.size as u64;
+        if size == 0 {
+            return (0, 0);
+        }
+        let played = self.played(playback, now);
+        let write = played + LEAD_MS * self.bytes_per_sec() / 1000;
+        ((played % size) as u32, (write % size) as u32)
+    }
+}
+
 bitflags::bitflags! {
     pub struct DSBCAPS: u32 {
         const PRIMARYBUFFER       = 0x00000001;
@@ -112,7 +276,60 @@ pub struct DSBUFFERDESC {
 unsafe impl memory::Pod for DSBUFFERDESC {}
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Default)]
+pub struct DSCAPS {
+    pub dwSize: u32,
+    pub dwFlags: u32,
+    pub dwMinSecondarySampleRate: u32,
+    pub dwMaxSecondarySampleRate: u32,
+    pub dwPrimaryBuffers: u32,
+    pub dwMaxHwMixingAllBuffers: u32,
+    pub dwMaxHwMixingStaticBuffers: u32,
+    pub dwMaxHwMixingStreamingBuffers: u32,
+    pub dwFreeHwMixingAllBuffers: u32,
+    pub dwFreeHwMixingStaticBuffers: u32,
+    pub dwFreeHwMixingStreamingBuffers: u32,
+    pub dwMaxHw3DAllBuffers: u32,
+    pub dwMaxHw3DStaticBuffers: u32,
+    pub dwMaxHw3DStreamingBuffers: u32,
+    pub dwFreeHw3DAllBuffers: u32,
+    pub dwFreeHw3DStaticBuffers: u32,
+    pub dwFreeHw3DStreamingBuffers: u32,
+    pub dwTotalHwMemBytes: u32,
+    pub dwFreeHwMemBytes: u32,
+    pub dwMaxContigFreeHwMemBytes: u32,
+    pub dwUnlockTransferRateHwBuffers: u32,
+    pub dwPlayCpuOverheadSwBuffers: u32,
+    pub dwReserved1: u32,
+    pub dwReserved2: u32,
+}
+unsafe impl memory::Pod for DSCAPS {}
+
+// DSCAPS flags
+const DSCAPS_PRIMARYMONO: u32 = 0x00000001;
+const DSCAPS_PRIMARYSTEREO: u32 = 0x00000002;
+const DSCAPS_PRIMARY8BIT: u32 = 0x00000004;
+const DSCAPS_PRIMARY16BIT: u32 = 0x00000008;
+const DSCAPS_CONTINUOUSRATE: u32 = 0x00000010;
+const DSCAPS_SECONDARYMONO: u32 = 0x00000100;
+const DSCAPS_SECONDARYSTEREO: u32 = 0x00000200;
+const DSCAPS_SECONDARY8BIT: u32 = 0x00000400;
+const DSCAPS_SECONDARY16BIT: u32 = 0x00000800;
+
+/// The caps of a buffer, named apart from the DSBCAPS flags it holds.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct DSBUFFERCAPS {
+    pub dwSize: u32,
+    pub dwFlags: u32,
+    pub dwBufferBytes: u32,
+    pub dwUnlockTransferRate: u32,
+    pub dwPlayCpuOverhead: u32,
+}
+unsafe impl memory::Pod for DSBUFFERCAPS {}
+
+#[repr(C)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct WAVEFORMATEX {
     pub wFormatTag: u16,
     pub nChannels: u16,
@@ -124,20 +341,73 @@ pub struct WAVEFORMATEX {
 }
 unsafe impl memory::Pod for WAVEFORMATEX {}
 
+impl WAVEFORMATEX {
+    /// The struct as laid out in memory, which is packed to 18 bytes.
+    fn to_bytes(&self) -> [u8; 18] {
+        let mut bytes = [0u8; 18];
+        bytes[0..2].copy_from_slice(&self.wFormatTag.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.nChannels.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.nSamplesPerSec.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.nAvgBytesPerSec.to_le_bytes());
+        bytes[12..14].copy_from_slice(&self.nBlockAlign.to_le_bytes());
+        bytes[14..16].copy_from_slice(&self.wBitsPerSample.to_le_bytes());
+        bytes[16..18].copy_from_slice(&self.cbSize.to_le_bytes());
+        bytes
+    }
+
+    fn to_host(&self) -> host::AudioFormat {
+        host::AudioFormat {
+            channels: self.nChannels,
+            sample_rate: self.nSamplesPerSec,
+            bits_per_sample: self.wBitsPerSample,
+        }
+    }
+}
+
 #[win32_derive::dllexport]
 pub mod IDirectSound {
     use super::*;
 
     pub fn new(machine: &mut Machine) -> u32 {
-        let dsound = &mut machine.state.dsound;
-        let lpDirectSound = dsound.heap.alloc(machine.emu.memory.mem(), 4);
         let vtable = get_symbol(machine, "dsound.dll", "IDirectSound");
-        machine.mem().put_pod::<u32>(lpDirectSound, vtable);
-        lpDirectSound
+        let dsound = &mut machine.state.dsound;
+        ComObject::alloc(&mut dsound.heap, machine.emu.memory.mem(), vtable)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn QueryInterface(
+        machine: &mut Machine,
+        this: u32,
+        riid: Option<&GUID>,
+        ppvObject: Option<&mut u32>,
+    ) -> u32 {
+        query_interface(machine, this, &IID_IDirectSound, riid, ppvObject)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn AddRef(machine: &mut Machine, this: u32) -> u32 {
+        ComObject::add_ref(machine.mem(), this)
     }
 
     #[win32_derive::dllexport]
-    pub fn Release(_machine: &mut Machine, this: u32) -> u32 {
+    pub fn Release(machine: &mut Machine, this: u32) -> u32 {
+        let count = ComObject::release(machine.mem(), this);
+        if count > 0 {
+            return count;
+        }
+        // The device's buffers go with it, whatever their own reference counts.
+        let buffers = (machine.state.dsound.buffers.iter())
+            .filter(|(_, buf)| buf.device == this)
+            .map(|(&addr, _)| addr)
+            .collect::<Vec<_>>();
+        for buffer in buffers {
+            free_buffer(machine, buffer);
+        }
+        machine
+            .state
+            .dsound
+            .heap
+            .free(machine.emu.memory.mem(), this);
         0
     }
 
@@ -155,7 +425,11 @@ pub mod IDirectSound {
         *lplpDirectSoundBuffer.unwrap() = x86_buffer;
         log::info!("=> {x86_buffer:x}");
 
-        let mut buffer = Buffer::default();
+        let mut buffer = Buffer {
+            device: this,
+            flags: (desc.dwFlags | DSBCAPS::LOCSOFTWARE).bits(),
+            ..Default::default()
+        };
         if !desc.dwFlags.contains(DSBCAPS::PRIMARYBUFFER) {
             buffer.addr = machine
                 .state
@@ -163,9 +437,74 @@ pub mod IDirectSound {
                 .heap
                 .alloc(machine.emu.memory.mem(), desc.dwBufferBytes);
             buffer.size = desc.dwBufferBytes;
+            if desc.lpwfxFormat != 0 {
+                buffer.format = Some(machine.mem().get_pod::<WAVEFORMATEX>(desc.lpwfxFormat));
+            }
+        }
+
+        machine.state.dsound.buffers.insert(x86_buffer, buffer);
+        DS_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetCaps(machine: &mut Machine, this: u32, lpDSCaps: Option<&mut DSCAPS>) -> u32 {
+        let Some(caps) = lpDSCaps else {
+            return DSERR_INVALIDPARAM;
+        };
+        if caps.dwSize != std::mem::size_of::<DSCAPS>() as u32 {
+            return DSERR_INVALIDPARAM;
         }
+        // We mix everything in software.
+        *caps = DSCAPS {
+            dwSize: caps.dwSize,
+            dwFlags: DSCAPS_PRIMARYMONO
+                | DSCAPS_PRIMARYSTEREO
+                | DSCAPS_PRIMARY8BIT
+                | DSCAPS_PRIMARY16BIT
+                | DSCAPS_CONTINUOUSRATE
+                | DSCAPS_SECONDARYMONO
+                | DSCAPS_SECONDARYSTEREO
+                | DSCAPS_SECONDARY8BIT
+                | DSCAPS_SECONDARY16BIT,
+            dwMinSecondarySampleRate: DSBFREQUENCY_MIN,
+            dwMaxSecondarySampleRate: DSBFREQUENCY_MAX,
+            dwPrimaryBuffers: 1,
+            ..Default::default()
+        };
+        DS_OK
+    }
 
+    #[win32_derive::dllexport]
+    pub fn DuplicateSoundBuffer(
+        machine: &mut Machine,
+        this: u32,
+        lpDsbOriginal: u32,
+        lplpDsbDuplicate: Option<&mut u32>,
+    ) -> u32 {
+        let Some(lplpDsbDuplicate) = lplpDsbDuplicate else {
+            return DSERR_INVALIDPARAM;
+        };
+        let Some(original) = machine.state.dsound.buffers.get(&lpDsbOriginal) else {
+            return DSERR_INVALIDPARAM;
+        };
+        if original.caps().contains(DSBCAPS::PRIMARYBUFFER) {
+            return DSERR_INVALIDPARAM;
+        }
+        // The duplicate shares the original's samples but plays independently.
+        let buffer = Buffer {
+            device: original.device,
+            addr: original.addr,
+            size: original.size,
+            flags: original.flags,
+            format: original.format.clone(),
+            volume: original.volume,
+            pan: original.pan,
+            frequency: original.frequency,
+            ..Default::default()
+        };
+        let x86_buffer = IDirectSoundBuffer::new(machine);
         machine.state.dsound.buffers.insert(x86_buffer, buffer);
+        *lplpDsbDuplicate = x86_buffer;
         DS_OK
     }
 
@@ -174,18 +513,47 @@ pub mod IDirectSound {
         DS_OK
     }
 
+    #[win32_derive::dllexport]
+    pub fn Compact(_machine: &mut Machine, this: u32) -> u32 {
+        DS_OK // no hardware memory to compact
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetSpeakerConfig(
+        _machine: &mut Machine,
+        this: u32,
+        lpdwSpeakerConfig: Option<&mut u32>,
+    ) -> u32 {
+        let Some(config) = lpdwSpeakerConfig else {
+            return DSERR_INVALIDPARAM;
+        };
+        *config = DSSPEAKER_STEREO;
+        DS_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn SetSpeakerConfig(_machine: &mut Machine, this: u32, dwSpeakerConfig: u32) -> u32 {
+        DS_OK // the host decides
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Initialize(_machine: &mut Machine, this: u32, pcGuidDevice: Option<&GUID>) -> u32 {
+        // DirectSoundCreate hands out initialized objects.
+        DSERR_ALREADYINITIALIZED
+    }
+
     vtable![
-        QueryInterface: todo,
-        AddRef: todo,
+        QueryInterface: ok,
+        AddRef: ok,
         Release: ok,
         CreateSoundBuffer: ok,
-        GetCaps: todo,
-        DuplicateSoundBuffer: todo,
+        GetCaps: ok,
+        DuplicateSoundBuffer: ok,
         SetCooperativeLevel: ok,
-        Compact: todo,
-        GetSpeakerConfig: todo,
-        SetSpeakerConfig: todo,
-        Initialize: todo,
+        Compact: ok,
+        GetSpeakerConfig: ok,
+        SetSpeakerConfig: ok,
+        Initialize: ok,
     ];
 }
 
@@ -194,43 +562,173 @@ pub mod IDirectSoundBuffer {
     use super::*;
 
     pub fn new(machine: &mut Machine) -> u32 {
-        let dsound = &mut machine.state.dsound;
-        let lpDirectSoundBuffer = dsound.heap.alloc(machine.emu.memory.mem(), 4);
         let vtable = get_symbol(machine, "dsound.dll", "IDirectSoundBuffer");
-        machine.mem().put_pod::<u32>(lpDirectSoundBuffer, vtable);
-        lpDirectSoundBuffer
+        let dsound = &mut machine.state.dsound;
+        ComObject::alloc(&mut dsound.heap, machine.emu.memory.mem(), vtable)
     }
 
     #[win32_derive::dllexport]
-    pub fn Release(_machine: &mut Machine, this: u32) -> u32 {
-        0
+    pub fn QueryInterface(
+        machine: &mut Machine,
+        this: u32,
+        riid: Option<&GUID>,
+        ppvObject: Option<&mut u32>,
+    ) -> u32 {
+        query_interface(machine, this, &IID_IDirectSoundBuffer, riid, ppvObject)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn AddRef(machine: &mut Machine, this: u32) -> u32 {
+        ComObject::add_ref(machine.mem(), this)
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Release(machine: &mut Machine, this: u32) -> u32 {
+        let count = ComObject::release(machine.mem(), this);
+        if count == 0 {
+            free_buffer(machine, this);
+        }
+        count
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetCaps(
+        machine: &mut Machine,
+        this: u32,
+        lpDSBufferCaps: Option<&mut DSBUFFERCAPS>,
+    ) -> u32 {
+        let Some(caps) = lpDSBufferCaps else {
+            return DSERR_INVALIDPARAM;
+        };
+        if caps.dwSize != std::mem::size_of::<DSBUFFERCAPS>() as u32 {
+            return DSERR_INVALIDPARAM;
+        }
+        let buf = machine.state.dsound.buffers.get(&this).unwrap();
+        *caps = DSBUFFERCAPS {
+            dwSize: caps.dwSize,
+            dwFlags: buf.flags,
+            dwBufferBytes: buf.size,
+            ..Default::default()
+        };
+        DS_OK
     }
 
     #[win32_derive::dllexport]
     pub fn GetCurrentPosition(
-        _machine: &mut Machine,
+        machine: &mut Machine,
         this: u32,
         lpdwCurrentPlayCursor: Option<&mut u32>,
         lpdwCurrentWriteCursor: Option<&mut u32>,
     ) -> u32 {
-        match lpdwCurrentPlayCursor {
-            Some(play) => *play = 0,
-            None => {}
+        // Games poll this as they stream, so feed the host here as well as from the message loop.
+        let now = machine.host.ticks();
+        let buf = update(machine, this, now);
+        let (play, write) = buf.cursors(now);
+        if let Some(p) = lpdwCurrentPlayCursor {
+            *p = play;
         }
-        match lpdwCurrentWriteCursor {
-            Some(write) => *write = 0,
-            None => {}
+        if let Some(w) = lpdwCurrentWriteCursor {
+            *w = write;
         }
         DS_OK
     }
 
     #[win32_derive::dllexport]
-    pub fn GetStatus(_machine: &mut Machine, this: u32, lpdwStatus: Option<&mut u32>) -> u32 {
-        let status = lpdwStatus.unwrap();
-        *status = 0;
+    pub fn GetFormat(
+        machine: &mut Machine,
+        this: u32,
+        lpwfxFormat: u32,
+        dwSizeAllocated: u32,
+        lpdwSizeWritten: Option<&mut u32>,
+    ) -> u32 {
+        let buf = machine.state.dsound.buffers.get(&this).unwrap();
+        let Some(format) = &buf.format else {
+            return DSERR_INVALIDPARAM;
+        };
+        let bytes = format.to_bytes();
+        // With no format pointer, the caller is asking for the size to allocate.
+        if lpwfxFormat == 0 {
+            let Some(written) = lpdwSizeWritten else {
+                return DSERR_INVALIDPARAM;
+            };
+            *written = bytes.len() as u32;
+            return DS_OK;
+        }
+        let len = (bytes.len() as u32).min(dwSizeAllocated);
+        let mem = machine.emu.memory.mem();
+        mem.sub32_mut(lpwfxFormat, len)
+            .copy_from_slice(&bytes[..len as usize]);
+        if let Some(written) = lpdwSizeWritten {
+            *written = len;
+        }
         DS_OK
     }
 
+    #[win32_derive::dllexport]
+    pub fn GetVolume(machine: &mut Machine, this: u32, lplVolume: Option<&mut i32>) -> u32 {
+        let buf = machine.state.dsound.buffers.get(&this).unwrap();
+        if !buf.caps().contains(DSBCAPS::CTRLVOLUME) {
+            return DSERR_CONTROLUNAVAIL;
+        }
+        let Some(volume) = lplVolume else {
+            return DSERR_INVALIDPARAM;
+        };
+        *volume = buf.volume;
+        DS_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetPan(machine: &mut Machine, this: u32, lplPan: Option<&mut i32>) -> u32 {
+        let buf = machine.state.dsound.buffers.get(&this).unwrap();
+        if !buf.caps().contains(DSBCAPS::CTRLPAN) {
+            return DSERR_CONTROLUNAVAIL;
+        }
+        let Some(pan) = lplPan else {
+            return DSERR_INVALIDPARAM;
+        };
+        *pan = buf.pan;
+        DS_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetFrequency(machine: &mut Machine, this: u32, lpdwFrequency: Option<&mut u32>) -> u32 {
+        let buf = machine.state.dsound.buffers.get(&this).unwrap();
+        if !buf.caps().contains(DSBCAPS::CTRLFREQUENCY) {
+            return DSERR_CONTROLUNAVAIL;
+        }
+        let Some(frequency) = lpdwFrequency else {
+            return DSERR_INVALIDPARAM;
+        };
+        *frequency = match (buf.frequency, &buf.format) {
+            (DSBFREQUENCY_ORIGINAL, Some(format)) => format.nSamplesPerSec,
+            (frequency, _) => frequency,
+        };
+        DS_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn GetStatus(machine: &mut Machine, this: u32, lpdwStatus: Option<&mut u32>) -> u32 {
+        let now = machine.host.ticks();
+        let buf = update(machine, this, now);
+        *lpdwStatus.unwrap() = match &buf.playing {
+            Some(playback) if playback.looping => DSBSTATUS_PLAYING | DSBSTATUS_LOOPING,
+            Some(_) => DSBSTATUS_PLAYING,
+            None => 0,
+        };
+        DS_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Initialize(
+        _machine: &mut Machine,
+        this: u32,
+        lpDirectSound: u32,
+        lpcDSBufferDesc: Option<&DSBUFFERDESC>,
+    ) -> u32 {
+        // CreateSoundBuffer hands out initialized buffers.
+        DSERR_ALREADYINITIALIZED
+    }
+
     #[win32_derive::dllexport]
     pub fn Lock(
         machine: &mut Machine,
@@ -244,49 +742,103 @@ pub mod IDirectSoundBuffer {
         dwFlags: Result<DSBLOCK, u32>,
     ) -> u32 {
         let flags = dwFlags.unwrap();
-        if flags.contains(DSBLOCK::ENTIREBUFFER) {
-            let buf = machine.state.dsound.buffers.get_mut(&this).unwrap();
-            assert!(buf.lock.is_none());
-            *lplpvAudioPtr1.unwrap() = buf.addr;
-            *lpdwAudioBytes1.unwrap() = buf.size;
-            buf.lock = Some(Lock {
-                addr: buf.addr,
-                size: buf.size,
-            });
+        let now = machine.host.ticks();
+        let buf = update(machine, this, now);
+        let (cursor, len) = if flags.contains(DSBLOCK::ENTIREBUFFER) {
+            (0, buf.size)
+        } else if flags.contains(DSBLOCK::FROMWRITECURSOR) {
+            (buf.cursors(now).1, dwWriteBytes)
         } else {
-            todo!();
+            (dwWriteCursor, dwWriteBytes)
+        };
+        if cursor >= buf.size || len > buf.size {
+            return DSERR_INVALIDPARAM;
+        }
+        // A lock past the end of the buffer wraps around to its start.
+        let len1 = len.min(buf.size - cursor);
+        *lplpvAudioPtr1.unwrap() = buf.addr + cursor;
+        *lpdwAudioBytes1.unwrap() = len1;
+        let len2 = len - len1;
+        if let Some(ptr2) = lplpvAudioPtr2 {
+            *ptr2 = if len2 > 0 { buf.addr } else { 0 };
         }
+        if let Some(bytes2) = lpdwAudioBytes2 {
+            *bytes2 = len2;
+        }
+        buf.lock = Some(Lock {
+            addr: buf.addr + cursor,
+            size: len,
+        });
         DS_OK
     }
 
     #[win32_derive::dllexport]
     pub fn Play(
-        _machine: &mut Machine,
+        machine: &mut Machine,
         this: u32,
         dwReserved1: u32,
         dwReserved2: u32,
         dwFlags: u32,
     ) -> u32 {
-        assert_eq!(dwFlags, 1); // LOOPING
+        let now = machine.host.ticks();
+        let buf = machine.state.dsound.buffers.get_mut(&this).unwrap();
+        let Some(format) = &buf.format else {
+            return DS_OK; // primary buffer; nothing to mix
+        };
+        if buf.size == 0 {
+            return DS_OK;
+        }
+        if buf.audio.is_none() {
+            buf.audio = Some(machine.host.open_audio(&format.to_host()));
+        }
+        let looping = dwFlags & DSBPLAY_LOOPING != 0;
+        match &mut buf.playing {
+            // Playing again just updates the looping flag.
+            Some(playback) => playback.looping = looping,
+            None => buf.start(now, buf.position, looping),
+        }
+        buf.update(machine.emu.memory.mem(), now);
+        DS_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn SetCurrentPosition(machine: &mut Machine, this: u32, dwNewPosition: u32) -> u32 {
+        let now = machine.host.ticks();
+        let buf = update(machine, this, now);
+        if dwNewPosition >= buf.size {
+            return DSERR_INVALIDPARAM;
+        }
+        match &buf.playing {
+            // What the host has already been handed still plays first.
+            Some(playback) => buf.start(now, dwNewPosition, playback.looping),
+            None => buf.position = dwNewPosition,
+        }
+        DS_OK
+    }
+
+    #[win32_derive::dllexport]
+    pub fn Stop(machine: &mut Machine, this: u32) -> u32 {
+        let now = machine.host.ticks();
+        let buf = update(machine, this, now);
+        if buf.playing.is_some() {
+            buf.position = buf.cursors(now).0;
+            buf.playing = None;
+        }
         DS_OK
     }
 
     #[win32_derive::dllexport]
-    pub fn SetFormat(_machine: &mut Machine, this: u32, lpcfxFormat: Option<&WAVEFORMATEX>) -> u32 {
+    pub fn SetFormat(machine: &mut Machine, this: u32, lpcfxFormat: Option<&WAVEFORMATEX>) -> u32 {
         let fmt = lpcfxFormat.unwrap();
-        // Just check fmt is the one we support..
-        assert!(matches!(
-            fmt,
-            WAVEFORMATEX {
-                wFormatTag: 1, // PCM
-                nChannels: 2,
-                nSamplesPerSec: 44100,
-                nAvgBytesPerSec: 176400, // 4*44100
-                nBlockAlign: 4,
-                wBitsPerSample: 16,
-                cbSize: 0,
-            }
-        ));
+        const WAVE_FORMAT_PCM: u16 = 1;
+        if fmt.wFormatTag != WAVE_FORMAT_PCM {
+            log::warn!("unsupported sound format {fmt:?}");
+            return DSERR_INVALIDPARAM;
+        }
+        // This is only called on the primary buffer, which we don't mix into, but
+        // remember the format anyway.
+        let buf = machine.state.dsound.buffers.get_mut(&this).unwrap();
+        buf.format = Some(fmt.clone());
         DS_OK
     }
 
@@ -300,44 +852,128 @@ pub mod IDirectSoundBuffer {
         dwAudioBytes2: u32,
     ) -> u32 {
         let buf = machine.state.dsound.buffers.get_mut(&this).unwrap();
-        let lock = buf.lock.take().unwrap();
+        let Some(lock) = buf.lock.take() else {
+            return DSERR_INVALIDPARAM;
+        };
+        if lpvAudioPtr1 != lock.addr || dwAudioBytes1 + dwAudioBytes2 > lock.size {
+            return DSERR_INVALIDPARAM;
+        }
+        // The bytes were written in place, and are picked up as playback reaches them.
+        DS_OK
+    }
 
-        assert_eq!(lpvAudioPtr1, lock.addr);
-        assert_eq!(dwAudioBytes1, lock.size);
-        // TODO: handle case where these don't match
+    #[win32_derive::dllexport]
+    pub fn SetVolume(machine: &mut Machine, this: u32, lVolume: i32) -> u32 {
+        let buf = machine.state.dsound.buffers.get_mut(&this).unwrap();
+        if !buf.caps().contains(DSBCAPS::CTRLVOLUME) {
+            return DSERR_CONTROLUNAVAIL;
+        }
+        if !(DSBVOLUME_MIN..=DSBVOLUME_MAX).contains(&lVolume) {
+            return DSERR_INVALIDPARAM;
+        }
+        buf.volume = lVolume;
+        DS_OK
+    }
 
-        // Secondary lock not used.
-        assert_eq!(lpvAudioPtr2, 0);
-        assert_eq!(dwAudioBytes2, 0);
+    #[win32_derive::dllexport]
+    pub fn SetPan(machine: &mut Machine, this: u32, lPan: i32) -> u32 {
+        let buf = machine.state.dsound.buffers.get_mut(&this).unwrap();
+        if !buf.caps().contains(DSBCAPS::CTRLPAN) {
+            return DSERR_CONTROLUNAVAIL;
+        }
+        if !(DSBPAN_LEFT..=DSBPAN_RIGHT).contains(&lPan) {
+            return DSERR_INVALIDPARAM;
+        }
+        buf.pan = lPan;
+        DS_OK
+    }
 
+    #[win32_derive::dllexport]
+    pub fn SetFrequency(machine: &mut Machine, this: u32, dwFrequency: u32) -> u32 {
+        let buf = machine.state.dsound.buffers.get_mut(&this).unwrap();
+        if !buf.caps().contains(DSBCAPS::CTRLFREQUENCY) {
+            return DSERR_CONTROLUNAVAIL;
+        }
+        if dwFrequency != DSBFREQUENCY_ORIGINAL
+            && !(DSBFREQUENCY_MIN..=DSBFREQUENCY_MAX).contains(&dwFrequency)
+        {
+            return DSERR_INVALIDPARAM;
+        }
+        log::warn!("{this:x}->SetFrequency({dwFrequency}): host output isn't resampled");
+        buf.frequency = dwFrequency;
         DS_OK
     }
 
+    #[win32_derive::dllexport]
+    pub fn Restore(_machine: &mut Machine, this: u32) -> u32 {
+        DS_OK // our buffer memory is never lost
+    }
+
     vtable![
-        QueryInterface: todo,
-        AddRef: todo,
+        QueryInterface: ok,
+        AddRef: ok,
         Release: ok,
-        GetCaps: todo,
+        GetCaps: ok,
         GetCurrentPosition: ok,
-        GetFormat: todo,
-        GetVolume: todo,
-        GetPan: todo,
-        GetFrequency: todo,
+        GetFormat: ok,
+        GetVolume: ok,
+        GetPan: ok,
+        GetFrequency: ok,
         GetStatus: ok,
-        Initialize: todo,
+        Initialize: ok,
         Lock: ok,
         Play: ok,
-        SetCurrentPosition: todo,
+        SetCurrentPosition: ok,
         SetFormat: ok,
-        SetVolume: todo,
-        SetPan: todo,
-        SetFrequency: todo,
-        Stop: todo,
+        SetVolume: ok,
+        SetPan: ok,
+        SetFrequency: ok,
+        Stop: ok,
         Unlock: ok,
-        Restore: todo,
+        Restore: ok,
     ];
 }
 
+/// QueryInterface for objects that implement only IUnknown and the one interface iid.
+fn query_interface(
+    machine: &mut Machine,
+    this: u32,
+    iid: &GUID,
+    riid: Option<&GUID>,
+    ppvObject: Option<&mut u32>,
+) -> u32 {
+    let (Some(riid), Some(ppvObject)) = (riid, ppvObject) else {
+        return DSERR_INVALIDPARAM;
+    };
+    if *riid != IID_IUnknown && riid != iid {
+        *ppvObject = 0;
+        return E_NOINTERFACE;
+    }
+    ComObject::add_ref(machine.mem(), this);
+    *ppvObject = this;
+    DS_OK
+}
+
+/// Stop a buffer and free it, along with its samples unless a duplicate still uses them.
+fn free_buffer(machine: &mut Machine, this: u32) {
+    let dsound = &mut machine.state.dsound;
+    let mem = machine.emu.memory.mem();
+    // Dropping the buffer closes its host audio.
+    if let Some(buf) = dsound.buffers.remove(&this) {
+        if buf.addr != 0 && !dsound.buffers.values().any(|b| b.addr == buf.addr) {
+            dsound.heap.free(mem, buf.addr);
+        }
+    }
+    dsound.heap.free(mem, this);
+}
+
+/// Bring a buffer's playback up to date with the host clock time now, returning the buffer.
+fn update(machine: &mut Machine, this: u32, now: u32) -> &mut Buffer {
+    let buf = machine.state.dsound.buffers.get_mut(&this).unwrap();
+    buf.update(machine.emu.memory.mem(), now);
+    buf
+}
+
 #[win32_derive::dllexport(ordinal = 1)]
 pub fn DirectSoundCreate(
     machine: &mut Machine,
@@ -361,3 +997,239 @@ pub fn DirectSoundEnumerateA(_machine: &mut Machine, lpDSEnumCallback: u32, lpCo
     // No sound devices => no calling the callback.
     DS_OK
 }
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use super::*;
+    use crate::testing::{alloc_data, TestHost};
+    use std::{cell::RefCell, rc::Rc};
+
+    /// A machine whose host records the audio it's handed.
+    fn machine() -> (Machine, Rc<RefCell<Vec<u8>>>) {
+        let host = TestHost::default();
+        let audio = host.audio.clone();
+        (Machine::new(Box::new(host), "test.exe".into()), audio)
+    }
+
+    fn create_device(machine: &mut Machine) -> u32 {
+        let mut ds = 0;
+        assert_eq!(DirectSoundCreate(machine, None, Some(&mut ds), 0), DS_OK);
+        ds
+    }
+
+    /// Create a buffer of 8-bit mono samples at 1000Hz, so a byte plays for a millisecond,
+    /// holding its offset into the buffer in each sample.
+    fn create_buffer(machine: &mut Machine, ds: u32, flags: DSBCAPS, size: u32) -> u32 {
+        let format = WAVEFORMATEX {
+            wFormatTag: 1,
+            nChannels: 1,
+            nSamplesPerSec: 1000,
+            nAvgBytesPerSec: 1000,
+            nBlockAlign: 1,
+            wBitsPerSample: 8,
+            cbSize: 0,
+        };
+        let desc = DSBUFFERDESC {
+            dwSize: std::mem::size_of::<DSBUFFERDESC>() as u32,
+            dwFlags: flags,
+            dwBufferBytes: size,
+            dwReserved: 0,
+            lpwfxFormat: alloc_data(machine, &format.to_bytes()),
+        };
+        let mut buf = 0;
+        let ret = IDirectSound::CreateSoundBuffer(machine, ds, Some(&desc), Some(&mut buf), 0);
+        assert_eq!(ret, DS_OK);
+        let addr = machine.state.dsound.buffers[&buf].addr;
+        for (i, b) in machine.mem().sub32_mut(addr, size).iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        buf
+    }
+
+    fn advance(machine: &mut Machine, ticks: u32) {
+        machine.host.block(Some(ticks));
+    }
+
+    fn position(machine: &mut Machine, buf: u32) -> u32 {
+        let mut play = 0;
+        let ret = IDirectSoundBuffer::GetCurrentPosition(machine, buf, Some(&mut play), None);
+        assert_eq!(ret, DS_OK);
+        play
+    }
+
+    fn status(machine: &mut Machine, buf: u32) -> u32 {
+        let mut status = 0;
+        assert_eq!(
+            IDirectSoundBuffer::GetStatus(machine, buf, Some(&mut status)),
+            DS_OK
+        );
+        status
+    }
+
+    #[test]
+    fn plays_with_host_clock() {
+        let (mut machine, audio) = machine();
+        let ds = create_device(&mut machine);
+        let buf = create_buffer(&mut machine, ds, DSBCAPS::empty(), 1000);
+        assert_eq!(update_all(&mut machine), None);
+
+        // Play hands the host the first LEAD_MS of samples.
+        assert_eq!(IDirectSoundBuffer::Play(&mut machine, buf, 0, 0, 0), DS_OK);
+        assert_eq!(audio.borrow().len(), 100);
+
+        // The message loop keeps it fed without the game polling the buffer.
+        advance(&mut machine, 500);
+        assert_eq!(update_all(&mut machine), Some(550));
+        assert_eq!(audio.borrow().len(), 600);
+        assert_eq!(audio.borrow()[599], (599 % 256) as u8);
+
+        // A buffer that isn't looping stops at its end.
+        advance(&mut machine, 1200);
+        assert_eq!(update_all(&mut machine), None);
+        assert_eq!(audio.borrow().len(), 1000);
+        assert_eq!(status(&mut machine, buf), 0);
+        assert_eq!(position(&mut machine, buf), 0);
+    }
+
+    #[test]
+    fn stop_and_set_position() {
+        let (mut machine, audio) = machine();
+        let ds = create_device(&mut machine);
+        let buf = create_buffer(&mut machine, ds, DSBCAPS::empty(), 1000);
+
+        let looping = DSBPLAY_LOOPING;
+        IDirectSoundBuffer::Play(&mut machine, buf, 0, 0, looping);
+        advance(&mut machine, 300);
+        assert_eq!(IDirectSoundBuffer::Stop(&mut machine, buf), DS_OK);
+        assert_eq!(position(&mut machine, buf), 300);
+        assert_eq!(status(&mut machine, buf), 0);
+
+        // Playing again resumes where it stopped.
+        advance(&mut machine, 500);
+        assert_eq!(update_all(&mut machine), None);
+        IDirectSoundBuffer::Play(&mut machine, buf, 0, 0, looping);
+        assert_eq!(position(&mut machine, buf), 300);
+        assert_eq!(
+            status(&mut machine, buf),
+            DSBSTATUS_PLAYING | DSBSTATUS_LOOPING
+        );
+
+        // Moving the play cursor while playing carries on from there, wrapping around.
+        audio.borrow_mut().clear();
+        let set_position = IDirectSoundBuffer::SetCurrentPosition;
+        assert_eq!(set_position(&mut machine, buf, 900), DS_OK);
+        advance(&mut machine, 650);
+        assert_eq!(position(&mut machine, buf), 50);
+        assert_eq!(
+            audio.borrow()[..100],
+            (900..1000).map(|i| i as u8).collect::<Vec<_>>()
+        );
+        assert_eq!(audio.borrow()[100], 0);
+
+        assert_eq!(set_position(&mut machine, buf, 1000), DSERR_INVALIDPARAM);
+        IDirectSoundBuffer::Stop(&mut machine, buf);
+        assert_eq!(set_position(&mut machine, buf, 10), DS_OK);
+        assert_eq!(position(&mut machine, buf), 10);
+    }
+
+    #[test]
+    fn release() {
+        let (mut machine, _audio) = machine();
+        let ds = create_device(&mut machine);
+        let buf = create_buffer(&mut machine, ds, DSBCAPS::empty(), 1000);
+        let addr = machine.state.dsound.buffers[&buf].addr;
+
+        let mut dup = 0;
+        let ret = IDirectSound::DuplicateSoundBuffer(&mut machine, ds, buf, Some(&mut dup));
+        assert_eq!(ret, DS_OK);
+        assert_eq!(machine.state.dsound.buffers[&dup].addr, addr);
+
+        let mut obj = 0;
+        let query = IDirectSoundBuffer::QueryInterface;
+        assert_eq!(
+            query(&mut machine, buf, Some(&IID_IDirectSound), Some(&mut obj)),
+            E_NOINTERFACE
+        );
+        assert_eq!(
+            query(
+                &mut machine,
+                buf,
+                Some(&IID_IDirectSoundBuffer),
+                Some(&mut obj)
+            ),
+            DS_OK
+        );
+        assert_eq!(obj, buf);
+        assert_eq!(IDirectSoundBuffer::AddRef(&mut machine, buf), 3);
+
+        // The last Release stops and frees the buffer, but its duplicate keeps the samples.
+        IDirectSoundBuffer::Play(&mut machine, buf, 0, 0, DSBPLAY_LOOPING);
+        assert_eq!(IDirectSoundBuffer::Release(&mut machine, buf), 2);
+        assert_eq!(IDirectSoundBuffer::Release(&mut machine, buf), 1);
+        assert_eq!(update_all(&mut machine), Some(LEAD_MS as u32 / 2));
+        assert_eq!(IDirectSoundBuffer::Release(&mut machine, buf), 0);
+        assert!(!machine.state.dsound.buffers.contains_key(&buf));
+        assert_eq!(update_all(&mut machine), None);
+        let heap = &machine.state.dsound.heap;
+        assert_eq!(heap.size(machine.emu.memory.mem(), addr), 1000);
+
+        // Releasing the device releases its remaining buffers.
+        assert_eq!(IDirectSound::Release(&mut machine, ds), 0);
+        assert!(machine.state.dsound.buffers.is_empty());
+    }
+
+    #[test]
+    fn controls() {
+        let (mut machine, _audio) = machine();
+        let ds = create_device(&mut machine);
+        let plain = create_buffer(&mut machine, ds, DSBCAPS::empty(), 100);
+        let flags = DSBCAPS::CTRLVOLUME | DSBCAPS::CTRLPAN | DSBCAPS::CTRLFREQUENCY;
+        let buf = create_buffer(&mut machine, ds, flags, 100);
+
+        let mut value = 0i32;
+        let ret = IDirectSoundBuffer::GetVolume(&mut machine, plain, Some(&mut value));
+        assert_eq!(ret, DSERR_CONTROLUNAVAIL);
+        let ret = IDirectSoundBuffer::SetPan(&mut machine, plain, 0);
+        assert_eq!(ret, DSERR_CONTROLUNAVAIL);
+
+        let ret = IDirectSoundBuffer::SetVolume(&mut machine, buf, -600);
+        assert_eq!(ret, DS_OK);
+        let ret = IDirectSoundBuffer::SetVolume(&mut machine, buf, 1);
+        assert_eq!(ret, DSERR_INVALIDPARAM);
+        IDirectSoundBuffer::GetVolume(&mut machine, buf, Some(&mut value));
+        assert_eq!(value, -600);
+        let ret = IDirectSoundBuffer::SetPan(&mut machine, buf, DSBPAN_LEFT);
+        assert_eq!(ret, DS_OK);
+        IDirectSoundBuffer::GetPan(&mut machine, buf, Some(&mut value));
+        assert_eq!(value, DSBPAN_LEFT);
+
+        let mut frequency = 0;
+        IDirectSoundBuffer::GetFrequency(&mut machine, buf, Some(&mut frequency));
+        assert_eq!(frequency, 1000);
+        IDirectSoundBuffer::SetFrequency(&mut machine, buf, 22050);
+        IDirectSoundBuffer::GetFrequency(&mut machine, buf, Some(&mut frequency));
+        assert_eq!(frequency, 22050);
+        IDirectSoundBuffer::SetFrequency(&mut machine, buf, DSBFREQUENCY_ORIGINAL);
+        IDirectSoundBuffer::GetFrequency(&mut machine, buf, Some(&mut frequency));
+        assert_eq!(frequency, 1000);
+
+        let mut caps = DSBUFFERCAPS {
+            dwSize: std::mem::size_of::<DSBUFFERCAPS>() as u32,
+            ..Default::default()
+        };
+        let ret = IDirectSoundBuffer::GetCaps(&mut machine, buf, Some(&mut caps));
+        assert_eq!(ret, DS_OK);
+        assert_eq!(caps.dwFlags, (flags | DSBCAPS::LOCSOFTWARE).bits());
+        assert_eq!(caps.dwBufferBytes, 100);
+
+        // GetFormat with no buffer reports the size to allocate.
+        let mut size = 0;
+        let ret = IDirectSoundBuffer::GetFormat(&mut machine, buf, 0, 0, Some(&mut size));
+        assert_eq!(ret, DS_OK);
+        assert_eq!(size, 18);
+        let fmt = alloc_data(&mut machine, &[0; 18]);
+        let ret = IDirectSoundBuffer::GetFormat(&mut machine, buf, fmt, 18, Some(&mut size));
+        assert_eq!(ret, DS_OK);
+        assert_eq!(machine.mem().get_pod::<u32>(fmt + 4), 1000);
+    }
+}
@@ -5,16 +5,45 @@
 #[derive(Debug, win32_derive::TryFromEnum)]
 pub enum ERROR {
     SUCCESS = 0,
+    INVALID_FUNCTION = 1,
     FILE_NOT_FOUND = 2,
+    PATH_NOT_FOUND = 3,
     ACCESS_DENIED = 5,
     INVALID_HANDLE = 6,
+    NOT_ENOUGH_MEMORY = 8,
     INVALID_ACCESS = 12,
     INVALID_DATA = 13,
+    OUTOFMEMORY = 14,
+    NO_MORE_FILES = 18,
     OUT_OF_PAPER = 28,
+    HANDLE_EOF = 38,
+    NOT_SUPPORTED = 50,
     FILE_EXISTS = 80,
+    INVALID_PARAMETER = 87,
     OPEN_FAILED = 110,
+    INSUFFICIENT_BUFFER = 122,
+    INVALID_NAME = 123,
     MOD_NOT_FOUND = 126,
+    PROC_NOT_FOUND = 127,
+    NOT_LOCKED = 158,
     ALREADY_EXISTS = 183,
+    ENVVAR_NOT_FOUND = 203,
+    MORE_DATA = 234,
+    NO_MORE_ITEMS = 259,
+    DIRECTORY = 267,
+    NOT_OWNER = 288,
+    INVALID_ADDRESS = 487,
+    NOACCESS = 998,
+    FILE_INVALID = 1006,
+    MAPPED_ALIGNMENT = 1132,
+    INVALID_WINDOW_HANDLE = 1400,
+    CANNOT_FIND_WND_CLASS = 1407,
+    CLASS_ALREADY_EXISTS = 1410,
+    INVALID_INDEX = 1413,
+    CONTROL_ID_NOT_FOUND = 1421,
+    RESOURCE_DATA_NOT_FOUND = 1812,
+    RESOURCE_TYPE_NOT_FOUND = 1813,
+    RESOURCE_NAME_NOT_FOUND = 1814,
 }
 
 impl From<std::io::Error> for ERROR {
@@ -1,15 +1,18 @@
-use super::{BitmapType, DCTarget, Object, BITMAPINFOHEADER, HDC, HGDIOBJ};
+use super::{BitmapType, DCTarget, Object, BITMAPINFOHEADER, DC, HDC, HGDIOBJ};
 use crate::{
     machine::Machine,
     winapi::{
-        bitmap::{BitmapMono, BitmapRGBA32, PixelData, BI},
+        bitmap::{BitmapMono, BitmapPal8, BitmapRGBA32, PixelData},
         kernel32,
     },
 };
+use memory::{Extensions, Mem};
 use std::cmp::min;
 
 const TRACE_CONTEXT: &'static str = "gdi32/bitmap";
 
+#[repr(C)]
+#[derive(Clone)]
 #[allow(dead_code)]
 pub struct BITMAP {
     pub bmType: u32,
@@ -22,7 +25,28 @@ pub struct BITMAP {
 }
 unsafe impl memory::Pod for BITMAP {}
 
-/// Copy pixels from src to dst.  Asserts that everything has been appropriately clipped.
+#[repr(C)]
+#[derive(Clone)]
+pub struct DIBSECTION {
+    pub dsBm: BITMAP,
+    pub dsBmih: BITMAPINFOHEADER,
+    pub dsBitfields: [u32; 3],
+    pub dshSection: u32,
+    pub dsOffset: u32,
+}
+unsafe impl memory::Pod for DIBSECTION {}
+
+/// Compute the result of a raster op on a single pixel.
+fn rop_pixel(d: [u8; 4], s: [u8; 4], rop: &RasterOp) -> [u8; 4] {
+    match rop {
+        RasterOp::SRCCOPY => s,
+        RasterOp::NOTSRCCOPY => [!s[0], !s[1], !s[2], s[3]],
+        RasterOp::SRCAND => [d[0] & s[0], d[1] & s[1], d[2] & s[2], d[3] & s[3]],
+        _ => unreachable!("{rop:?} ignores the source, so PatBlt handles it"),
+    }
+}
+
+/// Copy pixels from src to dst, clipping to both.
 /// flush_alpha is true when the output drops alpha channel (e.g. Window backing store).
 fn bit_blt(
     dst: &mut [[u8; 4]],
@@ -38,6 +62,9 @@ fn bit_blt(
     flush_alpha: bool,
     rop: RasterOp,
 ) {
+    if dstride == 0 || sstride == 0 {
+        return;
+    }
     let min_x = min(dx, sx);
     let min_y = min(dy, sy);
     if min_x < 0 {
@@ -52,6 +79,8 @@ fn bit_blt(
     }
     w = min(w, dstride as isize - dx);
     w = min(w, sstride as isize - sx);
+    h = min(h, (dst.len() / dstride) as isize - dy);
+    h = min(h, (src.len() / sstride) as isize - sy);
     if w <= 0 || h <= 0 {
         return;
     }
@@ -59,32 +88,17 @@ fn bit_blt(
     for row in 0..h {
         let dst_off = ((dy + row) * dstride as isize) + dx;
         let src_off = ((sy + row) * sstride as isize) + sx;
-        if dst_off + w > dst.len() as isize || src_off + w > src.len() as isize {
-            break;
-        }
         let dst_row = &mut dst[dst_off as usize..][..w as usize];
         let src_row = &src[src_off as usize..][..w as usize];
         match rop {
             RasterOp::SRCCOPY => {
                 dst_row.copy_from_slice(src_row);
             }
-            RasterOp::NOTSRCCOPY => {
+            _ => {
                 for (d, s) in dst_row.iter_mut().zip(src_row.iter()) {
-                    d[0] = !s[0];
-                    d[1] = !s[1];
-                    d[2] = !s[2];
-                    d[3] = s[3];
+                    *d = rop_pixel(*d, *s, &rop);
                 }
             }
-            RasterOp::SRCAND => {
-                for (d, s) in dst_row.iter_mut().zip(src_row.iter()) {
-                    d[0] &= s[0];
-                    d[1] &= s[1];
-                    d[2] &= s[2];
-                    d[3] &= s[3];
-                }
-            }
-            _ => todo!("unimplemented BitBlt with rop={rop:?}"),
         }
         if flush_alpha {
             for p in dst_row {
@@ -94,6 +108,63 @@ fn bit_blt(
     }
 }
 
+/// A rectangle edge and extent as passed to StretchBlt, where a negative extent runs
+/// leftward/upward from the edge, normalized to a start and a non-negative extent.
+fn span(start: isize, len: isize) -> (isize, isize) {
+    if len < 0 {
+        (start + len, -len)
+    } else {
+        (start, len)
+    }
+}
+
+/// Copy a src rectangle to a differently-sized dst rectangle, sampling nearest pixels.
+/// Pixels that land outside of either bitmap are skipped.  As with StretchBlt, the
+/// image is mirrored along an axis when the src and dst extents along it differ in sign.
+fn stretch_blt(
+    dst: &mut [[u8; 4]],
+    dx: isize,
+    dy: isize,
+    dstride: usize,
+    dw: isize,
+    dh: isize,
+    src: &[[u8; 4]],
+    sx: isize,
+    sy: isize,
+    sstride: usize,
+    sw: isize,
+    sh: isize,
+    rop: RasterOp,
+) {
+    if dstride == 0 || sstride == 0 {
+        return;
+    }
+    let (mirror_x, mirror_y) = ((dw < 0) != (sw < 0), (dh < 0) != (sh < 0));
+    let ((dx, dw), (dy, dh)) = (span(dx, dw), span(dy, dh));
+    let ((sx, sw), (sy, sh)) = (span(sx, sw), span(sy, sh));
+    let dst_height = (dst.len() / dstride) as isize;
+    let src_height = (src.len() / sstride) as isize;
+    // Only visit the part of the dst rectangle within the dst bitmap.
+    for ty in dy.max(0)..(dy + dh).min(dst_height) {
+        let row = if mirror_y { dy + dh - 1 - ty } else { ty - dy };
+        let fy = sy + row * sh / dh;
+        if fy < 0 || fy >= src_height {
+            continue;
+        }
+        for tx in dx.max(0)..(dx + dw).min(dstride as isize) {
+            let col = if mirror_x { dx + dw - 1 - tx } else { tx - dx };
+            let fx = sx + col * sw / dw;
+            if fx < 0 || fx >= sstride as isize {
+                continue;
+            }
+            let s = src[(fy * sstride as isize + fx) as usize];
+            let d = &mut dst[(ty * dstride as isize + tx) as usize];
+            *d = rop_pixel(*d, s, &rop);
+            d[3] = 0xFF;
+        }
+    }
+}
+
 fn pat_blt(
     dst: &mut [[u8; 4]],
     mut x: isize,
@@ -112,14 +183,16 @@ fn pat_blt(
         h += y;
         y = 0;
     }
+    if stride == 0 {
+        return;
+    }
+    w = min(w, stride as isize - x);
+    h = min(h, (dst.len() / stride) as isize - y);
     if w <= 0 || h <= 0 {
         return;
     }
     for row in 0..h {
         let dst_off = ((y + row) * stride as isize) + x;
-        if dst_off + w > dst.len() as isize {
-            break;
-        }
         let dst_row = &mut dst[dst_off as usize..][..w as usize];
         match rop {
             RasterOp::PATCOPY => {
@@ -128,7 +201,10 @@ fn pat_blt(
             RasterOp::BLACKNESS => {
                 dst_row.fill([0, 0, 0, 0xFF]);
             }
-            _ => todo!("unimplemented PatBlt with rop={rop:?}"),
+            RasterOp::WHITENESS => {
+                dst_row.fill([0xFF, 0xFF, 0xFF, 0xFF]);
+            }
+            _ => unreachable!("PatBlt with source rop {rop:?}"),
         }
     }
 }
@@ -140,118 +216,171 @@ pub enum RasterOp {
     SRCAND = 0x8800c6,
     PATCOPY = 0xf00021,
     BLACKNESS = 0x000042,
+    WHITENESS = 0xff0062,
 }
 
-#[win32_derive::dllexport]
-pub fn BitBlt(
-    machine: &mut Machine,
-    hdc: HDC,
-    x: i32,
-    y: i32,
-    cx: u32,
-    cy: u32,
-    hdcSrc: HDC,
-    x1: i32,
-    y1: i32,
-    rop: Result<RasterOp, u32>,
-) -> bool {
-    let rop = rop.unwrap();
-    if rop == RasterOp::BLACKNESS {
-        // It seems like passing null as `hdcSrc` when using BLACKNESS is supported on Windows.
-        return PatBlt(
-            machine,
-            hdc,
-            x,
-            y,
-            cx as i32,
-            cy as i32,
-            Ok(RasterOp::BLACKNESS),
-        );
+impl RasterOp {
+    /// Whether the op only depends on the destination and brush, so PatBlt can do it.
+    fn is_pattern(&self) -> bool {
+        matches!(
+            self,
+            RasterOp::PATCOPY | RasterOp::BLACKNESS | RasterOp::WHITENESS
+        )
     }
+}
 
-    let src_dc = machine.state.gdi32.dcs.get(hdcSrc).unwrap();
-    let src_bitmap = match src_dc.target {
+/// Get a copy of the bitmap to read from for a blit with the given source DC.
+fn src_bitmap(machine: &mut Machine, hdc: HDC) -> Option<BitmapRGBA32> {
+    let Some(dc) = machine.state.gdi32.dcs.get(hdc) else {
+        log::warn!("blit from invalid DC {hdc:?}");
+        return None;
+    };
+    Some(match dc.target {
         DCTarget::Memory(bitmap) => {
             let obj = machine.state.gdi32.objects.get(bitmap).unwrap();
             match obj {
-                Object::Bitmap(BitmapType::RGBA32(bmp)) => bmp.clone(),
-                _ => unimplemented!("{:?}", obj),
+                Object::Bitmap(BitmapType::RGBA32(bmp)) => {
+                    bmp.to_top_down(machine.emu.memory.mem())
+                }
+                Object::Bitmap(BitmapType::Pal8(bmp)) => bmp.to_rgba32(machine.emu.memory.mem()),
+                _ => {
+                    log::warn!("TODO: blit from {obj:?}");
+                    return None;
+                }
             }
         }
         DCTarget::Window(hwnd) => {
             let window = machine.state.user32.windows.get_mut(hwnd).unwrap();
             window.bitmap_mut().clone()
         }
-        _ => todo!(),
-    };
-    let src = src_bitmap.pixels_slice(machine.emu.memory.mem());
+        DCTarget::DirectDrawSurface(_) => {
+            log::warn!("TODO: blit from DirectDraw surface DC");
+            return None;
+        }
+    })
+}
 
-    let dst_dc = machine.state.gdi32.dcs.get(hdc).unwrap();
-    match dst_dc.target {
+/// Run f over the pixels backing a destination DC, flushing them to the screen afterwards
+/// for window DCs.  f receives the pixels, their stride, and memory for reading the source.
+/// Returns false, without calling f, for DCs whose pixels we can't draw into.
+pub(super) fn with_dst_pixels(
+    machine: &mut Machine,
+    hdc: HDC,
+    f: impl FnOnce(&mut [[u8; 4]], usize, Mem),
+) -> bool {
+    let Some(dc) = machine.state.gdi32.dcs.get(hdc) else {
+        log::warn!("blit to invalid DC {hdc:?}");
+        return false;
+    };
+    let mem = machine.emu.memory.mem();
+    match dc.target {
         DCTarget::Memory(obj) => {
-            let dst = match machine.state.gdi32.objects.get_mut(obj).unwrap() {
-                Object::Bitmap(BitmapType::RGBA32(bmp)) => bmp,
-                _ => unimplemented!("{:?}", obj),
-            };
-
-            bit_blt(
-                dst.pixels.as_slice_mut(),
-                x as isize,
-                y as isize,
-                dst.width as usize,
-                cx as isize,
-                cy as isize,
-                src,
-                x1 as isize,
-                y1 as isize,
-                src_bitmap.width as usize,
-                true,
-                rop,
-            );
+            match machine.state.gdi32.objects.get_mut(obj).unwrap() {
+                Object::Bitmap(BitmapType::RGBA32(dst)) if dst.top_down => {
+                    f(dst.pixels.as_slice_mut(mem), dst.width as usize, mem);
+                }
+                Object::Bitmap(BitmapType::RGBA32(dst)) => {
+                    // Draw into a top-down copy, then store it back bottom-up.
+                    let mut rgba = dst.to_top_down(mem);
+                    f(rgba.pixels.as_slice_mut(mem), rgba.width as usize, mem);
+                    dst.store_top_down(mem, rgba.pixels_slice(mem));
+                }
+                Object::Bitmap(BitmapType::Pal8(dst)) => {
+                    // Draw into an expanded copy, then map the result back into the palette.
+                    let mut rgba = dst.to_rgba32(mem);
+                    f(rgba.pixels.as_slice_mut(mem), rgba.width as usize, mem);
+                    dst.store_rgba32(mem, rgba.pixels_slice(mem));
+                }
+                bmp => {
+                    log::warn!("TODO: draw to {bmp:?}");
+                    return false;
+                }
+            }
         }
         DCTarget::Window(hwnd) => {
             let window = machine.state.user32.windows.get_mut(hwnd).unwrap();
             let dst = window.bitmap_mut();
+            f(dst.pixels.as_slice_mut(mem), dst.width as usize, mem);
+            window.expect_toplevel_mut().flush_pixels(mem);
+        }
+        DCTarget::DirectDrawSurface(_) => {
+            log::warn!("TODO: draw to DirectDraw surface DC");
+            return false;
+        }
+    }
+    true
+}
 
-            // Clip to src/dst regions.
-            if x >= dst.width as i32
-                || x1 >= src_bitmap.width as i32
-                || y >= dst.height as i32
-                || y1 >= src_bitmap.height as i32
-            {
-                return true;
-            }
-
-            bit_blt(
-                dst.pixels.as_slice_mut(),
-                x as isize,
-                y as isize,
-                dst.width as usize,
-                cx as isize,
-                cy as isize,
-                src,
-                x1 as isize,
-                y1 as isize,
-                src_bitmap.width as usize,
-                true,
-                rop,
-            );
+#[win32_derive::dllexport]
+pub fn BitBlt(
+    machine: &mut Machine,
+    hdc: HDC,
+    x: i32,
+    y: i32,
+    cx: u32,
+    cy: u32,
+    hdcSrc: HDC,
+    x1: i32,
+    y1: i32,
+    rop: Result<RasterOp, u32>,
+) -> bool {
+    let Ok(rop) = rop else {
+        log::warn!("BitBlt: unsupported rop {rop:x?}");
+        return false;
+    };
+    if rop.is_pattern() {
+        // These ignore the source, and passing null as `hdcSrc` is supported on Windows.
+        return PatBlt(machine, hdc, x, y, cx as i32, cy as i32, Ok(rop));
+    }
 
-            window
-                .expect_toplevel_mut()
-                .flush_pixels(machine.emu.memory.mem());
-        }
-        DCTarget::DirectDrawSurface(ptr) => {
-            let surface = machine.state.ddraw.surfaces.get_mut(&ptr).unwrap();
+    let Some(src_bitmap) = src_bitmap(machine, hdcSrc) else {
+        return false;
+    };
 
-            assert!(x == 0 && y == 0 && x1 == 0 && y1 == 0);
-            assert!(cx == surface.width && cy == surface.height);
-            assert!(surface.width == src_bitmap.width && surface.height == src_bitmap.height);
+    if let Some(&DC {
+        target: DCTarget::DirectDrawSurface(ptr),
+        ..
+    }) = machine.state.gdi32.dcs.get(hdc)
+    {
+        let surface = &machine.state.ddraw.surfaces[&ptr];
 
-            surface.host.write_pixels(src);
+        // Only whole-surface copies are supported, as used to show a loading screen.
+        if x != 0
+            || y != 0
+            || x1 != 0
+            || y1 != 0
+            || (cx, cy) != (surface.width, surface.height)
+            || (src_bitmap.width, src_bitmap.height) != (surface.width, surface.height)
+        {
+            log::warn!("TODO: partial BitBlt to DirectDraw surface");
+            return false;
         }
+
+        machine
+            .state
+            .ddraw
+            .buffer_mut(ptr)
+            .host
+            .write_pixels(src_bitmap.pixels_slice(machine.emu.memory.mem()));
+        return true;
     }
-    true
+
+    with_dst_pixels(machine, hdc, |dst, dstride, mem| {
+        bit_blt(
+            dst,
+            x as isize,
+            y as isize,
+            dstride,
+            cx as isize,
+            cy as isize,
+            src_bitmap.pixels_slice(mem),
+            x1 as isize,
+            y1 as isize,
+            src_bitmap.width as usize,
+            true,
+            rop,
+        );
+    })
 }
 
 #[win32_derive::dllexport]
@@ -260,21 +389,68 @@ pub fn StretchBlt(
     hdcDest: HDC,
     xDest: i32,
     yDest: i32,
-    wDest: u32,
-    hDest: u32,
+    wDest: i32,
+    hDest: i32,
     hdcSrc: HDC,
     xSrc: i32,
     ySrc: i32,
-    wSrc: u32,
-    hSrc: u32,
+    wSrc: i32,
+    hSrc: i32,
     rop: Result<RasterOp, u32>,
 ) -> bool {
-    if wDest != wSrc || hDest != hSrc {
-        todo!("unimp: StretchBlt with actual stretching");
+    if wDest == wSrc && hDest == hSrc && wDest >= 0 && hDest >= 0 {
+        return BitBlt(
+            machine,
+            hdcDest,
+            xDest,
+            yDest,
+            wDest as u32,
+            hDest as u32,
+            hdcSrc,
+            xSrc,
+            ySrc,
+            rop,
+        );
     }
-    BitBlt(
-        machine, hdcDest, xDest, yDest, wDest, hDest, hdcSrc, xSrc, ySrc, rop,
-    )
+    let Ok(rop) = rop else {
+        log::warn!("StretchBlt: unsupported rop {rop:x?}");
+        return false;
+    };
+    if rop.is_pattern() {
+        // Without a source there's nothing to mirror; just fill the dst rectangle.
+        let (x, w) = span(xDest as isize, wDest as isize);
+        let (y, h) = span(yDest as isize, hDest as isize);
+        return PatBlt(
+            machine,
+            hdcDest,
+            x as i32,
+            y as i32,
+            w as i32,
+            h as i32,
+            Ok(rop),
+        );
+    }
+
+    let Some(src_bitmap) = src_bitmap(machine, hdcSrc) else {
+        return false;
+    };
+    with_dst_pixels(machine, hdcDest, |dst, dstride, mem| {
+        stretch_blt(
+            dst,
+            xDest as isize,
+            yDest as isize,
+            dstride,
+            wDest as isize,
+            hDest as isize,
+            src_bitmap.pixels_slice(mem),
+            xSrc as isize,
+            ySrc as isize,
+            src_bitmap.width as usize,
+            wSrc as isize,
+            hSrc as isize,
+            rop,
+        );
+    })
 }
 
 #[win32_derive::dllexport]
@@ -287,7 +463,14 @@ pub fn PatBlt(
     h: i32,
     rop: Result<RasterOp, u32>,
 ) -> bool {
-    let rop = rop.unwrap();
+    let Ok(rop) = rop else {
+        log::warn!("PatBlt: unsupported rop {rop:x?}");
+        return false;
+    };
+    if !rop.is_pattern() {
+        log::warn!("PatBlt: {rop:?} needs a source");
+        return false;
+    }
     let Some(dc) = machine.state.gdi32.dcs.get(hdc) else {
         log::warn!("PatBlt: ignoring invalid DC {hdc:?}");
         return false;
@@ -307,10 +490,19 @@ pub fn PatBlt(
         DCTarget::Memory(hbitmap) => {
             let bitmap = match machine.state.gdi32.objects.get_mut(hbitmap).unwrap() {
                 Object::Bitmap(BitmapType::RGBA32(bmp)) => bmp,
-                _ => unimplemented!(),
+                bmp => {
+                    log::warn!("TODO: PatBlt to {bmp:?}");
+                    return false;
+                }
+            };
+            // A bottom-up bitmap's rows are stored in reverse.
+            let y = if bitmap.top_down {
+                y
+            } else {
+                bitmap.height as i32 - y - h
             };
             pat_blt(
-                bitmap.pixels.as_slice_mut(),
+                bitmap.pixels.as_slice_mut(machine.emu.memory.mem()),
                 x as isize,
                 y as isize,
                 bitmap.width as usize,
@@ -328,7 +520,7 @@ pub fn PatBlt(
             let window = machine.state.user32.windows.get_mut(hwnd).unwrap();
             let bitmap = window.bitmap_mut();
             pat_blt(
-                bitmap.pixels.as_slice_mut(),
+                bitmap.pixels.as_slice_mut(machine.emu.memory.mem()),
                 x as isize,
                 y as isize,
                 bitmap.width as usize,
@@ -341,7 +533,10 @@ pub fn PatBlt(
                 .expect_toplevel_mut()
                 .flush_pixels(machine.emu.memory.mem());
         }
-        _ => todo!(),
+        DCTarget::DirectDrawSurface(_) => {
+            log::warn!("TODO: PatBlt to DirectDraw surface DC");
+            return false;
+        }
     };
     true
 }
@@ -375,41 +570,128 @@ pub fn CreateBitmap(
 }
 
 const DIB_RGB_COLORS: u32 = 0;
-// const DIB_PAL_COLORS: u32 = 1;
+const DIB_PAL_COLORS: u32 = 1;
+
+/// Check that a DIB is in a format we can read, logging why not.
+fn check_dib(func: &str, header: &BITMAPINFOHEADER, usage: u32) -> bool {
+    let paletted = matches!(header.biBitCount, 1 | 4 | 8);
+    if !(usage == DIB_RGB_COLORS || usage == DIB_PAL_COLORS && paletted) {
+        log::warn!("TODO: {func} with color usage {usage}");
+        return false;
+    }
+    if !header.is_decodable() {
+        log::warn!(
+            "TODO: {func} with {}bpp, compression {:#x}",
+            header.biBitCount,
+            header.biCompression
+        );
+        return false;
+    }
+    true
+}
+
+/// The RGBA colors of a paletted DIB's color table.  With DIB_PAL_COLORS the table
+/// holds WORD indices into the DC's selected palette, or into the realized screen
+/// palette if the DC has none selected; None if hdc isn't a DC.
+fn dib_palette(
+    machine: &Machine,
+    hdc: HDC,
+    pbmi: u32,
+    header: &BITMAPINFOHEADER,
+    usage: u32,
+) -> Option<Box<[[u8; 4]]>> {
+    let count = match header.biClrUsed {
+        0 => 1 << header.biBitCount,
+        n => n,
+    };
+    let mem = machine.mem();
+    let table = pbmi + header.biSize;
+    if usage != DIB_PAL_COLORS {
+        // RGBQUAD is BGRx.
+        return Some(
+            mem.iter_pod::<[u8; 4]>(table, count)
+                .map(|[b, g, r, _]| [r, g, b, 0xFF])
+                .collect(),
+        );
+    }
+    let gdi32 = &machine.state.gdi32;
+    let dc = gdi32.dcs.get(hdc)?;
+    let entries = match gdi32.objects.get(dc.palette) {
+        Some(Object::Palette(palette)) => &palette.entries,
+        _ => &gdi32.screen_palette,
+    };
+    Some(
+        mem.iter_pod::<u16>(table, count)
+            .map(|i| match entries.get(i as usize) {
+                Some(entry) => entry.to_rgba(),
+                None => [0, 0, 0, 0xFF],
+            })
+            .collect(),
+    )
+}
+
+/// Parse the given lines of a DIB for drawing on hdc, looking up its colors per usage.
+fn parse_dib(
+    machine: &Machine,
+    hdc: HDC,
+    pbmi: u32,
+    header: &BITMAPINFOHEADER,
+    usage: u32,
+    bits: u32,
+    lines: u32,
+) -> Option<BitmapRGBA32> {
+    let mem = machine.mem();
+    if usage != DIB_PAL_COLORS {
+        return Some(BitmapRGBA32::parse(
+            mem.slice(pbmi..),
+            Some((mem.slice(bits..), lines as usize)),
+        ));
+    }
+    let palette = dib_palette(machine, hdc, pbmi, header, usage)?;
+    Some(BitmapRGBA32::parse_with_palette(
+        mem.slice(pbmi..),
+        &palette,
+        mem.slice(bits..),
+        lines as usize,
+    ))
+}
 
 #[win32_derive::dllexport]
 pub fn CreateDIBSection(
     machine: &mut Machine,
     hdc: HDC,
-    pbmi: Option<&BITMAPINFOHEADER>,
+    pbmi: u32, // BITMAPINFO*
     usage: u32,
     ppvBits: Option<&mut u32>, // **void
     hSection: u32,
     offset: u32,
 ) -> HGDIOBJ {
-    if usage != DIB_RGB_COLORS {
-        todo!()
-    }
     if hSection != 0 || offset != 0 {
-        todo!()
+        log::warn!("TODO: CreateDIBSection backed by a file mapping");
+        return HGDIOBJ::null();
     }
 
-    let bi = &pbmi.unwrap();
+    let bi = machine.mem().get_pod::<BITMAPINFOHEADER>(pbmi);
     if bi.biSize != std::mem::size_of::<BITMAPINFOHEADER>() as u32 {
-        todo!()
+        log::warn!("TODO: CreateDIBSection with header size {}", bi.biSize);
+        return HGDIOBJ::null();
     }
-    if !bi.is_top_down() {
-        log::warn!("CreateDIBSection: bitmap may need flipping");
+    // TODO: with BI_BITFIELDS, ought to check that .bmiColors masks are the RGBX we expect.
+    if !check_dib("CreateDIBSection", &bi, usage) {
+        return HGDIOBJ::null();
     }
-    if bi.biBitCount != 32 {
-        todo!()
+    if !matches!(bi.biBitCount, 8 | 32) {
+        log::warn!("TODO: CreateDIBSection with {}bpp", bi.biBitCount);
+        return HGDIOBJ::null();
     }
-    match bi.compression().unwrap() {
-        BI::BITFIELDS => {
-            // TODO: ought to check that .bmiColors masks are the RGBX we expect.
+
+    let palette = if bi.biBitCount == 8 {
+        match dib_palette(machine, hdc, pbmi, &bi, usage) {
+            Some(palette) => palette,
+            None => return HGDIOBJ::null(),
         }
-        BI::RGB => {} // ok
-        _ => todo!(),
+    } else {
+        Default::default()
     };
 
     let byte_count = bi.stride() as u32 * bi.height();
@@ -421,23 +703,35 @@ pub fn CreateDIBSection(
         byte_count,
     );
 
-    *ppvBits.unwrap() = pixels;
-
-    let bitmap = BitmapRGBA32 {
-        width: bi.width(),
-        height: bi.height(),
-        pixels: PixelData::Ptr(pixels, byte_count),
+    let bitmap = match bi.biBitCount {
+        32 => BitmapType::RGBA32(BitmapRGBA32 {
+            width: bi.width(),
+            height: bi.height(),
+            top_down: bi.is_top_down(),
+            pixels: PixelData::Ptr(pixels, byte_count),
+        }),
+        8 => BitmapType::Pal8(BitmapPal8 {
+            width: bi.width(),
+            height: bi.height(),
+            stride: bi.stride() as u32,
+            top_down: bi.is_top_down(),
+            palette,
+            pixels: PixelData::Ptr(pixels, byte_count),
+        }),
+        _ => unreachable!(),
     };
-    machine
-        .state
-        .gdi32
-        .objects
-        .add(Object::Bitmap(BitmapType::RGBA32(bitmap)))
+
+    if let Some(ppvBits) = ppvBits {
+        *ppvBits = pixels;
+    }
+    machine.state.gdi32.objects.add(Object::Bitmap(bitmap))
 }
 
 #[win32_derive::dllexport]
 pub fn CreateCompatibleBitmap(machine: &mut Machine, hdc: HDC, cx: u32, cy: u32) -> HGDIOBJ {
-    let dc = machine.state.gdi32.dcs.get(hdc).unwrap();
+    let Some(dc) = machine.state.gdi32.dcs.get(hdc) else {
+        return HGDIOBJ::null();
+    };
     match dc.target {
         DCTarget::Memory(hbitmap) => match machine.state.gdi32.objects.get_mut(hbitmap).unwrap() {
             Object::Bitmap(BitmapType::RGBA32(_)) => {}
@@ -451,8 +745,7 @@ pub fn CreateCompatibleBitmap(machine: &mut Machine, hdc: HDC, cx: u32, cy: u32)
             }
             _ => todo!(),
         },
-        DCTarget::Window(_) => {} // screen has known format
-        _ => todo!(),
+        DCTarget::Window(_) | DCTarget::DirectDrawSurface(_) => {} // screen has known format
     };
 
     let mut pixels = Vec::new();
@@ -460,6 +753,7 @@ pub fn CreateCompatibleBitmap(machine: &mut Machine, hdc: HDC, cx: u32, cy: u32)
     let bitmap = BitmapRGBA32 {
         width: cx,
         height: cy,
+        top_down: true,
         pixels: PixelData::Owned(pixels.into_boxed_slice()),
     };
     machine
@@ -473,72 +767,52 @@ pub fn CreateCompatibleBitmap(machine: &mut Machine, hdc: HDC, cx: u32, cy: u32)
 pub fn SetDIBitsToDevice(
     machine: &mut Machine,
     hdc: HDC,
-    xDest: u32,
-    yDest: u32,
+    xDest: i32,
+    yDest: i32,
     w: u32,
     h: u32,
-    xSrc: u32,
-    ySrc: u32,
+    xSrc: i32,
+    ySrc: i32,
     StartScan: u32,
     cLines: u32,
     lpvBits: u32,
     lpbmi: u32,
     ColorUse: u32,
 ) -> u32 {
-    if StartScan != ySrc || cLines != h {
-        todo!()
+    if StartScan as i32 != ySrc || cLines != h {
+        log::warn!("TODO: SetDIBitsToDevice with partial scan lines");
+        return 0;
     }
-    if ColorUse != DIB_RGB_COLORS {
-        todo!();
+    let header = machine.mem().get_pod::<BITMAPINFOHEADER>(lpbmi);
+    if !check_dib("SetDIBitsToDevice", &header, ColorUse) {
+        return 0;
     }
-    let src_bitmap = BitmapRGBA32::parse(
-        machine.mem().slice(lpbmi..),
-        Some((machine.mem().slice(lpvBits..), cLines as usize)),
-    );
-    let src = src_bitmap.pixels_slice(machine.emu.memory.mem());
-
-    let dc = machine.state.gdi32.dcs.get(hdc).unwrap();
-    let (dst, flush_alpha) = match dc.target {
-        DCTarget::Memory(hbitmap) => match machine.state.gdi32.objects.get_mut(hbitmap).unwrap() {
-            Object::Bitmap(BitmapType::RGBA32(b)) => (b, false),
-            _ => todo!(),
-        },
-        DCTarget::Window(hwnd) => {
-            let window = machine.state.user32.windows.get_mut(hwnd).unwrap();
-            (window.bitmap_mut(), true)
-        }
-        _ => todo!(),
+    let Some(src_bitmap) = parse_dib(machine, hdc, lpbmi, &header, ColorUse, lpvBits, cLines)
+    else {
+        return 0;
     };
 
-    bit_blt(
-        &mut dst.pixels.as_slice_mut(),
-        xDest as isize,
-        yDest as isize,
-        dst.width as usize,
-        w as isize,
-        std::cmp::min(h, dst.height - yDest) as isize,
-        src,
-        xSrc as isize,
-        ySrc as isize,
-        src_bitmap.width as usize,
-        flush_alpha,
-        RasterOp::SRCCOPY,
-    );
-
-    match dc.target {
-        DCTarget::Window(hwnd) => {
-            let window = machine
-                .state
-                .user32
-                .windows
-                .get_mut(hwnd)
-                .unwrap()
-                .expect_toplevel_mut();
-            window.flush_pixels(machine.emu.memory.mem());
-        }
-        _ => {}
+    // The parsed bitmap holds just the cLines scan lines starting at ySrc, so it is
+    // copied from its origin.
+    let ok = with_dst_pixels(machine, hdc, |dst, dstride, mem| {
+        bit_blt(
+            dst,
+            xDest as isize,
+            yDest as isize,
+            dstride,
+            w as isize,
+            h as isize,
+            src_bitmap.pixels_slice(mem),
+            xSrc as isize,
+            0,
+            src_bitmap.width as usize,
+            true,
+            RasterOp::SRCCOPY,
+        );
+    });
+    if !ok {
+        return 0;
     }
-
     cLines
 }
 
@@ -546,26 +820,603 @@ pub fn SetDIBitsToDevice(
 pub fn StretchDIBits(
     machine: &mut Machine,
     hdc: HDC,
-    // XXX these are signed ints in the MSDN docs
-    xDest: u32,
-    yDest: u32,
-    DestWidth: u32,
-    DestHeight: u32,
-    xSrc: u32,
-    ySrc: u32,
-    SrcWidth: u32,
-    SrcHeight: u32,
+    xDest: i32,
+    yDest: i32,
+    DestWidth: i32,
+    DestHeight: i32,
+    xSrc: i32,
+    ySrc: i32,
+    SrcWidth: i32,
+    SrcHeight: i32,
     lpBits: u32,
     lpbmi: u32,
     iUsage: u32,
     rop: Result<RasterOp, u32>,
 ) -> u32 {
-    if SrcWidth != DestWidth || SrcHeight != DestHeight {
-        log::warn!("TODO: StretchDIBits doesn't stretch");
+    if DestWidth < 0 || DestHeight < 0 || SrcWidth < 0 || SrcHeight < 0 {
+        log::warn!("TODO: StretchDIBits doesn't mirror");
+        return 0;
+    }
+    let Ok(rop) = rop else {
+        log::warn!("StretchDIBits: unsupported rop {rop:x?}");
+        return 0;
+    };
+    if rop.is_pattern() {
+        log::warn!("TODO: StretchDIBits with {rop:?}");
+        return 0;
     }
 
-    SetDIBitsToDevice(
-        machine, hdc, xDest, yDest, SrcWidth, SrcHeight, xSrc, ySrc, 0, SrcHeight, lpBits, lpbmi,
+    let header = machine.mem().get_pod::<BITMAPINFOHEADER>(lpbmi);
+    if !check_dib("StretchDIBits", &header, iUsage) {
+        return 0;
+    }
+    let Some(src_bitmap) = parse_dib(
+        machine,
+        hdc,
+        lpbmi,
+        &header,
         iUsage,
-    )
+        lpBits,
+        header.height(),
+    ) else {
+        return 0;
+    };
+    // The parsed bitmap is top-down, while the source rectangle of a bottom-up DIB
+    // is measured from the bottom.
+    let ySrc = if header.is_top_down() {
+        ySrc
+    } else {
+        src_bitmap.height as i32 - ySrc - SrcHeight
+    };
+
+    let ok = with_dst_pixels(machine, hdc, |dst, dstride, mem| {
+        stretch_blt(
+            dst,
+            xDest as isize,
+            yDest as isize,
+            dstride,
+            DestWidth as isize,
+            DestHeight as isize,
+            src_bitmap.pixels_slice(mem),
+            xSrc as isize,
+            ySrc as isize,
+            src_bitmap.width as usize,
+            SrcWidth as isize,
+            SrcHeight as isize,
+            rop,
+        );
+    });
+    if !ok {
+        return 0;
+    }
+    SrcHeight as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A memory DC over a new RGBA32 bitmap of the given pixels.
+    #[cfg(feature = "x86-emu")]
+    fn rgba_dc(machine: &mut Machine, pixels: Vec<[u8; 4]>, width: u32) -> (HDC, HGDIOBJ) {
+        let bitmap = BitmapRGBA32 {
+            width,
+            height: pixels.len() as u32 / width,
+            top_down: true,
+            pixels: PixelData::Owned(pixels.into_boxed_slice()),
+        };
+        let bitmap = machine
+            .state
+            .gdi32
+            .objects
+            .add(Object::Bitmap(BitmapType::RGBA32(bitmap)));
+        let hdc = machine
+            .state
+            .gdi32
+            .dcs
+            .add(DC::new(DCTarget::Memory(bitmap)));
+        (hdc, bitmap)
+    }
+
+    #[cfg(feature = "x86-emu")]
+    fn rgba_pixels(machine: &Machine, bitmap: HGDIOBJ) -> Vec<[u8; 4]> {
+        match machine.state.gdi32.objects.get(bitmap) {
+            Some(Object::Bitmap(BitmapType::RGBA32(bitmap))) => {
+                bitmap.pixels_slice(machine.mem()).to_vec()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_blt_clipping() {
+        let src = [
+            [1, 2, 3, 4],
+            [5, 6, 7, 8],
+            [9, 10, 11, 12],
+            [13, 14, 15, 16],
+        ];
+        let mut dst = [[0u8; 4]; 9];
+
+        // Copy a 2x2 source to the bottom-right corner of a 3x3 destination, overhanging it.
+        bit_blt(
+            &mut dst,
+            2,
+            2,
+            3,
+            2,
+            2,
+            &src,
+            0,
+            0,
+            2,
+            false,
+            RasterOp::SRCCOPY,
+        );
+        assert_eq!(dst[8], [1, 2, 3, 4]);
+        assert!(dst[..8].iter().all(|p| *p == [0; 4]));
+
+        // Stretch the same source to 4x4 at (-1, -1): each source pixel becomes 2x2.
+        dst = [[0; 4]; 9];
+        stretch_blt(
+            &mut dst,
+            -1,
+            -1,
+            3,
+            4,
+            4,
+            &src,
+            0,
+            0,
+            2,
+            2,
+            2,
+            RasterOp::SRCCOPY,
+        );
+        assert_eq!(dst[0], [1, 2, 3, 0xFF]);
+        assert_eq!(dst[1], [5, 6, 7, 0xFF]);
+        assert_eq!(dst[2], [5, 6, 7, 0xFF]);
+        assert_eq!(dst[3], [9, 10, 11, 0xFF]);
+        assert_eq!(dst[8], [13, 14, 15, 0xFF]);
+    }
+
+    /// StretchBlt mirrors along an axis when the src and dst extents differ in sign, and
+    /// only visits the dst pixels within the bitmap however large the extents.
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_stretch_blt_mirror() {
+        let mut machine = crate::testing::machine();
+        const A: [u8; 4] = [1, 1, 1, 0xFF];
+        const B: [u8; 4] = [2, 2, 2, 0xFF];
+        const C: [u8; 4] = [3, 3, 3, 0xFF];
+        const D: [u8; 4] = [4, 4, 4, 0xFF];
+        let (src, _) = rgba_dc(&mut machine, vec![A, B, C, D], 2);
+        let mut stretch = |x, y, w, h, sx, sy, sw, sh| {
+            let (dst, bitmap) = rgba_dc(&mut machine, vec![[0; 4]; 4], 2);
+            let srccopy = Ok(RasterOp::SRCCOPY);
+            assert!(StretchBlt(
+                &mut machine,
+                dst,
+                x,
+                y,
+                w,
+                h,
+                src,
+                sx,
+                sy,
+                sw,
+                sh,
+                srccopy
+            ));
+            rgba_pixels(&machine, bitmap)
+        };
+
+        assert_eq!(stretch(0, 0, 2, 2, 0, 0, 2, 2), [A, B, C, D]);
+        // A negative extent runs leftward/upward from the given edge.
+        assert_eq!(stretch(2, 0, -2, 2, 0, 0, 2, 2), [B, A, D, C]);
+        assert_eq!(stretch(0, 0, 2, 2, 0, 2, 2, -2), [C, D, A, B]);
+        assert_eq!(stretch(2, 2, -2, -2, 0, 0, 2, 2), [D, C, B, A]);
+        // Negative on both sides is no mirror.
+        assert_eq!(stretch(2, 2, -2, -2, 2, 2, -2, -2), [A, B, C, D]);
+        // Mirrored while stretching: each src pixel covers 1x2 dst pixels.
+        assert_eq!(stretch(2, 0, -2, 2, 0, 0, 2, 1), [B, A, B, A]);
+        // Huge extents only touch the dst bitmap.
+        assert_eq!(stretch(2, 2, -0x7FFF_FFFF, -0x7FFF_FFFF, 0, 0, 2, 2)[3], A);
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_unsupported_blits_fail() {
+        let mut machine = crate::testing::machine();
+        let bitmap = BitmapRGBA32 {
+            width: 2,
+            height: 2,
+            top_down: true,
+            pixels: PixelData::Owned(vec![[0; 4]; 4].into_boxed_slice()),
+        };
+        let bitmap = machine
+            .state
+            .gdi32
+            .objects
+            .add(Object::Bitmap(BitmapType::RGBA32(bitmap)));
+        let hdc = machine
+            .state
+            .gdi32
+            .dcs
+            .add(DC::new(DCTarget::Memory(bitmap)));
+        let mono = DC::new_memory(&mut machine);
+        let mono = machine.state.gdi32.dcs.add(mono);
+        let surface = machine
+            .state
+            .gdi32
+            .dcs
+            .add(DC::new(DCTarget::DirectDrawSurface(0x1234)));
+        let srccopy = || Ok(RasterOp::SRCCOPY);
+
+        assert!(!BitBlt(
+            &mut machine,
+            hdc,
+            0,
+            0,
+            2,
+            2,
+            hdc,
+            0,
+            0,
+            Err(0x123456)
+        ));
+        assert!(!BitBlt(
+            &mut machine,
+            mono,
+            0,
+            0,
+            1,
+            1,
+            hdc,
+            0,
+            0,
+            srccopy()
+        ));
+        assert!(!BitBlt(
+            &mut machine,
+            hdc,
+            0,
+            0,
+            1,
+            1,
+            mono,
+            0,
+            0,
+            srccopy()
+        ));
+        assert!(!BitBlt(
+            &mut machine,
+            hdc,
+            0,
+            0,
+            1,
+            1,
+            surface,
+            0,
+            0,
+            srccopy()
+        ));
+        assert!(!StretchBlt(
+            &mut machine,
+            surface,
+            0,
+            0,
+            4,
+            4,
+            hdc,
+            0,
+            0,
+            2,
+            2,
+            srccopy()
+        ));
+        assert!(!PatBlt(
+            &mut machine,
+            surface,
+            0,
+            0,
+            1,
+            1,
+            Ok(RasterOp::WHITENESS)
+        ));
+        assert!(!PatBlt(&mut machine, hdc, 0, 0, 1, 1, srccopy()));
+
+        // Pattern ops need no source DC.
+        assert!(BitBlt(
+            &mut machine,
+            hdc,
+            0,
+            0,
+            2,
+            2,
+            HDC::null(),
+            0,
+            0,
+            Ok(RasterOp::PATCOPY)
+        ));
+        let Some(Object::Bitmap(BitmapType::RGBA32(bitmap))) =
+            machine.state.gdi32.objects.get(bitmap)
+        else {
+            unreachable!();
+        };
+        let pixels = bitmap.pixels_slice(machine.emu.memory.mem());
+        assert!(pixels.iter().all(|&p| p == [0xFF; 4]));
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_dib_section_pal8() {
+        use memory::ExtensionsMut;
+        let mut machine = crate::testing::machine();
+
+        // A 2x2 top-down 8bpp DIB whose palette is black and red (as BGRx).
+        let pbmi = crate::testing::alloc_data(&mut machine, &[0; 48]);
+        let mut header = BITMAPINFOHEADER {
+            biSize: 40,
+            biWidth: 2,
+            biHeight: -2i32 as u32,
+            biPlanes: 1,
+            biBitCount: 8,
+            biCompression: 0,
+            biSizeImage: 0,
+            biXPelsPerMeter: 0,
+            biYPelsPerMeter: 0,
+            biClrUsed: 2,
+            biClrImportant: 0,
+        };
+        machine.mem().put_pod::<[u8; 4]>(pbmi + 44, [0, 0, 0xFF, 0]);
+
+        // Unsupported formats fail rather than panic.
+        header.biCompression = 1; // BI_RLE8
+        machine
+            .mem()
+            .put_pod::<BITMAPINFOHEADER>(pbmi, header.clone());
+        let dib = CreateDIBSection(&mut machine, HDC::null(), pbmi, DIB_RGB_COLORS, None, 0, 0);
+        assert!(dib.is_null());
+        header.biCompression = 0;
+        machine.mem().put_pod::<BITMAPINFOHEADER>(pbmi, header);
+        let dib = CreateDIBSection(&mut machine, HDC::null(), pbmi, 1, None, 0, 0);
+        assert!(dib.is_null());
+        assert_eq!(
+            StretchDIBits(
+                &mut machine,
+                HDC::null(),
+                0,
+                0,
+                2,
+                2,
+                0,
+                0,
+                2,
+                2,
+                pbmi + 48,
+                pbmi,
+                1,
+                Ok(RasterOp::SRCCOPY)
+            ),
+            0
+        );
+
+        let mut bits = 0;
+        let dib = CreateDIBSection(
+            &mut machine,
+            HDC::null(),
+            pbmi,
+            DIB_RGB_COLORS,
+            Some(&mut bits),
+            0,
+            0,
+        );
+        assert!(!dib.is_null());
+        let hdc = machine.state.gdi32.dcs.add(DC::new(DCTarget::Memory(dib)));
+
+        // Stretch a reddish pixel over the DIB, which maps to the nearest palette entry.
+        let src = BitmapRGBA32 {
+            width: 1,
+            height: 1,
+            top_down: true,
+            pixels: PixelData::Owned(vec![[250, 10, 10, 255]].into_boxed_slice()),
+        };
+        let src = machine
+            .state
+            .gdi32
+            .objects
+            .add(Object::Bitmap(BitmapType::RGBA32(src)));
+        let src = machine.state.gdi32.dcs.add(DC::new(DCTarget::Memory(src)));
+        machine.mem().sub32_mut(bits, 8).fill(0);
+        assert!(StretchBlt(
+            &mut machine,
+            hdc,
+            0,
+            0,
+            2,
+            1,
+            src,
+            0,
+            0,
+            1,
+            1,
+            Ok(RasterOp::SRCCOPY)
+        ));
+        // Rows are padded to 4 bytes; only the first row was drawn.
+        assert_eq!(machine.mem().sub32(bits, 8), [1, 1, 0, 0, 0, 0, 0, 0]);
+    }
+
+    /// A bottom-up 32bpp DIB section's first row in memory is its bottom row, both when
+    /// blitting from it and when drawing into it.
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_dib_section_bottom_up() {
+        use memory::ExtensionsMut;
+        let mut machine = crate::testing::machine();
+        const A: [u8; 4] = [1, 1, 1, 0xFF];
+        const B: [u8; 4] = [2, 2, 2, 0xFF];
+        const C: [u8; 4] = [3, 3, 3, 0xFF];
+        const D: [u8; 4] = [4, 4, 4, 0xFF];
+
+        let header = BITMAPINFOHEADER {
+            biSize: 40,
+            biWidth: 2,
+            biHeight: 2,
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: 0,
+            biSizeImage: 0,
+            biXPelsPerMeter: 0,
+            biYPelsPerMeter: 0,
+            biClrUsed: 0,
+            biClrImportant: 0,
+        };
+        let pbmi = crate::testing::alloc_data(&mut machine, &[0; 40]);
+        machine.mem().put_pod::<BITMAPINFOHEADER>(pbmi, header);
+        let mut bits = 0;
+        let dib = CreateDIBSection(
+            &mut machine,
+            HDC::null(),
+            pbmi,
+            DIB_RGB_COLORS,
+            Some(&mut bits),
+            0,
+            0,
+        );
+        assert!(!dib.is_null());
+        let hdc = machine.state.gdi32.dcs.add(DC::new(DCTarget::Memory(dib)));
+        let row = |machine: &Machine, y: u32| machine.mem().sub32(bits + y * 8, 8).to_vec();
+
+        machine
+            .mem()
+            .sub32_mut(bits, 16)
+            .copy_from_slice(&[C, D, A, B].concat());
+        let (dst, bitmap) = rgba_dc(&mut machine, vec![[0; 4]; 4], 2);
+        let srccopy = || Ok(RasterOp::SRCCOPY);
+        assert!(BitBlt(&mut machine, dst, 0, 0, 2, 2, hdc, 0, 0, srccopy()));
+        assert_eq!(rgba_pixels(&machine, bitmap), [A, B, C, D]);
+
+        // Drawing the top row lands in the last row in memory.
+        machine.mem().sub32_mut(bits, 16).fill(0);
+        assert!(BitBlt(&mut machine, hdc, 0, 0, 2, 1, dst, 0, 0, srccopy()));
+        assert_eq!(row(&machine, 0), [0; 8]);
+        assert_eq!(row(&machine, 1), [A, B].concat());
+
+        // Likewise for pattern fills.
+        assert!(PatBlt(
+            &mut machine,
+            hdc,
+            0,
+            0,
+            2,
+            1,
+            Ok(RasterOp::WHITENESS)
+        ));
+        assert_eq!(row(&machine, 0), [0; 8]);
+        assert_eq!(row(&machine, 1), [0xFF; 8]);
+    }
+
+    /// With DIB_PAL_COLORS, a DIB's color table indexes the DC's palette: the selected
+    /// one, or else the realized screen palette.
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_dib_pal_colors() {
+        use crate::winapi::{gdi32, types::HWND};
+        use memory::ExtensionsMut;
+        let mut machine = crate::testing::machine();
+
+        // A logical palette of red, green, blue, and one of blue, green, red.
+        const RED: [u8; 4] = [0xFF, 0, 0, 0xFF];
+        const GREEN: [u8; 4] = [0, 0xFF, 0, 0xFF];
+        const BLUE: [u8; 4] = [0, 0, 0xFF, 0xFF];
+        let create_palette = |machine: &mut Machine, colors: [[u8; 4]; 3]| {
+            let mut logpal = vec![0x00, 0x03, 3, 0];
+            for color in colors {
+                logpal.extend_from_slice(&color[..3]);
+                logpal.push(0);
+            }
+            let logpal = crate::testing::alloc_data(machine, &logpal);
+            gdi32::CreatePalette(machine, logpal)
+        };
+        let rgb = create_palette(&mut machine, [RED, GREEN, BLUE]);
+        let bgr = create_palette(&mut machine, [BLUE, GREEN, RED]);
+
+        // A 2x1 top-down 8bpp DIB, whose color table maps pixels 0 and 1 to palette
+        // entries 1 and 0, followed by its pixels.
+        let header = BITMAPINFOHEADER {
+            biSize: 40,
+            biWidth: 2,
+            biHeight: -1i32 as u32,
+            biPlanes: 1,
+            biBitCount: 8,
+            biCompression: 0,
+            biSizeImage: 0,
+            biXPelsPerMeter: 0,
+            biYPelsPerMeter: 0,
+            biClrUsed: 2,
+            biClrImportant: 0,
+        };
+        let pbmi = crate::testing::alloc_data(&mut machine, &[0; 48]);
+        machine.mem().put_pod::<BITMAPINFOHEADER>(pbmi, header);
+        machine.mem().put_pod::<u16>(pbmi + 40, 1);
+        machine.mem().put_pod::<u16>(pbmi + 42, 0);
+        let bits = pbmi + 44;
+        machine.mem().put_pod::<[u8; 4]>(bits, [0, 1, 0, 0]);
+
+        let (hdc, bitmap) = rgba_dc(&mut machine, vec![[0; 4]; 2], 2);
+        gdi32::SelectPalette(&mut machine, hdc, rgb, false);
+        let lines = SetDIBitsToDevice(
+            &mut machine,
+            hdc,
+            0,
+            0,
+            2,
+            1,
+            0,
+            0,
+            0,
+            1,
+            bits,
+            pbmi,
+            DIB_PAL_COLORS,
+        );
+        assert_eq!(lines, 1);
+        assert_eq!(rgba_pixels(&machine, bitmap), [GREEN, RED]);
+
+        // A DIB section takes its colors from the palette when it's created.
+        let dib = CreateDIBSection(&mut machine, hdc, pbmi, DIB_PAL_COLORS, None, 0, 0);
+        match machine.state.gdi32.objects.get(dib) {
+            Some(Object::Bitmap(BitmapType::Pal8(dib))) => assert_eq!(*dib.palette, [GREEN, RED]),
+            _ => unreachable!(),
+        }
+
+        // Without a selected palette, the realized one applies.
+        let screen = machine
+            .state
+            .gdi32
+            .dcs
+            .add(DC::new(DCTarget::Window(HWND::null())));
+        gdi32::SelectPalette(&mut machine, screen, bgr, false);
+        assert_eq!(gdi32::RealizePalette(&mut machine, screen), 3);
+        let (hdc, bitmap) = rgba_dc(&mut machine, vec![[0; 4]; 2], 2);
+        let ret = StretchDIBits(
+            &mut machine,
+            hdc,
+            0,
+            0,
+            2,
+            1,
+            0,
+            0,
+            2,
+            1,
+            bits,
+            pbmi,
+            DIB_PAL_COLORS,
+            Ok(RasterOp::SRCCOPY),
+        );
+        assert_eq!(ret, 1);
+        assert_eq!(rgba_pixels(&machine, bitmap), [GREEN, BLUE]);
+    }
 }
@@ -1,4 +1,4 @@
-use super::{BitmapType, Object, HGDIOBJ, R2};
+use super::{BitmapType, BkMode, Object, COLORREF, HGDIOBJ, R2};
 use crate::winapi::types::POINT;
 use crate::{
     machine::Machine,
@@ -13,14 +13,14 @@ const TRACE_CONTEXT: &'static str = "gdi32/dc";
 pub type HDC = HANDLE<DC>;
 
 /// Target device for a DC.
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum DCTarget {
     Memory(HGDIOBJ), // aka Bitmap
     Window(HWND),
     DirectDrawSurface(u32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct DC {
     // TODO: it's unclear to me what the representation of a DC ought to be.
     // DirectDraw can also create a DC, and DirectDraw (as a DLL that came
@@ -37,6 +37,13 @@ pub struct DC {
     // per object type.
     pub brush: HGDIOBJ,
     pub pen: HGDIOBJ,
+    pub font: HGDIOBJ,
+    /// Selected via SelectPalette rather than SelectObject; null means the default palette.
+    pub palette: HGDIOBJ,
+
+    pub text_color: COLORREF,
+    pub bk_color: COLORREF,
+    pub bk_mode: BkMode,
 }
 
 impl DC {
@@ -48,6 +55,11 @@ impl DC {
             y: 0,
             brush: Default::default(),
             pen: Default::default(),
+            font: Default::default(),
+            palette: Default::default(),
+            text_color: COLORREF::from_rgb(0, 0, 0),
+            bk_color: COLORREF::white(),
+            bk_mode: BkMode::default(),
         }
     }
 
@@ -76,9 +88,15 @@ pub fn CreateCompatibleDC(machine: &mut Machine, hdc: HDC) -> HDC {
 }
 
 #[win32_derive::dllexport]
-pub fn DeleteDC(_machine: &mut Machine, hdc: u32) -> u32 {
-    log::warn!("todo: DeleteDC({hdc:x})");
-    0 // fail
+pub fn DeleteDC(machine: &mut Machine, hdc: HDC) -> bool {
+    // Note: there is also ReleaseDC; this one is specifically for CreateCompatibleDC.
+    match machine.state.gdi32.dcs.remove(hdc) {
+        Some(_) => true,
+        None => {
+            log::warn!("DeleteDC of unknown DC");
+            false // fail
+        }
+    }
 }
 
 #[derive(Debug, win32_derive::TryFromEnum)]
@@ -125,17 +143,48 @@ pub enum GetDeviceCapsArg {
 }
 
 #[win32_derive::dllexport]
-pub fn GetDeviceCaps(
-    _machine: &mut Machine,
-    hdc: HDC,
-    index: Result<GetDeviceCapsArg, u32>,
-) -> u32 {
-    match index.unwrap() {
-        GetDeviceCapsArg::NUMCOLORS => -1i32 as u32, // true color
-        GetDeviceCapsArg::HORZRES => 640,
-        GetDeviceCapsArg::VERTRES => 480,
+pub fn GetDeviceCaps(machine: &mut Machine, hdc: HDC, index: Result<GetDeviceCapsArg, u32>) -> u32 {
+    let screen = machine.state.ddraw.screen_mode();
+    // Memory DCs describe their selected bitmap rather than the screen.
+    let (width, height, bpp) = match machine.state.gdi32.dcs.get(hdc) {
+        Some(DC {
+            target: DCTarget::Memory(hbitmap),
+            ..
+        }) => match machine.state.gdi32.objects.get(*hbitmap) {
+            Some(Object::Bitmap(bitmap)) => (
+                bitmap.inner().width(),
+                bitmap.inner().height(),
+                bitmap.bit_count(),
+            ),
+            _ => screen,
+        },
+        _ => screen,
+    };
+
+    let index = match index {
+        Ok(index) => index,
+        Err(index) => {
+            log::warn!("GetDeviceCaps: unknown index {index}");
+            return 0;
+        }
+    };
+    match index {
+        GetDeviceCapsArg::HORZRES => width,
+        GetDeviceCapsArg::VERTRES => height,
+        GetDeviceCapsArg::DESKTOPHORZRES => screen.0,
+        GetDeviceCapsArg::DESKTOPVERTRES => screen.1,
+        GetDeviceCapsArg::BITSPIXEL => bpp,
+        GetDeviceCapsArg::PLANES => 1,
+        GetDeviceCapsArg::NUMCOLORS => match bpp {
+            1..=8 => 1 << bpp,
+            _ => -1i32 as u32, // true color
+        },
+        GetDeviceCapsArg::LOGPIXELSX | GetDeviceCapsArg::LOGPIXELSY => 96,
         GetDeviceCapsArg::RASTERCAPS => 0, // none
-        _ => unimplemented!(),
+        _ => {
+            log::warn!("GetDeviceCaps: unimplemented index {index:?}");
+            0
+        }
     }
 }
 
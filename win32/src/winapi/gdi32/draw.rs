@@ -1,6 +1,6 @@
 //! Pens, brushes, color.
 
-use super::{DCTarget, Object, HDC, HGDIOBJ};
+use super::{bitmap::with_dst_pixels, DCTarget, Object, DC, HDC, HGDIOBJ};
 use crate::{
     machine::Machine,
     winapi::types::{POINT, RECT},
@@ -10,11 +10,11 @@ const TRACE_CONTEXT: &'static str = "gdi32/draw";
 
 /// COLORREF is a u32 containing RGB0, modeled specially here because there is the
 /// invalid marker value CLR_INVALID=0xffffffff.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct COLORREF(u32);
 
 impl COLORREF {
-    pub fn from_rgb(r: u8, g: u8, b: u8) -> Self {
+    pub const fn from_rgb(r: u8, g: u8, b: u8) -> Self {
         Self(u32::from_le_bytes([r, g, b, 0]))
     }
     pub fn to_pixel(&self) -> [u8; 4] {
@@ -51,29 +51,57 @@ impl crate::winapi::stack_args::ToX86 for COLORREF {
 
 pub const CLR_INVALID: COLORREF = COLORREF(0xffff_ffff);
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Pen {
-    pub color: COLORREF,
+    /// None for PS_NULL, which draws nothing.
+    pub color: Option<COLORREF>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Brush {
     pub color: Option<COLORREF>,
 }
 
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    win32_derive::TryFromEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum BkMode {
+    TRANSPARENT = 1,
+    #[default]
+    OPAQUE = 2,
+}
+
 #[win32_derive::dllexport]
-pub fn SetBkMode(_machine: &mut Machine, hdc: HDC, mode: i32) -> i32 {
-    0 // fail
+pub fn SetBkMode(machine: &mut Machine, hdc: HDC, mode: Result<BkMode, u32>) -> i32 {
+    let Some(dc) = machine.state.gdi32.dcs.get_mut(hdc) else {
+        return 0; // fail
+    };
+    let Ok(mode) = mode else {
+        return 0; // fail
+    };
+    std::mem::replace(&mut dc.bk_mode, mode) as i32
 }
 
 #[win32_derive::dllexport]
-pub fn SetBkColor(_machine: &mut Machine, hdc: HDC, color: COLORREF) -> COLORREF {
-    CLR_INVALID // fail
+pub fn SetBkColor(machine: &mut Machine, hdc: HDC, color: COLORREF) -> COLORREF {
+    let Some(dc) = machine.state.gdi32.dcs.get_mut(hdc) else {
+        return CLR_INVALID; // fail
+    };
+    std::mem::replace(&mut dc.bk_color, color)
 }
 
 #[derive(Debug, win32_derive::TryFromEnum)]
 pub enum PS {
     SOLID = 0,
+    NULL = 5,
 }
 
 #[win32_derive::dllexport]
@@ -83,73 +111,222 @@ pub fn CreatePen(
     cWidth: u32,
     color: COLORREF,
 ) -> HGDIOBJ {
-    iStyle.unwrap();
-    if cWidth != 1 {
-        todo!();
+    let color = match iStyle {
+        Ok(PS::SOLID) => Some(color),
+        Ok(PS::NULL) => None,
+        Err(style) => {
+            log::warn!("CreatePen: unhandled style {style:#x}");
+            return HGDIOBJ::null();
+        }
+    };
+    if cWidth > 1 {
+        log::warn!("CreatePen: drawing width {cWidth} as 1");
     }
 
     machine.state.gdi32.objects.add(Object::Pen(Pen { color }))
 }
 
 #[win32_derive::dllexport]
-pub fn MoveToEx(machine: &mut Machine, hdc: HDC, x: u32, y: u32, lppt: Option<&mut POINT>) -> bool {
-    let dc = machine.state.gdi32.dcs.get_mut(hdc).unwrap();
+pub fn MoveToEx(machine: &mut Machine, hdc: HDC, x: i32, y: i32, lppt: Option<&mut POINT>) -> bool {
+    let Some(dc) = machine.state.gdi32.dcs.get_mut(hdc) else {
+        return false;
+    };
     if let Some(pt) = lppt {
         *pt = POINT { x: dc.x, y: dc.y };
     }
-    dc.x = x;
-    dc.y = y;
+    dc.x = x as u32;
+    dc.y = y as u32;
     true
 }
 
-fn ascending(a: u32, b: u32) -> (u32, u32) {
-    if a > b {
-        (b, a)
-    } else {
-        (a, b)
+/// Set a single pixel, if it's within bounds.
+fn set_pixel(dst: &mut [[u8; 4]], stride: usize, x: i32, y: i32, color: [u8; 4]) {
+    if x < 0 || y < 0 || x as usize >= stride {
+        return;
+    }
+    if let Some(p) = dst.get_mut(y as usize * stride + x as usize) {
+        *p = color;
     }
 }
 
-#[win32_derive::dllexport]
-pub fn LineTo(machine: &mut Machine, hdc: HDC, x: u32, y: u32) -> bool {
-    let dc = machine.state.gdi32.dcs.get_mut(hdc).unwrap();
-    let hwnd = match dc.target {
-        DCTarget::Memory(_) => todo!(),
-        DCTarget::Window(hwnd) => hwnd,
-        _ => todo!(),
+/// Fill the rectangle [left, right) x [top, bottom), clipped to the destination.
+fn fill(
+    dst: &mut [[u8; 4]],
+    stride: usize,
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+    color: [u8; 4],
+) {
+    if stride == 0 {
+        return;
+    }
+    let height = (dst.len() / stride) as i32;
+    let (left, right) = (left.max(0), right.min(stride as i32));
+    let (top, bottom) = (top.max(0), bottom.min(height));
+    if left >= right {
+        return;
+    }
+    for y in top..bottom {
+        let row = y as usize * stride;
+        dst[row + left as usize..row + right as usize].fill(color);
+    }
+}
+
+/// Draw a line with Bresenham's algorithm.  Like GDI, the end point is not drawn.
+fn line(dst: &mut [[u8; 4]], stride: usize, from: (i32, i32), to: (i32, i32), color: [u8; 4]) {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let sx = if x < to.0 { 1 } else { -1 };
+    let sy = if y < to.1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    while (x, y) != to {
+        set_pixel(dst, stride, x, y, color);
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+/// Draw an ellipse inscribed in the rectangle [left, right) x [top, bottom),
+/// filling it with fill_color and outlining it with line_color.
+fn ellipse(
+    dst: &mut [[u8; 4]],
+    stride: usize,
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+    fill_color: Option<[u8; 4]>,
+    line_color: Option<[u8; 4]>,
+) {
+    if right <= left || bottom <= top {
+        return;
+    }
+    let cx = (left + right - 1) as f64 / 2.0;
+    let cy = (top + bottom - 1) as f64 / 2.0;
+    let (a, b) = (cx - left as f64, cy - top as f64);
+    // Half the width of the ellipse at distance d from the center along an axis of radius r,
+    // where other is the radius across that axis.
+    let extent = |other: f64, r: f64, d: f64| {
+        if r == 0.0 {
+            other
+        } else {
+            other * (1.0 - (d / r).powi(2)).max(0.0).sqrt()
+        }
     };
-    let window = machine.state.user32.windows.get_mut(hwnd).unwrap();
-    let stride = window.width;
-    let pixels = window.bitmap_mut().pixels.as_slice_mut();
-
-    let color = match dc.r2 {
-        R2::COPYPEN => match machine.state.gdi32.objects.get(dc.pen).unwrap() {
-            Object::Pen(pen) => pen.color.to_pixel(),
-            _ => todo!(),
+
+    for y in top..bottom {
+        let half = extent(a, b, y as f64 - cy);
+        let (x0, x1) = ((cx - half).round() as i32, (cx + half).round() as i32);
+        if let Some(color) = fill_color {
+            fill(dst, stride, x0, y, x1 + 1, y + 1, color);
+        }
+        if let Some(color) = line_color {
+            set_pixel(dst, stride, x0, y, color);
+            set_pixel(dst, stride, x1, y, color);
+        }
+    }
+    // Also outline by columns, so the flatter parts of the curve have no gaps.
+    if let Some(color) = line_color {
+        for x in left..right {
+            let half = extent(b, a, x as f64 - cx);
+            let (y0, y1) = ((cy - half).round() as i32, (cy + half).round() as i32);
+            set_pixel(dst, stride, x, y0, color);
+            set_pixel(dst, stride, x, y1, color);
+        }
+    }
+}
+
+/// The pixel colors of the pen and brush selected into a DC, None if they draw nothing.
+fn pen_and_brush(machine: &Machine, dc: &DC) -> (Option<[u8; 4]>, Option<[u8; 4]>) {
+    let pen = match dc.r2 {
+        R2::COPYPEN => match machine.state.gdi32.objects.get(dc.pen) {
+            Some(Object::Pen(pen)) => pen.color.map(|c| c.to_pixel()),
+            _ => Some(COLORREF::from_rgb(0, 0, 0).to_pixel()), // BLACK_PEN
         },
-        R2::WHITE => COLORREF::white().to_pixel(),
+        R2::WHITE => Some(COLORREF::white().to_pixel()),
+    };
+    let brush = match machine.state.gdi32.objects.get(dc.brush) {
+        Some(Object::Brush(brush)) => brush.color.map(|c| c.to_pixel()),
+        _ => Some(COLORREF::white().to_pixel()), // WHITE_BRUSH
     };
+    (pen, brush)
+}
 
-    let (dstX, dstY) = (x, y);
-    if dstX == dc.x {
-        let (y0, y1) = ascending(dstY, dc.y);
-        for y in y0..=y1 {
-            pixels[((y * stride) + x) as usize] = color;
-        }
-        dc.y = dstY;
-    } else if dstY == dc.y {
-        let (x0, x1) = ascending(dstX, dc.x);
-        for x in x0..=x1 {
-            pixels[((y * stride) + x) as usize] = color;
+#[win32_derive::dllexport]
+pub fn LineTo(machine: &mut Machine, hdc: HDC, x: i32, y: i32) -> bool {
+    let Some(dc) = machine.state.gdi32.dcs.get(hdc) else {
+        return false;
+    };
+    let from = (dc.x as i32, dc.y as i32);
+    let (pen, _) = pen_and_brush(machine, dc);
+    if let Some(color) = pen {
+        if !with_dst_pixels(machine, hdc, |dst, stride, _| {
+            line(dst, stride, from, (x, y), color)
+        }) {
+            return false;
         }
-        dc.x = dstX;
-    } else {
-        todo!();
     }
-    false // fail
+    let dc = machine.state.gdi32.dcs.get_mut(hdc).unwrap();
+    dc.x = x as u32;
+    dc.y = y as u32;
+    true
+}
+
+#[win32_derive::dllexport]
+pub fn Rectangle(
+    machine: &mut Machine,
+    hdc: HDC,
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+) -> bool {
+    let Some(dc) = machine.state.gdi32.dcs.get(hdc) else {
+        return false;
+    };
+    let (pen, brush) = pen_and_brush(machine, dc);
+    with_dst_pixels(machine, hdc, |dst, stride, _| {
+        if let Some(color) = brush {
+            fill(dst, stride, left, top, right, bottom, color);
+        }
+        if let Some(color) = pen {
+            fill(dst, stride, left, top, right, top + 1, color);
+            fill(dst, stride, left, bottom - 1, right, bottom, color);
+            fill(dst, stride, left, top, left + 1, bottom, color);
+            fill(dst, stride, right - 1, top, right, bottom, color);
+        }
+    })
 }
 
-#[derive(Debug, Default, win32_derive::TryFromEnum)]
+#[win32_derive::dllexport]
+pub fn Ellipse(
+    machine: &mut Machine,
+    hdc: HDC,
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+) -> bool {
+    let Some(dc) = machine.state.gdi32.dcs.get(hdc) else {
+        return false;
+    };
+    let (pen, brush) = pen_and_brush(machine, dc);
+    with_dst_pixels(machine, hdc, |dst, stride, _| {
+        ellipse(dst, stride, left, top, right, bottom, brush, pen);
+    })
+}
+
+#[derive(Debug, Default, win32_derive::TryFromEnum, serde::Serialize, serde::Deserialize)]
 pub enum R2 {
     #[default]
     COPYPEN = 13,
@@ -162,24 +339,19 @@ pub fn SetROP2(machine: &mut Machine, hdc: HDC, rop2: Result<R2, u32>) -> u32 {
     std::mem::replace(&mut dc.r2, rop2.unwrap()) as u32
 }
 
-pub fn fill_rect(machine: &mut Machine, hdc: HDC, _rect: &RECT, color: COLORREF) {
-    let dc = machine.state.gdi32.dcs.get_mut(hdc).unwrap();
-    match dc.target {
-        DCTarget::Memory(_) => todo!(),
-        DCTarget::Window(hwnd) => {
-            let window = machine.state.user32.windows.get_mut(hwnd).unwrap();
-            // TODO: obey rect
-            window
-                .bitmap_mut()
-                .pixels
-                .as_slice_mut()
-                .fill(color.to_pixel());
-            window
-                .expect_toplevel_mut()
-                .flush_pixels(machine.emu.memory.mem());
-        }
-        DCTarget::DirectDrawSurface(_) => todo!(),
-    }
+pub fn fill_rect(machine: &mut Machine, hdc: HDC, rect: &RECT, color: COLORREF) {
+    let color = color.to_pixel();
+    with_dst_pixels(machine, hdc, |dst, stride, _| {
+        fill(
+            dst,
+            stride,
+            rect.left,
+            rect.top,
+            rect.right,
+            rect.bottom,
+            color,
+        );
+    });
 }
 
 #[win32_derive::dllexport]
@@ -192,7 +364,10 @@ pub fn SetPixel(machine: &mut Machine, hdc: HDC, x: u32, y: u32, color: COLORREF
                 return CLR_INVALID;
             }
             let stride = window.width;
-            let pixels = window.bitmap_mut().pixels.as_slice_mut();
+            let pixels = window
+                .bitmap_mut()
+                .pixels
+                .as_slice_mut(machine.emu.memory.mem());
             pixels[((y * stride) + x) as usize] = color.to_pixel();
             // TODO: don't need to flush whole window for just one pixel
             window
@@ -216,7 +391,10 @@ pub fn GetPixel(machine: &mut Machine, hdc: HDC, x: u32, y: u32) -> COLORREF {
         DCTarget::Window(hwnd) => {
             let window = machine.state.user32.windows.get_mut(hwnd).unwrap();
             let stride = window.width;
-            let pixels = window.bitmap_mut().pixels.as_slice_mut();
+            let pixels = window
+                .bitmap_mut()
+                .pixels
+                .as_slice_mut(machine.emu.memory.mem());
             let color = pixels[((y * stride) + x) as usize];
             COLORREF::from_rgb(color[0], color[1], color[2])
         }
@@ -264,3 +442,49 @@ pub fn LineDDA(
 ) -> bool {
     true // stub
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C: [u8; 4] = [1, 1, 1, 1];
+
+    fn render(dst: &[[u8; 4]], stride: usize) -> Vec<String> {
+        dst.chunks(stride)
+            .map(|row| {
+                row.iter()
+                    .map(|p| if *p == C { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_line() {
+        let mut dst = [[0u8; 4]; 5 * 4];
+        line(&mut dst, 5, (0, 0), (4, 2), C);
+        line(&mut dst, 5, (4, 3), (-2, 3), C);
+        assert_eq!(render(&dst, 5), ["#....", ".##..", "...#.", "#####"]);
+    }
+
+    #[test]
+    fn test_ellipse() {
+        let mut dst = [[0u8; 4]; 7 * 5];
+        ellipse(&mut dst, 7, 0, 0, 7, 5, None, Some(C));
+        assert_eq!(
+            render(&dst, 7),
+            ["..###..", "##...##", "#.....#", "##...##", "..###.."]
+        );
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_create_pen() {
+        let mut machine = crate::testing::machine();
+        let black = COLORREF::from_rgb(0, 0, 0);
+        assert!(!CreatePen(&mut machine, Ok(PS::SOLID), 1, black).is_null());
+        assert!(!CreatePen(&mut machine, Ok(PS::NULL), 1, black).is_null());
+        // Styles we don't draw, like PS_DASH, fail rather than panic.
+        assert!(CreatePen(&mut machine, Err(1), 1, black).is_null());
+    }
+}
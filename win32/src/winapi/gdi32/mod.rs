@@ -4,6 +4,7 @@
 mod bitmap;
 mod dc;
 mod draw;
+mod font;
 mod object;
 mod palette;
 mod state;
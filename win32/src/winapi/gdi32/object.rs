@@ -1,17 +1,22 @@
-use super::{Brush, DCTarget, Pen, BITMAP, COLORREF, HDC};
+use super::{
+    Brush, DCTarget, Font, Palette, Pen, BITMAP, BITMAPINFOHEADER, COLORREF, DIBSECTION, HDC, PS,
+};
 use crate::{
     winapi::{
-        bitmap::{Bitmap, BitmapMono, BitmapRGBA32},
-        types::HANDLE,
+        bitmap::{Bitmap, BitmapMono, BitmapPal8, BitmapRGBA32, PixelData},
+        stack_args::ToX86,
+        types::{HANDLE, POINT},
     },
     Machine,
 };
+use memory::ExtensionsMut;
 
 const TRACE_CONTEXT: &'static str = "gdi32/object";
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum BitmapType {
     RGBA32(BitmapRGBA32),
+    Pal8(BitmapPal8),
     Mono(BitmapMono),
 }
 
@@ -19,17 +24,95 @@ impl BitmapType {
     pub fn inner(&self) -> &dyn Bitmap {
         match self {
             BitmapType::RGBA32(b) => b,
+            BitmapType::Pal8(b) => b,
             BitmapType::Mono(b) => b,
         }
     }
+
+    pub fn bit_count(&self) -> u32 {
+        match self {
+            BitmapType::RGBA32(_) => 32,
+            BitmapType::Pal8(_) => 8,
+            BitmapType::Mono(_) => 1,
+        }
+    }
+
+    /// Bytes per row of pixels.
+    pub fn stride(&self) -> u32 {
+        match self {
+            BitmapType::RGBA32(b) => b.width * 4,
+            BitmapType::Pal8(b) => b.stride,
+            BitmapType::Mono(b) => BitmapMono::stride(b.width),
+        }
+    }
+
+    /// For DIB sections, the address of the pixels in x86 memory.
+    pub fn dib_bits(&self) -> Option<u32> {
+        let pixels = match self {
+            BitmapType::RGBA32(b) => match b.pixels {
+                PixelData::Ptr(addr, _) => addr,
+                PixelData::Owned(_) => 0,
+            },
+            BitmapType::Pal8(b) => match b.pixels {
+                PixelData::Ptr(addr, _) => addr,
+                PixelData::Owned(_) => 0,
+            },
+            BitmapType::Mono(b) => match b.pixels {
+                PixelData::Ptr(addr, _) => addr,
+                PixelData::Owned(_) => 0,
+            },
+        };
+        if pixels == 0 {
+            None
+        } else {
+            Some(pixels)
+        }
+    }
+
+    fn to_bitmap(&self) -> BITMAP {
+        let inner = self.inner();
+        BITMAP {
+            bmType: 0,
+            bmWidth: inner.width(),
+            bmHeight: inner.height(),
+            bmWidthBytes: self.stride(),
+            bmPlanes: 1,
+            bmBitsPixel: self.bit_count() as u16,
+            bmBits: self.dib_bits().unwrap_or(0),
+        }
+    }
 }
 
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct LOGBRUSH {
+    pub lbStyle: u32,
+    pub lbColor: u32,
+    pub lbHatch: u32,
+}
+unsafe impl memory::Pod for LOGBRUSH {}
+
+const BS_SOLID: u32 = 0;
+const BS_NULL: u32 = 1;
+
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct LOGPEN {
+    pub lopnStyle: u32,
+    /// Only x is used, as the pen width.
+    pub lopnWidth: POINT,
+    pub lopnColor: u32,
+}
+unsafe impl memory::Pod for LOGPEN {}
+
 /// GDI Object, as identified by HANDLEs.
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum Object {
     Brush(Brush),
     Bitmap(BitmapType),
     Pen(Pen),
+    Font(Font),
+    Palette(Palette),
 }
 
 pub type HGDIOBJ = HANDLE<Object>;
@@ -94,44 +177,284 @@ pub fn SelectObject(machine: &mut Machine, hdc: HDC, hGdiObj: HGDIOBJ) -> HGDIOB
                 dc.target = DCTarget::Memory(hGdiObj);
                 prev
             }
-            DCTarget::Window(_) => todo!(),
-            DCTarget::DirectDrawSurface(_) => todo!(),
+            // Bitmaps can only be selected into memory DCs.
+            DCTarget::Window(_) | DCTarget::DirectDrawSurface(_) => {
+                log::warn!("SelectObject of bitmap into non-memory DC");
+                HGDIOBJ::null()
+            }
         },
         Object::Brush(_) => std::mem::replace(&mut dc.brush, hGdiObj),
         Object::Pen(_) => std::mem::replace(&mut dc.pen, hGdiObj),
+        Object::Font(_) => std::mem::replace(&mut dc.font, hGdiObj),
+        // Palettes are selected with SelectPalette instead.
+        Object::Palette(_) => {
+            log::warn!("SelectObject of palette");
+            HGDIOBJ::null()
+        }
     }
 }
 
+/// Copy a struct describing a GDI object to out, returning its size, or 0 if
+/// it doesn't fit.  A null out queries the size.
+fn put_object<T: memory::Pod + Clone>(mem: memory::Mem, bytes: u32, out: u32, value: T) -> u32 {
+    let size = std::mem::size_of::<T>() as u32;
+    if out == 0 {
+        return size;
+    }
+    if bytes < size {
+        return 0;
+    }
+    mem.put_pod::<T>(out, value);
+    size
+}
+
 #[win32_derive::dllexport]
 pub fn GetObjectA(machine: &mut Machine, handle: HGDIOBJ, bytes: u32, out: u32) -> u32 {
+    let mem = machine.emu.memory.mem();
     let obj = match machine.state.gdi32.objects.get(handle) {
         None => return 0, // fail
         Some(obj) => obj,
     };
 
     match obj {
-        Object::Brush(_) => todo!(),
+        Object::Brush(brush) => {
+            let logbrush = match brush.color {
+                Some(color) => LOGBRUSH {
+                    lbStyle: BS_SOLID,
+                    lbColor: color.to_raw(),
+                    lbHatch: 0,
+                },
+                None => LOGBRUSH {
+                    lbStyle: BS_NULL,
+                    lbColor: 0,
+                    lbHatch: 0,
+                },
+            };
+            put_object(mem, bytes, out, logbrush)
+        }
         Object::Bitmap(bitmap) => {
-            assert_eq!(bytes as usize, std::mem::size_of::<BITMAP>());
-            let out = machine.mem().view_mut::<BITMAP>(out);
-            let bitmap = bitmap.inner();
-            *out = BITMAP {
-                bmType: 0,
-                bmWidth: bitmap.width(),
-                bmHeight: bitmap.height(),
-                bmWidthBytes: 0,
-                bmPlanes: 0,
-                bmBitsPixel: 0,
-                bmBits: 0,
+            let bm = bitmap.to_bitmap();
+            // DIB sections describe themselves with a DIBSECTION if there's room.
+            let dib_size = std::mem::size_of::<DIBSECTION>() as u32;
+            if bitmap.dib_bits().is_some() && (out == 0 || bytes >= dib_size) {
+                let top_down = match bitmap {
+                    BitmapType::RGBA32(b) => b.top_down,
+                    BitmapType::Pal8(b) => b.top_down,
+                    BitmapType::Mono(_) => false,
+                };
+                let height = if top_down {
+                    -(bm.bmHeight as i32) as u32
+                } else {
+                    bm.bmHeight
+                };
+                let dib = DIBSECTION {
+                    dsBmih: BITMAPINFOHEADER {
+                        biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                        biWidth: bm.bmWidth,
+                        biHeight: height,
+                        biPlanes: 1,
+                        biBitCount: bm.bmBitsPixel,
+                        biCompression: 0, // BI_RGB
+                        biSizeImage: bm.bmWidthBytes * bm.bmHeight,
+                        biXPelsPerMeter: 0,
+                        biYPelsPerMeter: 0,
+                        biClrUsed: 0,
+                        biClrImportant: 0,
+                    },
+                    dsBm: bm,
+                    dsBitfields: [0; 3],
+                    dshSection: 0,
+                    dsOffset: 0,
+                };
+                return put_object(mem, bytes, out, dib);
+            }
+            put_object(mem, bytes, out, bm)
+        }
+        Object::Pen(pen) => {
+            // We draw every pen one pixel wide, so that's the width we report.
+            let logpen = match pen.color {
+                Some(color) => LOGPEN {
+                    lopnStyle: PS::SOLID as u32,
+                    lopnWidth: POINT { x: 1, y: 0 },
+                    lopnColor: color.to_raw(),
+                },
+                None => LOGPEN {
+                    lopnStyle: PS::NULL as u32,
+                    lopnWidth: POINT { x: 1, y: 0 },
+                    lopnColor: 0,
+                },
             };
-            bytes
+            put_object(mem, bytes, out, logpen)
+        }
+        Object::Font(font) => put_object(mem, bytes, out, font.to_logfont()),
+        Object::Palette(palette) => {
+            // The palette's entry count, as a WORD.
+            put_object(mem, bytes, out, palette.entries.len() as u16)
         }
-        Object::Pen(_) => todo!(),
     }
 }
 
 #[win32_derive::dllexport]
-pub fn DeleteObject(_machine: &mut Machine, handle: HGDIOBJ) -> bool {
-    // TODO: leak
-    true
+pub fn DeleteObject(machine: &mut Machine, handle: HGDIOBJ) -> bool {
+    match machine.state.gdi32.objects.get(handle) {
+        None => false,
+        Some(Object::Brush(_) | Object::Pen(_) | Object::Font(_) | Object::Palette(_)) => {
+            machine.state.gdi32.objects.remove(handle);
+            true
+        }
+        Some(Object::Bitmap(_)) => {
+            // TODO: leak; bitmaps may still be selected into a DC.
+            true
+        }
+    }
+}
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use super::*;
+    use crate::winapi::gdi32::{
+        CreateDIBSection, CreateFontA, CreatePalette, CreatePen, CreateSolidBrush, LOGFONTA,
+    };
+    use memory::Extensions;
+
+    /// GetObjectA into a fresh buffer of the given size, returning the result and buffer.
+    fn get_object(machine: &mut Machine, handle: HGDIOBJ, bytes: u32) -> (u32, u32) {
+        let out = crate::testing::alloc_data(machine, &vec![0xCC; bytes as usize]);
+        (GetObjectA(machine, handle, bytes, out), out)
+    }
+
+    #[test]
+    fn test_brush() {
+        let mut machine = crate::testing::machine();
+        let brush = CreateSolidBrush(&mut machine, COLORREF::from_rgb(0x56, 0x34, 0x12));
+        let (size, out) = get_object(&mut machine, brush, 12);
+        assert_eq!(size, 12);
+        let logbrush = machine.mem().get_pod::<LOGBRUSH>(out);
+        assert_eq!(logbrush.lbStyle, BS_SOLID);
+        assert_eq!(logbrush.lbColor, 0x123456);
+
+        let brush = GetStockObject(&mut machine, Ok(GetStockObjectArg::NULL_BRUSH));
+        let (size, out) = get_object(&mut machine, brush, 12);
+        assert_eq!(size, 12);
+        assert_eq!(machine.mem().get_pod::<LOGBRUSH>(out).lbStyle, BS_NULL);
+    }
+
+    #[test]
+    fn test_pen() {
+        let mut machine = crate::testing::machine();
+        let pen = CreatePen(
+            &mut machine,
+            Ok(PS::SOLID),
+            1,
+            COLORREF::from_rgb(0xFF, 0, 0),
+        );
+        let (size, out) = get_object(&mut machine, pen, 16);
+        assert_eq!(size, 16);
+        let logpen = machine.mem().get_pod::<LOGPEN>(out);
+        assert_eq!(logpen.lopnStyle, PS::SOLID as u32);
+        assert_eq!({ logpen.lopnWidth.x }, 1);
+        assert_eq!(logpen.lopnColor, 0xFF);
+
+        let pen = CreatePen(
+            &mut machine,
+            Ok(PS::NULL),
+            1,
+            COLORREF::from_rgb(0xFF, 0, 0),
+        );
+        let (size, out) = get_object(&mut machine, pen, 16);
+        assert_eq!(size, 16);
+        assert_eq!(
+            machine.mem().get_pod::<LOGPEN>(out).lopnStyle,
+            PS::NULL as u32
+        );
+    }
+
+    #[test]
+    fn test_font_and_palette() {
+        let mut machine = crate::testing::machine();
+        let font = CreateFontA(
+            &mut machine,
+            -12,
+            0,
+            0,
+            0,
+            700,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            Some("Arial"),
+        );
+        let logfont_size = std::mem::size_of::<LOGFONTA>() as u32;
+        let (size, out) = get_object(&mut machine, font, logfont_size);
+        assert_eq!(size, logfont_size);
+        let logfont = machine.mem().get_pod::<LOGFONTA>(out);
+        assert_eq!((logfont.lfHeight, logfont.lfWeight), (-12, 700));
+        assert_eq!(&logfont.lfFaceName[..6], b"Arial\0");
+
+        // LOGPALETTE: version 0x300, two entries.
+        let plpal = crate::testing::alloc_data(&mut machine, &[0, 3, 2, 0, 1, 2, 3, 0, 4, 5, 6, 0]);
+        let palette = CreatePalette(&mut machine, plpal);
+        let (size, out) = get_object(&mut machine, palette, 2);
+        assert_eq!(size, 2);
+        assert_eq!(machine.mem().get_pod::<u16>(out), 2);
+    }
+
+    #[test]
+    fn test_bitmap() {
+        let mut machine = crate::testing::machine();
+        let header = BITMAPINFOHEADER {
+            biSize: 40,
+            biWidth: 3,
+            biHeight: -2i32 as u32,
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: 0,
+            biSizeImage: 0,
+            biXPelsPerMeter: 0,
+            biYPelsPerMeter: 0,
+            biClrUsed: 0,
+            biClrImportant: 0,
+        };
+        let pbmi = crate::testing::alloc_data(&mut machine, &[0; 40]);
+        machine.mem().put_pod::<BITMAPINFOHEADER>(pbmi, header);
+        let dib = CreateDIBSection(&mut machine, HDC::null(), pbmi, 0, None, 0, 0);
+
+        // With room, a DIB section describes itself with a DIBSECTION...
+        let dib_size = std::mem::size_of::<DIBSECTION>() as u32;
+        assert_eq!(GetObjectA(&mut machine, dib, 0, 0), dib_size);
+        let (size, out) = get_object(&mut machine, dib, dib_size);
+        assert_eq!(size, dib_size);
+        let ds = machine.mem().get_pod::<DIBSECTION>(out);
+        assert_eq!(ds.dsBm.bmWidth, 3);
+        assert_eq!(ds.dsBm.bmWidthBytes, 12);
+        assert_eq!(ds.dsBmih.biHeight, -2i32 as u32);
+        assert_ne!(ds.dsBm.bmBits, 0);
+
+        // ...and otherwise with just a BITMAP.
+        let bitmap_size = std::mem::size_of::<BITMAP>() as u32;
+        let (size, out) = get_object(&mut machine, dib, bitmap_size);
+        assert_eq!(size, bitmap_size);
+        let bm = machine.mem().get_pod::<BITMAP>(out);
+        assert_eq!((bm.bmWidth, bm.bmHeight, bm.bmBitsPixel), (3, 2, 32));
+    }
+
+    #[test]
+    fn test_failures() {
+        let mut machine = crate::testing::machine();
+        let pen = CreatePen(&mut machine, Ok(PS::SOLID), 1, COLORREF::from_rgb(0, 0, 0));
+
+        // A null out queries the size; a buffer too small fails and is untouched.
+        assert_eq!(GetObjectA(&mut machine, pen, 0, 0), 16);
+        let (size, out) = get_object(&mut machine, pen, 15);
+        assert_eq!(size, 0);
+        assert_eq!(machine.mem().get_pod::<u32>(out), 0xCCCC_CCCC);
+
+        let (size, _) = get_object(&mut machine, HGDIOBJ::from_raw(0x1234), 16);
+        assert_eq!(size, 0);
+    }
 }
@@ -1,8 +1,127 @@
-use crate::{winapi::gdi32::HGDIOBJ, Machine};
+use super::{BitmapType, DCTarget, Object, HDC};
+use crate::{
+    winapi::{bitmap::PALETTEENTRY, ddraw, gdi32::HGDIOBJ},
+    Machine,
+};
+use memory::{Extensions, ExtensionsMut};
 
 const TRACE_CONTEXT: &'static str = "gdi32/palette";
 
+const GDI_ERROR: u32 = 0xFFFF_FFFF;
+
+/// A logical palette, as created by CreatePalette.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Palette {
+    pub entries: Box<[PALETTEENTRY]>,
+}
+
 #[win32_derive::dllexport]
 pub fn CreatePalette(machine: &mut Machine, plpal: u32) -> HGDIOBJ {
-    HGDIOBJ::null()
+    if plpal == 0 {
+        return HGDIOBJ::null();
+    }
+    // LOGPALETTE: WORD palVersion, WORD palNumEntries, then the entries.
+    let mem = machine.mem();
+    let count = mem.get_pod::<u16>(plpal + 2) as u32;
+    let entries = mem
+        .iter_pod::<PALETTEENTRY>(plpal + 4, count)
+        .collect::<Vec<_>>()
+        .into_boxed_slice();
+    machine
+        .state
+        .gdi32
+        .objects
+        .add(Object::Palette(Palette { entries }))
+}
+
+#[win32_derive::dllexport]
+pub fn SelectPalette(machine: &mut Machine, hdc: HDC, hPal: HGDIOBJ, bForceBkgd: bool) -> HGDIOBJ {
+    if !matches!(
+        machine.state.gdi32.objects.get(hPal),
+        Some(Object::Palette(_))
+    ) {
+        return HGDIOBJ::null();
+    }
+    let Some(dc) = machine.state.gdi32.dcs.get_mut(hdc) else {
+        return HGDIOBJ::null();
+    };
+    std::mem::replace(&mut dc.palette, hPal)
+}
+
+/// Map the DC's selected palette into the system palette, returning how many
+/// entries were mapped.  For DCs on the screen this changes what 8bpp output
+/// looks like, including DirectDraw's.
+#[win32_derive::dllexport]
+pub fn RealizePalette(machine: &mut Machine, hdc: HDC) -> u32 {
+    let Some(dc) = machine.state.gdi32.dcs.get(hdc) else {
+        return GDI_ERROR;
+    };
+    let on_screen = !matches!(dc.target, DCTarget::Memory(_));
+    let entries = match machine.state.gdi32.objects.get(dc.palette) {
+        Some(Object::Palette(palette)) => palette.entries.clone(),
+        // The default palette is already realized.
+        _ => return 0,
+    };
+    let count = entries.len() as u32;
+    if on_screen {
+        ddraw::realize_gdi_palette(machine, &entries);
+        machine.state.gdi32.screen_palette = entries;
+    }
+    count
+}
+
+/// The 8bpp bitmap selected into a memory DC, whose color table the DIB color table
+/// functions operate on.
+fn dc_pal8_palette(gdi32: &mut super::State, hdc: HDC) -> Option<&mut Box<[[u8; 4]]>> {
+    let DCTarget::Memory(hbitmap) = gdi32.dcs.get(hdc)?.target else {
+        return None;
+    };
+    match gdi32.objects.get_mut(hbitmap)? {
+        Object::Bitmap(BitmapType::Pal8(bitmap)) => Some(&mut bitmap.palette),
+        _ => None,
+    }
+}
+
+#[win32_derive::dllexport]
+pub fn SetDIBColorTable(
+    machine: &mut Machine,
+    hdc: HDC,
+    iStart: u32,
+    cEntries: u32,
+    prgbq: u32,
+) -> u32 {
+    let mem = machine.emu.memory.mem();
+    let Some(palette) = dc_pal8_palette(&mut machine.state.gdi32, hdc) else {
+        return 0;
+    };
+    let start = (iStart as usize).min(palette.len());
+    let count = (cEntries as usize).min(palette.len() - start);
+    // RGBQUAD is BGRx.
+    for (dst, [b, g, r, _]) in palette[start..][..count]
+        .iter_mut()
+        .zip(mem.iter_pod::<[u8; 4]>(prgbq, count as u32))
+    {
+        *dst = [r, g, b, 0xFF];
+    }
+    count as u32
+}
+
+#[win32_derive::dllexport]
+pub fn GetDIBColorTable(
+    machine: &mut Machine,
+    hdc: HDC,
+    iStart: u32,
+    cEntries: u32,
+    prgbq: u32,
+) -> u32 {
+    let mem = machine.emu.memory.mem();
+    let Some(palette) = dc_pal8_palette(&mut machine.state.gdi32, hdc) else {
+        return 0;
+    };
+    let start = (iStart as usize).min(palette.len());
+    let count = (cEntries as usize).min(palette.len() - start);
+    for (i, &[r, g, b, _]) in palette[start..][..count].iter().enumerate() {
+        mem.put_pod::<[u8; 4]>(prgbq + i as u32 * 4, [b, g, r, 0]);
+    }
+    count as u32
 }
@@ -1,10 +1,13 @@
 use super::{DCTarget, Object, DC, HDC, HGDIOBJ};
-use crate::winapi::{handle::Handles, types::HWND};
+use crate::winapi::{bitmap::PALETTEENTRY, handle::Handles, types::HWND};
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct State {
     pub dcs: Handles<HDC, DC>,
     pub screen_dc: HDC,
     pub objects: Handles<HGDIOBJ, Object>,
+    /// The palette last realized on a screen DC, used to display 8bpp output.
+    pub screen_palette: Box<[PALETTEENTRY]>,
 }
 
 impl Default for State {
@@ -15,6 +18,7 @@ impl Default for State {
             dcs,
             screen_dc,
             objects: Handles::new(HGDIOBJ::lowest_value()),
+            screen_palette: Default::default(),
         }
     }
 }
@@ -1,18 +1,110 @@
-use super::{CLR_INVALID, HDC};
+use super::{bitmap::with_dst_pixels, font, BkMode, Object, CLR_INVALID, HDC, HGDIOBJ};
 use crate::{
-    winapi::{gdi32::COLORREF, stack_args::ArrayWithSize, types::HANDLE},
+    winapi::{gdi32::COLORREF, stack_args::ArrayWithSize},
     Machine,
 };
 use memory::Pod;
 
 const TRACE_CONTEXT: &'static str = "gdi32/text";
 
-pub struct FONT {}
-pub type HFONT = HANDLE<FONT>;
+/// A requested font.  All text is drawn with the built-in bitmap font, scaled to the
+/// requested height.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Font {
+    /// Character height in pixels, or 0 for the default.  Negative values request the
+    /// height of the characters rather than the cell, which we don't distinguish.
+    pub height: i32,
+    pub width: i32,
+    pub weight: u32,
+    pub italic: bool,
+    pub underline: bool,
+    pub strike_out: bool,
+    pub char_set: u8,
+    pub pitch_and_family: u8,
+    pub face_name: String,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LOGFONTA {
+    pub lfHeight: i32,
+    pub lfWidth: i32,
+    pub lfEscapement: i32,
+    pub lfOrientation: i32,
+    pub lfWeight: u32,
+    pub lfItalic: u8,
+    pub lfUnderline: u8,
+    pub lfStrikeOut: u8,
+    pub lfCharSet: u8,
+    pub lfOutPrecision: u8,
+    pub lfClipPrecision: u8,
+    pub lfQuality: u8,
+    pub lfPitchAndFamily: u8,
+    pub lfFaceName: [u8; 32],
+}
+unsafe impl memory::Pod for LOGFONTA {}
+
+impl Font {
+    pub fn to_logfont(&self) -> LOGFONTA {
+        let mut face_name = [0; 32];
+        let len = self.face_name.len().min(face_name.len() - 1);
+        face_name[..len].copy_from_slice(&self.face_name.as_bytes()[..len]);
+        LOGFONTA {
+            lfHeight: self.height,
+            lfWidth: self.width,
+            lfWeight: self.weight,
+            lfItalic: self.italic as u8,
+            lfUnderline: self.underline as u8,
+            lfStrikeOut: self.strike_out as u8,
+            lfCharSet: self.char_set,
+            lfPitchAndFamily: self.pitch_and_family,
+            lfFaceName: face_name,
+            ..Default::default()
+        }
+    }
+}
+
+const FW_SEMIBOLD: u32 = 600;
+
+/// The layout of text drawn with the font selected into a DC.
+struct Metrics {
+    width: u32,
+    height: u32,
+    bold: bool,
+}
+
+impl Metrics {
+    fn for_dc(machine: &Machine, hdc: HDC) -> Self {
+        let font = match machine.state.gdi32.dcs.get(hdc) {
+            Some(dc) => match machine.state.gdi32.objects.get(dc.font) {
+                Some(Object::Font(font)) => Some(font),
+                _ => None,
+            },
+            None => None,
+        };
+        let (height, bold) = match font {
+            Some(font) if font.height != 0 => {
+                (font.height.unsigned_abs(), font.weight >= FW_SEMIBOLD)
+            }
+            Some(font) => (font::HEIGHT, font.weight >= FW_SEMIBOLD),
+            None => (font::HEIGHT, false),
+        };
+        Metrics {
+            width: std::cmp::max(1, height * font::WIDTH / font::HEIGHT),
+            height,
+            bold,
+        }
+    }
+
+    /// The font's baseline, as a distance from the top of the cell.
+    fn ascent(&self) -> u32 {
+        self.height * 11 / 16
+    }
+}
 
 #[win32_derive::dllexport]
 pub fn CreateFontA(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     cHeight: i32,
     cWidth: i32,
     cEscapement: i32,
@@ -27,8 +119,18 @@ pub fn CreateFontA(
     iQuality: u32,
     iPitchAndFamily: u32,
     pszFaceName: Option<&str>,
-) -> HFONT {
-    HFONT::null()
+) -> HGDIOBJ {
+    machine.state.gdi32.objects.add(Object::Font(Font {
+        height: cHeight,
+        width: cWidth,
+        weight: cWeight,
+        italic: bItalic != 0,
+        underline: bUnderline != 0,
+        strike_out: bStrikeOut != 0,
+        char_set: iCharSet as u8,
+        pitch_and_family: iPitchAndFamily as u8,
+        face_name: pszFaceName.unwrap_or_default().to_string(),
+    }))
 }
 
 #[win32_derive::dllexport]
@@ -37,31 +139,101 @@ pub fn SetTextAlign(_machine: &mut Machine, hdc: HDC, fMode: u32) -> u32 {
 }
 
 #[win32_derive::dllexport]
-pub fn SetTextColor(_machine: &mut Machine, hdc: HDC, color: COLORREF) -> COLORREF {
-    CLR_INVALID // fail
+pub fn SetTextColor(machine: &mut Machine, hdc: HDC, color: COLORREF) -> COLORREF {
+    let Some(dc) = machine.state.gdi32.dcs.get_mut(hdc) else {
+        return CLR_INVALID; // fail
+    };
+    std::mem::replace(&mut dc.text_color, color)
+}
+
+/// Rasterize text into pixels, one character cell per byte of text.
+/// Unset glyph pixels are filled with bg if present, and left alone otherwise.
+fn draw_glyphs(
+    dst: &mut [[u8; 4]],
+    stride: usize,
+    x: i32,
+    y: i32,
+    text: &[u8],
+    metrics: &Metrics,
+    fg: [u8; 4],
+    bg: Option<[u8; 4]>,
+) {
+    for (i, &c) in text.iter().enumerate() {
+        let glyph = font::glyph(c);
+        let cx = x + (i as u32 * metrics.width) as i32;
+        for py in 0..metrics.height {
+            let ty = y + py as i32;
+            let row = glyph[(py * font::HEIGHT / metrics.height) as usize];
+            for px in 0..metrics.width {
+                let tx = cx + px as i32;
+                if tx < 0 || ty < 0 || tx as usize >= stride {
+                    continue;
+                }
+                let col = px * font::WIDTH / metrics.width;
+                let mut on = row & (0x80 >> col) != 0;
+                if metrics.bold && col > 0 {
+                    // Embolden by smearing each glyph pixel one to the right.
+                    on |= row & (0x80 >> (col - 1)) != 0;
+                }
+                let color = match (on, bg) {
+                    (true, _) => fg,
+                    (false, Some(bg)) => bg,
+                    (false, None) => continue,
+                };
+                if let Some(p) = dst.get_mut(ty as usize * stride + tx as usize) {
+                    *p = color;
+                }
+            }
+        }
+    }
+}
+
+fn text_out(machine: &mut Machine, hdc: HDC, x: i32, y: i32, text: &[u8]) -> bool {
+    let Some(dc) = machine.state.gdi32.dcs.get(hdc) else {
+        return false;
+    };
+    let fg = dc.text_color.to_pixel();
+    let bg = match dc.bk_mode {
+        BkMode::OPAQUE => Some(dc.bk_color.to_pixel()),
+        BkMode::TRANSPARENT => None,
+    };
+    let metrics = Metrics::for_dc(machine, hdc);
+    with_dst_pixels(machine, hdc, |dst, stride, _| {
+        draw_glyphs(dst, stride, x, y, text, &metrics, fg, bg);
+    })
 }
 
 #[win32_derive::dllexport]
 pub fn TextOutA(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     hdc: HDC,
-    x: u32,
-    y: u32,
+    x: i32,
+    y: i32,
     lpString: ArrayWithSize<u8>,
 ) -> bool {
-    let _text = std::str::from_utf8(lpString.unwrap()).unwrap();
-    true
+    let Some(text) = lpString else {
+        return false;
+    };
+    text_out(machine, hdc, x, y, text)
 }
 
 #[win32_derive::dllexport]
 pub fn TextOutW(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     hdc: HDC,
-    x: u32,
-    y: u32,
+    x: i32,
+    y: i32,
     lpString: ArrayWithSize<u16>,
 ) -> bool {
-    true
+    let Some(text) = lpString else {
+        return false;
+    };
+    // The built-in font only covers ASCII.
+    let text = text
+        .iter()
+        .map(|&c| if c < 0x80 { c as u8 } else { b'?' })
+        .collect::<Vec<_>>();
+    text_out(machine, hdc, x, y, &text)
 }
 
 #[repr(C)]
@@ -117,22 +289,38 @@ pub struct TEXTMETRICW {
 unsafe impl memory::Pod for TEXTMETRICW {}
 
 #[win32_derive::dllexport]
-pub fn GetTextMetricsA(_machine: &mut Machine, hdc: HDC, lptm: Option<&mut TEXTMETRICA>) -> bool {
+pub fn GetTextMetricsA(machine: &mut Machine, hdc: HDC, lptm: Option<&mut TEXTMETRICA>) -> bool {
+    let m = Metrics::for_dc(machine, hdc);
     let tm = lptm.unwrap();
     *tm = TEXTMETRICA::zeroed();
-
-    // SkiFree only cares about the height, just make something up for now.
-    tm.tmHeight = 12;
+    tm.tmHeight = m.height;
+    tm.tmAscent = m.ascent();
+    tm.tmDescent = m.height - m.ascent();
+    tm.tmAveCharWidth = m.width;
+    tm.tmMaxCharWidth = m.width;
+    tm.tmWeight = if m.bold { 700 } else { 400 };
+    tm.tmFirstChar = 0x20;
+    tm.tmLastChar = 0x7e;
+    tm.tmDefaultChar = b'?';
+    tm.tmBreakChar = b' ';
     true
 }
 
 #[win32_derive::dllexport]
-pub fn GetTextMetricsW(_machine: &mut Machine, hdc: HDC, lptm: Option<&mut TEXTMETRICW>) -> bool {
+pub fn GetTextMetricsW(machine: &mut Machine, hdc: HDC, lptm: Option<&mut TEXTMETRICW>) -> bool {
+    let m = Metrics::for_dc(machine, hdc);
     let tm = lptm.unwrap();
     *tm = TEXTMETRICW::zeroed();
-
-    // SkiFree only cares about the height, just make something up for now.
-    tm.tmHeight = 12;
+    tm.tmHeight = m.height;
+    tm.tmAscent = m.ascent();
+    tm.tmDescent = m.height - m.ascent();
+    tm.tmAveCharWidth = m.width;
+    tm.tmMaxCharWidth = m.width;
+    tm.tmWeight = if m.bold { 700 } else { 400 };
+    tm.tmFirstChar = 0x20;
+    tm.tmLastChar = 0x7e;
+    tm.tmDefaultChar = b'?' as u16;
+    tm.tmBreakChar = b' ' as u16;
     true
 }
 
@@ -146,30 +334,78 @@ unsafe impl memory::Pod for SIZE {}
 
 #[win32_derive::dllexport]
 pub fn GetTextExtentPoint32A(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     hdc: HDC,
     lpString: Option<&str>,
     c: i32,
     psizl: Option<&mut SIZE>,
 ) -> bool {
+    let m = Metrics::for_dc(machine, hdc);
     *psizl.unwrap() = SIZE {
-        cx: lpString.unwrap().len() as i32 * 10,
-        cy: 12,
+        cx: c * m.width as i32,
+        cy: m.height as i32,
     };
     true
 }
 
 #[win32_derive::dllexport]
 pub fn GetTextExtentPoint32W(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     hdc: HDC,
     lpString: Option<&str>,
     c: i32,
     psizl: Option<&mut SIZE>,
 ) -> bool {
+    let m = Metrics::for_dc(machine, hdc);
     *psizl.unwrap() = SIZE {
-        cx: lpString.unwrap().len() as i32 * 10,
-        cy: 12,
+        cx: c * m.width as i32,
+        cy: m.height as i32,
     };
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw_glyphs() {
+        const FG: [u8; 4] = [1, 1, 1, 1];
+        const BG: [u8; 4] = [2, 2, 2, 2];
+        let metrics = Metrics {
+            width: 8,
+            height: 16,
+            bold: false,
+        };
+
+        // '!' is a vertical bar in column 3, starting at row 4.
+        let mut dst = [[0u8; 4]; 16 * 16];
+        draw_glyphs(&mut dst, 16, 8, 0, b"!", &metrics, FG, None);
+        assert_eq!(dst[4 * 16 + 8 + 3], FG);
+        assert_eq!(dst[4 * 16 + 8 + 2], [0; 4]);
+        assert_eq!(dst[4 * 16 + 3], [0; 4]);
+
+        // Opaque background fills the cell, and text hanging off the left is clipped.
+        draw_glyphs(&mut dst, 16, -8, 0, b"!!", &metrics, FG, Some(BG));
+        assert_eq!(dst[4 * 16 + 3], FG);
+        assert_eq!(dst[4 * 16 + 2], BG);
+        assert_eq!(dst[4 * 16 + 8 + 3], FG);
+        assert_eq!(dst[4 * 16 + 8 + 2], [0; 4]);
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_text_out_fails() {
+        use crate::winapi::gdi32::{DCTarget, DC};
+        let mut machine = crate::testing::machine();
+        let surface = machine
+            .state
+            .gdi32
+            .dcs
+            .add(DC::new(DCTarget::DirectDrawSurface(0x1234)));
+        assert!(!TextOutA(&mut machine, surface, 0, 0, Some(b"hi")));
+        assert!(!TextOutA(&mut machine, surface, 0, 0, None));
+        assert!(!TextOutW(&mut machine, surface, 0, 0, None));
+        assert!(!TextOutA(&mut machine, HDC::null(), 0, 0, Some(b"hi")));
+    }
+}
@@ -26,6 +26,16 @@ impl<T> std::clone::Clone for HANDLE<T> {
     }
 }
 impl<T> std::marker::Copy for HANDLE<T> {}
+impl<T: Eq> PartialOrd for HANDLE<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<T: Eq> Ord for HANDLE<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.raw.cmp(&other.raw)
+    }
+}
 impl<T> Default for HANDLE<T> {
     fn default() -> Self {
         Self {
@@ -37,6 +47,17 @@ impl<T> Default for HANDLE<T> {
 
 unsafe impl<T: 'static> memory::Pod for HANDLE<T> {}
 
+impl<T> serde::Serialize for HANDLE<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.raw.serialize(serializer)
+    }
+}
+impl<'de, T> serde::Deserialize<'de> for HANDLE<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::from_raw(u32::deserialize(deserializer)?))
+    }
+}
+
 impl<T> HANDLE<T> {
     pub const fn from_raw(raw: u32) -> Self {
         HANDLE {
@@ -110,6 +131,11 @@ impl<T> Handle for HANDLE<T> {
 }
 
 /// Maintains a mapping of HANDLE -> V, vending out new handles.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(bound(
+    serialize = "H: serde::Serialize, V: serde::Serialize",
+    deserialize = "H: serde::Deserialize<'de>, V: serde::Deserialize<'de>"
+))]
 pub struct Handles<H: Handle, V> {
     map: HashMap<u32, V>,
     next: H,
@@ -157,6 +183,14 @@ impl<H: Handle, V> Handles<H, V> {
         self.map.values()
     }
 
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.map.values_mut()
+    }
+
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = (H, &mut V)> {
+        self.map.iter_mut().map(|(&raw, v)| (H::from_raw(raw), v))
+    }
+
     pub fn remove(&mut self, handle: H) -> Option<V> {
         self.map.remove(&handle.to_raw())
     }
@@ -5,7 +5,7 @@
 use super::alloc::align_to;
 use memory::{Extensions, ExtensionsMut, Mem};
 
-#[derive(Default)]
+#[derive(Default, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Heap {
     pub addr: u32,
     pub size: u32,
@@ -26,13 +26,16 @@ impl Heap {
         self.addr..self.addr + self.size
     }
 
+    /// Allocate a block of at least `size` bytes, returning 0 if the heap is exhausted.
     pub fn alloc(&mut self, mem: Mem, size: u32) -> u32 {
         let size = align_to(size, 4) + 4;
-        let i = self
-            .freelist
-            .iter()
-            .position(|f| f.size >= size)
-            .unwrap_or_else(|| panic!("heap size {:x} oom {:x}", self.size, size));
+        let i = match self.freelist.iter().position(|f| f.size >= size) {
+            Some(i) => i,
+            None => {
+                log::warn!("heap size {:x} oom {:x}", self.size, size);
+                return 0;
+            }
+        };
         let free = &mut self.freelist[i];
         let addr = free.addr;
         free.size -= size;
@@ -105,7 +108,7 @@ impl Heap {
 }
 
 /// Entry in the FreeList.
-#[derive(Debug)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct FreeNode {
     addr: u32,
     size: u32,
@@ -116,3 +119,37 @@ impl FreeNode {
         self.addr..self.addr + self.size
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Heap;
+    use memory::Mem;
+
+    #[test]
+    fn alloc_free() {
+        let buf = vec![0u8; 0x100];
+        let mem = Mem::from_slice(&buf);
+        let mut heap = Heap::new(0x10, 0x40);
+
+        let a = heap.alloc(mem, 5);
+        assert_eq!(a, 0x14);
+        assert_eq!(heap.size(mem, a), 8);
+        let b = heap.alloc(mem, 8);
+        assert_eq!(b, 0x20);
+
+        heap.free(mem, a);
+        heap.free(mem, b);
+        // Everything merged back into one free block, so a full-size alloc fits.
+        assert_eq!(heap.alloc(mem, 0x3c), 0x14);
+    }
+
+    #[test]
+    fn oom() {
+        let buf = vec![0u8; 0x100];
+        let mem = Mem::from_slice(&buf);
+        let mut heap = Heap::new(0x10, 0x40);
+        assert_eq!(heap.alloc(mem, 0x40), 0);
+        assert_ne!(heap.alloc(mem, 0x20), 0);
+        assert_eq!(heap.alloc(mem, 0x20), 0);
+    }
+}
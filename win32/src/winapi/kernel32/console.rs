@@ -64,18 +64,13 @@ pub fn GetConsoleScreenBufferInfo(
 
 #[win32_derive::dllexport]
 pub fn WriteConsoleA(
-    _machine: &mut Machine,
-    hConsoleOutput: HANDLE<()>,
+    machine: &mut Machine,
+    hConsoleOutput: HFILE,
     lpBuffer: ArrayWithSize<u8>,
     lpNumberOfCharsWritten: Option<&mut u32>,
     lpReserved: u32,
 ) -> bool {
-    let msg = std::str::from_utf8(lpBuffer.unwrap()).unwrap();
-    log::debug!("WriteConsoleA: {:?}", msg);
-    if let Some(w) = lpNumberOfCharsWritten {
-        *w = msg.len() as u32;
-    }
-    true // success
+    WriteFile(machine, hConsoleOutput, lpBuffer, lpNumberOfCharsWritten, 0)
 }
 
 #[win32_derive::dllexport]
@@ -1,5 +1,7 @@
-use crate::winapi::kernel32::set_last_error;
-use memory::{Extensions, Pod};
+use crate::winapi::kernel32::{
+    full_path, raise_from_shim, set_last_error, STDERR_HFILE, STDIN_HFILE, STDOUT_HFILE,
+};
+use memory::{Extensions, ExtensionsMut, Pod};
 
 use crate::{
     host,
@@ -8,7 +10,6 @@ use crate::{
     str16::expect_ascii,
     winapi::{self, stack_args::ArrayWithSizeMut, types::*, ImportSymbol},
 };
-use std::io::Write;
 use typed_path::WindowsPath;
 
 const TRACE_CONTEXT: &'static str = "kernel32/dll";
@@ -19,10 +20,20 @@ const TRACE_CONTEXT: &'static str = "kernel32/dll";
 pub struct HMODULET;
 pub type HMODULE = HANDLE<HMODULET>;
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct DLL {
     pub name: String,
 
+    /// Full Windows path the DLL was loaded from, as returned by GetModuleFileName.
+    pub path: String,
+
     pub dll: pe::DLL,
+
+    /// Count of outstanding LoadLibrary calls, decremented by FreeLibrary.
+    pub refcount: u32,
+
+    /// Whether DllMain has been called with DLL_PROCESS_ATTACH.
+    pub attached: bool,
 }
 
 impl DLL {
@@ -32,12 +43,69 @@ impl DLL {
             ImportSymbol::Ordinal(ord) => self
                 .dll
                 .fns
-                .get((ord - self.dll.ordinal_base) as usize)
-                .copied(),
+                .get(ord.checked_sub(self.dll.ordinal_base)? as usize)
+                .copied()
+                .filter(|&addr| addr != self.dll.base), // unused ordinal slot
+        }
+    }
+}
+
+/// An import from a module's delay-load table, resolved on first call by
+/// retrowin32_delay_load.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct DelayImport {
+    pub dll: String,
+    /// Imported name, or None when importing by ordinal.
+    pub name: Option<String>,
+    pub ordinal: u32,
+    /// IAT slot to patch once resolved.
+    pub iat_addr: u32,
+    /// Where to store the module's HMODULE once loaded.
+    pub hmodule_addr: u32,
+}
+
+impl DelayImport {
+    pub fn symbol(&self) -> ImportSymbol<'_> {
+        match &self.name {
+            Some(name) => ImportSymbol::Name(name),
+            None => ImportSymbol::Ordinal(self.ordinal),
         }
     }
 }
 
+/// Forwarded exports can chain through multiple DLLs; give up after this many hops.
+const MAX_FORWARD_DEPTH: usize = 8;
+
+/// Look up a symbol exported by a loaded module, following forwarded exports
+/// (like "NTDLL.RtlAllocateHeap") into the modules they name.
+pub fn resolve_export(machine: &mut Machine, hmodule: HMODULE, sym: &ImportSymbol) -> Option<u32> {
+    resolve_export_depth(machine, hmodule, sym, MAX_FORWARD_DEPTH)
+}
+
+fn resolve_export_depth(
+    machine: &mut Machine,
+    hmodule: HMODULE,
+    sym: &ImportSymbol,
+    depth: usize,
+) -> Option<u32> {
+    let dll = machine.state.kernel32.dlls.get_mut(&hmodule)?;
+    let addr = dll.resolve(sym)?;
+    let forward = match dll.dll.forwards.get(&addr) {
+        None => return Some(addr),
+        Some(forward) => forward.clone(),
+    };
+    if depth == 0 {
+        log::warn!("{}!{sym}: too many forwarded exports", dll.name);
+        return None;
+    }
+    let (dll_name, sym) = pe::parse_forward(&forward)?;
+    let target = load_library(machine, dll_name);
+    if target.is_null() {
+        return None;
+    }
+    resolve_export_depth(machine, target, &sym, depth - 1)
+}
+
 fn normalize_module_name(name: &str) -> String {
     let mut name = name.to_ascii_lowercase();
     if !name.ends_with(".dll") && !name.ends_with(".") {
@@ -92,33 +160,63 @@ pub fn GetModuleHandleExW(
     return !hMod.is_null();
 }
 
+/// Path of the exe (for a null hModule) or of a loaded DLL.
+fn module_file_name(machine: &Machine, hModule: HMODULE) -> Option<&str> {
+    let kernel32 = &machine.state.kernel32;
+    if hModule.is_null() || hModule.to_raw() == kernel32.image_base {
+        return Some(&kernel32.exe_path);
+    }
+    kernel32.dlls.get(&hModule).map(|dll| dll.path.as_str())
+}
+
+/// Copy a module path into the caller's buffer, returning the GetModuleFileName result.
+/// Like Vista and later, a truncated path is still nul-terminated.
+fn write_module_file_name<T: Copy + Default>(
+    machine: &mut Machine,
+    name: &[T],
+    buf: Option<&mut [T]>,
+) -> u32 {
+    let buf = buf.unwrap_or_default();
+    if name.len() < buf.len() {
+        buf[..name.len()].copy_from_slice(name);
+        buf[name.len()] = T::default();
+        set_last_error(machine, winapi::ERROR::SUCCESS);
+        return name.len() as u32;
+    }
+    if let Some((nul, prefix)) = buf.split_last_mut() {
+        prefix.copy_from_slice(&name[..prefix.len()]);
+        *nul = T::default();
+    }
+    set_last_error(machine, winapi::ERROR::INSUFFICIENT_BUFFER);
+    buf.len() as u32
+}
+
 #[win32_derive::dllexport]
 pub fn GetModuleFileNameA(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     hModule: HMODULE,
     filename: ArrayWithSizeMut<u8>,
 ) -> u32 {
-    assert!(hModule.is_null());
-    match filename.unwrap().write(b"TODO.exe\0") {
-        Ok(n) => n as u32,
-        Err(err) => {
-            log::warn!("GetModuleFileNameA(): {}", err);
-            0
-        }
-    }
+    let Some(name) = module_file_name(machine, hModule) else {
+        set_last_error(machine, winapi::ERROR::MOD_NOT_FOUND);
+        return 0;
+    };
+    let name = name.as_bytes().to_vec();
+    write_module_file_name(machine, &name, filename.to_option())
 }
 
 #[win32_derive::dllexport]
 pub fn GetModuleFileNameW(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     hModule: HMODULE,
-    _lpFilename: u32,
-    _nSize: u32,
+    filename: ArrayWithSizeMut<u16>,
 ) -> u32 {
-    if !hModule.is_null() {
-        log::error!("unimplemented: GetModuleFileNameW(non-null)")
-    }
-    0 // fail
+    let Some(name) = module_file_name(machine, hModule) else {
+        set_last_error(machine, winapi::ERROR::MOD_NOT_FOUND);
+        return 0;
+    };
+    let name = String16::from(name).0;
+    write_module_file_name(machine, &name, filename.to_option())
 }
 
 pub fn load_library(machine: &mut Machine, filename: &str) -> HMODULE {
@@ -138,7 +236,10 @@ pub fn load_library(machine: &mut Machine, filename: &str) -> HMODULE {
     if filename.starts_with("api-") {
         match winapi::apiset(&filename) {
             Some(name) => filename = name.to_string(),
-            None => return HMODULE::null(),
+            None => {
+                set_last_error(machine, winapi::ERROR::MOD_NOT_FOUND);
+                return HMODULE::null();
+            }
         }
     } else if let Some(alias) = winapi::dll_alias(&filename) {
         filename = alias.to_string();
@@ -149,21 +250,28 @@ pub fn load_library(machine: &mut Machine, filename: &str) -> HMODULE {
     let builtin = winapi::DLLS.iter().find(|&dll| dll.file_name == filename);
     let mut buf = Vec::new();
 
+    let mut path = format!("C:\\Windows\\System32\\{filename}");
     let contents = {
         if let Some(builtin) = builtin {
             builtin.raw
         } else {
-            let exe = machine.state.kernel32.cmdline.args.first().unwrap();
+            let exe = &machine.state.kernel32.exe_path;
             let exe_dir = exe.rsplitn(2, '\\').last().unwrap();
-            let dll_paths = [format!("{exe_dir}\\{filename}"), filename.to_string()];
-            for path in &dll_paths {
-                let path = WindowsPath::new(path);
-                let mut file = match machine.host.open(path, host::FileOptions::read()) {
+            let mut dll_paths = vec![format!("{exe_dir}\\{filename}")];
+            if let Ok(path) = full_path(machine, &filename) {
+                dll_paths.push(path.to_string_lossy().into_owned());
+            }
+            for dll_path in &dll_paths {
+                let mut file = match machine
+                    .host
+                    .open(WindowsPath::new(dll_path), host::FileOptions::read())
+                {
                     Ok(file) => file,
                     Err(_) => continue,
                 };
                 file.read_to_end(&mut buf).unwrap();
                 // TODO: close file.
+                path = dll_path.clone();
                 break;
             }
             &buf
@@ -172,6 +280,7 @@ pub fn load_library(machine: &mut Machine, filename: &str) -> HMODULE {
 
     if contents.is_empty() {
         log::warn!("load_library({filename:?}): not found");
+        set_last_error(machine, winapi::ERROR::MOD_NOT_FOUND);
         return HMODULE::null();
     }
 
@@ -191,30 +300,87 @@ pub fn load_library(machine: &mut Machine, filename: &str) -> HMODULE {
         hmodule,
         DLL {
             name: filename,
+            path,
             dll,
+            refcount: 0,
+            attached: false,
         },
     );
     hmodule
 }
 
+/// Call DllMain(DLL_PROCESS_ATTACH) on any loaded DLLs that haven't been attached yet.
+/// Loops because a DllMain may itself load more DLLs.
+pub async fn attach_dlls(machine: &mut Machine) {
+    loop {
+        let Some((&hmodule, dll)) = machine
+            .state
+            .kernel32
+            .dlls
+            .iter_mut()
+            .find(|(_, dll)| !dll.attached)
+        else {
+            break;
+        };
+        dll.attached = true;
+        let Some(entry_point) = dll.dll.entry_point else {
+            continue;
+        };
+        let hInstance = hmodule.to_raw();
+        let fdwReason = 1u32; // DLL_PROCESS_ATTACH
+        let lpvReserved = 0u32;
+        machine
+            .call_x86(entry_point, vec![hInstance, fdwReason, lpvReserved])
+            .await;
+    }
+}
+
 #[win32_derive::dllexport]
-pub fn LoadLibraryA(machine: &mut Machine, filename: Option<&str>) -> HMODULE {
-    load_library(machine, filename.unwrap())
+pub async fn LoadLibraryA(machine: &mut Machine, filename: Option<&str>) -> HMODULE {
+    let Some(filename) = filename else {
+        set_last_error(machine, winapi::ERROR::INVALID_PARAMETER);
+        return HMODULE::null();
+    };
+    let hmodule = load_library(machine, filename);
+    if let Some(dll) = machine.state.kernel32.dlls.get_mut(&hmodule) {
+        dll.refcount += 1;
+        attach_dlls(machine).await;
+    }
+    hmodule
 }
 
 #[win32_derive::dllexport]
-pub fn LoadLibraryExW(
+pub async fn LoadLibraryExA(
+    machine: &mut Machine,
+    lpLibFileName: Option<&str>,
+    hFile: HFILE,
+    dwFlags: u32,
+) -> HMODULE {
+    if dwFlags != 0 {
+        log::warn!("LoadLibraryExA: ignoring flags {dwFlags:x}");
+    }
+    LoadLibraryA(machine, lpLibFileName).await
+}
+
+#[win32_derive::dllexport]
+pub async fn LoadLibraryExW(
     machine: &mut Machine,
     lpLibFileName: Option<&Str16>,
     hFile: HFILE,
     dwFlags: u32,
 ) -> HMODULE {
     let filename = lpLibFileName.map(|f| f.to_string());
-    LoadLibraryA(machine, filename.as_deref())
+    LoadLibraryExA(machine, filename.as_deref(), hFile, dwFlags).await
 }
 
 #[win32_derive::dllexport]
-pub fn FreeLibrary(_machine: &mut Machine, hLibModule: HMODULE) -> bool {
+pub fn FreeLibrary(machine: &mut Machine, hLibModule: HMODULE) -> bool {
+    let Some(dll) = machine.state.kernel32.dlls.get_mut(&hLibModule) else {
+        set_last_error(machine, winapi::ERROR::INVALID_HANDLE);
+        return false;
+    };
+    // We never unmap DLLs, so reaching zero doesn't unload anything.
+    dll.refcount = dll.refcount.saturating_sub(1);
     true // success
 }
 
@@ -244,18 +410,55 @@ pub fn get_kernel32_builtin(machine: &mut Machine, name: &str) -> u32 {
     get_symbol(machine, "kernel32.dll", name)
 }
 
+/// The exceptions the delay-load helper raises when resolving an import fails,
+/// VcppException(ERROR_SEVERITY_ERROR, ERROR_MOD_NOT_FOUND/ERROR_PROC_NOT_FOUND).
+pub const DELAYLOAD_MOD_NOT_FOUND: u32 = 0xC06D_007E;
+pub const DELAYLOAD_PROC_NOT_FOUND: u32 = 0xC06D_007F;
+
+/// This function is not part of the Windows API, but is rather the target of the
+/// thunks the loader installs for delay-loaded imports.  It resolves the import,
+/// patches its IAT slot so later calls go direct, and returns the address for the
+/// thunk to jump to.  If the import can't be resolved it raises the delay-load
+/// exception instead, and returns 0 should a handler continue execution.
+#[win32_derive::dllexport]
+pub async fn retrowin32_delay_load(machine: &mut Machine, index: u32) -> u32 {
+    let import = machine.state.kernel32.delay_imports[index as usize].clone();
+    let sym = import.symbol();
+    let hmodule = load_library(machine, &import.dll);
+    if hmodule.is_null() {
+        log::error!("delay-load import {}!{sym}: module not found", import.dll);
+        raise_from_shim(machine, DELAYLOAD_MOD_NOT_FOUND, 0, &[], 4).await;
+        return 0;
+    }
+    machine
+        .mem()
+        .put_pod::<u32>(import.hmodule_addr, hmodule.to_raw());
+    if let Some(dll) = machine.state.kernel32.dlls.get_mut(&hmodule) {
+        dll.refcount += 1;
+    }
+    attach_dlls(machine).await;
+
+    let Some(addr) = resolve_export(machine, hmodule, &sym) else {
+        log::error!("delay-load import {}!{sym}: symbol not found", import.dll);
+        raise_from_shim(machine, DELAYLOAD_PROC_NOT_FOUND, 0, &[], 4).await;
+        return 0;
+    };
+    machine.mem().put_pod::<u32>(import.iat_addr, addr);
+    machine.labels.insert(addr, format!("{}!{sym}", import.dll));
+    addr
+}
+
 #[win32_derive::dllexport]
 pub fn GetProcAddress(
     machine: &mut Machine,
     hModule: HMODULE,
     lpProcName: GetProcAddressArg,
 ) -> u32 {
-    if let Some(dll) = machine.state.kernel32.dlls.get_mut(&hModule) {
-        if let Some(addr) = dll.resolve(&lpProcName.0) {
-            return addr;
-        }
+    if let Some(addr) = resolve_export(machine, hModule, &lpProcName.0) {
+        return addr;
     }
     log::warn!("GetProcAddress({:x?}, {:?}) failed", hModule, lpProcName);
+    set_last_error(machine, winapi::ERROR::PROC_NOT_FOUND);
     0 // fail
 }
 
@@ -283,14 +486,22 @@ pub struct STARTUPINFOA {
 }
 unsafe impl ::memory::Pod for STARTUPINFOA {}
 
+const STARTF_USESHOWWINDOW: u32 = 0x1;
+const STARTF_USESTDHANDLES: u32 = 0x100;
+
 #[win32_derive::dllexport]
-pub fn GetStartupInfoA(_machine: &mut Machine, lpStartupInfo: Option<&mut STARTUPINFOA>) -> u32 {
+pub fn GetStartupInfoA(machine: &mut Machine, lpStartupInfo: Option<&mut STARTUPINFOA>) -> u32 {
     // MSVC runtime library passes in uninitialized memory for lpStartupInfo, so don't trust info.cb.
     let info = lpStartupInfo.unwrap();
     let len = std::mem::size_of::<STARTUPINFOA>() as u32;
     unsafe { info.clear_memory(len) };
 
     info.cb = len;
+    info.dwFlags = STARTF_USESHOWWINDOW | STARTF_USESTDHANDLES;
+    info.wShowWindow = machine.host.initial_show_state();
+    info.hStdInput = STDIN_HFILE.to_raw();
+    info.hStdOutput = STDOUT_HFILE.to_raw();
+    info.hStdError = STDERR_HFILE.to_raw();
 
     0
 }
@@ -305,3 +516,54 @@ pub fn GetStartupInfoW(machine: &mut Machine, lpStartupInfo: Option<&mut STARTUP
 pub fn DisableThreadLibraryCalls(_machine: &mut Machine, hLibModule: HMODULE) -> bool {
     true // succeed
 }
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn delay_load_missing_module() {
+        let mut machine = testing::machine();
+        let slots = testing::alloc_data(&mut machine, &[0; 8]);
+        machine.state.kernel32.delay_imports.push(DelayImport {
+            dll: "nosuch.dll".into(),
+            name: Some("Missing".into()),
+            ordinal: 0,
+            iat_addr: slots,
+            hmodule_addr: slots + 4,
+        });
+        let helper = get_kernel32_builtin(&mut machine, "retrowin32_delay_load");
+
+        // push 0; mov eax, helper; call eax; ret
+        let mut code = vec![0x6a, 0x00, 0xb8];
+        code.extend_from_slice(&helper.to_le_bytes());
+        code.extend_from_slice(&[0xff, 0xd0, 0xc3]);
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        assert_eq!(
+            testing::run_exe(&mut machine, entry),
+            DELAYLOAD_MOD_NOT_FOUND
+        );
+    }
+
+    /// A DLL that isn't next to the exe is found in the emulated current directory.
+    #[test]
+    fn load_library_current_dir() {
+        let host = testing::TestHost::default();
+        let dll = include_bytes!("../../../../exe/local_dll/local.dll");
+        host.files.borrow_mut().insert(
+            r"C:\plugins\local.dll".into(),
+            std::rc::Rc::new(std::cell::RefCell::new(dll.to_vec())),
+        );
+        let mut machine = Machine::new(Box::new(host), r"C:\game\main.exe".into());
+        machine.state.kernel32.exe_path = r"C:\game\main.exe".into();
+        assert!(load_library(&mut machine, "local.dll").is_null());
+
+        machine.state.kernel32.current_dir = Some(r"C:\plugins".into());
+        let hmodule = load_library(&mut machine, "local.dll");
+        assert!(!hmodule.is_null());
+        let dll = &machine.state.kernel32.dlls[&hmodule];
+        assert_eq!(dll.path, r"C:\plugins\local.dll");
+    }
+}
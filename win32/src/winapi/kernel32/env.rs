@@ -1,51 +1,219 @@
-use crate::{str16::Str16, winapi::stack_args::ArrayWithSize, Machine};
+use super::set_last_error;
+use crate::{
+    str16::{Str16, String16},
+    winapi::{stack_args::ArrayWithSizeMut, ERROR},
+    Machine,
+};
+use memory::ExtensionsMut;
 
 const TRACE_CONTEXT: &'static str = "kernel32/env";
 
+/// Process environment variables, in insertion order.
+/// Names are matched case-insensitively, as on Windows.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Env(Vec<(String, String)>);
+
+impl Default for Env {
+    fn default() -> Self {
+        Env(vec![("WINDIR".into(), "C:\\Windows".into())])
+    }
+}
+
+impl Env {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Set a variable, or remove it when value is None.
+    pub fn set(&mut self, name: &str, value: Option<&str>) {
+        let pos = self
+            .0
+            .iter()
+            .position(|(n, _)| n.eq_ignore_ascii_case(name));
+        match (pos, value) {
+            (Some(i), Some(value)) => self.0[i].1 = value.to_string(),
+            (Some(i), None) => {
+                self.0.remove(i);
+            }
+            (None, Some(value)) => self.0.push((name.to_string(), value.to_string())),
+            (None, None) => {}
+        }
+    }
+
+    /// The classic environment block: NAME=value strings, each nul-terminated,
+    /// with an extra nul at the end.
+    pub(crate) fn block(&self) -> String {
+        let mut block = String::new();
+        for (name, value) in &self.0 {
+            block.push_str(name);
+            block.push('=');
+            block.push_str(value);
+            block.push('\0');
+        }
+        block.push('\0');
+        block
+    }
+}
+
+/// Copy a snapshot of the environment block into a fresh process heap allocation.
+fn alloc_block(machine: &mut Machine, block: &[u8]) -> u32 {
+    let heap = machine
+        .state
+        .kernel32
+        .get_process_heap(&mut machine.emu.memory);
+    let addr = heap.alloc(machine.emu.memory.mem(), block.len() as u32);
+    if addr == 0 {
+        return 0;
+    }
+    machine
+        .mem()
+        .sub32_mut(addr, block.len() as u32)
+        .copy_from_slice(block);
+    addr
+}
+
+fn free_block(machine: &mut Machine, addr: u32) -> bool {
+    if addr == 0 {
+        return false;
+    }
+    let heap = machine
+        .state
+        .kernel32
+        .get_process_heap(&mut machine.emu.memory);
+    heap.free(machine.emu.memory.mem(), addr);
+    true
+}
+
 #[win32_derive::dllexport]
 pub fn GetEnvironmentStrings(machine: &mut Machine) -> u32 {
-    machine.state.kernel32.env
+    let block = machine.state.kernel32.env.block();
+    alloc_block(machine, block.as_bytes())
 }
 
 #[win32_derive::dllexport]
-pub fn FreeEnvironmentStringsA(_machine: &mut Machine, _penv: u32) -> bool {
-    true // success
+pub fn GetEnvironmentStringsA(machine: &mut Machine) -> u32 {
+    GetEnvironmentStrings(machine)
 }
 
 #[win32_derive::dllexport]
-pub fn GetEnvironmentStringsW(_machine: &mut Machine) -> u32 {
-    // CRT startup appears to fallback on non-W version of this if it returns null.
-    0
+pub fn FreeEnvironmentStringsA(machine: &mut Machine, penv: u32) -> bool {
+    free_block(machine, penv)
 }
 
 #[win32_derive::dllexport]
-pub fn FreeEnvironmentStringsW(_machine: &mut Machine) -> bool {
-    true // success
+pub fn GetEnvironmentStringsW(machine: &mut Machine) -> u32 {
+    let block = String16::from(&machine.state.kernel32.env.block());
+    let bytes = block
+        .0
+        .iter()
+        .flat_map(|c| c.to_le_bytes())
+        .collect::<Vec<_>>();
+    alloc_block(machine, &bytes)
+}
+
+#[win32_derive::dllexport]
+pub fn FreeEnvironmentStringsW(machine: &mut Machine, penv: u32) -> bool {
+    free_block(machine, penv)
+}
+
+/// Shared lookup for GetEnvironmentVariable{A,W}.
+/// On failure sets the last error and returns the value the API should return.
+fn get_environment_variable(machine: &mut Machine, name: Option<&str>) -> Result<String, u32> {
+    let Some(name) = name else {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return Err(0);
+    };
+    match machine.state.kernel32.env.get(name) {
+        Some(value) => Ok(value.to_string()),
+        None => {
+            set_last_error(machine, ERROR::ENVVAR_NOT_FOUND);
+            Err(0)
+        }
+    }
 }
 
 #[win32_derive::dllexport]
 pub fn GetEnvironmentVariableA(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     name: Option<&str>,
-    buf: ArrayWithSize<u8>,
-) -> bool {
-    false
+    buf: ArrayWithSizeMut<u8>,
+) -> u32 {
+    let value = match get_environment_variable(machine, name) {
+        Ok(value) => value,
+        Err(ret) => return ret,
+    };
+    let buf = buf.to_option().unwrap_or_default();
+    if buf.len() <= value.len() {
+        // Too small: return the needed size, including the nul.
+        return value.len() as u32 + 1;
+    }
+    buf[..value.len()].copy_from_slice(value.as_bytes());
+    buf[value.len()] = 0;
+    value.len() as u32
 }
 
 #[win32_derive::dllexport]
 pub fn GetEnvironmentVariableW(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     name: Option<&Str16>,
-    buf: ArrayWithSize<u16>,
-) -> bool {
-    false
+    buf: ArrayWithSizeMut<u16>,
+) -> u32 {
+    let name = name.map(|name| name.to_string());
+    let value = match get_environment_variable(machine, name.as_deref()) {
+        Ok(value) => String16::from(&value),
+        Err(ret) => return ret,
+    };
+    let buf = buf.to_option().unwrap_or_default();
+    let len = value.0.len();
+    if buf.len() <= len {
+        return len as u32 + 1;
+    }
+    buf[..len].copy_from_slice(&value.0);
+    buf[len] = 0;
+    len as u32
 }
 
 #[win32_derive::dllexport]
 pub fn SetEnvironmentVariableA(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     name: Option<&str>,
     value: Option<&str>,
 ) -> bool {
+    let Some(name) = name else {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return false;
+    };
+    machine.state.kernel32.env.set(name, value);
     true
 }
+
+#[win32_derive::dllexport]
+pub fn SetEnvironmentVariableW(
+    machine: &mut Machine,
+    name: Option<&Str16>,
+    value: Option<&Str16>,
+) -> bool {
+    let name = name.map(|name| name.to_string());
+    let value = value.map(|value| value.to_string());
+    SetEnvironmentVariableA(machine, name.as_deref(), value.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Env;
+
+    #[test]
+    fn test_env() {
+        let mut env = Env::default();
+        env.set("Foo", Some("bar"));
+        assert_eq!(env.get("FOO"), Some("bar"));
+        env.set("foo", Some("baz"));
+        assert_eq!(env.get("Foo"), Some("baz"));
+        assert_eq!(env.block(), "WINDIR=C:\\Windows\0Foo=baz\0\0");
+        env.set("FOO", None);
+        assert_eq!(env.get("foo"), None);
+    }
+}
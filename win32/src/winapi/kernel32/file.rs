@@ -9,11 +9,11 @@ use crate::{
         stack_args::{ArrayWithSize, ArrayWithSizeMut},
         types::{Str16, HFILE},
     },
-    FileOptions, ReadDir, ReadDirEntry, Stat, StatKind,
+    FileOptions, Host, ReadDir, ReadDirEntry, Stat, StatKind,
 };
 use bitflags::bitflags;
 use memory::ExtensionsMut;
-use typed_path::WindowsPath;
+use typed_path::{WindowsPath, WindowsPathBuf};
 
 const TRACE_CONTEXT: &'static str = "kernel32/file";
 
@@ -140,6 +140,38 @@ impl TryFrom<u32> for GENERIC {
     }
 }
 
+/// A file opened by CreateFile.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct OpenFile {
+    /// The path and options the file was opened with, so it can be reopened by restore_state().
+    path: String,
+    options: FileOptions,
+    /// The file position, as of the last save_state().
+    position: u64,
+    #[serde(skip, default = "crate::save_state::detached")]
+    pub file: Box<dyn crate::host::File>,
+}
+
+impl OpenFile {
+    /// Record the file position, ahead of a save_state().
+    pub fn save_position(&mut self) -> std::io::Result<()> {
+        self.position = self.file.stream_position()?;
+        Ok(())
+    }
+
+    /// Reopen the file after a restore_state(), at the position it was saved at.
+    pub fn reopen(&mut self, host: &dyn Host) -> Result<(), ERROR> {
+        let options = FileOptions {
+            read: self.options.read,
+            write: self.options.write,
+            ..Default::default()
+        };
+        self.file = host.open(WindowsPath::new(&self.path), options)?;
+        self.file.seek(std::io::SeekFrom::Start(self.position))?;
+        Ok(())
+    }
+}
+
 #[win32_derive::dllexport]
 pub fn CreateFileA(
     machine: &mut Machine,
@@ -157,8 +189,16 @@ pub fn CreateFileA(
         return HFILE::invalid();
     };
 
-    // If this from_bits fails, it's due to more complex access bits; see ACCESS_MASK in MSDN docs.
-    let generic_access = GENERIC::from_bits(dwDesiredAccess).unwrap();
+    // Besides the GENERIC_* bits, callers may request specific rights like
+    // FILE_READ_DATA (0x1) / FILE_WRITE_DATA (0x2) / FILE_APPEND_DATA (0x4);
+    // see ACCESS_MASK in MSDN docs.
+    let mut generic_access = GENERIC::from_bits_truncate(dwDesiredAccess);
+    if generic_access.contains(GENERIC::ALL) || dwDesiredAccess & 0x1 != 0 {
+        generic_access |= GENERIC::READ;
+    }
+    if generic_access.contains(GENERIC::ALL) || dwDesiredAccess & 0x6 != 0 {
+        generic_access |= GENERIC::WRITE;
+    }
     let creation_disposition = match dwCreationDisposition {
         Ok(value) => value,
         Err(value) => {
@@ -183,20 +223,29 @@ pub fn CreateFileA(
         create_new: creation_disposition == CreationDisposition::CREATE_NEW,
     };
 
-    let attr = dwFlagsAndAttributes.unwrap();
+    // The high bits are FILE_FLAG_* hints (e.g. FILE_FLAG_SEQUENTIAL_SCAN) that don't
+    // affect us, and attributes only matter when creating a file, so just note them.
+    let attr = FileAttribute::from_bits_truncate(dwFlagsAndAttributes.unwrap().bits() & 0xFFFF);
     if !(attr & !FileAttribute::NORMAL).is_empty() {
-        unimplemented!("dwFlagsAndAttributes {attr:?}");
+        log::debug!("CreateFileA({file_name:?}): ignoring attributes {attr:?}");
     }
 
     if !hTemplateFile.is_null() {
         unimplemented!("hTemplateFile {hTemplateFile:?}");
     }
 
-    let path = WindowsPath::new(file_name);
-    match machine.host.open(path, file_options) {
+    let Some(path) = resolve_path(machine, "CreateFileA", file_name) else {
+        return HFILE::invalid();
+    };
+    match machine.host.open(&path, file_options.clone()) {
         Ok(file) => {
             set_last_error(machine, ERROR::SUCCESS);
-            machine.state.kernel32.files.add(file)
+            machine.state.kernel32.files.add(OpenFile {
+                path: path.to_string_lossy().into_owned(),
+                options: file_options,
+                position: 0,
+                file,
+            })
         }
         Err(err) => {
             log::debug!("CreateFileA({file_name:?}) failed: {err:?}",);
@@ -286,7 +335,9 @@ impl FILETIME {
         if hnsecs > i64::MAX as u64 {
             return i64::MAX;
         }
-        (hnsecs as i64).saturating_sub(HNSEC_UNIX_OFFSET) * 100
+        (hnsecs as i64)
+            .saturating_sub(HNSEC_UNIX_OFFSET)
+            .saturating_mul(100)
     }
 }
 
@@ -329,7 +380,7 @@ pub fn GetFileInformationByHandle(
     lpFileInformation: Option<&mut BY_HANDLE_FILE_INFORMATION>,
 ) -> bool {
     let file = match machine.state.kernel32.files.get(hFile) {
-        Some(f) => f,
+        Some(f) => &f.file,
         None => {
             log::debug!("GetFileInformationByHandle({hFile:?}) unknown handle");
             set_last_error(machine, ERROR::INVALID_DATA);
@@ -373,7 +424,7 @@ pub fn SetFilePointer(
     if let Some(high) = &mut lpDistanceToMoveHigh {
         lDistanceToMove |= (**high as i64) << 32;
     }
-    let Some(file) = machine.state.kernel32.files.get_mut(hFile) else {
+    let Some(OpenFile { file, .. }) = machine.state.kernel32.files.get_mut(hFile) else {
         log::debug!("SetFilePointer({hFile:?}) unknown handle");
         set_last_error(machine, ERROR::INVALID_HANDLE);
         return u32::MAX;
@@ -414,10 +465,13 @@ pub fn ReadFile(
     if let Some(bytes) = lpNumberOfBytesRead.as_deref_mut() {
         *bytes = 0;
     }
-    let Some(file) = (match hFile {
-        STDIN_HFILE => unimplemented!("ReadFile(stdin)"),
-        _ => machine.state.kernel32.files.get_mut(hFile),
-    }) else {
+    if hFile == STDIN_HFILE {
+        // The host doesn't provide any input stream, so stdin is always at EOF.
+        log::warn!("ReadFile(stdin): no input available");
+        set_last_error(machine, ERROR::SUCCESS);
+        return true;
+    }
+    let Some(OpenFile { file, .. }) = machine.state.kernel32.files.get_mut(hFile) else {
         log::debug!("ReadFile({hFile:?}) unknown handle");
         set_last_error(machine, ERROR::INVALID_HANDLE);
         return false;
@@ -476,12 +530,16 @@ pub fn WriteFile(
     };
 
     let n = match hFile {
-        STDOUT_HFILE | STDERR_HFILE => {
+        STDOUT_HFILE => {
             machine.host.log(buf);
             buf.len()
         }
+        STDERR_HFILE => {
+            machine.host.write_err(buf);
+            buf.len()
+        }
         _ => {
-            let Some(file) = machine.state.kernel32.files.get_mut(hFile) else {
+            let Some(OpenFile { file, .. }) = machine.state.kernel32.files.get_mut(hFile) else {
                 log::debug!("WriteFile({hFile:?}) unknown handle");
                 set_last_error(machine, ERROR::INVALID_HANDLE);
                 return false;
@@ -522,6 +580,33 @@ pub fn GetConsoleMode(
     true
 }
 
+/// The process's current directory, which starts out as the host's.
+pub fn current_dir(machine: &Machine) -> Result<WindowsPathBuf, ERROR> {
+    match &machine.state.kernel32.current_dir {
+        Some(dir) => Ok(dir.clone()),
+        None => machine.host.current_dir(),
+    }
+}
+
+/// Resolve a path against the current directory and collapse any "." and ".."
+/// components, so that the host only ever sees absolute paths.
+pub fn full_path(machine: &Machine, path: &str) -> Result<WindowsPathBuf, ERROR> {
+    Ok(current_dir(machine)?.join(path).normalize())
+}
+
+/// full_path() for the APIs taking a path, which on failure log and set the last error
+/// before returning their failure value.
+fn resolve_path(machine: &mut Machine, api: &str, path: &str) -> Option<WindowsPathBuf> {
+    match full_path(machine, path) {
+        Ok(path) => Some(path),
+        Err(err) => {
+            log::debug!("{api}({path:?}) failed: {err:?}");
+            set_last_error(machine, err);
+            None
+        }
+    }
+}
+
 #[win32_derive::dllexport]
 pub fn GetFullPathNameA(
     machine: &mut Machine,
@@ -536,15 +621,9 @@ pub fn GetFullPathNameA(
         return 0;
     };
 
-    let cwd = match machine.host.current_dir() {
-        Ok(value) => value,
-        Err(err) => {
-            log::debug!("GetFullPathNameA({file_name:?}) failed: {err:?}",);
-            set_last_error(machine, err);
-            return 0;
-        }
+    let Some(out_path) = resolve_path(machine, "GetFullPathNameA", file_name) else {
+        return 0;
     };
-    let out_path = cwd.join(file_name).normalize();
     let out_bytes = out_path.as_bytes();
 
     set_last_error(machine, ERROR::SUCCESS);
@@ -592,15 +671,9 @@ pub fn GetFullPathNameW(
     };
 
     let file_name = file_name.to_string();
-    let cwd = match machine.host.current_dir() {
-        Ok(value) => value,
-        Err(err) => {
-            log::debug!("GetFullPathNameW({file_name:?}) failed: {err:?}",);
-            set_last_error(machine, err);
-            return 0;
-        }
+    let Some(out_path) = resolve_path(machine, "GetFullPathNameW", &file_name) else {
+        return 0;
     };
-    let out_path = cwd.join(&file_name).normalize();
     let out_bytes = String16::from(out_path.to_string_lossy().as_ref()).0;
 
     set_last_error(machine, ERROR::SUCCESS);
@@ -641,8 +714,10 @@ pub fn DeleteFileA(machine: &mut Machine, lpFileName: Option<&str>) -> bool {
         return false;
     };
 
-    let path = WindowsPath::new(file_name);
-    match machine.host.remove_file(path) {
+    let Some(path) = resolve_path(machine, "DeleteFileA", file_name) else {
+        return false;
+    };
+    match machine.host.remove_file(&path) {
         Ok(()) => {
             set_last_error(machine, ERROR::SUCCESS);
             true
@@ -663,8 +738,10 @@ pub fn RemoveDirectoryA(machine: &mut Machine, lpPathName: Option<&str>) -> bool
         return false;
     };
 
-    let path = WindowsPath::new(path_name);
-    match machine.host.remove_dir(path) {
+    let Some(path) = resolve_path(machine, "RemoveDirectoryA", path_name) else {
+        return false;
+    };
+    match machine.host.remove_dir(&path) {
         Ok(()) => {
             set_last_error(machine, ERROR::SUCCESS);
             true
@@ -685,8 +762,10 @@ pub fn GetFileAttributesA(machine: &mut Machine, lpFileName: Option<&str>) -> Fi
         return FileAttribute::INVALID;
     };
 
-    let path = WindowsPath::new(file_name);
-    let stat = match machine.host.stat(path) {
+    let Some(path) = resolve_path(machine, "GetFileAttributesA", file_name) else {
+        return FileAttribute::INVALID;
+    };
+    let stat = match machine.host.stat(&path) {
         Ok(stat) => stat,
         Err(err) => {
             log::debug!("GetFileAttributesA({file_name:?}) failed: {err:?}",);
@@ -706,7 +785,7 @@ pub fn GetFileAttributesA(machine: &mut Machine, lpFileName: Option<&str>) -> Fi
 
 #[win32_derive::dllexport]
 pub fn GetCurrentDirectoryA(machine: &mut Machine, nBufferLength: u32, lpBuffer: u32) -> u32 {
-    let cwd = match machine.host.current_dir() {
+    let cwd = match current_dir(machine) {
         Ok(value) => value,
         Err(err) => {
             log::debug!("GetCurrentDirectoryA failed: {err:?}");
@@ -731,6 +810,36 @@ pub fn GetCurrentDirectoryA(machine: &mut Machine, nBufferLength: u32, lpBuffer:
     out_bytes.len() as u32
 }
 
+#[win32_derive::dllexport]
+pub fn SetCurrentDirectoryA(machine: &mut Machine, lpPathName: Option<&str>) -> bool {
+    let Some(path_name) = lpPathName else {
+        log::debug!("SetCurrentDirectoryA failed: null lpPathName");
+        set_last_error(machine, ERROR::INVALID_DATA);
+        return false;
+    };
+
+    let Some(path) = resolve_path(machine, "SetCurrentDirectoryA", path_name) else {
+        return false;
+    };
+    match machine.host.stat(&path) {
+        Ok(stat) if stat.kind == StatKind::Directory => {}
+        Ok(_) => {
+            log::debug!("SetCurrentDirectoryA({path_name:?}) failed: not a directory");
+            set_last_error(machine, ERROR::DIRECTORY);
+            return false;
+        }
+        Err(err) => {
+            log::debug!("SetCurrentDirectoryA({path_name:?}) failed: {err:?}");
+            set_last_error(machine, ERROR::PATH_NOT_FOUND);
+            return false;
+        }
+    }
+
+    machine.state.kernel32.current_dir = Some(path);
+    set_last_error(machine, ERROR::SUCCESS);
+    true
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct WIN32_FIND_DATAA {
@@ -776,9 +885,37 @@ impl From<&ReadDirEntry> for WIN32_FIND_DATAA {
 }
 unsafe impl memory::Pod for WIN32_FIND_DATAA {}
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct FindHandle {
-    pub pattern: String,
-    pub read_dir: Box<dyn ReadDir>,
+    /// The directory searched, so the search can be reopened by restore_state().
+    dir: String,
+    pattern: String,
+    /// Count of directory entries read so far.
+    position: u32,
+    #[serde(skip, default = "crate::save_state::detached")]
+    read_dir: Box<dyn ReadDir>,
+}
+
+impl FindHandle {
+    /// Read ahead to the next directory entry matching the pattern.
+    fn next_match(&mut self) -> Result<Option<ReadDirEntry>, ERROR> {
+        while let Some(entry) = self.read_dir.next()? {
+            self.position += 1;
+            if glob_match(&entry.name, &self.pattern) {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reopen the search after a restore_state(), skipping the entries already read.
+    pub fn reopen(&mut self, host: &dyn Host) -> Result<(), ERROR> {
+        self.read_dir = host.read_dir(WindowsPath::new(&self.dir))?;
+        for _ in 0..self.position {
+            self.read_dir.next()?;
+        }
+        Ok(())
+    }
 }
 
 #[win32_derive::dllexport]
@@ -793,7 +930,9 @@ pub fn FindFirstFileA(
         return HFIND::invalid();
     };
 
-    let path = WindowsPath::new(file_name);
+    let Some(path) = resolve_path(machine, "FindFirstFileA", file_name) else {
+        return HFIND::invalid();
+    };
     let parent = path.parent().unwrap_or(WindowsPath::new("."));
     let Some(pattern) = path.file_name() else {
         log::debug!("FindFirstFileA({file_name:?}) no file name");
@@ -812,7 +951,7 @@ pub fn FindFirstFileA(
         pattern = "*".to_string();
     }
 
-    let mut read_dir = match machine.host.read_dir(parent) {
+    let read_dir = match machine.host.read_dir(parent) {
         Ok(handle) => handle,
         Err(err) => {
             log::debug!("FindFirstFileA({file_name:?}) failed: {err:?}",);
@@ -820,24 +959,24 @@ pub fn FindFirstFileA(
             return HFIND::invalid();
         }
     };
+    let mut handle = FindHandle {
+        dir: parent.to_string_lossy().into_owned(),
+        pattern,
+        position: 0,
+        read_dir,
+    };
 
-    let next = loop {
-        match read_dir.next() {
-            Ok(Some(entry)) => {
-                if glob_match(&entry.name, &pattern) {
-                    break entry;
-                }
-            }
-            Ok(None) => {
-                log::debug!("FindFirstFileA({file_name:?}) not found");
-                set_last_error(machine, ERROR::FILE_NOT_FOUND);
-                return HFIND::invalid();
-            }
-            Err(err) => {
-                log::debug!("FindFirstFileA({file_name:?}) failed: {err:?}",);
-                set_last_error(machine, err);
-                return HFIND::invalid();
-            }
+    let next = match handle.next_match() {
+        Ok(Some(entry)) => entry,
+        Ok(None) => {
+            log::debug!("FindFirstFileA({file_name:?}) not found");
+            set_last_error(machine, ERROR::FILE_NOT_FOUND);
+            return HFIND::invalid();
+        }
+        Err(err) => {
+            log::debug!("FindFirstFileA({file_name:?}) failed: {err:?}",);
+            set_last_error(machine, err);
+            return HFIND::invalid();
         }
     };
 
@@ -846,11 +985,7 @@ pub fn FindFirstFileA(
     }
 
     set_last_error(machine, ERROR::SUCCESS);
-    machine
-        .state
-        .kernel32
-        .find_handles
-        .add(FindHandle { pattern, read_dir })
+    machine.state.kernel32.find_handles.add(handle)
 }
 
 #[win32_derive::dllexport]
@@ -868,23 +1003,17 @@ pub fn FindNextFileA(
         }
     };
 
-    let next = loop {
-        match handle.read_dir.next() {
-            Ok(Some(entry)) => {
-                if glob_match(&entry.name, &handle.pattern) {
-                    break entry;
-                }
-            }
-            Ok(None) => {
-                set_last_error(machine, ERROR::FILE_NOT_FOUND);
-                return false;
-            }
-            Err(err) => {
-                log::debug!("FindNextFileA({hFindFile:?}) failed: {err:?}",);
-                set_last_error(machine, err);
-                return false;
-            }
-        };
+    let next = match handle.next_match() {
+        Ok(Some(entry)) => entry,
+        Ok(None) => {
+            set_last_error(machine, ERROR::NO_MORE_FILES);
+            return false;
+        }
+        Err(err) => {
+            log::debug!("FindNextFileA({hFindFile:?}) failed: {err:?}",);
+            set_last_error(machine, err);
+            return false;
+        }
     };
 
     if let Some(data) = lpFindFileData {
@@ -916,7 +1045,7 @@ pub fn FindClose(machine: &mut Machine, hFindFile: HFIND) -> bool {
 #[win32_derive::dllexport]
 pub fn GetFileSize(machine: &mut Machine, hFile: HFILE, lpFileSizeHigh: Option<&mut u32>) -> u32 {
     let file = match machine.state.kernel32.files.get(hFile) {
-        Some(f) => f,
+        Some(f) => &f.file,
         None => {
             log::debug!("GetFileSize({hFile:?}) unknown handle");
             set_last_error(machine, ERROR::INVALID_HANDLE);
@@ -953,7 +1082,7 @@ pub fn GetFileTime(
     lpLastWriteTime: Option<&mut FILETIME>,
 ) -> bool {
     let file = match machine.state.kernel32.files.get(hFile) {
-        Some(f) => f,
+        Some(f) => &f.file,
         None => {
             log::debug!("GetFileTime({hFile:?}) unknown handle");
             set_last_error(machine, ERROR::INVALID_HANDLE);
@@ -987,7 +1116,7 @@ pub fn GetFileTime(
 #[win32_derive::dllexport]
 pub fn SetEndOfFile(machine: &mut Machine, hFile: HFILE) -> bool {
     let file = match machine.state.kernel32.files.get_mut(hFile) {
-        Some(f) => f,
+        Some(f) => &mut f.file,
         None => {
             log::debug!("SetEndOfFile({hFile:?}) unknown handle");
             set_last_error(machine, ERROR::INVALID_HANDLE);
@@ -1028,8 +1157,10 @@ pub fn CreateDirectoryA(
         return false;
     };
 
-    let path = WindowsPath::new(path_name);
-    match machine.host.create_dir(path) {
+    let Some(path) = resolve_path(machine, "CreateDirectoryA", path_name) else {
+        return false;
+    };
+    match machine.host.create_dir(&path) {
         Ok(()) => {
             set_last_error(machine, ERROR::SUCCESS);
             true
@@ -1069,7 +1200,7 @@ pub fn SetFileTime(
     lpLastWriteTime: Option<&FILETIME>,
 ) -> bool {
     let file = match machine.state.kernel32.files.get_mut(hFile) {
-        Some(f) => f,
+        Some(f) => &mut f.file,
         None => {
             log::debug!("SetFileTime({hFile:?}) unknown handle");
             set_last_error(machine, ERROR::INVALID_HANDLE);
@@ -1104,42 +1235,46 @@ pub fn SetFileTime(
 /// Matches a string against a glob pattern with `*` and `?` wildcards.
 /// The pattern is case-insensitive. Used by `FindFirstFileA` and `FindNextFileA`.
 fn glob_match(input: &str, pattern: &str) -> bool {
-    let mut input = input.chars();
-    let mut pattern = pattern.chars();
-    loop {
-        match (input.next(), pattern.next()) {
-            (Some(_), Some('*')) => {
-                if let Some(p) = pattern.next() {
-                    while let Some(c) = input.next() {
-                        if c.eq_ignore_ascii_case(&p) {
-                            break;
-                        }
-                    }
-                } else {
-                    return true;
-                }
-            }
-            (Some(c), Some('?')) => {
-                if c == '\\' {
-                    return false;
-                }
-            }
-            (Some(c), Some(p)) => {
-                if !c.eq_ignore_ascii_case(&p) {
-                    return false;
-                }
+    // DOS heritage: "*.*" matches everything, including names without an extension.
+    if pattern == "*.*" {
+        return true;
+    }
+    let input = input.chars().collect::<Vec<_>>();
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let (mut i, mut p) = (0, 0);
+    // Position of the last `*` seen, and the input position it's currently matched up to,
+    // to backtrack to when the rest of the pattern fails.
+    let mut star: Option<(usize, usize)> = None;
+    while i < input.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, i));
+                p += 1;
+                continue;
             }
-            (None, Some('*')) => {
-                return pattern.all(|c| c == '*');
+            Some('?') => {
+                i += 1;
+                p += 1;
+                continue;
             }
-            (None, None) => {
-                return true;
+            Some(c) if c.eq_ignore_ascii_case(&input[i]) => {
+                i += 1;
+                p += 1;
+                continue;
             }
-            _ => {
-                return false;
+            _ => {}
+        }
+        match star {
+            Some((star_p, star_i)) => {
+                // Let the `*` swallow one more character and retry.
+                star = Some((star_p, star_i + 1));
+                p = star_p + 1;
+                i = star_i + 1;
             }
+            None => return false,
         }
     }
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
 #[cfg(test)]
@@ -1157,6 +1292,84 @@ mod tests {
         assert!(!glob_match("foo.txt", "foo.??"));
         assert!(!glob_match("foo.txt", "foo"));
         assert!(glob_match("FOO.txt", "foo.txt"));
+        assert!(glob_match("a.b.txt", "*.txt"));
+        assert!(glob_match("abcabd", "*abd"));
+        assert!(glob_match("noext", "*.*"));
+        assert!(!glob_match("foo.txt", "*.bmp"));
+    }
+
+    /// A machine whose filesystem holds C:\games\doom\doom.wad.
+    #[cfg(feature = "x86-emu")]
+    fn machine() -> Machine {
+        let host = crate::testing::TestHost::default();
+        host.files
+            .borrow_mut()
+            .insert(r"C:\games\doom\doom.wad".into(), Default::default());
+        Machine::new(Box::new(host), "test.exe".into())
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_full_path() {
+        let mut machine = machine();
+        let full = |machine: &Machine, path: &str| {
+            full_path(machine, path)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        };
+        // The current directory starts out as the host's.
+        assert_eq!(current_dir(&machine).unwrap().to_string_lossy(), r"C:\");
+        assert_eq!(full(&machine, "a.txt"), r"C:\a.txt");
+
+        machine.state.kernel32.current_dir = Some(r"C:\games\doom".into());
+        assert_eq!(full(&machine, "doom.wad"), r"C:\games\doom\doom.wad");
+        assert_eq!(
+            full(&machine, r"..\quake\.\pak0.pak"),
+            r"C:\games\quake\pak0.pak"
+        );
+        assert_eq!(full(&machine, r"D:\x.txt"), r"D:\x.txt");
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_set_current_directory() {
+        let mut machine = machine();
+        let cwd = |machine: &Machine| current_dir(machine).unwrap().to_string_lossy().into_owned();
+        let last_error = |machine: &mut Machine| crate::winapi::kernel32::GetLastError(machine);
+
+        // Relative paths resolve against the previous current directory.
+        assert!(SetCurrentDirectoryA(&mut machine, Some("games")));
+        assert!(SetCurrentDirectoryA(&mut machine, Some("doom")));
+        assert_eq!(cwd(&machine), r"C:\games\doom");
+
+        // Failures leave it unchanged.
+        assert!(!SetCurrentDirectoryA(&mut machine, Some("doom.wad")));
+        assert_eq!(last_error(&mut machine), ERROR::DIRECTORY as u32);
+        assert!(!SetCurrentDirectoryA(&mut machine, Some("missing")));
+        assert_eq!(last_error(&mut machine), ERROR::PATH_NOT_FOUND as u32);
+        assert!(!SetCurrentDirectoryA(&mut machine, None));
+        assert_eq!(cwd(&machine), r"C:\games\doom");
+
+        assert!(SetCurrentDirectoryA(&mut machine, Some("..")));
+        assert_eq!(cwd(&machine), r"C:\games");
+        let buf = crate::testing::alloc_data(&mut machine, &[0xFF; 16]);
+        assert_eq!(GetCurrentDirectoryA(&mut machine, 4, buf), 9);
+        assert_eq!(GetCurrentDirectoryA(&mut machine, 16, buf), 8);
+        assert_eq!(
+            memory::Extensions::sub32(machine.mem(), buf, 9),
+            b"C:\\games\0"
+        );
+
+        // Other APIs taking paths resolve them against it too.
+        assert_eq!(
+            GetFileAttributesA(&mut machine, Some(r"doom\doom.wad")),
+            FileAttribute::NORMAL
+        );
+        assert_eq!(
+            GetFileAttributesA(&mut machine, Some("doom.wad")),
+            FileAttribute::INVALID
+        );
     }
 }
 
@@ -0,0 +1,287 @@
+//! File mappings: CreateFileMapping/MapViewOfFile.
+//!
+//! Emulated memory is one flat buffer, so two views can't alias the same pages.
+//! Instead each view is a private copy of the mapping's contents, which are written
+//! back into the mapping object when a writable view is unmapped.  Changes are never
+//! written back to the underlying file.
+
+use super::{set_last_error, OpenFile, PAGE};
+use crate::{
+    machine::Machine,
+    pe::ImageSectionFlags,
+    winapi::{types::*, ERROR},
+};
+use memory::{Extensions, ExtensionsMut};
+use std::io::{Read, Seek, SeekFrom};
+
+const TRACE_CONTEXT: &'static str = "kernel32/file_mapping";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct HFILEMAPPINGT;
+pub type HFILEMAPPING = HANDLE<HFILEMAPPINGT>;
+
+/// First file mapping handle, kept apart from the other kinds of kernel handles.
+pub const FILE_MAPPING_HANDLE_BASE: u32 = 0x3000;
+
+/// Views must start at a multiple of the allocation granularity.
+const ALLOCATION_GRANULARITY: u32 = 0x1_0000;
+
+const FILE_MAP_COPY: u32 = 0x1;
+const FILE_MAP_WRITE: u32 = 0x2;
+const FILE_MAP_EXECUTE: u32 = 0x20;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct FileMapping {
+    /// Contents of the mapping, read from the file when the mapping was created.
+    data: Vec<u8>,
+    /// Whether the mapping's protection allows writable views.
+    writable: bool,
+}
+
+/// A view created by MapViewOfFile, keyed by its address.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct MappedView {
+    mapping: HFILEMAPPING,
+    offset: u32,
+    size: u32,
+    /// Whether changes are written back to the mapping on unmap (i.e. not FILE_MAP_COPY).
+    shared_write: bool,
+}
+
+#[win32_derive::dllexport]
+pub fn CreateFileMappingA(
+    machine: &mut Machine,
+    hFile: HFILE,
+    lpFileMappingAttributes: u32,
+    flProtect: Result<PAGE, u32>,
+    dwMaximumSizeHigh: u32,
+    dwMaximumSizeLow: u32,
+    lpName: Option<&str>,
+) -> HFILEMAPPING {
+    if let Some(name) = lpName {
+        log::warn!("CreateFileMappingA({name:?}): named mappings not supported, creating unnamed");
+    }
+    let Ok(protect) = flProtect else {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return HFILEMAPPING::null();
+    };
+    if dwMaximumSizeHigh != 0 {
+        set_last_error(machine, ERROR::NOT_ENOUGH_MEMORY);
+        return HFILEMAPPING::null();
+    }
+
+    let data = if hFile.is_invalid() {
+        // Backed by the pagefile, i.e. plain shared memory.
+        if dwMaximumSizeLow == 0 {
+            set_last_error(machine, ERROR::INVALID_PARAMETER);
+            return HFILEMAPPING::null();
+        }
+        vec![0; dwMaximumSizeLow as usize]
+    } else {
+        let Some(OpenFile { file, .. }) = machine.state.kernel32.files.get_mut(hFile) else {
+            set_last_error(machine, ERROR::INVALID_HANDLE);
+            return HFILEMAPPING::null();
+        };
+        let mut data = Vec::new();
+        let read = file
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| file.read_to_end(&mut data));
+        if let Err(err) = read {
+            log::debug!("CreateFileMappingA({hFile:?}) failed: {err:?}");
+            set_last_error(machine, ERROR::from(err));
+            return HFILEMAPPING::null();
+        }
+        if dwMaximumSizeLow != 0 {
+            // A mapping larger than the file extends it with zeros.
+            data.resize(dwMaximumSizeLow as usize, 0);
+        }
+        if data.is_empty() {
+            set_last_error(machine, ERROR::FILE_INVALID);
+            return HFILEMAPPING::null();
+        }
+        data
+    };
+
+    let writable = protect.to_flags().contains(ImageSectionFlags::MEM_WRITE);
+    set_last_error(machine, ERROR::SUCCESS);
+    machine
+        .state
+        .kernel32
+        .file_mappings
+        .add(FileMapping { data, writable })
+}
+
+#[win32_derive::dllexport]
+pub fn MapViewOfFile(
+    machine: &mut Machine,
+    hFileMappingObject: HFILEMAPPING,
+    dwDesiredAccess: u32,
+    dwFileOffsetHigh: u32,
+    dwFileOffsetLow: u32,
+    dwNumberOfBytesToMap: u32,
+) -> u32 {
+    let Some(mapping) = machine.state.kernel32.file_mappings.get(hFileMappingObject) else {
+        set_last_error(machine, ERROR::INVALID_HANDLE);
+        return 0;
+    };
+    if dwFileOffsetHigh != 0 || dwFileOffsetLow % ALLOCATION_GRANULARITY != 0 {
+        set_last_error(machine, ERROR::MAPPED_ALIGNMENT);
+        return 0;
+    }
+    let offset = dwFileOffsetLow;
+    let total = mapping.data.len() as u32;
+    let size = if dwNumberOfBytesToMap == 0 {
+        total.saturating_sub(offset)
+    } else {
+        dwNumberOfBytesToMap
+    };
+    let wants_write = dwDesiredAccess & (FILE_MAP_WRITE | FILE_MAP_COPY) != 0;
+    let in_bounds = offset.checked_add(size).is_some_and(|end| end <= total);
+    if size == 0 || !in_bounds || (wants_write && !mapping.writable) {
+        set_last_error(machine, ERROR::ACCESS_DENIED);
+        return 0;
+    }
+
+    let mut flags = ImageSectionFlags::MEM_READ;
+    if wants_write {
+        flags |= ImageSectionFlags::MEM_WRITE;
+    }
+    if dwDesiredAccess & FILE_MAP_EXECUTE != 0 {
+        flags |= ImageSectionFlags::MEM_EXECUTE;
+    }
+    let kernel32 = &mut machine.state.kernel32;
+    let view = kernel32.mappings.alloc(
+        size,
+        format!("file mapping {:x} view", hFileMappingObject.raw),
+        &mut machine.emu.memory,
+    );
+    view.flags = flags;
+    let addr = view.addr;
+
+    let mapping = kernel32.file_mappings.get(hFileMappingObject).unwrap();
+    let data = &mapping.data[offset as usize..][..size as usize];
+    machine
+        .emu
+        .memory
+        .mem()
+        .sub32_mut(addr, size)
+        .copy_from_slice(data);
+    kernel32.mapped_views.insert(
+        addr,
+        MappedView {
+            mapping: hFileMappingObject,
+            offset,
+            size,
+            shared_write: dwDesiredAccess & FILE_MAP_WRITE != 0,
+        },
+    );
+    set_last_error(machine, ERROR::SUCCESS);
+    addr
+}
+
+#[win32_derive::dllexport]
+pub fn UnmapViewOfFile(machine: &mut Machine, lpBaseAddress: u32) -> bool {
+    let kernel32 = &mut machine.state.kernel32;
+    let Some(view) = kernel32.mapped_views.remove(&lpBaseAddress) else {
+        set_last_error(machine, ERROR::INVALID_ADDRESS);
+        return false;
+    };
+    if view.shared_write {
+        // The mapping may already be closed, in which case nothing can see the changes.
+        if let Some(mapping) = kernel32.file_mappings.get_mut(view.mapping) {
+            let contents = machine.emu.memory.mem().sub32(lpBaseAddress, view.size);
+            mapping.data[view.offset as usize..][..view.size as usize].copy_from_slice(contents);
+        }
+    }
+    let mem = machine.emu.memory.mem();
+    kernel32.mappings.release(lpBaseAddress, mem);
+    set_last_error(machine, ERROR::SUCCESS);
+    true
+}
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use super::*;
+    use crate::winapi::kernel32::GetLastError;
+
+    const FILE_MAP_READ: u32 = 0x4;
+
+    fn create(machine: &mut Machine, protect: PAGE, size: u32) -> HFILEMAPPING {
+        CreateFileMappingA(machine, HFILE::invalid(), 0, Ok(protect), 0, size, None)
+    }
+
+    #[test]
+    fn views_share_writes() {
+        let mut machine = crate::testing::machine();
+        let hmap = create(&mut machine, PAGE::READWRITE, 0x2000);
+        assert!(!hmap.is_null());
+
+        let view = MapViewOfFile(&mut machine, hmap, FILE_MAP_WRITE, 0, 0, 0);
+        assert_ne!(view, 0);
+        machine.mem().put_pod::<u32>(view + 0x1000, 0x1234_5678);
+        // Changes to a copy-on-write view stay private.
+        let copy = MapViewOfFile(&mut machine, hmap, FILE_MAP_COPY, 0, 0, 0x10);
+        assert_ne!(copy, 0);
+        machine.mem().put_pod::<u32>(copy, 0xdead);
+        assert!(UnmapViewOfFile(&mut machine, copy));
+        assert!(UnmapViewOfFile(&mut machine, view));
+        assert!(!UnmapViewOfFile(&mut machine, view));
+        assert_eq!(GetLastError(&mut machine), ERROR::INVALID_ADDRESS as u32);
+
+        let view = MapViewOfFile(&mut machine, hmap, FILE_MAP_READ, 0, 0, 0);
+        assert_eq!(machine.mem().get_pod::<u32>(view), 0);
+        assert_eq!(machine.mem().get_pod::<u32>(view + 0x1000), 0x1234_5678);
+        assert!(!machine
+            .state
+            .kernel32
+            .mappings
+            .allows(view, 4, ImageSectionFlags::MEM_WRITE));
+    }
+
+    #[test]
+    fn bad_views() {
+        let mut machine = crate::testing::machine();
+        let hmap = create(&mut machine, PAGE::READONLY, 0x2_0000);
+
+        let view = MapViewOfFile(&mut machine, hmap, FILE_MAP_READ, 0, 0x1000, 0);
+        assert_eq!(view, 0);
+        assert_eq!(GetLastError(&mut machine), ERROR::MAPPED_ALIGNMENT as u32);
+
+        // Past the end, including by overflowing.
+        for (offset, size) in [(0x1_0000, 0x1_0001), (0x1_0000, 0xFFFF_0000)] {
+            let view = MapViewOfFile(&mut machine, hmap, FILE_MAP_READ, 0, offset, size);
+            assert_eq!(view, 0);
+            assert_eq!(GetLastError(&mut machine), ERROR::ACCESS_DENIED as u32);
+        }
+
+        // A writable view of a read-only mapping.
+        assert_eq!(
+            MapViewOfFile(&mut machine, hmap, FILE_MAP_WRITE, 0, 0, 0),
+            0
+        );
+        assert_eq!(GetLastError(&mut machine), ERROR::ACCESS_DENIED as u32);
+
+        let view = MapViewOfFile(&mut machine, HFILEMAPPING::null(), FILE_MAP_READ, 0, 0, 0);
+        assert_eq!(view, 0);
+        assert_eq!(GetLastError(&mut machine), ERROR::INVALID_HANDLE as u32);
+    }
+
+    #[test]
+    fn bad_mappings() {
+        let mut machine = crate::testing::machine();
+        // A pagefile-backed mapping needs a size.
+        assert!(create(&mut machine, PAGE::READWRITE, 0).is_null());
+        assert_eq!(GetLastError(&mut machine), ERROR::INVALID_PARAMETER as u32);
+        let hmap = CreateFileMappingA(
+            &mut machine,
+            HFILE::from_raw(0x1234),
+            0,
+            Ok(PAGE::READONLY),
+            0,
+            0,
+            None,
+        );
+        assert!(hmap.is_null());
+        assert_eq!(GetLastError(&mut machine), ERROR::INVALID_HANDLE as u32);
+    }
+}
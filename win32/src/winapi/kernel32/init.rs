@@ -1,7 +1,11 @@
 //! Process initialization and startup.
 
 use super::{
-    EventObject, FindHandle, Mappings, ResourceHandle, DLL, HMODULE, STDERR_HFILE, STDOUT_HFILE,
+    attach_dlls, current_thread, DelayImport, Env, ExitThread, FileMapping, FindHandle, MappedView,
+    Mappings, MoveableBlock, OSVersion, OpenFile, ResourceHandle, SyncObjects, Thread, Tls,
+    _EXCEPTION_REGISTRATION_RECORD, DLL, FILE_MAPPING_HANDLE_BASE, HFILEMAPPING, HMODULE, HTHREAD,
+    PLACEHOLDER_HANDLER, PROCESS_ID, STDERR_HFILE, STDOUT_HFILE, SYNC_HANDLE_BASE,
+    THREAD_HANDLE_BASE,
 };
 use crate::{
     machine::MemImpl,
@@ -12,13 +16,14 @@ use crate::{
 };
 use ::memory::Mem;
 use memory::ExtensionsMut;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 const TRACE_CONTEXT: &'static str = "kernel32/init";
 
 /// Process command line, as exposed in GetCommandLine() and also TEB.
 /// Gross: GetCommandLineA() needs to return a pointer that's never freed,
 /// so we need to hang on to both versions of the command line.
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct CommandLine {
     /// Command line, split args.
     pub args: Vec<String>,
@@ -28,6 +33,8 @@ pub struct CommandLine {
     pub cmdline16: u32,
     /// Length without trailing nul.
     pub len: usize,
+    /// The arguments after the program name, ASCII, as passed to WinMain.
+    pub tail: u32,
 }
 
 impl CommandLine {
@@ -53,6 +60,7 @@ impl CommandLine {
             cmdline: cmdline8_ptr,
             cmdline16: cmdline16_ptr,
             len,
+            tail: cmdline8_ptr + cmdline_tail(&cmdline) as u32,
         }
     }
 
@@ -65,31 +73,101 @@ impl CommandLine {
     }
 }
 
-// TODO: follow the logic for CommandLineToArgvW
-// https://learn.microsoft.com/en-us/windows/win32/api/shellapi/nf-shellapi-commandlinetoargvw
+/// Offset of the arguments following the program name, skipping the program name
+/// and the whitespace after it as the CRT does when computing WinMain's lpCmdLine.
+fn cmdline_tail(cmdline: &str) -> usize {
+    let bytes = cmdline.as_bytes();
+    let mut i = 0;
+    if bytes.first() == Some(&b'"') {
+        i = 1;
+        while i < bytes.len() && bytes[i] != b'"' {
+            i += 1;
+        }
+        i = (i + 1).min(bytes.len());
+    } else {
+        while i < bytes.len() && !matches!(bytes[i], b' ' | b'\t' | 0) {
+            i += 1;
+        }
+    }
+    while i < bytes.len() && matches!(bytes[i], b' ' | b'\t') {
+        i += 1;
+    }
+    i
+}
+
+/// Split a command line into arguments following the rules of CommandLineToArgvW.
+/// https://learn.microsoft.com/en-us/windows/win32/api/shellapi/nf-shellapi-commandlinetoargvw
 fn split_cmdline(cmdline: &str) -> Vec<String> {
     let mut args = Vec::new();
+    let mut chars = cmdline.chars().peekable();
+
+    // The program name is special: it's either quoted or runs to the first whitespace,
+    // and backslashes have no special meaning.
     let mut arg = String::new();
-    let mut in_quote = false;
-    for c in cmdline.chars() {
-        match c {
-            ' ' if !in_quote => {
-                if !arg.is_empty() {
-                    args.push(arg);
-                    arg = String::new();
-                }
-            }
-            '"' => {
-                in_quote = !in_quote;
-            }
-            _ => {
-                arg.push(c);
+    if chars.peek() == Some(&'"') {
+        chars.next();
+        arg.extend(chars.by_ref().take_while(|&c| c != '"'));
+    } else {
+        while let Some(&c) = chars.peek() {
+            if c == ' ' || c == '\t' {
+                break;
             }
+            arg.push(c);
+            chars.next();
         }
     }
     if !arg.is_empty() {
         args.push(arg);
     }
+
+    loop {
+        while matches!(chars.peek(), Some(' ' | '\t')) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut arg = String::new();
+        let mut in_quote = false;
+        while let Some(&c) = chars.peek() {
+            match c {
+                ' ' | '\t' if !in_quote => break,
+                '\\' => {
+                    let mut backslashes = 0;
+                    while chars.peek() == Some(&'\\') {
+                        backslashes += 1;
+                        chars.next();
+                    }
+                    if chars.peek() == Some(&'"') {
+                        // 2n backslashes + quote => n backslashes, quote is a delimiter;
+                        // 2n+1 backslashes + quote => n backslashes and a literal quote.
+                        arg.extend(std::iter::repeat('\\').take(backslashes / 2));
+                        if backslashes % 2 == 1 {
+                            arg.push('"');
+                            chars.next();
+                        }
+                    } else {
+                        arg.extend(std::iter::repeat('\\').take(backslashes));
+                    }
+                }
+                '"' => {
+                    chars.next();
+                    if in_quote && chars.peek() == Some(&'"') {
+                        // A doubled quote within a quoted region is a literal quote.
+                        arg.push('"');
+                        chars.next();
+                    } else {
+                        in_quote = !in_quote;
+                    }
+                }
+                _ => {
+                    arg.push(c);
+                    chars.next();
+                }
+            }
+        }
+        args.push(arg);
+    }
     args
 }
 
@@ -132,13 +210,6 @@ struct RTL_USER_PROCESS_PARAMETERS {
 }
 unsafe impl ::memory::Pod for RTL_USER_PROCESS_PARAMETERS {}
 
-#[repr(C)]
-struct _EXCEPTION_REGISTRATION_RECORD {
-    Prev: DWORD,
-    Handler: DWORD,
-}
-unsafe impl ::memory::Pod for _EXCEPTION_REGISTRATION_RECORD {}
-
 /// Set up TEB, PEB, and other process info.
 /// The FS register points at the TEB (thread info), which points at the PEB (process info).
 fn init_teb(cmdline: &CommandLine, arena: &mut Arena, mem: Mem) -> u32 {
@@ -164,7 +235,6 @@ fn init_teb(cmdline: &CommandLine, arena: &mut Arena, mem: Mem) -> u32 {
     let peb = mem.view_mut::<PEB>(peb_addr);
     peb.ProcessParameters = params_addr;
     peb.ProcessHeap = 0; // TODO: we use state.process_heap instead
-    peb.TlsCount = 0;
 
     // SEH chain
     let seh_addr = arena.alloc(
@@ -173,13 +243,14 @@ fn init_teb(cmdline: &CommandLine, arena: &mut Arena, mem: Mem) -> u32 {
     );
     let seh = mem.view_mut::<_EXCEPTION_REGISTRATION_RECORD>(seh_addr);
     seh.Prev = 0xFFFF_FFFF;
-    seh.Handler = 0xFF5E_5EFF; // Hopefully easier to spot.
+    seh.Handler = PLACEHOLDER_HANDLER;
 
     // TEB
     let teb_addr = arena.alloc(std::cmp::max(std::mem::size_of::<TEB>() as u32, 0x100), 4);
     let teb = mem.view_mut::<TEB>(teb_addr);
     teb.Tib.ExceptionList = seh_addr;
     teb.Tib._Self = teb_addr; // Confusing: it points to itself.
+    teb.ClientId_UniqueProcess = PROCESS_ID;
     teb.Peb = peb_addr;
 
     teb_addr
@@ -196,32 +267,87 @@ pub struct GDTEntries {
     pub ss: u16,
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct State {
     /// Memory for kernel32 data structures.
     arena: Arena,
     /// Address image was loaded at.
     pub image_base: u32,
-    /// Address of TEB (what FS register-relative addresses refer to).
+    /// Full Windows path of the exe, as returned by GetModuleFileName.
+    pub exe_path: String,
+    /// Address of the main thread's TEB (what FS register-relative addresses refer to).
+    /// Other threads have their own; see teb() for the current thread's.
     pub teb: u32,
     pub mappings: Mappings,
     /// Heaps created by HeapAlloc().
     heaps: HashMap<u32, Heap>,
     pub process_heap: u32,
 
-    pub dlls: HashMap<HMODULE, DLL>,
+    /// Ordered so that DllMain calls happen in a deterministic order.
+    pub dlls: BTreeMap<HMODULE, DLL>,
+    pub delay_imports: Vec<DelayImport>,
 
     pub resources: pe::IMAGE_DATA_DIRECTORY,
     pub resource_handles: Handles<HRSRC, ResourceHandle>,
 
-    pub event_handles: Handles<HEVENT, EventObject>,
+    /// Events and mutexes.
+    pub sync_objects: SyncObjects,
 
-    pub files: Handles<HFILE, Box<dyn crate::host::File>>,
+    pub files: Handles<HFILE, OpenFile>,
+    /// Current directory once set by SetCurrentDirectory; until then, the host's.
+    #[serde(with = "crate::save_state::windows_path")]
+    pub current_dir: Option<typed_path::WindowsPathBuf>,
 
     pub find_handles: Handles<HFIND, FindHandle>,
 
-    pub(super) env: u32,
+    pub file_mappings: Handles<HFILEMAPPING, FileMapping>,
+    /// MapViewOfFile views, by address.
+    pub mapped_views: HashMap<u32, MappedView>,
+
+    /// GMEM_MOVEABLE allocations, by handle.
+    pub moveable_blocks: HashMap<u32, MoveableBlock>,
+
+    pub env: Env,
+
+    pub tls: Tls,
+
+    /// Threads, including the main thread; handles double as thread ids.
+    pub threads: Handles<HTHREAD, Thread>,
 
     pub cmdline: CommandLine,
+
+    /// Top-level exception filter, per SetUnhandledExceptionFilter.
+    pub unhandled_exception_filter: u32,
+    /// For each in-progress exception dispatch, the async depth it runs at;
+    /// see seh::abandon_dispatch.
+    pub seh_dispatches: Vec<usize>,
+
+    /// Windows version reported by GetVersion and friends.
+    pub version: OSVersion,
+
+    /// The exe's IMAGE_SUBSYSTEM_*, which determines the signature of its entry point
+    /// under EntryMode::Main.
+    pub subsystem: u16,
+    /// How retrowin32_main calls the exe's entry point.
+    pub entry_mode: EntryMode,
+}
+
+pub const IMAGE_SUBSYSTEM_WINDOWS_GUI: u16 = 2;
+pub const IMAGE_SUBSYSTEM_WINDOWS_CUI: u16 = 3;
+
+/// How to call the exe's entry point.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum EntryMode {
+    /// As Windows does, with the PEB as the only argument.  The entry point is
+    /// normally CRT startup code that gathers its arguments via GetCommandLine and
+    /// GetStartupInfo before calling the program's WinMain or main.
+    #[default]
+    Windows,
+    /// The entry point is WinMain or main itself, as in programs linked without a CRT,
+    /// so pass it the arguments the CRT would have, depending on the subsystem.
+    Main,
+    /// Call the entry point with no arguments.
+    Raw,
 }
 
 impl State {
@@ -230,7 +356,7 @@ impl State {
         let mapping = mappings.alloc(0x1000, "kernel32 data".into(), mem);
         let mut arena = Arena::new(mapping.addr, mapping.size);
 
-        let mut dlls = HashMap::new();
+        let mut dlls = BTreeMap::new();
         let dll = {
             let addr = arena.alloc(retrowin32_syscall.len() as u32, 8);
             mem.mem()
@@ -240,46 +366,90 @@ impl State {
             names.insert("retrowin32_syscall".into(), addr);
             DLL {
                 name: "retrowin32.dll".into(),
+                path: "C:\\Windows\\System32\\retrowin32.dll".into(),
                 dll: pe::DLL {
                     base: 0, // unused
                     names,
                     ordinal_base: 0,         // unused
                     fns: Default::default(), // unused
+                    forwards: Default::default(),
                     resources: None,
                     entry_point: None,
                 },
+                refcount: 0,
+                attached: true,
             }
         };
         dlls.insert(HMODULE::from_raw(dll.dll.base), dll);
 
-        let env = b"WINDIR=C:\\Windows\0\0";
-        let env_addr = arena.alloc(env.len() as u32, 1);
-        mem.mem()
-            .sub32_mut(env_addr, env.len() as u32)
-            .copy_from_slice(env);
-
         let cmdline = CommandLine::new(cmdline, &mut arena, mem.mem());
 
         let teb = init_teb(&cmdline, &mut arena, mem.mem());
+        let mut threads = Handles::new(THREAD_HANDLE_BASE);
+        let main_thread = threads.reserve();
+        threads.set(main_thread, Thread::new(main_thread, 0, teb));
+        mem.mem().view_mut::<TEB>(teb).ClientId_UniqueThread = main_thread.to_raw();
 
         State {
             arena,
             image_base: 0,
+            exe_path: String::new(),
             teb,
             process_heap: 0,
             mappings,
             heaps: HashMap::new(),
             dlls,
-            event_handles: Default::default(),
+            delay_imports: Default::default(),
+            sync_objects: Handles::new(SYNC_HANDLE_BASE),
             files: Default::default(),
+            current_dir: None,
             find_handles: Default::default(),
-            env: env_addr,
+            file_mappings: Handles::new(FILE_MAPPING_HANDLE_BASE),
+            mapped_views: Default::default(),
+            moveable_blocks: Default::default(),
+            env: Default::default(),
+            tls: Default::default(),
+            threads,
             cmdline,
             resources: Default::default(),
             resource_handles: Default::default(),
+            unhandled_exception_filter: 0,
+            seh_dispatches: Default::default(),
+            version: Default::default(),
+            subsystem: IMAGE_SUBSYSTEM_WINDOWS_GUI,
+            entry_mode: Default::default(),
         }
     }
 
+    /// Set up the TEB of a new thread, sharing the process-wide parts of the main thread's.
+    // TODO: static TLS (ThreadLocalStoragePointer) is shared rather than copied per thread.
+    pub fn new_teb(
+        &mut self,
+        mem: &mut MemImpl,
+        id: u32,
+        stack_base: u32,
+        stack_limit: u32,
+    ) -> u32 {
+        let addr = self
+            .mappings
+            .alloc(0x1000, format!("thread {id:x} teb"), mem)
+            .addr;
+        let mem = mem.mem();
+        let main = mem.view::<TEB>(self.teb);
+        let (peb, tls) = (main.Peb, main.ThreadLocalStoragePointer);
+        let teb = mem.view_mut::<TEB>(addr);
+        // The main thread's chain points at frames on its own stack, so start out empty.
+        teb.Tib.ExceptionList = 0xFFFF_FFFF;
+        teb.Tib.StackBase = stack_base;
+        teb.Tib.StackLimit = stack_limit;
+        teb.Tib._Self = addr;
+        teb.ClientId_UniqueProcess = PROCESS_ID;
+        teb.ClientId_UniqueThread = id;
+        teb.Peb = peb;
+        teb.ThreadLocalStoragePointer = tls;
+        addr
+    }
+
     pub fn new_private_heap(&mut self, mem: &mut MemImpl, size: usize, desc: String) -> Heap {
         let mapping = self.mappings.alloc(size as u32, desc, mem);
         Heap::new(mapping.addr, mapping.size)
@@ -292,10 +462,15 @@ impl State {
         addr
     }
 
+    /// Capture the mappings and heaps, which describe how emulated memory is laid out.
     pub fn get_heap<'a>(&'a mut self, addr: u32) -> Option<&mut Heap> {
         self.heaps.get_mut(&addr)
     }
 
+    pub fn destroy_heap(&mut self, addr: u32) -> Option<Heap> {
+        self.heaps.remove(&addr)
+    }
+
     pub fn get_process_heap<'a>(&'a mut self, memory: &mut MemImpl) -> &mut Heap {
         if self.process_heap == 0 {
             let size = 24 << 20;
@@ -385,11 +560,13 @@ impl State {
     }
 }
 
+/// The current thread's TEB.
 pub fn teb(machine: &Machine) -> &TEB {
-    machine.mem().view::<TEB>(machine.state.kernel32.teb)
+    machine.mem().view::<TEB>(current_thread(machine).teb)
 }
 pub fn teb_mut(machine: &mut Machine) -> &mut TEB {
-    machine.mem().view_mut::<TEB>(machine.state.kernel32.teb)
+    let addr = current_thread(machine).teb;
+    machine.mem().view_mut::<TEB>(addr)
 }
 pub fn peb_mut(machine: &mut Machine) -> &mut PEB {
     let peb_addr = teb(machine).Peb;
@@ -410,16 +587,12 @@ pub struct PEB {
     pub SubSystemData: DWORD,
     pub ProcessHeap: DWORD,
     // TODO: more fields
-
-    // This is at the wrong offset, but it shouldn't matter.
-    // TODO: this should be TlsBitmap.
-    pub TlsCount: DWORD,
 }
 unsafe impl ::memory::Pod for PEB {}
 
 #[repr(C)]
 pub struct NT_TIB {
-    ExceptionList: DWORD,
+    pub ExceptionList: DWORD,
     StackBase: DWORD,
     StackLimit: DWORD,
     SubSystemTib: DWORD,
@@ -447,9 +620,6 @@ pub struct TEB {
     pub WOW32Reserved: DWORD,
     pub CurrentLocale: DWORD,
     // TODO: ... there are many more fields here
-
-    // This is at the wrong offset, but it shouldn't matter.
-    pub TlsSlots: [DWORD; 64],
 }
 unsafe impl ::memory::Pod for TEB {}
 
@@ -468,41 +638,250 @@ pub fn GetCommandLineW(machine: &mut Machine) -> u32 {
 /// It probably has some better name within ntdll.dll.
 #[win32_derive::dllexport]
 pub async fn retrowin32_main(machine: &mut Machine, entry_point: u32) {
-    struct DllData {
-        base: u32,
-        entry_point: u32,
-    }
+    attach_dlls(machine).await;
 
-    let dlls = machine
-        .state
-        .kernel32
-        .dlls
-        .iter()
-        .filter(|(_, dll)| dll.dll.entry_point.is_some())
-        .map(|(_, dll)| DllData {
-            base: dll.dll.base,
-            entry_point: dll.dll.entry_point.unwrap(),
-        })
-        .collect::<Vec<_>>();
-    // TODO: invoking dllmains can load more dlls.
-    for dll in dlls {
-        let hInstance = dll.base;
+    let image_base = machine.state.kernel32.image_base;
+    for callback in machine.state.kernel32.tls.callbacks.clone() {
         let fdwReason = 1u32; // DLL_PROCESS_ATTACH
-        let lpvReserved = 0u32;
         machine
-            .call_x86(dll.entry_point, vec![hInstance, fdwReason, lpvReserved])
+            .call_x86(callback, vec![image_base, fdwReason, 0])
             .await;
     }
 
-    machine.call_x86(entry_point, vec![]).await;
+    let exit_code = call_entry_point(machine, entry_point).await;
     // TODO: if the entry point returns, the Windows behavior is to wait for any
     // spawned threads before exiting.
-    machine.exit(0);
+    machine.exit(exit_code);
+}
+
+/// Copy strings into the process heap as a null-terminated array of pointers to
+/// nul-terminated strings, like argv, returning the array's address.
+#[cfg(feature = "x86-emu")]
+fn alloc_string_array(machine: &mut Machine, strs: &[&str]) -> u32 {
+    let heap = machine
+        .state
+        .kernel32
+        .get_process_heap(&mut machine.emu.memory);
+    let mem = machine.emu.memory.mem();
+    let array = heap.alloc(mem, (strs.len() as u32 + 1) * 4);
+    for (i, str) in strs.iter().enumerate() {
+        let addr = heap.alloc(mem, str.len() as u32 + 1);
+        let buf = mem.sub32_mut(addr, str.len() as u32 + 1);
+        buf[..str.len()].copy_from_slice(str.as_bytes());
+        buf[str.len()] = 0;
+        mem.put_pod::<u32>(array + i as u32 * 4, addr);
+    }
+    mem.put_pod::<u32>(array + strs.len() as u32 * 4, 0);
+    array
+}
+
+/// Call the exe's entry point per State::entry_mode, returning its exit code.
+#[cfg(feature = "x86-emu")]
+async fn call_entry_point(machine: &mut Machine, entry_point: u32) -> u32 {
+    let kernel32 = &machine.state.kernel32;
+    let (args, cdecl) = match (kernel32.entry_mode, kernel32.subsystem) {
+        (EntryMode::Raw, _) => (vec![], false),
+        // The CRT entry point is declared as taking no arguments, so it returns
+        // without popping the PEB.
+        (EntryMode::Windows, _) => (vec![teb(machine).Peb], true),
+        (EntryMode::Main, IMAGE_SUBSYSTEM_WINDOWS_CUI) => {
+            let args = kernel32.cmdline.args.clone();
+            let env = kernel32.env.block();
+            let args = args.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+            let env = env
+                .split('\0')
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>();
+            let argv = alloc_string_array(machine, &args);
+            let envp = alloc_string_array(machine, &env);
+            (vec![args.len() as u32, argv, envp], true)
+        }
+        (EntryMode::Main, subsystem) => {
+            if subsystem != IMAGE_SUBSYSTEM_WINDOWS_GUI {
+                log::warn!("subsystem {subsystem}, calling entry point as WinMain");
+            }
+            let args = vec![
+                kernel32.image_base,
+                0, // hPrevInstance
+                kernel32.cmdline.tail,
+                machine.host.initial_show_state() as u32,
+            ];
+            (args, false)
+        }
+    };
+
+    if !cdecl {
+        return machine.call_x86(entry_point, args).await;
+    }
+    let mem = machine.emu.memory.mem();
+    machine
+        .emu
+        .x86
+        .cpu_mut()
+        .call_x86_cdecl(mem, entry_point, args)
+        .await
+}
+
+#[cfg(not(feature = "x86-emu"))]
+async fn call_entry_point(machine: &mut Machine, entry_point: u32) -> u32 {
+    if machine.state.kernel32.entry_mode != EntryMode::Raw {
+        log::warn!("entry point arguments only implemented for x86-emu");
+    }
+    machine.call_x86(entry_point, vec![]).await
 }
 
 #[win32_derive::dllexport]
 pub async fn retrowin32_thread_main(machine: &mut Machine, entry_point: u32, param: u32) {
-    machine.call_x86(entry_point, vec![param]).await;
-    log::warn!("TODO: thread exiting, but we don't have a way to stop a single thread yet");
-    machine.exit(0);
+    // Returning from the thread function is equivalent to calling ExitThread.
+    let exit_code = machine.call_x86(entry_point, vec![param]).await;
+    ExitThread(machine, exit_code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cmdline_tail, split_cmdline};
+
+    #[test]
+    fn test_cmdline_tail() {
+        assert_eq!(cmdline_tail("foo.exe"), 7);
+        assert_eq!(cmdline_tail("foo.exe  a b"), 9);
+        assert_eq!(cmdline_tail(r#""c:\some dir\foo.exe" a"#), 22);
+    }
+
+    #[test]
+    fn test_split_cmdline() {
+        assert_eq!(split_cmdline("foo.exe"), vec!["foo.exe"]);
+        assert_eq!(
+            split_cmdline(r#""c:\some dir\foo.exe"  a   b"#),
+            vec![r"c:\some dir\foo.exe", "a", "b"]
+        );
+        assert_eq!(
+            split_cmdline(r#"foo.exe "a b c" d e"#),
+            vec!["foo.exe", "a b c", "d", "e"]
+        );
+        assert_eq!(
+            split_cmdline(r#"foo.exe "ab\"c" "\\" d"#),
+            vec!["foo.exe", r#"ab"c"#, r"\", "d"]
+        );
+        assert_eq!(
+            split_cmdline(r#"foo.exe a\\\b d"e f"g h"#),
+            vec!["foo.exe", r"a\\\b", "de fg", "h"]
+        );
+        assert_eq!(
+            split_cmdline(r#"foo.exe a\\\"b c d"#),
+            vec!["foo.exe", r#"a\"b"#, "c", "d"]
+        );
+        assert_eq!(
+            split_cmdline(r#"foo.exe a\\\\"b c" d e"#),
+            vec!["foo.exe", r"a\\b c", "d", "e"]
+        );
+        assert_eq!(split_cmdline(r#"foo.exe "" x"#), vec!["foo.exe", "", "x"]);
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn tls_callbacks_before_entry_point() {
+        let mut machine = crate::testing::machine();
+        let flag = crate::testing::alloc_data(&mut machine, &[0; 4]);
+        let flag_bytes = flag.to_le_bytes();
+
+        // mov dword [flag], 7; ret 0xc
+        let mut callback = vec![0xc7, 0x05];
+        callback.extend_from_slice(&flag_bytes);
+        callback.extend_from_slice(&[7, 0, 0, 0, 0xc2, 0x0c, 0x00]);
+        let callback = crate::testing::alloc_code(&mut machine, &callback);
+        machine.state.kernel32.tls.callbacks = vec![callback];
+
+        // mov eax, [flag]; ret
+        let mut entry = vec![0xa1];
+        entry.extend_from_slice(&flag_bytes);
+        entry.push(0xc3);
+        let entry = crate::testing::alloc_code(&mut machine, &entry);
+
+        assert_eq!(crate::testing::run_exe(&mut machine, entry), 7);
+    }
+
+    /// Run an entry point that saves its first four stack arguments and returns 3,
+    /// popping the arguments if stdcall.  Returns the exit code and the arguments.
+    #[cfg(feature = "x86-emu")]
+    fn entry_args(machine: &mut crate::Machine, stdcall: bool) -> (u32, Vec<u32>) {
+        use memory::Extensions;
+        let out = crate::testing::alloc_data(machine, &[0; 16]);
+        let mut entry = vec![];
+        for i in 0..4 {
+            // mov eax, [esp+4+4*i]; mov [out+4*i], eax
+            entry.extend_from_slice(&[0x8b, 0x44, 0x24, 4 + 4 * i as u8, 0xa3]);
+            entry.extend_from_slice(&(out + 4 * i).to_le_bytes());
+        }
+        entry.extend_from_slice(&[0xb8, 3, 0, 0, 0]); // mov eax, 3
+        if stdcall {
+            entry.extend_from_slice(&[0xc2, 0x10, 0x00]); // ret 0x10
+        } else {
+            entry.push(0xc3); // ret
+        }
+        let entry = crate::testing::alloc_code(machine, &entry);
+        let exit_code = crate::testing::run_exe(machine, entry);
+        let mem = machine.mem();
+        (
+            exit_code,
+            (0..4).map(|i| mem.get_pod::<u32>(out + 4 * i)).collect(),
+        )
+    }
+
+    #[cfg(feature = "x86-emu")]
+    fn main_machine(subsystem: u16) -> crate::Machine {
+        let mut machine = crate::Machine::new(
+            Box::new(crate::testing::TestHost::default()),
+            r#"test.exe a "b c""#.into(),
+        );
+        machine.state.kernel32.entry_mode = super::EntryMode::Main;
+        machine.state.kernel32.subsystem = subsystem;
+        machine
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn entry_point_gets_peb() {
+        let mut machine = crate::testing::machine();
+        let (exit_code, args) = entry_args(&mut machine, false);
+        assert_eq!(exit_code, 3);
+        assert_eq!(args[0], super::teb(&machine).Peb);
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn entry_point_winmain() {
+        use memory::Extensions;
+        let mut machine = main_machine(super::IMAGE_SUBSYSTEM_WINDOWS_GUI);
+        machine.state.kernel32.image_base = 0x40_0000;
+        let (exit_code, args) = entry_args(&mut machine, true);
+        assert_eq!(exit_code, 3);
+        assert_eq!(args[..2], [0x40_0000, 0]);
+        assert_eq!(machine.mem().slicez(args[2]), br#"a "b c""#);
+        assert_eq!(args[3], 1); // SW_SHOWNORMAL
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn entry_point_main() {
+        use memory::Extensions;
+        let mut machine = main_machine(super::IMAGE_SUBSYSTEM_WINDOWS_CUI);
+        machine.state.kernel32.env.set("FOO", Some("bar"));
+        let (exit_code, args) = entry_args(&mut machine, false);
+        assert_eq!(exit_code, 3);
+        let mem = machine.mem();
+        let strings = |array: u32| -> Vec<&[u8]> {
+            (0..)
+                .map(|i| mem.get_pod::<u32>(array + i * 4))
+                .take_while(|&addr| addr != 0)
+                .map(|addr| mem.slicez(addr))
+                .collect()
+        };
+        assert_eq!(args[0], 3);
+        assert_eq!(
+            strings(args[1]),
+            [b"test.exe".as_slice(), b"a".as_slice(), b"b c".as_slice()]
+        );
+        assert!(strings(args[2]).contains(&b"FOO=bar".as_slice()));
+    }
 }
@@ -1,17 +1,52 @@
 //! For some reason kernel32 exports functions that I would've expected to find in the libc...
 
 use crate::{winapi::types::Str16, Machine};
-use memory::ExtensionsMut;
+use memory::{Extensions, ExtensionsMut, Mem};
 
 const TRACE_CONTEXT: &'static str = "kernel32/libc";
 
-#[win32_derive::dllexport]
-pub fn lstrlenA(_machine: &mut Machine, lpString: Option<&str>) -> u32 {
-    match lpString {
-        None => 0,
-        // The mapping to str already computes the string length.
-        Some(str) => str.len() as u32,
+/// Length of the nul-terminated string at addr, with null pointers treated as empty.
+fn strlen(mem: Mem, addr: u32) -> u32 {
+    if addr == 0 {
+        return 0;
     }
+    mem.slicez(addr).len() as u32
+}
+
+/// Copy the string at src to dst, truncating to at most max-1 bytes, always nul-terminating.
+fn strcpy(mem: Mem, dst: u32, src: u32, max: Option<u32>) {
+    let mut len = strlen(mem, src);
+    if let Some(max) = max {
+        if max == 0 {
+            return;
+        }
+        len = len.min(max - 1);
+    }
+    mem.copy(src, dst, len);
+    mem.put_pod::<u8>(dst + len, 0);
+}
+
+/// Compare the strings at a and b, returning <0, 0, or >0 like strcmp.
+fn strcmp(mem: Mem, a: u32, b: u32, ignore_case: bool) -> i32 {
+    let a = if a == 0 { &[][..] } else { mem.slicez(a) };
+    let b = if b == 0 { &[][..] } else { mem.slicez(b) };
+    let fold = |c: &u8| {
+        if ignore_case {
+            c.to_ascii_lowercase()
+        } else {
+            *c
+        }
+    };
+    match a.iter().map(fold).cmp(b.iter().map(fold)) {
+        std::cmp::Ordering::Less => -1,
+        std::cmp::Ordering::Equal => 0,
+        std::cmp::Ordering::Greater => 1,
+    }
+}
+
+#[win32_derive::dllexport]
+pub fn lstrlenA(machine: &mut Machine, lpString: u32) -> u32 {
+    strlen(machine.mem(), lpString)
 }
 
 #[win32_derive::dllexport]
@@ -24,11 +59,35 @@ pub fn lstrlenW(_machine: &mut Machine, lpString: Option<&Str16>) -> u32 {
 }
 
 #[win32_derive::dllexport]
-pub fn lstrcpyA(machine: &mut Machine, lpString1: u32, lpString2: Option<&str>) -> u32 {
-    let src = lpString2.unwrap();
-    let dst = machine.mem().sub32_mut(lpString1, (src.len() + 1) as u32);
-    dst[..src.len()].copy_from_slice(src.as_bytes());
-    dst[src.len()] = 0;
+pub fn lstrcpyA(machine: &mut Machine, lpString1: u32, lpString2: u32) -> u32 {
+    if lpString1 == 0 || lpString2 == 0 {
+        return 0;
+    }
+    strcpy(machine.mem(), lpString1, lpString2, None);
+    lpString1
+}
+
+#[win32_derive::dllexport]
+pub fn lstrcpynA(machine: &mut Machine, lpString1: u32, lpString2: u32, iMaxLength: i32) -> u32 {
+    if lpString1 == 0 || lpString2 == 0 {
+        return 0;
+    }
+    strcpy(
+        machine.mem(),
+        lpString1,
+        lpString2,
+        Some(iMaxLength.max(0) as u32),
+    );
+    lpString1
+}
+
+#[win32_derive::dllexport]
+pub fn lstrcatA(machine: &mut Machine, lpString1: u32, lpString2: u32) -> u32 {
+    if lpString1 == 0 || lpString2 == 0 {
+        return 0;
+    }
+    let mem = machine.mem();
+    strcpy(mem, lpString1 + strlen(mem, lpString1), lpString2, None);
     lpString1
 }
 
@@ -45,22 +104,44 @@ pub fn lstrcpyW(machine: &mut Machine, lpString1: u32, lpString2: Option<&Str16>
 }
 
 #[win32_derive::dllexport]
-pub fn lstrcmpiA(_machine: &mut Machine, lpString1: Option<&str>, lpString2: Option<&str>) -> i32 {
-    let lpString1 = lpString1.unwrap();
-    let lpString2 = lpString2.unwrap();
+pub fn lstrcmpA(machine: &mut Machine, lpString1: u32, lpString2: u32) -> i32 {
+    strcmp(machine.mem(), lpString1, lpString2, false)
+}
 
-    for (a, b) in lpString1.bytes().zip(lpString2.bytes()) {
-        // TODO: case insensitive
-        if a < b {
-            return -1;
-        } else if a > b {
-            return 1;
-        }
-    }
+#[win32_derive::dllexport]
+pub fn lstrcmpiA(machine: &mut Machine, lpString1: u32, lpString2: u32) -> i32 {
+    // TODO: Windows compares using the locale's collation, not bytewise.
+    strcmp(machine.mem(), lpString1, lpString2, true)
+}
 
-    match lpString1.len().cmp(&lpString2.len()) {
-        std::cmp::Ordering::Less => -1,
-        std::cmp::Ordering::Greater => 1,
-        std::cmp::Ordering::Equal => 0,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strings() {
+        let buf = [0u8; 64];
+        let mem = Mem::from_slice(&buf);
+        mem.sub32_mut(1, 6).copy_from_slice(b"hello\0");
+        mem.sub32_mut(7, 7).copy_from_slice(b"World!\0");
+        assert_eq!(strlen(mem, 0), 0);
+        assert_eq!(strlen(mem, 1), 5);
+
+        strcpy(mem, 20, 1, None);
+        assert_eq!(mem.slicez(20), b"hello");
+        strcpy(mem, 20 + strlen(mem, 20), 7, None);
+        assert_eq!(mem.slicez(20), b"helloWorld!");
+
+        strcpy(mem, 40, 7, Some(4));
+        assert_eq!(mem.slicez(40), b"Wor");
+        strcpy(mem, 40, 7, Some(1));
+        assert_eq!(mem.slicez(40), b"");
+
+        assert_eq!(strcmp(mem, 1, 20, false), -1);
+        assert_eq!(strcmp(mem, 20, 1, false), 1);
+        assert_eq!(strcmp(mem, 0, 40, false), 0);
+        mem.sub32_mut(50, 6).copy_from_slice(b"HELLO\0");
+        assert_eq!(strcmp(mem, 1, 50, false), 1);
+        assert_eq!(strcmp(mem, 1, 50, true), 0);
     }
 }
@@ -1,7 +1,7 @@
 use crate::{
     machine::{Machine, MemImpl},
     pe::ImageSectionFlags,
-    winapi::stack_args,
+    winapi::{kernel32::set_last_error, stack_args, ERROR},
 };
 use bitflags::bitflags;
 use memory::{Extensions, ExtensionsMut, Mem};
@@ -13,14 +13,27 @@ pub fn round_up_to_page_granularity(size: u32) -> u32 {
     size + (0x1000 - 1) & !(0x1000 - 1)
 }
 
+/// Size of the inaccessible mapping at address 0, which catches null pointer accesses.
+/// As on Windows it covers the low 64kb, so that no pointer is mistaken for the
+/// 16-bit atoms and resource ids that APIs accept in place of strings.
+pub const NULL_GUARD_SIZE: u32 = 0x10000;
+
 /// Memory span as managed by the kernel.  Some come from the exe and others are allocated dynamically.
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
 pub struct Mapping {
     pub addr: u32,
     pub size: u32,
+    /// Address of the allocation this mapping came from; it differs from addr when
+    /// VirtualProtect has split an allocation into pieces.
+    pub base: u32,
     pub desc: String,
     pub flags: ImageSectionFlags,
+    /// False for decommitted memory, which faults on any access.
+    pub committed: bool,
+    /// Part of a loaded PE image, which VirtualFree can't release or decommit.
+    #[serde(default)]
+    pub image: bool,
 }
 
 impl Mapping {
@@ -31,44 +44,82 @@ impl Mapping {
 
 /// The set of Mappings managed by the kernel.
 /// These get visualized in the debugger when you hover a pointer.
-#[derive(serde::Serialize, Debug)]
-pub struct Mappings(Vec<Mapping>);
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(transparent)]
+pub struct Mappings {
+    mappings: Vec<Mapping>,
+    /// Set whenever the mappings change, so the CPU's copy of the protections can be refreshed.
+    /// Mappings loaded from a save state count as changed.
+    #[serde(skip, default = "changed")]
+    changed: bool,
+}
+fn changed() -> bool {
+    true
+}
+
 impl Mappings {
     pub fn new() -> Self {
-        Mappings(vec![Mapping {
-            addr: 0,
-            size: 0x1000,
-            desc: "avoid null pointers".into(),
-            flags: ImageSectionFlags::empty(),
-        }])
+        Mappings {
+            mappings: vec![Mapping {
+                addr: 0,
+                size: NULL_GUARD_SIZE,
+                base: 0,
+                desc: "avoid null pointers".into(),
+                // Any access faults.
+                flags: ImageSectionFlags::empty(),
+                committed: true,
+                image: false,
+            }],
+            changed: true,
+        }
     }
 
-    pub fn add(&mut self, mut mapping: Mapping) -> &Mapping {
+    /// Returns whether the mappings changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub fn add(&mut self, mut mapping: Mapping) -> &mut Mapping {
         mapping.size = round_up_to_page_granularity(mapping.size);
         let pos = self
-            .0
+            .mappings
             .iter()
             .position(|m| m.addr > mapping.addr)
-            .unwrap_or(self.0.len());
+            .unwrap_or(self.mappings.len());
         if pos > 0 {
-            let prev = &mut self.0[pos - 1];
+            let prev = &mut self.mappings[pos - 1];
             if prev.addr + prev.size > mapping.addr {
                 panic!("mapping conflict loading {mapping:x?} conflicts with {prev:x?}",);
             }
         }
-        if pos < self.0.len() {
-            let next = &self.0[pos];
+        if pos < self.mappings.len() {
+            let next = &self.mappings[pos];
             assert!(mapping.addr + mapping.size <= next.addr);
         }
-        self.0.insert(pos, mapping);
-        &self.0[pos]
+        self.mappings.insert(pos, mapping);
+        self.changed = true;
+        &mut self.mappings[pos]
+    }
+
+    /// Find the mapping containing addr.
+    pub fn find(&self, addr: u32) -> Option<&Mapping> {
+        let pos = self.mappings.partition_point(|m| m.addr + m.size <= addr);
+        self.mappings.get(pos).filter(|m| m.contains(addr))
+    }
+
+    /// Whether the given span overlaps no existing mapping.
+    pub fn is_free(&self, addr: u32, size: u32) -> bool {
+        let end = addr as u64 + round_up_to_page_granularity(size) as u64;
+        self.mappings
+            .iter()
+            .all(|m| (m.addr as u64 + m.size as u64) <= addr as u64 || m.addr as u64 >= end)
     }
 
     /// Find an address where we can create a new mapping of given size.
     pub fn find_space(&self, size: u32) -> u32 {
         let size = round_up_to_page_granularity(size);
         let mut prev_end = 0;
-        for mapping in &self.0 {
+        for mapping in &self.mappings {
             let space = mapping.addr - prev_end;
             if space > size {
                 break;
@@ -78,7 +129,7 @@ impl Mappings {
         prev_end
     }
 
-    pub fn alloc(&mut self, size: u32, desc: String, mem: &mut MemImpl) -> &Mapping {
+    pub fn alloc(&mut self, size: u32, desc: String, mem: &mut MemImpl) -> &mut Mapping {
         let size = round_up_to_page_granularity(size);
         if size > 32 << 20 {
             panic!("new mapping {:?} too large: {size:x} bytes", desc);
@@ -93,34 +144,133 @@ impl Mappings {
         self.add(Mapping {
             addr,
             size,
+            base: addr,
             desc,
-            flags: ImageSectionFlags::empty(),
+            flags: ImageSectionFlags::MEM_READ | ImageSectionFlags::MEM_WRITE,
+            committed: true,
+            image: false,
         })
     }
 
+    /// Split the mapping containing addr, if any, such that a mapping starts at addr.
+    fn split_at(&mut self, addr: u32) {
+        let pos = self.mappings.partition_point(|m| m.addr + m.size <= addr);
+        let Some(mapping) = self.mappings.get_mut(pos) else {
+            return;
+        };
+        if mapping.addr >= addr {
+            return;
+        }
+        let mut tail = mapping.clone();
+        mapping.size = addr - mapping.addr;
+        tail.size -= mapping.size;
+        tail.addr = addr;
+        self.mappings.insert(pos + 1, tail);
+    }
+
+    /// Apply f to the pages spanning [addr, addr+size), splitting mappings at the
+    /// page boundaries as needed.  Fails, changing nothing, if any of it is unmapped.
+    pub fn modify(&mut self, addr: u32, size: u32, mut f: impl FnMut(&mut Mapping)) -> bool {
+        let start = addr & !(0x1000 - 1);
+        let end = round_up_to_page_granularity(addr + size.max(1));
+        let mut next = start;
+        while next < end {
+            match self.find(next) {
+                Some(m) => next = m.addr + m.size,
+                None => return false,
+            }
+        }
+
+        self.split_at(start);
+        self.split_at(end);
+        for mapping in self.mappings.iter_mut() {
+            if mapping.addr >= start && mapping.addr < end {
+                f(mapping);
+            }
+        }
+        self.changed = true;
+        true
+    }
+
+    /// Whether every byte of [addr, addr+size) is committed and allows `access`,
+    /// one of MEM_READ, MEM_WRITE or MEM_EXECUTE.  As on x86, writable or executable
+    /// memory is also readable.
+    pub fn allows(&self, addr: u32, size: u32, access: ImageSectionFlags) -> bool {
+        let Some(end) = addr.checked_add(size) else {
+            return false;
+        };
+        let mut next = addr;
+        while next < end {
+            let Some(mapping) = self.find(next) else {
+                return false;
+            };
+            let flags = if mapping.committed {
+                mapping.flags
+            } else {
+                ImageSectionFlags::empty()
+            };
+            let ok = if access == ImageSectionFlags::MEM_READ {
+                flags.intersects(
+                    ImageSectionFlags::MEM_READ
+                        | ImageSectionFlags::MEM_WRITE
+                        | ImageSectionFlags::MEM_EXECUTE,
+                )
+            } else {
+                flags.contains(access)
+            };
+            if !ok {
+                return false;
+            }
+            next = mapping.addr + mapping.size;
+        }
+        true
+    }
+
+    /// Remove all the mappings making up the allocation starting at base, zeroing their
+    /// memory so a later allocation there doesn't see stale contents.
+    /// Fails if base isn't the start of an allocation.
+    pub fn release(&mut self, base: u32, mem: Mem) -> bool {
+        if !self
+            .find(base)
+            .map_or(false, |m| m.addr == base && m.base == base)
+        {
+            return false;
+        }
+        self.mappings.retain(|m| {
+            if m.base != base {
+                return true;
+            }
+            mem.sub32_mut(m.addr, m.size).fill(0);
+            false
+        });
+        self.changed = true;
+        true
+    }
+
     pub fn vec(&self) -> &Vec<Mapping> {
-        &self.0
+        &self.mappings
     }
 
     pub fn grow(&mut self, addr: u32, min_growth: u32) -> u32 {
-        let pos = self.0.iter().position(|m| m.addr == addr).unwrap();
-        let mapping = &self.0[pos];
+        let pos = self.mappings.iter().position(|m| m.addr == addr).unwrap();
+        let mapping = &self.mappings[pos];
         let mut new_size = mapping.size;
         while new_size - mapping.size < min_growth {
             new_size *= 2;
         }
 
         // Check if we run into a mapping after this one.
-        if pos + 1 < self.0.len() {
-            let next = &self.0[pos + 1];
+        if pos + 1 < self.mappings.len() {
+            let next = &self.mappings[pos + 1];
             if mapping.addr + new_size > next.addr {
                 panic!("cannot grow {:?}", mapping);
             }
         }
 
-        let mapping = &mut self.0[pos];
+        let mapping = &mut self.mappings[pos];
         let growth = new_size - mapping.size;
         mapping.size = new_size;
+        self.changed = true;
         log::info!(
             "grew mapping {:?} by {:#x}, new size {:#x}",
             mapping.desc,
@@ -132,7 +282,7 @@ impl Mappings {
     }
 
     pub fn dump(&self) {
-        for map in &self.0 {
+        for map in &self.mappings {
             println!(
                 "{:08x}-{:08x} {:?} {:?}",
                 map.addr,
@@ -144,7 +294,7 @@ impl Mappings {
     }
 
     pub fn dump_memory(&self, mem: Mem) {
-        for map in &self.0 {
+        for map in &self.mappings {
             println!("{map:x?}");
             for addr in (map.addr..map.addr + map.size).step_by(16) {
                 println!("{addr:x} {:x?}", mem.sub32(addr, 16));
@@ -159,6 +309,7 @@ bitflags! {
         const HEAP_GENERATE_EXCEPTIONS = 0x4;
         const HEAP_NO_SERIALIZE = 0x1;
         const HEAP_ZERO_MEMORY = 0x8;
+        const HEAP_REALLOC_IN_PLACE_ONLY = 0x10;
     }
 }
 impl TryFrom<u32> for HeapAllocFlags {
@@ -185,6 +336,7 @@ pub fn HeapAlloc(
     let heap = match machine.state.kernel32.get_heap(hHeap) {
         None => {
             log::error!("HeapAlloc({hHeap:x}): no such heap");
+            set_last_error(machine, ERROR::INVALID_HANDLE);
             return 0;
         }
         Some(heap) => heap,
@@ -192,6 +344,8 @@ pub fn HeapAlloc(
     let addr = heap.alloc(machine.emu.memory.mem(), dwBytes);
     if addr == 0 {
         log::warn!("HeapAlloc({hHeap:x}) failed");
+        set_last_error(machine, ERROR::NOT_ENOUGH_MEMORY);
+        return 0;
     }
     if flags.contains(HeapAllocFlags::HEAP_ZERO_MEMORY) {
         machine.mem().sub32_mut(addr, dwBytes).fill(0);
@@ -208,12 +362,18 @@ pub fn HeapFree(machine: &mut Machine, hHeap: u32, dwFlags: u32, lpMem: u32) ->
     if dwFlags != 0 {
         log::warn!("HeapFree flags {dwFlags:x}");
     }
-    machine
-        .state
-        .kernel32
-        .get_heap(hHeap)
-        .unwrap()
-        .free(machine.emu.memory.mem(), lpMem);
+    if lpMem == 0 {
+        return true;
+    }
+    let heap = match machine.state.kernel32.get_heap(hHeap) {
+        None => {
+            log::error!("HeapFree({hHeap:x}): no such heap");
+            set_last_error(machine, ERROR::INVALID_HANDLE);
+            return false;
+        }
+        Some(heap) => heap,
+    };
+    heap.free(machine.emu.memory.mem(), lpMem);
     true
 }
 
@@ -247,25 +407,47 @@ pub fn HeapSetInformation(
 pub fn HeapReAlloc(
     machine: &mut Machine,
     hHeap: u32,
-    dwFlags: u32,
+    dwFlags: Result<HeapAllocFlags, u32>,
     lpMem: u32,
     dwBytes: u32,
 ) -> u32 {
-    if dwFlags != 0 {
-        log::warn!("HeapReAlloc flags: {:x}", dwFlags);
-    }
+    let mut flags = dwFlags.unwrap_or_else(|_| {
+        log::warn!("HeapReAlloc invalid flags {dwFlags:x?}");
+        HeapAllocFlags::empty()
+    });
+    flags.remove(HeapAllocFlags::HEAP_GENERATE_EXCEPTIONS); // todo: OOM
+    flags.remove(HeapAllocFlags::HEAP_NO_SERIALIZE); // todo: threads
     let heap = match machine.state.kernel32.get_heap(hHeap) {
         None => {
-            log::error!("HeapSize({hHeap:x}): no such heap");
+            log::error!("HeapReAlloc({hHeap:x}): no such heap");
+            set_last_error(machine, ERROR::INVALID_HANDLE);
             return 0;
         }
         Some(heap) => heap,
     };
-    let old_size = heap.size(machine.emu.memory.mem(), lpMem);
-    let new_addr = heap.alloc(machine.emu.memory.mem(), dwBytes);
-    assert!(dwBytes >= old_size);
-    heap.free(machine.emu.memory.mem(), lpMem);
-    machine.mem().copy(lpMem, new_addr, old_size);
+    let mem = machine.emu.memory.mem();
+    let old_size = heap.size(mem, lpMem);
+    if dwBytes <= old_size {
+        // Shrinking always succeeds in place; we just don't reclaim the tail.
+        return lpMem;
+    }
+    if flags.contains(HeapAllocFlags::HEAP_REALLOC_IN_PLACE_ONLY) {
+        set_last_error(machine, ERROR::NOT_ENOUGH_MEMORY);
+        return 0;
+    }
+    let new_addr = heap.alloc(mem, dwBytes);
+    if new_addr == 0 {
+        // On failure the original block is left untouched.
+        log::warn!("HeapReAlloc({hHeap:x}) failed");
+        set_last_error(machine, ERROR::NOT_ENOUGH_MEMORY);
+        return 0;
+    }
+    mem.copy(lpMem, new_addr, old_size);
+    heap.free(mem, lpMem);
+    if flags.contains(HeapAllocFlags::HEAP_ZERO_MEMORY) {
+        mem.sub32_mut(new_addr + old_size, dwBytes - old_size)
+            .fill(0);
+    }
     new_addr
 }
 
@@ -302,9 +484,15 @@ pub fn HeapCreate(
 }
 
 #[win32_derive::dllexport]
-pub fn HeapDestroy(_machine: &mut Machine, hHeap: u32) -> u32 {
-    log::warn!("HeapDestroy({hHeap:x})");
-    1 // success
+pub fn HeapDestroy(machine: &mut Machine, hHeap: u32) -> u32 {
+    // The backing mapping is leaked; only the heap bookkeeping goes away.
+    match machine.state.kernel32.destroy_heap(hHeap) {
+        Some(_) => 1, // success
+        None => {
+            set_last_error(machine, ERROR::INVALID_HANDLE);
+            0
+        }
+    }
 }
 
 #[win32_derive::dllexport]
@@ -316,6 +504,8 @@ bitflags! {
     pub struct MEM: u32 {
         const COMMIT = 0x00001000;
         const RESERVE = 0x00002000;
+        const DECOMMIT = 0x00004000;
+        const RELEASE = 0x00008000;
         const RESET = 0x00080000;
         const RESET_UNDO = 0x1000000;
         const LARGE_PAGES = 0x20000000;
@@ -357,6 +547,46 @@ impl TryFrom<u32> for PAGE {
     }
 }
 
+impl PAGE {
+    /// The access a PAGE_* protection grants, in the terms Mapping uses.
+    /// Modifiers like PAGE_GUARD are ignored.
+    pub fn to_flags(self) -> ImageSectionFlags {
+        let (r, w, x) = (
+            ImageSectionFlags::MEM_READ,
+            ImageSectionFlags::MEM_WRITE,
+            ImageSectionFlags::MEM_EXECUTE,
+        );
+        if self.contains(PAGE::EXECUTE_READWRITE) || self.contains(PAGE::EXECUTE_WRITECOPY) {
+            r | w | x
+        } else if self.contains(PAGE::EXECUTE_READ) {
+            r | x
+        } else if self.contains(PAGE::EXECUTE) {
+            x
+        } else if self.contains(PAGE::READWRITE) || self.contains(PAGE::WRITECOPY) {
+            r | w
+        } else if self.contains(PAGE::READONLY) {
+            r
+        } else {
+            ImageSectionFlags::empty()
+        }
+    }
+
+    /// The inverse of to_flags.
+    pub fn from_flags(flags: ImageSectionFlags) -> PAGE {
+        let r = flags.contains(ImageSectionFlags::MEM_READ);
+        let w = flags.contains(ImageSectionFlags::MEM_WRITE);
+        let x = flags.contains(ImageSectionFlags::MEM_EXECUTE);
+        match (x, w, r) {
+            (true, true, _) => PAGE::EXECUTE_READWRITE,
+            (true, false, true) => PAGE::EXECUTE_READ,
+            (true, false, false) => PAGE::EXECUTE,
+            (false, true, _) => PAGE::READWRITE,
+            (false, false, true) => PAGE::READONLY,
+            (false, false, false) => PAGE::NOACCESS,
+        }
+    }
+}
+
 #[win32_derive::dllexport]
 pub fn VirtualAlloc(
     machine: &mut Machine,
@@ -365,34 +595,70 @@ pub fn VirtualAlloc(
     flAllocationType: Result<MEM, u32>,
     flProtec: Result<PAGE, u32>,
 ) -> u32 {
-    if lpAddress != 0 {
-        // Changing flags on an existing address, hopefully.
-        match machine
-            .state
-            .kernel32
-            .mappings
-            .vec()
-            .iter()
-            .find(|&mapping| mapping.contains(lpAddress))
-        {
-            None => {
-                log::error!("failing VirtualAlloc({lpAddress:x}, ...) refers to unknown mapping");
-                return 0;
-            }
-            Some(_) => {
-                // adjusting flags on existing mapping, ignore.
-                return lpAddress;
+    let alloc_type = match flAllocationType {
+        Ok(t) if t.intersects(MEM::COMMIT | MEM::RESERVE) => t,
+        _ => {
+            set_last_error(machine, ERROR::INVALID_PARAMETER);
+            return 0;
+        }
+    };
+    let commit = alloc_type.contains(MEM::COMMIT);
+    let flags = match flProtec {
+        Ok(protect) => protect.to_flags(),
+        Err(_) => ImageSectionFlags::MEM_READ | ImageSectionFlags::MEM_WRITE,
+    };
+    let size = round_up_to_page_granularity(dwSize);
+    let mappings = &mut machine.state.kernel32.mappings;
+
+    if lpAddress != 0 && mappings.find(lpAddress).is_some() {
+        // Committing pages within a prior reservation.
+        if !commit {
+            set_last_error(machine, ERROR::INVALID_ADDRESS);
+            return 0;
+        }
+        let mem = machine.emu.memory.mem();
+        let ok = mappings.modify(lpAddress, dwSize, |mapping| {
+            if !mapping.committed {
+                // Newly committed pages read as zero.
+                mem.sub32_mut(mapping.addr, mapping.size).fill(0);
+                mapping.committed = true;
             }
+            mapping.flags = flags;
+        });
+        if !ok {
+            log::error!("failing VirtualAlloc({lpAddress:x}, ...) runs past its reservation");
+            set_last_error(machine, ERROR::INVALID_ADDRESS);
+            return 0;
         }
+        return lpAddress & !(0x1000 - 1);
     }
-    // TODO round dwSize to page boundary
 
-    let mapping = machine.state.kernel32.mappings.alloc(
-        dwSize,
-        "VirtualAlloc".into(),
-        &mut machine.emu.memory,
-    );
-    mapping.addr
+    let mapping = if lpAddress != 0 {
+        // A new allocation at a requested address.
+        let addr = lpAddress & !(0x1000 - 1);
+        if !mappings.is_free(addr, size) || addr + size > machine.emu.memory.len() {
+            set_last_error(machine, ERROR::INVALID_ADDRESS);
+            return 0;
+        }
+        mappings.add(Mapping {
+            addr,
+            size,
+            base: addr,
+            desc: "VirtualAlloc".into(),
+            flags,
+            committed: true,
+            image: false,
+        })
+    } else {
+        mappings.alloc(size, "VirtualAlloc".into(), &mut machine.emu.memory)
+    };
+    mapping.flags = flags;
+    mapping.committed = commit;
+    let (addr, size) = (mapping.addr, mapping.size);
+    if commit {
+        machine.emu.memory.mem().sub32_mut(addr, size).fill(0);
+    }
+    addr
 }
 
 #[derive(Debug)]
@@ -419,23 +685,69 @@ pub fn VirtualQuery(
 }
 
 #[win32_derive::dllexport]
-pub fn VirtualFree(_machine: &mut Machine, lpAddress: u32, dwSize: u32, dwFreeType: u32) -> u32 {
-    1 // success
+pub fn VirtualFree(
+    machine: &mut Machine,
+    lpAddress: u32,
+    dwSize: u32,
+    dwFreeType: Result<MEM, u32>,
+) -> bool {
+    let mappings = &mut machine.state.kernel32.mappings;
+    if lpAddress < NULL_GUARD_SIZE {
+        set_last_error(machine, ERROR::INVALID_ADDRESS);
+        return false;
+    }
+    if mappings.find(lpAddress).map_or(false, |m| m.image) {
+        // Images are unloaded with FreeLibrary, not piecemeal.
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return false;
+    }
+    let mem = machine.emu.memory.mem();
+    let ok = match dwFreeType {
+        Ok(MEM::RELEASE) if dwSize == 0 => mappings.release(lpAddress, mem),
+        Ok(MEM::DECOMMIT) => {
+            let size = if dwSize != 0 {
+                dwSize
+            } else {
+                // Zero means the whole allocation, which lpAddress must be the start of.
+                match mappings.find(lpAddress) {
+                    Some(m) if m.base == lpAddress => mappings
+                        .vec()
+                        .iter()
+                        .filter(|m| m.base == lpAddress)
+                        .map(|m| m.size)
+                        .sum(),
+                    _ => 0,
+                }
+            };
+            size != 0 && mappings.modify(lpAddress, size, |mapping| mapping.committed = false)
+        }
+        _ => {
+            set_last_error(machine, ERROR::INVALID_PARAMETER);
+            return false;
+        }
+    };
+    if !ok {
+        set_last_error(machine, ERROR::INVALID_ADDRESS);
+    }
+    ok
 }
 
 #[win32_derive::dllexport]
-pub fn IsBadReadPtr(_machine: &mut Machine, lp: u32, ucb: u32) -> bool {
-    false // all pointers are valid
+pub fn IsBadReadPtr(machine: &mut Machine, lp: u32, ucb: u32) -> bool {
+    let mappings = &machine.state.kernel32.mappings;
+    !mappings.allows(lp, ucb, ImageSectionFlags::MEM_READ)
 }
 
 #[win32_derive::dllexport]
-pub fn IsBadWritePtr(_machine: &mut Machine, lp: u32, ucb: u32) -> bool {
-    false // all pointers are valid
+pub fn IsBadWritePtr(machine: &mut Machine, lp: u32, ucb: u32) -> bool {
+    let mappings = &machine.state.kernel32.mappings;
+    !mappings.allows(lp, ucb, ImageSectionFlags::MEM_WRITE)
 }
 
 #[win32_derive::dllexport]
-pub fn IsBadCodePtr(_machine: &mut Machine, lpfn: u32) -> bool {
-    false // all pointers are valid
+pub fn IsBadCodePtr(machine: &mut Machine, lpfn: u32) -> bool {
+    let mappings = &machine.state.kernel32.mappings;
+    !mappings.allows(lpfn, 1, ImageSectionFlags::MEM_EXECUTE)
 }
 
 bitflags! {
@@ -453,19 +765,53 @@ impl<'a> stack_args::FromArg<'a> for GMEM {
     }
 }
 
+/// A GMEM_MOVEABLE allocation.  Its handle is the address of a heap-allocated "master pointer"
+/// to the data, as on Windows, so code that dereferences handles directly still works.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct MoveableBlock {
+    lock_count: u32,
+}
+
 fn alloc(machine: &mut Machine, uFlags: GMEM, dwBytes: u32) -> u32 {
-    if uFlags.contains(GMEM::MOVEABLE) {
-        todo!("GMEM_MOVEABLE");
-    }
     let heap = machine
         .state
         .kernel32
         .get_process_heap(&mut machine.emu.memory); // lazy init process_heap
-    let addr = heap.alloc(machine.emu.memory.mem(), dwBytes);
+    let mem = machine.emu.memory.mem();
+    let addr = heap.alloc(mem, dwBytes);
+    if addr == 0 {
+        set_last_error(machine, ERROR::NOT_ENOUGH_MEMORY);
+        return 0;
+    }
     if uFlags.contains(GMEM::ZEROINIT) {
-        machine.mem().sub32_mut(addr, dwBytes).fill(0);
+        mem.sub32_mut(addr, dwBytes).fill(0);
+    }
+    if !uFlags.contains(GMEM::MOVEABLE) {
+        return addr;
+    }
+
+    let handle = heap.alloc(mem, 4);
+    if handle == 0 {
+        heap.free(mem, addr);
+        set_last_error(machine, ERROR::NOT_ENOUGH_MEMORY);
+        return 0;
+    }
+    mem.put_pod::<u32>(handle, addr);
+    machine
+        .state
+        .kernel32
+        .moveable_blocks
+        .insert(handle, MoveableBlock { lock_count: 0 });
+    handle
+}
+
+/// The data pointer of a moveable block, or hMem itself for fixed memory.
+fn block_data(machine: &Machine, hMem: u32) -> u32 {
+    if machine.state.kernel32.moveable_blocks.contains_key(&hMem) {
+        machine.mem().get_pod::<u32>(hMem)
+    } else {
+        hMem
     }
-    addr
 }
 
 #[win32_derive::dllexport]
@@ -478,41 +824,134 @@ pub fn GlobalReAlloc(machine: &mut Machine, hMem: u32, dwBytes: u32, uFlags: GME
     if uFlags.contains(GMEM::MODIFY) {
         todo!("GMEM_MODIFY");
     }
+    let data = block_data(machine, hMem);
     let heap = machine
         .state
         .kernel32
         .get_process_heap(&mut machine.emu.memory);
     let mem = machine.emu.memory.mem();
-    let old_size = heap.size(mem, hMem);
+    let old_size = heap.size(mem, data);
     if dwBytes <= old_size {
         return hMem;
     }
     let addr = heap.alloc(mem, dwBytes);
-    mem.copy(hMem, addr, old_size);
-    heap.free(mem, hMem);
+    if addr == 0 {
+        set_last_error(machine, ERROR::NOT_ENOUGH_MEMORY);
+        return 0;
+    }
+    mem.copy(data, addr, old_size);
+    heap.free(mem, data);
     if uFlags.contains(GMEM::ZEROINIT) {
         mem.sub32_mut(addr + old_size, dwBytes - old_size).fill(0);
     }
+    if machine.state.kernel32.moveable_blocks.contains_key(&hMem) {
+        // The handle stays the same; only the master pointer moves.
+        mem.put_pod::<u32>(hMem, addr);
+        return hMem;
+    }
     addr
 }
 
 fn free(machine: &mut Machine, hMem: u32) -> u32 {
+    let data = block_data(machine, hMem);
+    let moveable = machine
+        .state
+        .kernel32
+        .moveable_blocks
+        .remove(&hMem)
+        .is_some();
     let heap = machine
         .state
         .kernel32
         .get_process_heap(&mut machine.emu.memory);
-    heap.free(machine.emu.memory.mem(), hMem);
+    let mem = machine.emu.memory.mem();
+    heap.free(mem, data);
+    if moveable {
+        heap.free(mem, hMem);
+    }
     return 0; // success
 }
 
+fn lock(machine: &mut Machine, hMem: u32) -> u32 {
+    if hMem == 0 {
+        set_last_error(machine, ERROR::INVALID_HANDLE);
+        return 0;
+    }
+    if let Some(block) = machine.state.kernel32.moveable_blocks.get_mut(&hMem) {
+        block.lock_count += 1;
+    }
+    block_data(machine, hMem)
+}
+
+fn unlock(machine: &mut Machine, hMem: u32) -> bool {
+    let Some(block) = machine.state.kernel32.moveable_blocks.get_mut(&hMem) else {
+        // Fixed memory is never locked, and unlocking it is harmless.
+        return true;
+    };
+    if block.lock_count == 0 {
+        set_last_error(machine, ERROR::NOT_LOCKED);
+        return false;
+    }
+    block.lock_count -= 1;
+    if block.lock_count == 0 {
+        // Per the docs, "no longer locked" is reported as zero with NO_ERROR.
+        set_last_error(machine, ERROR::SUCCESS);
+        return false;
+    }
+    true
+}
+
+/// The handle of the block whose data is at pMem: a moveable block is found by its master
+/// pointer, and fixed memory is its own handle.
+fn handle(machine: &Machine, pMem: u32) -> u32 {
+    let mem = machine.mem();
+    machine
+        .state
+        .kernel32
+        .moveable_blocks
+        .keys()
+        .copied()
+        .find(|&handle| mem.get_pod::<u32>(handle) == pMem)
+        .unwrap_or(pMem)
+}
+
 #[win32_derive::dllexport]
 pub fn GlobalFree(machine: &mut Machine, hMem: u32) -> u32 {
     free(machine, hMem)
 }
 
 #[win32_derive::dllexport]
-pub fn GlobalFlags(_machine: &mut Machine, hMem: u32) -> u32 {
-    0 // stub
+pub fn GlobalLock(machine: &mut Machine, hMem: u32) -> u32 {
+    lock(machine, hMem)
+}
+
+#[win32_derive::dllexport]
+pub fn GlobalUnlock(machine: &mut Machine, hMem: u32) -> bool {
+    unlock(machine, hMem)
+}
+
+#[win32_derive::dllexport]
+pub fn GlobalHandle(machine: &mut Machine, pMem: u32) -> u32 {
+    handle(machine, pMem)
+}
+
+#[win32_derive::dllexport]
+pub fn GlobalSize(machine: &mut Machine, hMem: u32) -> u32 {
+    let data = block_data(machine, hMem);
+    let heap = machine
+        .state
+        .kernel32
+        .get_process_heap(&mut machine.emu.memory);
+    heap.size(machine.emu.memory.mem(), data)
+}
+
+#[win32_derive::dllexport]
+pub fn GlobalFlags(machine: &mut Machine, hMem: u32) -> u32 {
+    // The low byte is the lock count; none of the other flags apply to us.
+    match machine.state.kernel32.moveable_blocks.get(&hMem) {
+        Some(block) => block.lock_count.min(0xFF),
+        None => 0,
+    }
 }
 
 #[win32_derive::dllexport]
@@ -526,15 +965,62 @@ pub fn LocalFree(machine: &mut Machine, hMem: u32) -> u32 {
     free(machine, hMem)
 }
 
+#[win32_derive::dllexport]
+pub fn LocalLock(machine: &mut Machine, hMem: u32) -> u32 {
+    lock(machine, hMem)
+}
+
+#[win32_derive::dllexport]
+pub fn LocalUnlock(machine: &mut Machine, hMem: u32) -> bool {
+    unlock(machine, hMem)
+}
+
+#[win32_derive::dllexport]
+pub fn LocalHandle(machine: &mut Machine, pMem: u32) -> u32 {
+    handle(machine, pMem)
+}
+
 #[win32_derive::dllexport]
 pub fn VirtualProtect(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     lpAddress: u32,
     dwSize: u32,
-    flNewProtect: u32,
+    flNewProtect: Result<PAGE, u32>,
     lpflOldProtect: Option<&mut u32>,
 ) -> bool {
-    true // success
+    let Ok(protect) = flNewProtect else {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return false;
+    };
+    if lpAddress < NULL_GUARD_SIZE {
+        // The null page must keep faulting.
+        set_last_error(machine, ERROR::INVALID_ADDRESS);
+        return false;
+    }
+    let Some(old) = machine
+        .state
+        .kernel32
+        .mappings
+        .find(lpAddress)
+        .map(|m| PAGE::from_flags(m.flags))
+    else {
+        set_last_error(machine, ERROR::INVALID_ADDRESS);
+        return false;
+    };
+    let flags = protect.to_flags();
+    if !machine
+        .state
+        .kernel32
+        .mappings
+        .modify(lpAddress, dwSize, |mapping| mapping.flags = flags)
+    {
+        set_last_error(machine, ERROR::INVALID_ADDRESS);
+        return false;
+    }
+    if let Some(lpflOldProtect) = lpflOldProtect {
+        *lpflOldProtect = old.bits();
+    }
+    true
 }
 
 #[win32_derive::dllexport]
@@ -545,3 +1031,177 @@ pub fn GetProcessHeap(machine: &mut Machine) -> u32 {
         .get_process_heap(&mut machine.emu.memory); // lazy init process_heap
     machine.state.kernel32.process_heap
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ImageSectionFlags, Mapping, Mappings};
+    use memory::Mem;
+
+    #[test]
+    fn test_modify_release() {
+        let mut mappings = Mappings::new();
+        mappings.add(Mapping {
+            addr: 0x10000,
+            size: 0x4000,
+            base: 0x10000,
+            desc: "test".into(),
+            flags: ImageSectionFlags::MEM_READ | ImageSectionFlags::MEM_WRITE,
+            committed: true,
+            image: false,
+        });
+
+        assert!(mappings.modify(0x11800, 0x1000, |m| m.flags = ImageSectionFlags::MEM_READ));
+        let spans = mappings
+            .vec()
+            .iter()
+            .map(|m| {
+                (
+                    m.addr,
+                    m.size,
+                    m.flags.contains(ImageSectionFlags::MEM_WRITE),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans[1..],
+            [
+                (0x10000, 0x1000, true),
+                (0x11000, 0x2000, false),
+                (0x13000, 0x1000, true)
+            ]
+        );
+        assert!(!mappings.modify(0x13000, 0x2000, |_| {}));
+
+        let buf = vec![0xFFu8; 0x14000];
+        let mem = Mem::from_slice(&buf);
+        assert!(!mappings.release(0x11000, mem));
+        assert!(mappings.release(0x10000, mem));
+        assert_eq!(mappings.vec().len(), 1);
+        assert!(buf[0x10000..].iter().all(|&b| b == 0));
+        assert_eq!(buf[0xFFFF], 0xFF);
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_protect_free_reserved() {
+        use super::{VirtualFree, VirtualProtect, MEM, PAGE};
+        let mut machine = crate::testing::machine();
+        assert!(!VirtualProtect(
+            &mut machine,
+            0,
+            0x1000,
+            Ok(PAGE::READWRITE),
+            None
+        ));
+        assert!(!machine
+            .state
+            .kernel32
+            .mappings
+            .allows(0, 4, ImageSectionFlags::MEM_READ));
+        assert!(!VirtualFree(&mut machine, 0, 0, Ok(MEM::RELEASE)));
+        assert!(machine.state.kernel32.mappings.find(0).is_some());
+
+        machine.state.kernel32.mappings.add(Mapping {
+            addr: 0x400000,
+            size: 0x1000,
+            base: 0x400000,
+            desc: "test.exe".into(),
+            flags: ImageSectionFlags::MEM_READ,
+            committed: true,
+            image: true,
+        });
+        assert!(!VirtualFree(&mut machine, 0x400000, 0, Ok(MEM::RELEASE)));
+        assert!(!VirtualFree(
+            &mut machine,
+            0x400000,
+            0x1000,
+            Ok(MEM::DECOMMIT)
+        ));
+        assert!(
+            machine
+                .state
+                .kernel32
+                .mappings
+                .find(0x400000)
+                .unwrap()
+                .committed
+        );
+    }
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_moveable() {
+        use super::{
+            GlobalAlloc, GlobalFlags, GlobalFree, GlobalHandle, GlobalLock, GlobalReAlloc,
+            GlobalSize, GlobalUnlock, GMEM,
+        };
+        use crate::winapi::{kernel32::GetLastError, ERROR};
+        use memory::{Extensions, ExtensionsMut};
+        let mut machine = crate::testing::machine();
+
+        // Fixed memory is its own handle, and locking it is a no-op.
+        let fixed = GlobalAlloc(&mut machine, GMEM::ZEROINIT, 8);
+        assert_eq!(GlobalLock(&mut machine, fixed), fixed);
+        assert!(GlobalUnlock(&mut machine, fixed));
+        assert_eq!(GlobalHandle(&mut machine, fixed), fixed);
+
+        // A moveable handle points at its data, and locks nest.
+        let hmem = GlobalAlloc(&mut machine, GMEM::MOVEABLE | GMEM::ZEROINIT, 8);
+        let data = GlobalLock(&mut machine, hmem);
+        assert_ne!(data, hmem);
+        assert_eq!(machine.mem().get_pod::<u32>(hmem), data);
+        assert_eq!(machine.mem().sub32(data, 8), [0; 8]);
+        assert_eq!(GlobalHandle(&mut machine, data), hmem);
+        assert_eq!(GlobalLock(&mut machine, hmem), data);
+        assert_eq!(GlobalFlags(&mut machine, hmem), 2);
+        assert!(GlobalUnlock(&mut machine, hmem));
+        assert!(!GlobalUnlock(&mut machine, hmem));
+        assert_eq!(GetLastError(&mut machine), ERROR::SUCCESS as u32);
+        assert!(!GlobalUnlock(&mut machine, hmem));
+        assert_eq!(GetLastError(&mut machine), ERROR::NOT_LOCKED as u32);
+
+        // Growing moves the data but keeps the handle, zeroing the new part.
+        machine.mem().put_pod::<u64>(data, 0x0123_4567_89AB_CDEF);
+        assert_eq!(GlobalReAlloc(&mut machine, hmem, 64, GMEM::ZEROINIT), hmem);
+        let moved = GlobalLock(&mut machine, hmem);
+        assert_ne!(moved, data);
+        assert_eq!(machine.mem().get_pod::<u64>(moved), 0x0123_4567_89AB_CDEF);
+        assert!(machine.mem().sub32(moved + 8, 56).iter().all(|&b| b == 0));
+        assert!(GlobalSize(&mut machine, hmem) >= 64);
+        assert_eq!(GlobalHandle(&mut machine, moved), hmem);
+        // Shrinking happens in place.
+        assert_eq!(GlobalReAlloc(&mut machine, hmem, 4, GMEM::empty()), hmem);
+        assert_eq!(GlobalLock(&mut machine, hmem), moved);
+
+        assert_eq!(GlobalFree(&mut machine, hmem), 0);
+        assert!(!machine.state.kernel32.moveable_blocks.contains_key(&hmem));
+    }
+
+    #[test]
+    fn test_allows() {
+        let mut mappings = Mappings::new();
+        mappings.add(Mapping {
+            addr: 0x10000,
+            size: 0x2000,
+            base: 0x10000,
+            desc: "test".into(),
+            flags: ImageSectionFlags::MEM_READ | ImageSectionFlags::MEM_WRITE,
+            committed: true,
+            image: false,
+        });
+        mappings.modify(0x11000, 0x1000, |m| m.flags = ImageSectionFlags::MEM_READ);
+
+        let read = ImageSectionFlags::MEM_READ;
+        let write = ImageSectionFlags::MEM_WRITE;
+        assert!(mappings.allows(0x10000, 0x2000, read));
+        assert!(!mappings.allows(0x10000, 0x2000, write));
+        assert!(mappings.allows(0x10ff0, 0x10, write));
+        assert!(!mappings.allows(0x11ff0, 0x20, read));
+        assert!(!mappings.allows(0, 4, read));
+        assert!(!mappings.allows(0x10000, 1, ImageSectionFlags::MEM_EXECUTE));
+        assert!(mappings.allows(0x20000, 0, read));
+        assert!(!mappings.allows(0xFFFF_FFF0, 0x20, read));
+
+        mappings.modify(0x10000, 0x1000, |m| m.committed = false);
+        assert!(!mappings.allows(0x10000, 4, read));
+    }
+}
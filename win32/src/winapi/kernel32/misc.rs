@@ -1,13 +1,15 @@
 //! kernel32 API without a better home.
 
-use super::teb_mut;
+use super::{
+    from_ansi, teb_mut, HFILEMAPPING, HSYNC, HTHREAD, STDERR_HFILE, STDIN_HFILE, STDOUT_HFILE,
+};
 use crate::{
     winapi::{types::*, ERROR},
     Machine,
 };
 use ::memory::Pod;
 use bitflags::bitflags;
-use memory::ExtensionsMut;
+use memory::{Extensions, ExtensionsMut, Mem};
 
 const TRACE_CONTEXT: &'static str = "kernel32/misc";
 
@@ -33,8 +35,12 @@ pub fn ExitProcess(machine: &mut Machine, uExitCode: u32) {
 }
 
 #[win32_derive::dllexport]
-pub fn TerminateProcess(_machine: &mut Machine, hProcess: u32, uExitCode: u32) -> bool {
-    todo!();
+pub fn TerminateProcess(machine: &mut Machine, hProcess: HANDLE<()>, uExitCode: u32) -> bool {
+    if !check_process(machine, hProcess) {
+        return false;
+    }
+    machine.exit(uExitCode);
+    true
 }
 
 #[derive(Debug, win32_derive::TryFromEnum)]
@@ -93,50 +99,122 @@ pub fn DebugBreak(_machine: &mut Machine) {
     todo!()
 }
 
+/// Our process id; there's only ever the one process.
+pub const PROCESS_ID: u32 = 1;
+
+/// What GetCurrentProcess() returns, meaning the calling process.
+const CURRENT_PROCESS_PSEUDO_HANDLE: u32 = 0xFFFF_FFFF;
+
+/// Validate a process handle, which can only refer to our own process.
+fn check_process(machine: &mut Machine, hProcess: HANDLE<()>) -> bool {
+    if hProcess.raw != CURRENT_PROCESS_PSEUDO_HANDLE {
+        log::warn!("unknown process handle {hProcess:?}");
+        set_last_error(machine, ERROR::INVALID_HANDLE);
+        return false;
+    }
+    true
+}
+
 #[win32_derive::dllexport]
-pub fn GetCurrentProcess(_machine: &mut Machine) -> u32 {
-    todo!()
+pub fn GetCurrentProcess(_machine: &mut Machine) -> HANDLE<()> {
+    HANDLE::from_raw(CURRENT_PROCESS_PSEUDO_HANDLE)
 }
 
 #[win32_derive::dllexport]
 pub fn GetCurrentProcessId(_machine: &mut Machine) -> u32 {
-    1
+    PROCESS_ID
+}
+
+/// The Windows version reported to programs, e.g. by GetVersion.
+/// The platform is always VER_PLATFORM_WIN32_NT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct OSVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+}
+
+impl Default for OSVersion {
+    /// Windows XP.
+    fn default() -> Self {
+        OSVersion {
+            major: 5,
+            minor: 1,
+            build: 2600,
+        }
+    }
 }
 
+const VER_PLATFORM_WIN32_NT: u32 = 2;
+const VER_NT_WORKSTATION: u8 = 1;
+
 #[win32_derive::dllexport]
-pub fn GetVersion(_machine: &mut Machine) -> u32 {
-    // Win95, version 4.0.
-    (1 << 31) | 0x4
+pub fn GetVersion(machine: &mut Machine) -> u32 {
+    let version = machine.state.kernel32.version;
+    // The high bit is clear on NT.
+    (version.build & 0x7FFF) << 16 | version.minor << 8 | version.major
 }
 
 #[repr(C)]
 #[derive(Debug)]
-pub struct OSVERSIONINFO {
+pub struct OSVERSIONINFOA {
     dwOSVersionInfoSize: DWORD,
     dwMajorVersion: DWORD,
     dwMinorVersion: DWORD,
     dwBuildNumber: DWORD,
     dwPlatformId: DWORD,
-    //szCSDVersion: [u8; 128],
+    szCSDVersion: [u8; 128],
 }
-unsafe impl Pod for OSVERSIONINFO {}
+unsafe impl Pod for OSVERSIONINFOA {}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct OSVERSIONINFOEXA {
+    info: OSVERSIONINFOA,
+    wServicePackMajor: WORD,
+    wServicePackMinor: WORD,
+    wSuiteMask: WORD,
+    wProductType: u8,
+    wReserved: u8,
+}
+unsafe impl Pod for OSVERSIONINFOEXA {}
 
 #[win32_derive::dllexport]
-pub fn GetVersionExA(
-    _machine: &mut Machine,
-    lpVersionInformation: Option<&mut OSVERSIONINFO>,
-) -> u32 {
-    let info = lpVersionInformation.unwrap();
-    if info.dwOSVersionInfoSize < std::mem::size_of::<OSVERSIONINFO>() as u32 {
-        log::error!("GetVersionExA undersized buffer");
-        return 0;
+pub fn GetVersionExA(machine: &mut Machine, lpVersionInformation: u32) -> bool {
+    if lpVersionInformation == 0 {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return false;
     }
-    unsafe { info.clear_memory(info.dwOSVersionInfoSize) };
-
-    info.dwMajorVersion = 6; // ? pulled from debugger
-    info.dwPlatformId = 2 /* VER_PLATFORM_WIN32_NT */;
+    let mem = machine.mem();
+    let size = mem.get_pod::<u32>(lpVersionInformation);
+    let ex = match size as usize {
+        s if s == std::mem::size_of::<OSVERSIONINFOA>() => false,
+        s if s == std::mem::size_of::<OSVERSIONINFOEXA>() => true,
+        _ => {
+            set_last_error(machine, ERROR::INSUFFICIENT_BUFFER);
+            return false;
+        }
+    };
 
-    1
+    let version = machine.state.kernel32.version;
+    let info = mem.view_mut::<OSVERSIONINFOA>(lpVersionInformation);
+    *info = OSVERSIONINFOA {
+        dwOSVersionInfoSize: size,
+        dwMajorVersion: version.major,
+        dwMinorVersion: version.minor,
+        dwBuildNumber: version.build,
+        dwPlatformId: VER_PLATFORM_WIN32_NT,
+        szCSDVersion: [0; 128],
+    };
+    if ex {
+        let info = mem.view_mut::<OSVERSIONINFOEXA>(lpVersionInformation);
+        info.wServicePackMajor = 0;
+        info.wServicePackMinor = 0;
+        info.wSuiteMask = 0;
+        info.wProductType = VER_NT_WORKSTATION;
+        info.wReserved = 0;
+    }
+    true
 }
 
 #[win32_derive::dllexport]
@@ -145,37 +223,46 @@ pub fn SetHandleCount(_machine: &mut Machine, uNumber: u32) -> u32 {
     uNumber
 }
 
-#[win32_derive::dllexport]
-pub fn OutputDebugStringA(_machine: &mut Machine, msg: Option<&str>) -> u32 {
-    log::warn!("OutputDebugStringA: {:?}", msg);
-    0
-}
+/// Longest string OutputDebugString reads, so an unterminated one can't run off the end
+/// of memory.
+const MAX_DEBUG_STRING: u32 = 4 << 10;
 
-#[win32_derive::dllexport]
-pub fn SetUnhandledExceptionFilter(_machine: &mut Machine, _lpTopLevelExceptionFilter: u32) -> u32 {
-    0 // No current handler.
+/// The memory at addr holding at most MAX_DEBUG_STRING units of unit_size bytes.
+fn debug_string_mem<'a>(mem: Mem<'a>, addr: u32, unit_size: u32) -> &'a [u8] {
+    if addr == 0 || addr >= mem.len() {
+        return &[];
+    }
+    let end = addr
+        .saturating_add(MAX_DEBUG_STRING * unit_size)
+        .min(mem.len());
+    mem.slice(addr..end)
 }
 
 #[win32_derive::dllexport]
-pub fn UnhandledExceptionFilter(_machine: &mut Machine, _exceptionInfo: u32) -> u32 {
-    // "The process is being debugged, so the exception should be passed (as second chance) to the application's debugger."
-    0 // EXCEPTION_CONTINUE_SEARCH
+pub fn OutputDebugStringA(machine: &mut Machine, lpOutputString: u32) {
+    let bytes = debug_string_mem(machine.mem(), lpOutputString, 1);
+    let msg: String = bytes
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| char::from_u32(from_ansi(c) as u32).unwrap())
+        .collect();
+    machine.host.debug_string(&msg);
 }
 
 #[win32_derive::dllexport]
-pub fn RaiseException(
-    _machine: &mut Machine,
-    dwExceptionCode: u32,
-    dwExceptionFlags: u32,
-    nNumberOfArguments: u32,
-    lpArguments: u32,
-) {
-    todo!();
+pub fn OutputDebugStringW(machine: &mut Machine, lpOutputString: u32) {
+    let bytes = debug_string_mem(machine.mem(), lpOutputString, 2);
+    let units = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&c| c != 0)
+        .collect::<Vec<_>>();
+    machine.host.debug_string(&String::from_utf16_lossy(&units));
 }
 
 #[win32_derive::dllexport]
 pub fn NtCurrentTeb(machine: &mut Machine) -> u32 {
-    machine.state.kernel32.teb
+    super::current_thread(machine).teb
 }
 
 // TODO: this has a bunch of synchronization magic that I haven't implemented,
@@ -195,12 +282,8 @@ pub fn InitializeSListHead(_machine: &mut Machine, ListHead: Option<&mut SLIST_H
 }
 
 #[win32_derive::dllexport]
-pub fn SetPriorityClass(
-    _machine: &mut Machine,
-    hProcess: HANDLE<()>,
-    dwPriorityClass: u32,
-) -> bool {
-    true // success
+pub fn SetPriorityClass(machine: &mut Machine, hProcess: HANDLE<()>, dwPriorityClass: u32) -> bool {
+    check_process(machine, hProcess)
 }
 
 #[win32_derive::dllexport]
@@ -269,7 +352,24 @@ pub fn FormatMessageW(
 
 #[win32_derive::dllexport]
 pub fn CloseHandle(machine: &mut Machine, hObject: HFILE) -> bool {
-    if machine.state.kernel32.files.remove(hObject).is_none() {
+    if matches!(hObject, STDIN_HFILE | STDOUT_HFILE | STDERR_HFILE) {
+        // The std handles are owned by the host and outlive the process.
+        set_last_error(machine, ERROR::SUCCESS);
+        return true;
+    }
+    let kernel32 = &mut machine.state.kernel32;
+    let known = kernel32.files.remove(hObject).is_some()
+        || kernel32
+            .sync_objects
+            .remove(HSYNC::from_raw(hObject.raw))
+            .is_some()
+        || kernel32
+            .file_mappings
+            .remove(HFILEMAPPING::from_raw(hObject.raw))
+            .is_some()
+        // Thread records are kept after their handle is closed, as the handle is also the id.
+        || kernel32.threads.get(HTHREAD::from_raw(hObject.raw)).is_some();
+    if !known {
         log::debug!("CloseHandle({hObject:?}): unknown handle");
         set_last_error(machine, ERROR::INVALID_HANDLE);
         return false;
@@ -359,14 +459,3 @@ pub fn MulDiv(_machine: &mut Machine, nNumber: i32, nNumerator: i32, nDenominato
 
     result as i32
 }
-
-#[win32_derive::dllexport]
-pub fn RtlUnwind(
-    _machine: &mut Machine,
-    TargetFrame: u32,
-    TargetIp: u32,
-    ExceptionRecord: u32,
-    ReturnValue: u32,
-) {
-    todo!();
-}
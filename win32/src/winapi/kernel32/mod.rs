@@ -5,6 +5,7 @@ mod console;
 mod dll;
 mod env;
 mod file;
+mod file_mapping;
 mod ini;
 mod init;
 mod libc;
@@ -12,6 +13,7 @@ mod memory;
 mod misc;
 mod nls;
 mod resource;
+mod seh;
 mod sync;
 mod thread;
 mod time;
@@ -21,12 +23,14 @@ pub use console::*;
 pub use dll::*;
 pub use env::*;
 pub use file::*;
+pub use file_mapping::*;
 pub use ini::*;
 pub use init::*;
 pub use libc::*;
 pub use misc::*;
 pub use nls::*;
 pub use resource::*;
+pub use seh::*;
 pub use sync::*;
 pub use thread::*;
 pub use time::*;
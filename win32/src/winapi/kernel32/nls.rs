@@ -1,7 +1,10 @@
 //! "National Language Support", e.g. code page conversions.
 
-use crate::{winapi::stack_args::ArrayWithSizeMut, Machine};
-use memory::Extensions;
+use crate::{
+    winapi::{kernel32::set_last_error, stack_args::ArrayWithSizeMut, ERROR},
+    Machine,
+};
+use memory::{Extensions, ExtensionsMut, Mem};
 
 const TRACE_CONTEXT: &'static str = "kernel32/nls";
 
@@ -15,6 +18,93 @@ pub enum CP {
     UTF8 = 65001,
 }
 
+/// Windows-1252 characters 0x80..=0x9F, which differ from Latin-1.
+/// (Unassigned ones map to the same-valued C1 control character.)
+const CP1252_HIGH: [u16; 32] = [
+    0x20AC, 0x0081, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, //
+    0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0x008D, 0x017D, 0x008F, //
+    0x0090, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, //
+    0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0x009D, 0x017E, 0x0178, //
+];
+
+/// Convert a UTF-16 code unit to the ANSI code page (which we fix as windows-1252),
+/// substituting '?' for unrepresentable characters as WideCharToMultiByte does.
+pub fn to_ansi(c: u16) -> u8 {
+    match c {
+        0..=0x7F | 0xA0..=0xFF => c as u8,
+        _ => match CP1252_HIGH.iter().position(|&hi| hi == c) {
+            Some(i) => 0x80 + i as u8,
+            None => b'?',
+        },
+    }
+}
+
+/// Convert an ANSI code page byte to UTF-16; the inverse of to_ansi.
+pub fn from_ansi(c: u8) -> u16 {
+    match c {
+        0x80..=0x9F => CP1252_HIGH[(c - 0x80) as usize],
+        _ => c as u16,
+    }
+}
+
+/// Read a nul-terminated string in the ANSI code page.
+pub fn read_ansi(mem: Mem, addr: u32) -> Option<String> {
+    if addr == 0 {
+        return None;
+    }
+    Some(
+        mem.slicez(addr)
+            .iter()
+            .map(|&c| char::from_u32(from_ansi(c) as u32).unwrap())
+            .collect(),
+    )
+}
+
+/// Decode bytes in the given code page to UTF-16.
+fn decode(cp: &CP, bytes: &[u8]) -> Vec<u16> {
+    match cp {
+        CP::UTF8 => String::from_utf8_lossy(bytes).encode_utf16().collect(),
+        CP::ACP | CP::OEMCP | CP::WINDOWS_1252 => bytes.iter().map(|&c| from_ansi(c)).collect(),
+    }
+}
+
+/// Encode UTF-16 in the given code page, returning the bytes and whether
+/// default_char had to be substituted for anything.
+fn encode(cp: &CP, chars: &[u16], default_char: Option<u8>) -> (Vec<u8>, bool) {
+    match cp {
+        CP::UTF8 => (String::from_utf16_lossy(chars).into_bytes(), false),
+        CP::ACP | CP::OEMCP | CP::WINDOWS_1252 => {
+            let mut used_default = false;
+            let bytes = chars
+                .iter()
+                .map(|&c| match to_ansi(c) {
+                    b'?' if c != b'?' as u16 => {
+                        used_default = true;
+                        default_char.unwrap_or(b'?')
+                    }
+                    b => b,
+                })
+                .collect();
+            (bytes, used_default)
+        }
+    }
+}
+
+/// Read a UTF-16 string of len code units, or through its nul terminator if len is -1.
+fn read_wide(mem: Mem, addr: u32, len: i32) -> Vec<u16> {
+    if len >= 0 {
+        return mem.iter_pod::<u16>(addr, len as u32).collect();
+    }
+    let mut chars = Vec::new();
+    loop {
+        let c = mem.get_pod::<u16>(addr + chars.len() as u32 * 2);
+        chars.push(c);
+        if c == 0 {
+            return chars;
+        }
+    }
+}
+
 #[win32_derive::dllexport]
 pub fn GetACP(_machine: &mut Machine) -> u32 {
     1252 // windows-1252
@@ -44,37 +134,41 @@ pub fn MultiByteToWideChar(
     cbMultiByte: i32,
     lpWideCharStr: ArrayWithSizeMut<u16>,
 ) -> u32 {
-    match CodePage {
-        Err(value) => unimplemented!("MultiByteToWideChar code page {value}"),
-        _ => {} // treat all others as ansi for now
-    }
-    // TODO: dwFlags
-
-    let input_len = match cbMultiByte {
-        0 => return 0,                                               // TODO: invalid param
-        -1 => machine.mem().slicez(lpMultiByteStr).len() as u32 + 1, // include nul
-        len => len as u32,
+    let cp = match CodePage {
+        Ok(cp) => cp,
+        Err(value) => {
+            log::warn!("MultiByteToWideChar: unsupported code page {value}");
+            set_last_error(machine, ERROR::INVALID_PARAMETER);
+            return 0;
+        }
     };
+    // TODO: dwFlags
 
-    let mut lpWideCharStr = lpWideCharStr.to_option();
-    match lpWideCharStr {
-        Some(buf) if buf.len() == 0 => lpWideCharStr = None,
-        _ => (),
+    let input = match cbMultiByte {
+        0 => {
+            set_last_error(machine, ERROR::INVALID_PARAMETER);
+            return 0;
+        }
+        // Include the nul, so that it's converted too.
+        -1 => {
+            let len = machine.mem().slicez(lpMultiByteStr).len() as u32 + 1;
+            machine.mem().sub32(lpMultiByteStr, len)
+        }
+        len => machine.mem().sub32(lpMultiByteStr, len as u32),
     };
+    let output = decode(&cp, input);
 
-    match lpWideCharStr {
-        None => input_len,
+    match lpWideCharStr.to_option() {
+        // cchWideChar == 0: just report the required size.
+        None => output.len() as u32,
+        Some(buf) if buf.len() == 0 => output.len() as u32,
         Some(buf) => {
-            let input = machine.mem().sub32(lpMultiByteStr, input_len);
-            let mut len = 0;
-            for (&c_in, c_out) in std::iter::zip(input, buf) {
-                if c_in > 0x7f {
-                    unimplemented!("unicode");
-                }
-                *c_out = c_in as u16;
-                len += 1;
+            if buf.len() < output.len() {
+                set_last_error(machine, ERROR::INSUFFICIENT_BUFFER);
+                return 0;
             }
-            len
+            buf[..output.len()].copy_from_slice(&output);
+            output.len() as u32
         }
     }
 }
@@ -88,9 +182,45 @@ pub fn WideCharToMultiByte(
     cchWideChar: i32,
     lpMultiByteStr: u32,
     cbMultiByte: i32,
+    lpDefaultChar: Option<&u8>,
     lpUsedDefaultChar: Option<&mut u32>,
 ) -> u32 {
-    todo!()
+    let cp = match CodePage {
+        Ok(cp) => cp,
+        Err(value) => {
+            log::warn!("WideCharToMultiByte: unsupported code page {value}");
+            set_last_error(machine, ERROR::INVALID_PARAMETER);
+            return 0;
+        }
+    };
+    // TODO: dwFlags
+    if cchWideChar == 0
+        || cbMultiByte < 0
+        || (matches!(cp, CP::UTF8) && (lpDefaultChar.is_some() || lpUsedDefaultChar.is_some()))
+    {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return 0;
+    }
+
+    let input = read_wide(machine.mem(), lpWideCharStr, cchWideChar);
+    let (output, used_default) = encode(&cp, &input, lpDefaultChar.copied());
+    if let Some(lpUsedDefaultChar) = lpUsedDefaultChar {
+        *lpUsedDefaultChar = used_default as u32;
+    }
+
+    if cbMultiByte == 0 {
+        // Just report the required size.
+        return output.len() as u32;
+    }
+    if (cbMultiByte as usize) < output.len() {
+        set_last_error(machine, ERROR::INSUFFICIENT_BUFFER);
+        return 0;
+    }
+    machine
+        .mem()
+        .sub32_mut(lpMultiByteStr, output.len() as u32)
+        .copy_from_slice(&output);
+    output.len() as u32
 }
 
 #[win32_derive::dllexport]
@@ -152,3 +282,44 @@ pub fn GetStringTypeW(
 ) -> bool {
     todo!();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ansi() {
+        let bytes = b"a\x80\xe9\x9f";
+        let wide = decode(&CP::ACP, bytes);
+        assert_eq!(wide, [0x61, 0x20AC, 0xE9, 0x178]);
+        assert_eq!(encode(&CP::ACP, &wide, None), (bytes.to_vec(), false));
+        assert_eq!(
+            encode(&CP::ACP, &[0x3B1, 0x3F], Some(b'#')),
+            (b"#?".to_vec(), true)
+        );
+    }
+
+    #[test]
+    fn test_utf8() {
+        let wide = decode(&CP::UTF8, "h\u{e9}\u{1F600}".as_bytes());
+        assert_eq!(wide, [0x68, 0xE9, 0xD83D, 0xDE00]);
+        assert_eq!(
+            encode(&CP::UTF8, &wide, None).0,
+            "h\u{e9}\u{1F600}".as_bytes()
+        );
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_unknown_code_page() {
+        let mut machine = crate::testing::machine();
+        assert_eq!(
+            WideCharToMultiByte(&mut machine, Err(437), 0, 0, -1, 0, 0, None, None),
+            0
+        );
+        assert_eq!(
+            crate::winapi::kernel32::GetLastError(&mut machine),
+            ERROR::INVALID_PARAMETER as u32
+        );
+    }
+}
@@ -6,7 +6,7 @@ use crate::winapi::user32::HINSTANCE;
 use crate::{
     pe,
     winapi::{
-        kernel32,
+        self, kernel32,
         stack_args::FromArg,
         types::{Str16, String16},
     },
@@ -21,6 +21,7 @@ fn IS_INTRESOURCE(x: u32) -> bool {
     x >> 16 == 0
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct ResourceHandle(Range<u32>);
 
 /// ResourceKey is the type of queries into the Windows resources system, including
@@ -126,7 +127,10 @@ pub fn FindResourceW(
         lpType,
         lpName,
     ) {
-        None => HRSRC::null(),
+        None => {
+            kernel32::set_last_error(machine, winapi::ERROR::RESOURCE_NAME_NOT_FOUND);
+            HRSRC::null()
+        }
         Some(mem) => machine
             .state
             .kernel32
@@ -0,0 +1,698 @@
+//! Structured exception handling: dispatching faults and RaiseException to the
+//! handlers registered in the fs:[0] chain.
+
+#[cfg(feature = "x86-emu")]
+use super::{teb, teb_mut};
+use crate::Machine;
+use memory::Pod;
+
+const TRACE_CONTEXT: &'static str = "kernel32/seh";
+
+pub const EXCEPTION_ACCESS_VIOLATION: u32 = 0xC000_0005;
+pub const EXCEPTION_INT_DIVIDE_BY_ZERO: u32 = 0xC000_0094;
+pub const STATUS_UNWIND: u32 = 0xC000_0027;
+
+pub const EXCEPTION_NONCONTINUABLE: u32 = 0x1;
+pub const EXCEPTION_UNWINDING: u32 = 0x2;
+pub const EXCEPTION_EXIT_UNWIND: u32 = 0x4;
+
+const EXCEPTION_MAXIMUM_PARAMETERS: usize = 15;
+
+/// Handler of the placeholder outermost frame that init_teb installs; reaching it
+/// means no program handler wanted the exception.
+pub const PLACEHOLDER_HANDLER: u32 = 0xFF5E_5EFF; // Hopefully easier to spot.
+
+/// Marks the end of the fs:[0] chain.
+#[cfg(feature = "x86-emu")]
+const END_OF_CHAIN: u32 = 0xFFFF_FFFF;
+
+// EXCEPTION_DISPOSITION, as returned by handlers.
+#[cfg(feature = "x86-emu")]
+const EXCEPTION_CONTINUE_EXECUTION: u32 = 0;
+#[cfg(feature = "x86-emu")]
+const EXCEPTION_CONTINUE_SEARCH: u32 = 1;
+
+// Return values of exception filters.
+#[cfg(feature = "x86-emu")]
+const EXCEPTION_CONTINUE_EXECUTION_FILTER: i32 = -1;
+const EXCEPTION_EXECUTE_HANDLER: u32 = 1;
+
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct EXCEPTION_RECORD {
+    pub ExceptionCode: u32,
+    pub ExceptionFlags: u32,
+    pub ExceptionRecord: u32,
+    pub ExceptionAddress: u32,
+    pub NumberParameters: u32,
+    pub ExceptionInformation: [u32; EXCEPTION_MAXIMUM_PARAMETERS],
+}
+unsafe impl Pod for EXCEPTION_RECORD {}
+
+impl EXCEPTION_RECORD {
+    pub fn new(code: u32, flags: u32, address: u32, params: &[u32]) -> Self {
+        let mut record = EXCEPTION_RECORD::zeroed();
+        record.ExceptionCode = code;
+        record.ExceptionFlags = flags;
+        record.ExceptionAddress = address;
+        let count = params.len().min(EXCEPTION_MAXIMUM_PARAMETERS);
+        record.NumberParameters = count as u32;
+        record.ExceptionInformation[..count].copy_from_slice(&params[..count]);
+        record
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct FLOATING_SAVE_AREA {
+    pub ControlWord: u32,
+    pub StatusWord: u32,
+    pub TagWord: u32,
+    pub ErrorOffset: u32,
+    pub ErrorSelector: u32,
+    pub DataOffset: u32,
+    pub DataSelector: u32,
+    pub RegisterArea: [u8; 80],
+    pub Cr0NpxState: u32,
+}
+unsafe impl Pod for FLOATING_SAVE_AREA {}
+
+pub const CONTEXT_I386: u32 = 0x10000;
+pub const CONTEXT_CONTROL: u32 = CONTEXT_I386 | 0x1;
+pub const CONTEXT_INTEGER: u32 = CONTEXT_I386 | 0x2;
+pub const CONTEXT_SEGMENTS: u32 = CONTEXT_I386 | 0x4;
+
+/// x86 register state, as seen and modified by exception handlers.
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct CONTEXT {
+    pub ContextFlags: u32,
+    pub Dr0: u32,
+    pub Dr1: u32,
+    pub Dr2: u32,
+    pub Dr3: u32,
+    pub Dr6: u32,
+    pub Dr7: u32,
+    pub FloatSave: FLOATING_SAVE_AREA,
+    pub SegGs: u32,
+    pub SegFs: u32,
+    pub SegEs: u32,
+    pub SegDs: u32,
+    pub Edi: u32,
+    pub Esi: u32,
+    pub Ebx: u32,
+    pub Edx: u32,
+    pub Ecx: u32,
+    pub Eax: u32,
+    pub Ebp: u32,
+    pub Eip: u32,
+    pub SegCs: u32,
+    pub EFlags: u32,
+    pub Esp: u32,
+    pub SegSs: u32,
+    pub ExtendedRegisters: [u8; 512],
+}
+unsafe impl Pod for CONTEXT {}
+
+#[cfg(feature = "x86-emu")]
+impl CONTEXT {
+    pub fn capture(cpu: &x86::CPU) -> Self {
+        use x86::Register::*;
+        let mut context = CONTEXT::zeroed();
+        context.ContextFlags = CONTEXT_CONTROL | CONTEXT_INTEGER | CONTEXT_SEGMENTS;
+        let regs = &cpu.regs;
+        context.SegGs = regs.get16(GS) as u32;
+        context.SegFs = regs.get16(FS) as u32;
+        context.SegEs = regs.get16(ES) as u32;
+        context.SegDs = regs.get16(DS) as u32;
+        context.Edi = regs.get32(EDI);
+        context.Esi = regs.get32(ESI);
+        context.Ebx = regs.get32(EBX);
+        context.Edx = regs.get32(EDX);
+        context.Ecx = regs.get32(ECX);
+        context.Eax = regs.get32(EAX);
+        context.Ebp = regs.get32(EBP);
+        context.Eip = regs.eip;
+        context.SegCs = regs.get16(CS) as u32;
+        context.EFlags = cpu.flags.bits();
+        context.Esp = regs.get32(ESP);
+        context.SegSs = regs.get16(SS) as u32;
+        // TODO: FPU state.
+        context
+    }
+
+    pub fn restore(&self, cpu: &mut x86::CPU) {
+        use x86::Register::*;
+        let regs = &mut cpu.regs;
+        regs.set32(EDI, self.Edi);
+        regs.set32(ESI, self.Esi);
+        regs.set32(EBX, self.Ebx);
+        regs.set32(EDX, self.Edx);
+        regs.set32(ECX, self.Ecx);
+        regs.set32(EAX, self.Eax);
+        regs.set32(EBP, self.Ebp);
+        regs.set32(ESP, self.Esp);
+        regs.eip = self.Eip;
+        cpu.flags = x86::Flags::from_bits_truncate(self.EFlags);
+        // Segment registers are left alone; changing them isn't meaningful for us.
+    }
+}
+
+#[cfg(feature = "x86-emu")]
+impl EXCEPTION_RECORD {
+    pub fn from_fault(fault: &x86::Fault, eip: u32) -> Self {
+        match *fault {
+            x86::Fault::DivideError => {
+                EXCEPTION_RECORD::new(EXCEPTION_INT_DIVIDE_BY_ZERO, 0, eip, &[])
+            }
+            x86::Fault::AccessViolation { addr, write } => {
+                EXCEPTION_RECORD::new(EXCEPTION_ACCESS_VIOLATION, 0, eip, &[write as u32, addr])
+            }
+            // Windows reports #GP as an access violation reading address -1.
+            x86::Fault::GeneralProtection => {
+                EXCEPTION_RECORD::new(EXCEPTION_ACCESS_VIOLATION, 0, eip, &[0, 0xFFFF_FFFF])
+            }
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct EXCEPTION_POINTERS {
+    pub ExceptionRecord: u32,
+    pub ContextRecord: u32,
+}
+unsafe impl Pod for EXCEPTION_POINTERS {}
+
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct _EXCEPTION_REGISTRATION_RECORD {
+    pub Prev: u32,
+    pub Handler: u32,
+}
+unsafe impl Pod for _EXCEPTION_REGISTRATION_RECORD {}
+
+/// Copy a value onto the x86 stack, returning its address.
+#[cfg(feature = "x86-emu")]
+fn push_pod<T: Clone + Pod>(machine: &mut Machine, val: T) -> u32 {
+    use memory::ExtensionsMut;
+    let cpu = machine.emu.x86.cpu_mut();
+    let esp = (cpu.regs.get32(x86::Register::ESP) - std::mem::size_of::<T>() as u32) & !3;
+    cpu.regs.set32(x86::Register::ESP, esp);
+    machine.mem().put_pod::<T>(esp, val);
+    esp
+}
+
+/// Walk the fs:[0] chain, offering the exception to each handler in turn, then to the
+/// unhandled exception filter.  Returns the (possibly handler-modified) context to resume
+/// with if some handler asked to continue execution, or None if nobody handled it.
+///
+/// Handlers that catch an exception typically don't return here at all, but instead
+/// call RtlUnwind and jump into their except block; see abandon_dispatch.
+#[cfg(feature = "x86-emu")]
+pub async fn dispatch_exception(
+    machine: &mut Machine,
+    record: EXCEPTION_RECORD,
+    context: CONTEXT,
+) -> Option<CONTEXT> {
+    use memory::Extensions;
+
+    log::info!(
+        "exception {:#x} at {:#x}",
+        record.ExceptionCode,
+        record.ExceptionAddress
+    );
+    let depth = machine.emu.x86.cpu().async_depth() - 1;
+    machine.state.kernel32.seh_dispatches.push(depth);
+
+    let context_addr = push_pod(machine, context);
+    let record_addr = push_pod(machine, record.clone());
+    let pointers_addr = push_pod(
+        machine,
+        EXCEPTION_POINTERS {
+            ExceptionRecord: record_addr,
+            ContextRecord: context_addr,
+        },
+    );
+
+    let mut handled = false;
+    let mut frame = teb(machine).Tib.ExceptionList;
+    while frame != END_OF_CHAIN {
+        let registration = machine
+            .mem()
+            .get_pod::<_EXCEPTION_REGISTRATION_RECORD>(frame);
+        if registration.Handler == PLACEHOLDER_HANDLER {
+            break;
+        }
+        let mem = machine.emu.memory.mem();
+        let disposition = machine
+            .emu
+            .x86
+            .cpu_mut()
+            .call_x86_cdecl(
+                mem,
+                registration.Handler,
+                vec![record_addr, frame, context_addr, 0],
+            )
+            .await;
+        match disposition {
+            EXCEPTION_CONTINUE_EXECUTION => {
+                handled = true;
+                break;
+            }
+            EXCEPTION_CONTINUE_SEARCH => {}
+            _ => log::warn!("exception handler returned unknown disposition {disposition}"),
+        }
+        frame = registration.Prev;
+    }
+
+    let filter = machine.state.kernel32.unhandled_exception_filter;
+    if !handled && filter != 0 {
+        let mem = machine.emu.memory.mem();
+        let ret = machine
+            .emu
+            .x86
+            .cpu_mut()
+            .call_x86(mem, filter, vec![pointers_addr])
+            .await;
+        handled = ret as i32 == EXCEPTION_CONTINUE_EXECUTION_FILTER;
+    }
+
+    machine.state.kernel32.seh_dispatches.pop();
+    if handled && record.ExceptionFlags & EXCEPTION_NONCONTINUABLE != 0 {
+        log::error!("handler continued a noncontinuable exception");
+        handled = false;
+    }
+    if !handled {
+        return None;
+    }
+    Some(machine.mem().get_pod::<CONTEXT>(context_addr))
+}
+
+/// Called when the stack is unwound past an in-progress dispatch_exception, which
+/// then will never complete; drops it so its futures don't linger.
+#[cfg(feature = "x86-emu")]
+fn abandon_dispatch(machine: &mut Machine) {
+    if let Some(depth) = machine.state.kernel32.seh_dispatches.pop() {
+        machine.emu.x86.cpu_mut().abandon_async(depth);
+    }
+}
+
+/// Dispatch a CPU fault, resuming execution per the handlers or stopping if unhandled.
+/// The context must be captured at the fault, before the CPU is switched over to
+/// running this future.
+#[cfg(feature = "x86-emu")]
+pub async fn dispatch_fault(machine: &mut Machine, fault: x86::Fault, context: CONTEXT) {
+    let record = EXCEPTION_RECORD::from_fault(&fault, context.Eip);
+    match dispatch_exception(machine, record.clone(), context.clone()).await {
+        Some(context) => context.restore(machine.emu.x86.cpu_mut()),
+        None => {
+            // Leave the CPU pointing at the fault for debugging.
+            context.restore(machine.emu.x86.cpu_mut());
+            log::error!("unhandled {fault:x?}");
+            exit_unhandled(machine, &record);
+        }
+    }
+}
+
+/// Terminate the process for an exception that no handler or filter claimed, using
+/// the exception code as the exit code as Windows does.
+#[cfg(feature = "x86-emu")]
+fn exit_unhandled(machine: &mut Machine, record: &EXCEPTION_RECORD) {
+    log::error!(
+        "unhandled exception {:#x} at {:#x}, exiting",
+        record.ExceptionCode,
+        record.ExceptionAddress
+    );
+    machine.exit(record.ExceptionCode);
+}
+
+#[win32_derive::dllexport]
+pub fn SetUnhandledExceptionFilter(machine: &mut Machine, lpTopLevelExceptionFilter: u32) -> u32 {
+    std::mem::replace(
+        &mut machine.state.kernel32.unhandled_exception_filter,
+        lpTopLevelExceptionFilter,
+    )
+}
+
+/// Offers the exception to the filter registered with SetUnhandledExceptionFilter.
+/// With no filter, reports the exception and returns EXCEPTION_EXECUTE_HANDLER, which
+/// tells the caller to terminate the process.
+#[win32_derive::dllexport]
+pub async fn UnhandledExceptionFilter(machine: &mut Machine, exceptionInfo: u32) -> u32 {
+    let filter = machine.state.kernel32.unhandled_exception_filter;
+    if filter != 0 {
+        return machine.call_x86(filter, vec![exceptionInfo]).await;
+    }
+    if exceptionInfo != 0 {
+        use memory::Extensions;
+        let pointers = machine.mem().get_pod::<EXCEPTION_POINTERS>(exceptionInfo);
+        let record = machine
+            .mem()
+            .get_pod::<EXCEPTION_RECORD>(pointers.ExceptionRecord);
+        log::error!(
+            "unhandled exception {:#x} at {:#x}",
+            record.ExceptionCode,
+            record.ExceptionAddress
+        );
+    }
+    EXCEPTION_EXECUTE_HANDLER
+}
+
+/// Raise an exception on behalf of the x86 code that called the current shim, as
+/// RaiseException does, terminating the process if no handler claims it.
+/// arg_bytes is the size of the shim's stdcall arguments.  If a handler continues execution,
+/// the shim's caller resumes with the handler's context; the shim must return the eax
+/// this returns for that to include eax.
+pub async fn raise_from_shim(
+    machine: &mut Machine,
+    code: u32,
+    flags: u32,
+    params: &[u32],
+    arg_bytes: u32,
+) -> u32 {
+    #[cfg(feature = "x86-emu")]
+    {
+        use memory::{Extensions, ExtensionsMut};
+        let cpu = machine.emu.x86.cpu();
+        // See doc/shims.md: the stack holds the return address within the shim DLL, then
+        // the return address within the caller, which is where the exception appears.
+        // The context is that of the caller once the shim returns, though we're within the
+        // shim so the other registers are only an approximation of the caller's.
+        let esp = cpu.regs.get32(x86::Register::ESP);
+        let stub = machine.mem().get_pod::<u32>(esp);
+        let caller = machine.mem().get_pod::<u32>(esp + 4);
+        let mut context = CONTEXT::capture(cpu);
+        context.Eip = caller;
+        context.Esp = esp + 8 + arg_bytes;
+        let record = EXCEPTION_RECORD::new(code, flags & EXCEPTION_NONCONTINUABLE, caller, params);
+        let Some(context) = dispatch_exception(machine, record.clone(), context).await else {
+            machine
+                .emu
+                .x86
+                .cpu_mut()
+                .regs
+                .set32(x86::Register::ESP, esp);
+            exit_unhandled(machine, &record);
+            return 0;
+        };
+
+        // The shim still returns through the DLL stub, which pops its arguments, so lay out
+        // the stack such that it returns to the context's eip with the context's esp.
+        let cpu = machine.emu.x86.cpu_mut();
+        context.restore(cpu);
+        let esp = context.Esp - arg_bytes - 8;
+        cpu.regs.set32(x86::Register::ESP, esp);
+        machine.mem().put_pod::<u32>(esp, stub);
+        machine.mem().put_pod::<u32>(esp + 4, context.Eip);
+        context.Eax
+    }
+
+    #[cfg(not(feature = "x86-emu"))]
+    {
+        _ = (flags, params, arg_bytes);
+        log::error!("exception {code:#x} raised, but SEH dispatch needs x86-emu");
+        machine.exit(code);
+        0
+    }
+}
+
+/// Returns the eax a continuing handler's context asks for; see raise_from_shim.
+#[win32_derive::dllexport]
+pub async fn RaiseException(
+    machine: &mut Machine,
+    dwExceptionCode: u32,
+    dwExceptionFlags: u32,
+    nNumberOfArguments: u32,
+    lpArguments: u32,
+) -> u32 {
+    let params = if lpArguments == 0 {
+        vec![]
+    } else {
+        machine
+            .mem()
+            .view_n::<u32>(lpArguments, nNumberOfArguments)
+            .to_vec()
+    };
+    raise_from_shim(machine, dwExceptionCode, dwExceptionFlags, &params, 16).await
+}
+
+/// Call the handlers of the frames above TargetFrame for unwinding, and pop them from
+/// the chain.  MSVC exception handlers call this before transferring control to the
+/// except block.
+#[win32_derive::dllexport]
+pub async fn RtlUnwind(
+    machine: &mut Machine,
+    TargetFrame: u32,
+    TargetIp: u32,
+    ExceptionRecord: u32,
+    ReturnValue: u32,
+) -> u32 {
+    #[cfg(feature = "x86-emu")]
+    {
+        use memory::Extensions;
+        let esp = machine.emu.x86.cpu().regs.get32(x86::Register::ESP);
+
+        let mut flags = EXCEPTION_UNWINDING;
+        if TargetFrame == 0 {
+            flags |= EXCEPTION_EXIT_UNWIND;
+        }
+        let record_addr = if ExceptionRecord == 0 {
+            push_pod(
+                machine,
+                EXCEPTION_RECORD::new(STATUS_UNWIND, flags, TargetIp, &[]),
+            )
+        } else {
+            let record = machine.mem().view_mut::<EXCEPTION_RECORD>(ExceptionRecord);
+            record.ExceptionFlags |= flags;
+            ExceptionRecord
+        };
+        let context_addr = push_pod(machine, CONTEXT::zeroed());
+
+        let mut frame = teb(machine).Tib.ExceptionList;
+        while frame != TargetFrame && frame != END_OF_CHAIN {
+            let registration = machine
+                .mem()
+                .get_pod::<_EXCEPTION_REGISTRATION_RECORD>(frame);
+            if registration.Handler == PLACEHOLDER_HANDLER {
+                break;
+            }
+            let mem = machine.emu.memory.mem();
+            machine
+                .emu
+                .x86
+                .cpu_mut()
+                .call_x86_cdecl(
+                    mem,
+                    registration.Handler,
+                    vec![record_addr, frame, context_addr, 0],
+                )
+                .await;
+            frame = registration.Prev;
+            teb_mut(machine).Tib.ExceptionList = frame;
+        }
+
+        machine
+            .emu
+            .x86
+            .cpu_mut()
+            .regs
+            .set32(x86::Register::ESP, esp);
+        abandon_dispatch(machine);
+        ReturnValue
+    }
+
+    #[cfg(not(feature = "x86-emu"))]
+    {
+        // Without x86-emu we can't call the handlers, but can at least pop their frames.
+        _ = (TargetIp, ExceptionRecord);
+        log::warn!("RtlUnwind: skipping unwind handlers, which need x86-emu");
+        if TargetFrame != 0 {
+            super::teb_mut(machine).Tib.ExceptionList = TargetFrame;
+        }
+        ReturnValue
+    }
+}
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use super::*;
+    use crate::{testing, winapi::kernel32::get_kernel32_builtin};
+    use memory::Extensions;
+
+    /// push handler; push dword fs:[0]; mov fs:[0], esp
+    fn push_frame(code: &mut Vec<u8>, handler: u32) {
+        code.push(0x68);
+        code.extend_from_slice(&handler.to_le_bytes());
+        code.extend_from_slice(&[0x64, 0xff, 0x35, 0, 0, 0, 0]);
+        code.extend_from_slice(&[0x64, 0x89, 0x25, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn fault_continue_execution() {
+        let mut machine = testing::machine();
+
+        // Fix up the divisor in the context and retry the faulting instruction.
+        // mov eax, [esp+0xc]; mov dword [eax+CONTEXT.Ecx], 2; xor eax, eax; ret
+        let ecx_offset = std::mem::offset_of!(CONTEXT, Ecx) as u32;
+        let mut handler = vec![0x8b, 0x44, 0x24, 0x0c, 0xc7, 0x80];
+        handler.extend_from_slice(&ecx_offset.to_le_bytes());
+        handler.extend_from_slice(&[2, 0, 0, 0, 0x31, 0xc0, 0xc3]);
+        let handler = testing::alloc_code(&mut machine, &handler);
+
+        let mut code = vec![];
+        push_frame(&mut code, handler);
+        code.extend_from_slice(&[
+            0xb8, 10, 0, 0, 0, // mov eax, 10
+            0x31, 0xc9, // xor ecx, ecx
+            0x31, 0xd2, // xor edx, edx
+            0xf7, 0xf1, // div ecx
+            0x64, 0x8f, 0x05, 0, 0, 0, 0, // pop dword fs:[0]
+            0x83, 0xc4, 0x04, // add esp, 4
+            0xc3, // ret
+        ]);
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        assert_eq!(testing::run_exe(&mut machine, entry), 5);
+    }
+
+    #[test]
+    fn raise_and_unwind() {
+        let mut machine = testing::machine();
+        let calls = testing::alloc_data(&mut machine, &[0; 4]);
+        let calls_bytes = calls.to_le_bytes();
+        let raise_exception = get_kernel32_builtin(&mut machine, "RaiseException");
+        let rtl_unwind = get_kernel32_builtin(&mut machine, "RtlUnwind");
+
+        // Inner handler: count calls, declining the exception.
+        // inc dword [calls]; mov eax, EXCEPTION_CONTINUE_SEARCH; ret
+        let mut inner = vec![0xff, 0x05];
+        inner.extend_from_slice(&calls_bytes);
+        inner.extend_from_slice(&[0xb8, 1, 0, 0, 0, 0xc3]);
+        let inner = testing::alloc_code(&mut machine, &inner);
+
+        // Outer handler: unwind to its own frame, then resume after it, as MSVC's
+        // __except does, returning the count from the entry point.
+        let mut outer = vec![
+            0x8b, 0x5c, 0x24, 0x08, // mov ebx, [esp+8] (frame)
+            0x8b, 0x44, 0x24, 0x04, // mov eax, [esp+4] (record)
+            0x6a, 0x00, // push 0
+            0x50, // push eax
+            0x6a, 0x00, // push 0
+            0x53, // push ebx
+            0xb8, // mov eax, RtlUnwind
+        ];
+        outer.extend_from_slice(&rtl_unwind.to_le_bytes());
+        outer.extend_from_slice(&[
+            0xff, 0xd0, // call eax
+            0x89, 0xdc, // mov esp, ebx
+            0x64, 0x8f, 0x05, 0, 0, 0, 0, // pop dword fs:[0]
+            0x83, 0xc4, 0x04, // add esp, 4
+            0xa1, // mov eax, [calls]
+        ]);
+        outer.extend_from_slice(&calls_bytes);
+        outer.push(0xc3); // ret
+        let outer = testing::alloc_code(&mut machine, &outer);
+
+        let mut code = vec![];
+        push_frame(&mut code, outer);
+        push_frame(&mut code, inner);
+        // RaiseException(0xE0000001, 0, 0, NULL)
+        code.extend_from_slice(&[
+            0x6a, 0x00, 0x6a, 0x00, 0x6a, 0x00, 0x68, 1, 0, 0, 0xe0, 0xb8,
+        ]);
+        code.extend_from_slice(&raise_exception.to_le_bytes());
+        code.extend_from_slice(&[0xff, 0xd0]); // call eax
+        code.extend_from_slice(&[0xb8, 0xad, 0xde, 0, 0, 0xc3]); // mov eax, 0xdead; ret
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        // The inner handler saw the exception once while searching and once while unwinding.
+        assert_eq!(testing::run_exe(&mut machine, entry), 2);
+        assert!(machine.state.kernel32.seh_dispatches.is_empty());
+    }
+
+    #[test]
+    fn raise_continue_execution() {
+        let mut machine = testing::machine();
+        let raise_exception = get_kernel32_builtin(&mut machine, "RaiseException");
+
+        // Continue with eax = 7 and ebx = 42 in the context.
+        // mov eax, [esp+0xc]; mov dword [eax+CONTEXT.Eax], 7; mov dword [eax+CONTEXT.Ebx], 42
+        let mut handler = vec![0x8b, 0x44, 0x24, 0x0c];
+        for (offset, value) in [
+            (std::mem::offset_of!(CONTEXT, Eax), 7u32),
+            (std::mem::offset_of!(CONTEXT, Ebx), 42),
+        ] {
+            handler.extend_from_slice(&[0xc7, 0x80]);
+            handler.extend_from_slice(&(offset as u32).to_le_bytes());
+            handler.extend_from_slice(&value.to_le_bytes());
+        }
+        handler.extend_from_slice(&[0x31, 0xc0, 0xc3]); // xor eax, eax; ret
+        let handler = testing::alloc_code(&mut machine, &handler);
+
+        let mut code = vec![0x31, 0xdb]; // xor ebx, ebx
+        push_frame(&mut code, handler);
+        for arg in [0, 0, 0, 0xE000_0001] {
+            testing::push(&mut code, arg);
+        }
+        testing::call(&mut code, raise_exception);
+        code.extend_from_slice(&[
+            0x01, 0xd8, // add eax, ebx
+            0x64, 0x8f, 0x05, 0, 0, 0, 0, // pop dword fs:[0]
+            0x83, 0xc4, 0x04, // add esp, 4
+            0xc3, // ret
+        ]);
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        // Popping the frame also relies on the context's esp being the caller's.
+        assert_eq!(testing::run_exe(&mut machine, entry), 49);
+    }
+
+    /// Run code raising an exception with the given flags and no handlers, with an unhandled
+    /// exception filter returning filter_ret, if any.  Returns the exit code and how many
+    /// times the filter ran.
+    fn raise_unhandled(flags: u32, filter_ret: Option<u32>) -> (u32, u32) {
+        let mut machine = testing::machine();
+        let calls = testing::alloc_data(&mut machine, &[0; 4]);
+        let raise_exception = get_kernel32_builtin(&mut machine, "RaiseException");
+        let set_filter = get_kernel32_builtin(&mut machine, "SetUnhandledExceptionFilter");
+
+        let mut code = vec![];
+        if let Some(ret) = filter_ret {
+            // inc dword [calls]; mov eax, ret; ret 4
+            let mut filter = vec![0xff, 0x05];
+            filter.extend_from_slice(&calls.to_le_bytes());
+            filter.push(0xb8);
+            filter.extend_from_slice(&ret.to_le_bytes());
+            filter.extend_from_slice(&[0xc2, 4, 0]);
+            let filter = testing::alloc_code(&mut machine, &filter);
+            testing::push(&mut code, filter);
+            testing::call(&mut code, set_filter);
+        }
+        for arg in [0, 0, flags, 0xE000_0002] {
+            testing::push(&mut code, arg);
+        }
+        testing::call(&mut code, raise_exception);
+        code.extend_from_slice(&[0xb8, 5, 0, 0, 0, 0xc3]); // mov eax, 5; ret
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        let exit_code = testing::run_exe(&mut machine, entry);
+        (exit_code, machine.mem().get_pod::<u32>(calls))
+    }
+
+    #[test]
+    fn unhandled_exception() {
+        // With nothing to handle it, the exception code becomes the exit code.
+        assert_eq!(raise_unhandled(0, None), (0xE000_0002, 0));
+        assert_eq!(
+            raise_unhandled(0, Some(EXCEPTION_EXECUTE_HANDLER)),
+            (0xE000_0002, 1)
+        );
+        // The filter can continue execution, but not of a noncontinuable exception.
+        assert_eq!(raise_unhandled(0, Some(0xFFFF_FFFF)), (5, 1));
+        assert_eq!(
+            raise_unhandled(EXCEPTION_NONCONTINUABLE, Some(0xFFFF_FFFF)),
+            (0xE000_0002, 1)
+        );
+    }
+}
@@ -1,16 +1,276 @@
-//! Synchronization.  Currently all no-ops as we don't support threads.
+//! Synchronization objects and waiting on them.
+//!
+//! Threads are cooperatively scheduled, so a waiting thread blocks in the scheduler until an
+//! object it waits on changes state or its timeout passes, at which point it rechecks.
 
-use crate::{winapi::types::HEVENT, Machine};
+use super::{current_thread, current_thread_mut, set_last_error, thread_signaled, HTHREAD};
+use crate::{
+    pe::ImageSectionFlags,
+    winapi::{handle::Handles, types::*, ERROR},
+    Machine,
+};
+use memory::Extensions;
+use std::collections::VecDeque;
 
 const TRACE_CONTEXT: &'static str = "kernel32/misc";
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct HMUTEXT;
+pub type HMUTEX = HANDLE<HMUTEXT>;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct HSYNCT;
+/// Handle to any SyncObject; events and mutexes share one handle space.
+pub type HSYNC = HANDLE<HSYNCT>;
+
+/// First sync object handle, kept apart from file and thread handles.
+pub const SYNC_HANDLE_BASE: u32 = 0x2000;
+
+const INFINITE: u32 = 0xFFFF_FFFF;
+const WAIT_OBJECT_0: u32 = 0;
+const WAIT_ABANDONED_0: u32 = 0x80;
+const WAIT_TIMEOUT: u32 = 0x102;
+const WAIT_FAILED: u32 = 0xFFFF_FFFF;
+const MAXIMUM_WAIT_OBJECTS: u32 = 64;
+
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct EventObject {
+    manual_reset: bool,
     state: bool,
+    /// Threads in a wait-any wait on this event, longest waiting first, for PulseEvent.
+    waiters: VecDeque<HTHREAD>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct MutexObject {
+    /// Owning thread and how many times it has acquired the mutex.
+    owner: Option<(HTHREAD, u32)>,
+    /// Set when the owner exited without releasing it; reported to the next acquirer.
+    abandoned: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum SyncObject {
+    Event(EventObject),
+    Mutex(MutexObject),
+}
+
+pub type SyncObjects = Handles<HSYNC, SyncObject>;
+
+/// A thread's in-progress WaitFor*Object(s) call.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Wait {
+    handles: Vec<u32>,
+    /// Set by PulseEvent to the handle that released this wait.
+    pulsed: Option<u32>,
+}
+
+fn event_mut(machine: &mut Machine, hEvent: HEVENT) -> Option<&mut EventObject> {
+    match machine
+        .state
+        .kernel32
+        .sync_objects
+        .get_mut(HSYNC::from_raw(hEvent.raw))
+    {
+        Some(SyncObject::Event(event)) => Some(event),
+        _ => None,
+    }
+}
+
+/// Whether a waitable handle is signaled for the current thread, or None for an unknown handle.
+fn is_signaled(machine: &Machine, handle: u32) -> Option<bool> {
+    if let Some(signaled) = thread_signaled(machine, HTHREAD::from_raw(handle)) {
+        return Some(signaled);
+    }
+    let current = current_thread(machine).handle;
+    match machine
+        .state
+        .kernel32
+        .sync_objects
+        .get(HSYNC::from_raw(handle))?
+    {
+        SyncObject::Event(event) => Some(event.state),
+        SyncObject::Mutex(mutex) => Some(match mutex.owner {
+            None => true,
+            Some((owner, _)) => owner == current,
+        }),
+    }
+}
+
+/// Consume the signal of a signaled object, returning whether it was an abandoned mutex.
+fn acquire(machine: &mut Machine, handle: u32) -> bool {
+    let current = current_thread(machine).handle;
+    match machine
+        .state
+        .kernel32
+        .sync_objects
+        .get_mut(HSYNC::from_raw(handle))
+    {
+        Some(SyncObject::Event(event)) => {
+            if !event.manual_reset {
+                event.state = false;
+            }
+            false
+        }
+        Some(SyncObject::Mutex(mutex)) => {
+            let count = mutex.owner.map_or(0, |(_, count)| count);
+            mutex.owner = Some((current, count + 1));
+            std::mem::take(&mut mutex.abandoned)
+        }
+        // Exited threads stay signaled.
+        None => false,
+    }
+}
+
+/// Wake the threads waiting on a handle, so they recheck their waits.
+fn wake_waiters(machine: &mut Machine, handle: u32) {
+    for thread in machine.state.kernel32.threads.iter() {
+        let Some(wait) = &thread.wait else { continue };
+        if !wait.handles.contains(&handle) {
+            continue;
+        }
+        #[cfg(feature = "x86-emu")]
+        {
+            let cpu = &mut machine.emu.x86.cpus[thread.cpu];
+            if matches!(
+                cpu.state,
+                x86::CPUState::Blocked(_) | x86::CPUState::Waiting
+            ) {
+                cpu.state = x86::CPUState::Running;
+            }
+        }
+    }
+}
+
+/// Called as a thread exits: abandons the mutexes it owns and wakes threads waiting on it.
+pub fn thread_exited(machine: &mut Machine, thread: HTHREAD) {
+    let mut abandoned = Vec::new();
+    for (handle, object) in machine.state.kernel32.sync_objects.entries_mut() {
+        if let SyncObject::Mutex(mutex) = object {
+            if matches!(mutex.owner, Some((owner, _)) if owner == thread) {
+                mutex.owner = None;
+                mutex.abandoned = true;
+                abandoned.push(handle.raw);
+            }
+        }
+    }
+    for handle in abandoned {
+        wake_waiters(machine, handle);
+    }
+    wake_waiters(machine, thread.raw);
+}
+
+async fn wait(machine: &mut Machine, handles: Vec<u32>, wait_all: bool, timeout: u32) -> u32 {
+    let deadline = if timeout == INFINITE {
+        None
+    } else {
+        Some(machine.host.ticks().wrapping_add(timeout))
+    };
+
+    let current = current_thread(machine).handle;
+    if !wait_all {
+        for &handle in &handles {
+            if let Some(event) = event_mut(machine, HEVENT::from_raw(handle)) {
+                event.waiters.push_back(current);
+            }
+        }
+    }
+    current_thread_mut(machine).wait = Some(Wait {
+        handles: handles.clone(),
+        pulsed: None,
+    });
+
+    let result = loop {
+        let wait = current_thread_mut(machine).wait.as_mut().unwrap();
+        if let Some(pulsed) = wait.pulsed.take() {
+            let index = handles.iter().position(|&h| h == pulsed).unwrap();
+            break WAIT_OBJECT_0 + index as u32;
+        }
+
+        let signaled = handles
+            .iter()
+            .map(|&handle| is_signaled(machine, handle))
+            .collect::<Option<Vec<_>>>();
+        let Some(signaled) = signaled else {
+            set_last_error(machine, ERROR::INVALID_HANDLE);
+            break WAIT_FAILED;
+        };
+        if wait_all {
+            if signaled.iter().all(|&s| s) {
+                let mut abandoned = None;
+                for (i, &handle) in handles.iter().enumerate() {
+                    if acquire(machine, handle) && abandoned.is_none() {
+                        abandoned = Some(i);
+                    }
+                }
+                break match abandoned {
+                    Some(i) => WAIT_ABANDONED_0 + i as u32,
+                    None => WAIT_OBJECT_0,
+                };
+            }
+        } else if let Some(i) = signaled.iter().position(|&s| s) {
+            break if acquire(machine, handles[i]) {
+                WAIT_ABANDONED_0 + i as u32
+            } else {
+                WAIT_OBJECT_0 + i as u32
+            };
+        }
+
+        if let Some(deadline) = deadline {
+            if machine.host.ticks() >= deadline {
+                break WAIT_TIMEOUT;
+            }
+        }
+
+        #[cfg(feature = "x86-emu")]
+        {
+            let cpu = machine.emu.x86.cpu_mut();
+            match deadline {
+                Some(deadline) => cpu.block(Some(deadline)).await,
+                None => cpu.wait().await,
+            }
+        }
+
+        #[cfg(not(feature = "x86-emu"))]
+        {
+            log::warn!("can't block on a wait without the emulator's scheduler");
+            break WAIT_TIMEOUT;
+        }
+    };
+
+    current_thread_mut(machine).wait = None;
+    for &handle in &handles {
+        if let Some(event) = event_mut(machine, HEVENT::from_raw(handle)) {
+            event.waiters.retain(|&waiter| waiter != current);
+        }
+    }
+    result
 }
 
 #[win32_derive::dllexport]
-pub fn WaitForSingleObject(_machine: &mut Machine, hHandle: HEVENT, dwMilliseconds: u32) -> u32 {
-    todo!()
+pub async fn WaitForSingleObject(machine: &mut Machine, hHandle: u32, dwMilliseconds: u32) -> u32 {
+    wait(machine, vec![hHandle], false, dwMilliseconds).await
+}
+
+#[win32_derive::dllexport]
+pub async fn WaitForMultipleObjects(
+    machine: &mut Machine,
+    nCount: u32,
+    lpHandles: u32,
+    bWaitAll: bool,
+    dwMilliseconds: u32,
+) -> u32 {
+    if nCount == 0 || nCount > MAXIMUM_WAIT_OBJECTS {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return WAIT_FAILED;
+    }
+    let mappings = &machine.state.kernel32.mappings;
+    if !mappings.allows(lpHandles, nCount * 4, ImageSectionFlags::MEM_READ) {
+        set_last_error(machine, ERROR::NOACCESS);
+        return WAIT_FAILED;
+    }
+    let handles = machine.mem().iter_pod::<u32>(lpHandles, nCount).collect();
+    wait(machine, handles, bWaitAll, dwMilliseconds).await
 }
 
 #[win32_derive::dllexport]
@@ -25,23 +285,470 @@ pub fn CreateEventA(
         todo!("CreateEventA: named events not supported");
     }
 
-    machine
+    let handle = machine
         .state
         .kernel32
-        .event_handles
-        .add(EventObject { state: false })
+        .sync_objects
+        .add(SyncObject::Event(EventObject {
+            manual_reset: bManualReset,
+            state: bInitialState,
+            waiters: VecDeque::new(),
+        }));
+    HEVENT::from_raw(handle.raw)
 }
 
 #[win32_derive::dllexport]
 pub fn SetEvent(machine: &mut Machine, hEvent: HEVENT) -> bool {
-    match machine.state.kernel32.event_handles.get_mut(hEvent) {
-        Some(handle) => {
-            handle.state = true;
+    match event_mut(machine, hEvent) {
+        Some(event) => {
+            event.state = true;
+            wake_waiters(machine, hEvent.raw);
             true
         }
         None => {
             log::warn!("SetEvent: invalid handle");
+            set_last_error(machine, ERROR::INVALID_HANDLE);
             false
         }
     }
 }
+
+#[win32_derive::dllexport]
+pub fn ResetEvent(machine: &mut Machine, hEvent: HEVENT) -> bool {
+    match event_mut(machine, hEvent) {
+        Some(event) => {
+            event.state = false;
+            true
+        }
+        None => {
+            log::warn!("ResetEvent: invalid handle");
+            set_last_error(machine, ERROR::INVALID_HANDLE);
+            false
+        }
+    }
+}
+
+#[win32_derive::dllexport]
+pub fn PulseEvent(machine: &mut Machine, hEvent: HEVENT) -> bool {
+    let Some(event) = event_mut(machine, hEvent) else {
+        log::warn!("PulseEvent: invalid handle");
+        set_last_error(machine, ERROR::INVALID_HANDLE);
+        return false;
+    };
+    // Release current waiters without leaving the event signaled: all of them for a
+    // manual-reset event, otherwise just the longest waiting.
+    let released: Vec<HTHREAD> = if event.manual_reset {
+        event.waiters.drain(..).collect()
+    } else {
+        event.waiters.pop_front().into_iter().collect()
+    };
+    event.state = false;
+    for handle in released {
+        if let Some(thread) = machine.state.kernel32.threads.get_mut(handle) {
+            if let Some(wait) = &mut thread.wait {
+                wait.pulsed = Some(hEvent.raw);
+            }
+        }
+    }
+    wake_waiters(machine, hEvent.raw);
+    true
+}
+
+#[win32_derive::dllexport]
+pub fn CreateMutexA(
+    machine: &mut Machine,
+    lpMutexAttributes: u32,
+    bInitialOwner: bool,
+    lpName: Option<&str>,
+) -> HMUTEX {
+    if let Some(name) = lpName {
+        log::warn!("CreateMutexA({name:?}): named mutexes not supported, creating unnamed");
+    }
+    let owner = if bInitialOwner {
+        Some((current_thread(machine).handle, 1))
+    } else {
+        None
+    };
+    let handle = machine
+        .state
+        .kernel32
+        .sync_objects
+        .add(SyncObject::Mutex(MutexObject {
+            owner,
+            abandoned: false,
+        }));
+    set_last_error(machine, ERROR::SUCCESS);
+    HMUTEX::from_raw(handle.raw)
+}
+
+#[win32_derive::dllexport]
+pub fn ReleaseMutex(machine: &mut Machine, hMutex: HMUTEX) -> bool {
+    let current = current_thread(machine).handle;
+    let Some(SyncObject::Mutex(mutex)) = machine
+        .state
+        .kernel32
+        .sync_objects
+        .get_mut(HSYNC::from_raw(hMutex.raw))
+    else {
+        set_last_error(machine, ERROR::INVALID_HANDLE);
+        return false;
+    };
+    match mutex.owner {
+        Some((owner, count)) if owner == current => {
+            if count > 1 {
+                mutex.owner = Some((owner, count - 1));
+            } else {
+                mutex.owner = None;
+                wake_waiters(machine, hMutex.raw);
+            }
+            true
+        }
+        _ => {
+            set_last_error(machine, ERROR::NOT_OWNER);
+            false
+        }
+    }
+}
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use super::*;
+    use crate::{
+        machine::Status,
+        testing::{self, create_thread, kernel32, load_eax, store_eax, wait_ebx, Arg::*},
+    };
+
+    /// Memory shared between a test's threads.
+    struct Cells {
+        handle: u32,
+        count: u32,
+        snapshot: u32,
+        result: u32,
+    }
+
+    impl Cells {
+        fn new(machine: &mut Machine) -> Self {
+            let base = testing::alloc_data(machine, &[0; 16]);
+            Cells {
+                handle: base,
+                count: base + 4,
+                snapshot: base + 8,
+                result: base + 12,
+            }
+        }
+
+        fn get(&self, machine: &Machine, addr: u32) -> u32 {
+            machine.mem().get_pod::<u32>(addr)
+        }
+    }
+
+    /// A thread function that waits for the object in the handle cell, counts its wakeup,
+    /// and releases the object if it's a mutex.
+    fn waiter(machine: &mut Machine, cells: &Cells, release: bool) -> u32 {
+        let mut code = vec![];
+        kernel32(
+            machine,
+            &mut code,
+            "WaitForSingleObject",
+            &[Mem(cells.handle), Imm(INFINITE)],
+        );
+        code.extend_from_slice(&[0xff, 0x05]); // inc dword [count]
+        code.extend_from_slice(&cells.count.to_le_bytes());
+        if release {
+            kernel32(machine, &mut code, "ReleaseMutex", &[Mem(cells.handle)]);
+        }
+        code.extend_from_slice(&[0x31, 0xc0, 0xc2, 4, 0]); // xor eax, eax; ret 4
+        testing::alloc_code(machine, &code)
+    }
+
+    /// Let the other threads run until they block.  TestHost's clock jumps straight to
+    /// the deadline once every thread is blocked, so this takes no real time.
+    fn sleep(machine: &mut Machine, code: &mut Vec<u8>) {
+        kernel32(machine, code, "Sleep", &[Imm(10)]);
+    }
+
+    /// Run two waiters on an event, signaling it twice with the given function.
+    /// Returns the wakeups after the first signal, after both, and the event's final state
+    /// as a zero-timeout wait result.
+    fn signal_event(manual_reset: bool, signal: &str) -> (u32, u32, u32) {
+        let mut machine = testing::machine();
+        let cells = Cells::new(&mut machine);
+        let waiter = waiter(&mut machine, &cells, false);
+
+        let mut code = vec![];
+        kernel32(
+            &mut machine,
+            &mut code,
+            "CreateEventA",
+            &[Imm(0), Imm(manual_reset as u32), Imm(0), Imm(0)],
+        );
+        store_eax(&mut code, cells.handle);
+        create_thread(&mut machine, &mut code, waiter, 0, 0);
+        create_thread(&mut machine, &mut code, waiter, 0, 0);
+        sleep(&mut machine, &mut code);
+        kernel32(&mut machine, &mut code, signal, &[Mem(cells.handle)]);
+        sleep(&mut machine, &mut code);
+        load_eax(&mut code, cells.count);
+        store_eax(&mut code, cells.snapshot);
+        kernel32(&mut machine, &mut code, signal, &[Mem(cells.handle)]);
+        sleep(&mut machine, &mut code);
+        kernel32(
+            &mut machine,
+            &mut code,
+            "WaitForSingleObject",
+            &[Mem(cells.handle), Imm(0)],
+        );
+        code.push(0xc3); // ret
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        let state = testing::run_exe(&mut machine, entry);
+        (
+            cells.get(&machine, cells.snapshot),
+            cells.get(&machine, cells.count),
+            state,
+        )
+    }
+
+    #[test]
+    fn set_event() {
+        // An auto-reset event releases exactly one waiter per signal.
+        assert_eq!(signal_event(false, "SetEvent"), (1, 2, WAIT_TIMEOUT));
+        // A manual-reset event releases them all and stays signaled.
+        assert_eq!(signal_event(true, "SetEvent"), (2, 2, WAIT_OBJECT_0));
+    }
+
+    #[test]
+    fn pulse_event() {
+        // Pulsing releases waiters like SetEvent, but never leaves the event signaled.
+        assert_eq!(signal_event(false, "PulseEvent"), (1, 2, WAIT_TIMEOUT));
+        assert_eq!(signal_event(true, "PulseEvent"), (2, 2, WAIT_TIMEOUT));
+    }
+
+    #[test]
+    fn mutex_contention() {
+        let mut machine = testing::machine();
+        let cells = Cells::new(&mut machine);
+        let waiter = waiter(&mut machine, &cells, true);
+
+        let mut code = vec![];
+        kernel32(
+            &mut machine,
+            &mut code,
+            "CreateMutexA",
+            &[Imm(0), Imm(1), Imm(0)],
+        );
+        store_eax(&mut code, cells.handle);
+        create_thread(&mut machine, &mut code, waiter, 0, 0);
+        sleep(&mut machine, &mut code);
+        load_eax(&mut code, cells.count);
+        store_eax(&mut code, cells.snapshot);
+        kernel32(
+            &mut machine,
+            &mut code,
+            "ReleaseMutex",
+            &[Mem(cells.handle)],
+        );
+        wait_ebx(&mut machine, &mut code);
+        load_eax(&mut code, cells.count);
+        code.push(0xc3); // ret
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        // The waiter only got the mutex once the main thread released it.
+        assert_eq!(testing::run_exe(&mut machine, entry), 1);
+        assert_eq!(cells.get(&machine, cells.snapshot), 0);
+    }
+
+    #[test]
+    fn mutex_abandoned() {
+        let mut machine = testing::machine();
+        let cells = Cells::new(&mut machine);
+        let waiter = waiter(&mut machine, &cells, false);
+
+        let mut code = vec![];
+        kernel32(
+            &mut machine,
+            &mut code,
+            "CreateMutexA",
+            &[Imm(0), Imm(0), Imm(0)],
+        );
+        store_eax(&mut code, cells.handle);
+        create_thread(&mut machine, &mut code, waiter, 0, 0);
+        wait_ebx(&mut machine, &mut code);
+        // The first acquirer after the owner exits hears it was abandoned; later ones don't.
+        kernel32(
+            &mut machine,
+            &mut code,
+            "WaitForSingleObject",
+            &[Mem(cells.handle), Imm(0)],
+        );
+        store_eax(&mut code, cells.result);
+        kernel32(
+            &mut machine,
+            &mut code,
+            "WaitForSingleObject",
+            &[Mem(cells.handle), Imm(0)],
+        );
+        code.push(0xc3); // ret
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        assert_eq!(testing::run_exe(&mut machine, entry), WAIT_OBJECT_0);
+        assert_eq!(cells.get(&machine, cells.result), WAIT_ABANDONED_0);
+    }
+
+    #[test]
+    fn wait_for_multiple_objects() {
+        let mut machine = testing::machine();
+        let cells = Cells::new(&mut machine);
+        // The handle array is the handle and count cells.
+        let (first, second) = (cells.handle, cells.count);
+
+        // A thread that signals the first event.
+        let mut setter = vec![];
+        kernel32(&mut machine, &mut setter, "SetEvent", &[Mem(first)]);
+        setter.extend_from_slice(&[0xc2, 4, 0]); // ret 4
+        let setter = testing::alloc_code(&mut machine, &setter);
+
+        let mut code = vec![];
+        for (event, initial) in [(first, 0), (second, 1)] {
+            kernel32(
+                &mut machine,
+                &mut code,
+                "CreateEventA",
+                &[Imm(0), Imm(1), Imm(initial), Imm(0)],
+            );
+            store_eax(&mut code, event);
+        }
+        kernel32(
+            &mut machine,
+            &mut code,
+            "WaitForMultipleObjects",
+            &[Imm(2), Imm(first), Imm(0), Imm(0)],
+        );
+        store_eax(&mut code, cells.snapshot);
+        kernel32(
+            &mut machine,
+            &mut code,
+            "WaitForMultipleObjects",
+            &[Imm(2), Imm(first), Imm(1), Imm(0)],
+        );
+        store_eax(&mut code, cells.result);
+        create_thread(&mut machine, &mut code, setter, 0, 0);
+        kernel32(
+            &mut machine,
+            &mut code,
+            "WaitForMultipleObjects",
+            &[Imm(2), Imm(first), Imm(1), Imm(INFINITE)],
+        );
+        code.push(0xc3); // ret
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        assert_eq!(testing::run_exe(&mut machine, entry), WAIT_OBJECT_0);
+        // Wait-any reports the signaled one; wait-all needs both.
+        assert_eq!(cells.get(&machine, cells.snapshot), WAIT_OBJECT_0 + 1);
+        assert_eq!(cells.get(&machine, cells.result), WAIT_TIMEOUT);
+    }
+
+    #[test]
+    fn timed_waits() {
+        let mut machine = testing::machine();
+        let cells = Cells::new(&mut machine);
+
+        // A thread that signals the event after 20ms.
+        let mut setter = vec![];
+        kernel32(&mut machine, &mut setter, "Sleep", &[Imm(20)]);
+        kernel32(&mut machine, &mut setter, "SetEvent", &[Mem(cells.handle)]);
+        setter.extend_from_slice(&[0xc2, 4, 0]); // ret 4
+        let setter = testing::alloc_code(&mut machine, &setter);
+
+        let mut code = vec![];
+        kernel32(
+            &mut machine,
+            &mut code,
+            "CreateEventA",
+            &[Imm(0), Imm(1), Imm(0), Imm(0)],
+        );
+        store_eax(&mut code, cells.handle);
+        kernel32(
+            &mut machine,
+            &mut code,
+            "WaitForSingleObject",
+            &[Mem(cells.handle), Imm(50)],
+        );
+        store_eax(&mut code, cells.result);
+        kernel32(&mut machine, &mut code, "GetTickCount", &[]);
+        store_eax(&mut code, cells.snapshot);
+        create_thread(&mut machine, &mut code, setter, 0, 0);
+        kernel32(
+            &mut machine,
+            &mut code,
+            "WaitForSingleObject",
+            &[Mem(cells.handle), Imm(100)],
+        );
+        store_eax(&mut code, cells.count);
+        kernel32(&mut machine, &mut code, "GetTickCount", &[]);
+        code.push(0xc3); // ret
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        // An unsignaled wait times out after exactly its timeout in host ticks, and a
+        // signal wakes a timed wait as soon as it comes.
+        assert_eq!(testing::run_exe(&mut machine, entry), 70);
+        assert_eq!(cells.get(&machine, cells.result), WAIT_TIMEOUT);
+        assert_eq!(cells.get(&machine, cells.snapshot), 50);
+        assert_eq!(cells.get(&machine, cells.count), WAIT_OBJECT_0);
+    }
+
+    #[test]
+    fn wait_for_multiple_objects_invalid() {
+        let mut machine = testing::machine();
+        let handles = testing::alloc_data(&mut machine, &[0; 4]);
+        let mut wait = |count: u32, handles: u32| {
+            let result = testing::poll(WaitForMultipleObjects(
+                &mut machine,
+                count,
+                handles,
+                false,
+                0,
+            ));
+            (result, crate::winapi::kernel32::GetLastError(&mut machine))
+        };
+
+        let invalid = ERROR::INVALID_PARAMETER as u32;
+        assert_eq!(wait(0, handles), (WAIT_FAILED, invalid));
+        assert_eq!(
+            wait(MAXIMUM_WAIT_OBJECTS + 1, handles),
+            (WAIT_FAILED, invalid)
+        );
+        // A handle array outside mapped memory fails rather than panicking.
+        let noaccess = ERROR::NOACCESS as u32;
+        assert_eq!(wait(1, 0), (WAIT_FAILED, noaccess));
+        assert_eq!(wait(4, 0xFFFF_FFF8), (WAIT_FAILED, noaccess));
+    }
+
+    #[test]
+    fn deadlock() {
+        let mut machine = testing::machine();
+        let mut code = vec![];
+        kernel32(
+            &mut machine,
+            &mut code,
+            "CreateEventA",
+            &[Imm(0), Imm(0), Imm(0), Imm(0)],
+        );
+        let handle = testing::alloc_data(&mut machine, &[0; 4]);
+        store_eax(&mut code, handle);
+        kernel32(
+            &mut machine,
+            &mut code,
+            "WaitForSingleObject",
+            &[Mem(handle), Imm(INFINITE)],
+        );
+        code.push(0xc3); // ret
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        testing::start(&mut machine, entry);
+        assert!(matches!(
+            testing::run(&mut machine),
+            Status::Error { message } if message.contains("deadlock")
+        ));
+    }
+}
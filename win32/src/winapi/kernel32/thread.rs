@@ -1,10 +1,13 @@
-use super::{peb_mut, teb_mut};
+use super::{set_last_error, thread_exited, Wait};
 use crate::{
     machine::Machine,
     winapi,
-    winapi::types::{Str16, HANDLE},
+    winapi::{
+        types::{Str16, HANDLE},
+        ERROR,
+    },
 };
-use memory::Pod;
+use memory::{ExtensionsMut, Pod};
 
 const TRACE_CONTEXT: &'static str = "kernel32/thread";
 
@@ -12,55 +15,183 @@ const TRACE_CONTEXT: &'static str = "kernel32/thread";
 pub struct HTHREADT;
 pub type HTHREAD = HANDLE<HTHREADT>;
 
-#[win32_derive::dllexport]
-pub fn GetCurrentThread(machine: &mut Machine) -> HTHREAD {
-    HTHREAD::from_raw(GetCurrentThreadId(machine))
+/// First thread handle, kept apart from event handles so WaitForSingleObject can tell them apart.
+pub const THREAD_HANDLE_BASE: u32 = 0x1000;
+
+/// What GetCurrentThread() returns, meaning the calling thread.
+const CURRENT_THREAD_PSEUDO_HANDLE: u32 = 0xFFFF_FFFE;
+
+/// Number of TLS slots; Windows guarantees at least 64.
+const TLS_MINIMUM_AVAILABLE: usize = 64;
+const TLS_OUT_OF_INDEXES: u32 = 0xFFFF_FFFF;
+
+#[cfg(feature = "x86-emu")]
+const CREATE_SUSPENDED: u32 = 0x4;
+/// Stack size used when CreateThread is passed 0.
+#[cfg(feature = "x86-emu")]
+const DEFAULT_STACK_SIZE: u32 = 0x10_0000;
+
+/// A thread, which under the x86 emulator runs on its own CPU.
+/// Threads are cooperatively scheduled: a thread runs until it blocks, suspends or exits.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Thread {
+    /// The thread's handle, also used as its id.
+    pub handle: HTHREAD,
+    /// Index of the thread's CPU in the emulator.
+    pub cpu: usize,
+    pub teb: u32,
+    /// Count of SuspendThread calls not yet balanced by ResumeThread.
+    suspend_count: u32,
+    /// Set once the thread has exited, at which point its handle is signaled.
+    pub exit_code: Option<u32>,
+    /// Values of the TlsAlloc() slots.
+    #[serde(with = "crate::save_state::array")]
+    tls: [u32; TLS_MINIMUM_AVAILABLE],
+    /// The wait the thread is blocked in, if any.
+    pub wait: Option<Wait>,
 }
 
-#[win32_derive::dllexport]
-pub fn GetCurrentThreadId(machine: &mut Machine) -> u32 {
+impl Thread {
+    pub fn new(handle: HTHREAD, cpu: usize, teb: u32) -> Self {
+        Thread {
+            handle,
+            cpu,
+            teb,
+            suspend_count: 0,
+            exit_code: None,
+            tls: [0; TLS_MINIMUM_AVAILABLE],
+            wait: None,
+        }
+    }
+}
+
+fn current_cpu(machine: &Machine) -> usize {
     #[cfg(feature = "x86-emu")]
     {
-        machine.emu.x86.cur_cpu as u32
+        machine.emu.x86.cur_cpu
     }
 
     #[cfg(not(feature = "x86-emu"))]
     {
         _ = machine;
-        1
+        0
+    }
+}
+
+/// The thread whose code is currently running.
+pub fn current_thread(machine: &Machine) -> &Thread {
+    let cpu = current_cpu(machine);
+    machine
+        .state
+        .kernel32
+        .threads
+        .iter()
+        .find(|thread| thread.cpu == cpu)
+        .unwrap()
+}
+
+pub fn current_thread_mut(machine: &mut Machine) -> &mut Thread {
+    let cpu = current_cpu(machine);
+    machine
+        .state
+        .kernel32
+        .threads
+        .iter_mut()
+        .find(|thread| thread.cpu == cpu)
+        .unwrap()
+}
+
+/// Map the GetCurrentThread() pseudo-handle to the real handle.
+fn resolve_thread(machine: &Machine, hThread: HTHREAD) -> HTHREAD {
+    if hThread.raw == CURRENT_THREAD_PSEUDO_HANDLE {
+        current_thread(machine).handle
+    } else {
+        hThread
+    }
+}
+
+#[win32_derive::dllexport]
+pub fn GetCurrentThread(_machine: &mut Machine) -> HTHREAD {
+    HTHREAD::from_raw(CURRENT_THREAD_PSEUDO_HANDLE)
+}
+
+#[win32_derive::dllexport]
+pub fn GetCurrentThreadId(machine: &mut Machine) -> u32 {
+    current_thread(machine).handle.to_raw()
+}
+
+/// TlsAlloc() slots: which indices are allocated.  The values are per thread.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Tls {
+    allocated: u64,
+    /// The exe's TLS directory callbacks, run before its entry point.
+    pub callbacks: Vec<u32>,
+}
+
+impl Tls {
+    fn alloc(&mut self) -> Option<u32> {
+        let index = (!self.allocated).trailing_zeros();
+        if index as usize >= TLS_MINIMUM_AVAILABLE {
+            return None;
+        }
+        self.allocated |= 1 << index;
+        Some(index)
+    }
+
+    fn is_allocated(&self, index: u32) -> bool {
+        (index as usize) < TLS_MINIMUM_AVAILABLE && self.allocated & (1 << index) != 0
     }
 }
 
 #[win32_derive::dllexport]
 pub fn TlsAlloc(machine: &mut Machine) -> u32 {
-    let peb = peb_mut(machine);
-    let slot = peb.TlsCount;
-    peb.TlsCount = slot + 1;
-    slot
+    let kernel32 = &mut machine.state.kernel32;
+    match kernel32.tls.alloc() {
+        Some(index) => {
+            for thread in kernel32.threads.iter_mut() {
+                thread.tls[index as usize] = 0;
+            }
+            index
+        }
+        None => {
+            set_last_error(machine, ERROR::NO_MORE_ITEMS);
+            TLS_OUT_OF_INDEXES
+        }
+    }
 }
 
 #[win32_derive::dllexport]
 pub fn TlsFree(machine: &mut Machine, dwTlsIndex: u32) -> bool {
-    let peb = peb_mut(machine);
-    if dwTlsIndex >= peb.TlsCount {
+    let tls = &mut machine.state.kernel32.tls;
+    if !tls.is_allocated(dwTlsIndex) {
         log::warn!("TlsFree of unknown slot {dwTlsIndex}");
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
         return false;
     }
-    // TODO
+    tls.allocated &= !(1 << dwTlsIndex);
     true
 }
 
 #[win32_derive::dllexport]
 pub fn TlsSetValue(machine: &mut Machine, dwTlsIndex: u32, lpTlsValue: u32) -> bool {
-    let teb = teb_mut(machine);
-    teb.TlsSlots[dwTlsIndex as usize] = lpTlsValue;
+    if !machine.state.kernel32.tls.is_allocated(dwTlsIndex) {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return false;
+    }
+    current_thread_mut(machine).tls[dwTlsIndex as usize] = lpTlsValue;
     true
 }
 
 #[win32_derive::dllexport]
 pub fn TlsGetValue(machine: &mut Machine, dwTlsIndex: u32) -> u32 {
-    let teb = teb_mut(machine);
-    teb.TlsSlots[dwTlsIndex as usize]
+    if !machine.state.kernel32.tls.is_allocated(dwTlsIndex) {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return 0;
+    }
+    let value = current_thread(machine).tls[dwTlsIndex as usize];
+    // A stored zero is only distinguishable from failure via the last error.
+    set_last_error(machine, ERROR::SUCCESS);
+    value
 }
 
 #[win32_derive::dllexport]
@@ -71,30 +202,58 @@ pub async fn CreateThread(
     lpStartAddress: u32,
     lpParameter: u32,
     dwCreationFlags: u32,
-    lpThreadId: u32,
+    lpThreadId: Option<&mut u32>,
 ) -> HTHREAD {
     let retrowin32_thread_main =
         winapi::kernel32::get_kernel32_builtin(machine, "retrowin32_thread_main");
 
     #[cfg(feature = "x86-emu")]
     {
-        let id = 1; // TODO
-        let stack_pointer = machine.create_stack(format!("thread{id} stack"), dwStackSize);
+        let handle = machine.state.kernel32.threads.reserve();
+        let stack_size = if dwStackSize == 0 {
+            DEFAULT_STACK_SIZE
+        } else {
+            dwStackSize
+        };
+        let stack_pointer =
+            machine.create_stack(format!("thread {:x} stack", handle.raw), stack_size);
+        let stack_base = stack_pointer + 4;
+        let teb = machine.state.kernel32.new_teb(
+            &mut machine.emu.memory,
+            handle.raw,
+            stack_base,
+            stack_base - stack_size,
+        );
+
+        let mut thread = Thread::new(handle, machine.emu.x86.cpus.len(), teb);
         let cpu = machine.emu.x86.new_cpu();
-        cpu.regs.set32(x86::Register::ESP, stack_pointer);
-        cpu.regs.set32(x86::Register::EBP, stack_pointer);
+        cpu.regs.fs_addr = teb;
+        // Write the initial frame directly rather than via x86 pushes, as the new CPU's
+        // view of the mappings doesn't include its stack until the next memory map sync.
+        let esp = stack_pointer - 12;
         let mem = machine.emu.memory.mem();
-        x86::ops::push(cpu, mem, lpParameter);
-        x86::ops::push(cpu, mem, lpStartAddress);
-        x86::ops::push(cpu, mem, 0);
+        mem.put_pod::<u32>(esp, 0); // return address
+        mem.put_pod::<u32>(esp + 4, lpStartAddress);
+        mem.put_pod::<u32>(esp + 8, lpParameter);
+        cpu.regs.set32(x86::Register::ESP, esp);
+        cpu.regs.set32(x86::Register::EBP, stack_pointer);
         cpu.regs.eip = retrowin32_thread_main;
-
-        HTHREAD::from_raw(id)
+        if dwCreationFlags & CREATE_SUSPENDED != 0 {
+            thread.suspend_count = 1;
+            cpu.state = x86::CPUState::Suspended;
+        }
+        machine.state.kernel32.threads.set(handle, thread);
+
+        if let Some(id) = lpThreadId {
+            *id = handle.raw;
+        }
+        handle
     }
 
     #[cfg(not(feature = "x86-emu"))]
     {
         _ = retrowin32_thread_main;
+        _ = lpThreadId;
         log::warn!("CreateThread running thread synchronously");
         machine.call_x86(lpStartAddress, vec![lpParameter]).await;
         HTHREAD::null()
@@ -102,22 +261,113 @@ pub async fn CreateThread(
 }
 
 #[win32_derive::dllexport]
-pub fn ResumeThread(_machine: &mut Machine, hThread: HTHREAD) -> u32 {
-    1
+pub fn ExitThread(machine: &mut Machine, dwExitCode: u32) {
+    let thread = current_thread_mut(machine);
+    thread.exit_code = Some(dwExitCode);
+    let handle = thread.handle;
+    thread_exited(machine, handle);
+    if machine
+        .state
+        .kernel32
+        .threads
+        .iter()
+        .all(|thread| thread.exit_code.is_some())
+    {
+        // The last thread exiting ends the process.
+        machine.exit(dwExitCode);
+        return;
+    }
+
+    #[cfg(feature = "x86-emu")]
+    {
+        machine.emu.x86.cpu_mut().state = x86::CPUState::Exited;
+    }
+
+    #[cfg(not(feature = "x86-emu"))]
+    {
+        // Without the emulator other threads ran to completion inside CreateThread, so
+        // there's nothing to switch to; treat this as the process exiting.
+        log::warn!("ExitThread with other threads running, exiting process");
+        machine.exit(dwExitCode);
+    }
+}
+
+#[win32_derive::dllexport]
+pub fn SuspendThread(machine: &mut Machine, hThread: HTHREAD) -> u32 {
+    let hThread = resolve_thread(machine, hThread);
+    let Some(thread) = machine.state.kernel32.threads.get_mut(hThread) else {
+        set_last_error(machine, ERROR::INVALID_HANDLE);
+        return -1i32 as u32;
+    };
+    let prev = thread.suspend_count;
+    thread.suspend_count += 1;
+
+    #[cfg(feature = "x86-emu")]
+    {
+        // TODO: a thread blocked in e.g. Sleep is left to resume once it unblocks.
+        let cpu = &mut machine.emu.x86.cpus[thread.cpu];
+        if cpu.state.is_running() {
+            cpu.state = x86::CPUState::Suspended;
+        }
+    }
+
+    prev
+}
+
+#[win32_derive::dllexport]
+pub fn ResumeThread(machine: &mut Machine, hThread: HTHREAD) -> u32 {
+    let hThread = resolve_thread(machine, hThread);
+    let Some(thread) = machine.state.kernel32.threads.get_mut(hThread) else {
+        set_last_error(machine, ERROR::INVALID_HANDLE);
+        return -1i32 as u32;
+    };
+    let prev = thread.suspend_count;
+    if prev > 0 {
+        thread.suspend_count -= 1;
+    }
+
+    #[cfg(feature = "x86-emu")]
+    if thread.suspend_count == 0 {
+        let cpu = &mut machine.emu.x86.cpus[thread.cpu];
+        if cpu.state == x86::CPUState::Suspended {
+            cpu.state = x86::CPUState::Running;
+        }
+    }
+
+    prev
+}
+
+/// Whether a thread handle is signaled, i.e. the thread has exited.
+/// Returns None if the handle isn't a thread.
+pub fn thread_signaled(machine: &Machine, hThread: HTHREAD) -> Option<bool> {
+    let hThread = resolve_thread(machine, hThread);
+    let thread = machine.state.kernel32.threads.get(hThread)?;
+    Some(thread.exit_code.is_some())
 }
 
 #[win32_derive::dllexport]
 pub fn SetThreadDescription(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     hThread: HTHREAD,
     lpThreadDescription: Option<&Str16>,
 ) -> bool {
-    true // success
+    check_thread(machine, hThread)
 }
 
 #[win32_derive::dllexport]
-pub fn SetThreadPriority(_machine: &mut Machine, hThread: HTHREAD, nPriority: u32) -> bool {
-    true // success
+pub fn SetThreadPriority(machine: &mut Machine, hThread: HTHREAD, nPriority: u32) -> bool {
+    // Priorities don't affect our scheduling.
+    check_thread(machine, hThread)
+}
+
+/// Validate a thread handle for calls that otherwise ignore the thread.
+fn check_thread(machine: &mut Machine, hThread: HTHREAD) -> bool {
+    let hThread = resolve_thread(machine, hThread);
+    if machine.state.kernel32.threads.get(hThread).is_none() {
+        set_last_error(machine, ERROR::INVALID_HANDLE);
+        return false;
+    }
+    true
 }
 
 #[win32_derive::dllexport]
@@ -126,16 +376,67 @@ pub fn SetThreadStackGuarantee(_machine: &mut Machine, StackSizeInBytes: Option<
     true // success
 }
 
+// There's only one thread running x86 code at a time, so the Interlocked functions
+// are plain read-modify-writes.
+
+/// Windows would fault on a NULL target.  These shims are hot enough that we keep them
+/// sync rather than raise an exception, so just log the bug and carry on.
+fn null_interlocked(func: &str) -> u32 {
+    log::error!("{func}: NULL target");
+    0
+}
+
 #[win32_derive::dllexport]
 pub fn InterlockedIncrement(_machine: &mut Machine, addend: Option<&mut u32>) -> u32 {
-    let addend = addend.unwrap();
-    *addend += 1;
+    let Some(addend) = addend else {
+        return null_interlocked("InterlockedIncrement");
+    };
+    *addend = addend.wrapping_add(1);
     *addend
 }
 
 #[win32_derive::dllexport]
 pub fn InterlockedDecrement(_machine: &mut Machine, addend: Option<&mut u32>) -> u32 {
-    todo!()
+    let Some(addend) = addend else {
+        return null_interlocked("InterlockedDecrement");
+    };
+    *addend = addend.wrapping_sub(1);
+    *addend
+}
+
+#[win32_derive::dllexport]
+pub fn InterlockedExchange(_machine: &mut Machine, Target: Option<&mut u32>, Value: u32) -> u32 {
+    let Some(target) = Target else {
+        return null_interlocked("InterlockedExchange");
+    };
+    std::mem::replace(target, Value)
+}
+
+#[win32_derive::dllexport]
+pub fn InterlockedExchangeAdd(_machine: &mut Machine, Addend: Option<&mut u32>, Value: u32) -> u32 {
+    let Some(addend) = Addend else {
+        return null_interlocked("InterlockedExchangeAdd");
+    };
+    let prev = *addend;
+    *addend = prev.wrapping_add(Value);
+    prev
+}
+
+#[win32_derive::dllexport]
+pub fn InterlockedCompareExchange(
+    _machine: &mut Machine,
+    Destination: Option<&mut u32>,
+    Exchange: u32,
+    Comperand: u32,
+) -> u32 {
+    let Some(dest) = Destination else {
+        return null_interlocked("InterlockedCompareExchange");
+    };
+    let prev = *dest;
+    if prev == Comperand {
+        *dest = Exchange;
+    }
+    prev
 }
 
 #[repr(C)]
@@ -246,3 +547,163 @@ pub fn TryAcquireSRWLockExclusive(_machine: &mut Machine, SRWLock: Option<&mut S
 pub fn ReleaseSRWLockExclusive(_machine: &mut Machine, SRWLock: Option<&mut SRWLOCK>) -> u32 {
     0
 }
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{self, call, create_thread, load_eax, push, store_eax, wait_ebx},
+        winapi::kernel32::get_kernel32_builtin,
+    };
+    use memory::Extensions;
+
+    /// A thread function storing its parameter to cell and returning ret.
+    fn store_param(machine: &mut Machine, cell: u32, ret: u32) -> u32 {
+        let mut code = vec![0x8b, 0x44, 0x24, 0x04, 0xa3]; // mov eax, [esp+4]; mov [cell], eax
+        code.extend_from_slice(&cell.to_le_bytes());
+        code.push(0xb8); // mov eax, ret
+        code.extend_from_slice(&ret.to_le_bytes());
+        code.extend_from_slice(&[0xc2, 4, 0]); // ret 4
+        testing::alloc_code(machine, &code)
+    }
+
+    #[test]
+    fn thread_runs_once_main_blocks() {
+        let mut machine = testing::machine();
+        let cell = testing::alloc_data(&mut machine, &[0; 8]);
+        let func = store_param(&mut machine, cell, 7);
+
+        let mut code = vec![];
+        create_thread(&mut machine, &mut code, func, 5, 0);
+        // Threads are cooperative, so the new thread hasn't run yet.
+        load_eax(&mut code, cell);
+        store_eax(&mut code, cell + 4);
+        wait_ebx(&mut machine, &mut code);
+        load_eax(&mut code, cell);
+        code.push(0xc3); // ret
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        assert_eq!(testing::run_exe(&mut machine, entry), 5);
+        assert_eq!(machine.mem().get_pod::<u32>(cell + 4), 0);
+        let thread = machine
+            .state
+            .kernel32
+            .threads
+            .iter()
+            .find(|t| t.cpu == 1)
+            .unwrap();
+        assert_eq!(thread.exit_code, Some(7));
+    }
+
+    #[test]
+    fn exit_thread() {
+        let mut machine = testing::machine();
+        let cell = testing::alloc_data(&mut machine, &[0; 4]);
+        let exit_thread = get_kernel32_builtin(&mut machine, "ExitThread");
+
+        // Store the new thread's SEH chain head, then ExitThread(3).
+        let mut func = vec![0x64, 0xa1, 0, 0, 0, 0, 0xa3]; // mov eax, fs:[0]; mov [cell], eax
+        func.extend_from_slice(&cell.to_le_bytes());
+        push(&mut func, 3);
+        call(&mut func, exit_thread);
+        func.push(0xcc); // int3, unreachable
+        let func = testing::alloc_code(&mut machine, &func);
+
+        let mut code = vec![];
+        create_thread(&mut machine, &mut code, func, 0, 0);
+        wait_ebx(&mut machine, &mut code);
+        code.extend_from_slice(&[0xb8, 9, 0, 0, 0, 0xc3]); // mov eax, 9; ret
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        // The process outlives the thread.
+        assert_eq!(testing::run_exe(&mut machine, entry), 9);
+        assert_eq!(machine.mem().get_pod::<u32>(cell), 0xFFFF_FFFF);
+        let thread = machine
+            .state
+            .kernel32
+            .threads
+            .iter()
+            .find(|t| t.cpu == 1)
+            .unwrap();
+        assert_eq!(thread.exit_code, Some(3));
+    }
+
+    #[test]
+    fn suspended_thread() {
+        let mut machine = testing::machine();
+        let cell = testing::alloc_data(&mut machine, &[0; 8]);
+        let func = store_param(&mut machine, cell, 0);
+        let sleep = get_kernel32_builtin(&mut machine, "Sleep");
+        let resume_thread = get_kernel32_builtin(&mut machine, "ResumeThread");
+
+        let mut code = vec![];
+        create_thread(&mut machine, &mut code, func, 5, CREATE_SUSPENDED);
+        // Sleeping lets other threads run, but not a suspended one.
+        push(&mut code, 10);
+        call(&mut code, sleep);
+        load_eax(&mut code, cell);
+        store_eax(&mut code, cell + 4);
+        code.push(0x53); // push ebx
+        call(&mut code, resume_thread);
+        wait_ebx(&mut machine, &mut code);
+        load_eax(&mut code, cell);
+        code.push(0xc3); // ret
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        assert_eq!(testing::run_exe(&mut machine, entry), 5);
+        assert_eq!(machine.mem().get_pod::<u32>(cell + 4), 0);
+    }
+
+    #[test]
+    fn last_thread_exit_ends_process() {
+        let mut machine = testing::machine();
+        let cell = testing::alloc_data(&mut machine, &[0; 4]);
+        let func = store_param(&mut machine, cell, 4);
+        let exit_thread = get_kernel32_builtin(&mut machine, "ExitThread");
+
+        // The main thread exits first, leaving the process to the other thread.
+        let mut code = vec![];
+        create_thread(&mut machine, &mut code, func, 5, 0);
+        push(&mut code, 1);
+        call(&mut code, exit_thread);
+        code.push(0xcc); // int3, unreachable
+        let entry = testing::alloc_code(&mut machine, &code);
+
+        assert_eq!(testing::run_exe(&mut machine, entry), 4);
+        assert_eq!(machine.mem().get_pod::<u32>(cell), 5);
+    }
+
+    #[test]
+    fn interlocked() {
+        let mut machine = crate::testing::machine();
+        let mut val = u32::MAX;
+        assert_eq!(InterlockedIncrement(&mut machine, Some(&mut val)), 0);
+        assert_eq!(InterlockedDecrement(&mut machine, Some(&mut val)), u32::MAX);
+        assert_eq!(
+            InterlockedExchange(&mut machine, Some(&mut val), 5),
+            u32::MAX
+        );
+        assert_eq!(InterlockedExchangeAdd(&mut machine, Some(&mut val), 3), 5);
+        assert_eq!(val, 8);
+        assert_eq!(
+            InterlockedCompareExchange(&mut machine, Some(&mut val), 1, 7),
+            8
+        );
+        assert_eq!(val, 8);
+        assert_eq!(
+            InterlockedCompareExchange(&mut machine, Some(&mut val), 1, 8),
+            8
+        );
+        assert_eq!(val, 1);
+    }
+
+    #[test]
+    fn interlocked_null() {
+        let mut machine = crate::testing::machine();
+        assert_eq!(InterlockedIncrement(&mut machine, None), 0);
+        assert_eq!(InterlockedDecrement(&mut machine, None), 0);
+        assert_eq!(InterlockedExchange(&mut machine, None, 1), 0);
+        assert_eq!(InterlockedExchangeAdd(&mut machine, None, 1), 0);
+        assert_eq!(InterlockedCompareExchange(&mut machine, None, 1, 0), 0);
+    }
+}
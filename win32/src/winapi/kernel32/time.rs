@@ -1,7 +1,7 @@
 use super::{set_last_error, FILETIME};
 use crate::{winapi::ERROR, Machine};
 use chrono::{Datelike, Timelike};
-use memory::{ExtensionsMut, Pod};
+use memory::Pod;
 
 const TRACE_CONTEXT: &'static str = "kernel32/time";
 
@@ -10,10 +10,18 @@ pub fn GetTickCount(machine: &mut Machine) -> u32 {
     machine.host.ticks()
 }
 
+#[win32_derive::dllexport]
+pub fn GetTickCount64(machine: &mut Machine) -> u32 {
+    // TODO: 64-bit return values go through edx:eax, which is not yet modeled in the shims
+    // machinery.  The emulator clears edx on return from a shim, so this is exact until
+    // the 32-bit tick count wraps after 49.7 days of uptime.
+    machine.host.ticks()
+}
+
 // The number of "counts" per second, where counts are the units returned by
-// QueryPerformanceCounter.  On my Windows machine this value was 10m, which
-// is to say a count is 0.1us.
-const QUERY_PERFORMANCE_FREQ: u32 = 10_000_000;
+// QueryPerformanceCounter.  Real Windows reports 10m here, but our counter is derived
+// from the millisecond host clock anyway, so a count is 1us.
+const QUERY_PERFORMANCE_FREQ: u64 = 1_000_000;
 
 // In principle we could just use an i64 here, but when Windows passes one of these via
 // the stack it may align it on a 4-byte address when Rust requires 8-byte alignment for
@@ -26,26 +34,40 @@ pub struct LARGE_INTEGER {
 }
 unsafe impl memory::Pod for LARGE_INTEGER {}
 
+impl From<u64> for LARGE_INTEGER {
+    fn from(value: u64) -> Self {
+        LARGE_INTEGER {
+            LowPart: value as u32,
+            HighPart: (value >> 32) as u32 as i32,
+        }
+    }
+}
+
 #[win32_derive::dllexport]
 pub fn QueryPerformanceCounter(
     machine: &mut Machine,
     lpPerformanceCount: Option<&mut LARGE_INTEGER>,
 ) -> bool {
-    let counter = lpPerformanceCount.unwrap();
+    let Some(counter) = lpPerformanceCount else {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return false;
+    };
+    // Derived from the same clock as GetTickCount, so code mixing the two stays consistent.
     let ms = machine.host.ticks();
-    let counts = ms as u64 * (QUERY_PERFORMANCE_FREQ as u64 / 1000);
-    counter.LowPart = counts as u32;
-    counter.HighPart = (counts >> 32) as u32 as i32;
-    true // success
+    *counter = LARGE_INTEGER::from(ms as u64 * (QUERY_PERFORMANCE_FREQ / 1000));
+    true
 }
 
 #[win32_derive::dllexport]
-pub fn QueryPerformanceFrequency(machine: &mut Machine, lpFrequency: u32) -> bool {
-    // 64-bit write
-    machine
-        .mem()
-        .put_pod::<u32>(lpFrequency, QUERY_PERFORMANCE_FREQ);
-    machine.mem().put_pod::<u32>(lpFrequency + 4, 0);
+pub fn QueryPerformanceFrequency(
+    machine: &mut Machine,
+    lpFrequency: Option<&mut LARGE_INTEGER>,
+) -> bool {
+    let Some(freq) = lpFrequency else {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return false;
+    };
+    *freq = LARGE_INTEGER::from(QUERY_PERFORMANCE_FREQ);
     true
 }
 
@@ -137,6 +159,11 @@ pub fn FileTimeToSystemTime(
         set_last_error(machine, ERROR::INVALID_DATA);
         return false;
     };
+    // FILETIMEs are signed internally, and negative ones are rejected.
+    if lpFileTime.dwHighDateTime & 0x8000_0000 != 0 {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return false;
+    }
     let nanos = lpFileTime.to_unix_nanos();
     let date_time = chrono::DateTime::from_timestamp_nanos(nanos);
     if let Some(time) = lpSystemTime {
@@ -147,20 +174,18 @@ pub fn FileTimeToSystemTime(
 
 #[win32_derive::dllexport]
 pub async fn Sleep(machine: &mut Machine, dwMilliseconds: u32) -> u32 {
-    if dwMilliseconds == 0 {
-        return 0;
-    }
+    // Even Sleep(0) blocks (with an already-expired deadline), which gives the host a
+    // chance to pump its event loop and other threads a chance to run.
+    let until = machine.host.ticks().saturating_add(dwMilliseconds);
 
     #[cfg(feature = "x86-emu")]
     {
-        let until = machine.host.ticks() + dwMilliseconds;
         machine.emu.x86.cpu_mut().block(Some(until)).await;
     }
 
     #[cfg(not(feature = "x86-emu"))]
     {
-        _ = machine;
-        log::warn!("TODO: sleep");
+        machine.host.block(Some(until));
     }
     0
 }
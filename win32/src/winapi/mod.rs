@@ -1,11 +1,12 @@
 use crate::machine::MemImpl;
 
-mod advapi32;
+pub mod advapi32;
 mod alloc;
 mod bass;
 mod bitmap;
 mod builtin;
 mod com;
+pub mod comctl32;
 pub mod ddraw;
 pub mod dsound;
 mod error;
@@ -17,6 +18,7 @@ mod ntdll;
 mod ole32;
 mod oleaut32;
 mod retrowin32_test;
+mod shell32;
 mod stack_args;
 pub mod types;
 mod ucrtbase;
@@ -28,6 +30,8 @@ mod winmm;
 
 pub use error::ERROR;
 
+/// A symbol imported by name or by ordinal.  Displayed as "Name" or "#ordinal",
+/// so ordinal-only imports get labels like "dll.dll!#12".
 #[derive(Debug)]
 pub enum ImportSymbol<'a> {
     Name(&'a str),
@@ -37,14 +41,15 @@ impl<'a> std::fmt::Display for ImportSymbol<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ImportSymbol::Name(name) => f.write_str(name),
-            ImportSymbol::Ordinal(ord) => f.write_fmt(format_args!("{}", ord)),
+            ImportSymbol::Ordinal(ord) => f.write_fmt(format_args!("#{}", ord)),
         }
     }
 }
 
-pub const DLLS: [builtin::BuiltinDLL; 16] = [
+pub const DLLS: [builtin::BuiltinDLL; 18] = [
     builtin::advapi32::DLL,
     builtin::bass::DLL,
+    builtin::comctl32::DLL,
     builtin::ddraw::DLL,
     builtin::dsound::DLL,
     builtin::gdi32::DLL,
@@ -52,6 +57,7 @@ pub const DLLS: [builtin::BuiltinDLL; 16] = [
     builtin::ntdll::DLL,
     builtin::ole32::DLL,
     builtin::oleaut32::DLL,
+    builtin::shell32::DLL,
     builtin::ucrtbase::DLL,
     builtin::user32::DLL,
     builtin::vcruntime140::DLL,
@@ -81,9 +87,12 @@ pub fn apiset(name: &str) -> Option<&'static str> {
     })
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct State {
     scratch: heap::Heap,
 
+    pub advapi32: advapi32::State,
+    pub comctl32: comctl32::State,
     pub ddraw: ddraw::State,
     pub dsound: dsound::State,
     pub gdi32: gdi32::State,
@@ -97,6 +106,8 @@ impl State {
 
         State {
             scratch,
+            advapi32: advapi32::State::default(),
+            comctl32: comctl32::State::default(),
             ddraw: ddraw::State::default(),
             dsound: dsound::State::default(),
             gdi32: gdi32::State::default(),
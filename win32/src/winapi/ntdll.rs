@@ -30,7 +30,13 @@ pub fn NtReadFile(
     ByteOffset: Option<&mut u64>,
     Key: u32,
 ) -> u32 {
-    let file = machine.state.kernel32.files.get_mut(FileHandle).unwrap();
+    let file = &mut machine
+        .state
+        .kernel32
+        .files
+        .get_mut(FileHandle)
+        .unwrap()
+        .file;
     if Event != 0 {
         todo!();
     }
@@ -1,11 +1,84 @@
 #![allow(non_snake_case)]
 #![allow(non_camel_case_types)]
 
+use super::ddraw;
+pub use crate::winapi::com::GUID;
 use crate::Machine;
 
 const TRACE_CONTEXT: &'static str = "ole32";
 
+const S_OK: u32 = 0;
+const E_INVALIDARG: u32 = 0x80070057;
+const CLASS_E_NOAGGREGATION: u32 = 0x80040110;
+const REGDB_E_CLASSNOTREG: u32 = 0x80040154;
+
+/// Creates an object of some class, returning its riid interface in *ppv.
+type Factory = fn(&mut Machine, Option<&GUID>, Option<&mut u32>) -> u32;
+
+/// The classes CoCreateInstance can create, by CLSID.
+const CLASSES: [(&GUID, Factory); 1] = [(&ddraw::CLSID_DirectDraw, ddraw::create_instance)];
+
 #[win32_derive::dllexport]
 pub fn OleInitialize(_machine: &mut Machine, _pvReserved: u32) -> u32 {
     0
 }
+
+#[win32_derive::dllexport]
+pub fn CoInitialize(_machine: &mut Machine, pvReserved: u32) -> u32 {
+    S_OK
+}
+
+#[win32_derive::dllexport]
+pub fn CoInitializeEx(_machine: &mut Machine, pvReserved: u32, dwCoInit: u32) -> u32 {
+    // We don't distinguish apartment models.
+    S_OK
+}
+
+#[win32_derive::dllexport]
+pub fn CoUninitialize(_machine: &mut Machine) {}
+
+#[win32_derive::dllexport]
+pub fn CoCreateInstance(
+    machine: &mut Machine,
+    rclsid: Option<&GUID>,
+    pUnkOuter: u32,
+    dwClsContext: u32,
+    riid: Option<&GUID>,
+    ppv: Option<&mut u32>,
+) -> u32 {
+    let Some(rclsid) = rclsid else {
+        return E_INVALIDARG;
+    };
+    let Some(&(_, factory)) = CLASSES.iter().find(|(clsid, _)| *clsid == rclsid) else {
+        log::warn!("CoCreateInstance: unsupported class {rclsid:?}");
+        if let Some(ppv) = ppv {
+            *ppv = 0;
+        }
+        return REGDB_E_CLASSNOTREG;
+    };
+    if pUnkOuter != 0 {
+        return CLASS_E_NOAGGREGATION;
+    }
+    factory(machine, riid, ppv)
+}
+
+#[win32_derive::dllexport]
+pub fn CoTaskMemAlloc(machine: &mut Machine, cb: u32) -> u32 {
+    let heap = machine
+        .state
+        .kernel32
+        .get_process_heap(&mut machine.emu.memory);
+    heap.alloc(machine.emu.memory.mem(), cb)
+}
+
+#[win32_derive::dllexport]
+pub fn CoTaskMemFree(machine: &mut Machine, pv: u32) {
+    if pv == 0 {
+        return;
+    }
+    let heap = machine
+        .state
+        .kernel32
+        .get_process_heap(&mut machine.emu.memory);
+    heap.free(machine.emu.memory.mem(), pv);
+}
@@ -0,0 +1,211 @@
+#![allow(non_snake_case)]
+
+use crate::{
+    host::SpecialFolder,
+    winapi::{
+        kernel32::{self, full_path},
+        types::{HWND, MAX_PATH},
+        user32::HINSTANCE,
+        ERROR,
+    },
+    Machine,
+};
+use memory::ExtensionsMut;
+
+const TRACE_CONTEXT: &'static str = "shell32";
+
+const S_OK: u32 = 0;
+const E_FAIL: u32 = 0x80004005;
+const E_INVALIDARG: u32 = 0x80070057;
+
+fn hresult_from_win32(err: ERROR) -> u32 {
+    0x8007_0000 | u32::from(err)
+}
+
+/// ShellExecute failures are reported as values <= 32; these are the SE_ERR_* codes.
+const SE_ERR_FNF: HINSTANCE = 2;
+const SE_ERR_PNF: HINSTANCE = 3;
+const SE_ERR_ACCESSDENIED: HINSTANCE = 5;
+const SE_ERR_NOASSOC: HINSTANCE = 31;
+
+/// Whether target is a URL like "http://..." rather than a path; the scheme is
+/// distinguished from a drive letter by its length.
+fn is_url(target: &str) -> bool {
+    matches!(target.find(':'), Some(i) if i > 1)
+}
+
+#[win32_derive::dllexport]
+pub fn ShellExecuteA(
+    machine: &mut Machine,
+    hwnd: HWND,
+    lpOperation: Option<&str>,
+    lpFile: u32,
+    lpParameters: Option<&str>,
+    lpDirectory: u32,
+    nShowCmd: u32,
+) -> HINSTANCE {
+    // A null verb means the default one, which is "open" for everything we handle.
+    let verb = lpOperation.unwrap_or("open");
+    if !matches!(verb.to_ascii_lowercase().as_str(), "open" | "explore") {
+        log::warn!("ShellExecuteA: unsupported verb {verb:?}");
+        return SE_ERR_NOASSOC;
+    }
+    // Paths are in the ANSI code page, so may not be ASCII.
+    let Some(file) = kernel32::read_ansi(machine.mem(), lpFile) else {
+        return SE_ERR_FNF;
+    };
+    if lpParameters.is_some() {
+        log::warn!("ShellExecuteA: ignoring parameters {lpParameters:?}");
+    }
+
+    let target = if is_url(&file) {
+        file
+    } else {
+        let path = match kernel32::read_ansi(machine.mem(), lpDirectory) {
+            Some(dir) => full_path(machine, &dir).map(|dir| dir.join(&file).normalize()),
+            None => full_path(machine, &file),
+        };
+        match path {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(_) => return SE_ERR_PNF,
+        }
+    };
+
+    match machine.host.open_external(verb, &target) {
+        // Success is any value > 32; real Windows returns a fake HINSTANCE.
+        Ok(()) => 42,
+        Err(ERROR::FILE_NOT_FOUND) => SE_ERR_FNF,
+        Err(ERROR::PATH_NOT_FOUND) => SE_ERR_PNF,
+        Err(ERROR::ACCESS_DENIED) => SE_ERR_ACCESSDENIED,
+        Err(err) => {
+            log::warn!("ShellExecuteA: {target:?} failed: {err:?}");
+            SE_ERR_NOASSOC
+        }
+    }
+}
+
+const CSIDL_FLAG_CREATE: u32 = 0x8000;
+const CSIDL_FLAG_MASK: u32 = 0xFF00;
+
+fn csidl_folder(csidl: u32) -> Option<SpecialFolder> {
+    Some(match csidl {
+        0x0000 | 0x0010 => SpecialFolder::Desktop, // CSIDL_DESKTOP, CSIDL_DESKTOPDIRECTORY
+        0x0005 => SpecialFolder::Personal,
+        0x001a => SpecialFolder::AppData,
+        0x001c => SpecialFolder::LocalAppData,
+        0x0023 => SpecialFolder::CommonAppData,
+        0x0024 => SpecialFolder::Windows,
+        0x0025 => SpecialFolder::System,
+        0x0026 => SpecialFolder::ProgramFiles,
+        _ => return None,
+    })
+}
+
+#[win32_derive::dllexport]
+pub fn SHGetFolderPathA(
+    machine: &mut Machine,
+    hwnd: HWND,
+    csidl: u32,
+    hToken: u32,
+    dwFlags: u32,
+    pszPath: u32,
+) -> u32 {
+    let Some(folder) = csidl_folder(csidl & !CSIDL_FLAG_MASK) else {
+        log::warn!("SHGetFolderPathA: unknown CSIDL {csidl:#x}");
+        return E_INVALIDARG;
+    };
+    let Some(path) = machine.host.special_folder(folder) else {
+        return E_FAIL;
+    };
+    if csidl & CSIDL_FLAG_CREATE != 0 && machine.host.stat(&path).is_err() {
+        if let Err(err) = machine.host.create_dir(&path) {
+            return hresult_from_win32(err);
+        }
+    }
+
+    let bytes = path.as_bytes();
+    if bytes.len() + 1 > MAX_PATH || pszPath == 0 {
+        return E_FAIL;
+    }
+    let buf = machine.mem().sub32_mut(pszPath, MAX_PATH as u32);
+    buf[..bytes.len()].copy_from_slice(bytes);
+    buf[bytes.len()] = 0;
+    S_OK
+}
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use super::*;
+    use crate::testing::{alloc_data, TestHost};
+    use memory::Extensions;
+    use std::{cell::RefCell, rc::Rc};
+
+    fn machine(host: TestHost) -> Machine {
+        Machine::new(Box::new(host), "test.exe".into())
+    }
+
+    #[test]
+    fn shell_execute_verbs() {
+        let host = TestHost::default();
+        let output = host.output.clone();
+        let mut machine = machine(host);
+        let readme = alloc_data(&mut machine, b"readme.txt\0");
+        let a = alloc_data(&mut machine, b"a.txt\0");
+        let docs = alloc_data(&mut machine, b"docs\0");
+        let drive = alloc_data(&mut machine, b"D:\\\0");
+        let url = alloc_data(&mut machine, b"http://example.com/\0");
+        let cafe = alloc_data(&mut machine, b"caf\xE9.txt\0");
+        let mut shell_execute =
+            |verb, file, dir| ShellExecuteA(&mut machine, HWND::null(), verb, file, None, dir, 1);
+        let launched = |output: &Rc<RefCell<Vec<u8>>>| {
+            String::from_utf8(std::mem::take(&mut *output.borrow_mut())).unwrap()
+        };
+
+        assert_eq!(shell_execute(Some("open"), readme, 0), 42);
+        assert_eq!(launched(&output), r"ShellExecute: open C:\readme.txt");
+        assert_eq!(shell_execute(None, a, docs), 42);
+        assert_eq!(launched(&output), r"ShellExecute: open C:\docs\a.txt");
+        assert_eq!(shell_execute(Some("Explore"), drive, 0), 42);
+        assert_eq!(launched(&output), r"ShellExecute: Explore D:\");
+        assert_eq!(shell_execute(Some("open"), url, docs), 42);
+        assert_eq!(launched(&output), "ShellExecute: open http://example.com/");
+        // Paths are decoded from the ANSI code page.
+        assert_eq!(shell_execute(Some("open"), cafe, 0), 42);
+        assert_eq!(launched(&output), "ShellExecute: open C:\\caf\u{e9}.txt");
+
+        assert_eq!(shell_execute(Some("print"), readme, 0), SE_ERR_NOASSOC);
+        assert_eq!(shell_execute(Some("open"), 0, 0), SE_ERR_FNF);
+        assert_eq!(launched(&output), "");
+    }
+
+    const CSIDL_APPDATA: u32 = 0x001a;
+    const CSIDL_PERSONAL: u32 = 0x0005;
+
+    #[test]
+    fn get_folder_path() {
+        let mut host = TestHost::default();
+        host.special_folders
+            .insert(SpecialFolder::AppData, r"C:\AppData".into());
+        host.special_folders.insert(
+            SpecialFolder::Personal,
+            format!(r"C:\{}", "x".repeat(MAX_PATH)).into(),
+        );
+        let mut machine = machine(host);
+        let buf = alloc_data(&mut machine, &[0; MAX_PATH]);
+        let mut get = |csidl, buf| SHGetFolderPathA(&mut machine, HWND::null(), csidl, 0, 0, buf);
+
+        assert_eq!(get(CSIDL_APPDATA, buf), S_OK);
+        assert_eq!(get(0x1234, buf), E_INVALIDARG);
+        // No such folder on this host.
+        assert_eq!(get(0x0024, buf), E_FAIL);
+        // Too long for the buffer.
+        assert_eq!(get(CSIDL_PERSONAL, buf), E_FAIL);
+        assert_eq!(get(CSIDL_APPDATA, 0), E_FAIL);
+        // The folder doesn't exist and can't be created.
+        assert_eq!(
+            get(CSIDL_APPDATA | CSIDL_FLAG_CREATE, buf),
+            hresult_from_win32(ERROR::ACCESS_DENIED)
+        );
+        assert_eq!(machine.mem().slicez(buf), br"C:\AppData");
+    }
+}
@@ -209,6 +209,12 @@ impl ToX86 for i32 {
         *self as u32
     }
 }
+impl ToX86 for i16 {
+    fn to_raw(&self) -> u32 {
+        // SHORT results are sign-extended into EAX.
+        *self as i32 as u32
+    }
+}
 impl ToX86 for () {
     fn to_raw(&self) -> u32 {
         0
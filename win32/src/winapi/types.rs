@@ -28,7 +28,7 @@ pub struct HWNDT;
 pub type HWND = HANDLE<HWNDT>;
 
 #[repr(C, packed)]
-#[derive(Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct RECT {
     pub left: i32,
     pub top: i32,
@@ -37,8 +37,47 @@ pub struct RECT {
 }
 unsafe impl memory::Pod for RECT {}
 
+impl RECT {
+    pub fn is_empty(&self) -> bool {
+        self.left >= self.right || self.top >= self.bottom
+    }
+
+    /// Smallest rect containing both; empty rects don't contribute.
+    pub fn union(&self, other: &RECT) -> RECT {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        RECT {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    /// Overlap of both rects, which may be empty.
+    pub fn intersect(&self, other: &RECT) -> RECT {
+        RECT {
+            left: self.left.max(other.left),
+            top: self.top.max(other.top),
+            right: self.right.min(other.right),
+            bottom: self.bottom.min(other.bottom),
+        }
+    }
+
+    pub fn contains(&self, other: &RECT) -> bool {
+        self.left <= other.left
+            && self.top <= other.top
+            && self.right >= other.right
+            && self.bottom >= other.bottom
+    }
+}
+
 #[repr(C, packed)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct POINT {
     pub x: DWORD,
     pub y: DWORD,
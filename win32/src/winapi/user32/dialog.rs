@@ -1,5 +1,15 @@
+use super::*;
 use crate::{
-    winapi::{stack_args::ArrayWithSizeMut, types::*},
+    host::MessageBoxButton,
+    pe,
+    str16::String16,
+    winapi::{
+        gdi32::{self, COLORREF},
+        kernel32::{self, set_last_error},
+        stack_args::ArrayWithSizeMut,
+        types::*,
+        ERROR,
+    },
     Machine,
 };
 
@@ -42,23 +52,49 @@ pub mod MessageBoxFlags {
 }
 */
 
+/// The button set of a message box, the low bits of its uType.
+#[derive(Debug, win32_derive::TryFromEnum)]
+pub enum MB {
+    OK = 0,
+    OKCANCEL = 1,
+    ABORTRETRYIGNORE = 2,
+    YESNOCANCEL = 3,
+    YESNO = 4,
+    RETRYCANCEL = 5,
+}
+
+fn message_box(machine: &mut Machine, text: &str, caption: &str, uType: u32) -> u32 {
+    use MessageBoxButton as B;
+    let buttons: &[MessageBoxButton] = match MB::try_from(uType & 0xF) {
+        Ok(MB::OK) => &[B::Ok],
+        Ok(MB::OKCANCEL) => &[B::Ok, B::Cancel],
+        Ok(MB::ABORTRETRYIGNORE) => &[B::Abort, B::Retry, B::Ignore],
+        Ok(MB::YESNOCANCEL) => &[B::Yes, B::No, B::Cancel],
+        Ok(MB::YESNO) => &[B::Yes, B::No],
+        Ok(MB::RETRYCANCEL) => &[B::Retry, B::Cancel],
+        Err(n) => {
+            log::warn!("MessageBox: unimplemented buttons {n:#x}");
+            &[B::Ok]
+        }
+    };
+    let default = std::cmp::min(((uType >> 8) & 0xF) as usize, buttons.len() - 1);
+    if uType & 0xF0 != 0 {
+        log::info!("MessageBox: ignoring icon {:#x}", uType & 0xF0);
+    }
+    machine.host.message_box(text, caption, buttons, default) as u32
+}
+
 #[win32_derive::dllexport]
 pub fn MessageBoxA(
     machine: &mut Machine,
     hWnd: HWND,
-    lpText: Option<&str>,
-    lpCaption: Option<&str>,
+    lpText: u32,
+    lpCaption: u32,
     uType: u32,
 ) -> u32 {
-    machine.host.log(
-        format!(
-            "MessageBox: {}\n{}",
-            lpCaption.unwrap_or("Error"),
-            lpText.unwrap_or("")
-        )
-        .as_bytes(),
-    );
-    1 // IDOK
+    let text = kernel32::read_ansi(machine.mem(), lpText).unwrap_or_default();
+    let caption = kernel32::read_ansi(machine.mem(), lpCaption).unwrap_or_else(|| "Error".into());
+    message_box(machine, &text, &caption, uType)
 }
 
 #[win32_derive::dllexport]
@@ -69,57 +105,572 @@ pub fn MessageBoxW(
     lpCaption: Option<&Str16>,
     uType: u32,
 ) -> u32 {
-    machine.host.log(
-        format!(
-            "MessageBox: {}\n{}",
-            lpCaption.unwrap().to_string(),
-            lpText.unwrap().to_string()
+    let text = lpText.map(|s| s.to_string()).unwrap_or_default();
+    let caption = lpCaption
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "Error".into());
+    message_box(machine, &text, &caption, uType)
+}
+
+/// The window class of dialogs that don't name their own.
+const DIALOG_CLASS: &str = "#32770";
+
+/// Dialog template style: the template includes a font.
+const DS_SETFONT: u32 = 0x40;
+
+/// WM_COMMAND notification code for a button click.
+const BN_CLICKED: u32 = 0;
+
+/// A string or ordinal field of a dialog template.
+#[derive(Debug, PartialEq)]
+enum SzOrOrd {
+    None,
+    Ord(u16),
+    Name(String),
+}
+
+/// Bounds-checked reads through a dialog template, which is little-endian
+/// and, unlike most resources, not a fixed layout.
+struct TemplateReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> TemplateReader<'a> {
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.buf.get(self.pos..self.pos + 2)?;
+        self.pos += 2;
+        Some(u16::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.buf.get(self.pos..self.pos + 4)?;
+        self.pos += 4;
+        Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        self.u16().map(|v| v as i16)
+    }
+
+    fn skip(&mut self, n: usize) {
+        self.pos += n;
+    }
+
+    /// Items start on DWORD boundaries.
+    fn align4(&mut self) {
+        self.pos = (self.pos + 3) & !3;
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let mut chars = Vec::new();
+        loop {
+            match self.u16()? {
+                0 => break,
+                c => chars.push(c),
+            }
+        }
+        Some(String::from_utf16_lossy(&chars))
+    }
+
+    fn sz_or_ord(&mut self) -> Option<SzOrOrd> {
+        Some(match self.u16()? {
+            0 => SzOrOrd::None,
+            0xFFFF => SzOrOrd::Ord(self.u16()?),
+            _ => {
+                self.pos -= 2;
+                SzOrOrd::Name(self.string()?)
+            }
+        })
+    }
+}
+
+/// A parsed DLGTEMPLATE or DLGTEMPLATEEX.  Positions and sizes are in dialog units.
+#[derive(Debug, PartialEq)]
+struct DialogTemplate {
+    style: u32,
+    ex_style: u32,
+    x: i16,
+    y: i16,
+    cx: i16,
+    cy: i16,
+    class: Option<String>,
+    title: String,
+    items: Vec<DialogItem>,
+}
+
+/// A parsed DLGITEMTEMPLATE or DLGITEMTEMPLATEEX.
+#[derive(Debug, PartialEq)]
+struct DialogItem {
+    style: u32,
+    ex_style: u32,
+    x: i16,
+    y: i16,
+    cx: i16,
+    cy: i16,
+    id: u32,
+    class: String,
+    text: String,
+}
+
+fn parse_dialog_template(buf: &[u8]) -> Option<DialogTemplate> {
+    let mut r = TemplateReader { buf, pos: 0 };
+    // DLGTEMPLATEEX starts with a version of 1 and a signature of 0xFFFF.
+    let ex = r.u16()? == 1 && r.u16()? == 0xFFFF;
+    let (style, ex_style) = if ex {
+        let _help_id = r.u32()?;
+        let ex_style = r.u32()?;
+        (r.u32()?, ex_style)
+    } else {
+        r.pos = 0;
+        let style = r.u32()?;
+        (style, r.u32()?)
+    };
+    let count = r.u16()?;
+    let (x, y, cx, cy) = (r.i16()?, r.i16()?, r.i16()?, r.i16()?);
+    if let SzOrOrd::Ord(_) | SzOrOrd::Name(_) = r.sz_or_ord()? {
+        log::warn!("TODO: dialog menus");
+    }
+    let class = match r.sz_or_ord()? {
+        SzOrOrd::None => None,
+        SzOrOrd::Name(name) => Some(name),
+        SzOrOrd::Ord(atom) => {
+            log::warn!("TODO: dialog class atom {atom:#x}");
+            None
+        }
+    };
+    let title = r.string()?;
+    if style & DS_SETFONT != 0 {
+        // We draw with the built-in font regardless.
+        let _point_size = r.u16()?;
+        if ex {
+            let _weight = r.u16()?;
+            r.skip(2); // italic, charset
+        }
+        let _typeface = r.string()?;
+    }
+
+    let mut items = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        r.align4();
+        let (style, ex_style) = if ex {
+            let _help_id = r.u32()?;
+            let ex_style = r.u32()?;
+            (r.u32()?, ex_style)
+        } else {
+            let style = r.u32()?;
+            (style, r.u32()?)
+        };
+        let (x, y, cx, cy) = (r.i16()?, r.i16()?, r.i16()?, r.i16()?);
+        let id = if ex { r.u32()? } else { r.u16()? as u32 };
+        let class = match r.sz_or_ord()? {
+            SzOrOrd::Name(name) => name,
+            SzOrOrd::Ord(0x80) => "Button".into(),
+            SzOrOrd::Ord(0x81) => "Edit".into(),
+            SzOrOrd::Ord(0x82) => "Static".into(),
+            SzOrOrd::Ord(0x83) => "ListBox".into(),
+            SzOrOrd::Ord(0x84) => "ScrollBar".into(),
+            SzOrOrd::Ord(0x85) => "ComboBox".into(),
+            class => {
+                log::warn!("unknown dialog item class {class:?}");
+                String::new()
+            }
+        };
+        let text = match r.sz_or_ord()? {
+            SzOrOrd::Name(text) => text,
+            // An ordinal here is a resource id, e.g. of an icon.
+            _ => String::new(),
+        };
+        let extra = r.u16()?;
+        r.skip(extra as usize);
+        items.push(DialogItem {
+            style,
+            ex_style,
+            x,
+            y,
+            cx,
+            cy,
+            id,
+            class,
+            text,
+        });
+    }
+
+    Some(DialogTemplate {
+        style,
+        ex_style,
+        x,
+        y,
+        cx,
+        cy,
+        class,
+        title,
+        items,
+    })
+}
+
+/// Convert dialog units to pixels.  A dialog unit is a quarter of the font's average
+/// character width and an eighth of its height, and the built-in font is 8x16.
+fn dlu_to_pixels(x: i16, y: i16) -> (u32, u32) {
+    ((x as i32 * 2) as u32, (y as i32 * 2) as u32)
+}
+
+fn find_dialog_template(
+    machine: &Machine,
+    hInstance: u32,
+    name: ResourceKey<&Str16>,
+) -> Option<DialogTemplate> {
+    let buf = kernel32::find_resource(
+        &machine.state.kernel32,
+        machine.mem(),
+        hInstance,
+        ResourceKey::Id(pe::RT::DIALOG as u32),
+        name,
+    )?;
+    parse_dialog_template(machine.mem().slice(buf))
+}
+
+/// Create a dialog and its controls, and send it WM_INITDIALOG.
+async fn create_dialog(
+    machine: &mut Machine,
+    hInstance: u32,
+    template: &DialogTemplate,
+    hWndParent: HWND,
+    lpDialogFunc: u32,
+    dwInitParam: u32,
+) -> HWND {
+    let class = String16::from(template.class.as_deref().unwrap_or(DIALOG_CLASS));
+    let title = String16::from(template.title.as_str());
+    let (x, y) = dlu_to_pixels(template.x, template.y);
+    let (width, height) = dlu_to_pixels(template.cx, template.cy);
+    let hwnd = CreateWindowExW(
+        machine,
+        WindowStyleEx::from_bits(template.ex_style).ok_or(template.ex_style),
+        CreateWindowClassName::Name(class.as_str16()),
+        Some(title.as_str16()),
+        // The low bits are DS_ flags, which aren't window styles.
+        Ok(WindowStyle::from_bits_truncate(template.style)),
+        x,
+        y,
+        width,
+        height,
+        hWndParent,
+        0,
+        hInstance,
+        0,
+    )
+    .await;
+    let Some(window) = machine.state.user32.windows.get_mut(hwnd) else {
+        return HWND::null();
+    };
+    // The template gives the client size, not the window size.
+    window.set_client_size(&mut *machine.host, width, height);
+    window.wndproc = lpDialogFunc;
+
+    let mut first_control = HWND::null();
+    for item in &template.items {
+        let class = String16::from(item.class.as_str());
+        let text = String16::from(item.text.as_str());
+        let (x, y) = dlu_to_pixels(item.x, item.y);
+        let (width, height) = dlu_to_pixels(item.cx, item.cy);
+        let control = CreateWindowExW(
+            machine,
+            WindowStyleEx::from_bits(item.ex_style).ok_or(item.ex_style),
+            CreateWindowClassName::Name(class.as_str16()),
+            Some(text.as_str16()),
+            Ok(WindowStyle::from_bits_truncate(item.style) | WindowStyle::CHILD),
+            x,
+            y,
+            width,
+            height,
+            hwnd,
+            item.id,
+            hInstance,
+            0,
         )
-        .as_bytes(),
-    );
-    1 // IDOK
+        .await;
+        if first_control.is_null() {
+            first_control = control;
+        }
+    }
+
+    // TODO: WM_SETFONT, and focusing the control if the DialogProc returns TRUE.
+    let msg = MSG {
+        hwnd,
+        message: WM::INITDIALOG as u32,
+        wParam: first_control.to_raw(),
+        lParam: dwInitParam,
+        time: 0,
+        pt_x: 0,
+        pt_y: 0,
+    };
+    dispatch_message(machine, &msg).await;
+    hwnd
+}
+
+/// Destroy a dialog along with its controls.
+async fn destroy_dialog(machine: &mut Machine, hDlg: HWND) {
+    let controls: Vec<HWND> = machine
+        .state
+        .user32
+        .windows
+        .iter()
+        .filter(|w| w.parent == hDlg)
+        .map(|w| w.hwnd)
+        .collect();
+    for control in controls {
+        DestroyWindow(machine, control).await;
+    }
+    DestroyWindow(machine, hDlg).await;
+    machine.state.user32.ended_dialogs.remove(&hDlg);
+}
+
+/// Run a modal dialog's message loop until EndDialog is called, returning its result.
+async fn modal_loop(machine: &mut Machine, hDlg: HWND) -> i32 {
+    loop {
+        if let Some(result) = machine.state.user32.ended_dialogs.remove(&hDlg) {
+            destroy_dialog(machine, hDlg).await;
+            return result as i32;
+        }
+        let mut msg = MSG {
+            hwnd: HWND::null(),
+            message: 0,
+            wParam: 0,
+            lParam: 0,
+            time: 0,
+            pt_x: 0,
+            pt_y: 0,
+        };
+        if get_message(machine, Some(&mut msg), HWND::null(), 0, 0).await == 0 {
+            // Leave the WM_QUIT for the application's own loop.
+            machine.state.user32.messages.push_front(msg);
+            destroy_dialog(machine, hDlg).await;
+            return 0;
+        }
+        DispatchMessageW(machine, Some(&msg)).await;
+    }
+}
+
+/// Whether the window was created by create_dialog with the standard dialog class.
+pub fn is_dialog(window: &Window) -> bool {
+    window.wndclass.name == DIALOG_CLASS
+}
+
+/// Dispatch a message to a dialog's DialogProc, standing in for the behavior of the
+/// standard dialog class and its controls: painting them and turning clicks on
+/// buttons into WM_COMMAND.
+pub async fn dispatch_dialog_message(machine: &mut Machine, msg: &MSG, dlgproc: u32) -> u32 {
+    let mut msg = msg.clone();
+    if msg.message == WM::LBUTTONUP as u32 {
+        let (x, y) = (msg.lParam as i16 as i32, (msg.lParam >> 16) as i16 as i32);
+        if let Some(button) = button_at(machine, msg.hwnd, x, y) {
+            let id = machine.state.user32.windows.get(button).unwrap().id;
+            msg.message = WM::COMMAND as u32;
+            msg.wParam = (BN_CLICKED << 16) | (id & 0xFFFF);
+            msg.lParam = button.to_raw();
+        }
+    }
+    if msg.message == WM::PAINT as u32 {
+        erase_dialog(machine, msg.hwnd);
+    }
+    let ret = machine
+        .call_x86(
+            dlgproc,
+            vec![msg.hwnd.to_raw(), msg.message, msg.wParam, msg.lParam],
+        )
+        .await;
+    if msg.message == WM::PAINT as u32 {
+        paint_controls(machine, msg.hwnd);
+    }
+    ret
+}
+
+/// Find the button control of hDlg containing the given client point.
+fn button_at(machine: &Machine, hDlg: HWND, x: i32, y: i32) -> Option<HWND> {
+    machine
+        .state
+        .user32
+        .windows
+        .iter()
+        .find(|w| {
+            w.parent == hDlg
+                && w.wndclass.name.eq_ignore_ascii_case("Button")
+                && (w.x..w.x + w.width as i32).contains(&x)
+                && (w.y..w.y + w.height as i32).contains(&y)
+        })
+        .map(|w| w.hwnd)
+}
+
+/// The color of dialogs and buttons.
+const DIALOG_FACE: COLORREF = COLORREF::from_rgb(0xc0, 0xc0, 0xc0);
+
+/// Fill a dialog's background, before its DialogProc gets WM_PAINT, as erasing on
+/// BeginPaint would; anything the DialogProc draws then stays on top.
+fn erase_dialog(machine: &mut Machine, hDlg: HWND) {
+    let Some(window) = machine.state.user32.windows.get(hDlg) else {
+        return;
+    };
+    let background = window.client_rect();
+    let hdc = machine.state.gdi32.new_window_dc(hDlg);
+    gdi32::fill_rect(machine, hdc, &background, DIALOG_FACE);
+    machine.state.gdi32.dcs.remove(hdc);
+}
+
+/// Draw a dialog's controls, after its DialogProc has handled WM_PAINT.
+fn paint_controls(machine: &mut Machine, hDlg: HWND) {
+    if machine.state.user32.windows.get(hDlg).is_none() {
+        return;
+    }
+    let controls: Vec<(String, RECT, String)> = machine
+        .state
+        .user32
+        .windows
+        .iter()
+        .filter(|w| w.parent == hDlg)
+        .map(|w| {
+            let rect = RECT {
+                left: w.x,
+                top: w.y,
+                right: w.x + w.width as i32,
+                bottom: w.y + w.height as i32,
+            };
+            (w.wndclass.name.to_ascii_uppercase(), rect, w.text.clone())
+        })
+        .collect();
+
+    let hdc = machine.state.gdi32.new_window_dc(hDlg);
+    gdi32::SetBkMode(machine, hdc, Ok(gdi32::BkMode::TRANSPARENT));
+    for (class, rect, text) in controls {
+        let inner = RECT {
+            left: rect.left + 1,
+            top: rect.top + 1,
+            right: rect.right - 1,
+            bottom: rect.bottom - 1,
+        };
+        match class.as_str() {
+            "BUTTON" | "EDIT" => {
+                let fill = if class == "EDIT" {
+                    COLORREF::from_rgb(0xff, 0xff, 0xff)
+                } else {
+                    DIALOG_FACE
+                };
+                gdi32::fill_rect(machine, hdc, &rect, COLORREF::from_rgb(0, 0, 0));
+                gdi32::fill_rect(machine, hdc, &inner, fill);
+            }
+            _ => {}
+        }
+        // Drop the '&' marking keyboard mnemonics; the built-in font only covers ASCII.
+        let text: Vec<u8> = text
+            .chars()
+            .filter(|&c| c != '&')
+            .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+            .collect();
+        gdi32::TextOutA(machine, hdc, inner.left + 2, inner.top + 2, Some(&text));
+    }
+    flush_window(machine, hDlg);
+    machine.state.gdi32.dcs.remove(hdc);
+    ValidateRect(machine, hDlg, None);
 }
 
 #[win32_derive::dllexport]
-pub fn DialogBoxParamA(
-    _machine: &mut Machine,
+pub async fn DialogBoxParamA(
+    machine: &mut Machine,
     hInstance: u32,
-    lpTemplateName: u32,
+    lpTemplateName: ResourceKey<&str>,
     hWndParent: HWND,
     lpDialogFunc: u32,
     dwInitParam: u32,
-) -> u32 {
-    log::warn!("TODO: not showing dialog");
-    // TODO: this should run a nested message loop that will call back into lpDialogFunc,
-    // which then will call EndDialog to end the nested message loop and return the value
-    // passed to EndDialog.
-    // Unfortunately we don't know what value to return here otherwise; it's application specific.
-    0
+) -> i32 {
+    let name = lpTemplateName.to_string16();
+    DialogBoxParamW(
+        machine,
+        hInstance,
+        name.as_ref(),
+        hWndParent,
+        lpDialogFunc,
+        dwInitParam,
+    )
+    .await
 }
 
 #[win32_derive::dllexport]
-pub fn DialogBoxParamW(
-    _machine: &mut Machine,
+pub async fn DialogBoxParamW(
+    machine: &mut Machine,
     hInstance: u32,
-    lpTemplateName: u32,
+    lpTemplateName: ResourceKey<&Str16>,
     hWndParent: HWND,
     lpDialogFunc: u32,
     dwInitParam: u32,
-) -> u32 {
-    todo!();
+) -> i32 {
+    let Some(template) = find_dialog_template(machine, hInstance, lpTemplateName) else {
+        set_last_error(machine, ERROR::RESOURCE_NAME_NOT_FOUND);
+        return -1;
+    };
+    let hwnd = create_dialog(
+        machine,
+        hInstance,
+        &template,
+        hWndParent,
+        lpDialogFunc,
+        dwInitParam,
+    )
+    .await;
+    if hwnd.is_null() {
+        return -1;
+    }
+    modal_loop(machine, hwnd).await
 }
 
 #[win32_derive::dllexport]
-pub fn DialogBoxIndirectParamA(
-    _machine: &mut Machine,
+pub async fn DialogBoxIndirectParamA(
+    machine: &mut Machine,
     hInstance: u32,
     hDialogTemplate: u32,
     hWndParent: HWND,
     lpDialogFunc: u32,
     dwInitParam: u32,
 ) -> i32 {
-    log::warn!("TODO: not showing dialog");
-    -1 // error
+    let Some(template) = parse_dialog_template(machine.mem().slice(hDialogTemplate..)) else {
+        return -1;
+    };
+    let hwnd = create_dialog(
+        machine,
+        hInstance,
+        &template,
+        hWndParent,
+        lpDialogFunc,
+        dwInitParam,
+    )
+    .await;
+    if hwnd.is_null() {
+        return -1;
+    }
+    modal_loop(machine, hwnd).await
+}
+
+#[win32_derive::dllexport]
+pub async fn CreateDialogParamA(
+    machine: &mut Machine,
+    hInstance: u32,
+    lpTemplateName: ResourceKey<&str>,
+    hWndParent: HWND,
+    lpDialogFunc: u32,
+    dwInitParam: u32,
+) -> HWND {
+    let name = lpTemplateName.to_string16();
+    let Some(template) = find_dialog_template(machine, hInstance, name.as_ref()) else {
+        set_last_error(machine, ERROR::RESOURCE_NAME_NOT_FOUND);
+        return HWND::null();
+    };
+    create_dialog(
+        machine,
+        hInstance,
+        &template,
+        hWndParent,
+        lpDialogFunc,
+        dwInitParam,
+    )
+    .await
 }
 
 #[win32_derive::dllexport]
@@ -155,7 +706,20 @@ pub fn SetDlgItemInt(
 
 #[win32_derive::dllexport]
 pub fn GetDlgItem(machine: &mut Machine, hDlg: HWND, nIDDlgItem: i32) -> HWND {
-    todo!();
+    let control = machine
+        .state
+        .user32
+        .windows
+        .iter()
+        .find(|w| w.parent == hDlg && w.id == nIDDlgItem as u32)
+        .map(|w| w.hwnd);
+    match control {
+        Some(hwnd) => hwnd,
+        None => {
+            set_last_error(machine, ERROR::CONTROL_ID_NOT_FOUND);
+            HWND::null()
+        }
+    }
 }
 
 #[win32_derive::dllexport]
@@ -201,6 +765,70 @@ pub fn IsDlgButtonChecked(_machine: &mut Machine, hDlg: HWND, nIDButton: i32) ->
 }
 
 #[win32_derive::dllexport]
-pub fn EndDialog(_machine: &mut Machine, hDlg: HWND, nResult: Option<&mut u32>) -> bool {
-    todo!();
+pub fn EndDialog(machine: &mut Machine, hDlg: HWND, nResult: u32) -> bool {
+    if machine.state.user32.windows.get(hDlg).is_none() {
+        set_last_error(machine, ERROR::INVALID_WINDOW_HANDLE);
+        return false;
+    }
+    // The dialog is destroyed once control returns to its modal loop.
+    machine.state.user32.ended_dialogs.insert(hDlg, nResult);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_str(buf: &mut Vec<u8>, s: &str) {
+        for c in s.encode_utf16().chain(std::iter::once(0)) {
+            buf.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_parse_dialog_template() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(DS_SETFONT | 0x80C8_0000).to_le_bytes()); // style
+        buf.extend_from_slice(&0u32.to_le_bytes()); // ex_style
+        buf.extend_from_slice(&2u16.to_le_bytes()); // item count
+        for v in [10i16, 20, 100, 50] {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+        buf.extend_from_slice(&0u16.to_le_bytes()); // no menu
+        buf.extend_from_slice(&0u16.to_le_bytes()); // default class
+        push_str(&mut buf, "About");
+        buf.extend_from_slice(&8u16.to_le_bytes()); // point size
+        push_str(&mut buf, "MS Sans Serif");
+
+        for (id, class, text) in [(1u16, 0x80u16, "OK"), (0xFFFF, 0x82, "Hello")] {
+            buf.resize((buf.len() + 3) & !3, 0);
+            buf.extend_from_slice(&0x5000_0000u32.to_le_bytes()); // style
+            buf.extend_from_slice(&0u32.to_le_bytes()); // ex_style
+            for v in [4i16, 4, 40, 14] {
+                buf.extend_from_slice(&v.to_le_bytes());
+            }
+            buf.extend_from_slice(&id.to_le_bytes());
+            buf.extend_from_slice(&0xFFFFu16.to_le_bytes());
+            buf.extend_from_slice(&class.to_le_bytes());
+            push_str(&mut buf, text);
+            buf.extend_from_slice(&0u16.to_le_bytes()); // no creation data
+        }
+
+        let template = parse_dialog_template(&buf).unwrap();
+        assert_eq!(template.title, "About");
+        assert_eq!(template.class, None);
+        assert_eq!(
+            (template.x, template.y, template.cx, template.cy),
+            (10, 20, 100, 50)
+        );
+        let items: Vec<_> = template
+            .items
+            .iter()
+            .map(|item| (item.id, item.class.as_str(), item.text.as_str()))
+            .collect();
+        assert_eq!(items, [(1, "Button", "OK"), (0xFFFF, "Static", "Hello")]);
+
+        // Truncated templates fail rather than reading out of bounds.
+        assert_eq!(parse_dialog_template(&buf[..buf.len() - 4]), None);
+    }
 }
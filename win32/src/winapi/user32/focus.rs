@@ -0,0 +1,339 @@
+//! Tracking of the active window and the keyboard focus.
+//!
+//! We run a single app, so the active window is also the foreground window,
+//! except while the host window itself doesn't have focus.
+//!
+//! State changes are computed as a list of messages to deliver, so that API calls
+//! can send them synchronously as Windows does while host focus changes, which arrive
+//! via the message queue, can post them.
+
+use super::{dispatch_message, State, MSG, WM};
+use crate::{winapi::types::HWND, Machine};
+
+const TRACE_CONTEXT: &'static str = "user32/focus";
+
+// WM_ACTIVATE wParam.
+const WA_INACTIVE: u32 = 0;
+const WA_ACTIVE: u32 = 1;
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Focus {
+    /// The active top-level window.
+    active: HWND,
+    /// The window receiving keyboard input; the active window or one of its children.
+    focus: HWND,
+    /// Whether the app as a whole is active, i.e. has had WM_ACTIVATEAPP(TRUE).
+    app_active: bool,
+    /// The window that was active when the host took focus away, to restore later.
+    last_active: HWND,
+}
+
+fn msg(hwnd: HWND, message: WM, wParam: u32, lParam: u32) -> MSG {
+    MSG {
+        hwnd,
+        message: message as u32,
+        wParam,
+        lParam,
+        time: 0,
+        pt_x: 0,
+        pt_y: 0,
+    }
+}
+
+/// The top-level window containing hwnd.
+fn top_level(user32: &State, mut hwnd: HWND) -> HWND {
+    while let Some(window) = user32.windows.get(hwnd) {
+        if window.parent.is_null() {
+            break;
+        }
+        hwnd = window.parent;
+    }
+    hwnd
+}
+
+/// Move the keyboard focus to hwnd, which may be null to clear it.
+fn change_focus(user32: &mut State, hwnd: HWND) -> Vec<MSG> {
+    let old = user32.focus.focus;
+    if old == hwnd {
+        return vec![];
+    }
+    let mut msgs = vec![];
+    if user32.windows.get(old).is_some() {
+        msgs.push(msg(old, WM::KILLFOCUS, hwnd.to_raw(), 0));
+    }
+    user32.focus.focus = hwnd;
+    if !hwnd.is_null() {
+        msgs.push(msg(hwnd, WM::SETFOCUS, old.to_raw(), 0));
+    }
+    msgs
+}
+
+/// Make the top-level window hwnd active, which may be null to deactivate.
+fn change_active(user32: &mut State, hwnd: HWND) -> Vec<MSG> {
+    let old = user32.focus.active;
+    if old == hwnd {
+        return vec![];
+    }
+    let mut msgs = vec![];
+    if user32.windows.get(old).is_some() {
+        msgs.push(msg(old, WM::ACTIVATE, WA_INACTIVE, hwnd.to_raw()));
+    }
+    user32.focus.active = hwnd;
+    if hwnd.is_null() {
+        msgs.extend(change_focus(user32, HWND::null()));
+        return msgs;
+    }
+    if !user32.focus.app_active {
+        user32.focus.app_active = true;
+        msgs.push(msg(hwnd, WM::ACTIVATEAPP, true as u32, 0));
+    }
+    msgs.push(msg(hwnd, WM::ACTIVATE, WA_ACTIVE, old.to_raw()));
+    // Keep focus on a child of the new active window if it's already there.
+    if top_level(user32, user32.focus.focus) != hwnd {
+        msgs.extend(change_focus(user32, hwnd));
+    }
+    msgs
+}
+
+/// Update for the host window gaining or losing focus, e.g. via alt-tab.
+pub fn host_activate(user32: &mut State, hwnd: HWND, active: bool) -> Vec<MSG> {
+    if active {
+        let target = if user32.windows.get(hwnd).is_some() {
+            top_level(user32, hwnd)
+        } else {
+            user32.focus.last_active
+        };
+        if user32.windows.get(target).is_none() {
+            return vec![];
+        }
+        return change_active(user32, target);
+    }
+
+    if !user32.focus.app_active {
+        return vec![];
+    }
+    let active = user32.focus.active;
+    let mut msgs = vec![];
+    if user32.windows.get(active).is_some() {
+        msgs.push(msg(active, WM::ACTIVATE, WA_INACTIVE, 0));
+        msgs.push(msg(active, WM::ACTIVATEAPP, false as u32, 0));
+    }
+    msgs.extend(change_focus(user32, HWND::null()));
+    user32.focus.last_active = active;
+    user32.focus.active = HWND::null();
+    user32.focus.app_active = false;
+    msgs
+}
+
+/// Drop references to a window being destroyed.
+pub fn forget_window(user32: &mut State, hwnd: HWND) {
+    let focus = &mut user32.focus;
+    for h in [&mut focus.active, &mut focus.focus, &mut focus.last_active] {
+        if *h == hwnd {
+            *h = HWND::null();
+        }
+    }
+}
+
+async fn send(machine: &mut Machine, msgs: Vec<MSG>) {
+    for msg in msgs {
+        dispatch_message(machine, &msg).await;
+    }
+}
+
+/// Activate a top-level window as ShowWindow does.
+pub async fn activate(machine: &mut Machine, hwnd: HWND) {
+    let msgs = change_active(&mut machine.state.user32, hwnd);
+    send(machine, msgs).await;
+}
+
+#[win32_derive::dllexport]
+pub fn GetForegroundWindow(machine: &mut Machine) -> HWND {
+    machine.state.user32.focus.active
+}
+
+#[win32_derive::dllexport]
+pub async fn SetForegroundWindow(machine: &mut Machine, hWnd: HWND) -> bool {
+    if machine.state.user32.windows.get(hWnd).is_none() {
+        return false;
+    }
+    let top = top_level(&machine.state.user32, hWnd);
+    activate(machine, top).await;
+    true
+}
+
+#[win32_derive::dllexport]
+pub fn GetActiveWindow(machine: &mut Machine) -> HWND {
+    machine.state.user32.focus.active
+}
+
+#[win32_derive::dllexport]
+pub async fn SetActiveWindow(machine: &mut Machine, hWnd: HWND) -> HWND {
+    if machine.state.user32.windows.get(hWnd).is_none() {
+        log::warn!("SetActiveWindow: unknown window {hWnd:?}");
+        return HWND::null();
+    }
+    let prev = machine.state.user32.focus.active;
+    let top = top_level(&machine.state.user32, hWnd);
+    activate(machine, top).await;
+    prev
+}
+
+#[win32_derive::dllexport]
+pub fn GetFocus(machine: &mut Machine) -> HWND {
+    machine.state.user32.focus.focus
+}
+
+#[win32_derive::dllexport]
+pub async fn SetFocus(machine: &mut Machine, hWnd: HWND) -> HWND {
+    let prev = machine.state.user32.focus.focus;
+    let user32 = &mut machine.state.user32;
+    let mut msgs = vec![];
+    if !hWnd.is_null() {
+        if user32.windows.get(hWnd).is_none() {
+            log::warn!("SetFocus: unknown window {hWnd:?}");
+            return HWND::null();
+        }
+        let top = top_level(user32, hWnd);
+        if user32.focus.active != top {
+            msgs.extend(change_active(user32, top));
+        }
+    }
+    msgs.extend(change_focus(user32, hWnd));
+    send(machine, msgs).await;
+    prev
+}
+
+#[cfg(all(test, feature = "x86-emu"))]
+mod tests {
+    use super::*;
+    use crate::{testing, winapi::kernel32::get_symbol};
+    use memory::Extensions;
+
+    /// Append a call to the user32 function name with the arguments pushed by push_args,
+    /// storing its result to cell.
+    fn call_user32(
+        machine: &mut Machine,
+        code: &mut Vec<u8>,
+        name: &str,
+        push_args: &[u8],
+        cell: u32,
+    ) {
+        code.extend_from_slice(push_args);
+        testing::call(code, get_symbol(machine, "user32.dll", name));
+        testing::store_eax(code, cell);
+    }
+
+    /// Create hidden top-level windows A, holding a child, and B, then run the code that
+    /// more appends, returning (A, child, B).
+    fn windows(machine: &mut Machine, more: impl FnOnce(&mut Machine, &mut Vec<u8>)) -> [HWND; 3] {
+        let mut code = Vec::new();
+        testing::create_window(machine, &mut code, "a", 0x8000_0000); // WS_POPUP
+        code.extend_from_slice(&[0x89, 0xc3]); // mov ebx, eax
+        testing::create_child_window(machine, &mut code, "a class", 1);
+        code.extend_from_slice(&[0x89, 0xc6]); // mov esi, eax
+        testing::create_window(machine, &mut code, "b", 0x8000_0000);
+        code.extend_from_slice(&[0x89, 0xc7]); // mov edi, eax
+        more(machine, &mut code);
+        testing::start_spinning(machine, code);
+
+        let regs = &machine.emu.x86.cpu().regs;
+        [x86::Register::EBX, x86::Register::ESI, x86::Register::EDI]
+            .map(|reg| HWND::from_raw(regs.get32(reg)))
+    }
+
+    /// The (hwnd, message, wParam) of each message.
+    fn summary(msgs: Vec<MSG>) -> Vec<(HWND, u32, u32)> {
+        msgs.iter().map(|m| (m.hwnd, m.message, m.wParam)).collect()
+    }
+
+    #[test]
+    fn activation_messages() {
+        let mut machine = testing::machine();
+        let [a, child, b] = windows(&mut machine, |_, _| {});
+        let user32 = &mut machine.state.user32;
+        user32.focus = Focus::default();
+        let (activate, activateapp) = (WM::ACTIVATE as u32, WM::ACTIVATEAPP as u32);
+        let (setfocus, killfocus) = (WM::SETFOCUS as u32, WM::KILLFOCUS as u32);
+
+        // The first activation also activates the app, and focuses the window.
+        assert_eq!(
+            summary(change_active(user32, a)),
+            [
+                (a, activateapp, 1),
+                (a, activate, WA_ACTIVE),
+                (a, setfocus, 0)
+            ]
+        );
+        assert_eq!(
+            summary(change_focus(user32, child)),
+            [
+                (a, killfocus, child.to_raw()),
+                (child, setfocus, a.to_raw())
+            ]
+        );
+        // Switching windows takes the focus along.
+        assert_eq!(
+            summary(change_active(user32, b)),
+            [
+                (a, activate, WA_INACTIVE),
+                (b, activate, WA_ACTIVE),
+                (child, killfocus, b.to_raw()),
+                (b, setfocus, child.to_raw())
+            ]
+        );
+
+        // Losing host focus deactivates the app, and regaining it restores the window.
+        assert_eq!(
+            summary(host_activate(user32, HWND::null(), false)),
+            [
+                (b, activate, WA_INACTIVE),
+                (b, activateapp, 0),
+                (b, killfocus, 0)
+            ]
+        );
+        assert_eq!(
+            (user32.focus.active, user32.focus.focus),
+            (HWND::null(), HWND::null())
+        );
+        assert_eq!(
+            summary(host_activate(user32, HWND::null(), true)),
+            [
+                (b, activateapp, 1),
+                (b, activate, WA_ACTIVE),
+                (b, setfocus, 0)
+            ]
+        );
+
+        forget_window(user32, b);
+        assert_eq!(
+            (user32.focus.active, user32.focus.focus),
+            (HWND::null(), HWND::null())
+        );
+    }
+
+    #[test]
+    fn set_focus() {
+        let mut machine = testing::machine();
+        let cells = testing::alloc_data(&mut machine, &[0; 16]);
+        let [a, child, b] = windows(&mut machine, |machine, code| {
+            let show_b = [0x6a, 0x05, 0x57]; // push SW_SHOW; push edi
+            call_user32(machine, code, "ShowWindow", &show_b, cells);
+            call_user32(machine, code, "SetFocus", &[0x56], cells); // push esi
+            call_user32(machine, code, "GetFocus", &[], cells + 4);
+            call_user32(machine, code, "GetActiveWindow", &[], cells + 8);
+            let bogus = [0x68, 0x34, 0x12, 0, 0]; // push 0x1234
+            call_user32(machine, code, "SetFocus", &bogus, cells + 12);
+        });
+        let cell = |i: u32| HWND::from_raw(machine.mem().get_pod::<u32>(cells + i * 4));
+
+        // Showing b activated it, so focusing a's child moves activation over to a.
+        assert_eq!(cell(0), b);
+        assert_eq!(cell(1), child);
+        assert_eq!(cell(2), a);
+        // Focusing an unknown window fails without changing anything.
+        assert_eq!(cell(3), HWND::null());
+        let focus = &machine.state.user32.focus;
+        assert_eq!((focus.active, focus.focus), (a, child));
+    }
+}
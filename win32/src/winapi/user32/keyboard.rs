@@ -0,0 +1,231 @@
+//! Keyboard input: virtual-key codes, the key state table, and WM_KEY*/WM_CHAR.
+
+use super::{MSG, WM};
+use crate::{host, winapi::types::HWND, Machine};
+
+const TRACE_CONTEXT: &'static str = "user32/keyboard";
+
+/// Virtual-key codes, VK_*.
+/// Letters and digits have no named constants; their codes are the ASCII
+/// values of '0'-'9' and uppercase 'A'-'Z'.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, win32_derive::TryFromEnum)]
+pub enum VK {
+    LBUTTON = 0x01,
+    RBUTTON = 0x02,
+    MBUTTON = 0x04,
+    BACK = 0x08,
+    TAB = 0x09,
+    RETURN = 0x0D,
+    SHIFT = 0x10,
+    CONTROL = 0x11,
+    MENU = 0x12, // alt
+    PAUSE = 0x13,
+    CAPITAL = 0x14, // caps lock
+    ESCAPE = 0x1B,
+    SPACE = 0x20,
+    PRIOR = 0x21, // page up
+    NEXT = 0x22,  // page down
+    END = 0x23,
+    HOME = 0x24,
+    LEFT = 0x25,
+    UP = 0x26,
+    RIGHT = 0x27,
+    DOWN = 0x28,
+    INSERT = 0x2D,
+    DELETE = 0x2E,
+    F1 = 0x70,
+    F2 = 0x71,
+    F3 = 0x72,
+    F4 = 0x73,
+    F5 = 0x74,
+    F6 = 0x75,
+    F7 = 0x76,
+    F8 = 0x77,
+    F9 = 0x78,
+    F10 = 0x79,
+    F11 = 0x7A,
+    F12 = 0x7B,
+}
+
+/// Map a virtual key to its (set 1) keyboard scan code and whether it's an
+/// extended key, for the lParam of key messages.
+fn scan_code(vk: u32) -> (u32, bool) {
+    const LETTER_ROWS: [(&[u8], u32); 3] = [
+        (b"QWERTYUIOP", 0x10),
+        (b"ASDFGHJKL", 0x1E),
+        (b"ZXCVBNM", 0x2C),
+    ];
+    for (row, base) in LETTER_ROWS {
+        if let Some(i) = row.iter().position(|&c| c as u32 == vk) {
+            return (base + i as u32, false);
+        }
+    }
+    match vk {
+        0x31..=0x39 => (vk - 0x31 + 0x02, false), // '1'-'9'
+        0x30 => (0x0B, false),                    // '0'
+        0x70..=0x79 => (vk - 0x70 + 0x3B, false), // F1-F10
+        _ => match VK::try_from(vk) {
+            Ok(VK::ESCAPE) => (0x01, false),
+            Ok(VK::BACK) => (0x0E, false),
+            Ok(VK::TAB) => (0x0F, false),
+            Ok(VK::RETURN) => (0x1C, false),
+            Ok(VK::CONTROL) => (0x1D, false),
+            Ok(VK::SHIFT) => (0x2A, false),
+            Ok(VK::MENU) => (0x38, false),
+            Ok(VK::SPACE) => (0x39, false),
+            Ok(VK::CAPITAL) => (0x3A, false),
+            Ok(VK::PAUSE) => (0x45, false),
+            Ok(VK::F11) => (0x57, false),
+            Ok(VK::F12) => (0x58, false),
+            Ok(VK::HOME) => (0x47, true),
+            Ok(VK::UP) => (0x48, true),
+            Ok(VK::PRIOR) => (0x49, true),
+            Ok(VK::LEFT) => (0x4B, true),
+            Ok(VK::RIGHT) => (0x4D, true),
+            Ok(VK::END) => (0x4F, true),
+            Ok(VK::DOWN) => (0x50, true),
+            Ok(VK::NEXT) => (0x51, true),
+            Ok(VK::INSERT) => (0x52, true),
+            Ok(VK::DELETE) => (0x53, true),
+            _ => (0, false),
+        },
+    }
+}
+
+/// Per-virtual-key state, in the GetKeyboardState format:
+/// high bit set when down, low bit flipped on each press.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct KeyState(#[serde(with = "crate::save_state::array")] [u8; 256]);
+
+impl Default for KeyState {
+    fn default() -> Self {
+        KeyState([0; 256])
+    }
+}
+
+impl KeyState {
+    pub fn is_down(&self, vk: u32) -> bool {
+        self.0[(vk & 0xFF) as usize] & 0x80 != 0
+    }
+
+    pub fn is_toggled(&self, vk: u32) -> bool {
+        self.0[(vk & 0xFF) as usize] & 0x01 != 0
+    }
+
+    pub fn set(&mut self, vk: u32, down: bool) {
+        let state = &mut self.0[(vk & 0xFF) as usize];
+        if down {
+            if *state & 0x80 == 0 {
+                *state ^= 0x01;
+            }
+            *state |= 0x80;
+        } else {
+            *state &= !0x80;
+        }
+    }
+
+    /// The character TranslateMessage produces for a key press, if any.
+    fn to_char(&self, vk: u32) -> Option<u8> {
+        let shift = self.is_down(VK::SHIFT as u32);
+        Some(match vk {
+            0x41..=0x5A => {
+                if self.is_down(VK::CONTROL as u32) {
+                    vk as u8 - 0x40 // ctl-A => 0x01
+                } else if shift != self.is_toggled(VK::CAPITAL as u32) {
+                    vk as u8
+                } else {
+                    vk as u8 + 0x20 // lowercase
+                }
+            }
+            0x30..=0x39 if shift => b")!@#$%^&*("[(vk - 0x30) as usize],
+            0x30..=0x39 => vk as u8,
+            _ => match VK::try_from(vk) {
+                Ok(VK::SPACE) => b' ',
+                Ok(VK::RETURN) => b'\r',
+                Ok(VK::BACK) => 0x08,
+                Ok(VK::TAB) => b'\t',
+                Ok(VK::ESCAPE) => 0x1B,
+                _ => return None,
+            },
+        })
+    }
+}
+
+/// Update the key state for a host key event and build the WM_KEYDOWN/WM_KEYUP for it.
+pub fn key_message(machine: &mut Machine, hwnd: HWND, key: &host::KeyMessage) -> MSG {
+    let keys = &mut machine.state.user32.keys;
+    let vk = key.vk & 0xFF;
+    let was_down = keys.is_down(vk);
+    keys.set(vk, key.down);
+
+    let (scan, extended) = scan_code(vk);
+    // Bits: 0-15 repeat count, 16-23 scan code, 24 extended,
+    // 30 previous key state, 31 transition state.
+    let mut lParam = 1 | (scan << 16) | ((extended as u32) << 24);
+    if was_down {
+        lParam |= 1 << 30;
+    }
+    if !key.down {
+        lParam |= 1 << 31;
+    }
+    // TODO: WM_SYSKEYDOWN/WM_SYSKEYUP when alt is held.
+    MSG {
+        hwnd,
+        message: if key.down { WM::KEYDOWN } else { WM::KEYUP } as u32,
+        wParam: vk,
+        lParam,
+        time: 0,
+        pt_x: 0,
+        pt_y: 0,
+    }
+}
+
+#[win32_derive::dllexport]
+pub fn TranslateMessage(machine: &mut Machine, lpMsg: Option<&MSG>) -> bool {
+    let Some(msg) = lpMsg else {
+        return false;
+    };
+    match WM::try_from(msg.message) {
+        Ok(WM::KEYDOWN) => {
+            if let Some(c) = machine.state.user32.keys.to_char(msg.wParam) {
+                // Posted so that it's the next message retrieved.
+                machine.state.user32.messages.push_front(MSG {
+                    hwnd: msg.hwnd,
+                    message: WM::CHAR as u32,
+                    wParam: c as u32,
+                    lParam: msg.lParam,
+                    time: msg.time,
+                    pt_x: msg.pt_x,
+                    pt_y: msg.pt_y,
+                });
+            }
+            true
+        }
+        // Nonzero for all key messages, whether or not a character was produced.
+        Ok(WM::KEYUP) => true,
+        _ => false,
+    }
+}
+
+#[win32_derive::dllexport]
+pub fn GetKeyState(machine: &mut Machine, nVirtKey: u32) -> i16 {
+    let keys = &machine.state.user32.keys;
+    let mut state = 0u16;
+    if keys.is_down(nVirtKey) {
+        state |= 0x8000;
+    }
+    if keys.is_toggled(nVirtKey) {
+        state |= 0x0001;
+    }
+    state as i16
+}
+
+#[win32_derive::dllexport]
+pub fn GetAsyncKeyState(machine: &mut Machine, vKey: u32) -> i16 {
+    // There's no separate hardware state: keys are tracked as host events are queued.
+    if machine.state.user32.keys.is_down(vKey) {
+        0x8000u16 as i16
+    } else {
+        0
+    }
+}
@@ -1,11 +1,18 @@
-use super::WindowType;
-use crate::{host, winapi::types::*, Machine, MouseButton};
+use super::{
+    dispatch_dialog_message, dispatch_timer_proc, host_activate, is_dialog, is_system_class,
+    key_message, mouse_message, mouse_wheel_message, WindowType,
+};
+use crate::{
+    host,
+    winapi::{dsound, kernel32::set_last_error, types::*, ERROR},
+    Machine,
+};
 use bitflags::bitflags;
 
 const TRACE_CONTEXT: &'static str = "user32/message";
 
 #[repr(C)]
-#[derive(Clone)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct MSG {
     pub hwnd: HWND,
     pub message: u32,
@@ -20,6 +27,7 @@ pub struct MSG {
     // we leave it out.  See commit 30d1bb3ea9c955b82a724f36490dbe0914af5355.
 }
 unsafe impl memory::Pod for MSG {}
+const _: () = assert!(std::mem::size_of::<MSG>() == 28);
 
 // Manually implement Debug so we decode the WM_FOO value.
 impl std::fmt::Debug for MSG {
@@ -41,13 +49,24 @@ impl std::fmt::Debug for MSG {
 pub enum WM {
     NULL = 0,
     CREATE = 0x0001,
+    DESTROY = 0x0002,
     MOVE = 0x0003,
     SIZE = 0x0005,
     ACTIVATE = 0x0006,
+    SETFOCUS = 0x0007,
+    KILLFOCUS = 0x0008,
+    SETTEXT = 0x000C,
     PAINT = 0x000F,
+    CLOSE = 0x0010,
     QUIT = 0x0012,
+    ERASEBKGND = 0x0014,
     ACTIVATEAPP = 0x001C,
     WINDOWPOSCHANGED = 0x0047,
+    KEYDOWN = 0x0100,
+    KEYUP = 0x0101,
+    CHAR = 0x0102,
+    INITDIALOG = 0x0110,
+    COMMAND = 0x0111,
     TIMER = 0x0113,
     MOUSEMOVE = 0x0200,
     LBUTTONDOWN = 0x0201,
@@ -59,10 +78,11 @@ pub enum WM {
     MBUTTONDOWN = 0x0207,
     MBUTTONUP = 0x0208,
     MBUTTONDBLCLK = 0x0209,
+    MOUSEWHEEL = 0x020A,
     USER = 0x0400,
 }
 
-fn msg_from_message(message: host::Message) -> MSG {
+fn msg_from_message(machine: &mut Machine, message: host::Message) -> MSG {
     let mut msg = MSG {
         hwnd: HWND::from_raw(message.hwnd),
         message: WM::QUIT as u32, // will be overwritten
@@ -78,20 +98,15 @@ fn msg_from_message(message: host::Message) -> MSG {
             msg.message = WM::QUIT as u32;
         }
         host::MessageDetail::Mouse(mouse) => {
-            msg.message = match (mouse.button, mouse.down) {
-                (MouseButton::None, _) => WM::MOUSEMOVE,
-                (MouseButton::Left, true) => WM::LBUTTONDOWN,
-                (MouseButton::Left, false) => WM::LBUTTONUP,
-                (MouseButton::Right, true) => WM::RBUTTONDOWN,
-                (MouseButton::Right, false) => WM::RBUTTONUP,
-                (MouseButton::Middle, true) => WM::MBUTTONDOWN,
-                (MouseButton::Middle, false) => WM::MBUTTONUP,
-            } as u32;
-            msg.wParam = 0; // TODO:  modifiers
-            msg.lParam = (mouse.y << 16) | mouse.x;
-            msg.pt_x = mouse.x;
-            msg.pt_y = mouse.y;
+            msg = mouse_message(machine, msg.hwnd, mouse);
         }
+        host::MessageDetail::MouseWheel(wheel) => {
+            msg = mouse_wheel_message(machine, msg.hwnd, wheel);
+        }
+        host::MessageDetail::Key(key) => {
+            msg = key_message(machine, msg.hwnd, key);
+        }
+        host::MessageDetail::Activate(_) => unreachable!("handled by fill_message_queue"),
     }
 
     msg
@@ -121,12 +136,18 @@ fn enqueue_timer_event_if_ready(machine: &mut Machine, hwnd: HWND) -> Result<(),
 /// Returns Ok if an event is enqueued.
 /// Returns Err(wait) if we need to wait for an event.
 fn fill_message_queue(machine: &mut Machine, hwnd: HWND) -> Result<(), Option<u32>> {
+    // Keep playing sound fed while the game sits in its message loop.
+    let audio_wait = dsound::update_all(machine);
+
     if let Some(msg) = machine.host.get_message() {
-        machine
-            .state
-            .user32
-            .messages
-            .push_back(msg_from_message(msg));
+        if let host::MessageDetail::Activate(active) = msg.detail {
+            let user32 = &mut machine.state.user32;
+            let msgs = host_activate(user32, HWND::from_raw(msg.hwnd), active);
+            user32.messages.extend(msgs);
+            return Ok(());
+        }
+        let msg = msg_from_message(machine, msg);
+        machine.state.user32.messages.push_back(msg);
         return Ok(());
     }
 
@@ -134,7 +155,10 @@ fn fill_message_queue(machine: &mut Machine, hwnd: HWND) -> Result<(), Option<u3
         return Ok(());
     }
 
-    enqueue_timer_event_if_ready(machine, hwnd)
+    enqueue_timer_event_if_ready(machine, hwnd).map_err(|wait| match (wait, audio_wait) {
+        (Some(wait), Some(audio_wait)) => Some(wait.min(audio_wait)),
+        (wait, audio_wait) => wait.or(audio_wait),
+    })
 }
 
 #[cfg(feature = "x86-emu")]
@@ -187,7 +211,41 @@ fn enqueue_paint_if_needed(machine: &mut Machine, hwnd: HWND) -> bool {
         }
         hwnd
     };
-    machine.state.user32.messages.push_front(MSG {
+    if machine
+        .state
+        .user32
+        .messages
+        .iter()
+        .any(|msg| msg.hwnd == hwnd && msg.message == WM::PAINT as u32)
+    {
+        // Already pending; don't pile up duplicates while waiting for other messages.
+        return false;
+    }
+    let paints = std::iter::once(hwnd).chain(child_windows(machine, hwnd));
+    for (i, hwnd) in paints.enumerate() {
+        machine.state.user32.messages.insert(i, paint_message(hwnd));
+    }
+    true
+}
+
+/// The child windows of hwnd.  They don't have update regions of their own, so they
+/// repaint whenever their parent does, each on its own WM_PAINT after the parent's.
+pub fn child_windows(machine: &Machine, hwnd: HWND) -> Vec<HWND> {
+    let mut children: Vec<HWND> = machine
+        .state
+        .user32
+        .windows
+        .iter()
+        .filter(|w| w.parent == hwnd && matches!(w.typ, WindowType::Child))
+        .map(|w| w.hwnd)
+        .collect();
+    // In creation order, rather than the window table's arbitrary one.
+    children.sort_by_key(|hwnd| hwnd.to_raw());
+    children
+}
+
+pub fn paint_message(hwnd: HWND) -> MSG {
+    MSG {
         hwnd,
         message: WM::PAINT as u32,
         wParam: 0,
@@ -195,8 +253,7 @@ fn enqueue_paint_if_needed(machine: &mut Machine, hwnd: HWND) -> bool {
         time: 0,
         pt_x: 0,
         pt_y: 0,
-    });
-    true
+    }
 }
 
 fn find_message(machine: &mut Machine, hwnd: HWND, min: u32, max: u32) -> Option<usize> {
@@ -254,32 +311,33 @@ pub fn PeekMessageW(
     )
 }
 
-async fn get_message(
+pub async fn get_message(
     machine: &mut Machine,
     lpMsg: Option<&mut MSG>,
     hWnd: HWND,
     wMsgFilterMin: u32,
     wMsgFilterMax: u32,
 ) -> i32 {
-    assert_eq!(wMsgFilterMin, 0);
-    assert_eq!(wMsgFilterMax, 0);
-
-    loop {
+    // Check already-queued messages (e.g. from PostMessage) before asking the host,
+    // which may block.
+    let index = loop {
+        if let Some(index) = find_message(machine, hWnd, wMsgFilterMin, wMsgFilterMax) {
+            break index;
+        }
         match fill_message_queue(machine, hWnd) {
-            Ok(_) => break,
+            Ok(_) => continue,
             Err(wait_until) => await_message(machine, wait_until).await,
         }
-    }
+    };
 
-    if let Some(index) = find_message(machine, hWnd, wMsgFilterMin, wMsgFilterMax) {
-        let msg = machine.state.user32.messages.get(index).unwrap().clone();
-        machine.state.user32.messages.remove(index);
-        *lpMsg.unwrap() = msg.clone();
-        if msg.message == WM::QUIT as u32 {
-            return 0;
-        }
+    let msg = machine.state.user32.messages.remove(index).unwrap();
+    let quit = msg.message == WM::QUIT as u32;
+    *lpMsg.unwrap() = msg;
+    if quit {
+        0
+    } else {
+        1
     }
-    return 1;
 }
 
 // Note: the docs say this returns BOOL, but really it can return -1/0/nonzero.
@@ -317,27 +375,22 @@ pub async fn WaitMessage(machine: &mut Machine) -> bool {
     true
 }
 
-#[win32_derive::dllexport]
-pub fn TranslateMessage(_machine: &mut Machine, lpMsg: Option<&MSG>) -> bool {
-    // TODO: translate key-related messages into enqueuing a WM_CHAR.
-    false // no message translated
-}
-
 pub async fn dispatch_message(machine: &mut Machine, msg: &MSG) -> u32 {
     assert!(!msg.hwnd.is_null());
-    let wndproc = machine
-        .state
-        .user32
-        .windows
-        .get(msg.hwnd)
-        .unwrap()
-        .wndclass
-        .wndproc;
+    let Some(window) = machine.state.user32.windows.get(msg.hwnd) else {
+        log::warn!("dispatch to unknown window {:?}", msg.hwnd);
+        return 0;
+    };
+    let wndproc = window.wndproc;
+    if is_dialog(window) && wndproc != 0 {
+        return dispatch_dialog_message(machine, msg, wndproc).await;
+    }
     if wndproc == 0 {
-        log::error!("window has no wndproc, skipping message dispatch");
+        if !is_system_class(&window.wndclass.name) {
+            log::error!("window has no wndproc, skipping message dispatch");
+        }
         return 0;
     }
-    // TODO: SetWindowLong can change the wndproc.
     machine
         .call_x86(
             wndproc,
@@ -348,31 +401,33 @@ pub async fn dispatch_message(machine: &mut Machine, msg: &MSG) -> u32 {
                 msg.lParam,
             ],
         )
-        .await;
-    // TODO: copy eax to return value
-    0
+        .await
 }
 
 #[win32_derive::dllexport]
 pub async fn DispatchMessageA(machine: &mut Machine, lpMsg: Option<&MSG>) -> u32 {
     let msg = lpMsg.unwrap();
+    if let Some(ret) = dispatch_timer_proc(machine, msg).await {
+        return ret;
+    }
     if msg.hwnd.is_null() {
         // No associated hwnd.
         return 0;
     }
-    dispatch_message(machine, msg).await;
-    0
+    dispatch_message(machine, msg).await
 }
 
 #[win32_derive::dllexport]
 pub async fn DispatchMessageW(machine: &mut Machine, lpMsg: Option<&MSG>) -> u32 {
     let msg = lpMsg.unwrap();
+    if let Some(ret) = dispatch_timer_proc(machine, msg).await {
+        return ret;
+    }
     if msg.hwnd.is_null() {
         // No associated hwnd.
         return 0;
     }
-    dispatch_message(machine, msg).await;
-    0
+    dispatch_message(machine, msg).await
 }
 
 #[win32_derive::dllexport]
@@ -380,7 +435,7 @@ pub fn PostQuitMessage(machine: &mut Machine, nExitCode: i32) {
     machine.state.user32.messages.push_back(MSG {
         hwnd: HWND::null(),
         message: WM::QUIT as u32,
-        wParam: 0,
+        wParam: nExitCode as u32,
         lParam: 0,
         time: 0,
         pt_x: 0,
@@ -389,7 +444,13 @@ pub fn PostQuitMessage(machine: &mut Machine, nExitCode: i32) {
 }
 
 #[win32_derive::dllexport]
-pub fn PostMessageW(machine: &mut Machine, hWnd: HWND, Msg: u32, wParam: u32, lParam: u32) -> bool {
+pub fn PostMessageA(machine: &mut Machine, hWnd: HWND, Msg: u32, wParam: u32, lParam: u32) -> bool {
+    // A null hWnd posts to the thread's queue, which is the only queue we have.
+    if !hWnd.is_null() && machine.state.user32.windows.get(hWnd).is_none() {
+        log::warn!("PostMessage to unknown window {hWnd:?}");
+        set_last_error(machine, ERROR::INVALID_WINDOW_HANDLE);
+        return false;
+    }
     machine.state.user32.messages.push_back(MSG {
         hwnd: hWnd,
         message: Msg,
@@ -402,6 +463,11 @@ pub fn PostMessageW(machine: &mut Machine, hWnd: HWND, Msg: u32, wParam: u32, lP
     true
 }
 
+#[win32_derive::dllexport]
+pub fn PostMessageW(machine: &mut Machine, hWnd: HWND, Msg: u32, wParam: u32, lParam: u32) -> bool {
+    PostMessageA(machine, hWnd, Msg, wParam, lParam)
+}
+
 #[win32_derive::dllexport]
 pub fn TranslateAcceleratorW(
     _machine: &mut Machine,
@@ -416,13 +482,20 @@ pub fn TranslateAcceleratorW(
 pub async fn SendMessageA(
     machine: &mut Machine,
     hWnd: HWND,
-    Msg: Result<WM, u32>,
+    Msg: u32,
     wParam: u32,
     lParam: u32,
 ) -> u32 {
+    // There is only one thread, so every window belongs to the caller
+    // and the WndProc can be invoked directly.
+    if hWnd.is_null() || machine.state.user32.windows.get(hWnd).is_none() {
+        log::warn!("SendMessage to unknown window {hWnd:?}");
+        set_last_error(machine, ERROR::INVALID_WINDOW_HANDLE);
+        return 0;
+    }
     let msg = MSG {
         hwnd: hWnd,
-        message: Msg.unwrap() as u32,
+        message: Msg,
         wParam,
         lParam,
         time: 0,
@@ -436,11 +509,12 @@ pub async fn SendMessageA(
 pub async fn SendMessageW(
     machine: &mut Machine,
     hWnd: HWND,
-    Msg: Result<WM, u32>,
+    Msg: u32,
     wParam: u32,
     lParam: u32,
 ) -> u32 {
-    todo!()
+    // TODO: messages carrying strings need conversion for ANSI windows.
+    SendMessageA(machine, hWnd, Msg, wParam, lParam).await
 }
 
 #[win32_derive::dllexport]
@@ -1,49 +1,87 @@
-use crate::{
-    str16::Str16,
-    winapi::{stack_args::VarArgs, types::HWND},
-    Machine,
-};
-use memory::{Extensions, ExtensionsMut};
-use std::io::{Cursor, Write};
+use crate::{str16::Str16, winapi::types::HWND, Machine};
 
 const TRACE_CONTEXT: &'static str = "user32/misc";
 
 pub type HINSTANCE = u32;
 
+/// Sizes of window decorations, in pixels.  Window rect computations must agree
+/// with what GetSystemMetrics reports for these.
+pub const CAPTION_HEIGHT: i32 = 19;
+pub const MENU_HEIGHT: i32 = 19;
+pub const BORDER_WIDTH: i32 = 1;
+pub const DLGFRAME_WIDTH: i32 = 3;
+pub const FRAME_WIDTH: i32 = 4;
+
 #[derive(Debug, win32_derive::TryFromEnum)]
 pub enum SystemMetric {
     CXSCREEN = 0,
     CYSCREEN = 1,
+    CXVSCROLL = 2,
+    CYHSCROLL = 3,
     CYCAPTION = 4,
     CXBORDER = 5,
     CYBORDER = 6,
+    CXDLGFRAME = 7,
+    CYDLGFRAME = 8,
+    CXICON = 11,
+    CYICON = 12,
+    CXCURSOR = 13,
+    CYCURSOR = 14,
     CYMENU = 15,
+    CXFULLSCREEN = 16,
+    CYFULLSCREEN = 17,
+    MOUSEPRESENT = 19,
+    CYVSCROLL = 20,
+    CXHSCROLL = 21,
+    SWAPBUTTON = 23,
     CXFRAME = 32,
     CYFRAME = 33,
+    CXDOUBLECLK = 36,
+    CYDOUBLECLK = 37,
+    CXEDGE = 45,
+    CYEDGE = 46,
+    CXSMICON = 49,
+    CYSMICON = 50,
     CXVIRTUALSCREEN = 78,
     CYVIRTUALSCREEN = 79,
+    CMONITORS = 80,
 }
 
 #[win32_derive::dllexport]
-pub fn GetSystemMetrics(_machine: &mut Machine, nIndex: Result<SystemMetric, u32>) -> u32 {
+pub fn GetSystemMetrics(machine: &mut Machine, nIndex: Result<SystemMetric, u32>) -> u32 {
     let metric = match nIndex {
         Ok(metric) => metric,
         Err(val) => {
-            log::error!("GetSystemMetrics({val}) => 0");
+            log::warn!("GetSystemMetrics({val}) => 0");
             return 0;
         }
     };
+    let (width, height, _) = machine.state.ddraw.screen_mode();
     match metric {
-        SystemMetric::CXSCREEN => 640,
-        SystemMetric::CYSCREEN => 480,
-        SystemMetric::CYCAPTION => 19,
-        SystemMetric::CXBORDER => 1,
-        SystemMetric::CYBORDER => 1,
-        SystemMetric::CYMENU => 19,
-        SystemMetric::CXFRAME => 4,
-        SystemMetric::CYFRAME => 4,
-        SystemMetric::CXVIRTUALSCREEN => 640,
-        SystemMetric::CYVIRTUALSCREEN => 480,
+        SystemMetric::CXSCREEN | SystemMetric::CXVIRTUALSCREEN => width,
+        SystemMetric::CYSCREEN | SystemMetric::CYVIRTUALSCREEN => height,
+        // The size of a maximized window's client area.
+        SystemMetric::CXFULLSCREEN => width,
+        SystemMetric::CYFULLSCREEN => height - CAPTION_HEIGHT as u32,
+        SystemMetric::CYCAPTION => CAPTION_HEIGHT as u32,
+        SystemMetric::CYMENU => MENU_HEIGHT as u32,
+        SystemMetric::CXBORDER | SystemMetric::CYBORDER => BORDER_WIDTH as u32,
+        SystemMetric::CXFRAME | SystemMetric::CYFRAME => FRAME_WIDTH as u32,
+        SystemMetric::CXDLGFRAME | SystemMetric::CYDLGFRAME => DLGFRAME_WIDTH as u32,
+        SystemMetric::CXEDGE | SystemMetric::CYEDGE => 2,
+        SystemMetric::CXVSCROLL
+        | SystemMetric::CYHSCROLL
+        | SystemMetric::CYVSCROLL
+        | SystemMetric::CXHSCROLL => 16,
+        SystemMetric::CXICON
+        | SystemMetric::CYICON
+        | SystemMetric::CXCURSOR
+        | SystemMetric::CYCURSOR => 32,
+        SystemMetric::CXSMICON | SystemMetric::CYSMICON => 16,
+        SystemMetric::CXDOUBLECLK | SystemMetric::CYDOUBLECLK => 4,
+        SystemMetric::MOUSEPRESENT => 1,
+        SystemMetric::SWAPBUTTON => 0,
+        SystemMetric::CMONITORS => 1,
     }
 }
 
@@ -52,88 +90,6 @@ pub fn GetSysColor(_machine: &mut Machine, nIndex: i32) -> u32 {
     todo!();
 }
 
-#[win32_derive::dllexport(cdecl)]
-pub fn wsprintfA(machine: &mut Machine, buf: u32, fmt: Option<&str>, mut args: VarArgs) -> u32 {
-    const BUF_LEN: u32 = 1024;
-    let mem = machine.mem();
-    let buf = mem.sub32_mut(buf, BUF_LEN);
-    let mut out = Cursor::new(buf);
-
-    fn read_number(c: u8) -> usize {
-        // TODO: multiple digits, error handling, etc.
-        assert!(c >= b'0' && c <= b'9');
-        (c - b'0') as usize
-    }
-
-    let mut i = fmt.unwrap().bytes();
-    while let Some(c) = i.next() {
-        if c == b'%' {
-            let mut c = i.next().unwrap();
-            let mut width = 0;
-            if c >= b'0' && c <= b'9' {
-                width = read_number(c);
-                c = i.next().unwrap();
-            }
-            let mut precision = 0;
-            if c == b'.' {
-                c = i.next().unwrap();
-                precision = read_number(c);
-                c = i.next().unwrap();
-            }
-
-            let mut long = false;
-            if c == b'l' {
-                long = true;
-                c = i.next().unwrap();
-            }
-            _ = long; // currently ignored
-
-            match c {
-                b'u' => write!(
-                    out,
-                    "{:width$.precision$}",
-                    args.pop::<u32>(mem),
-                    width = width,
-                    precision = precision
-                )
-                .unwrap(),
-                b'd' => write!(
-                    out,
-                    "{:width$.precision$}",
-                    args.pop::<i32>(mem),
-                    width = width,
-                    precision = precision
-                )
-                .unwrap(),
-                b's' => {
-                    let addr = args.pop::<u32>(mem);
-                    let str = mem.slicez(addr);
-                    write!(out, "{}", std::str::from_utf8(str).unwrap()).unwrap();
-                }
-                _ => todo!("format string character {:?}", c as char),
-            }
-        } else {
-            out.write(&[c]).unwrap();
-        }
-    }
-    out.write(&[0]).unwrap();
-    // let len = out.position() as usize;
-    // let buf = &out.into_inner()[..len];
-    // log::info!("=> {}", std::str::from_utf8(buf).unwrap());
-    // len as u32 - 1
-    out.position() as u32 - 1
-}
-
-#[win32_derive::dllexport(cdecl)]
-pub fn wsprintfW(machine: &mut Machine, buf: u32, fmt: Option<&Str16>, args: VarArgs) -> u32 {
-    todo!()
-}
-
-#[win32_derive::dllexport]
-pub fn GetKeyState(_machine: &mut Machine, nVirtKey: u32) -> u32 {
-    0
-}
-
 #[win32_derive::dllexport]
 pub fn IsIconic(_machine: &mut Machine, hwnd: HWND) -> bool {
     false
@@ -149,8 +105,3 @@ pub fn WinHelpW(
 ) -> bool {
     todo!();
 }
-
-#[win32_derive::dllexport]
-pub fn SetCursorPos(_machine: &mut Machine, x: i32, y: i32) -> bool {
-    todo!();
-}
@@ -1,10 +1,14 @@
 #![allow(non_snake_case)]
 
 mod dialog;
+mod focus;
+mod keyboard;
 mod menu;
 mod message;
 mod misc;
+mod mouse;
 mod paint;
+mod printf;
 mod rect;
 mod resource;
 mod timer;
@@ -16,20 +20,29 @@ use super::types::HWND;
 pub use super::gdi32::HDC;
 pub use super::kernel32::ResourceKey;
 pub use dialog::*;
+pub use focus::*;
+pub use keyboard::*;
 pub use menu::*;
 pub use message::*;
 pub use misc::*;
+pub use mouse::*;
 pub use paint::*;
+pub use printf::*;
 pub use rect::*;
 pub use resource::*;
 pub use timer::*;
 pub use window::*;
 
-#[derive(Default)]
+#[derive(Default, serde::Serialize, serde::Deserialize)]
 pub struct State {
     wndclasses: Vec<std::rc::Rc<WndClass>>,
     pub user_window_message_count: u32,
     pub windows: Handles<HWND, Window>,
     messages: std::collections::VecDeque<MSG>,
     timers: Timers,
+    pub keys: KeyState,
+    pub mouse: MouseState,
+    focus: Focus,
+    /// Dialogs that EndDialog has been called on, with their results.
+    ended_dialogs: std::collections::HashMap<HWND, u32>,
 }
@@ -0,0 +1,173 @@
+//! Mouse input: WM_MOUSE* messages, capture, and the cursor position.
+
+use super::{MSG, VK, WM};
+use crate::{
+    host,
+    winapi::types::{HWND, POINT},
+    Machine, MouseButton,
+};
+
+const TRACE_CONTEXT: &'static str = "user32/mouse";
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct MouseState {
+    /// Last known cursor position, in screen coordinates.
+    pub pos: (i32, i32),
+    /// Window that receives mouse input regardless of the cursor position, per SetCapture.
+    pub capture: HWND,
+}
+
+/// MK_* flags, for the wParam of mouse messages.
+const MK_LBUTTON: u32 = 0x0001;
+const MK_RBUTTON: u32 = 0x0002;
+const MK_SHIFT: u32 = 0x0004;
+const MK_CONTROL: u32 = 0x0008;
+const MK_MBUTTON: u32 = 0x0010;
+
+const WHEEL_DELTA: i32 = 120;
+
+/// Screen position of a window's client area, or 0,0 for an unknown window.
+fn client_origin(machine: &Machine, hwnd: HWND) -> (i32, i32) {
+    match machine.state.user32.windows.get(hwnd) {
+        Some(window) => window.client_origin(),
+        None => (0, 0),
+    }
+}
+
+fn mk_flags(machine: &Machine) -> u32 {
+    let keys = &machine.state.user32.keys;
+    let mut flags = 0;
+    for (vk, mk) in [
+        (VK::LBUTTON, MK_LBUTTON),
+        (VK::RBUTTON, MK_RBUTTON),
+        (VK::MBUTTON, MK_MBUTTON),
+        (VK::SHIFT, MK_SHIFT),
+        (VK::CONTROL, MK_CONTROL),
+    ] {
+        if keys.is_down(vk as u32) {
+            flags |= mk;
+        }
+    }
+    flags
+}
+
+/// Pack a coordinate pair into an lParam as two signed 16-bit values.
+fn make_lparam(x: i32, y: i32) -> u32 {
+    ((y as u16 as u32) << 16) | (x as u16 as u32)
+}
+
+/// Track the cursor and button state for a host mouse event and build the message for it.
+/// The host reports coordinates relative to the client area of hwnd.
+pub fn mouse_message(machine: &mut Machine, hwnd: HWND, mouse: &host::MouseMessage) -> MSG {
+    let (message, vk) = match (mouse.button, mouse.down) {
+        (MouseButton::None, _) => (WM::MOUSEMOVE, None),
+        (MouseButton::Left, true) => (WM::LBUTTONDOWN, Some(VK::LBUTTON)),
+        (MouseButton::Left, false) => (WM::LBUTTONUP, Some(VK::LBUTTON)),
+        (MouseButton::Right, true) => (WM::RBUTTONDOWN, Some(VK::RBUTTON)),
+        (MouseButton::Right, false) => (WM::RBUTTONUP, Some(VK::RBUTTON)),
+        (MouseButton::Middle, true) => (WM::MBUTTONDOWN, Some(VK::MBUTTON)),
+        (MouseButton::Middle, false) => (WM::MBUTTONUP, Some(VK::MBUTTON)),
+    };
+    if let Some(vk) = vk {
+        machine.state.user32.keys.set(vk as u32, mouse.down);
+    }
+
+    // Hosts may report positions outside the window (e.g. while dragging), so
+    // treat them as signed.
+    let (origin_x, origin_y) = client_origin(machine, hwnd);
+    let screen = (mouse.x as i32 + origin_x, mouse.y as i32 + origin_y);
+    machine.state.user32.mouse.pos = screen;
+
+    let target = match machine.state.user32.mouse.capture {
+        capture if !capture.is_null() => capture,
+        _ => hwnd,
+    };
+    let (origin_x, origin_y) = client_origin(machine, target);
+    MSG {
+        hwnd: target,
+        message: message as u32,
+        wParam: mk_flags(machine),
+        lParam: make_lparam(screen.0 - origin_x, screen.1 - origin_y),
+        time: 0,
+        pt_x: screen.0 as u32,
+        pt_y: screen.1 as u32,
+    }
+}
+
+/// Build the WM_MOUSEWHEEL for a host wheel event, at the last known cursor position.
+pub fn mouse_wheel_message(
+    machine: &mut Machine,
+    hwnd: HWND,
+    wheel: &host::MouseWheelMessage,
+) -> MSG {
+    let (x, y) = machine.state.user32.mouse.pos;
+    let delta = wheel.delta * WHEEL_DELTA;
+    MSG {
+        hwnd,
+        message: WM::MOUSEWHEEL as u32,
+        wParam: ((delta as u16 as u32) << 16) | mk_flags(machine),
+        // Unlike the other mouse messages, the position is in screen coordinates.
+        lParam: make_lparam(x, y),
+        time: 0,
+        pt_x: x as u32,
+        pt_y: y as u32,
+    }
+}
+
+#[win32_derive::dllexport]
+pub fn SetCapture(machine: &mut Machine, hwnd: HWND) -> HWND {
+    std::mem::replace(&mut machine.state.user32.mouse.capture, hwnd)
+}
+
+#[win32_derive::dllexport]
+pub fn ReleaseCapture(machine: &mut Machine) -> bool {
+    machine.state.user32.mouse.capture = HWND::null();
+    true
+}
+
+#[win32_derive::dllexport]
+pub fn GetCapture(machine: &mut Machine) -> HWND {
+    machine.state.user32.mouse.capture
+}
+
+#[win32_derive::dllexport]
+pub fn GetCursorPos(machine: &mut Machine, lpPoint: Option<&mut POINT>) -> bool {
+    let Some(point) = lpPoint else {
+        return false;
+    };
+    let (x, y) = machine.state.user32.mouse.pos;
+    *point = POINT {
+        x: x as u32,
+        y: y as u32,
+    };
+    true
+}
+
+#[win32_derive::dllexport]
+pub fn SetCursorPos(machine: &mut Machine, x: i32, y: i32) -> bool {
+    // TODO: move the host cursor too.
+    machine.state.user32.mouse.pos = (x, y);
+    true
+}
+
+#[win32_derive::dllexport]
+pub fn ClientToScreen(machine: &mut Machine, hWnd: HWND, lpPoint: Option<&mut POINT>) -> bool {
+    let Some(point) = lpPoint else {
+        return false;
+    };
+    let (x, y) = client_origin(machine, hWnd);
+    point.x = (point.x as i32 + x) as u32;
+    point.y = (point.y as i32 + y) as u32;
+    true
+}
+
+#[win32_derive::dllexport]
+pub fn ScreenToClient(machine: &mut Machine, hWnd: HWND, lpPoint: Option<&mut POINT>) -> bool {
+    let Some(point) = lpPoint else {
+        return false;
+    };
+    let (x, y) = client_origin(machine, hWnd);
+    point.x = (point.x as i32 - x) as u32;
+    point.y = (point.y as i32 - y) as u32;
+    true
+}
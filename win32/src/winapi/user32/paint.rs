@@ -1,4 +1,4 @@
-use super::{UpdateRegion, WindowType, HBRUSH, HDC};
+use super::{dispatch_message, flush_window, UpdateRegion, WindowType, HBRUSH, HDC, MSG, WM};
 use crate::str16::Str16;
 use crate::{
     winapi::{
@@ -18,35 +18,36 @@ pub fn InvalidateRect(
     lpRect: Option<&RECT>,
     bErase: bool,
 ) -> bool {
-    let window = machine
-        .state
-        .user32
-        .windows
-        .get_mut(hWnd)
-        .unwrap()
-        .expect_toplevel_mut();
-    window.dirty = Some(UpdateRegion {
-        erase_background: bErase,
-    });
+    if hWnd.is_null() {
+        // Windows invalidates and redraws all windows in this case.
+        for window in machine.state.user32.windows.iter_mut() {
+            window.invalidate(None, bErase);
+        }
+        return true;
+    }
+    let Some(window) = machine.state.user32.windows.get_mut(hWnd) else {
+        return false;
+    };
+    window.invalidate(lpRect, bErase);
     true // success
 }
 
 #[win32_derive::dllexport]
 pub fn ValidateRect(machine: &mut Machine, hWnd: HWND, lpRect: Option<&RECT>) -> bool {
-    let window = machine
-        .state
-        .user32
-        .windows
-        .get_mut(hWnd)
-        .unwrap()
-        .expect_toplevel_mut();
-    match lpRect {
-        Some(_rect) => {
-            // TODO: ignored.
+    let Some(window) = machine.state.user32.windows.get_mut(hWnd) else {
+        return false;
+    };
+    let WindowType::TopLevel(top) = &mut window.typ else {
+        return true;
+    };
+    match (lpRect, &top.dirty) {
+        (None, _) => top.dirty = None,
+        (Some(rect), Some(region)) if rect.contains(&region.rect) => top.dirty = None,
+        (Some(_), _) => {
+            // TODO: partial validation; the region is a bounding box so we can't subtract.
         }
-        None => window.dirty = None,
     }
-    false // fail
+    true
 }
 
 pub type HRGN = u32;
@@ -56,16 +57,10 @@ pub fn InvalidateRgn(machine: &mut Machine, hWnd: HWND, hRgn: HRGN, bErase: bool
     if hRgn != 0 {
         todo!("invalidate specific region");
     }
-    let window = machine
-        .state
-        .user32
-        .windows
-        .get_mut(hWnd)
-        .unwrap()
-        .expect_toplevel_mut();
-    window.dirty = Some(UpdateRegion {
-        erase_background: bErase,
-    });
+    let Some(window) = machine.state.user32.windows.get_mut(hWnd) else {
+        return false;
+    };
+    window.invalidate(None, bErase);
     true // success
 }
 
@@ -82,40 +77,44 @@ pub struct PAINTSTRUCT {
 unsafe impl memory::Pod for PAINTSTRUCT {}
 
 #[win32_derive::dllexport]
-pub fn BeginPaint(machine: &mut Machine, hWnd: HWND, lpPaint: Option<&mut PAINTSTRUCT>) -> HDC {
-    let window = machine.state.user32.windows.get_mut(hWnd).unwrap();
-    // TODO: take from update region
-    let dirty_rect = RECT {
-        left: 0,
-        top: 0,
-        right: window.width as i32,
-        bottom: window.height as i32,
+pub async fn BeginPaint(
+    machine: &mut Machine,
+    hWnd: HWND,
+    lpPaint: Option<&mut PAINTSTRUCT>,
+) -> HDC {
+    let Some(window) = machine.state.user32.windows.get_mut(hWnd) else {
+        return HDC::null();
     };
-
-    let mut background_drawn = false;
-
-    let WindowType::TopLevel(toplevel) = &window.typ else {
+    let WindowType::TopLevel(toplevel) = &mut window.typ else {
         log::warn!("TODO: BeginPaint for child windows");
         return HDC::null();
     };
+    // BeginPaint validates the update region, so invalidations made while
+    // painting will produce another WM_PAINT.
+    let update = toplevel.dirty.take().unwrap_or(UpdateRegion {
+        erase_background: false,
+        rect: RECT::default(),
+    });
     let hdc = machine.state.gdi32.new_window_dc(hWnd);
-    let update = toplevel.dirty.as_ref().unwrap();
 
+    let mut erased = false;
     if update.erase_background {
-        if let Some(hbrush) = window.wndclass.background.to_option() {
-            if let gdi32::Object::Brush(brush) = machine.state.gdi32.objects.get(hbrush).unwrap() {
-                if let Some(color) = brush.color {
-                    gdi32::fill_rect(machine, hdc, &dirty_rect, color);
-                    background_drawn = true;
-                }
-            }
-        }
+        let msg = MSG {
+            hwnd: hWnd,
+            message: WM::ERASEBKGND as u32,
+            wParam: hdc.to_raw(),
+            lParam: 0,
+            time: 0,
+            pt_x: 0,
+            pt_y: 0,
+        };
+        erased = dispatch_message(machine, &msg).await != 0;
     }
 
     *lpPaint.unwrap() = PAINTSTRUCT {
         hdc,
-        fErase: (!background_drawn).into(),
-        rcPaint: dirty_rect,
+        fErase: (update.erase_background && !erased).into(),
+        rcPaint: update.rect,
         fRestore: 0,          // reserved
         fIncUpdate: 0,        // reserved
         rgbReserved: [0; 32], // reserved
@@ -125,15 +124,12 @@ pub fn BeginPaint(machine: &mut Machine, hWnd: HWND, lpPaint: Option<&mut PAINTS
 
 #[win32_derive::dllexport]
 pub fn EndPaint(machine: &mut Machine, hWnd: HWND, lpPaint: Option<&PAINTSTRUCT>) -> bool {
-    let window = machine.state.user32.windows.get_mut(hWnd).unwrap();
-    match &mut window.typ {
-        WindowType::TopLevel(toplevel) => {
-            toplevel.flush_pixels(machine.emu.memory.mem());
-            toplevel.dirty = None;
-        }
-        _ => {
-            log::warn!("TODO: EndPaint for child windows");
-        }
+    if machine.state.user32.windows.get(hWnd).is_none() {
+        return false;
+    }
+    flush_window(machine, hWnd);
+    if let Some(paint) = lpPaint {
+        machine.state.gdi32.dcs.remove(paint.hdc);
     }
     true
 }
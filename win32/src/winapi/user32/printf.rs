@@ -0,0 +1,284 @@
+//! wsprintf, which implements its own subset of printf.  Notably there is no
+//! floating point and no '*' width, and unknown conversions print as plain text.
+
+use crate::{
+    str16::Str16,
+    winapi::{kernel32::to_ansi, stack_args::VarArgs},
+    Machine,
+};
+use memory::{Extensions, ExtensionsMut, Mem};
+
+const TRACE_CONTEXT: &'static str = "user32/printf";
+
+/// wsprintf output is limited to this many bytes, including the nul.
+const MAX_LEN: usize = 1024;
+
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    alt: bool,
+    width: usize,
+    precision: Option<usize>,
+    short: bool,
+    wide: bool,
+}
+
+/// Append `body` to `out` padded to the spec's width, with `prefix` (e.g. a sign)
+/// placed before any zero padding.
+fn pad(out: &mut Vec<u8>, spec: &Spec, prefix: &[u8], body: &[u8]) {
+    let len = prefix.len() + body.len();
+    let fill = spec.width.saturating_sub(len);
+    if spec.left {
+        out.extend_from_slice(prefix);
+        out.extend_from_slice(body);
+        out.extend(std::iter::repeat(b' ').take(fill));
+    } else if spec.zero {
+        out.extend_from_slice(prefix);
+        out.extend(std::iter::repeat(b'0').take(fill));
+        out.extend_from_slice(body);
+    } else {
+        out.extend(std::iter::repeat(b' ').take(fill));
+        out.extend_from_slice(prefix);
+        out.extend_from_slice(body);
+    }
+}
+
+fn number(out: &mut Vec<u8>, spec: &Spec, prefix: &[u8], digits: String) {
+    let mut body = digits.into_bytes();
+    if let Some(precision) = spec.precision {
+        if body.len() < precision {
+            let zeros = precision - body.len();
+            body.splice(0..0, std::iter::repeat(b'0').take(zeros));
+        }
+    }
+    pad(out, spec, prefix, &body);
+}
+
+fn string(mem: Mem, addr: u32, wide: bool) -> Vec<u8> {
+    if addr == 0 {
+        return b"(null)".to_vec();
+    }
+    if wide {
+        match unsafe { Str16::from_nul_term_ptr(mem, addr) } {
+            Some(str) => str.buf().iter().map(|&c| to_ansi(c)).collect(),
+            None => vec![],
+        }
+    } else {
+        mem.slicez(addr).to_vec()
+    }
+}
+
+/// Format `fmt` per wsprintfA, pulling each argument from `next_arg`.  The result
+/// excludes the nul and is at most MAX_LEN - 1 bytes.
+fn format(mem: Mem, fmt: &[u8], mut next_arg: impl FnMut() -> u32) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < fmt.len() && out.len() < MAX_LEN {
+        let c = fmt[i];
+        i += 1;
+        if c != b'%' {
+            out.push(c);
+            continue;
+        }
+
+        let mut spec = Spec::default();
+        while let Some(&c) = fmt.get(i) {
+            match c {
+                b'-' => spec.left = true,
+                b'0' => spec.zero = true,
+                b'#' => spec.alt = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        while let Some(c @ b'0'..=b'9') = fmt.get(i) {
+            spec.width = (spec.width * 10 + (c - b'0') as usize).min(MAX_LEN);
+            i += 1;
+        }
+        if fmt.get(i) == Some(&b'.') {
+            i += 1;
+            let mut precision = 0;
+            while let Some(c @ b'0'..=b'9') = fmt.get(i) {
+                precision = (precision * 10 + (c - b'0') as usize).min(MAX_LEN);
+                i += 1;
+            }
+            spec.precision = Some(precision);
+        }
+        match fmt.get(i) {
+            Some(b'h') => {
+                spec.short = true;
+                i += 1;
+            }
+            // 'l' means long for numbers, which is no different, and wide for strings.
+            Some(b'l' | b'w') => {
+                spec.wide = true;
+                i += 1;
+            }
+            _ => {}
+        }
+
+        let Some(&conv) = fmt.get(i) else {
+            break;
+        };
+        i += 1;
+        match conv {
+            b'd' | b'i' => {
+                let mut n = next_arg() as i32;
+                if spec.short {
+                    n = n as i16 as i32;
+                }
+                let sign: &[u8] = if n < 0 { b"-" } else { b"" };
+                number(&mut out, &spec, sign, n.unsigned_abs().to_string());
+            }
+            b'u' => {
+                let mut n = next_arg();
+                if spec.short {
+                    n = n as u16 as u32;
+                }
+                number(&mut out, &spec, b"", n.to_string());
+            }
+            b'x' | b'X' => {
+                let mut n = next_arg();
+                if spec.short {
+                    n = n as u16 as u32;
+                }
+                let (digits, prefix): (_, &[u8]) = if conv == b'x' {
+                    (format!("{n:x}"), b"0x")
+                } else {
+                    (format!("{n:X}"), b"0X")
+                };
+                let prefix = if spec.alt && n != 0 { prefix } else { b"" };
+                number(&mut out, &spec, prefix, digits);
+            }
+            b'c' | b'C' => {
+                let wide = if conv == b'C' { !spec.short } else { spec.wide };
+                let n = next_arg();
+                let c = if wide { to_ansi(n as u16) } else { n as u8 };
+                spec.zero = false;
+                pad(&mut out, &spec, b"", &[c]);
+            }
+            b's' | b'S' => {
+                let wide = if conv == b'S' { !spec.short } else { spec.wide };
+                let mut s = string(mem, next_arg(), wide);
+                if let Some(precision) = spec.precision {
+                    s.truncate(precision);
+                }
+                spec.zero = false;
+                pad(&mut out, &spec, b"", &s);
+            }
+            // Anything else, including "%%" and the unsupported "%f", prints the
+            // character itself without consuming an argument.
+            c => out.push(c),
+        }
+    }
+    out.truncate(MAX_LEN - 1);
+    out
+}
+
+/// Write the formatted output and its nul to buf, returning the length sans nul.
+fn write_out(mem: Mem, buf: u32, out: &[u8]) -> u32 {
+    let dst = mem.sub32_mut(buf, out.len() as u32 + 1);
+    dst[..out.len()].copy_from_slice(out);
+    dst[out.len()] = 0;
+    out.len() as u32
+}
+
+#[win32_derive::dllexport(cdecl)]
+pub fn wsprintfA(machine: &mut Machine, buf: u32, fmt: u32, mut args: VarArgs) -> u32 {
+    if fmt == 0 {
+        log::warn!("wsprintfA: null format");
+        return 0;
+    }
+    let mem = machine.mem();
+    let out = format(mem, mem.slicez(fmt), || args.pop::<u32>(mem));
+    write_out(mem, buf, &out)
+}
+
+#[win32_derive::dllexport]
+pub fn wvsprintfA(machine: &mut Machine, buf: u32, fmt: u32, arglist: u32) -> u32 {
+    if fmt == 0 {
+        log::warn!("wvsprintfA: null format");
+        return 0;
+    }
+    let mem = machine.mem();
+    let mut arg = arglist;
+    let out = format(mem, mem.slicez(fmt), || {
+        let value = mem.get_pod::<u32>(arg);
+        arg += 4;
+        value
+    });
+    write_out(mem, buf, &out)
+}
+
+#[win32_derive::dllexport(cdecl)]
+pub fn wsprintfW(machine: &mut Machine, buf: u32, fmt: Option<&Str16>, args: VarArgs) -> u32 {
+    todo!()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprintf(mem: &[u8], fmt: &str, args: &[u32]) -> String {
+        let mut args = args.iter().copied();
+        let out = format(Mem::from_slice(mem), fmt.as_bytes(), || {
+            args.next().unwrap()
+        });
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn formats() {
+        // Aligned so the wide string at offset 8 is too.
+        #[repr(align(2))]
+        struct Buf([u8; 14]);
+        let buf = Buf(*b"\0hello\0\0w\0i\0\0\0");
+        let mem = &buf.0;
+        assert_eq!(sprintf(mem, "%d %u %i", &[-5i32 as u32, 7, 3]), "-5 7 3");
+        assert_eq!(
+            sprintf(mem, "[%5d|%-5d|%05d]", &[-42i32 as u32, 1, 2]),
+            "[  -42|1    |00002]"
+        );
+        assert_eq!(
+            sprintf(mem, "%x %X %#x %.4lx", &[255, 255, 16, 1]),
+            "ff FF 0x10 0001"
+        );
+        assert_eq!(sprintf(mem, "%s/%.3s/%6s", &[1, 1, 1]), "hello/hel/ hello");
+        assert_eq!(sprintf(mem, "%ws %c%%", &[8, b'!' as u32]), "wi !%");
+        assert_eq!(sprintf(mem, "%s", &[0]), "(null)");
+        // No floats: the conversion character is printed and no argument consumed.
+        assert_eq!(sprintf(mem, "%f %d", &[3]), "f 3");
+    }
+
+    #[test]
+    fn bounded() {
+        let out = format(Mem::from_slice(&[0]), b"%2000d", || 1);
+        assert_eq!(out.len(), MAX_LEN - 1);
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn null_format() {
+        let mut machine = crate::testing::machine();
+        assert_eq!(wvsprintfA(&mut machine, 0, 0, 0), 0);
+    }
+
+    /// The format string is ANSI bytes, not necessarily ASCII, and wide arguments are
+    /// converted to ANSI.
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn ansi() {
+        let mut machine = crate::testing::machine();
+        let fmt = crate::testing::alloc_data(&mut machine, b"caf\xE9 %ws %C\0");
+        // "\u{20AC}" (the euro sign), which is 0x80 in windows-1252.
+        let wide = crate::testing::alloc_data(&mut machine, &[0xAC, 0x20, 0, 0]);
+        let mut args = wide.to_le_bytes().to_vec();
+        // '\u{2122}' (the trademark sign), 0x99.
+        args.extend_from_slice(&0x2122u32.to_le_bytes());
+        let args = crate::testing::alloc_data(&mut machine, &args);
+        let buf = crate::testing::alloc_data(&mut machine, &[0xFF; 16]);
+        assert_eq!(wvsprintfA(&mut machine, buf, fmt, args), 8);
+        assert_eq!(machine.mem().slicez(buf), b"caf\xE9 \x80 \x99");
+    }
+}
@@ -4,12 +4,13 @@ use crate::{
     winapi::{
         bitmap::BitmapRGBA32,
         gdi32::{self, HGDIOBJ},
-        kernel32::ResourceKey,
+        kernel32::{self, ResourceKey},
+        stack_args::ArrayWithSizeMut,
         types::*,
     },
     Machine,
 };
-use memory::{Extensions, ExtensionsMut};
+use memory::ExtensionsMut;
 
 const TRACE_CONTEXT: &'static str = "user32/resource";
 
@@ -156,6 +157,7 @@ pub fn LoadBitmapA(
     load_bitmap(machine, hInstance, name.as_ref()).unwrap()
 }
 
+/// Find a string table entry, returning its UTF-16 bytes.
 fn find_string(machine: &Machine, hInstance: HINSTANCE, uID: u32) -> Option<&[u8]> {
     // Strings are stored as blocks of 16 consecutive strings.
     let (resource_id, index) = ((uID >> 4) + 1, uID & 0xF);
@@ -171,14 +173,20 @@ fn find_string(machine: &Machine, hInstance: HINSTANCE, uID: u32) -> Option<&[u8
 
     // Each block is a sequence of two byte length-prefixed strings.
     // Iterate through them to find the requested index.
+    // Lengths come from the file, so bounds check rather than trusting them.
+    let read_len = |ofs: usize| -> Option<usize> {
+        Some(u16::from_le_bytes(block.get(ofs..ofs + 2)?.try_into().unwrap()) as usize)
+    };
     let mut ofs = 0;
     for _ in 0..index {
-        let len = block.get_pod::<u16>(ofs) as u32;
-        ofs += (1 + len) * 2;
+        ofs += (1 + read_len(ofs)?) * 2;
+    }
+    let len = read_len(ofs)?;
+    if len == 0 {
+        // An empty slot in the block means the ID isn't present.
+        return None;
     }
-    let len = block.get_pod::<u16>(ofs) as u32;
-    let str = block.sub32(ofs + 2, len * 2);
-    Some(str)
+    block.get(ofs + 2..ofs + 2 + len * 2)
 }
 
 #[win32_derive::dllexport]
@@ -186,21 +194,26 @@ pub fn LoadStringA(
     machine: &mut Machine,
     hInstance: u32,
     uID: u32,
-    lpBuffer: u32,
-    cchBufferMax: u32,
+    lpBuffer: ArrayWithSizeMut<u8>,
 ) -> u32 {
-    let str = match find_string(machine, hInstance, uID) {
-        Some(str) => Str16::from_bytes(str),
-        None => return 0,
+    let Some(buf) = lpBuffer.to_option() else {
+        return 0;
+    };
+    if buf.is_empty() {
+        // Only LoadStringW documents returning a read-only pointer for cchBufferMax == 0.
+        log::warn!("LoadStringA: zero-length buffer unsupported");
+        return 0;
+    }
+    let Some(str) = find_string(machine, hInstance, uID) else {
+        return 0;
     };
-    assert!(cchBufferMax != 0); // MSDN claims this is invalid
+    let str = Str16::from_bytes(str);
 
-    let dst = machine.mem().sub32_mut(lpBuffer, cchBufferMax);
-    let copy_len = std::cmp::min(dst.len() as usize - 1, str.len());
-    for i in 0..copy_len {
-        dst[i] = str.buf()[i] as u8;
+    let copy_len = std::cmp::min(buf.len() - 1, str.len());
+    for (dst, &c) in buf.iter_mut().zip(&str.buf()[..copy_len]) {
+        *dst = kernel32::to_ansi(c);
     }
-    dst[copy_len] = 0;
+    buf[copy_len] = 0;
     copy_len as u32
 }
 
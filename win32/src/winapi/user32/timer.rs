@@ -1,10 +1,13 @@
-use crate::{winapi::types::*, Machine};
+use crate::{
+    winapi::{kernel32::set_last_error, types::*, ERROR},
+    Machine,
+};
 
 use super::{MSG, WM};
 
 const TRACE_CONTEXT: &'static str = "user32/timer";
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Timer {
     id: u32,
     /// Associated window, if any.
@@ -25,14 +28,14 @@ impl Timer {
             message: WM::TIMER as u32,
             wParam: self.id,
             lParam: self.func,
-            time: 0,
+            time: now,
             pt_x: 0,
             pt_y: 0,
         }
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct Timers(Vec<Timer>);
 
 impl Timers {
@@ -52,6 +55,37 @@ impl Timers {
     pub fn soonest(&self) -> u32 {
         self.0.iter().map(|t| t.next).min().unwrap_or(0)
     }
+
+    /// Whether func is the TimerProc of a live timer, which is required for DispatchMessage
+    /// to call it.  Stale WM_TIMER messages for killed timers are dropped.
+    fn has_proc(&self, hwnd: HWND, id: u32, func: u32) -> bool {
+        self.0
+            .iter()
+            .any(|t| t.hwnd == hwnd && t.id == id && t.func == func)
+    }
+}
+
+/// DispatchMessage of a WM_TIMER for a timer with a TimerProc, which calls that
+/// instead of the window's wndproc.  Returns None for other messages.
+pub async fn dispatch_timer_proc(machine: &mut Machine, msg: &MSG) -> Option<u32> {
+    if msg.message != WM::TIMER as u32 || msg.lParam == 0 {
+        return None;
+    }
+    if !machine
+        .state
+        .user32
+        .timers
+        .has_proc(msg.hwnd, msg.wParam, msg.lParam)
+    {
+        return Some(0);
+    }
+    machine
+        .call_x86(
+            msg.lParam,
+            vec![msg.hwnd.to_raw(), msg.message, msg.wParam, msg.time],
+        )
+        .await;
+    Some(0)
 }
 
 #[win32_derive::dllexport]
@@ -81,26 +115,32 @@ pub fn SetTimer(
     const USER_TIMER_MAXIMUM: u32 = 0x7FFF_FFFF;
     let uElapse = num_traits::clamp(uElapse, USER_TIMER_MINIMUM, USER_TIMER_MAXIMUM);
 
-    if lpTimerFunc != 0 {
-        log::warn!("timer callbacks unimplemented");
+    if !hWnd.is_null() && machine.state.user32.windows.get(hWnd).is_none() {
+        set_last_error(machine, ERROR::INVALID_WINDOW_HANDLE);
+        return 0;
     }
 
-    let id = match machine
-        .state
-        .user32
-        .timers
-        .0
+    let timers = &mut machine.state.user32.timers.0;
+    let id = match timers
         .iter_mut()
         .find(|t| t.hwnd == hWnd && t.id == nIDEvent)
     {
         Some(timer) => {
+            // Setting an existing timer again resets it.
             timer.period = uElapse;
             timer.next = machine.host.ticks() + uElapse;
             timer.func = lpTimerFunc;
             timer.id
         }
         None => {
-            let id = machine.state.user32.timers.0.len() as u32 + 1;
+            // Window timers use the caller's id; thread timers get a fresh one.
+            let id = if !hWnd.is_null() {
+                nIDEvent
+            } else {
+                (1..)
+                    .find(|&id| !timers.iter().any(|t| t.hwnd.is_null() && t.id == id))
+                    .unwrap()
+            };
             let timer = Timer {
                 id,
                 hwnd: hWnd,
@@ -108,10 +148,14 @@ pub fn SetTimer(
                 next: machine.host.ticks() + uElapse,
                 func: lpTimerFunc,
             };
-            machine.state.user32.timers.0.push(timer);
+            timers.push(timer);
             id
         }
     };
 
+    if !hWnd.is_null() && id == 0 {
+        // The return value for window timers just needs to be nonzero.
+        return 1;
+    }
     id
 }
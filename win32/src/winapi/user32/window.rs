@@ -5,14 +5,16 @@ use crate::{
     winapi::{
         self,
         bitmap::{self, BitmapRGBA32},
-        gdi32::HDC,
+        gdi32::{self, HDC},
+        kernel32::set_last_error,
         stack_args::{ArrayWithSize, FromArg},
         types::{Str16, String16, HWND, POINT, RECT},
+        ERROR,
     },
     Host, Machine, SurfaceOptions,
 };
 use bitflags::bitflags;
-use memory::{Extensions, Mem};
+use memory::{Extensions, ExtensionsMut, Mem};
 use std::rc::Rc;
 
 const TRACE_CONTEXT: &'static str = "user32/window";
@@ -45,6 +47,7 @@ ShowWindow() {
 
 */
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct WindowPixels {
     pub bitmap: BitmapRGBA32,
 }
@@ -60,18 +63,22 @@ impl WindowPixels {
             bitmap: BitmapRGBA32 {
                 width,
                 height,
+                top_down: true,
                 pixels: bitmap::PixelData::Owned(raw),
             },
         }
     }
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct UpdateRegion {
     /// Whether to erase background in BeginPaint.
     pub erase_background: bool,
-    // TODO: rect
+    /// Bounding box of the invalid area, in client coordinates.
+    pub rect: RECT,
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct Window {
     pub hwnd: HWND,
     pub typ: WindowType,
@@ -80,17 +87,37 @@ pub struct Window {
     /// Client area height (not total window height).
     pub height: u32,
     pub wndclass: Rc<WndClass>,
+    /// Window procedure, initially the class's but changeable via SetWindowLong.
+    pub wndproc: u32,
     pub style: WindowStyle,
+    pub ex_style: u32,
+    /// GWL_USERDATA, for the program's use.
+    pub user_data: u32,
+    /// The class's cbWndExtra bytes of window memory.
+    pub extra: Vec<u8>,
+    /// Whether the window has a menu bar, which takes space from the client area.
+    pub menu: bool,
+    pub parent: HWND,
+    /// Control id, for child windows.
+    pub id: u32,
+    pub text: String,
+    /// Position, relative to the parent's client area for child windows.
+    pub x: i32,
+    pub y: i32,
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub enum WindowType {
     TopLevel(WindowTopLevel),
     Child,
 }
 
 /// Properties of only top-level windows.
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct WindowTopLevel {
+    #[serde(skip, default = "crate::save_state::detached")]
     pub host: Box<dyn host::Window>,
+    #[serde(skip, default = "crate::save_state::detached")]
     pub surface: Box<dyn host::Surface>,
     // TODO: CS_OWNDC windows do own a DC, but otherwise they don't.
     // pub hdc: HDC,
@@ -99,6 +126,47 @@ pub struct WindowTopLevel {
 }
 
 impl Window {
+    pub fn client_rect(&self) -> RECT {
+        RECT {
+            left: 0,
+            top: 0,
+            right: self.width as i32,
+            bottom: self.height as i32,
+        }
+    }
+
+    /// Screen position of the client area's top-left corner.
+    pub fn client_origin(&self) -> (i32, i32) {
+        // TODO: windows don't track their position; like GetWindowRect,
+        // pretend the window frame is at 0,0.
+        let mut rect = self.client_rect();
+        window_rect(&mut rect, self.style, self.menu);
+        (-rect.left, -rect.top)
+    }
+
+    /// Add to the update region, as InvalidateRect does.  The WM_PAINT itself
+    /// is generated when the message queue is next empty.
+    pub fn invalidate(&mut self, rect: Option<&RECT>, erase_background: bool) {
+        let rect = rect.copied().unwrap_or_else(|| self.client_rect());
+        match &mut self.typ {
+            WindowType::TopLevel(top) => {
+                top.dirty = Some(match top.dirty.take() {
+                    Some(region) => UpdateRegion {
+                        erase_background: region.erase_background || erase_background,
+                        rect: region.rect.union(&rect),
+                    },
+                    None => UpdateRegion {
+                        erase_background,
+                        rect,
+                    },
+                });
+            }
+            WindowType::Child => {
+                // TODO: child windows don't paint yet.
+            }
+        }
+    }
+
     // TODO: expect_toplevel was added to introduce child windows,
     // but many callers just need to handle child windows instead of calling these.
     pub fn expect_toplevel(&self) -> &WindowTopLevel {
@@ -163,16 +231,80 @@ impl WindowTopLevel {
     }
 }
 
+/// Give the top-level windows of a state loaded by restore_state() new host windows.
+pub fn reattach_windows(machine: &mut Machine) {
+    let mem = machine.emu.memory.mem();
+    for window in machine.state.user32.windows.iter_mut() {
+        let WindowType::TopLevel(top) = &mut window.typ else {
+            continue;
+        };
+        top.host = machine.host.create_window(window.hwnd.to_raw());
+        top.host.set_title(&window.text);
+        top.host.set_size(window.width, window.height);
+        top.host
+            .set_visible(window.style.contains(WindowStyle::VISIBLE));
+        top.surface = machine.host.create_surface(
+            window.hwnd.to_raw(),
+            &SurfaceOptions {
+                width: window.width,
+                height: window.height,
+                primary: true,
+            },
+        );
+        top.flush_pixels(mem);
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct WndClass {
+    pub atom: u16,
     pub name: String,
+    /// CS_* flags.
+    pub style: u32,
     pub wndproc: u32,
+    /// Bytes of extra window memory, per cbWndExtra.
+    pub wnd_extra: u32,
     pub background: HBRUSH,
+    /// Whether windows of this class get a menu bar, via lpszMenuName.
+    pub menu: bool,
+}
+
+/// Registered class atoms live in the range 0xC000-0xFFFF, like RegisterWindowMessage.
+const FIRST_CLASS_ATOM: u16 = 0xC000;
+
+impl State {
+    /// Whether a class of the given name is registered.
+    pub fn has_class(&self, name: &str) -> bool {
+        self.wndclasses
+            .iter()
+            .any(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    fn find_class(&self, name: &CreateWindowClassName<'_, Str16>) -> Option<&Rc<WndClass>> {
+        match *name {
+            CreateWindowClassName::Atom(atom) => self.wndclasses.iter().find(|c| c.atom == atom),
+            CreateWindowClassName::Name(name) => {
+                let name = name.to_string();
+                // Class names are case-insensitive.
+                self.wndclasses
+                    .iter()
+                    .find(|c| c.name.eq_ignore_ascii_case(&name))
+            }
+        }
+    }
 }
 
-fn register_class(machine: &mut Machine, wndclass: WndClass) -> u32 {
-    let atom = machine.state.user32.wndclasses.len() as u32 + 1;
-    machine.state.user32.wndclasses.push(Rc::new(wndclass));
-    atom
+/// Add a class to the registry, returning its atom or 0 on failure.
+pub fn register_class(machine: &mut Machine, mut wndclass: WndClass) -> u32 {
+    let user32 = &mut machine.state.user32;
+    if user32.has_class(&wndclass.name) {
+        set_last_error(machine, ERROR::CLASS_ALREADY_EXISTS);
+        return 0;
+    }
+    wndclass.atom = FIRST_CLASS_ATOM + user32.wndclasses.len() as u16;
+    let atom = wndclass.atom;
+    user32.wndclasses.push(Rc::new(wndclass));
+    atom as u32
 }
 
 #[repr(C, packed)]
@@ -219,9 +351,13 @@ pub fn RegisterClassW(machine: &mut Machine, lpWndClass: Option<&WNDCLASSA>) ->
         unsafe { Str16::from_nul_term_ptr(machine.mem(), lpWndClass.lpszClassName) }.unwrap();
     let background = unsafe { BrushOrColor::from_arg(machine.mem(), lpWndClass.hbrBackground) };
     let wndclass = WndClass {
+        atom: 0,
         name: name.to_string(),
+        style: lpWndClass.style,
         wndproc: lpWndClass.lpfnWndProc,
+        wnd_extra: lpWndClass.cbWndExtra,
         background: background.to_brush(machine),
+        menu: lpWndClass.lpszMenuName != 0,
     };
     register_class(machine, wndclass)
 }
@@ -267,10 +403,14 @@ pub fn RegisterClassExA(machine: &mut Machine, lpWndClassEx: Option<&WNDCLASSEXA
     let lpWndClassEx = lpWndClassEx.unwrap();
     let name = expect_ascii(machine.mem().slicez(lpWndClassEx.lpszClassName)).to_string();
     let wndclass = WndClass {
+        atom: 0,
         name,
+        style: lpWndClassEx.style,
         wndproc: lpWndClassEx.lpfnWndProc,
+        wnd_extra: lpWndClassEx.cbWndExtra,
         background: unsafe { BrushOrColor::from_arg(machine.mem(), lpWndClassEx.hbrBackground) }
             .to_brush(machine),
+        menu: lpWndClassEx.lpszMenuName != 0,
     };
     register_class(machine, wndclass)
 }
@@ -282,15 +422,20 @@ pub fn RegisterClassExW(machine: &mut Machine, lpWndClassEx: Option<&WNDCLASSEXW
         .unwrap()
         .to_string();
     let wndclass = WndClass {
+        atom: 0,
         name,
+        style: lpWndClassEx.style,
         wndproc: lpWndClassEx.lpfnWndProc,
+        wnd_extra: lpWndClassEx.cbWndExtra,
         background: unsafe { BrushOrColor::from_arg(machine.mem(), lpWndClassEx.hbrBackground) }
             .to_brush(machine),
+        menu: lpWndClassEx.lpszMenuName != 0,
     };
     register_class(machine, wndclass)
 }
 
 bitflags! {
+    #[derive(serde::Serialize, serde::Deserialize)]
     pub struct WindowStyle: u32 {
         const POPUP           = 0x80000000;
         const CHILD           = 0x40000000;
@@ -416,26 +561,32 @@ pub async fn CreateWindowExW(
     hInstance: u32,
     lpParam: u32,
 ) -> HWND {
-    let class_name = match lpClassName {
-        CreateWindowClassName::Atom(_) => unimplemented!(),
-        CreateWindowClassName::Name(name) => name.to_string(),
-    };
-    let wndclass = match machine
-        .state
-        .user32
-        .wndclasses
-        .iter()
-        .find(|c| c.name == class_name)
-    {
+    let wndclass = match machine.state.user32.find_class(&lpClassName) {
         Some(wndclass) => wndclass.clone(),
-        None => {
-            log::warn!("unknown wndclass {class_name:?}, using empty");
-            Rc::new(WndClass {
-                name: class_name,
-                wndproc: 0,
-                background: HBRUSH::null(),
-            })
-        }
+        None => match lpClassName {
+            CreateWindowClassName::Atom(atom) => {
+                log::warn!("unknown wndclass atom {atom:#x}");
+                set_last_error(machine, ERROR::CANNOT_FIND_WND_CLASS);
+                return HWND::null();
+            }
+            CreateWindowClassName::Name(name) => {
+                // TODO: system classes have no behavior of their own; dialogs draw and
+                // click their controls for them.
+                let name = name.to_string();
+                if !is_system_class(&name) {
+                    log::warn!("unknown wndclass {name:?}, using empty");
+                }
+                Rc::new(WndClass {
+                    atom: 0,
+                    name,
+                    style: 0,
+                    wndproc: 0,
+                    wnd_extra: 0,
+                    background: HBRUSH::null(),
+                    menu: false,
+                })
+            }
+        },
     };
 
     let style = dwStyle.unwrap();
@@ -444,6 +595,21 @@ pub async fn CreateWindowExW(
     // hInstance is only relevant when multiple DLLs register classes:
     //   https://devblogs.microsoft.com/oldnewthing/20050418-59/?p=35873
 
+    // The CREATESTRUCT passed with WM_CREATE lives on the heap, so fail before creating
+    // anything if there's no room for it.
+    let heap = machine
+        .state
+        .kernel32
+        .get_process_heap(&mut machine.emu.memory);
+    let createstruct_addr = heap.alloc(
+        machine.emu.memory.mem(),
+        std::mem::size_of::<CREATESTRUCTW>() as u32,
+    );
+    if createstruct_addr == 0 {
+        set_last_error(machine, ERROR::NOT_ENOUGH_MEMORY);
+        return HWND::null();
+    }
+
     let hwnd = machine.state.user32.windows.reserve();
     let width = if nWidth == CW_USEDEFAULT { 640 } else { nWidth };
     let height = if nHeight == CW_USEDEFAULT {
@@ -453,7 +619,8 @@ pub async fn CreateWindowExW(
     };
 
     let style = dwStyle.unwrap();
-    let menu = false; // TODO
+    // For child windows, hMenu is instead a control id.
+    let menu = !style.contains(WindowStyle::CHILD) && (hMenu != 0 || wndclass.menu);
     let (width, height) = client_size_from_window_size(style, menu, width, height);
 
     let typ = if style.contains(WindowStyle::CHILD) {
@@ -474,66 +641,148 @@ pub async fn CreateWindowExW(
             host: host_win,
             surface,
             pixels: None,
-            dirty: Some(UpdateRegion {
-                erase_background: true,
-            }),
+            dirty: None,
         })
     };
 
-    let window = Window {
+    let mut window = Window {
         hwnd,
         typ,
         width,
         height,
+        wndproc: wndclass.wndproc,
+        extra: vec![0; wndclass.wnd_extra as usize],
         wndclass,
         style,
+        ex_style: match dwExStyle {
+            Ok(style) => style.bits(),
+            Err(bits) => bits,
+        },
+        user_data: 0,
+        menu,
+        parent: hWndParent,
+        id: if style.contains(WindowStyle::CHILD) {
+            hMenu
+        } else {
+            0
+        },
+        text: lpWindowName.map(|s| s.to_string()).unwrap_or_default(),
+        x: if X == CW_USEDEFAULT { 0 } else { X as i32 },
+        y: if Y == CW_USEDEFAULT { 0 } else { Y as i32 },
     };
+    window.invalidate(None, true);
     machine.state.user32.windows.set(hwnd, window);
 
-    // Synchronously dispatch WM_CREATE.
+    // Synchronously dispatch WM_CREATE, with a CREATESTRUCT describing the window.
+    let createstruct = CREATESTRUCTW {
+        lpCreateParams: lpParam,
+        hInstance,
+        hMenu,
+        hwndParent: hWndParent,
+        cy: height as i32,
+        cx: width as i32,
+        y: Y as i32,
+        x: X as i32,
+        style: style.bits(),
+        lpszName: 0,  // TODO
+        lpszClass: 0, // TODO
+        dwExStyle: dwExStyle.map(|s| s.bits()).unwrap_or_else(|bits| bits),
+    };
+    machine
+        .mem()
+        .put_pod::<CREATESTRUCTW>(createstruct_addr, createstruct);
+
     let msg = MSG {
         hwnd,
         message: WM::CREATE as u32,
         wParam: 0,
-        lParam: 0, // TODO: CREATESTRUCT
+        lParam: createstruct_addr,
         time: 0,
         pt_x: 0,
         pt_y: 0,
     };
-    dispatch_message(machine, &msg).await;
+    let ret = dispatch_message(machine, &msg).await;
+
+    let heap = machine
+        .state
+        .kernel32
+        .get_process_heap(&mut machine.emu.memory);
+    heap.free(machine.emu.memory.mem(), createstruct_addr);
+
+    if ret as i32 == -1 {
+        // WndProc refused creation.
+        machine.state.user32.windows.remove(hwnd);
+        return HWND::null();
+    }
+
+    if nWidth == CW_USEDEFAULT || nHeight == CW_USEDEFAULT {
+        // We picked the size, so let the window know what it got.
+        const SIZE_RESTORED: u32 = 0;
+        let msg = MSG {
+            hwnd,
+            message: WM::SIZE as u32,
+            wParam: SIZE_RESTORED,
+            lParam: (height << 16) | width,
+            time: 0,
+            pt_x: 0,
+            pt_y: 0,
+        };
+        dispatch_message(machine, &msg).await;
+    }
 
     hwnd
 }
 
-#[win32_derive::dllexport]
-pub fn DestroyWindow(_machine: &mut Machine, hWnd: HWND) -> bool {
-    true // success
+/// Whether name is one of the predefined control classes, which have no wndproc here.
+pub fn is_system_class(name: &str) -> bool {
+    ["BUTTON", "EDIT", "STATIC", "#32770"]
+        .iter()
+        .any(|class| class.eq_ignore_ascii_case(name))
 }
 
-#[win32_derive::dllexport]
-pub fn GetDesktopWindow(_machine: &mut Machine) -> HWND {
-    HWND::null()
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct CREATESTRUCTW {
+    lpCreateParams: u32,
+    hInstance: u32,
+    hMenu: u32,
+    hwndParent: HWND,
+    cy: i32,
+    cx: i32,
+    y: i32,
+    x: i32,
+    style: u32,
+    lpszName: u32,
+    lpszClass: u32,
+    dwExStyle: u32,
 }
+unsafe impl memory::Pod for CREATESTRUCTW {}
 
 #[win32_derive::dllexport]
-pub fn GetForegroundWindow(machine: &mut Machine) -> HWND {
-    if let Some(window) = machine.state.user32.windows.iter().next() {
-        return window.hwnd;
+pub async fn DestroyWindow(machine: &mut Machine, hWnd: HWND) -> bool {
+    if machine.state.user32.windows.get(hWnd).is_none() {
+        set_last_error(machine, ERROR::INVALID_WINDOW_HANDLE);
+        return false;
     }
-    GetDesktopWindow(machine)
-}
-
-#[win32_derive::dllexport]
-pub fn SetForegroundWindow(_machine: &mut Machine, hWnd: HWND) -> bool {
-    true // success
+    // TODO: destroy child windows, WM_NCDESTROY.
+    let msg = MSG {
+        hwnd: hWnd,
+        message: WM::DESTROY as u32,
+        wParam: 0,
+        lParam: 0,
+        time: 0,
+        pt_x: 0,
+        pt_y: 0,
+    };
+    dispatch_message(machine, &msg).await;
+    forget_window(&mut machine.state.user32, hWnd);
+    machine.state.user32.windows.remove(hWnd);
+    true
 }
 
 #[win32_derive::dllexport]
-pub fn GetActiveWindow(machine: &mut Machine) -> HWND {
-    match machine.state.user32.windows.iter().next() {
-        Some(w) => w.hwnd,
-        None => HWND::null(),
-    }
+pub fn GetDesktopWindow(_machine: &mut Machine) -> HWND {
+    HWND::null()
 }
 
 #[win32_derive::dllexport]
@@ -570,16 +819,10 @@ pub async fn UpdateWindow(machine: &mut Machine, hWnd: HWND) -> bool {
         }
     }
 
-    let msg = MSG {
-        hwnd: hWnd,
-        message: WM::PAINT as u32,
-        wParam: 0,
-        lParam: 0,
-        time: 0,
-        pt_x: 0,
-        pt_y: 0,
-    };
-    dispatch_message(machine, &msg).await;
+    dispatch_message(machine, &paint_message(hWnd)).await;
+    for child in child_windows(machine, hWnd) {
+        dispatch_message(machine, &paint_message(child)).await;
+    }
 
     true // success
 }
@@ -603,34 +846,34 @@ pub enum SW {
 
 #[win32_derive::dllexport]
 pub async fn ShowWindow(machine: &mut Machine, hWnd: HWND, nCmdShow: Result<SW, u32>) -> bool {
-    dispatch_message(
-        machine,
-        &MSG {
-            hwnd: hWnd,
-            message: WM::ACTIVATEAPP as u32,
-            wParam: true as u32, // activating
-            lParam: 0,           // TODO: thread id
-            time: 0,
-            pt_x: 0,
-            pt_y: 0,
-        },
-    )
-    .await;
+    let Some(window) = machine.state.user32.windows.get_mut(hWnd) else {
+        log::warn!("ShowWindow: unknown window {hWnd:?}");
+        return false;
+    };
+    let previously_visible = window.style.contains(WindowStyle::VISIBLE);
+    let is_top_level = window.parent.is_null();
+    // TODO: minimize/maximize are treated as plain show.
+    let visible = !matches!(nCmdShow, Ok(SW::HIDE));
+    window.style.set(WindowStyle::VISIBLE, visible);
+    if let WindowType::TopLevel(top) = &mut window.typ {
+        top.host.set_visible(visible);
+    }
+    if visible && !previously_visible {
+        // Becoming visible queues the initial WM_PAINT.
+        window.invalidate(None, true);
+    }
+    if !visible {
+        return previously_visible;
+    }
 
-    const WA_ACTIVE: u32 = 1;
-    dispatch_message(
-        machine,
-        &MSG {
-            hwnd: hWnd,
-            message: WM::ACTIVATE as u32,
-            wParam: WA_ACTIVE,
-            lParam: 0, // TODO: previous window hwnd
-            time: 0,
-            pt_x: 0,
-            pt_y: 0,
-        },
-    )
-    .await;
+    if is_top_level
+        && !matches!(
+            nCmdShow,
+            Ok(SW::SHOWNOACTIVATE | SW::SHOWMINNOACTIVE | SW::SHOWNA)
+        )
+    {
+        activate(machine, hWnd).await;
+    }
 
     // TODO: WM_WINDOWPOSCHANGED should pass a WINDOWPOS struct,
     // but the DefWindowProc we provide ignores it and calls WM_MOVE/WM_SIZE directly.
@@ -649,26 +892,14 @@ pub async fn ShowWindow(machine: &mut Machine, hWnd: HWND, nCmdShow: Result<SW,
     )
     .await;
 
-    let previously_visible = true;
     previously_visible
 }
 
-#[win32_derive::dllexport]
-pub fn SetFocus(_machine: &mut Machine, hWnd: HWND) -> HWND {
-    let prev_focused = HWND::null();
-    prev_focused
-}
-
-#[win32_derive::dllexport]
-pub fn GetFocus(machine: &mut Machine) -> HWND {
-    machine.state.user32.windows.iter().next().unwrap().hwnd
-}
-
 async fn def_window_proc(
     machine: &mut Machine,
     hWnd: HWND,
     msg: Result<WM, u32>,
-    _wParam: u32,
+    wParam: u32,
     lParam: u32,
 ) -> u32 {
     let msg = match msg {
@@ -676,15 +907,34 @@ async fn def_window_proc(
         Err(_) => return 0, // ignore
     };
     match msg {
+        WM::CLOSE => {
+            DestroyWindow(machine, hWnd).await;
+        }
         WM::PAINT => {
-            let window = machine
-                .state
-                .user32
-                .windows
-                .get_mut(hWnd)
-                .unwrap()
-                .expect_toplevel_mut();
-            window.dirty = None;
+            let Some(window) = machine.state.user32.windows.get_mut(hWnd) else {
+                return 0;
+            };
+            if let WindowType::TopLevel(top) = &mut window.typ {
+                top.dirty = None;
+            }
+        }
+        WM::ERASEBKGND => {
+            let Some(window) = machine.state.user32.windows.get(hWnd) else {
+                return 0;
+            };
+            let rect = window.client_rect();
+            let Some(hbrush) = window.wndclass.background.to_option() else {
+                return 0;
+            };
+            let Some(gdi32::Object::Brush(gdi32::Brush { color: Some(color) })) =
+                machine.state.gdi32.objects.get(hbrush)
+            else {
+                return 0;
+            };
+            let color = *color;
+            let hdc = HDC::from_raw(wParam);
+            gdi32::fill_rect(machine, hdc, &rect, color);
+            return 1;
         }
         WM::WINDOWPOSCHANGED => {
             let Window { width, height, .. } = *machine.state.user32.windows.get_mut(hWnd).unwrap();
@@ -748,28 +998,26 @@ pub async fn DefWindowProcW(
 
 /// Compute window rectangle from client rectangle.
 fn window_rect(rect: &mut RECT, style: WindowStyle, menu: bool) {
-    const CAPTION: i32 = 19;
-    rect.top = rect.top - CAPTION;
-    rect.left = rect.left;
-    rect.right = rect.right;
-    rect.bottom = rect.bottom;
-    if menu {
-        rect.top -= 19;
-    }
-    if style.contains(WindowStyle::BORDER) {
-        const BORDER: i32 = 1;
-        rect.top -= BORDER;
-        rect.left -= BORDER;
-        rect.right += BORDER;
-        rect.bottom += BORDER;
+    // WS_CAPTION is BORDER|DLGFRAME.
+    if style.contains(WindowStyle::BORDER | WindowStyle::DLGFRAME) {
+        rect.top -= CAPTION_HEIGHT;
     }
-    if style.contains(WindowStyle::THICKFRAME) {
-        const FRAME: i32 = 4;
-        rect.top -= FRAME;
-        rect.left -= FRAME;
-        rect.right += FRAME;
-        rect.bottom += FRAME;
+    if menu {
+        rect.top -= MENU_HEIGHT;
     }
+    let edge = if style.contains(WindowStyle::THICKFRAME) {
+        FRAME_WIDTH
+    } else if style.contains(WindowStyle::DLGFRAME) {
+        DLGFRAME_WIDTH
+    } else if style.contains(WindowStyle::BORDER) {
+        BORDER_WIDTH
+    } else {
+        0
+    };
+    rect.top -= edge;
+    rect.left -= edge;
+    rect.right += edge;
+    rect.bottom += edge;
 }
 
 fn client_size_from_window_size(
@@ -804,13 +1052,17 @@ pub fn AdjustWindowRect(
 
 #[win32_derive::dllexport]
 pub fn AdjustWindowRectEx(
-    _machine: &mut Machine,
+    machine: &mut Machine,
     lpRect: Option<&mut RECT>,
     dwStyle: Result<WindowStyle, u32>,
     bMenu: bool,
     dwExStyle: Result<WindowStyleEx, u32>,
 ) -> bool {
-    window_rect(lpRect.unwrap(), dwStyle.unwrap(), bMenu);
+    let (Some(rect), Ok(style)) = (lpRect, dwStyle) else {
+        set_last_error(machine, ERROR::INVALID_PARAMETER);
+        return false;
+    };
+    window_rect(rect, style, bMenu);
     true
 }
 
@@ -896,8 +1148,8 @@ pub async fn SetWindowPos(
     dispatch_message(machine, &msg).await;
 
     let window = machine.state.user32.windows.get_mut(hWnd).unwrap();
-    let menu = true; // TODO
-    let (width, height) = client_size_from_window_size(window.style, menu, cx as u32, cy as u32);
+    let (width, height) =
+        client_size_from_window_size(window.style, window.menu, cx as u32, cy as u32);
     window.set_client_size(&mut *machine.host, width, height);
 
     true
@@ -914,22 +1166,21 @@ pub fn MoveWindow(
     bRepaint: bool,
 ) -> bool {
     let window = machine.state.user32.windows.get_mut(hWnd).unwrap();
-    let menu = true; // TODO
-    let (width, height) = client_size_from_window_size(window.style, menu, nWidth, nHeight);
+    let (width, height) = client_size_from_window_size(window.style, window.menu, nWidth, nHeight);
     window.set_client_size(&mut *machine.host, width, height);
     true // success
 }
 
 #[win32_derive::dllexport]
 pub fn GetClientRect(machine: &mut Machine, hWnd: HWND, lpRect: Option<&mut RECT>) -> bool {
-    let window = machine.state.user32.windows.get(hWnd).unwrap();
-    let rect = lpRect.unwrap();
-    *rect = RECT {
-        left: 0,
-        top: 0,
-        right: window.width as i32,
-        bottom: window.height as i32,
+    let Some(window) = machine.state.user32.windows.get(hWnd) else {
+        set_last_error(machine, ERROR::INVALID_WINDOW_HANDLE);
+        return false;
     };
+    let Some(rect) = lpRect else {
+        return false;
+    };
+    *rect = window.client_rect();
     true
 }
 
@@ -944,8 +1195,7 @@ pub fn GetWindowRect(machine: &mut Machine, hWnd: HWND, lpRect: Option<&mut RECT
         bottom: window.height as i32,
     };
 
-    let menu = true; // TODO
-    window_rect(&mut result, window.style, menu);
+    window_rect(&mut result, window.style, window.menu);
 
     // TODO: this pretends that the window is at 0,0
     let offset_x = -result.left;
@@ -968,57 +1218,118 @@ pub fn GetWindowPlacement(_machine: &mut Machine, hWnd: HWND, lpwndpl: Option<&m
 }
 
 #[win32_derive::dllexport]
-pub fn ClientToScreen(_machine: &mut Machine, hWnd: HWND, lpPoint: Option<&mut POINT>) -> bool {
-    true
-}
-
-#[win32_derive::dllexport]
-pub fn GetWindowDC(_machine: &mut Machine, hWnd: HWND) -> HDC {
-    HDC::null()
+pub fn GetWindowDC(machine: &mut Machine, hWnd: HWND) -> HDC {
+    // We don't draw a non-client area, so the whole window is its client area.
+    GetDC(machine, hWnd)
 }
 
 #[win32_derive::dllexport]
 pub fn ReleaseDC(machine: &mut Machine, hwnd: HWND, hdc: HDC) -> bool {
     // Note: there is also DeleteDC; this one is specifically for GetWindowDC/GetDC.
-    if let Some(dc) = machine.state.gdi32.dcs.remove(hdc) {
-        match dc.target {
-            winapi::gdi32::DCTarget::Window(dc_hwnd) => {
-                if dc_hwnd == hwnd {
-                    true
-                } else {
-                    log::warn!("ReleaseDC of DC not matching HWND");
-                    false // fail
-                }
+    if hdc.to_raw() == machine.state.gdi32.screen_dc.to_raw() {
+        // The screen DC is shared and lives forever.
+        return true;
+    }
+    let Some(dc) = machine.state.gdi32.dcs.get(hdc) else {
+        log::warn!("ReleaseDC of unknown DC");
+        return false;
+    };
+    match dc.target {
+        winapi::gdi32::DCTarget::Window(dc_hwnd) if dc_hwnd == hwnd => {}
+        winapi::gdi32::DCTarget::Window(_) => {
+            log::warn!("ReleaseDC of DC not matching HWND");
+            return false;
+        }
+        _ => {
+            log::warn!("ReleaseDC of non-window DC");
+            return false;
+        }
+    }
+    machine.state.gdi32.dcs.remove(hdc);
+    // Drawing outside of WM_PAINT should show up without waiting for the next paint.
+    flush_window(machine, hwnd);
+    true
+}
+
+/// Copy a window's pixels to its host surface, after drawing through a DC.
+pub fn flush_window(machine: &mut Machine, hwnd: HWND) {
+    let Some(window) = machine.state.user32.windows.get_mut(hwnd) else {
+        return;
+    };
+    match &mut window.typ {
+        WindowType::TopLevel(toplevel) => {
+            toplevel.flush_pixels(machine.emu.memory.mem());
+        }
+        _ => {
+            log::warn!("TODO: flush for child windows");
+        }
+    }
+}
+
+const GWL_WNDPROC: i32 = -4;
+const GWL_USERDATA: i32 = -21;
+const GWL_STYLE: i32 = -16;
+const GWL_EXSTYLE: i32 = -20;
+
+/// Read or replace (if new is Some) one of a window's longs, returning the previous value.
+fn window_long(machine: &mut Machine, hWnd: HWND, nIndex: i32, new: Option<u32>) -> i32 {
+    let Some(window) = machine.state.user32.windows.get_mut(hWnd) else {
+        set_last_error(machine, ERROR::INVALID_WINDOW_HANDLE);
+        return 0;
+    };
+    let field = match nIndex {
+        GWL_WNDPROC => &mut window.wndproc,
+        GWL_USERDATA => &mut window.user_data,
+        GWL_EXSTYLE => &mut window.ex_style,
+        GWL_STYLE => {
+            let old = window.style.bits();
+            if let Some(new) = new {
+                window.style = WindowStyle::from_bits_truncate(new);
             }
-            _ => {
-                log::warn!("ReleaseDC of non-window DC");
-                false // fail
+            return old as i32;
+        }
+        _ => {
+            // Non-negative indices are byte offsets into the extra window memory.
+            let Some(bytes) = usize::try_from(nIndex)
+                .ok()
+                .and_then(|ofs| window.extra.get_mut(ofs..ofs + 4))
+            else {
+                log::warn!("GetWindowLong({nIndex}): unknown index");
+                set_last_error(machine, ERROR::INVALID_INDEX);
+                return 0;
+            };
+            let old = u32::from_le_bytes(bytes.try_into().unwrap());
+            if let Some(new) = new {
+                bytes.copy_from_slice(&new.to_le_bytes());
             }
+            return old as i32;
         }
-    } else {
-        log::warn!("ReleaseDC of unknown DC");
-        false // fail
+    };
+    let old = *field;
+    if let Some(new) = new {
+        *field = new;
     }
+    old as i32
 }
 
 #[win32_derive::dllexport]
-pub fn GetWindowLongA(_machine: &mut Machine, hWnd: HWND, nIndex: i32) -> i32 {
-    match nIndex {
-        // GWL_STYLE
-        -16 => WindowStyle::empty().bits() as i32,
-
-        // GWL_EXSTYLE
-        -20 => WindowStyleEx::empty().bits() as i32,
+pub fn GetWindowLongA(machine: &mut Machine, hWnd: HWND, nIndex: i32) -> i32 {
+    window_long(machine, hWnd, nIndex, None)
+}
 
-        _ => todo!("GetWindowLong({nIndex})"),
-    }
+#[win32_derive::dllexport]
+pub fn SetWindowLongA(machine: &mut Machine, hWnd: HWND, nIndex: i32, dwNewLong: i32) -> i32 {
+    window_long(machine, hWnd, nIndex, Some(dwNewLong as u32))
 }
 
 #[win32_derive::dllexport]
 pub fn GetDC(machine: &mut Machine, hWnd: HWND) -> HDC {
     match hWnd.to_option() {
         Some(hwnd) => {
-            let window = machine.state.user32.windows.get(hwnd).unwrap();
+            let Some(window) = machine.state.user32.windows.get(hwnd) else {
+                set_last_error(machine, ERROR::INVALID_WINDOW_HANDLE);
+                return HDC::null();
+            };
             match &window.typ {
                 WindowType::TopLevel(_) => machine.state.gdi32.new_window_dc(hwnd),
                 _ => {
@@ -1047,24 +1358,15 @@ pub fn MapWindowPoints(
     (delta_y << 16) | delta_x
 }
 
-#[win32_derive::dllexport]
-pub fn SetCapture(_machine: &mut Machine, hwnd: HWND) -> HWND {
-    HWND::null()
-}
-
-#[win32_derive::dllexport]
-pub fn ReleaseCapture(_machine: &mut Machine) -> bool {
-    true
-}
-
 #[win32_derive::dllexport]
 pub fn SetWindowTextA(machine: &mut Machine, hWnd: HWND, lpString: Option<&str>) -> bool {
     match machine.state.user32.windows.get_mut(hWnd) {
         Some(window) => {
-            window
-                .expect_toplevel_mut()
-                .host
-                .set_title(lpString.unwrap());
+            let text = lpString.unwrap_or_default();
+            window.text = text.to_string();
+            if let WindowType::TopLevel(toplevel) = &mut window.typ {
+                toplevel.host.set_title(text);
+            }
             true
         }
         None => {
@@ -1082,11 +1384,120 @@ pub fn RegisterWindowMessageW(machine: &mut Machine, lpString: Option<&Str16>) -
 }
 
 #[win32_derive::dllexport]
-pub fn GetCapture(machine: &mut Machine) -> HWND {
+pub fn EnableWindow(_machine: &mut Machine, hWnd: HWND, bEnable: bool) -> bool {
     todo!();
 }
 
-#[win32_derive::dllexport]
-pub fn EnableWindow(_machine: &mut Machine, hWnd: HWND, bEnable: bool) -> bool {
-    todo!();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_rect_round_trip() {
+        let overlapped = WindowStyle::BORDER
+            | WindowStyle::DLGFRAME
+            | WindowStyle::SYSMENU
+            | WindowStyle::THICKFRAME;
+        for (style, menu) in [
+            (overlapped, true),
+            (overlapped, false),
+            (WindowStyle::POPUP, false),
+            (WindowStyle::POPUP | WindowStyle::BORDER, false),
+        ] {
+            let mut rect = RECT {
+                left: 0,
+                top: 0,
+                right: 320,
+                bottom: 200,
+            };
+            window_rect(&mut rect, style, menu);
+            let (w, h) = (
+                (rect.right - rect.left) as u32,
+                (rect.bottom - rect.top) as u32,
+            );
+            assert_eq!(client_size_from_window_size(style, menu, w, h), (320, 200));
+        }
+
+        let mut rect = RECT::default();
+        window_rect(&mut rect, overlapped, true);
+        assert_eq!(
+            (rect.left, rect.top, rect.right, rect.bottom),
+            (
+                -FRAME_WIDTH,
+                -(CAPTION_HEIGHT + MENU_HEIGHT + FRAME_WIDTH),
+                FRAME_WIDTH,
+                FRAME_WIDTH
+            )
+        );
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_adjust_window_rect_invalid() {
+        let mut machine = crate::testing::machine();
+        let mut rect = RECT::default();
+        assert!(!AdjustWindowRect(
+            &mut machine,
+            None,
+            Ok(WindowStyle::POPUP),
+            false
+        ));
+        assert!(!AdjustWindowRect(
+            &mut machine,
+            Some(&mut rect),
+            Err(0x0000_0001),
+            false
+        ));
+        assert_eq!(
+            crate::winapi::kernel32::GetLastError(&mut machine),
+            ERROR::INVALID_PARAMETER as u32
+        );
+        assert!(AdjustWindowRect(
+            &mut machine,
+            Some(&mut rect),
+            Ok(WindowStyle::POPUP),
+            false
+        ));
+    }
+
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_get_dc_invalid() {
+        let mut machine = crate::testing::machine();
+        assert!(GetDC(&mut machine, HWND::from_raw(0x1234)).is_null());
+        assert_eq!(
+            crate::winapi::kernel32::GetLastError(&mut machine),
+            ERROR::INVALID_WINDOW_HANDLE as u32
+        );
+        let screen_dc = machine.state.gdi32.screen_dc;
+        assert_eq!(
+            GetDC(&mut machine, HWND::null()).to_raw(),
+            screen_dc.to_raw()
+        );
+    }
+
+    /// With no heap left for the WM_CREATE CREATESTRUCT, window creation fails cleanly.
+    #[cfg(feature = "x86-emu")]
+    #[test]
+    fn test_create_window_out_of_memory() {
+        use crate::testing;
+        let mut machine = testing::machine();
+        let heap = machine
+            .state
+            .kernel32
+            .get_process_heap(&mut machine.emu.memory);
+        for shift in (2..25).rev() {
+            while heap.alloc(machine.emu.memory.mem(), 1 << shift) != 0 {}
+        }
+
+        let mut code = Vec::new();
+        testing::create_window(&mut machine, &mut code, "oom", 0x8000_0000); // WS_POPUP
+        testing::start_spinning(&mut machine, code);
+        assert_eq!(machine.emu.x86.cpu().regs.get32(x86::Register::EAX), 0);
+        assert_eq!(
+            crate::winapi::kernel32::GetLastError(&mut machine),
+            ERROR::NOT_ENOUGH_MEMORY as u32
+        );
+        assert!(machine.state.user32.windows.iter().next().is_none());
+    }
 }
@@ -14,15 +14,57 @@ pub fn timeSetEvent(
     0
 }
 
+/// Milliseconds from the same clock as GetTickCount, so the two always agree.
 #[win32_derive::dllexport]
 pub fn timeGetTime(machine: &mut Machine) -> u32 {
     machine.host.ticks()
 }
 
 const TIMERR_NOERROR: u32 = 0;
+const TIMERR_NOCANDO: u32 = 97;
+
+/// The range of timer resolutions we claim, in ms, matching real Windows.
+const PERIOD_MIN: u32 = 1;
+const PERIOD_MAX: u32 = 1_000_000;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct TIMECAPS {
+    pub wPeriodMin: u32,
+    pub wPeriodMax: u32,
+}
+unsafe impl memory::Pod for TIMECAPS {}
 
 #[win32_derive::dllexport]
-pub fn timeBeginPeriod(_machine: &mut Machine, uPeriod: u32) -> u32 {
-    // ignore
+pub fn timeGetDevCaps(_machine: &mut Machine, ptc: Option<&mut TIMECAPS>, cbtc: u32) -> u32 {
+    let Some(caps) = ptc else {
+        return TIMERR_NOCANDO;
+    };
+    if cbtc as usize != std::mem::size_of::<TIMECAPS>() {
+        return TIMERR_NOCANDO;
+    }
+    *caps = TIMECAPS {
+        wPeriodMin: PERIOD_MIN,
+        wPeriodMax: PERIOD_MAX,
+    };
     TIMERR_NOERROR
 }
+
+fn check_period(uPeriod: u32) -> u32 {
+    if (PERIOD_MIN..=PERIOD_MAX).contains(&uPeriod) {
+        TIMERR_NOERROR
+    } else {
+        TIMERR_NOCANDO
+    }
+}
+
+#[win32_derive::dllexport]
+pub fn timeBeginPeriod(_machine: &mut Machine, uPeriod: u32) -> u32 {
+    // Our timers are as precise as the host's regardless, so just validate.
+    check_period(uPeriod)
+}
+
+#[win32_derive::dllexport]
+pub fn timeEndPeriod(_machine: &mut Machine, uPeriod: u32) -> u32 {
+    check_period(uPeriod)
+}
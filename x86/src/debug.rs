@@ -1,25 +1,36 @@
-//! Disassembler producing serde/JSON for use in displaying code in the debugger.
+//! Disassembler producing serde/JSON for use in displaying code in the debugger,
+//! and instruction records for tools that lay out code or build a control flow graph.
 
 #![allow(non_snake_case)] // work around tsify generating lints
 
-use iced_x86::{Formatter, IntelFormatter};
-use memory::{Extensions, Mem};
+use iced_x86::{Decoder, DecoderOptions, Formatter, IntelFormatter, OpKind};
+use memory::Mem;
 use std::{collections::HashMap, fmt::Write};
 
 #[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
-#[derive(serde::Serialize)]
+#[derive(Debug, serde::Serialize)]
 pub struct CodePart {
     pub kind: String,
     pub text: String,
 }
 
 #[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
-#[derive(serde::Serialize)]
+#[derive(Debug, serde::Serialize)]
 pub struct Instruction {
     pub addr: u32,
+    /// Length in bytes.
+    pub len: u32,
     pub bytes: String,
     pub code: Vec<CodePart>,
     pub ops: Vec<String>,
+    /// e.g. "mov", or "db" for bytes that don't decode.
+    pub mnemonic: String,
+    /// Operands in Intel syntax, e.g. "eax,[ebp+8]".
+    pub operands: String,
+    /// The first immediate operand, if any.
+    pub immediate: Option<u32>,
+    /// Target of a direct jmp/jcc/call/loop, for following control flow.
+    pub branch_target: Option<u32>,
 }
 
 struct FormatterOutput {
@@ -34,35 +45,91 @@ impl iced_x86::FormatterOutput for FormatterOutput {
     }
 }
 
-pub fn disassemble(mem: Mem, addr: u32, limit: usize) -> Vec<Instruction> {
-    if addr >= mem.len() {
-        return Vec::new();
-    }
-    let decoder = iced_x86::Decoder::with_ip(
-        32,
-        mem.slice(addr..),
-        addr as u64,
-        iced_x86::DecoderOptions::NONE,
-    );
+/// Decode up to `limit` instructions from `mem`, which holds the code found at `addr`.
+/// Bytes that don't decode are emitted one at a time as `db`, so this is safe to point
+/// at data.
+pub fn disassemble(mem: &[u8], addr: u32, limit: usize) -> Vec<Instruction> {
+    let mut decoder = Decoder::with_ip(32, mem, addr as u64, DecoderOptions::NONE);
     let mut formatter = IntelFormatter::new();
 
     let mut instrs = Vec::new();
-    for instruction in decoder.into_iter().take(limit) {
-        let start_index = instruction.ip() as u32;
-        let instr_bytes = mem.sub32(start_index, instruction.len() as u32);
+    while instrs.len() < limit && decoder.can_decode() {
+        let pos = decoder.position();
+        let instruction = decoder.decode();
+        let instr_addr = addr.wrapping_add(pos as u32);
+        if instruction.is_invalid() {
+            // The decoder may have consumed more than one byte; resync after the first.
+            decoder.set_position(pos + 1).unwrap();
+            decoder.set_ip(instr_addr.wrapping_add(1) as u64);
+            let operands = format!("{:02X}h", mem[pos]);
+            instrs.push(Instruction {
+                addr: instr_addr,
+                len: 1,
+                bytes: format!("{:02x}", mem[pos]),
+                code: vec![
+                    CodePart {
+                        kind: "Mnemonic".into(),
+                        text: "db".into(),
+                    },
+                    CodePart {
+                        kind: "Text".into(),
+                        text: " ".into(),
+                    },
+                    CodePart {
+                        kind: "Number".into(),
+                        text: operands.clone(),
+                    },
+                ],
+                ops: Vec::new(),
+                mnemonic: "db".into(),
+                operands,
+                immediate: None,
+                branch_target: None,
+            });
+            continue;
+        }
+
         let mut bytes = String::new();
-        for &b in instr_bytes.iter() {
+        for &b in &mem[pos..pos + instruction.len()] {
             write!(&mut bytes, "{:02x}", b).unwrap();
         }
 
         let mut output = FormatterOutput { code: Vec::new() };
         formatter.format(&instruction, &mut output);
+        let mut mnemonic = String::new();
+        formatter.format_mnemonic(&instruction, &mut mnemonic);
+        let mut operands = String::new();
+        formatter.format_all_operands(&instruction, &mut operands);
+
+        let immediate = instruction
+            .op_kinds()
+            .enumerate()
+            .find_map(|(i, kind)| match kind {
+                OpKind::Immediate8
+                | OpKind::Immediate8_2nd
+                | OpKind::Immediate16
+                | OpKind::Immediate32
+                | OpKind::Immediate8to16
+                | OpKind::Immediate8to32 => Some(instruction.immediate(i as u32) as u32),
+                _ => None,
+            });
+        let branch_target = instruction.op_kinds().find_map(|kind| match kind {
+            OpKind::NearBranch16 | OpKind::NearBranch32 => {
+                Some(instruction.near_branch_target() as u32)
+            }
+            _ => None,
+        });
 
         instrs.push(Instruction {
-            addr: instruction.ip() as u32,
+            addr: instr_addr,
+            len: instruction.len() as u32,
             bytes,
             code: output.code,
             ops: instruction.op_kinds().map(|k| format!("{:?}", k)).collect(),
+            mnemonic,
+            operands,
+            immediate,
+            branch_target,
         });
     }
     instrs
@@ -91,7 +158,8 @@ pub fn dump_state(cpu: &crate::CPU, mem: Mem, labels: &HashMap<u32, String>, eip
         eip = cpu.regs.eip,
     );
     println!("nearby instructions:");
-    let instrs = disassemble(mem, cpu.regs.eip - eip_offset as u32, 5);
+    let addr = cpu.regs.eip - eip_offset as u32;
+    let instrs = disassemble(mem.slice(addr.min(mem.len())..), addr, 5);
     for instr in instrs {
         print!("{:08x} {:10} ", instr.addr, instr.bytes);
         for part in &instr.code {
@@ -107,3 +175,38 @@ pub fn dump_state(cpu: &crate::CPU, mem: Mem, labels: &HashMap<u32, String>, eip
         println!();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        let code = [
+            0xb8, 0x01, 0x00, 0x00, 0x00, // mov eax,1
+            0x74, 0x01, // je +1
+            0xc3, // ret
+            0xff, 0xff, // invalid, then truncated
+        ];
+        let insns = disassemble(&code, 0x1000, 10);
+        let summary: Vec<_> = insns
+            .iter()
+            .map(|i| (i.addr, i.len, i.mnemonic.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0x1000, 5, "mov"),
+                (0x1005, 2, "je"),
+                (0x1007, 1, "ret"),
+                (0x1008, 1, "db"),
+                (0x1009, 1, "db"),
+            ]
+        );
+        assert_eq!(insns[0].immediate, Some(1));
+        assert_eq!(insns[1].branch_target, Some(0x1008));
+        assert_eq!(insns[3].operands, "FFh");
+
+        assert_eq!(disassemble(&code, 0x1000, 2).len(), 2);
+    }
+}
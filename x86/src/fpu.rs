@@ -3,21 +3,56 @@
 use bitflags::bitflags;
 
 bitflags! {
+    #[derive(serde::Serialize, serde::Deserialize)]
     pub struct Status: u16 {
+        /// Busy.
+        const B = 1 << 15;
         const C3 = 1 << 14;
+        // Bits 11-13 are TOP, which lives in FPU::st_top instead.
         const C2 = 1 << 10;
         const C1 = 1 << 9;
         const C0 = 1 << 8;
+        /// Exception summary.
+        const ES = 1 << 7;
+        /// Stack fault.
+        const SF = 1 << 6;
+        /// Precision.
+        const PE = 1 << 5;
+        /// Underflow.
+        const UE = 1 << 4;
+        /// Overflow.
+        const OE = 1 << 3;
+        /// Divide by zero.
+        const ZE = 1 << 2;
+        /// Denormalized operand.
+        const DE = 1 << 1;
+        /// Invalid operation.
+        const IE = 1 << 0;
     }
 }
 
+/// Rounding control, bits 10-11 of the control word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Nearest = 0,
+    Down = 1,
+    Up = 2,
+    Truncate = 3,
+}
+
+/// Control word after finit: all exceptions masked, 64-bit precision, round to nearest.
+pub const DEFAULT_CONTROL: u16 = 0x37F;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct FPU {
     /// FPU ST0 through ST7 registers.
     pub st: [f64; 8],
     /// Index of top of FPU stack; 8 when stack empty.
     pub st_top: usize,
-    /// FPU status word (TODO fold st_top in here?)
+    /// FPU status word, excluding the TOP field; see status_word().
     pub status: Status,
+    /// FPU control word.
+    pub control: u16,
 }
 
 impl Default for FPU {
@@ -26,6 +61,7 @@ impl Default for FPU {
             st: [0.; 8],
             st_top: 8,
             status: Status::empty(),
+            control: DEFAULT_CONTROL,
         }
     }
 }
@@ -41,14 +77,71 @@ impl FPU {
         // log::warn!("{}", msg);
     }
 
+    /// Record a stack fault; C1 distinguishes overflow (set) from underflow (clear).
+    fn stack_fault(&mut self, overflow: bool) {
+        self.status.insert(Status::IE | Status::SF);
+        self.status.set(Status::C1, overflow);
+        Self::exception(if overflow {
+            "fpu stack overflow"
+        } else {
+            "fpu stack underflow"
+        });
+    }
+
+    /// The full status word as read by fnstsw, with TOP in bits 11-13.
+    pub fn status_word(&self) -> u16 {
+        self.status.bits() | (((self.st_top & 7) as u16) << 11)
+    }
+
+    pub fn rounding(&self) -> Rounding {
+        match (self.control >> 10) & 3 {
+            0 => Rounding::Nearest,
+            1 => Rounding::Down,
+            2 => Rounding::Up,
+            _ => Rounding::Truncate,
+        }
+    }
+
+    /// Round to an integral value according to the control word's rounding mode.
+    pub fn round(&self, val: f64) -> f64 {
+        match self.rounding() {
+            Rounding::Nearest => val.round_ties_even(),
+            Rounding::Down => val.floor(),
+            Rounding::Up => val.ceil(),
+            Rounding::Truncate => val.trunc(),
+        }
+    }
+
+    /// Convert to an integer for fist and friends, honoring the rounding mode.
+    /// Out of range values produce the "integer indefinite" value, i.e. T::MIN.
+    pub fn to_int<T: num_traits::Bounded + num_traits::NumCast>(&mut self, val: f64) -> T {
+        let rounded = self.round(val);
+        match num_traits::cast::<f64, T>(rounded) {
+            Some(i) => {
+                if rounded != val {
+                    self.status.insert(Status::PE);
+                }
+                i
+            }
+            None => {
+                self.status.insert(Status::IE);
+                T::min_value()
+            }
+        }
+    }
+
     /// Get st(0), the current top of the FPU stack.
     pub fn st0(&mut self) -> &mut f64 {
+        if self.st_top == 8 {
+            self.stack_fault(false);
+            return &mut self.st[7];
+        }
         &mut self.st[self.st_top]
     }
 
     pub fn push(&mut self, val: f64) {
         if self.st_top == 0 {
-            Self::exception("fpu stack overflow");
+            self.stack_fault(true);
             return;
         }
         self.st_top -= 1;
@@ -57,14 +150,14 @@ impl FPU {
 
     pub fn pop(&mut self) {
         if self.st_top == 8 {
-            Self::exception("fpu stack underflow");
+            self.stack_fault(false);
             return;
         }
         self.st_top += 1;
     }
 
     /// Index in self.st for a given ST0, ST1 etc reg.
-    fn st_offset(&self, reg: iced_x86::Register) -> usize {
+    fn st_offset(&mut self, reg: iced_x86::Register) -> usize {
         let ofs = match reg {
             iced_x86::Register::ST0 => 0,
             iced_x86::Register::ST1 => 1,
@@ -78,7 +171,7 @@ impl FPU {
         };
         let new = self.st_top + ofs;
         if new >= 8 {
-            Self::exception("fpu stack underflow");
+            self.stack_fault(false);
             return 7;
         }
         new
@@ -91,6 +184,146 @@ impl FPU {
     }
 
     pub fn get(&mut self, reg: iced_x86::Register) -> &mut f64 {
-        &mut self.st[self.st_offset(reg)]
+        let ofs = self.st_offset(reg);
+        &mut self.st[ofs]
+    }
+
+    /// Set C0/C2/C3 from comparing x against y, as fcom/fucom/ftst do.
+    pub fn compare(&mut self, x: f64, y: f64) {
+        let (c3, c2, c0) = match x.partial_cmp(&y) {
+            Some(std::cmp::Ordering::Greater) => (false, false, false),
+            Some(std::cmp::Ordering::Less) => (false, false, true),
+            Some(std::cmp::Ordering::Equal) => (true, false, false),
+            None => {
+                self.status.insert(Status::IE);
+                (true, true, true)
+            }
+        };
+        self.status.set(Status::C3, c3);
+        self.status.set(Status::C2, c2);
+        self.status.set(Status::C1, false);
+        self.status.set(Status::C0, c0);
+    }
+}
+
+/// Convert an 80-bit extended precision value, given as (mantissa, sign+exponent), to f64.
+pub fn f80_to_f64(mantissa: u64, sign_exp: u16) -> f64 {
+    let sign = if sign_exp & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = (sign_exp & 0x7FFF) as i32;
+    if exp == 0x7FFF {
+        return if mantissa << 1 == 0 {
+            sign * f64::INFINITY
+        } else {
+            f64::NAN
+        };
+    }
+    if mantissa == 0 {
+        return sign * 0.0;
+    }
+    // The f80 mantissa has an explicit integer bit at bit 63, so value = m * 2^(exp - 16383 - 63).
+    // Split the scaling to avoid overflowing powi for extreme exponents.
+    let e = exp - 16383 - 63;
+    sign * (mantissa as f64) * 2f64.powi(e / 2) * 2f64.powi(e - e / 2)
+}
+
+/// Convert an f64 to an 80-bit extended precision value, as (mantissa, sign+exponent).
+pub fn f64_to_f80(val: f64) -> (u64, u16) {
+    let bits = val.to_bits();
+    let sign = ((bits >> 48) as u16) & 0x8000;
+    let exp = ((bits >> 52) & 0x7FF) as i32;
+    let frac = bits & ((1 << 52) - 1);
+    match exp {
+        0 if frac == 0 => (0, sign),
+        0 => {
+            // Denormal f64s are normal in f80; shift the leading one up to bit 63.
+            let shift = frac.leading_zeros();
+            let e = -1022 - (shift as i32 - 11) + 16383;
+            (frac << shift, sign | e as u16)
+        }
+        0x7FF if frac == 0 => (1 << 63, sign | 0x7FFF),
+        0x7FF => ((1 << 63) | (1 << 62) | (frac << 11), sign | 0x7FFF),
+        _ => ((1 << 63) | (frac << 11), sign | (exp - 1023 + 16383) as u16),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iced_x86::Register;
+
+    #[test]
+    fn stack_top() {
+        let mut fpu = FPU::default();
+        assert_eq!(fpu.status_word() >> 11 & 7, 0);
+        fpu.push(1.0);
+        fpu.push(2.0);
+        assert_eq!(fpu.status_word() >> 11 & 7, 6);
+        assert_eq!(*fpu.st0(), 2.0);
+        assert_eq!(*fpu.get(Register::ST1), 1.0);
+        fpu.swap(Register::ST0, Register::ST1);
+        assert_eq!(*fpu.st0(), 1.0);
+        fpu.pop();
+        fpu.pop();
+        assert!(fpu.status.is_empty());
+        fpu.pop();
+        assert!(fpu.status.contains(Status::SF | Status::IE));
+        assert!(!fpu.status.contains(Status::C1));
+    }
+
+    #[test]
+    fn overflow() {
+        let mut fpu = FPU::default();
+        for i in 0..8 {
+            fpu.push(i as f64);
+        }
+        assert!(fpu.status.is_empty());
+        fpu.push(8.0);
+        assert!(fpu.status.contains(Status::SF | Status::C1));
+        assert_eq!(*fpu.st0(), 7.0);
+    }
+
+    #[test]
+    fn compare() {
+        let mut fpu = FPU::default();
+        let cc = Status::C3 | Status::C2 | Status::C0;
+        fpu.compare(2.0, 1.0);
+        assert_eq!(fpu.status & cc, Status::empty());
+        fpu.compare(1.0, 2.0);
+        assert_eq!(fpu.status & cc, Status::C0);
+        fpu.compare(1.0, 1.0);
+        assert_eq!(fpu.status & cc, Status::C3);
+        fpu.compare(f64::NAN, 1.0);
+        assert_eq!(fpu.status & cc, cc);
+    }
+
+    #[test]
+    fn rounding() {
+        let mut fpu = FPU::default();
+        let vals = [2.5, 3.5, -2.5, 1.7, -1.7];
+        let expected: [(u16, [i32; 5]); 4] = [
+            (0, [2, 4, -2, 2, -2]),
+            (1, [2, 3, -3, 1, -2]),
+            (2, [3, 4, -2, 2, -1]),
+            (3, [2, 3, -2, 1, -1]),
+        ];
+        for (rc, ints) in expected {
+            fpu.control = (DEFAULT_CONTROL & !0xC00) | (rc << 10);
+            let got = vals.map(|v| fpu.to_int::<i32>(v));
+            assert_eq!(got, ints, "rounding mode {rc}");
+        }
+        assert_eq!(fpu.to_int::<i16>(40000.0), i16::MIN);
+        assert!(fpu.status.contains(Status::IE));
+    }
+
+    #[test]
+    fn f80() {
+        for val in [0.0, -0.0, 1.0, -2.5, 1e300, 1e-310, std::f64::consts::PI] {
+            let (m, e) = f64_to_f80(val);
+            assert_eq!(f80_to_f64(m, e).to_bits(), val.to_bits(), "{val}");
+        }
+        // 1.0 in f80.
+        assert_eq!(f64_to_f80(1.0), (1 << 63, 0x3FFF));
+        assert_eq!(f80_to_f64(0xC000_0000_0000_0000, 0xC000), -3.0);
+        assert!(f80_to_f64(1 << 63, 0x7FFF).is_infinite());
     }
 }
@@ -0,0 +1,209 @@
+//! Per-instruction tracing, for diffing execution against a trace from real Windows.
+//!
+//! Each executed instruction produces one line of the form
+//!   `00401000: mov eax,[ebp+8] ; eax=00000001 esp=0012ff70`
+//! where the registers listed are only those the instruction changed.
+//! Call instructions are annotated with the symbol of their target, if known.
+
+use crate::{registers::Flags, Register, CPU};
+use iced_x86::{FlowControl, Formatter, IntelFormatter};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write as _,
+    io::Write,
+};
+
+const REGS: [(Register, &str); 8] = [
+    (Register::EAX, "eax"),
+    (Register::ECX, "ecx"),
+    (Register::EDX, "edx"),
+    (Register::EBX, "ebx"),
+    (Register::ESP, "esp"),
+    (Register::EBP, "ebp"),
+    (Register::ESI, "esi"),
+    (Register::EDI, "edi"),
+];
+
+/// Register state captured before an instruction, to compute deltas against.
+pub struct Snapshot {
+    r32: [u32; 8],
+    flags: Flags,
+}
+
+impl Snapshot {
+    pub fn new(cpu: &CPU) -> Self {
+        Snapshot {
+            r32: REGS.map(|(reg, _)| cpu.regs.get32(reg)),
+            flags: cpu.flags,
+        }
+    }
+}
+
+pub struct InstrTrace {
+    out: Box<dyn Write>,
+    /// If set, only the most recent `ring_size` lines are kept, to be written by dump().
+    ring: Option<(usize, VecDeque<String>)>,
+    /// Address => name, used to annotate call targets.
+    pub symbols: HashMap<u32, String>,
+    formatter: IntelFormatter,
+    line: String,
+}
+
+impl InstrTrace {
+    /// Trace every instruction as it executes to `out`.
+    pub fn new(out: Box<dyn Write>) -> Self {
+        InstrTrace {
+            out,
+            ring: None,
+            symbols: Default::default(),
+            formatter: IntelFormatter::new(),
+            line: String::new(),
+        }
+    }
+
+    /// Keep only the last `size` instructions in memory, to be written to `out` by dump().
+    pub fn new_ring(out: Box<dyn Write>, size: usize) -> Self {
+        let mut trace = Self::new(out);
+        trace.ring = Some((size, VecDeque::with_capacity(size)));
+        trace
+    }
+
+    /// Record an executed instruction, given the CPU state before and after it ran.
+    pub fn record(&mut self, instr: &iced_x86::Instruction, before: &Snapshot, cpu: &CPU) {
+        self.line.clear();
+        write!(self.line, "{:08x}: ", instr.ip32()).unwrap();
+        self.formatter.format(instr, &mut self.line);
+
+        let mut sep = " ; ";
+        for (i, (reg, name)) in REGS.iter().enumerate() {
+            let value = cpu.regs.get32(*reg);
+            if value != before.r32[i] {
+                write!(self.line, "{sep}{name}={value:08x}").unwrap();
+                sep = " ";
+            }
+        }
+        if cpu.flags != before.flags {
+            write!(self.line, "{sep}flags={:08x}", cpu.flags.bits()).unwrap();
+        }
+
+        if matches!(
+            instr.flow_control(),
+            FlowControl::Call | FlowControl::IndirectCall
+        ) {
+            let target = cpu.regs.eip;
+            match self.symbols.get(&target) {
+                Some(name) => write!(self.line, " -> {target:08x} {name}").unwrap(),
+                None => write!(self.line, " -> {target:08x}").unwrap(),
+            }
+        }
+
+        match &mut self.ring {
+            Some((0, _)) => {}
+            Some((size, ring)) => {
+                if ring.len() == *size {
+                    let mut line = ring.pop_front().unwrap();
+                    line.clear();
+                    line.push_str(&self.line);
+                    ring.push_back(line);
+                } else {
+                    ring.push_back(self.line.clone());
+                }
+            }
+            None => {
+                // Ignore write errors; a broken trace shouldn't take down the emulator.
+                _ = writeln!(self.out, "{}", self.line);
+            }
+        }
+    }
+
+    /// Write out any buffered lines, e.g. the ring contents after a crash.
+    pub fn dump(&mut self) {
+        if let Some((_, ring)) = &mut self.ring {
+            for line in ring.drain(..) {
+                _ = writeln!(self.out, "{}", line);
+            }
+        }
+        _ = self.out.flush();
+    }
+}
+
+/// Dump whatever is buffered when the trace goes away, so that the ring still gets
+/// written if the emulator panics rather than stopping cleanly.
+impl Drop for InstrTrace {
+    fn drop(&mut self) {
+        self.dump();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    impl Output {
+        fn lines(&self) -> Vec<String> {
+            let out = self.0.borrow();
+            std::str::from_utf8(&out)
+                .unwrap()
+                .lines()
+                .map(|l| l.to_string())
+                .collect()
+        }
+    }
+
+    /// Record `count` executions of `inc eax` at successive addresses.
+    fn run(trace: &mut InstrTrace, count: u32) {
+        let mut cpu = CPU::new();
+        for ip in 0..count {
+            let instr =
+                iced_x86::Decoder::with_ip(32, &[0x40], ip as u64, Default::default()).decode();
+            let before = Snapshot::new(&cpu);
+            cpu.regs.set32(Register::EAX, ip + 1);
+            trace.record(&instr, &before, &cpu);
+        }
+    }
+
+    #[test]
+    fn ring_keeps_last() {
+        let out = Output::default();
+        let mut trace = InstrTrace::new_ring(Box::new(out.clone()), 2);
+        run(&mut trace, 5);
+        assert!(out.lines().is_empty());
+        trace.dump();
+        assert_eq!(
+            out.lines(),
+            [
+                "00000003: inc eax ; eax=00000004",
+                "00000004: inc eax ; eax=00000005"
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_ring() {
+        let out = Output::default();
+        let mut trace = InstrTrace::new_ring(Box::new(out.clone()), 0);
+        run(&mut trace, 3);
+        drop(trace);
+        assert!(out.lines().is_empty());
+    }
+
+    #[test]
+    fn dump_on_drop() {
+        let out = Output::default();
+        let mut trace = InstrTrace::new_ring(Box::new(out.clone()), 4);
+        run(&mut trace, 1);
+        drop(trace);
+        assert_eq!(out.lines(), ["00000000: inc eax ; eax=00000001"]);
+    }
+}
@@ -1,10 +1,17 @@
 pub mod debug;
 mod fpu;
 mod icache;
+pub mod itrace;
+mod memmap;
 pub mod ops;
 mod registers;
+pub mod snapshot;
+#[cfg(test)]
+mod testing;
 mod x86;
 
-pub use crate::x86::{CPUState, CPU, X86};
+pub use crate::memmap::{MemoryMap, Protect, Region, Watch, WatchAction, WatchHit};
+pub use crate::registers::Flags;
+pub use crate::x86::{CPUState, Fault, CPU, X86};
 pub use iced_x86::Register;
 pub use ops::set_edx_eax;
@@ -0,0 +1,199 @@
+//! The CPU's view of which memory is mapped, and with what protection.
+//! The OS layer owns the real list of mappings and mirrors it here via X86::set_memory_map.
+
+use bitflags::bitflags;
+use std::cell::Cell;
+
+bitflags! {
+    pub struct Protect: u8 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXECUTE = 1 << 2;
+    }
+}
+
+/// A span of address space [start, end) with uniform protection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: u32,
+    pub end: u32,
+    pub protect: Protect,
+}
+
+/// What to do when a watched range is accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchAction {
+    /// Log the access and a backtrace, and keep running.
+    Log,
+    /// Log the access and stop with CPUState::DebugBreak after the instruction.
+    Break,
+}
+
+/// A watchpoint on [start, end), triggered by accesses of the given kinds.
+/// Watches are independent of regions, so they are unaffected by protection changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watch {
+    pub start: u32,
+    pub end: u32,
+    pub access: Protect,
+    pub action: WatchAction,
+}
+
+/// An access to a watched range, as recorded by the memory helpers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: u32,
+    pub len: u32,
+    pub write: bool,
+    /// Memory contents (up to 8 bytes) before and after the access; equal for reads.
+    pub old: u64,
+    pub new: u64,
+    pub action: WatchAction,
+}
+
+/// Sorted, non-overlapping regions.  Addresses outside any region are unmapped.
+/// An empty map disables checking entirely, for embedders that don't manage memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMap {
+    regions: Vec<Region>,
+    /// Watchpoints, checked on each access only when non-empty.
+    pub(crate) watches: Vec<Watch>,
+    /// Index of the region that satisfied the last lookup; accesses are very local,
+    /// so this usually saves the binary search.
+    last: Cell<usize>,
+}
+
+impl MemoryMap {
+    pub fn new(mut regions: Vec<Region>) -> Self {
+        regions.sort_by_key(|r| r.start);
+        MemoryMap {
+            regions,
+            watches: Vec::new(),
+            last: Cell::new(0),
+        }
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    fn find(&self, addr: u32) -> Option<&Region> {
+        if let Some(r) = self.regions.get(self.last.get()) {
+            if addr >= r.start && addr < r.end {
+                return Some(r);
+            }
+        }
+        let i = self.regions.partition_point(|r| r.end <= addr);
+        let r = self.regions.get(i)?;
+        if addr < r.start {
+            return None;
+        }
+        self.last.set(i);
+        Some(r)
+    }
+
+    /// Whether an access of len bytes at addr is allowed with the given protection.
+    /// Accesses may span adjacent regions, in which case all of them must allow it.
+    pub fn check(&self, mut addr: u32, len: u32, access: Protect) -> bool {
+        if self.regions.is_empty() {
+            return true;
+        }
+        let Some(end) = (addr as u64).checked_add(len as u64) else {
+            return false;
+        };
+        loop {
+            let Some(r) = self.find(addr) else {
+                return false;
+            };
+            if !r.protect.contains(access) {
+                return false;
+            }
+            if end <= r.end as u64 {
+                return true;
+            }
+            addr = r.end;
+        }
+    }
+
+    /// The action of the first watch covering any of [addr, addr+len) for this kind of access.
+    pub fn watched(&self, addr: u32, len: u32, access: Protect) -> Option<WatchAction> {
+        if self.watches.is_empty() {
+            return None;
+        }
+        let end = addr as u64 + len as u64;
+        self.watches
+            .iter()
+            .find(|w| {
+                w.access.intersects(access) && (addr as u64) < w.end as u64 && end > w.start as u64
+            })
+            .map(|w| w.action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> MemoryMap {
+        MemoryMap::new(vec![
+            Region {
+                start: 0x2000,
+                end: 0x3000,
+                protect: Protect::READ | Protect::WRITE,
+            },
+            Region {
+                start: 0,
+                end: 0x1000,
+                protect: Protect::empty(),
+            },
+            Region {
+                start: 0x3000,
+                end: 0x4000,
+                protect: Protect::READ,
+            },
+        ])
+    }
+
+    #[test]
+    fn check() {
+        let map = map();
+        assert!(!map.check(0, 1, Protect::READ));
+        assert!(!map.check(0x1800, 4, Protect::READ));
+        assert!(map.check(0x2000, 4, Protect::WRITE));
+        assert!(map.check(0x3ffc, 4, Protect::READ));
+        assert!(!map.check(0x3ffc, 4, Protect::WRITE));
+        assert!(!map.check(0x3ffe, 4, Protect::READ));
+    }
+
+    #[test]
+    fn spanning() {
+        let map = map();
+        assert!(map.check(0x2ffe, 4, Protect::READ));
+        assert!(!map.check(0x2ffe, 4, Protect::WRITE));
+        assert!(!map.check(0x0ffe, 4, Protect::READ));
+    }
+
+    #[test]
+    fn watched() {
+        let mut map = map();
+        assert_eq!(map.watched(0x2000, 4, Protect::WRITE), None);
+        map.watches.push(Watch {
+            start: 0x2010,
+            end: 0x2014,
+            access: Protect::WRITE,
+            action: WatchAction::Log,
+        });
+        assert_eq!(
+            map.watched(0x200e, 4, Protect::WRITE),
+            Some(WatchAction::Log)
+        );
+        assert_eq!(map.watched(0x200c, 4, Protect::WRITE), None);
+        assert_eq!(map.watched(0x2014, 1, Protect::WRITE), None);
+        assert_eq!(map.watched(0x2010, 4, Protect::READ), None);
+    }
+
+    #[test]
+    fn empty() {
+        assert!(MemoryMap::default().check(0, 4, Protect::WRITE));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{registers::Flags, x86::CPU, CPUState};
 use iced_x86::{Instruction, Register};
-use memory::{Extensions, ExtensionsMut, Mem};
+use memory::Mem;
 
 use super::helpers::*;
 
@@ -122,7 +122,8 @@ pub fn mov_rm8_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 
 pub fn mov_moffs8_al(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     let addr = x86_addr(cpu, instr);
-    mem.put_pod::<u8>(addr, cpu.regs.get8(Register::AL));
+    let al = cpu.regs.get8(Register::AL);
+    store::<u8>(cpu, mem, addr, al);
 }
 
 pub fn mov_r32m16_sreg(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
@@ -132,7 +133,7 @@ pub fn mov_r32m16_sreg(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
         iced_x86::OpKind::Register => cpu.regs.set32(instr.op0_register(), y as u32),
         iced_x86::OpKind::Memory => {
             let addr = x86_addr(cpu, instr);
-            mem.put_pod::<u16>(addr, y)
+            store::<u16>(cpu, mem, addr, y)
         }
         _ => unimplemented!(),
     }
@@ -143,7 +144,10 @@ pub fn mov_sreg_r32m16(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     // TODO: this is supposed to do segment selector validation stuff.
     let y = match instr.op1_kind() {
         iced_x86::OpKind::Register => cpu.regs.get32(instr.op1_register()) as u16,
-        iced_x86::OpKind::Memory => mem.get_pod::<u16>(x86_addr(cpu, instr)),
+        iced_x86::OpKind::Memory => {
+            let addr = x86_addr(cpu, instr);
+            load::<u16>(cpu, mem, addr)
+        }
         _ => unimplemented!(),
     };
     cpu.regs.set16(instr.op0_register(), y);
@@ -234,10 +238,10 @@ pub fn cmpxchg_rm32_r32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
         iced_x86::OpKind::Register => todo!(),
         iced_x86::OpKind::Memory => {
             let addr = x86_addr(cpu, instr);
-            let x = mem.get_pod::<u32>(addr);
+            let x = load::<u32>(cpu, mem, addr);
             if cpu.regs.get32(Register::EAX) == x {
+                store::<u32>(cpu, mem, addr, y);
                 cpu.flags.insert(Flags::ZF);
-                mem.put_pod::<u32>(addr, y);
             } else {
                 cpu.flags.remove(Flags::ZF);
                 cpu.regs.set32(Register::EAX, y);
@@ -249,13 +253,13 @@ pub fn cmpxchg_rm32_r32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 
 pub fn cmpxchg8b_m64(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     let addr = x86_addr(cpu, instr);
-    let m64 = mem.get_pod::<u64>(addr);
+    let m64 = load::<u64>(cpu, mem, addr);
     let test = get_edx_eax(cpu);
     if test == m64 {
-        cpu.flags.insert(Flags::ZF);
         let val =
             ((cpu.regs.get32(Register::ECX) as u64) << 32) | (cpu.regs.get32(Register::EBX) as u64);
-        mem.put_pod::<u64>(addr, val);
+        store::<u64>(cpu, mem, addr, val);
+        cpu.flags.insert(Flags::ZF);
     } else {
         cpu.flags.remove(Flags::ZF);
         set_edx_eax(cpu, m64);
@@ -473,15 +477,8 @@ pub fn bswap_r32(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
 
 pub fn xlat_m8(cpu: &mut CPU, mem: Mem, _instr: &Instruction) {
     let addr = cpu.regs.get32(Register::EBX) + (cpu.regs.get8(Register::AL) as u32);
-    cpu.regs.set8(Register::AL, mem.get_pod::<u8>(addr));
-}
-
-pub fn bts_rm32_r32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = op1_rm32(cpu, mem, instr) % 32;
-    let x = rm32(cpu, mem, instr);
-    let mask = 1u32 << y;
-    cpu.flags.set(Flags::CF, x.get() & mask != 0);
-    x.set(x.get() | mask);
+    let value = load::<u8>(cpu, mem, addr);
+    cpu.regs.set8(Register::AL, value);
 }
 
 pub fn tzcnt_r32_rm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
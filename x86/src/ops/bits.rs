@@ -0,0 +1,243 @@
+//! Bit test (BT/BTS/BTR/BTC) and bit scan (BSF/BSR) ops.
+
+use super::helpers::*;
+use crate::{registers::Flags, x86::CPU};
+use iced_x86::{Instruction, OpKind};
+use memory::Mem;
+
+/// What a bit test op does to the bit after copying it to CF.
+#[derive(Clone, Copy)]
+enum BitOp {
+    Test,
+    Set,
+    Reset,
+    Complement,
+}
+
+/// Apply op to bit of x, returning the new value and the original bit.
+fn bit_op(x: u32, bit: u32, op: BitOp) -> (u32, bool) {
+    let mask = 1 << bit;
+    let value = match op {
+        BitOp::Test => x,
+        BitOp::Set => x | mask,
+        BitOp::Reset => x & !mask,
+        BitOp::Complement => x ^ mask,
+    };
+    (value, x & mask != 0)
+}
+
+/// With a memory operand and a register bit offset, the offset is a signed index
+/// into the bit string starting at the operand, so it can address memory outside the
+/// operand itself.  Returns the byte displacement of the operand-sized unit holding
+/// the bit, and the bit's index within it.
+fn bit_string_offset(offset: i32, bits: u32) -> (i32, u32) {
+    let unit = offset >> bits.trailing_zeros();
+    (unit * (bits / 8) as i32, offset as u32 & (bits - 1))
+}
+
+/// Shared impl of the 32-bit forms, where offset is the bit offset operand.
+fn bt32(cpu: &mut CPU, mem: Mem, instr: &Instruction, offset: u32, op: BitOp) {
+    // Immediate offsets, and any offset into a register, are taken modulo the size.
+    let bit_string = instr.op0_kind() == OpKind::Memory && instr.op1_kind() == OpKind::Register;
+    let (x, bit) = if bit_string {
+        let (disp, bit) = bit_string_offset(offset as i32, 32);
+        let addr = x86_addr(cpu, instr).wrapping_add(disp as u32);
+        (mem_arg::<u32>(cpu, mem, addr), bit)
+    } else {
+        (rm32(cpu, mem, instr), offset % 32)
+    };
+    let (value, cf) = bit_op(x.get(), bit, op);
+    cpu.flags.set(Flags::CF, cf);
+    if !matches!(op, BitOp::Test) {
+        x.set(value);
+    }
+}
+
+/// Shared impl of the 16-bit forms, as with bt32.
+fn bt16(cpu: &mut CPU, mem: Mem, instr: &Instruction, offset: u16, op: BitOp) {
+    let bit_string = instr.op0_kind() == OpKind::Memory && instr.op1_kind() == OpKind::Register;
+    let (x, bit) = if bit_string {
+        let (disp, bit) = bit_string_offset(offset as i16 as i32, 16);
+        let addr = x86_addr(cpu, instr).wrapping_add(disp as u32);
+        (mem_arg::<u16>(cpu, mem, addr), bit)
+    } else {
+        (rm16(cpu, mem, instr), offset as u32 % 16)
+    };
+    let (value, cf) = bit_op(x.get() as u32, bit, op);
+    cpu.flags.set(Flags::CF, cf);
+    if !matches!(op, BitOp::Test) {
+        x.set(value as u16);
+    }
+}
+
+pub fn bt_rm32_r32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let offset = cpu.regs.get32(instr.op1_register());
+    bt32(cpu, mem, instr, offset, BitOp::Test);
+}
+
+pub fn bt_rm32_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    bt32(cpu, mem, instr, instr.immediate8() as u32, BitOp::Test);
+}
+
+pub fn bt_rm16_r16(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let offset = cpu.regs.get16(instr.op1_register());
+    bt16(cpu, mem, instr, offset, BitOp::Test);
+}
+
+pub fn bt_rm16_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    bt16(cpu, mem, instr, instr.immediate8() as u16, BitOp::Test);
+}
+
+pub fn bts_rm32_r32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let offset = cpu.regs.get32(instr.op1_register());
+    bt32(cpu, mem, instr, offset, BitOp::Set);
+}
+
+pub fn bts_rm32_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    bt32(cpu, mem, instr, instr.immediate8() as u32, BitOp::Set);
+}
+
+pub fn bts_rm16_r16(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let offset = cpu.regs.get16(instr.op1_register());
+    bt16(cpu, mem, instr, offset, BitOp::Set);
+}
+
+pub fn bts_rm16_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    bt16(cpu, mem, instr, instr.immediate8() as u16, BitOp::Set);
+}
+
+pub fn btr_rm32_r32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let offset = cpu.regs.get32(instr.op1_register());
+    bt32(cpu, mem, instr, offset, BitOp::Reset);
+}
+
+pub fn btr_rm32_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    bt32(cpu, mem, instr, instr.immediate8() as u32, BitOp::Reset);
+}
+
+pub fn btr_rm16_r16(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let offset = cpu.regs.get16(instr.op1_register());
+    bt16(cpu, mem, instr, offset, BitOp::Reset);
+}
+
+pub fn btr_rm16_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    bt16(cpu, mem, instr, instr.immediate8() as u16, BitOp::Reset);
+}
+
+pub fn btc_rm32_r32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let offset = cpu.regs.get32(instr.op1_register());
+    bt32(cpu, mem, instr, offset, BitOp::Complement);
+}
+
+pub fn btc_rm32_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    bt32(
+        cpu,
+        mem,
+        instr,
+        instr.immediate8() as u32,
+        BitOp::Complement,
+    );
+}
+
+pub fn btc_rm16_r16(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let offset = cpu.regs.get16(instr.op1_register());
+    bt16(cpu, mem, instr, offset, BitOp::Complement);
+}
+
+pub fn btc_rm16_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    bt16(
+        cpu,
+        mem,
+        instr,
+        instr.immediate8() as u16,
+        BitOp::Complement,
+    );
+}
+
+/// Index of the lowest (forward) or highest set bit of a nonzero value.
+fn bit_scan(y: u32, forward: bool) -> u32 {
+    if forward {
+        y.trailing_zeros()
+    } else {
+        31 - y.leading_zeros()
+    }
+}
+
+// For a zero source, BSF/BSR set ZF and leave the destination unchanged.  (Intel
+// documents the destination as undefined, but real CPUs leave it alone.)
+
+pub fn bsf_r32_rm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let y = op1_rm32(cpu, mem, instr);
+    cpu.flags.set(Flags::ZF, y == 0);
+    if y != 0 {
+        cpu.regs.set32(instr.op0_register(), bit_scan(y, true));
+    }
+}
+
+pub fn bsf_r16_rm16(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let y = op1_rm16(cpu, mem, instr);
+    cpu.flags.set(Flags::ZF, y == 0);
+    if y != 0 {
+        cpu.regs
+            .set16(instr.op0_register(), bit_scan(y as u32, true) as u16);
+    }
+}
+
+pub fn bsr_r32_rm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let y = op1_rm32(cpu, mem, instr);
+    cpu.flags.set(Flags::ZF, y == 0);
+    if y != 0 {
+        cpu.regs.set32(instr.op0_register(), bit_scan(y, false));
+    }
+}
+
+pub fn bsr_r16_rm16(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let y = op1_rm16(cpu, mem, instr);
+    cpu.flags.set(Flags::ZF, y == 0);
+    if y != 0 {
+        cpu.regs
+            .set16(instr.op0_register(), bit_scan(y as u32, false) as u16);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_ops() {
+        let x = 0xA5A5_0F0Fu32;
+        for bit in 0..32 {
+            let mask = 1u32 << bit;
+            let set = x & mask != 0;
+            assert_eq!(bit_op(x, bit, BitOp::Test), (x, set));
+            assert_eq!(bit_op(x, bit, BitOp::Set), (x | mask, set));
+            assert_eq!(bit_op(x, bit, BitOp::Reset), (x & !mask, set));
+            assert_eq!(bit_op(x, bit, BitOp::Complement), (x ^ mask, set));
+        }
+    }
+
+    #[test]
+    fn bit_string() {
+        for offset in 0..32 {
+            assert_eq!(bit_string_offset(offset, 32), (0, offset as u32));
+        }
+        assert_eq!(bit_string_offset(32, 32), (4, 0));
+        assert_eq!(bit_string_offset(100, 32), (12, 4));
+        assert_eq!(bit_string_offset(-1, 32), (-4, 31));
+        assert_eq!(bit_string_offset(-33, 32), (-8, 31));
+        assert_eq!(bit_string_offset(17, 16), (2, 1));
+        assert_eq!(bit_string_offset(-16, 16), (-2, 0));
+    }
+
+    #[test]
+    fn bit_scans() {
+        for bit in 0..32 {
+            let y = 1u32 << bit;
+            assert_eq!(bit_scan(y, true), bit);
+            assert_eq!(bit_scan(y, false), bit);
+            assert_eq!(bit_scan(y | 1, true), 0);
+            assert_eq!(bit_scan(y | 0x8000_0000, false), 31);
+        }
+    }
+}
@@ -1,31 +1,115 @@
+use super::helpers::set_edx_eax;
 use crate::CPU;
 use bitflags::bitflags;
 use iced_x86::{Instruction, Register};
 use memory::Mem;
+use std::collections::BTreeMap;
 
 bitflags! {
     pub struct EDXFeatures: u32 {
+        const FPU = 1 << 0;
+        const TSC = 1 << 4;
+        const CX8 = 1 << 8;
+        const CMOV = 1 << 15;
         const MMX = 1 << 23;
+        const SSE = 1 << 25;
+        const SSE2 = 1 << 26;
+    }
+}
+
+/// Result registers of a CPUID leaf.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CPUIDLeaf {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+/// The answers CPUID gives, keyed by the leaf number in eax.
+/// Public so embedders can tweak what programs see, e.g. to test their fallback paths.
+#[derive(Clone, Debug)]
+pub struct CPUIDLeaves(pub BTreeMap<u32, CPUIDLeaf>);
+
+impl Default for CPUIDLeaves {
+    fn default() -> Self {
+        let mut leaves = BTreeMap::new();
+        // Basic information: highest basic leaf, and "GenuineIntel".
+        leaves.insert(
+            0,
+            CPUIDLeaf {
+                eax: 1,
+                ebx: u32::from_le_bytes(*b"Genu"),
+                edx: u32::from_le_bytes(*b"ineI"),
+                ecx: u32::from_le_bytes(*b"ntel"),
+            },
+        );
+        // Version and features: a Pentium MMX (family 5, model 4), advertising only the
+        // features we actually emulate.  Notably no SSE, because we only have its core
+        // moves and arithmetic, and no CMOV because we lack most of the cmov/fcmov family.
+        leaves.insert(
+            1,
+            CPUIDLeaf {
+                eax: (5 << 8) | (4 << 4) | 3,
+                ebx: 0,
+                ecx: 0,
+                edx: (EDXFeatures::FPU | EDXFeatures::TSC | EDXFeatures::CX8 | EDXFeatures::MMX)
+                    .bits(),
+            },
+        );
+        // Highest extended leaf: none beyond this one.
+        leaves.insert(
+            0x8000_0000,
+            CPUIDLeaf {
+                eax: 0x8000_0000,
+                ..Default::default()
+            },
+        );
+        CPUIDLeaves(leaves)
     }
 }
 
 pub fn cpuid(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
-    match cpu.regs.get32(Register::EAX) {
-        0 => {
-            // basic information
-            cpu.regs.set32(Register::EAX, /* Pentium */ 0x2);
-            // "GenuineIntel"
-            cpu.regs.set32(Register::EBX, u32::from_le_bytes(*b"Genu"));
-            cpu.regs.set32(Register::EDX, u32::from_le_bytes(*b"ineI"));
-            cpu.regs.set32(Register::ECX, u32::from_le_bytes(*b"ntel"));
+    let leaf = cpu.regs.get32(Register::EAX);
+    let result = match cpu.cpuid.0.get(&leaf) {
+        Some(result) => *result,
+        None => {
+            log::warn!("cpuid: unknown leaf {leaf:#x}");
+            CPUIDLeaf::default()
         }
-        1 => {
-            // CPUID_GETFEATURES
-            // Just enough to convince heaven7 that we support MMX.
-            cpu.regs.set32(Register::EAX, 0);
-            cpu.regs.set32(Register::ECX, 0);
-            cpu.regs.set32(Register::EDX, EDXFeatures::MMX.bits());
-        }
-        mode => todo!("cpuid {mode}"),
+    };
+    cpu.regs.set32(Register::EAX, result.eax);
+    cpu.regs.set32(Register::EBX, result.ebx);
+    cpu.regs.set32(Register::ECX, result.ecx);
+    cpu.regs.set32(Register::EDX, result.edx);
+}
+
+/// Rate of the emulated timestamp counter.
+const TSC_PER_MS: u64 = 1_000_000;
+
+/// Timestamp counter state.  The host clock only advances when the embedder calls
+/// set_host_time, so between updates each read nudges the counter forward to keep it
+/// strictly increasing.
+#[derive(Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TimeStampCounter {
+    /// Host time in milliseconds, as of the last update.
+    host_ms: u64,
+    /// Last value returned by rdtsc.
+    last: u64,
+}
+
+impl TimeStampCounter {
+    pub fn set_host_time(&mut self, ms: u32) {
+        self.host_ms = ms as u64;
     }
+
+    fn read(&mut self) -> u64 {
+        self.last = std::cmp::max(self.host_ms * TSC_PER_MS, self.last + 1);
+        self.last
+    }
+}
+
+pub fn rdtsc(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    let tsc = cpu.tsc.read();
+    set_edx_eax(cpu, tsc);
 }
@@ -1,32 +1,32 @@
 use super::helpers::*;
 use crate::{fpu, registers::Flags, x86::CPU};
 use iced_x86::{Instruction, Register};
-use memory::{Extensions, ExtensionsMut, Mem};
+use memory::Mem;
 
 pub fn finit(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
     cpu.fpu.st_top = 8;
     cpu.fpu.status = fpu::Status::empty();
+    cpu.fpu.control = fpu::DEFAULT_CONTROL;
 }
 
-/// Compare two values and set floating-point comparison flags.
-fn fcom<T: std::cmp::PartialOrd>(cpu: &mut CPU, x: T, y: T) {
-    if x > y {
-        cpu.fpu.status.set(fpu::Status::C3, false);
-        cpu.fpu.status.set(fpu::Status::C2, false);
-        cpu.fpu.status.set(fpu::Status::C0, false);
-    } else if x < y {
-        cpu.fpu.status.set(fpu::Status::C3, false);
-        cpu.fpu.status.set(fpu::Status::C2, false);
-        cpu.fpu.status.set(fpu::Status::C0, true);
-    } else if x == y {
-        cpu.fpu.status.set(fpu::Status::C3, true);
-        cpu.fpu.status.set(fpu::Status::C2, false);
-        cpu.fpu.status.set(fpu::Status::C0, false);
-    } else {
-        cpu.fpu.status.set(fpu::Status::C3, true);
-        cpu.fpu.status.set(fpu::Status::C2, true);
-        cpu.fpu.status.set(fpu::Status::C0, true);
-    };
+pub fn fclex(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    // Clear the exception flags, leaving the condition codes alone.
+    cpu.fpu.status &= fpu::Status::C0 | fpu::Status::C1 | fpu::Status::C2 | fpu::Status::C3;
+}
+
+pub fn fincstp(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    // We track emptiness via st_top rather than a tag word, so this is an unchecked pop.
+    cpu.fpu.st_top = (cpu.fpu.st_top + 1).min(8);
+    cpu.fpu.status.remove(fpu::Status::C1);
+}
+
+pub fn fdecstp(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    cpu.fpu.st_top = cpu.fpu.st_top.saturating_sub(1);
+    cpu.fpu.status.remove(fpu::Status::C1);
+}
+
+pub fn ffree_sti(_cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    // TODO: we don't model the tag word.
 }
 
 pub fn fld1(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
@@ -45,43 +45,78 @@ pub fn fldl2e(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
     cpu.fpu.push(std::f64::consts::LOG2_E);
 }
 
+pub fn fldl2t(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    cpu.fpu.push(std::f64::consts::LOG2_10);
+}
+
+pub fn fldlg2(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    cpu.fpu.push(std::f64::consts::LOG10_2);
+}
+
+pub fn fldln2(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    cpu.fpu.push(std::f64::consts::LN_2);
+}
+
 pub fn fld_sti(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
     let x = *cpu.fpu.get(instr.op0_register());
     cpu.fpu.push(x);
 }
 
+pub fn fld_m80fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
+    let mantissa = load::<u64>(cpu, mem, addr);
+    let sign_exp = load::<u16>(cpu, mem, addr + 8);
+    cpu.fpu.push(fpu::f80_to_f64(mantissa, sign_exp));
+}
+
 pub fn fld_m64fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    cpu.fpu.push(mem.get_pod::<f64>(x86_addr(cpu, instr)));
+    let addr = x86_addr(cpu, instr);
+    let x = load::<f64>(cpu, mem, addr);
+    cpu.fpu.push(x);
 }
 
 pub fn fld_m32fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    cpu.fpu
-        .push(mem.get_pod::<f32>(x86_addr(cpu, instr)) as f64);
+    let addr = x86_addr(cpu, instr);
+    let x = load::<f32>(cpu, mem, addr);
+    cpu.fpu.push(x as f64);
 }
 
 pub fn fild_m64int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    cpu.fpu
-        .push(mem.get_pod::<i64>(x86_addr(cpu, instr)) as f64);
+    let addr = x86_addr(cpu, instr);
+    let x = load::<i64>(cpu, mem, addr);
+    cpu.fpu.push(x as f64);
 }
 
 pub fn fild_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    cpu.fpu
-        .push(mem.get_pod::<i32>(x86_addr(cpu, instr)) as f64);
+    let addr = x86_addr(cpu, instr);
+    let x = load::<i32>(cpu, mem, addr);
+    cpu.fpu.push(x as f64);
 }
 
 pub fn fild_m16int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    cpu.fpu
-        .push(mem.get_pod::<i16>(x86_addr(cpu, instr)) as f64);
+    let addr = x86_addr(cpu, instr);
+    let x = load::<i16>(cpu, mem, addr);
+    cpu.fpu.push(x as f64);
 }
 
 pub fn fst_m64fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
     let f = *cpu.fpu.st0();
-    mem.put_pod::<f64>(x86_addr(cpu, instr), f);
+    store::<f64>(cpu, mem, addr, f);
 }
 
 pub fn fst_m32fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
     let f = *cpu.fpu.st0();
-    mem.put_pod::<f32>(x86_addr(cpu, instr), f as f32);
+    store::<f32>(cpu, mem, addr, f as f32);
+}
+
+pub fn fstp_m80fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
+    let (mantissa, sign_exp) = fpu::f64_to_f80(*cpu.fpu.st0());
+    store::<u64>(cpu, mem, addr, mantissa);
+    store::<u16>(cpu, mem, addr + 8, sign_exp);
+    cpu.fpu.pop();
 }
 
 pub fn fstp_m64fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
@@ -94,21 +129,29 @@ pub fn fstp_m32fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     cpu.fpu.pop();
 }
 
-pub fn fstp_sti(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
+pub fn fst_sti(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
     let f = *cpu.fpu.st0();
     *cpu.fpu.get(instr.op0_register()) = f;
+}
+
+pub fn fstp_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    fst_sti(cpu, mem, instr);
     cpu.fpu.pop();
 }
 
 pub fn fistp_m64int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
     let f = *cpu.fpu.st0();
-    mem.put_pod::<i64>(x86_addr(cpu, instr), f as i64);
+    let i = cpu.fpu.to_int::<i64>(f);
+    store::<i64>(cpu, mem, addr, i);
     cpu.fpu.pop();
 }
 
 pub fn fist_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
     let f = *cpu.fpu.st0();
-    mem.put_pod::<i32>(x86_addr(cpu, instr), f as i32);
+    let i = cpu.fpu.to_int::<i32>(f);
+    store::<i32>(cpu, mem, addr, i);
 }
 
 pub fn fistp_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
@@ -116,9 +159,15 @@ pub fn fistp_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     cpu.fpu.pop();
 }
 
-pub fn fistp_m16int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+pub fn fist_m16int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
     let f = *cpu.fpu.st0();
-    mem.put_pod::<i16>(x86_addr(cpu, instr), f as i16);
+    let i = cpu.fpu.to_int::<i16>(f);
+    store::<i16>(cpu, mem, addr, i);
+}
+
+pub fn fistp_m16int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    fist_m16int(cpu, mem, instr);
     cpu.fpu.pop();
 }
 
@@ -158,6 +207,19 @@ pub fn fsqrt(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
     *reg = reg.sqrt();
 }
 
+pub fn fptan(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    let reg = cpu.fpu.st0();
+    *reg = reg.tan();
+    cpu.fpu.push(1.0);
+}
+
+pub fn fyl2x(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    let x = *cpu.fpu.st0();
+    cpu.fpu.pop();
+    let reg = cpu.fpu.st0();
+    *reg *= x.log2();
+}
+
 pub fn fadd_sti_sti(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
     let y = *cpu.fpu.get(instr.op1_register());
     let x = cpu.fpu.get(instr.op0_register());
@@ -170,33 +232,39 @@ pub fn faddp_sti_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 pub fn fadd_m64fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f64>(x86_addr(cpu, instr));
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f64>(cpu, mem, addr);
     *cpu.fpu.st0() += y;
 }
 
 pub fn fadd_m32fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f32>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f32>(cpu, mem, addr) as f64;
     *cpu.fpu.st0() += y;
 }
 
 pub fn fiadd_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<i32>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i32>(cpu, mem, addr) as f64;
     *cpu.fpu.st0() += y;
 }
 
 pub fn fiadd_m16int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<i16>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i16>(cpu, mem, addr) as f64;
     *cpu.fpu.st0() += y;
 }
 
 pub fn fsub_m64fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f64>(x86_addr(cpu, instr));
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f64>(cpu, mem, addr);
     let x = cpu.fpu.st0();
     *x -= y;
 }
 
 pub fn fsub_m32fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f32>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f32>(cpu, mem, addr) as f64;
     let x = cpu.fpu.st0();
     *x -= y;
 }
@@ -213,19 +281,43 @@ pub fn fsubp_sti_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 pub fn fisub_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<i32>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i32>(cpu, mem, addr) as f64;
+    let x = cpu.fpu.st0();
+    *x -= y;
+}
+
+pub fn fisub_m16int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i16>(cpu, mem, addr) as f64;
+    let x = cpu.fpu.st0();
+    *x -= y;
+}
+
+pub fn fisubr_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i32>(cpu, mem, addr) as f64;
+    let x = cpu.fpu.st0();
+    *x = y - *x;
+}
+
+pub fn fisubr_m16int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i16>(cpu, mem, addr) as f64;
     let x = cpu.fpu.st0();
-    *x = *x - y;
+    *x = y - *x;
 }
 
 pub fn fsubr_m64fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f64>(x86_addr(cpu, instr));
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f64>(cpu, mem, addr);
     let x = cpu.fpu.st0();
     *x = y - *x;
 }
 
 pub fn fsubr_m32fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f32>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f32>(cpu, mem, addr) as f64;
     let x = cpu.fpu.st0();
     *x = y - *x;
 }
@@ -242,22 +334,26 @@ pub fn fsubrp_sti_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 pub fn fmul_m64fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f64>(x86_addr(cpu, instr));
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f64>(cpu, mem, addr);
     *cpu.fpu.st0() *= y;
 }
 
 pub fn fmul_m32fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f32>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f32>(cpu, mem, addr) as f64;
     *cpu.fpu.st0() *= y;
 }
 
 pub fn fimul_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<i32>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i32>(cpu, mem, addr) as f64;
     *cpu.fpu.st0() *= y;
 }
 
 pub fn fimul_m16int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<i16>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i16>(cpu, mem, addr) as f64;
     *cpu.fpu.st0() *= y;
 }
 
@@ -284,12 +380,14 @@ pub fn fscale(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
 }
 
 pub fn fdiv_m64fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f64>(x86_addr(cpu, instr));
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f64>(cpu, mem, addr);
     *cpu.fpu.st0() /= y;
 }
 
 pub fn fdiv_m32fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f32>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f32>(cpu, mem, addr) as f64;
     *cpu.fpu.st0() /= y;
 }
 
@@ -305,23 +403,27 @@ pub fn fdivp_sti_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 pub fn fidiv_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<i32>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i32>(cpu, mem, addr) as f64;
     *cpu.fpu.st0() /= y;
 }
 
 pub fn fidiv_m16int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<i16>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i16>(cpu, mem, addr) as f64;
     *cpu.fpu.st0() /= y;
 }
 
 pub fn fdivr_m64fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f64>(x86_addr(cpu, instr));
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f64>(cpu, mem, addr);
     let x = cpu.fpu.st0();
     *x = y / *x;
 }
 
 pub fn fdivr_m32fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<f32>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<f32>(cpu, mem, addr) as f64;
     let x = cpu.fpu.st0();
     *x = y / *x;
 }
@@ -338,7 +440,15 @@ pub fn fdivrp_sti_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 pub fn fidivr_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = mem.get_pod::<i32>(x86_addr(cpu, instr)) as f64;
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i32>(cpu, mem, addr) as f64;
+    let x = cpu.fpu.st0();
+    *x = y / *x;
+}
+
+pub fn fidivr_m16int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
+    let y = load::<i16>(cpu, mem, addr) as f64;
     let x = cpu.fpu.st0();
     *x = y / *x;
 }
@@ -346,7 +456,9 @@ pub fn fidivr_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 pub fn fprem(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
     let y = *cpu.fpu.get(iced_x86::Register::ST1);
     let x = cpu.fpu.st0();
-    *x = *x % y;
+    *x %= y;
+    // C2 clear means the reduction is complete, which is always true for us.
+    cpu.fpu.status.remove(fpu::Status::C2);
 }
 
 pub fn fxch_st0_sti(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
@@ -354,15 +466,17 @@ pub fn fxch_st0_sti(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
 }
 
 pub fn fcom_m64fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
     let x = *cpu.fpu.st0();
-    let y = mem.get_pod::<f64>(x86_addr(cpu, instr));
-    fcom(cpu, x, y);
+    let y = load::<f64>(cpu, mem, addr);
+    cpu.fpu.compare(x, y);
 }
 
 pub fn fcom_m32fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
     let x = *cpu.fpu.st0();
-    let y = mem.get_pod::<f32>(x86_addr(cpu, instr)) as f64;
-    fcom(cpu, x, y);
+    let y = load::<f32>(cpu, mem, addr) as f64;
+    cpu.fpu.compare(x, y);
 }
 
 pub fn fcomp_m32fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
@@ -375,40 +489,103 @@ pub fn fcomp_m64fp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     cpu.fpu.pop();
 }
 
-pub fn fcomp_st0_sti(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
+pub fn fcom_st0_sti(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
     let x = *cpu.fpu.st0();
     let y = *cpu.fpu.get(instr.op1_register());
-    fcom(cpu, x, y);
+    cpu.fpu.compare(x, y);
+}
+
+pub fn fcomp_st0_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    fcom_st0_sti(cpu, mem, instr);
+    cpu.fpu.pop();
+}
+
+pub fn fcompp(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    let x = *cpu.fpu.st0();
+    let y = *cpu.fpu.get(Register::ST1);
+    cpu.fpu.compare(x, y);
+    cpu.fpu.pop();
+    cpu.fpu.pop();
+}
+
+pub fn ficom_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
+    let x = *cpu.fpu.st0();
+    let y = load::<i32>(cpu, mem, addr) as f64;
+    cpu.fpu.compare(x, y);
+}
+
+pub fn ficomp_m32int(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    ficom_m32int(cpu, mem, instr);
     cpu.fpu.pop();
 }
 
+// The fucom family differs from fcom only in not raising invalid-operation on quiet NaNs,
+// which we don't distinguish.
+
+pub fn fucom_st0_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    fcom_st0_sti(cpu, mem, instr);
+}
+
 pub fn fucomp_st0_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     fcomp_st0_sti(cpu, mem, instr);
-    // TODO: raise the invalid-arithmetic-operand exception when appropriate.
+}
+
+pub fn fucompp(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    fcompp(cpu, mem, instr);
+}
+
+pub fn ftst(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    let x = *cpu.fpu.st0();
+    cpu.fpu.compare(x, 0.0);
+}
+
+pub fn fxam(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
+    // Classify st0 into C3/C2/C0, with the sign in C1.
+    let (c3, c2, c0) = if cpu.fpu.st_top == 8 {
+        (true, false, true) // empty
+    } else {
+        let x = cpu.fpu.st[cpu.fpu.st_top];
+        match x.classify() {
+            std::num::FpCategory::Nan => (false, false, true),
+            std::num::FpCategory::Infinite => (false, true, true),
+            std::num::FpCategory::Zero => (true, false, false),
+            std::num::FpCategory::Subnormal => (true, true, false),
+            std::num::FpCategory::Normal => (false, true, false),
+        }
+    };
+    let negative = cpu.fpu.st_top < 8 && cpu.fpu.st[cpu.fpu.st_top].is_sign_negative();
+    cpu.fpu.status.set(fpu::Status::C3, c3);
+    cpu.fpu.status.set(fpu::Status::C2, c2);
+    cpu.fpu.status.set(fpu::Status::C1, negative);
+    cpu.fpu.status.set(fpu::Status::C0, c0);
 }
 
 pub fn fcomi_st0_sti(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
     let x = *cpu.fpu.st0();
     let y = *cpu.fpu.get(instr.op1_register());
-    //cpu.flags.set(Flags::PF, false);
-    if x > y {
-        cpu.flags.set(Flags::ZF, false);
-        cpu.flags.set(Flags::CF, false);
-    } else if x < y {
-        cpu.flags.set(Flags::ZF, false);
-        cpu.flags.set(Flags::CF, true);
-    } else {
-        cpu.flags.set(Flags::ZF, true);
-        cpu.flags.set(Flags::CF, false);
-    }
+    let (zf, pf, cf) = match x.partial_cmp(&y) {
+        Some(std::cmp::Ordering::Greater) => (false, false, false),
+        Some(std::cmp::Ordering::Less) => (false, false, true),
+        Some(std::cmp::Ordering::Equal) => (true, false, false),
+        None => (true, true, true),
+    };
+    cpu.flags.set(Flags::ZF, zf);
+    cpu.flags.set(Flags::PF, pf);
+    cpu.flags.set(Flags::CF, cf);
 
     cpu.flags.set(Flags::OF, false);
     cpu.flags.set(Flags::SF, false);
+    cpu.fpu.status.remove(fpu::Status::C1);
+}
+
+pub fn fcomip_st0_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    fcomi_st0_sti(cpu, mem, instr);
+    cpu.fpu.pop();
 }
 
 pub fn fucomi_st0_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     fcomi_st0_sti(cpu, mem, instr);
-    // TODO: raise the invalid-arithmetic-operand exception when appropriate.
 }
 
 pub fn fucomip_st0_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
@@ -417,25 +594,30 @@ pub fn fucomip_st0_sti(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 pub fn frndint(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
-    let x = cpu.fpu.st0();
-    *x = x.round();
+    let x = *cpu.fpu.st0();
+    let rounded = cpu.fpu.round(x);
+    *cpu.fpu.st0() = rounded;
 }
 
 pub fn fnstsw_ax(cpu: &mut CPU, _mem: Mem, _instr: &Instruction) {
-    // TODO: does this need stack top in it?
-    cpu.regs.set32(Register::EAX, cpu.fpu.status.bits() as u32);
+    cpu.regs.set16(Register::AX, cpu.fpu.status_word());
+}
+
+pub fn fnstsw_m2byte(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let addr = x86_addr(cpu, instr);
+    let status = cpu.fpu.status_word();
+    store::<u16>(cpu, mem, addr, status);
 }
 
 pub fn fnstcw_m2byte(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    // TODO: control word
-    let cw = 0x37u16; // default value
     let addr = x86_addr(cpu, instr);
-    mem.put_pod::<u16>(addr, cw);
+    let control = cpu.fpu.control;
+    store::<u16>(cpu, mem, addr, control);
 }
 
 pub fn fldcw_m2byte(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    // TODO: control word
-    mem.get_pod::<u16>(x86_addr(cpu, instr));
+    let addr = x86_addr(cpu, instr);
+    cpu.fpu.control = load::<u16>(cpu, mem, addr);
 }
 
 pub fn fcmovnbe_st0_sti(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
@@ -444,3 +626,49 @@ pub fn fcmovnbe_st0_sti(cpu: &mut CPU, _mem: Mem, instr: &Instruction) {
         *cpu.fpu.st0() = y;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{Register, X86};
+    use memory::{Extensions, ExtensionsMut, Mem};
+
+    #[test]
+    fn arithmetic_rounding_compare() {
+        let code: &[u8] = &[
+            0xdd, 0x05, 0x00, 0x20, 0x00, 0x00, // fld qword [2000]
+            0xdc, 0x05, 0x08, 0x20, 0x00, 0x00, // fadd qword [2008]
+            0xdd, 0x1d, 0x10, 0x20, 0x00, 0x00, // fstp qword [2010]
+            0xdd, 0x05, 0x00, 0x20, 0x00, 0x00, // fld qword [2000]
+            0xdb, 0x1d, 0x20, 0x20, 0x00, 0x00, // fistp dword [2020]
+            0xd9, 0x2d, 0x18, 0x20, 0x00, 0x00, // fldcw word [2018]
+            0xdd, 0x05, 0x00, 0x20, 0x00, 0x00, // fld qword [2000]
+            0xdb, 0x1d, 0x24, 0x20, 0x00, 0x00, // fistp dword [2024]
+            0xdd, 0x05, 0x08, 0x20, 0x00, 0x00, // fld qword [2008]
+            0xdc, 0x1d, 0x00, 0x20, 0x00, 0x00, // fcomp qword [2000]
+            0xdf, 0xe0, // fnstsw ax
+            0xeb, 0xfe, // jmp $
+        ];
+        let mut buf = vec![0u8; 0x3000];
+        buf[0x1000..][..code.len()].copy_from_slice(code);
+        let mem = Mem::from_slice(&buf);
+        mem.put_pod::<f64>(0x2000, 2.75);
+        mem.put_pod::<f64>(0x2008, 1.25);
+        // Default control word, but rounding toward zero.
+        mem.put_pod::<u16>(0x2018, 0x0F7F);
+
+        let mut x86 = X86::new();
+        x86.cpu_mut().regs.eip = 0x1000;
+        let end = 0x1000 + code.len() as u32 - 2;
+        while x86.cpu().regs.eip != end {
+            x86.execute_block(mem);
+        }
+
+        assert_eq!(mem.get_pod::<f64>(0x2010), 4.0);
+        assert_eq!(mem.get_pod::<u32>(0x2020), 3);
+        assert_eq!(mem.get_pod::<u32>(0x2024), 2);
+        let cpu = x86.cpu();
+        assert_eq!(cpu.fpu.control, 0x0F7F);
+        // 1.25 < 2.75 sets C0 (and not C2/C3); everything was popped, so TOP is back to 0.
+        assert_eq!(cpu.regs.get16(Register::AX) & 0x7D00, 0x0100);
+    }
+}
@@ -1,7 +1,88 @@
 //! Functions for common behaviors across all operations.
 
-use crate::{x86::CPU, Register};
-use memory::{Extensions, ExtensionsMut, Mem};
+use crate::{
+    memmap::{Protect, WatchHit},
+    x86::{Fault, CPU, MAGIC_ADDR},
+    Flags, Register,
+};
+use memory::{Extensions, ExtensionsMut, Mem, Pod};
+use std::mem::size_of;
+
+/// Check that an access to a T at addr is allowed, raising an access violation if not.
+fn check<T>(cpu: &mut CPU, mem: Mem, addr: u32, access: Protect) -> bool {
+    if mem.is_oob::<T>(addr) || !cpu.memmap.check(addr, size_of::<T>() as u32, access) {
+        cpu.fault(Fault::AccessViolation {
+            addr,
+            write: access.contains(Protect::WRITE),
+        });
+        return false;
+    }
+    true
+}
+
+/// Read a T from memory on behalf of the running instruction.
+/// On an access violation this faults the CPU and returns zero.
+pub fn load<T: Clone + Pod>(cpu: &mut CPU, mem: Mem, addr: u32) -> T {
+    if !check::<T>(cpu, mem, addr, Protect::READ) {
+        return T::zeroed();
+    }
+    get::<T>(cpu, mem, addr)
+}
+
+/// Write a T to memory on behalf of the running instruction.
+/// Does nothing if the access violates, or if the instruction already faulted.
+pub fn store<T: Clone + Pod>(cpu: &mut CPU, mem: Mem, addr: u32, val: T) {
+    if !cpu.state.is_running() || !check::<T>(cpu, mem, addr, Protect::WRITE) {
+        return;
+    }
+    put::<T>(cpu, mem, addr, val);
+}
+
+/// Up to 8 bytes of memory as a little-endian integer, for reporting watch hits.
+fn peek(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    let len = bytes.len().min(8);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(buf)
+}
+
+/// Note an access to a watched range, for execute_block to report after the instruction.
+fn watch_hit(cpu: &mut CPU, addr: u32, len: u32, write: bool, old: u64, new: u64) {
+    let access = if write { Protect::WRITE } else { Protect::READ };
+    if let Some(action) = cpu.memmap.watched(addr, len, access) {
+        cpu.watch_hits.push(WatchHit {
+            addr,
+            len,
+            write,
+            old,
+            new,
+            action,
+        });
+    }
+}
+
+/// Read a T from memory whose access was already checked.
+fn get<T: Clone + Pod>(cpu: &mut CPU, mem: Mem, addr: u32) -> T {
+    let len = size_of::<T>() as u32;
+    if cpu.memmap.watched(addr, len, Protect::READ).is_some() {
+        let value = peek(mem.sub32(addr, len));
+        watch_hit(cpu, addr, len, false, value, value);
+    }
+    mem.get_pod::<T>(addr)
+}
+
+/// Write a T to memory whose access was already checked.
+fn put<T: Clone + Pod>(cpu: &mut CPU, mem: Mem, addr: u32, val: T) {
+    let len = size_of::<T>() as u32;
+    if cpu.memmap.watched(addr, len, Protect::WRITE).is_none() {
+        mem.put_pod::<T>(addr, val);
+        return;
+    }
+    let old = peek(mem.sub32(addr, len));
+    mem.put_pod::<T>(addr, val);
+    let new = peek(mem.sub32(addr, len));
+    watch_hit(cpu, addr, len, true, old, new);
+}
 
 // TODO: maybe there are no 64-bit memory reads needed (?)
 pub fn rm64_x(
@@ -19,9 +100,9 @@ pub fn rm64_x(
         }
         iced_x86::OpKind::Memory => {
             let addr = x86_addr(cpu, instr);
-            let x = mem.get_pod::<u64>(addr);
+            let x = load::<u64>(cpu, mem, addr);
             let value = op(cpu, x);
-            mem.put_pod::<u64>(addr, value);
+            store::<u64>(cpu, mem, addr, value);
         }
         _ => unimplemented!(),
     }
@@ -36,15 +117,88 @@ pub fn rm64_x(
 /// but it turns out that memory accesses can be unaligned and Rust does not allow
 /// references to unaligned memory.  It turns out a lot easier to not need to worry
 /// about lifetimes anyway.
-pub struct Arg<T>(*mut T);
+pub struct Arg<T> {
+    ptr: *mut T,
+    /// For memory that can be read but not written, the CPU to fault, the address, and
+    /// the flags from before the instruction, so that set() raises the access violation
+    /// instead of writing and undoes any flags the instruction computed along the way.
+    readonly: Option<(*mut CPU, u32, Flags)>,
+    /// For memory covered by a watchpoint, the CPU to record hits on and the address.
+    watch: Option<(*mut CPU, u32)>,
+}
 
 impl<T> Arg<T> {
+    fn reg(ptr: *mut T) -> Self {
+        Arg {
+            ptr,
+            readonly: None,
+            watch: None,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, size_of::<T>()) }
+    }
+
     pub fn get(&self) -> T {
-        unsafe { std::ptr::read_unaligned(self.0) }
+        if let Some((cpu, addr)) = self.watch {
+            let value = peek(self.bytes());
+            unsafe { watch_hit(&mut *cpu, addr, size_of::<T>() as u32, false, value, value) };
+        }
+        unsafe { std::ptr::read_unaligned(self.ptr) }
     }
 
     pub fn set(&self, val: T) {
-        unsafe { std::ptr::write_unaligned(self.0, val) }
+        unsafe {
+            if let Some((cpu, addr, flags)) = self.readonly {
+                (*cpu).flags = flags;
+                (*cpu).fault(Fault::AccessViolation { addr, write: true });
+                return;
+            }
+            let Some((cpu, addr)) = self.watch else {
+                std::ptr::write_unaligned(self.ptr, val);
+                return;
+            };
+            let old = peek(self.bytes());
+            std::ptr::write_unaligned(self.ptr, val);
+            let new = peek(self.bytes());
+            watch_hit(&mut *cpu, addr, size_of::<T>() as u32, true, old, new);
+        }
+    }
+}
+
+/// An Arg for a memory operand.  Whether the instruction writes its operand isn't known
+/// here, so only reading is checked up front; writes to read-only memory fault in set().
+pub fn mem_arg<T: Pod>(cpu: &mut CPU, mem: Mem, addr: u32) -> Arg<T> {
+    let size = size_of::<T>() as u32;
+    let watch = if cpu
+        .memmap
+        .watched(addr, size, Protect::READ | Protect::WRITE)
+        .is_some()
+    {
+        Some((cpu as *mut CPU, addr))
+    } else {
+        None
+    };
+    if !mem.is_oob::<T>(addr) && cpu.memmap.check(addr, size, Protect::WRITE) {
+        return Arg {
+            ptr: mem.get_ptr_mut::<T>(addr),
+            readonly: None,
+            watch,
+        };
+    }
+    if !check::<T>(cpu, mem, addr, Protect::READ) {
+        // Point at something harmless; the instruction's results are discarded.
+        return Arg {
+            ptr: mem.get_ptr_mut::<T>(0),
+            readonly: None,
+            watch: None,
+        };
+    }
+    Arg {
+        ptr: mem.get_ptr_mut::<T>(addr),
+        readonly: Some((cpu as *mut CPU, addr, cpu.flags)),
+        watch,
     }
 }
 
@@ -52,11 +206,11 @@ pub fn rm32<'a>(cpu: &'a mut CPU, mem: Mem, instr: &iced_x86::Instruction) -> Ar
     match instr.op0_kind() {
         iced_x86::OpKind::Register => {
             let reg = instr.op0_register();
-            Arg(cpu.regs.get32_mut(reg))
+            Arg::reg(cpu.regs.get32_mut(reg))
         }
         iced_x86::OpKind::Memory => {
             let addr = x86_addr(cpu, instr);
-            Arg(mem.get_ptr_mut::<u32>(addr))
+            mem_arg::<u32>(cpu, mem, addr)
         }
         _ => unimplemented!(),
     }
@@ -66,11 +220,11 @@ pub fn rm16<'a>(cpu: &'a mut CPU, mem: Mem, instr: &iced_x86::Instruction) -> Ar
     match instr.op0_kind() {
         iced_x86::OpKind::Register => {
             let reg = instr.op0_register();
-            Arg(cpu.regs.get16_mut(reg))
+            Arg::reg(cpu.regs.get16_mut(reg))
         }
         iced_x86::OpKind::Memory => {
             let addr = x86_addr(cpu, instr);
-            Arg(mem.get_ptr_mut::<u16>(addr))
+            mem_arg::<u16>(cpu, mem, addr)
         }
         _ => unimplemented!(),
     }
@@ -80,15 +234,11 @@ pub fn rm8<'a>(cpu: &'a mut CPU, mem: Mem, instr: &iced_x86::Instruction) -> Arg
     match instr.op0_kind() {
         iced_x86::OpKind::Register => {
             let reg = instr.op0_register();
-            Arg(cpu.regs.get8_mut(reg))
+            Arg::reg(cpu.regs.get8_mut(reg))
         }
         iced_x86::OpKind::Memory => {
-            let mut addr = x86_addr(cpu, instr);
-            if mem.is_oob::<u8>(addr) {
-                cpu.err(format!("oob at {addr:x}"));
-                addr = 0;
-            }
-            Arg(mem.get_ptr_mut::<u8>(addr))
+            let addr = x86_addr(cpu, instr);
+            mem_arg::<u8>(cpu, mem, addr)
         }
         _ => unimplemented!(),
     }
@@ -99,11 +249,7 @@ pub fn op1_rm32(cpu: &mut CPU, mem: Mem, instr: &iced_x86::Instruction) -> u32 {
         iced_x86::OpKind::Register => cpu.regs.get32(instr.op1_register()),
         iced_x86::OpKind::Memory => {
             let addr = x86_addr(cpu, instr);
-            if mem.is_oob::<u32>(addr) {
-                cpu.err(format!("oob at {addr:x}"));
-                return 0;
-            }
-            mem.get_pod::<u32>(addr)
+            load::<u32>(cpu, mem, addr)
         }
         _ => unreachable!(),
     }
@@ -112,7 +258,10 @@ pub fn op1_rm32(cpu: &mut CPU, mem: Mem, instr: &iced_x86::Instruction) -> u32 {
 pub fn op1_rm16(cpu: &mut CPU, mem: Mem, instr: &iced_x86::Instruction) -> u16 {
     match instr.op1_kind() {
         iced_x86::OpKind::Register => cpu.regs.get16(instr.op1_register()),
-        iced_x86::OpKind::Memory => mem.get_pod::<u16>(x86_addr(cpu, instr)),
+        iced_x86::OpKind::Memory => {
+            let addr = x86_addr(cpu, instr);
+            load::<u16>(cpu, mem, addr)
+        }
         _ => unreachable!(),
     }
 }
@@ -120,39 +269,52 @@ pub fn op1_rm16(cpu: &mut CPU, mem: Mem, instr: &iced_x86::Instruction) -> u16 {
 pub fn op1_rm8(cpu: &mut CPU, mem: Mem, instr: &iced_x86::Instruction) -> u8 {
     match instr.op1_kind() {
         iced_x86::OpKind::Register => cpu.regs.get8(instr.op1_register()),
-        iced_x86::OpKind::Memory => mem.get_pod::<u8>(x86_addr(cpu, instr)),
+        iced_x86::OpKind::Memory => {
+            let addr = x86_addr(cpu, instr);
+            load::<u8>(cpu, mem, addr)
+        }
         _ => unreachable!(),
     }
 }
 
 /// Push a u32 on the x86 stack.
 pub fn push(cpu: &mut CPU, mem: Mem, value: u32) {
-    let esp = cpu.regs.get32_mut(Register::ESP);
-    *esp -= 4;
-    mem.put_pod::<u32>(*esp, value);
+    let esp = cpu.regs.get32(Register::ESP).wrapping_sub(4);
+    if !check::<u32>(cpu, mem, esp, Protect::WRITE) {
+        return;
+    }
+    cpu.regs.set32(Register::ESP, esp);
+    put::<u32>(cpu, mem, esp, value);
 }
 
 /// Push a u16 on the x86 stack.
 pub fn push16(cpu: &mut CPU, mem: Mem, value: u16) {
-    let esp = cpu.regs.get32_mut(Register::ESP);
-    *esp -= 2;
-    mem.put_pod::<u16>(*esp, value);
+    let esp = cpu.regs.get32(Register::ESP).wrapping_sub(2);
+    if !check::<u16>(cpu, mem, esp, Protect::WRITE) {
+        return;
+    }
+    cpu.regs.set32(Register::ESP, esp);
+    put::<u16>(cpu, mem, esp, value);
 }
 
 /// Pop a u32 from the x86 stack.
 pub fn pop(cpu: &mut CPU, mem: Mem) -> u32 {
-    let esp = cpu.regs.get32_mut(Register::ESP);
-    let value = mem.get_pod::<u32>(*esp);
-    *esp += 4;
-    value
+    let esp = cpu.regs.get32(Register::ESP);
+    if !check::<u32>(cpu, mem, esp, Protect::READ) {
+        return 0;
+    }
+    cpu.regs.set32(Register::ESP, esp.wrapping_add(4));
+    get::<u32>(cpu, mem, esp)
 }
 
 /// Pop a u16 from the x86 stack.
 pub fn pop16(cpu: &mut CPU, mem: Mem) -> u16 {
-    let esp = cpu.regs.get32_mut(Register::ESP);
-    let value = mem.get_pod::<u16>(*esp);
-    *esp += 2;
-    value
+    let esp = cpu.regs.get32(Register::ESP);
+    if !check::<u16>(cpu, mem, esp, Protect::READ) {
+        return 0;
+    }
+    cpu.regs.set32(Register::ESP, esp.wrapping_add(2));
+    get::<u16>(cpu, mem, esp)
 }
 
 /// Compute the address found in instructions that reference memory, e.g.
@@ -195,8 +357,10 @@ pub fn x86_addr(cpu: &CPU, instr: &iced_x86::Instruction) -> u32 {
 }
 
 pub fn x86_jmp(cpu: &mut CPU, addr: u32) {
-    if addr < 0x1000 {
-        cpu.err(format!("jmp to null page addr={addr:x}"));
+    // We don't model no-execute, as the CPUs of the era didn't have it, so any
+    // readable memory is fair game.
+    if addr != MAGIC_ADDR && !cpu.memmap.check(addr, 1, Protect::READ) {
+        cpu.fault(Fault::AccessViolation { addr, write: false });
         return;
     }
     cpu.regs.eip = addr;
@@ -1,8 +1,10 @@
 use super::helpers::*;
-use crate::{registers::Flags, x86::CPU};
+use crate::{
+    registers::Flags,
+    x86::{Fault, CPU},
+};
 use iced_x86::{Instruction, Register};
 use memory::Mem;
-use num_traits::ops::overflowing::OverflowingSub;
 
 /// This trait is implemented for u32/u16/u8 and lets us write operations generically
 /// over all those bit sizes.
@@ -47,11 +49,36 @@ impl Int for u8 {
     }
 }
 
+fn msb<I: Int>(x: I) -> bool {
+    (x >> (I::bits() - 1)).is_one()
+}
+
+fn to_u64<I: Int>(x: I) -> u64 {
+    x.to_u64().unwrap()
+}
+
+/// Truncate to the width of I.
+fn from_u64<I: Int>(x: u64) -> I {
+    I::from(x & to_u64(I::max_value())).unwrap()
+}
+
+/// Set SF, ZF and PF, which most ops derive from their result the same way.
+fn set_result_flags<I: Int>(result: I, flags: &mut Flags) {
+    flags.set(Flags::SF, msb(result));
+    flags.set(Flags::ZF, result.is_zero());
+    // PF reflects only the low byte, and is set for an even number of 1 bits.
+    flags.set(Flags::PF, (to_u64(result) as u8).count_ones() % 2 == 0);
+}
+
+/// AF is the carry or borrow out of bit 3, which shows up in bit 4 of x^y^result.
+fn set_af<I: Int>(x: I, y: I, result: I, flags: &mut Flags) {
+    flags.set(Flags::AF, to_u64(x ^ y ^ result) & 0x10 != 0);
+}
+
 // pub(crate) for use in the test opcode impl.
 pub(crate) fn and<I: Int>(x: I, y: I, flags: &mut Flags) -> I {
     let result = x & y;
-    flags.set(Flags::ZF, result.is_zero());
-    flags.set(Flags::SF, (result >> (I::bits() - 1)).is_one());
+    set_result_flags(result, flags);
     flags.set(Flags::OF, false);
     flags.set(Flags::CF, false);
     result
@@ -120,8 +147,7 @@ pub fn and_rm8_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 fn or<I: Int>(x: I, y: I, flags: &mut Flags) -> I {
     let result = x | y;
     flags.remove(Flags::OF | Flags::CF);
-    flags.set(Flags::SF, result.shr(I::bits() - 1).is_one());
-    flags.set(Flags::ZF, result.is_zero());
+    set_result_flags(result, flags);
     result
 }
 
@@ -179,27 +205,23 @@ pub fn or_r8_rm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     x.set(or(x.get(), y, &mut cpu.flags));
 }
 
-fn shl<I: Int + num_traits::WrappingShl>(x: I, y: u8, flags: &mut Flags) -> I {
-    let y = y % 32;
+fn shl<I: Int>(x: I, y: u8, flags: &mut Flags) -> I {
+    // The count is masked to 5 bits, even for narrower operands.
+    let y = y & 0x1F;
     if y == 0 {
-        return x;
+        return x; // Don't affect flags.
     }
 
-    // Carry is the highest bit that will be shifted out.
-    let cf = (x.shr(I::bits() - y as usize) & I::one()).is_one();
-    let val = x.wrapping_shl(y.as_usize() as u32);
+    // Shift in 64 bits so counts beyond the operand width are well defined.
+    let wide = to_u64(x) << y;
+    let val: I = from_u64(wide);
+    // Carry is the last bit shifted out.
+    let cf = (wide >> I::bits()) & 1 != 0;
     flags.set(Flags::CF, cf);
-    let msb = val.shr(I::bits() - 1).is_one();
-    flags.set(Flags::SF, msb);
-    // Note: OF only defined for 1-bit rotates.
-    // "For left shifts, the OF flag is set to 0 if the mostsignificant bit of the result is the
-    // same as the CF flag (that is, the top two bits of the original operand were the same) [...]"
-    flags.set(
-        Flags::OF,
-        x.shr(I::bits() - 1).is_one() ^ (x.shr(I::bits() - 2) & I::one()).is_one(),
-    );
-    flags.set(Flags::ZF, val.is_zero());
-
+    // Note: OF only defined for 1-bit shifts, where it's set if the sign changed,
+    // i.e. the top two bits of the original operand differed.
+    flags.set(Flags::OF, msb(val) ^ cf);
+    set_result_flags(val, flags);
     val
 }
 
@@ -263,21 +285,19 @@ pub fn shld_rm32_r32_cl(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 fn shr<I: Int>(x: I, y: u8, flags: &mut Flags) -> I {
-    // In all modes but 64 it is correct to mask to 32 bits.
+    // In all modes but 64 it is correct to mask to 5 bits.
     assert!(I::bits() < 64); // 64 not implemented
-    let y = y % 32;
-
+    let y = y & 0x1F;
     if y == 0 {
         return x; // Don't affect flags.
     }
 
-    flags.set(Flags::CF, ((x >> (y - 1) as usize) & I::one()).is_one());
-    let val = x >> y as usize;
-    flags.set(Flags::SF, false); // ?
-    flags.set(Flags::ZF, val.is_zero());
-
-    // Note: OF state undefined for shifts > 1 bit.
-    flags.set(Flags::OF, (x >> (I::bits() - 1)).is_one());
+    let wide = to_u64(x);
+    flags.set(Flags::CF, (wide >> (y - 1)) & 1 != 0);
+    let val: I = from_u64(wide >> y);
+    // Note: OF only defined for 1-bit shifts, where it's the original sign bit.
+    flags.set(Flags::OF, msb(x));
+    set_result_flags(val, flags);
     val
 }
 
@@ -351,17 +371,22 @@ pub fn shrd_rm32_r32_cl(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 fn sar<I: Int>(x: I, y: I, flags: &mut Flags) -> I {
-    if y.is_zero() {
-        return x;
+    let y = y.as_usize() & 0x1F;
+    if y == 0 {
+        return x; // Don't affect flags.
     }
-    flags.set(Flags::CF, x.shr(y.as_usize() - 1).bitand(I::one()).is_one());
-    // Note: OF only defined for 1-bit rotates.
-    flags.set(Flags::OF, false);
-    // There's a random "u32" type in the num-traits signed_shr signature, so cast here.
-    let result = x.signed_shr(y.as_usize() as u32);
 
-    flags.set(Flags::SF, result.shr(I::bits() - 1).is_one());
-    flags.set(Flags::ZF, result.is_zero());
+    // Sign-extend to 64 bits so counts beyond the operand width fill with the sign.
+    let mut wide = to_u64(x);
+    if msb(x) {
+        wide |= !to_u64(I::max_value());
+    }
+    let wide = wide as i64;
+    flags.set(Flags::CF, (wide >> (y - 1)) & 1 != 0);
+    // Note: OF only defined for 1-bit shifts, where it's always clear.
+    flags.set(Flags::OF, false);
+    let result: I = from_u64((wide >> y) as u64);
+    set_result_flags(result, flags);
     result
 }
 
@@ -390,14 +415,16 @@ pub fn sar_rm8_cl(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 fn rol<I: Int>(x: I, y: u8, flags: &mut Flags) -> I {
+    // The count is masked before rotating, so e.g. an 8-bit rotate by 8 still sets CF.
+    let y = y & 0x1F;
     if y == 0 {
-        return x;
+        return x; // Don't affect flags.
     }
     let result = x.rotate_left(y as u32);
     let carry = (result & I::one()).is_one();
     flags.set(Flags::CF, carry);
     // Note: OF only defined for 1-bit rotates.
-    flags.set(Flags::OF, carry ^ (result >> (I::bits() - 1)).is_one());
+    flags.set(Flags::OF, carry ^ msb(result));
     result
 }
 
@@ -426,14 +453,18 @@ pub fn rol_rm8_cl(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 fn ror<I: Int>(x: I, y: u8, flags: &mut Flags) -> I {
+    let y = y & 0x1F;
     if y == 0 {
-        return x;
+        return x; // Don't affect flags.
     }
     let result = x.rotate_right(y as u32);
-    let msb = (result >> (I::bits() - 1)).is_one();
-    flags.set(Flags::CF, msb);
-    // Note: OF only defined for 1-bit rotates.
-    flags.set(Flags::OF, msb ^ (result >> (I::bits() - 2)).is_one());
+    let carry = msb(result);
+    flags.set(Flags::CF, carry);
+    // Note: OF only defined for 1-bit rotates; it's the xor of the top two bits.
+    flags.set(
+        Flags::OF,
+        carry ^ ((result >> (I::bits() - 2)) & I::one()).is_one(),
+    );
     result
 }
 
@@ -466,8 +497,7 @@ fn xor<I: Int>(x: I, y: I, flags: &mut Flags) -> I {
     // The OF and CF flags are cleared; the SF, ZF, and PF flags are set according to the result. The state of the AF flag is undefined.
     flags.remove(Flags::OF);
     flags.remove(Flags::CF);
-    flags.set(Flags::ZF, result.is_zero());
-    flags.set(Flags::SF, (result >> (I::bits() - 1)).is_one());
+    set_result_flags(result, flags);
     result
 }
 
@@ -517,19 +547,23 @@ fn add<I: Int + num_traits::ops::wrapping::WrappingAdd>(x: I, y: I, flags: &mut
     addc(x, y, I::zero(), flags)
 }
 
+/// Add with carry-in z, which is 0 or 1.
 fn addc<I: Int + num_traits::ops::wrapping::WrappingAdd>(x: I, y: I, z: I, flags: &mut Flags) -> I {
-    // TODO "The CF, OF, SF, ZF, AF, and PF flags are set according to the result."
-    let y = y.wrapping_add(&z);
-    let result = x.wrapping_add(&y);
-    flags.set(Flags::CF, result < x || (y.is_zero() && !z.is_zero()));
-    flags.set(Flags::ZF, result.is_zero());
-    flags.set(Flags::SF, (result >> (I::bits() - 1)).is_one());
+    let result = x.wrapping_add(&y).wrapping_add(&z);
+    // Carry out of either step; they can't both carry.
+    let cf = x
+        .checked_add(&y)
+        .and_then(|sum| sum.checked_add(&z))
+        .is_none();
+    flags.set(Flags::CF, cf);
     // Overflow is true exactly when the high (sign) bits are like:
     //   x  y  result
     //   0  0  1
     //   1  1  0
-    let of = !(((x ^ !y) & (x ^ result)) >> (I::bits() - 1)).is_zero();
-    flags.set(Flags::OF, of);
+    // The carry-in doesn't change this, as long as y isn't first folded into it.
+    flags.set(Flags::OF, msb((x ^ result) & (y ^ result)));
+    set_af(x, y, result, flags);
+    set_result_flags(result, flags);
     result
 }
 
@@ -628,38 +662,28 @@ pub fn adc_rm8_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     x.set(addc(x.get(), y, carry as u8, &mut cpu.flags));
 }
 
-fn sbb<I: Int + num_traits::ops::overflowing::OverflowingSub + num_traits::WrappingAdd>(
-    x: I,
-    y: I,
-    b: bool,
-    flags: &mut Flags,
-) -> I {
-    let mut y = y;
-    if b {
-        y = y.wrapping_add(&I::one());
-    }
-    let (result, carry) = x.overflowing_sub(&y);
-    // TODO "The CF, OF, SF, ZF, AF, and PF flags are set according to the result."
-    flags.set(Flags::CF, carry || (b && y == I::zero()));
-    flags.set(Flags::ZF, result.is_zero());
-    flags.set(Flags::SF, (result >> (I::bits() - 1)).is_one());
+/// Subtract with borrow-in b.
+fn sbb<I: Int + num_traits::WrappingSub>(x: I, y: I, b: bool, flags: &mut Flags) -> I {
+    let b = if b { I::one() } else { I::zero() };
+    let result = x.wrapping_sub(&y).wrapping_sub(&b);
+    // Borrow out of either step.
+    let cf = x
+        .checked_sub(&y)
+        .and_then(|diff| diff.checked_sub(&b))
+        .is_none();
+    flags.set(Flags::CF, cf);
     // Overflow is true exactly when the high (sign) bits are like:
     //   x  y  result
     //   0  1  1
     //   1  0  0
-    let of = !(((x ^ y) & (x ^ result)) >> (I::bits() - 1)).is_zero();
-    flags.set(Flags::OF, of);
+    flags.set(Flags::OF, msb((x ^ y) & (x ^ result)));
+    set_af(x, y, result, flags);
+    set_result_flags(result, flags);
     result
 }
 
 // pub(crate) for use in the cmp opcode impl.
-pub(crate) fn sub<
-    I: Int + num_traits::ops::overflowing::OverflowingSub + num_traits::WrappingAdd,
->(
-    x: I,
-    y: I,
-    flags: &mut Flags,
-) -> I {
+pub(crate) fn sub<I: Int + num_traits::WrappingSub>(x: I, y: I, flags: &mut Flags) -> I {
     sbb(x, y, false, flags)
 }
 
@@ -837,29 +861,49 @@ pub fn imul_r32_rm32_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 pub fn idiv_rm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     let x = get_edx_eax(cpu) as i64;
     let y = rm32(cpu, mem, instr).get() as i32 as i64;
-    cpu.regs.set32(Register::EAX, (x / y) as i32 as u32);
-    cpu.regs.set32(Register::EDX, (x % y) as i32 as u32);
+    if y == 0 {
+        cpu.fault(Fault::DivideError);
+        return;
+    }
+    let quotient = x.wrapping_div(y);
+    if quotient > i32::MAX as i64 || quotient < i32::MIN as i64 {
+        cpu.fault(Fault::DivideError);
+        return;
+    }
+    cpu.regs.set32(Register::EAX, quotient as i32 as u32);
+    cpu.regs
+        .set32(Register::EDX, x.wrapping_rem(y) as i32 as u32);
 }
 
 pub fn idiv_rm16(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     let x = get_dx_ax(cpu) as i32;
     let y = rm16(cpu, mem, instr).get() as i16 as i32;
-    let quotient = x / y;
+    if y == 0 {
+        cpu.fault(Fault::DivideError);
+        return;
+    }
+    let quotient = x.wrapping_div(y);
     if quotient > 0x7FFF || quotient < -0x8000 {
-        panic!("divide error");
+        cpu.fault(Fault::DivideError);
+        return;
     }
     cpu.regs.set16(Register::AX, quotient as i16 as u16);
-    cpu.regs.set16(Register::DX, (x % y) as u16);
+    cpu.regs.set16(Register::DX, x.wrapping_rem(y) as u16);
 }
 
 pub fn idiv_rm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     let x = cpu.regs.get16(Register::AX) as i16;
     let y = rm8(cpu, mem, instr).get() as i8 as i16;
-    let quotient = x / y;
+    if y == 0 {
+        cpu.fault(Fault::DivideError);
+        return;
+    }
+    let quotient = x.wrapping_div(y);
     if quotient > 0x7F || quotient < -0x80 {
-        panic!("divide error");
+        cpu.fault(Fault::DivideError);
+        return;
     }
-    let rem = x % y;
+    let rem = x.wrapping_rem(y);
     cpu.regs
         .set16(Register::AX, ((rem << 8) as u16) | (quotient as i8 as u16));
 }
@@ -867,6 +911,10 @@ pub fn idiv_rm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 pub fn div_rm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     let x = get_edx_eax(cpu);
     let y = rm32(cpu, mem, instr).get() as u64;
+    if y == 0 || x / y > u32::MAX as u64 {
+        cpu.fault(Fault::DivideError);
+        return;
+    }
     cpu.regs.set32(Register::EAX, (x / y) as u32);
     cpu.regs.set32(Register::EDX, (x % y) as u32);
     // No flags.
@@ -875,6 +923,10 @@ pub fn div_rm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 pub fn div_rm16(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     let x = get_dx_ax(cpu);
     let y = rm16(cpu, mem, instr).get() as u32;
+    if y == 0 || x / y > u16::MAX as u32 {
+        cpu.fault(Fault::DivideError);
+        return;
+    }
     cpu.regs.set32(Register::EAX, ((x / y) as u16) as u32);
     cpu.regs.set32(Register::EDX, ((x % y) as u16) as u32);
     // No flags.
@@ -883,6 +935,10 @@ pub fn div_rm16(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 pub fn div_rm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     let x = cpu.regs.get16(Register::AX);
     let y = rm8(cpu, mem, instr).get() as u16;
+    if y == 0 || x / y > u8::MAX as u16 {
+        cpu.fault(Fault::DivideError);
+        return;
+    }
     cpu.regs
         .set32(Register::EAX, (((x % y) as u32) << 16) | ((x / y) as u32));
     // No flags.
@@ -890,10 +946,9 @@ pub fn div_rm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 
 fn dec<I: Int + num_traits::WrappingSub>(x: I, flags: &mut Flags) -> I {
     // Note this is not sub(1) because CF should be preserved.
-    let result = x.wrapping_sub(&I::one());
-    flags.set(Flags::OF, result.is_zero());
-    flags.set(Flags::SF, (result >> (I::bits() - 1)).is_one());
-    flags.set(Flags::ZF, result.is_zero());
+    let cf = flags.contains(Flags::CF);
+    let result = sub(x, I::one(), flags);
+    flags.set(Flags::CF, cf);
     result
 }
 
@@ -914,10 +969,9 @@ pub fn dec_rm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 
 fn inc<I: Int + num_traits::WrappingAdd>(x: I, flags: &mut Flags) -> I {
     // Note this is not add(1) because CF should be preserved.
-    let result = x.wrapping_add(&I::one());
-    flags.set(Flags::OF, result.is_zero());
-    flags.set(Flags::SF, (result >> (I::bits() - 1)).is_one());
-    flags.set(Flags::ZF, result.is_zero());
+    let cf = flags.contains(Flags::CF);
+    let result = add(x, I::one(), flags);
+    flags.set(Flags::CF, cf);
     result
 }
 
@@ -936,12 +990,9 @@ pub fn inc_rm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     x.set(inc(x.get(), &mut cpu.flags));
 }
 
-fn neg<I: Int + OverflowingSub>(x: I, flags: &mut Flags) -> I {
-    let (res, of) = I::zero().overflowing_sub(&x);
-    flags.set(Flags::ZF, res.is_zero());
-    flags.set(Flags::CF, !res.is_zero());
-    flags.set(Flags::OF, of);
-    res
+fn neg<I: Int + num_traits::WrappingSub>(x: I, flags: &mut Flags) -> I {
+    // Flags are as for 0 - x, which sets CF unless x is 0.
+    sub(I::zero(), x, flags)
 }
 
 pub fn neg_rm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
@@ -973,3 +1024,280 @@ pub fn not_rm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     let x = rm8(cpu, mem, instr);
     x.set(!x.get())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CF: Flags = Flags::CF;
+    const PF: Flags = Flags::PF;
+    const AF: Flags = Flags::AF;
+    const ZF: Flags = Flags::ZF;
+    const SF: Flags = Flags::SF;
+    const OF: Flags = Flags::OF;
+    const NONE: Flags = Flags::empty();
+
+    /// Flags defined by add/sub and friends.
+    const ARITH: Flags = CF.union(PF).union(AF).union(ZF).union(SF).union(OF);
+    /// Flags defined by 1-bit shifts, which leave AF undefined.
+    const SHIFT1: Flags = CF.union(PF).union(ZF).union(SF).union(OF);
+    /// Flags defined by multi-bit shifts, which also leave OF undefined.
+    const SHIFT: Flags = CF.union(PF).union(ZF).union(SF);
+    /// Flags defined by 1-bit rotates.
+    const ROTATE: Flags = CF.union(OF);
+
+    type Op<I> = fn(I, I, &mut Flags) -> I;
+
+    fn adc<I: Int + num_traits::WrappingAdd>(x: I, y: I, flags: &mut Flags) -> I {
+        let carry = if flags.contains(CF) {
+            I::one()
+        } else {
+            I::zero()
+        };
+        addc(x, y, carry, flags)
+    }
+
+    fn sbb_cf<I: Int + num_traits::WrappingSub>(x: I, y: I, flags: &mut Flags) -> I {
+        sbb(x, y, flags.contains(CF), flags)
+    }
+
+    /// Each case is (op, x, y, CF before, result, flags after, flags to compare).
+    fn check<I: Int + std::fmt::Debug>(cases: &[(Op<I>, I, I, bool, I, Flags, Flags)]) {
+        for (i, &(op, x, y, cf, result, expected, defined)) in cases.iter().enumerate() {
+            let mut flags = if cf { CF } else { NONE };
+            let actual = op(x, y, &mut flags);
+            assert_eq!(
+                (actual, flags & defined),
+                (result, expected),
+                "case {i}: {x:?}, {y:?}"
+            );
+        }
+    }
+
+    // Expected flags are as produced by an x86 CPU.
+    #[test]
+    fn flags8() {
+        check::<u8>(&[
+            (add, 0xFF, 0x01, false, 0x00, CF | PF | AF | ZF, ARITH),
+            (add, 0x7F, 0x01, false, 0x80, AF | SF | OF, ARITH),
+            (add, 0x08, 0x08, false, 0x10, AF, ARITH),
+            (adc, 0xFF, 0x00, true, 0x00, CF | PF | AF | ZF, ARITH),
+            (adc, 0x80, 0x7F, true, 0x00, CF | PF | AF | ZF, ARITH),
+            (adc, 0x7F, 0x00, true, 0x80, AF | SF | OF, ARITH),
+            (adc, 0x7F, 0xFF, true, 0x7F, CF | AF, ARITH),
+            (adc, 0x01, 0x02, false, 0x03, PF, ARITH),
+            (sub, 0x00, 0x01, false, 0xFF, CF | PF | AF | SF, ARITH),
+            (sub, 0x80, 0x01, false, 0x7F, AF | OF, ARITH),
+            (sub, 0x05, 0x05, true, 0x00, PF | ZF, ARITH),
+            (sbb_cf, 0x00, 0xFF, true, 0x00, CF | PF | AF | ZF, ARITH),
+            (sbb_cf, 0x80, 0x7F, true, 0x00, PF | AF | ZF | OF, ARITH),
+            (sbb_cf, 0x10, 0x00, true, 0x0F, PF | AF, ARITH),
+            (sbb_cf, 0x10, 0x00, false, 0x10, NONE, ARITH),
+            (
+                |x, _, f| inc(x, f),
+                0x7F,
+                0,
+                true,
+                0x80,
+                CF | AF | SF | OF,
+                ARITH,
+            ),
+            (
+                |x, _, f| inc(x, f),
+                0xFF,
+                0,
+                false,
+                0x00,
+                PF | AF | ZF,
+                ARITH,
+            ),
+            (
+                |x, _, f| dec(x, f),
+                0x80,
+                0,
+                true,
+                0x7F,
+                CF | AF | OF,
+                ARITH,
+            ),
+            (
+                |x, _, f| dec(x, f),
+                0x00,
+                0,
+                false,
+                0xFF,
+                PF | AF | SF,
+                ARITH,
+            ),
+            (
+                |x, _, f| neg(x, f),
+                0x80,
+                0,
+                false,
+                0x80,
+                CF | SF | OF,
+                ARITH,
+            ),
+            (|x, _, f| neg(x, f), 0x00, 0, true, 0x00, PF | ZF, ARITH),
+            (
+                |x, _, f| neg(x, f),
+                0x01,
+                0,
+                false,
+                0xFF,
+                CF | PF | AF | SF,
+                ARITH,
+            ),
+            (
+                |x, y, f| shl(x, y, f),
+                0x81,
+                1,
+                false,
+                0x02,
+                CF | OF,
+                SHIFT1,
+            ),
+            (|x, y, f| shl(x, y, f), 0x40, 1, true, 0x80, SF | OF, SHIFT1),
+            (
+                |x, y, f| shl(x, y, f),
+                0x01,
+                8,
+                false,
+                0x00,
+                CF | PF | ZF,
+                SHIFT,
+            ),
+            (|x, y, f| shl(x, y, f), 0x33, 0, true, 0x33, CF, ARITH),
+            (
+                |x, y, f| shr(x, y, f),
+                0x81,
+                1,
+                false,
+                0x40,
+                CF | OF,
+                SHIFT1,
+            ),
+            (
+                |x, y, f| shr(x, y, f),
+                0x80,
+                8,
+                false,
+                0x00,
+                CF | PF | ZF,
+                SHIFT,
+            ),
+            (
+                |x, y, f| sar(x, y, f),
+                0x81,
+                1,
+                false,
+                0xC0,
+                CF | PF | SF,
+                SHIFT1,
+            ),
+            (
+                |x, y, f| sar(x, y, f),
+                0x80,
+                9,
+                false,
+                0xFF,
+                CF | PF | SF,
+                SHIFT,
+            ),
+            (|x, y, f| sar(x, y, f), 0x40, 2, true, 0x10, NONE, SHIFT),
+            (
+                |x, y, f| rol(x, y, f),
+                0x81,
+                1,
+                false,
+                0x03,
+                CF | OF,
+                ROTATE,
+            ),
+            (|x, y, f| rol(x, y, f), 0x01, 8, false, 0x01, CF, CF),
+            (
+                |x, y, f| ror(x, y, f),
+                0x01,
+                1,
+                false,
+                0x80,
+                CF | OF,
+                ROTATE,
+            ),
+            (|x, y, f| ror(x, y, f), 0x81, 1, false, 0xC0, CF, ROTATE),
+        ]);
+    }
+
+    #[test]
+    fn flags16() {
+        check::<u16>(&[
+            (add, 0xFFFF, 0x0001, false, 0x0000, CF | PF | AF | ZF, ARITH),
+            (sub, 0x8000, 0x0001, false, 0x7FFF, PF | AF | OF, ARITH),
+            (adc, 0x00FF, 0x0000, true, 0x0100, PF | AF, ARITH),
+            (
+                |x, y, f| shr(x, y as u8, f),
+                0x8001,
+                1,
+                false,
+                0x4000,
+                CF | PF | OF,
+                SHIFT1,
+            ),
+        ]);
+    }
+
+    #[test]
+    fn flags32() {
+        check::<u32>(&[
+            (add, 0xFFFF_FFFF, 1, false, 0, CF | PF | AF | ZF, ARITH),
+            (
+                adc,
+                0x7FFF_FFFF,
+                0xFFFF_FFFF,
+                true,
+                0x7FFF_FFFF,
+                CF | PF | AF,
+                ARITH,
+            ),
+            (
+                adc,
+                0xFFFF_FFFF,
+                0xFFFF_FFFF,
+                true,
+                0xFFFF_FFFF,
+                CF | PF | AF | SF,
+                ARITH,
+            ),
+            (sbb_cf, 0, 0xFFFF_FFFF, true, 0, CF | PF | AF | ZF, ARITH),
+            (sbb_cf, 0, 0, true, 0xFFFF_FFFF, CF | PF | AF | SF, ARITH),
+            (
+                |x, y, f| shl(x, y as u8, f),
+                0x8000_0000,
+                1,
+                false,
+                0,
+                CF | PF | ZF | OF,
+                SHIFT1,
+            ),
+            (|x, y, f| shl(x, y as u8, f), 1, 33, false, 2, NONE, SHIFT1),
+            (
+                |x, y, f| sar(x, y, f),
+                0x8000_0000,
+                31,
+                false,
+                0xFFFF_FFFF,
+                PF | SF,
+                SHIFT,
+            ),
+            (
+                |x, y, f| ror(x, y as u8, f),
+                1,
+                1,
+                false,
+                0x8000_0000,
+                CF | OF,
+                ROTATE,
+            ),
+        ]);
+    }
+}
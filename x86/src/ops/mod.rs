@@ -1,13 +1,16 @@
 mod basic;
+mod bits;
 mod control;
 mod cpuid;
 mod fpu;
 mod helpers;
 mod math;
 mod mmx;
+mod sse;
 mod string;
 mod table;
 mod test;
 
+pub use cpuid::{CPUIDLeaf, CPUIDLeaves, TimeStampCounter};
 pub use helpers::{pop, push, set_edx_eax, x86_jmp};
 pub use table::{decode, init_op_tab, Op};
@@ -0,0 +1,376 @@
+//! SSE/SSE2 data movement and floating point arithmetic on the xmm registers.
+
+use super::helpers::*;
+use crate::{x86::Fault, CPU};
+use iced_x86::{Instruction, OpKind};
+use memory::Mem;
+
+/// Address of a 128-bit memory operand, faulting if it must be aligned and isn't.
+fn xmm_addr(cpu: &mut CPU, instr: &Instruction, aligned: bool) -> Option<u32> {
+    let addr = x86_addr(cpu, instr);
+    if aligned && addr % 16 != 0 {
+        cpu.fault(Fault::GeneralProtection);
+        return None;
+    }
+    Some(addr)
+}
+
+/// Read the xmm/m128 source operand.  Legacy SSE encodings require aligned memory
+/// operands everywhere except the unaligned moves.
+fn op1_xmmm128(cpu: &mut CPU, mem: Mem, instr: &Instruction, aligned: bool) -> Option<u128> {
+    match instr.op1_kind() {
+        OpKind::Register => Some(cpu.regs.get128(instr.op1_register())),
+        OpKind::Memory => {
+            let addr = xmm_addr(cpu, instr, aligned)?;
+            let value = load::<u128>(cpu, mem, addr);
+            cpu.state.is_running().then_some(value)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Read the xmm/m32 source operand, as its low 32 bits.
+fn op1_xmmm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) -> Option<u32> {
+    match instr.op1_kind() {
+        OpKind::Register => Some(cpu.regs.get128(instr.op1_register()) as u32),
+        OpKind::Memory => {
+            let addr = x86_addr(cpu, instr);
+            let value = load::<u32>(cpu, mem, addr);
+            cpu.state.is_running().then_some(value)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Read the xmm/m64 source operand, as its low 64 bits.
+fn op1_xmmm64(cpu: &mut CPU, mem: Mem, instr: &Instruction) -> Option<u64> {
+    match instr.op1_kind() {
+        OpKind::Register => Some(cpu.regs.get128(instr.op1_register()) as u64),
+        OpKind::Memory => {
+            let addr = x86_addr(cpu, instr);
+            let value = load::<u64>(cpu, mem, addr);
+            cpu.state.is_running().then_some(value)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn unpack_ps(x: u128) -> [f32; 4] {
+    std::array::from_fn(|i| f32::from_bits((x >> (i * 32)) as u32))
+}
+
+fn pack_ps(x: [f32; 4]) -> u128 {
+    x.iter()
+        .enumerate()
+        .fold(0, |acc, (i, f)| acc | ((f.to_bits() as u128) << (i * 32)))
+}
+
+/// Apply op lanewise to four packed singles.
+fn packed_ps(x: u128, y: u128, op: fn(f32, f32) -> f32) -> u128 {
+    let (x, y) = (unpack_ps(x), unpack_ps(y));
+    pack_ps(std::array::from_fn(|i| op(x[i], y[i])))
+}
+
+/// Apply op to the low single of x, leaving the upper lanes alone.
+fn scalar_ss(x: u128, y: u32, op: fn(f32, f32) -> f32) -> u128 {
+    let value = op(f32::from_bits(x as u32), f32::from_bits(y));
+    (x & !0xFFFF_FFFF) | value.to_bits() as u128
+}
+
+/// Apply op to the low double of x, leaving the upper lane alone.
+fn scalar_sd(x: u128, y: u64, op: fn(f64, f64) -> f64) -> u128 {
+    let value = op(f64::from_bits(x as u64), f64::from_bits(y));
+    (x & !(u64::MAX as u128)) | value.to_bits() as u128
+}
+
+fn add<T: std::ops::Add<Output = T>>(x: T, y: T) -> T {
+    x + y
+}
+fn sub<T: std::ops::Sub<Output = T>>(x: T, y: T) -> T {
+    x - y
+}
+fn mul<T: std::ops::Mul<Output = T>>(x: T, y: T) -> T {
+    x * y
+}
+fn div<T: std::ops::Div<Output = T>>(x: T, y: T) -> T {
+    x / y
+}
+
+/// Shared impl of the xmm/m128 stores; aligned for movaps, unaligned for movups.
+fn mov_xmmm128_xmm(cpu: &mut CPU, mem: Mem, instr: &Instruction, aligned: bool) {
+    let y = cpu.regs.get128(instr.op1_register());
+    match instr.op0_kind() {
+        OpKind::Register => cpu.regs.set128(instr.op0_register(), y),
+        OpKind::Memory => {
+            if let Some(addr) = xmm_addr(cpu, instr, aligned) {
+                store::<u128>(cpu, mem, addr, y);
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+pub fn movaps_xmm_xmmm128(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    if let Some(y) = op1_xmmm128(cpu, mem, instr, true) {
+        cpu.regs.set128(instr.op0_register(), y);
+    }
+}
+
+pub fn movaps_xmmm128_xmm(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    mov_xmmm128_xmm(cpu, mem, instr, true);
+}
+
+pub fn movups_xmm_xmmm128(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    if let Some(y) = op1_xmmm128(cpu, mem, instr, false) {
+        cpu.regs.set128(instr.op0_register(), y);
+    }
+}
+
+pub fn movups_xmmm128_xmm(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    mov_xmmm128_xmm(cpu, mem, instr, false);
+}
+
+pub fn movss_xmm_xmmm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let Some(y) = op1_xmmm32(cpu, mem, instr) else {
+        return;
+    };
+    let reg = instr.op0_register();
+    // From memory the upper lanes are zeroed; between registers they're kept.
+    let x = match instr.op1_kind() {
+        OpKind::Register => cpu.regs.get128(reg) & !0xFFFF_FFFF,
+        _ => 0,
+    };
+    cpu.regs.set128(reg, x | y as u128);
+}
+
+pub fn movss_xmmm32_xmm(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let y = cpu.regs.get128(instr.op1_register()) as u32;
+    match instr.op0_kind() {
+        OpKind::Register => {
+            let reg = instr.op0_register();
+            let x = cpu.regs.get128(reg) & !0xFFFF_FFFF;
+            cpu.regs.set128(reg, x | y as u128);
+        }
+        OpKind::Memory => {
+            let addr = x86_addr(cpu, instr);
+            store::<u32>(cpu, mem, addr, y);
+        }
+        _ => unreachable!(),
+    }
+}
+
+pub fn movsd_xmm_xmmm64(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let Some(y) = op1_xmmm64(cpu, mem, instr) else {
+        return;
+    };
+    let reg = instr.op0_register();
+    // As with movss, only the register form keeps the upper lane.
+    let x = match instr.op1_kind() {
+        OpKind::Register => cpu.regs.get128(reg) & !(u64::MAX as u128),
+        _ => 0,
+    };
+    cpu.regs.set128(reg, x | y as u128);
+}
+
+pub fn movsd_xmmm64_xmm(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    let y = cpu.regs.get128(instr.op1_register()) as u64;
+    match instr.op0_kind() {
+        OpKind::Register => {
+            let reg = instr.op0_register();
+            let x = cpu.regs.get128(reg) & !(u64::MAX as u128);
+            cpu.regs.set128(reg, x | y as u128);
+        }
+        OpKind::Memory => {
+            let addr = x86_addr(cpu, instr);
+            store::<u64>(cpu, mem, addr, y);
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn op_ps(cpu: &mut CPU, mem: Mem, instr: &Instruction, op: fn(f32, f32) -> f32) {
+    let Some(y) = op1_xmmm128(cpu, mem, instr, true) else {
+        return;
+    };
+    let reg = instr.op0_register();
+    let x = cpu.regs.get128(reg);
+    cpu.regs.set128(reg, packed_ps(x, y, op));
+}
+
+fn op_ss(cpu: &mut CPU, mem: Mem, instr: &Instruction, op: fn(f32, f32) -> f32) {
+    let Some(y) = op1_xmmm32(cpu, mem, instr) else {
+        return;
+    };
+    let reg = instr.op0_register();
+    let x = cpu.regs.get128(reg);
+    cpu.regs.set128(reg, scalar_ss(x, y, op));
+}
+
+fn op_sd(cpu: &mut CPU, mem: Mem, instr: &Instruction, op: fn(f64, f64) -> f64) {
+    let Some(y) = op1_xmmm64(cpu, mem, instr) else {
+        return;
+    };
+    let reg = instr.op0_register();
+    let x = cpu.regs.get128(reg);
+    cpu.regs.set128(reg, scalar_sd(x, y, op));
+}
+
+pub fn addps_xmm_xmmm128(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_ps(cpu, mem, instr, add);
+}
+
+pub fn subps_xmm_xmmm128(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_ps(cpu, mem, instr, sub);
+}
+
+pub fn mulps_xmm_xmmm128(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_ps(cpu, mem, instr, mul);
+}
+
+pub fn divps_xmm_xmmm128(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_ps(cpu, mem, instr, div);
+}
+
+pub fn addss_xmm_xmmm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_ss(cpu, mem, instr, add);
+}
+
+pub fn subss_xmm_xmmm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_ss(cpu, mem, instr, sub);
+}
+
+pub fn mulss_xmm_xmmm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_ss(cpu, mem, instr, mul);
+}
+
+pub fn divss_xmm_xmmm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_ss(cpu, mem, instr, div);
+}
+
+pub fn addsd_xmm_xmmm64(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_sd(cpu, mem, instr, add);
+}
+
+pub fn subsd_xmm_xmmm64(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_sd(cpu, mem, instr, sub);
+}
+
+pub fn mulsd_xmm_xmmm64(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_sd(cpu, mem, instr, mul);
+}
+
+pub fn divsd_xmm_xmmm64(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
+    op_sd(cpu, mem, instr, div);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{Test, CODE, DATA},
+        CPUState,
+    };
+    use iced_x86::Register::{XMM0, XMM1};
+    use memory::ExtensionsMut;
+
+    const X: [f32; 4] = [1.5, -2.25, 1e30, 0.1];
+    const Y: [f32; 4] = [0.5, 4.0, 1e10, 3.0];
+
+    /// What xmm0 holds before each test instruction.
+    const OLD: u128 = 0x1111_2222_3333_4444_5555_6666_7777_8888;
+    /// What xmm1 and the 16 bytes at DATA hold.
+    const NEW: u128 = 0xAAAA_BBBB_CCCC_DDDD_EEEE_FFFF_0123_4567;
+
+    /// Run an instruction, appending addr as its disp32 if given, with xmm0 = OLD and
+    /// NEW in xmm1 and at DATA.
+    fn run(instr: &[u8], addr: Option<u32>) -> Test {
+        let mut code = instr.to_vec();
+        if let Some(addr) = addr {
+            code.extend_from_slice(&addr.to_le_bytes());
+        }
+        let mut test = Test::new(&code);
+        test.mem().put_pod::<u128>(DATA, NEW);
+        let regs = &mut test.x86.cpu_mut().regs;
+        regs.set128(XMM0, OLD);
+        regs.set128(XMM1, NEW);
+        test.run();
+        test
+    }
+
+    #[test]
+    fn movaps_misaligned() {
+        // movaps xmm0, [addr]
+        const MOVAPS: [u8; 3] = [0x0f, 0x28, 0x05];
+        let test = run(&MOVAPS, Some(DATA));
+        assert_eq!(test.x86.cpu().regs.get128(XMM0), NEW);
+
+        let test = run(&MOVAPS, Some(DATA + 4));
+        let cpu = test.x86.cpu();
+        assert_eq!(cpu.state, CPUState::Fault(Fault::GeneralProtection));
+        assert_eq!(cpu.regs.eip, CODE);
+        assert_eq!(cpu.regs.get128(XMM0), OLD);
+
+        // movups xmm0, [addr] doesn't care.
+        let test = run(&[0x0f, 0x10, 0x05], Some(DATA + 4));
+        assert!(!matches!(test.x86.cpu().state, CPUState::Fault(_)));
+    }
+
+    #[test]
+    fn movss_movsd_from_memory() {
+        // movss xmm0, [DATA]: the upper lanes are zeroed.
+        let test = run(&[0xf3, 0x0f, 0x10, 0x05], Some(DATA));
+        assert_eq!(test.x86.cpu().regs.get128(XMM0), NEW & 0xFFFF_FFFF);
+        // movsd xmm0, [DATA]
+        let test = run(&[0xf2, 0x0f, 0x10, 0x05], Some(DATA));
+        assert_eq!(test.x86.cpu().regs.get128(XMM0), NEW & u64::MAX as u128);
+    }
+
+    #[test]
+    fn movss_movsd_between_registers() {
+        // movss xmm0, xmm1: the upper lanes are kept.
+        let test = run(&[0xf3, 0x0f, 0x10, 0xc1], None);
+        assert_eq!(
+            test.x86.cpu().regs.get128(XMM0),
+            (OLD & !0xFFFF_FFFF) | (NEW & 0xFFFF_FFFF)
+        );
+        // movsd xmm0, xmm1
+        let low = u64::MAX as u128;
+        let test = run(&[0xf2, 0x0f, 0x10, 0xc1], None);
+        assert_eq!(test.x86.cpu().regs.get128(XMM0), (OLD & !low) | (NEW & low));
+    }
+
+    #[test]
+    fn packed() {
+        let ops: [(u8, fn(f32, f32) -> f32); 4] = [
+            (0x58, |a, b| a + b),
+            (0x5c, |a, b| a - b),
+            (0x59, |a, b| a * b),
+            (0x5e, |a, b| a / b),
+        ];
+        for (opcode, reference) in ops {
+            // op xmm0, xmm1
+            let mut test = Test::new(&[0x0f, opcode, 0xc1]);
+            let regs = &mut test.x86.cpu_mut().regs;
+            regs.set128(XMM0, pack_ps(X));
+            regs.set128(XMM1, pack_ps(Y));
+            test.run();
+            let got = unpack_ps(test.x86.cpu().regs.get128(XMM0));
+            for i in 0..4 {
+                assert_eq!(got[i].to_bits(), reference(X[i], Y[i]).to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn scalar() {
+        let x = pack_ps(X);
+        let got = unpack_ps(scalar_ss(x, Y[0].to_bits(), mul));
+        assert_eq!(got, [X[0] * Y[0], X[1], X[2], X[3]]);
+
+        let high = 0x0123_4567_89AB_CDEFu128 << 64;
+        let x = high | 1.1f64.to_bits() as u128;
+        let got = scalar_sd(x, 3.3f64.to_bits(), div);
+        assert_eq!(got & !(u64::MAX as u128), high);
+        assert_eq!(f64::from_bits(got as u64), 1.1 / 3.3);
+    }
+}
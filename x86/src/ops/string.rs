@@ -1,9 +1,12 @@
 //! Ops that tend to loop with 'rep' prefix, e.g. movs, stos.
 
-use super::math::sub;
-use crate::{registers::Flags, x86::CPU};
+use super::{
+    helpers::{load, store},
+    math::sub,
+};
+use crate::{memmap::Protect, registers::Flags, x86::CPU};
 use iced_x86::{Instruction, Register};
-use memory::{Extensions, ExtensionsMut, Mem};
+use memory::{ExtensionsMut, Mem};
 
 /// Width of an operation, e.g. movsb/w/d.
 #[derive(Clone, Copy)]
@@ -45,12 +48,42 @@ impl Rep {
     }
 }
 
+/// The amount to adjust esi/edi by after each element, according to the direction flag.
+fn step(cpu: &CPU, size: Size) -> u32 {
+    if cpu.flags.contains(Flags::DF) {
+        (size as u32).wrapping_neg()
+    } else {
+        size as u32
+    }
+}
+
+fn advance(cpu: &mut CPU, reg: Register, delta: u32) {
+    let val = cpu.regs.get32(reg).wrapping_add(delta);
+    cpu.regs.set32(reg, val);
+}
+
+/// For a rep'd op over count elements starting at addr, the range of memory touched,
+/// or None if it wraps the address space.
+fn block(cpu: &CPU, addr: u32, count: u32, size: Size) -> Option<std::ops::Range<u32>> {
+    let len = count.checked_mul(size as u32)?;
+    let start = if cpu.flags.contains(Flags::DF) {
+        addr.checked_sub(len.checked_sub(size as u32)?)?
+    } else {
+        addr
+    };
+    Some(start..start.checked_add(len)?)
+}
+
 /// Looping logic of various 'rep' prefixes, generalized for different instructions.
 /// Note: some instructions do not have varying Reps and it is important to treat them
 /// as plain REP; e.g. "REPNE MOVS" just means "REP MOVS".
 fn rep(cpu: &mut CPU, mem: Mem, rep: Rep, size: Size, func: impl Fn(&mut CPU, Mem, Size)) {
     while cpu.regs.get32(Register::ECX) > 0 {
         func(cpu, mem, size);
+        if !cpu.state.is_running() {
+            // Faulted; leave ecx/esi/edi pointing at the failed element.
+            break;
+        }
         *cpu.regs.get32_mut(Register::ECX) -= 1;
         match rep {
             Rep::REPE if !cpu.flags.contains(Flags::ZF) => break,
@@ -61,30 +94,31 @@ fn rep(cpu: &mut CPU, mem: Mem, rep: Rep, size: Size, func: impl Fn(&mut CPU, Me
 }
 
 fn cmps_single(cpu: &mut CPU, mem: Mem, size: Size) {
+    let esi = cpu.regs.get32(Register::ESI);
+    let edi = cpu.regs.get32(Register::EDI);
     match size {
         Size::Dword => {
-            let x = mem.get_pod::<u32>(cpu.regs.get32(Register::ESI));
-            let y = mem.get_pod::<u32>(cpu.regs.get32(Register::EDI));
+            let x = load::<u32>(cpu, mem, esi);
+            let y = load::<u32>(cpu, mem, edi);
             sub(x, y, &mut cpu.flags);
         }
         Size::Word => {
-            let x = mem.get_pod::<u16>(cpu.regs.get32(Register::ESI));
-            let y = mem.get_pod::<u16>(cpu.regs.get32(Register::EDI));
+            let x = load::<u16>(cpu, mem, esi);
+            let y = load::<u16>(cpu, mem, edi);
             sub(x, y, &mut cpu.flags);
         }
         Size::Byte => {
-            let x = mem.get_pod::<u8>(cpu.regs.get32(Register::ESI));
-            let y = mem.get_pod::<u8>(cpu.regs.get32(Register::EDI));
+            let x = load::<u8>(cpu, mem, esi);
+            let y = load::<u8>(cpu, mem, edi);
             sub(x, y, &mut cpu.flags);
         }
     }
-    if cpu.flags.contains(Flags::DF) {
-        *cpu.regs.get32_mut(Register::EDI) -= size as u32;
-        *cpu.regs.get32_mut(Register::ESI) -= size as u32;
-    } else {
-        *cpu.regs.get32_mut(Register::EDI) += size as u32;
-        *cpu.regs.get32_mut(Register::ESI) += size as u32;
-    };
+    if !cpu.state.is_running() {
+        return;
+    }
+    let delta = step(cpu, size);
+    advance(cpu, Register::EDI, delta);
+    advance(cpu, Register::ESI, delta);
 }
 
 fn cmps(cpu: &mut CPU, mem: Mem, instr: &Instruction, size: Size) {
@@ -108,31 +142,82 @@ pub fn cmpsb(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 fn movs_single(cpu: &mut CPU, mem: Mem, size: Size) {
+    let esi = cpu.regs.get32(Register::ESI);
+    let edi = cpu.regs.get32(Register::EDI);
     match size {
         Size::Dword => {
-            let src = mem.get_pod::<u32>(cpu.regs.get32(Register::ESI));
-            mem.put_pod::<u32>(cpu.regs.get32(Register::EDI), src);
+            let src = load::<u32>(cpu, mem, esi);
+            store::<u32>(cpu, mem, edi, src);
         }
         Size::Word => {
-            let src = mem.get_pod::<u16>(cpu.regs.get32(Register::ESI));
-            mem.put_pod::<u16>(cpu.regs.get32(Register::EDI), src);
+            let src = load::<u16>(cpu, mem, esi);
+            store::<u16>(cpu, mem, edi, src);
         }
         Size::Byte => {
-            let src = mem.get_pod::<u8>(cpu.regs.get32(Register::ESI));
-            mem.put_pod::<u8>(cpu.regs.get32(Register::EDI), src);
+            let src = load::<u8>(cpu, mem, esi);
+            store::<u8>(cpu, mem, edi, src);
         }
     }
-    if cpu.flags.contains(Flags::DF) {
-        *cpu.regs.get32_mut(Register::EDI) -= size as u32;
-        *cpu.regs.get32_mut(Register::ESI) -= size as u32;
-    } else {
-        *cpu.regs.get32_mut(Register::EDI) += size as u32;
-        *cpu.regs.get32_mut(Register::ESI) += size as u32;
+    if !cpu.state.is_running() {
+        return;
+    }
+    let delta = step(cpu, size);
+    advance(cpu, Register::EDI, delta);
+    advance(cpu, Register::ESI, delta);
+}
+
+/// rep movs as a single memmove, when that gives the same result as copying element by element.
+/// Returns false if the caller must fall back to the element loop.
+fn movs_block(cpu: &mut CPU, mem: Mem, size: Size) -> bool {
+    let count = cpu.regs.get32(Register::ECX);
+    let src = cpu.regs.get32(Register::ESI);
+    let dst = cpu.regs.get32(Register::EDI);
+    let (Some(src_range), Some(dst_range)) =
+        (block(cpu, src, count, size), block(cpu, dst, count, size))
+    else {
+        return false;
     };
+    if src_range.end > mem.len() || dst_range.end > mem.len() {
+        return false;
+    }
+    // If any of it faults or is watched, let the element loop find exactly where.
+    if !cpu
+        .memmap
+        .check(src_range.start, src_range.len() as u32, Protect::READ)
+        || !cpu
+            .memmap
+            .check(dst_range.start, dst_range.len() as u32, Protect::WRITE)
+        || cpu
+            .memmap
+            .watched(src_range.start, src_range.len() as u32, Protect::READ)
+            .is_some()
+        || cpu
+            .memmap
+            .watched(dst_range.start, dst_range.len() as u32, Protect::WRITE)
+            .is_some()
+    {
+        return false;
+    }
+    // An overlapping copy "towards" the direction of travel re-reads bytes it already wrote,
+    // which is used e.g. to replicate a pattern, and which memmove doesn't reproduce.
+    let overlaps = src_range.start < dst_range.end && dst_range.start < src_range.end;
+    let forward = !cpu.flags.contains(Flags::DF);
+    if overlaps && (if forward { dst > src } else { dst < src }) {
+        return false;
+    }
+    mem.copy(src_range.start, dst_range.start, src_range.len() as u32);
+    let delta = step(cpu, size).wrapping_mul(count);
+    advance(cpu, Register::ESI, delta);
+    advance(cpu, Register::EDI, delta);
+    cpu.regs.set32(Register::ECX, 0);
+    true
 }
 
 fn movs(cpu: &mut CPU, mem: Mem, instr: &Instruction, size: Size) {
     if Rep::is_rep(instr) {
+        if movs_block(cpu, mem, size) {
+            return;
+        }
         rep(cpu, mem, Rep::REP, size, movs_single);
     } else {
         movs_single(cpu, mem, size);
@@ -152,25 +237,26 @@ pub fn movsb(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 fn scas_single(cpu: &mut CPU, mem: Mem, size: Size) {
+    let edi = cpu.regs.get32(Register::EDI);
     match size {
         Size::Dword => {
-            let src = mem.get_pod::<u32>(cpu.regs.get32(Register::EDI));
+            let src = load::<u32>(cpu, mem, edi);
             sub(cpu.regs.get32(Register::EAX), src, &mut cpu.flags);
         }
         Size::Word => {
-            let src = mem.get_pod::<u16>(cpu.regs.get32(Register::EDI));
+            let src = load::<u16>(cpu, mem, edi);
             sub(cpu.regs.get32(Register::EAX) as u16, src, &mut cpu.flags);
         }
         Size::Byte => {
-            let src = mem.get_pod::<u8>(cpu.regs.get32(Register::EDI));
+            let src = load::<u8>(cpu, mem, edi);
             sub(cpu.regs.get32(Register::EAX) as u8, src, &mut cpu.flags);
         }
     }
-    if cpu.flags.contains(Flags::DF) {
-        *cpu.regs.get32_mut(Register::EDI) -= size as u32;
-    } else {
-        *cpu.regs.get32_mut(Register::EDI) += size as u32;
-    };
+    if !cpu.state.is_running() {
+        return;
+    }
+    let delta = step(cpu, size);
+    advance(cpu, Register::EDI, delta);
 }
 
 fn scas(cpu: &mut CPU, mem: Mem, instr: &Instruction, size: Size) {
@@ -194,28 +280,63 @@ pub fn scasb(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 fn stos_single(cpu: &mut CPU, mem: Mem, size: Size) {
+    let edi = cpu.regs.get32(Register::EDI);
+    let eax = cpu.regs.get32(Register::EAX);
     match size {
-        Size::Byte => mem.put_pod::<u8>(
-            cpu.regs.get32(Register::EDI),
-            cpu.regs.get32(Register::EAX) as u8,
-        ),
-        Size::Word => mem.put_pod::<u16>(
-            cpu.regs.get32(Register::EDI),
-            cpu.regs.get32(Register::EAX) as u16,
-        ),
+        Size::Byte => store::<u8>(cpu, mem, edi, eax as u8),
+        Size::Word => store::<u16>(cpu, mem, edi, eax as u16),
+        Size::Dword => store::<u32>(cpu, mem, edi, eax),
+    }
+    if !cpu.state.is_running() {
+        return;
+    }
+    let delta = step(cpu, size);
+    advance(cpu, Register::EDI, delta);
+}
+
+/// rep stos as a single fill.  Returns false if the caller must fall back to the element loop.
+fn stos_block(cpu: &mut CPU, mem: Mem, size: Size) -> bool {
+    let count = cpu.regs.get32(Register::ECX);
+    let Some(range) = block(cpu, cpu.regs.get32(Register::EDI), count, size) else {
+        return false;
+    };
+    if range.end > mem.len()
+        || !cpu
+            .memmap
+            .check(range.start, range.len() as u32, Protect::WRITE)
+        || cpu
+            .memmap
+            .watched(range.start, range.len() as u32, Protect::WRITE)
+            .is_some()
+    {
+        return false;
+    }
+    let buf = mem.sub32_mut(range.start, range.len() as u32);
+    let eax = cpu.regs.get32(Register::EAX);
+    match size {
+        Size::Byte => buf.fill(eax as u8),
+        Size::Word => {
+            for chunk in buf.chunks_exact_mut(2) {
+                chunk.copy_from_slice(&(eax as u16).to_le_bytes());
+            }
+        }
         Size::Dword => {
-            mem.put_pod::<u32>(cpu.regs.get32(Register::EDI), cpu.regs.get32(Register::EAX))
+            for chunk in buf.chunks_exact_mut(4) {
+                chunk.copy_from_slice(&eax.to_le_bytes());
+            }
         }
     }
-    if cpu.flags.contains(Flags::DF) {
-        *cpu.regs.get32_mut(Register::EDI) -= size as u32;
-    } else {
-        *cpu.regs.get32_mut(Register::EDI) += size as u32;
-    };
+    let delta = step(cpu, size).wrapping_mul(count);
+    advance(cpu, Register::EDI, delta);
+    cpu.regs.set32(Register::ECX, 0);
+    true
 }
 
 fn stos(cpu: &mut CPU, mem: Mem, instr: &Instruction, size: Size) {
     if Rep::is_rep(instr) {
+        if stos_block(cpu, mem, size) {
+            return;
+        }
         rep(cpu, mem, Rep::REP, size, stos_single);
     } else {
         stos_single(cpu, mem, size);
@@ -235,31 +356,61 @@ pub fn stosb(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 }
 
 fn lods_single(cpu: &mut CPU, mem: Mem, size: Size) {
+    let esi = cpu.regs.get32(Register::ESI);
     match size {
         Size::Byte => {
-            let value = mem.get_pod::<u8>(cpu.regs.get32(Register::ESI));
+            let value = load::<u8>(cpu, mem, esi);
             cpu.regs.set8(iced_x86::Register::AL, value)
         }
         Size::Word => {
-            let value = mem.get_pod::<u16>(cpu.regs.get32(Register::ESI));
+            let value = load::<u16>(cpu, mem, esi);
             cpu.regs.set16(iced_x86::Register::AX, value)
         }
         Size::Dword => {
-            cpu.regs.set32(
-                Register::EAX,
-                mem.get_pod::<u32>(cpu.regs.get32(Register::ESI)),
-            );
+            let value = load::<u32>(cpu, mem, esi);
+            cpu.regs.set32(Register::EAX, value);
         }
     }
-    if cpu.flags.contains(Flags::DF) {
-        *cpu.regs.get32_mut(Register::ESI) -= size as u32;
-    } else {
-        *cpu.regs.get32_mut(Register::ESI) += size as u32;
+    if !cpu.state.is_running() {
+        return;
+    }
+    let delta = step(cpu, size);
+    advance(cpu, Register::ESI, delta);
+}
+
+/// rep lods as a single load of the last element, which is the only one observable.
+/// Returns false if the caller must fall back to the element loop.
+fn lods_block(cpu: &mut CPU, mem: Mem, size: Size) -> bool {
+    let count = cpu.regs.get32(Register::ECX);
+    if count == 0 {
+        return true;
+    }
+    let Some(range) = block(cpu, cpu.regs.get32(Register::ESI), count, size) else {
+        return false;
     };
+    // If any of it faults or is watched, let the element loop find exactly where.
+    if range.end > mem.len()
+        || !cpu
+            .memmap
+            .check(range.start, range.len() as u32, Protect::READ)
+        || cpu
+            .memmap
+            .watched(range.start, range.len() as u32, Protect::READ)
+            .is_some()
+    {
+        return false;
+    }
+    advance(cpu, Register::ESI, step(cpu, size).wrapping_mul(count - 1));
+    lods_single(cpu, mem, size);
+    cpu.regs.set32(Register::ECX, 0);
+    true
 }
 
 fn lods(cpu: &mut CPU, mem: Mem, instr: &Instruction, size: Size) {
     if Rep::is_rep(instr) {
+        if lods_block(cpu, mem, size) {
+            return;
+        }
         rep(cpu, mem, Rep::REP, size, lods_single);
     } else {
         lods_single(cpu, mem, size);
@@ -277,3 +428,140 @@ pub fn lodsw(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
 pub fn lodsb(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     lods(cpu, mem, instr, Size::Byte)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        testing::{Test, DATA, STACK},
+        CPUState, Fault, Flags, Protect, Watch, WatchAction,
+    };
+    use iced_x86::Register;
+    use memory::ExtensionsMut;
+
+    const REP_LODSD: [u8; 2] = [0xf3, 0xad];
+    const INC_EBX: u8 = 0x43;
+
+    fn rep_lodsd(esi: u32, ecx: u32) -> Test {
+        let mut test = Test::new(&[REP_LODSD[0], REP_LODSD[1], INC_EBX]);
+        for i in 0..(STACK - DATA) / 4 {
+            test.mem().put_pod::<u32>(DATA + i * 4, i);
+        }
+        let regs = &mut test.x86.cpu_mut().regs;
+        regs.set32(Register::ESI, esi);
+        regs.set32(Register::ECX, ecx);
+        test
+    }
+
+    #[test]
+    fn lods_loads_last() {
+        let mut test = rep_lodsd(DATA, 5);
+        test.run();
+        let regs = &test.x86.cpu().regs;
+        assert_eq!(regs.get32(Register::EAX), 4);
+        assert_eq!(regs.get32(Register::ESI), DATA + 20);
+        assert_eq!(regs.get32(Register::ECX), 0);
+    }
+
+    /// A fault partway stops at the faulting element, as the element loop would.
+    #[test]
+    fn lods_faults_at_element() {
+        let mut test = rep_lodsd(STACK - 8, 4);
+        test.run();
+        let cpu = test.x86.cpu();
+        assert_eq!(
+            cpu.state,
+            CPUState::Fault(Fault::AccessViolation {
+                addr: STACK,
+                write: false
+            })
+        );
+        assert_eq!(cpu.regs.get32(Register::EAX), (STACK - DATA) / 4 - 1);
+        assert_eq!(cpu.regs.get32(Register::ESI), STACK);
+        assert_eq!(cpu.regs.get32(Register::ECX), 2);
+    }
+
+    /// Reads of watched elements along the way are seen.
+    #[test]
+    fn lods_watched() {
+        let mut test = rep_lodsd(DATA, 5);
+        test.x86.add_watch(Watch {
+            start: DATA + 4,
+            end: DATA + 8,
+            access: Protect::READ,
+            action: WatchAction::Break,
+        });
+        test.run();
+        let cpu = test.x86.cpu();
+        assert_eq!(cpu.state, CPUState::DebugBreak);
+        // The break comes after the whole instruction.
+        assert_eq!(cpu.regs.get32(Register::ECX), 0);
+        assert_eq!(cpu.regs.get32(Register::EBX), 0);
+    }
+
+    /// Run a string op with the data page holding its offsets as bytes, returning the
+    /// data page and the final esi, edi and ecx.
+    fn string_op(code: &[u8], backward: bool, esi: u32, edi: u32, ecx: u32) -> (Vec<u8>, [u32; 3]) {
+        let mut test = Test::new(code);
+        for i in DATA..STACK {
+            test.mem().put_pod::<u8>(i, i as u8);
+        }
+        let cpu = test.x86.cpu_mut();
+        cpu.flags.set(Flags::DF, backward);
+        cpu.regs.set32(Register::EAX, 0x1234_5678);
+        cpu.regs.set32(Register::ESI, esi);
+        cpu.regs.set32(Register::EDI, edi);
+        cpu.regs.set32(Register::ECX, ecx);
+        test.run();
+        let regs = &test.x86.cpu().regs;
+        let regs = [Register::ESI, Register::EDI, Register::ECX].map(|r| regs.get32(r));
+        (
+            test.mem.as_slice()[DATA as usize..STACK as usize].to_vec(),
+            regs,
+        )
+    }
+
+    /// Check that `rep op` matches count plain `op`s, which run element by element.
+    fn check_rep(op: &[u8], backward: bool, esi: u32, edi: u32) {
+        const COUNT: u32 = 37;
+        let rep = [&[0xf3], op].concat();
+        let singles = op.repeat(COUNT as usize);
+        let (mem, regs) = string_op(&rep, backward, esi, edi, COUNT);
+        let (expected_mem, expected_regs) = string_op(&singles, backward, esi, edi, COUNT);
+        let case = format!("{op:x?} backward={backward} esi={esi:x} edi={edi:x}");
+        assert!(mem == expected_mem, "{case}: memory differs");
+        assert_eq!(regs[..2], expected_regs[..2], "{case}");
+        assert_eq!(regs[2], 0, "{case}");
+    }
+
+    const MOVS: [&[u8]; 3] = [&[0xa4], &[0x66, 0xa5], &[0xa5]];
+    const STOS: [&[u8]; 3] = [&[0xaa], &[0x66, 0xab], &[0xab]];
+
+    #[test]
+    fn rep_movs_matches_elements() {
+        let base = DATA + 0x200;
+        for op in MOVS {
+            for backward in [false, true] {
+                // Disjoint, then overlapping each way; copying towards the direction of
+                // travel replicates a pattern.
+                for (src, dst) in [(0, 0x100), (0, 3), (3, 0)] {
+                    check_rep(op, backward, base + src, base + dst);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rep_movs_replicates_pattern() {
+        let (mem, _) = string_op(&[0xf3, 0xa4], false, DATA, DATA + 3, 9);
+        assert_eq!(mem[..12], [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn rep_stos_matches_elements() {
+        for op in STOS {
+            for backward in [false, true] {
+                check_rep(op, backward, 0, DATA + 0x200 + 1);
+            }
+        }
+    }
+}
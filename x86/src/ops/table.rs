@@ -13,11 +13,13 @@ static mut OP_TAB: [Option<Op>; 2553] = [None; 2553];
 
 pub unsafe fn init_op_tab() {
     use super::basic::*;
+    use super::bits::*;
     use super::control::*;
     use super::cpuid::*;
     use super::fpu::*;
     use super::math::*;
     use super::mmx::*;
+    use super::sse::*;
     use super::string::*;
     use super::test::*;
 
@@ -319,11 +321,6 @@ pub unsafe fn init_op_tab() {
     OP_TAB[iced_x86::Code::Test_rm8_imm8 as usize] = Some(test_rm8_imm8);
     OP_TAB[iced_x86::Code::Test_AL_imm8 as usize] = Some(test_rm8_imm8);
 
-    OP_TAB[iced_x86::Code::Bt_rm32_r32 as usize] = Some(bt_rm32_r32);
-    OP_TAB[iced_x86::Code::Bt_rm32_imm8 as usize] = Some(bt_rm32_imm8);
-    OP_TAB[iced_x86::Code::Btr_rm32_imm8 as usize] = Some(btr_rm32_imm8);
-    OP_TAB[iced_x86::Code::Bsr_r32_rm32 as usize] = Some(bsr_r32_rm32);
-
     OP_TAB[iced_x86::Code::Cmove_r32_rm32 as usize] = Some(cmove_r32_rm32);
 
     OP_TAB[iced_x86::Code::Seta_rm8 as usize] = Some(seta_rm8);
@@ -345,8 +342,12 @@ pub unsafe fn init_op_tab() {
     OP_TAB[iced_x86::Code::Fldz as usize] = Some(fldz);
     OP_TAB[iced_x86::Code::Fldpi as usize] = Some(fldpi);
     OP_TAB[iced_x86::Code::Fldl2e as usize] = Some(fldl2e);
+    OP_TAB[iced_x86::Code::Fldl2t as usize] = Some(fldl2t);
+    OP_TAB[iced_x86::Code::Fldlg2 as usize] = Some(fldlg2);
+    OP_TAB[iced_x86::Code::Fldln2 as usize] = Some(fldln2);
 
     OP_TAB[iced_x86::Code::Fld_sti as usize] = Some(fld_sti);
+    OP_TAB[iced_x86::Code::Fld_m80fp as usize] = Some(fld_m80fp);
     OP_TAB[iced_x86::Code::Fld_m64fp as usize] = Some(fld_m64fp);
     OP_TAB[iced_x86::Code::Fld_m32fp as usize] = Some(fld_m32fp);
     OP_TAB[iced_x86::Code::Fild_m64int as usize] = Some(fild_m64int);
@@ -354,15 +355,16 @@ pub unsafe fn init_op_tab() {
     OP_TAB[iced_x86::Code::Fild_m16int as usize] = Some(fild_m16int);
     OP_TAB[iced_x86::Code::Fst_m64fp as usize] = Some(fst_m64fp);
     OP_TAB[iced_x86::Code::Fst_m32fp as usize] = Some(fst_m32fp);
+    OP_TAB[iced_x86::Code::Fstp_m80fp as usize] = Some(fstp_m80fp);
     OP_TAB[iced_x86::Code::Fstp_m64fp as usize] = Some(fstp_m64fp);
     OP_TAB[iced_x86::Code::Fstp_m32fp as usize] = Some(fstp_m32fp);
+    OP_TAB[iced_x86::Code::Fst_sti as usize] = Some(fst_sti);
     OP_TAB[iced_x86::Code::Fstp_sti as usize] = Some(fstp_sti);
     OP_TAB[iced_x86::Code::Fistp_m64int as usize] = Some(fistp_m64int);
     OP_TAB[iced_x86::Code::Fistp_m32int as usize] = Some(fistp_m32int);
     OP_TAB[iced_x86::Code::Fistp_m16int as usize] = Some(fistp_m16int);
-    // OP_TAB[iced_x86::Code::Fisp_m64int as usize] = Some(fist_m64int);
     OP_TAB[iced_x86::Code::Fist_m32int as usize] = Some(fist_m32int);
-    // OP_TAB[iced_x86::Code::Fist_m16int as usize] = Some(fist_m16int);
+    OP_TAB[iced_x86::Code::Fist_m16int as usize] = Some(fist_m16int);
 
     OP_TAB[iced_x86::Code::Fchs as usize] = Some(fchs);
     OP_TAB[iced_x86::Code::Fabs as usize] = Some(fabs);
@@ -371,6 +373,8 @@ pub unsafe fn init_op_tab() {
     OP_TAB[iced_x86::Code::Fsincos as usize] = Some(fsincos);
     OP_TAB[iced_x86::Code::Fpatan as usize] = Some(fpatan);
     OP_TAB[iced_x86::Code::Fsqrt as usize] = Some(fsqrt);
+    OP_TAB[iced_x86::Code::Fptan as usize] = Some(fptan);
+    OP_TAB[iced_x86::Code::Fyl2x as usize] = Some(fyl2x);
 
     OP_TAB[iced_x86::Code::Fadd_st0_sti as usize] = Some(fadd_sti_sti);
     OP_TAB[iced_x86::Code::Fadd_sti_st0 as usize] = Some(fadd_sti_sti);
@@ -383,8 +387,12 @@ pub unsafe fn init_op_tab() {
     OP_TAB[iced_x86::Code::Fsub_m64fp as usize] = Some(fsub_m64fp);
     OP_TAB[iced_x86::Code::Fsub_m32fp as usize] = Some(fsub_m32fp);
     OP_TAB[iced_x86::Code::Fsub_st0_sti as usize] = Some(fsub_sti_sti);
+    OP_TAB[iced_x86::Code::Fsub_sti_st0 as usize] = Some(fsub_sti_sti);
     OP_TAB[iced_x86::Code::Fsubp_sti_st0 as usize] = Some(fsubp_sti_sti);
     OP_TAB[iced_x86::Code::Fisub_m32int as usize] = Some(fisub_m32int);
+    OP_TAB[iced_x86::Code::Fisub_m16int as usize] = Some(fisub_m16int);
+    OP_TAB[iced_x86::Code::Fisubr_m32int as usize] = Some(fisubr_m32int);
+    OP_TAB[iced_x86::Code::Fisubr_m16int as usize] = Some(fisubr_m16int);
 
     OP_TAB[iced_x86::Code::Fsubr_m64fp as usize] = Some(fsubr_m64fp);
     OP_TAB[iced_x86::Code::Fsubr_m32fp as usize] = Some(fsubr_m32fp);
@@ -414,28 +422,46 @@ pub unsafe fn init_op_tab() {
     OP_TAB[iced_x86::Code::Fdivr_m64fp as usize] = Some(fdivr_m64fp);
     OP_TAB[iced_x86::Code::Fdivr_m32fp as usize] = Some(fdivr_m32fp);
     OP_TAB[iced_x86::Code::Fdivr_st0_sti as usize] = Some(fdivr_sti_sti);
+    OP_TAB[iced_x86::Code::Fdivr_sti_st0 as usize] = Some(fdivr_sti_sti);
     OP_TAB[iced_x86::Code::Fdivrp_sti_st0 as usize] = Some(fdivrp_sti_sti);
     OP_TAB[iced_x86::Code::Fidivr_m32int as usize] = Some(fidivr_m32int);
+    OP_TAB[iced_x86::Code::Fidivr_m16int as usize] = Some(fidivr_m16int);
 
     OP_TAB[iced_x86::Code::Fprem as usize] = Some(fprem);
 
     OP_TAB[iced_x86::Code::Fxch_st0_sti as usize] = Some(fxch_st0_sti);
-    OP_TAB[iced_x86::Code::Fcom_m64fp as usize] = Some(fcom_m32fp);
+    OP_TAB[iced_x86::Code::Fcom_m64fp as usize] = Some(fcom_m64fp);
     OP_TAB[iced_x86::Code::Fcom_m32fp as usize] = Some(fcom_m32fp);
     OP_TAB[iced_x86::Code::Fcomp_m32fp as usize] = Some(fcomp_m32fp);
     OP_TAB[iced_x86::Code::Fcomp_m64fp as usize] = Some(fcomp_m64fp);
+    OP_TAB[iced_x86::Code::Fcom_st0_sti as usize] = Some(fcom_st0_sti);
     OP_TAB[iced_x86::Code::Fcomp_st0_sti as usize] = Some(fcomp_st0_sti);
+    OP_TAB[iced_x86::Code::Fcompp as usize] = Some(fcompp);
+    OP_TAB[iced_x86::Code::Ficom_m32int as usize] = Some(ficom_m32int);
+    OP_TAB[iced_x86::Code::Ficomp_m32int as usize] = Some(ficomp_m32int);
+    OP_TAB[iced_x86::Code::Fucom_st0_sti as usize] = Some(fucom_st0_sti);
     OP_TAB[iced_x86::Code::Fucomp_st0_sti as usize] = Some(fucomp_st0_sti);
+    OP_TAB[iced_x86::Code::Fucompp as usize] = Some(fucompp);
+    OP_TAB[iced_x86::Code::Ftst as usize] = Some(ftst);
+    OP_TAB[iced_x86::Code::Fxam as usize] = Some(fxam);
     OP_TAB[iced_x86::Code::Fcomi_st0_sti as usize] = Some(fcomi_st0_sti);
+    OP_TAB[iced_x86::Code::Fcomip_st0_sti as usize] = Some(fcomip_st0_sti);
     OP_TAB[iced_x86::Code::Fucomi_st0_sti as usize] = Some(fucomi_st0_sti);
     OP_TAB[iced_x86::Code::Fucomip_st0_sti as usize] = Some(fucomip_st0_sti);
 
     OP_TAB[iced_x86::Code::Frndint as usize] = Some(frndint);
+    OP_TAB[iced_x86::Code::Fstsw_AX as usize] = Some(fnstsw_ax);
     OP_TAB[iced_x86::Code::Fnstsw_AX as usize] = Some(fnstsw_ax);
+    OP_TAB[iced_x86::Code::Fstsw_m2byte as usize] = Some(fnstsw_m2byte);
+    OP_TAB[iced_x86::Code::Fnstsw_m2byte as usize] = Some(fnstsw_m2byte);
+    OP_TAB[iced_x86::Code::Fstcw_m2byte as usize] = Some(fnstcw_m2byte);
     OP_TAB[iced_x86::Code::Fnstcw_m2byte as usize] = Some(fnstcw_m2byte);
     OP_TAB[iced_x86::Code::Fldcw_m2byte as usize] = Some(fldcw_m2byte);
-    OP_TAB[iced_x86::Code::Fclex as usize] = Some(nop);
-    OP_TAB[iced_x86::Code::Fnclex as usize] = Some(nop);
+    OP_TAB[iced_x86::Code::Fclex as usize] = Some(fclex);
+    OP_TAB[iced_x86::Code::Fnclex as usize] = Some(fclex);
+    OP_TAB[iced_x86::Code::Fincstp as usize] = Some(fincstp);
+    OP_TAB[iced_x86::Code::Fdecstp as usize] = Some(fdecstp);
+    OP_TAB[iced_x86::Code::Ffree_sti as usize] = Some(ffree_sti);
 
     OP_TAB[iced_x86::Code::Fcmovnbe_st0_sti as usize] = Some(fcmovnbe_st0_sti);
 
@@ -484,6 +510,27 @@ pub unsafe fn init_op_tab() {
     OP_TAB[iced_x86::Code::Psubw_mm_mmm64 as usize] = Some(psubw_mm_mmm64);
     OP_TAB[iced_x86::Code::Pcmpeqb_mm_mmm64 as usize] = Some(pcmpeqb_mm_mmm64);
 
+    OP_TAB[iced_x86::Code::Movaps_xmm_xmmm128 as usize] = Some(movaps_xmm_xmmm128);
+    OP_TAB[iced_x86::Code::Movaps_xmmm128_xmm as usize] = Some(movaps_xmmm128_xmm);
+    OP_TAB[iced_x86::Code::Movups_xmm_xmmm128 as usize] = Some(movups_xmm_xmmm128);
+    OP_TAB[iced_x86::Code::Movups_xmmm128_xmm as usize] = Some(movups_xmmm128_xmm);
+    OP_TAB[iced_x86::Code::Movss_xmm_xmmm32 as usize] = Some(movss_xmm_xmmm32);
+    OP_TAB[iced_x86::Code::Movss_xmmm32_xmm as usize] = Some(movss_xmmm32_xmm);
+    OP_TAB[iced_x86::Code::Movsd_xmm_xmmm64 as usize] = Some(movsd_xmm_xmmm64);
+    OP_TAB[iced_x86::Code::Movsd_xmmm64_xmm as usize] = Some(movsd_xmmm64_xmm);
+    OP_TAB[iced_x86::Code::Addps_xmm_xmmm128 as usize] = Some(addps_xmm_xmmm128);
+    OP_TAB[iced_x86::Code::Subps_xmm_xmmm128 as usize] = Some(subps_xmm_xmmm128);
+    OP_TAB[iced_x86::Code::Mulps_xmm_xmmm128 as usize] = Some(mulps_xmm_xmmm128);
+    OP_TAB[iced_x86::Code::Divps_xmm_xmmm128 as usize] = Some(divps_xmm_xmmm128);
+    OP_TAB[iced_x86::Code::Addss_xmm_xmmm32 as usize] = Some(addss_xmm_xmmm32);
+    OP_TAB[iced_x86::Code::Subss_xmm_xmmm32 as usize] = Some(subss_xmm_xmmm32);
+    OP_TAB[iced_x86::Code::Mulss_xmm_xmmm32 as usize] = Some(mulss_xmm_xmmm32);
+    OP_TAB[iced_x86::Code::Divss_xmm_xmmm32 as usize] = Some(divss_xmm_xmmm32);
+    OP_TAB[iced_x86::Code::Addsd_xmm_xmmm64 as usize] = Some(addsd_xmm_xmmm64);
+    OP_TAB[iced_x86::Code::Subsd_xmm_xmmm64 as usize] = Some(subsd_xmm_xmmm64);
+    OP_TAB[iced_x86::Code::Mulsd_xmm_xmmm64 as usize] = Some(mulsd_xmm_xmmm64);
+    OP_TAB[iced_x86::Code::Divsd_xmm_xmmm64 as usize] = Some(divsd_xmm_xmmm64);
+
     OP_TAB[iced_x86::Code::Nopd as usize] = Some(nop);
     OP_TAB[iced_x86::Code::Nopw as usize] = Some(nop);
     OP_TAB[iced_x86::Code::Nop_rm16 as usize] = Some(nop);
@@ -494,10 +541,30 @@ pub unsafe fn init_op_tab() {
 
     OP_TAB[iced_x86::Code::Bswap_r32 as usize] = Some(bswap_r32);
     OP_TAB[iced_x86::Code::Xlat_m8 as usize] = Some(xlat_m8);
+    OP_TAB[iced_x86::Code::Bt_rm32_r32 as usize] = Some(bt_rm32_r32);
+    OP_TAB[iced_x86::Code::Bt_rm32_imm8 as usize] = Some(bt_rm32_imm8);
+    OP_TAB[iced_x86::Code::Bt_rm16_r16 as usize] = Some(bt_rm16_r16);
+    OP_TAB[iced_x86::Code::Bt_rm16_imm8 as usize] = Some(bt_rm16_imm8);
     OP_TAB[iced_x86::Code::Bts_rm32_r32 as usize] = Some(bts_rm32_r32);
+    OP_TAB[iced_x86::Code::Bts_rm32_imm8 as usize] = Some(bts_rm32_imm8);
+    OP_TAB[iced_x86::Code::Bts_rm16_r16 as usize] = Some(bts_rm16_r16);
+    OP_TAB[iced_x86::Code::Bts_rm16_imm8 as usize] = Some(bts_rm16_imm8);
+    OP_TAB[iced_x86::Code::Btr_rm32_r32 as usize] = Some(btr_rm32_r32);
+    OP_TAB[iced_x86::Code::Btr_rm32_imm8 as usize] = Some(btr_rm32_imm8);
+    OP_TAB[iced_x86::Code::Btr_rm16_r16 as usize] = Some(btr_rm16_r16);
+    OP_TAB[iced_x86::Code::Btr_rm16_imm8 as usize] = Some(btr_rm16_imm8);
+    OP_TAB[iced_x86::Code::Btc_rm32_r32 as usize] = Some(btc_rm32_r32);
+    OP_TAB[iced_x86::Code::Btc_rm32_imm8 as usize] = Some(btc_rm32_imm8);
+    OP_TAB[iced_x86::Code::Btc_rm16_r16 as usize] = Some(btc_rm16_r16);
+    OP_TAB[iced_x86::Code::Btc_rm16_imm8 as usize] = Some(btc_rm16_imm8);
+    OP_TAB[iced_x86::Code::Bsf_r32_rm32 as usize] = Some(bsf_r32_rm32);
+    OP_TAB[iced_x86::Code::Bsf_r16_rm16 as usize] = Some(bsf_r16_rm16);
+    OP_TAB[iced_x86::Code::Bsr_r32_rm32 as usize] = Some(bsr_r32_rm32);
+    OP_TAB[iced_x86::Code::Bsr_r16_rm16 as usize] = Some(bsr_r16_rm16);
     OP_TAB[iced_x86::Code::Tzcnt_r32_rm32 as usize] = Some(tzcnt_r32_rm32);
 
     OP_TAB[iced_x86::Code::Cpuid as usize] = Some(cpuid);
+    OP_TAB[iced_x86::Code::Rdtsc as usize] = Some(rdtsc);
 
     // Code to print the necessary size of the table:
     // let last = OP_TAB.iter().rposition(|op| op.is_some());
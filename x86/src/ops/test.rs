@@ -1,7 +1,7 @@
 use super::math::{and, sub};
 use crate::{registers::Flags, x86::CPU};
 use iced_x86::Instruction;
-use memory::{Extensions, Mem};
+use memory::Mem;
 
 use super::helpers::*;
 
@@ -63,7 +63,10 @@ pub fn cmp_r8_rm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     let x = cpu.regs.get8(instr.op0_register());
     let y = match instr.op1_kind() {
         iced_x86::OpKind::Register => cpu.regs.get8(instr.op1_register()),
-        iced_x86::OpKind::Memory => mem.get_pod::<u8>(x86_addr(cpu, instr)),
+        iced_x86::OpKind::Memory => {
+            let addr = x86_addr(cpu, instr);
+            load::<u8>(cpu, mem, addr)
+        }
         _ => unreachable!(),
     };
     sub(x, y, &mut cpu.flags);
@@ -105,36 +108,6 @@ pub fn test_rm8_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     and(x, y, &mut cpu.flags);
 }
 
-pub fn bt_rm32_r32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let x = rm32(cpu, mem, instr).get();
-    let y = op1_rm32(cpu, mem, instr) % 32;
-    cpu.flags.set(Flags::CF, ((x >> y) & 1) != 0);
-}
-
-pub fn bt_rm32_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let x = rm32(cpu, mem, instr).get();
-    let y = instr.immediate8() % 32;
-    cpu.flags.set(Flags::CF, ((x >> y) & 1) != 0);
-}
-
-pub fn btr_rm32_imm8(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = instr.immediate8() % 32;
-    let x = rm32(cpu, mem, instr);
-    cpu.flags.set(Flags::CF, ((x.get() >> y) & 1) != 0);
-    x.set(x.get() & !(1 << y))
-}
-
-pub fn bsr_r32_rm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
-    let y = op1_rm32(cpu, mem, instr);
-    let x = rm32(cpu, mem, instr);
-    cpu.flags.set(Flags::ZF, y == 0);
-    for i in 31..0 {
-        if y & (1 << i) != 0 {
-            x.set(i)
-        }
-    }
-}
-
 pub fn cmove_r32_rm32(cpu: &mut CPU, mem: Mem, instr: &Instruction) {
     let y = op1_rm32(cpu, mem, instr);
     let x = rm32(cpu, mem, instr);
@@ -2,9 +2,14 @@ use bitflags::bitflags;
 use iced_x86::Register::{self, *};
 
 bitflags! {
+    #[derive(serde::Serialize, serde::Deserialize)]
     pub struct Flags: u32 {
         /// carry
         const CF = 1 << 0;
+        /// parity of the low byte of the result
+        const PF = 1 << 2;
+        /// auxiliary carry, out of bit 3, for BCD
+        const AF = 1 << 4;
         /// zero
         const ZF = 1 << 6;
         /// sign
@@ -18,7 +23,7 @@ bitflags! {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Registers {
     /// 32-bit registers, in order:
     ///   eax ecx edx ebx esp ebp esi edi,
@@ -46,6 +51,9 @@ pub struct Registers {
     /// MMX registers.
     // TODO: officially these should alias the FPU registers(!).
     mm: [u64; 8],
+
+    /// SSE registers.
+    xmm: [u128; 8],
 }
 
 #[allow(dead_code)]
@@ -83,6 +91,14 @@ const fn assert_enums_as_expected() {
     assert!(MM5 as u8 == MM0 as u8 + 5);
     assert!(MM6 as u8 == MM0 as u8 + 6);
     assert!(MM7 as u8 == MM0 as u8 + 7);
+
+    assert!(XMM1 as u8 == XMM0 as u8 + 1);
+    assert!(XMM2 as u8 == XMM0 as u8 + 2);
+    assert!(XMM3 as u8 == XMM0 as u8 + 3);
+    assert!(XMM4 as u8 == XMM0 as u8 + 4);
+    assert!(XMM5 as u8 == XMM0 as u8 + 5);
+    assert!(XMM6 as u8 == XMM0 as u8 + 6);
+    assert!(XMM7 as u8 == XMM0 as u8 + 7);
 }
 const _: () = assert_enums_as_expected();
 
@@ -216,4 +232,20 @@ impl Registers {
         }
         self.mm[index] = value;
     }
+
+    pub fn get128(&self, reg: Register) -> u128 {
+        let index = reg as usize - XMM0 as usize;
+        if index >= 8 {
+            unreachable!("{reg:?}");
+        }
+        self.xmm[index]
+    }
+
+    pub fn set128(&mut self, reg: Register, value: u128) {
+        let index = reg as usize - XMM0 as usize;
+        if index >= 8 {
+            unreachable!("{reg:?}");
+        }
+        self.xmm[index] = value;
+    }
 }
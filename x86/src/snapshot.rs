@@ -0,0 +1,126 @@
+//! Saving and restoring CPU state, for save states and for rewinding execution.
+
+use crate::{
+    fpu::FPU,
+    icache::InstrCache,
+    ops::TimeStampCounter,
+    registers::{Flags, Registers},
+    x86::{CPUState, X86},
+};
+
+/// The state of one CPU.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct CPUSnapshot {
+    regs: Registers,
+    flags: Flags,
+    fpu: FPU,
+    tsc: TimeStampCounter,
+    state: CPUState,
+    /// Async calls in flight can't be serialized, so only their count is recorded,
+    /// to check that a restore is resuming the same call stack.
+    futures: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct X86Snapshot {
+    cpus: Vec<CPUSnapshot>,
+    cur_cpu: usize,
+    instr_count: usize,
+}
+
+impl X86 {
+    pub fn snapshot(&self) -> X86Snapshot {
+        X86Snapshot {
+            cpus: self
+                .cpus
+                .iter()
+                .map(|cpu| CPUSnapshot {
+                    regs: cpu.regs.clone(),
+                    flags: cpu.flags,
+                    fpu: cpu.fpu.clone(),
+                    tsc: cpu.tsc.clone(),
+                    state: cpu.state.clone(),
+                    futures: cpu.futures.len(),
+                })
+                .collect(),
+            cur_cpu: self.cur_cpu,
+            instr_count: self.instr_count,
+        }
+    }
+
+    /// Restore a snapshot taken earlier from this X86.  The CPUs' async calls are still the
+    /// live ones, so this fails if the set of CPUs or their calls in flight changed since.
+    pub fn restore(&mut self, snap: &X86Snapshot) -> Result<(), String> {
+        if snap.cpus.len() != self.cpus.len() {
+            return Err(format!(
+                "snapshot has {} cpus, have {}",
+                snap.cpus.len(),
+                self.cpus.len()
+            ));
+        }
+        for (i, (cpu, saved)) in self.cpus.iter().zip(&snap.cpus).enumerate() {
+            if cpu.futures.len() != saved.futures {
+                return Err(format!(
+                    "cpu {i}: snapshot has {} async calls in flight, have {}",
+                    saved.futures,
+                    cpu.futures.len()
+                ));
+            }
+        }
+
+        for (cpu, saved) in self.cpus.iter_mut().zip(&snap.cpus) {
+            cpu.regs = saved.regs.clone();
+            cpu.flags = saved.flags;
+            cpu.fpu = saved.fpu.clone();
+            cpu.tsc = saved.tsc.clone();
+            cpu.state = saved.state.clone();
+            cpu.watch_hits.clear();
+        }
+        self.cur_cpu = snap.cur_cpu;
+        self.instr_count = snap.instr_count;
+        // Memory is restored separately and may hold different code than what we decoded.
+        self.icache = InstrCache::default();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Register;
+    use memory::Mem;
+
+    #[test]
+    fn round_trip() {
+        // 1000: inc eax
+        // 1001: add ebx,eax
+        // 1003: jmp 1000
+        let mut buf = vec![0u8; 0x2000];
+        buf[0x1000..0x1005].copy_from_slice(&[0x40, 0x01, 0xc3, 0xeb, 0xfb]);
+        let mem = Mem::from_slice(&buf);
+
+        let mut x86 = X86::new();
+        x86.cpu_mut().regs.eip = 0x1000;
+        for _ in 0..3 {
+            x86.execute_block(mem);
+        }
+        let snap = x86.snapshot();
+
+        let run = |x86: &mut X86| {
+            for _ in 0..5 {
+                x86.execute_block(mem);
+            }
+            let regs = &x86.cpu().regs;
+            (
+                regs.get32(Register::EAX),
+                regs.get32(Register::EBX),
+                regs.eip,
+            )
+        };
+        let first = run(&mut x86);
+        assert_eq!(first, (8, 36, 0x1000));
+        x86.restore(&snap).unwrap();
+        assert_eq!(x86.cpu().regs.get32(Register::EAX), 3);
+        assert_eq!(run(&mut x86), first);
+    }
+}
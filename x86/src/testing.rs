@@ -0,0 +1,57 @@
+//! Helpers for tests that run instructions on a CPU.
+
+use crate::{memmap::Protect, MemoryMap, Region, X86};
+use memory::Mem;
+
+/// Where test code is loaded.  Everything below it is unmapped.
+pub const CODE: u32 = 0x1000;
+/// A page of read/write memory.
+pub const DATA: u32 = 0x2000;
+/// Initial esp; the stack is the read/write page below it.
+pub const STACK: u32 = 0x4000;
+
+/// A CPU with a small address space: a page of read-only code, then a page of data and
+/// one of stack.
+pub struct Test {
+    pub x86: X86,
+    pub mem: Vec<u8>,
+}
+
+impl Test {
+    /// Load code at CODE.  Running stops at the end of it, so it needn't end itself.
+    pub fn new(code: &[u8]) -> Self {
+        let mut mem = vec![0u8; STACK as usize];
+        let code_end = CODE as usize + code.len();
+        mem[CODE as usize..code_end].copy_from_slice(code);
+        mem[code_end] = 0xcc; // int3
+
+        let mut x86 = X86::new();
+        x86.set_memory_map(MemoryMap::new(vec![
+            Region {
+                start: CODE,
+                end: DATA,
+                protect: Protect::READ | Protect::EXECUTE,
+            },
+            Region {
+                start: DATA,
+                end: STACK,
+                protect: Protect::READ | Protect::WRITE,
+            },
+        ]));
+        let cpu = x86.cpu_mut();
+        cpu.regs.eip = CODE;
+        cpu.regs.set32(iced_x86::Register::ESP, STACK);
+        Test { x86, mem }
+    }
+
+    pub fn mem(&self) -> Mem<'_> {
+        Mem::from_slice(&self.mem)
+    }
+
+    /// Run until the code ends, or an instruction faults.
+    pub fn run(&mut self) {
+        while self.x86.cpu().state.is_running() {
+            self.x86.execute_block(Mem::from_slice(&self.mem));
+        }
+    }
+}
@@ -3,22 +3,44 @@
 use crate::{
     fpu::FPU,
     icache::InstrCache,
-    ops,
+    itrace::{InstrTrace, Snapshot},
+    memmap::{MemoryMap, Watch, WatchAction, WatchHit},
+    ops::{self, CPUIDLeaves, TimeStampCounter},
     registers::{Flags, Registers},
     Register,
 };
-use memory::Mem;
+use memory::{Extensions, Mem};
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
-#[derive(Debug, Default, PartialEq, Eq)]
+/// A fault raised by an instruction, for the OS layer to turn into an exception.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Fault {
+    /// #DE: division by zero, or a quotient too large for the destination.
+    DivideError,
+    /// An access to memory we don't have.
+    AccessViolation { addr: u32, write: bool },
+    /// #GP, e.g. a misaligned operand to an aligned SSE move.
+    GeneralProtection,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum CPUState {
     #[default]
     Running,
     Blocked(Option<u32>),
+    /// Blocked with no timeout until another thread wakes it, e.g. by signaling an event.
+    /// Unlike Blocked, the host can't end this, so if nothing else can run it's a deadlock.
+    Waiting,
+    /// The thread was suspended and isn't scheduled until resumed.
+    Suspended,
+    /// The thread exited and is never scheduled again.
+    Exited,
     DebugBreak,
     SysCall,
+    /// An instruction faulted; eip points at it.
+    Fault(Fault),
     Error(String),
 }
 
@@ -29,7 +51,7 @@ impl CPUState {
 }
 
 /// When eip==MAGIC_ADDR, the CPU executes futures (async tasks) rather than x86 code.
-const MAGIC_ADDR: u32 = 0xFFFF_FFF0;
+pub(crate) const MAGIC_ADDR: u32 = 0xFFFF_FFF0;
 
 // Similar to futures::future::BoxFuture, but 'static + !Send.
 pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
@@ -42,11 +64,25 @@ pub struct CPU {
     pub flags: Flags,
     pub fpu: FPU,
 
+    /// What the cpuid instruction reports.
+    pub cpuid: CPUIDLeaves,
+    pub tsc: TimeStampCounter,
+
+    /// Which memory accesses are allowed; see X86::set_memory_map.
+    pub memmap: MemoryMap,
+
     pub state: CPUState,
 
+    /// Watchpoint hits made by the current instruction, reported by execute_block.
+    pub(crate) watch_hits: Vec<WatchHit>,
+
+    /// Registers as of the current instruction's first fault, which execute_block restores
+    /// so that nothing the instruction went on to compute is written back.
+    fault_regs: Option<Box<(Registers, Flags, FPU)>>,
+
     /// If eip==MAGIC_ADDR, then the next step is to poll a future rather than
     /// executing a basic block.
-    futures: Vec<BoxFuture<()>>,
+    pub(crate) futures: Vec<BoxFuture<()>>,
 }
 
 impl CPU {
@@ -58,15 +94,80 @@ impl CPU {
             regs: Registers::default(),
             flags: Flags::empty(),
             fpu: FPU::default(),
+            cpuid: CPUIDLeaves::default(),
+            tsc: TimeStampCounter::default(),
+            memmap: MemoryMap::default(),
             state: Default::default(),
+            watch_hits: Vec::new(),
+            fault_regs: None,
             futures: Default::default(),
         }
     }
 
+    /// Return addresses found by following the ebp chain, for code that keeps frame pointers.
+    pub fn backtrace(&self, mem: Mem) -> Vec<u32> {
+        let mut frames = Vec::new();
+        let mut ebp = self.regs.get32(Register::EBP);
+        while frames.len() < 16 && ebp != 0 && !mem.is_oob::<[u32; 2]>(ebp) {
+            frames.push(mem.get_pod::<u32>(ebp + 4));
+            let next = mem.get_pod::<u32>(ebp);
+            // The stack grows down, so caller frames are always higher.
+            if next <= ebp {
+                break;
+            }
+            ebp = next;
+        }
+        frames
+    }
+
+    /// Log the watchpoint hits made by the instruction at eip, and break if any asked to.
+    fn report_watch_hits(&mut self, mem: Mem, eip: u32) {
+        for hit in std::mem::take(&mut self.watch_hits) {
+            if hit.write {
+                log::warn!(
+                    "watch: {eip:08x} wrote {}@{:08x}: {:x} -> {:x}",
+                    hit.len,
+                    hit.addr,
+                    hit.old,
+                    hit.new
+                );
+            } else {
+                log::warn!(
+                    "watch: {eip:08x} read {}@{:08x}: {:x}",
+                    hit.len,
+                    hit.addr,
+                    hit.old
+                );
+            }
+            let frames = self.backtrace(mem);
+            if !frames.is_empty() {
+                let frames = frames
+                    .iter()
+                    .map(|addr| format!("{addr:08x}"))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                log::warn!("  backtrace: {frames}");
+            }
+            if hit.action == WatchAction::Break && self.state.is_running() {
+                self.state = CPUState::DebugBreak;
+            }
+        }
+    }
+
     pub fn err(&mut self, msg: String) {
         self.state = CPUState::Error(msg);
     }
 
+    /// Raise a fault from the running instruction.  Only the first fault counts; the
+    /// instruction carries on with dummy values, but its results are discarded.
+    pub fn fault(&mut self, fault: Fault) {
+        if let CPUState::Fault(_) = self.state {
+            return;
+        }
+        self.fault_regs = Some(Box::new((self.regs.clone(), self.flags, self.fpu.clone())));
+        self.state = CPUState::Fault(fault);
+    }
+
     // /// Check whether reading a T from mem[addr] would cause OOB, and crash() if so.
     // fn check_oob<T>(&mut self, addr: u32) -> bool {
     //     if addr < NULL_POINTER_REGION_SIZE {
@@ -99,15 +200,27 @@ impl CPU {
         self.regs.set32(Register::ECX, 0);
         self.regs.set32(Register::EDX, 0);
 
-        X86Future { cpu: self, esp }
+        X86Future {
+            cpu: self,
+            esp,
+            pop: 0,
+        }
+    }
+
+    /// Like call_x86, but for cdecl functions, where the caller pops the arguments.
+    pub fn call_x86_cdecl(&mut self, mem: Mem, func: u32, args: Vec<u32>) -> X86Future {
+        let pop = args.len() as u32 * 4;
+        let mut future = self.call_x86(mem, func, args);
+        future.esp -= pop;
+        future.pop = pop;
+        future
     }
 
     /// Set up the CPU such that we are making an x86->async call, enqueuing a Future
     /// that is polled the next time the CPU executes.
     pub fn call_async(&mut self, future: BoxFuture<u32>, return_address: u32) {
-        self.regs.eip = MAGIC_ADDR;
         let cpu = self as *mut CPU;
-        self.futures.push(Box::pin(async move {
+        self.run_async(Box::pin(async move {
             let cpu = unsafe { &mut *cpu };
             let ret = future.await;
             cpu.regs.set32(Register::EAX, ret);
@@ -115,13 +228,32 @@ impl CPU {
         }));
     }
 
+    /// Enqueue a Future that runs in place of x86 code until it completes.
+    /// The future is responsible for pointing eip somewhere useful when it's done.
+    pub fn run_async(&mut self, future: BoxFuture<()>) {
+        self.regs.eip = MAGIC_ADDR;
+        self.futures.push(future);
+    }
+
+    /// Number of pending futures, including the currently running one.
+    pub fn async_depth(&self) -> usize {
+        self.futures.len()
+    }
+
+    /// Drop the pending futures from index depth up to, but not including, the currently
+    /// running one.  Used when x86 code abandons the calls those futures were awaiting,
+    /// e.g. when an exception handler unwinds the stack rather than returning.
+    pub fn abandon_async(&mut self, depth: usize) {
+        let top = self.futures.len() - 1;
+        if depth < top {
+            self.futures.drain(depth..top);
+        }
+    }
+
     fn async_executor(&mut self) {
         let future = self.futures.last_mut().unwrap();
-        // TODO: we don't use the waker at all.  Rust doesn't like us passing a random null pointer
-        // here but it seems like nothing accesses it(?).
-        //let c = unsafe { std::task::Context::from_waker(&Waker::from_raw(std::task::RawWaker::)) };
-        #[allow(deref_nullptr)]
-        let context: &mut std::task::Context = unsafe { &mut *std::ptr::null_mut() };
+        // We don't use the waker at all; futures are polled again on each scheduling pass.
+        let context = &mut std::task::Context::from_waker(std::task::Waker::noop());
         let poll = future.as_mut().poll(context);
         match poll {
             Poll::Ready(()) => {
@@ -135,13 +267,23 @@ impl CPU {
         self.state = CPUState::Blocked(wait);
         BlockFuture { cpu: self }
     }
+
+    /// Like block(), but with no timeout: only another thread setting the state back to
+    /// Running ends it.
+    pub fn wait(&mut self) -> BlockFuture {
+        self.state = CPUState::Waiting;
+        BlockFuture { cpu: self }
+    }
 }
 
 pub struct X86Future {
     // We assume the CPU is around for the duration of the future execution.
     // https://github.com/rust-lang/futures-rs/issues/316
     cpu: *mut CPU,
+    /// Value of esp once the call returns.
     esp: u32,
+    /// Bytes of arguments to pop on return, for cdecl calls.
+    pop: u32,
 }
 impl Future for X86Future {
     type Output = u32;
@@ -150,6 +292,7 @@ impl Future for X86Future {
         let cpu = self.cpu;
         let cpu = unsafe { &mut *cpu };
         if cpu.regs.get32(Register::ESP) == self.esp {
+            cpu.regs.set32(Register::ESP, self.esp + self.pop);
             Poll::Ready(cpu.regs.get32(Register::EAX))
         } else {
             Poll::Pending
@@ -170,7 +313,7 @@ impl Future for BlockFuture {
     fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
         let cpu = self.cpu;
         let cpu = unsafe { &mut *cpu };
-        if matches!(cpu.state, CPUState::Blocked(_)) {
+        if matches!(cpu.state, CPUState::Blocked(_) | CPUState::Waiting) {
             Poll::Pending
         } else {
             Poll::Ready(())
@@ -185,8 +328,14 @@ pub struct X86 {
 
     /// Total number of instructions executed.
     pub instr_count: usize,
+    /// Instructions left before execute_block stops, even mid-block; usize::MAX
+    /// for no limit.  Only meaningful while positive.
+    pub budget: usize,
 
     pub icache: InstrCache,
+
+    /// Per-instruction trace, if enabled.
+    pub trace: Option<Box<InstrTrace>>,
 }
 
 impl X86 {
@@ -195,7 +344,9 @@ impl X86 {
             cpus: vec![Box::pin(CPU::new())],
             cur_cpu: 0,
             instr_count: 0,
+            budget: usize::MAX,
             icache: InstrCache::default(),
+            trace: None,
         }
     }
 
@@ -208,10 +359,48 @@ impl X86 {
     }
 
     pub fn new_cpu(&mut self) -> &mut CPU {
-        self.cpus.push(Box::pin(CPU::new()));
+        let mut cpu = CPU::new();
+        // Threads share their process's view of the machine.
+        cpu.cpuid = self.cpus[0].cpuid.clone();
+        cpu.memmap = self.cpus[0].memmap.clone();
+        self.cpus.push(Box::pin(cpu));
         self.cpus.last_mut().unwrap()
     }
 
+    /// Feed the host clock (in milliseconds) to the timestamp counters.
+    pub fn set_host_time(&mut self, ms: u32) {
+        for cpu in self.cpus.iter_mut() {
+            cpu.tsc.set_host_time(ms);
+        }
+    }
+
+    /// Replace the memory map used to check memory accesses on all CPUs.
+    /// Watchpoints carry over, as they aren't tied to any particular mapping.
+    pub fn set_memory_map(&mut self, mut memmap: MemoryMap) {
+        memmap.watches = self.cpus[0].memmap.watches.clone();
+        for cpu in self.cpus.iter_mut() {
+            cpu.memmap = memmap.clone();
+        }
+    }
+
+    /// Watch a range of memory for accesses; see WatchAction for what happens on a hit.
+    pub fn add_watch(&mut self, watch: Watch) {
+        for cpu in self.cpus.iter_mut() {
+            cpu.memmap.watches.push(watch);
+        }
+    }
+
+    /// Remove watches starting at addr, returning whether there were any.
+    pub fn clear_watch(&mut self, addr: u32) -> bool {
+        let mut found = false;
+        for cpu in self.cpus.iter_mut() {
+            let len = cpu.memmap.watches.len();
+            cpu.memmap.watches.retain(|w| w.start != addr);
+            found |= cpu.memmap.watches.len() != len;
+        }
+        found
+    }
+
     pub fn single_step_next_block(&mut self, mem: Mem) {
         let ip = self.cpu().regs.eip;
         if ip == MAGIC_ADDR {
@@ -244,8 +433,11 @@ impl X86 {
         let mut soonest = None;
         for (i, cpu) in self.cpus.iter().enumerate() {
             match cpu.state {
-                CPUState::Running => {}
-                CPUState::DebugBreak | CPUState::Error(_) | CPUState::SysCall => {
+                CPUState::Running | CPUState::Suspended | CPUState::Exited | CPUState::Waiting => {}
+                CPUState::DebugBreak
+                | CPUState::Error(_)
+                | CPUState::SysCall
+                | CPUState::Fault(_) => {
                     self.cur_cpu = i;
                     return;
                 }
@@ -259,7 +451,10 @@ impl X86 {
                 },
             }
         }
-        self.cur_cpu = soonest.unwrap().0;
+        // If nothing can run, leave cur_cpu as is for the caller to report.
+        if let Some((i, _)) = soonest {
+            self.cur_cpu = i;
+        }
 
         // if self.cur_cpu != prev || !self.cpu().state.is_running() {
         //     log::info!("cpu {prev}=>{} {:?}", self.cur_cpu, self.cpu().state);
@@ -276,16 +471,45 @@ impl X86 {
         }
         let mut prev_ip = cpu.regs.eip;
         let block = self.icache.get_block(mem, prev_ip);
-        for op in block.ops.iter() {
-            prev_ip = cpu.regs.eip;
-            cpu.regs.eip = op.instr.next_ip() as u32;
-            self.instr_count = self.instr_count.wrapping_add(1);
-            (op.op)(cpu, mem, &op.instr);
-            if !cpu.state.is_running() {
-                break;
+        if self.trace.is_none() && cpu.memmap.watches.is_empty() {
+            for op in block.ops.iter() {
+                prev_ip = cpu.regs.eip;
+                cpu.regs.eip = op.instr.next_ip() as u32;
+                self.instr_count = self.instr_count.wrapping_add(1);
+                self.budget -= 1;
+                (op.op)(cpu, mem, &op.instr);
+                if !cpu.state.is_running() || self.budget == 0 {
+                    break;
+                }
+            }
+        } else {
+            // Kept as a separate loop so the common path pays nothing for debugging aids.
+            for op in block.ops.iter() {
+                prev_ip = cpu.regs.eip;
+                let before = self.trace.as_ref().map(|_| Snapshot::new(cpu));
+                cpu.regs.eip = op.instr.next_ip() as u32;
+                self.instr_count = self.instr_count.wrapping_add(1);
+                self.budget -= 1;
+                (op.op)(cpu, mem, &op.instr);
+                if let (Some(trace), Some(before)) = (&mut self.trace, &before) {
+                    trace.record(&op.instr, before, cpu);
+                }
+                if !cpu.watch_hits.is_empty() {
+                    cpu.report_watch_hits(mem, op.instr.ip32());
+                }
+                if !cpu.state.is_running() || self.budget == 0 {
+                    break;
+                }
             }
         }
         match cpu.state {
+            CPUState::Fault(_) => {
+                if let Some(regs) = cpu.fault_regs.take() {
+                    (cpu.regs, cpu.flags, cpu.fpu) = *regs;
+                }
+                // Point the exception handler at the faulting instruction.
+                cpu.regs.eip = prev_ip;
+            }
             CPUState::Error(_) => {
                 // Point the debugger at the failed instruction.
                 cpu.regs.eip = prev_ip;
@@ -294,3 +518,57 @@ impl X86 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Test, CODE, DATA, STACK};
+    use memory::ExtensionsMut;
+
+    /// A faulting load stops the instruction before it writes anything back.
+    #[test]
+    fn fault_discards_results() {
+        // mov eax, [0x10]; add esp, 4
+        let mut test = Test::new(&[0xa1, 0x10, 0x00, 0x00, 0x00, 0x83, 0xc4, 0x04]);
+        test.x86.cpu_mut().regs.set32(Register::EAX, 0x1234);
+        test.run();
+        let cpu = test.x86.cpu();
+        assert_eq!(
+            cpu.state,
+            CPUState::Fault(Fault::AccessViolation {
+                addr: 0x10,
+                write: false
+            })
+        );
+        assert_eq!(cpu.regs.eip, CODE);
+        assert_eq!(cpu.regs.get32(Register::EAX), 0x1234);
+        assert_eq!(cpu.regs.get32(Register::ESP), STACK);
+    }
+
+    /// Writing read-only memory faults without touching the flags the instruction computed.
+    #[test]
+    fn fault_on_write_keeps_flags() {
+        // add dword [DATA], 1; add dword [CODE], 1
+        let mut code = vec![0x83, 0x05];
+        code.extend_from_slice(&DATA.to_le_bytes());
+        code.push(0x01);
+        code.extend_from_slice(&[0x83, 0x05]);
+        code.extend_from_slice(&CODE.to_le_bytes());
+        code.push(0x01);
+        let mut test = Test::new(&code);
+        test.mem().put_pod::<u32>(DATA, u32::MAX);
+        test.run();
+        let cpu = test.x86.cpu();
+        assert_eq!(
+            cpu.state,
+            CPUState::Fault(Fault::AccessViolation {
+                addr: CODE,
+                write: true
+            })
+        );
+        assert_eq!(cpu.regs.eip, CODE + 7);
+        // From the first add, which wrapped to zero.
+        assert!(cpu.flags.contains(Flags::ZF | Flags::CF));
+        assert_eq!(test.mem().sub32(CODE, 4), &code[..4]);
+    }
+}